@@ -0,0 +1,126 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use rylv_metrics::{
+    AsyncMetricCollector, AsyncMetricCollectorOptions, DrainMetricCollectorTrait,
+    MetricCollectorTrait, RylvStr, SharedCollector, SharedCollectorOptions,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+type BenchHasher = ahash::RandomState;
+
+fn make_shared() -> SharedCollector<BenchHasher> {
+    SharedCollector::new(SharedCollectorOptions {
+        stats_prefix: String::new(),
+        prefix_separator: None,
+        histogram_prefix: String::new(),
+        count_prefix: String::new(),
+        gauge_prefix: String::new(),
+        histogram_configs: std::collections::HashMap::with_hasher(ahash::RandomState::new()),
+        default_histogram_config: rylv_metrics::HistogramConfig::default(),
+        hasher_builder: ahash::RandomState::new(),
+        prewarm_histograms: 0,
+        max_pooled_histograms_per_sigfig: None,
+        max_pending_aggregators: 1,
+        normalize_names: false,
+        metric_filter: None,
+        keep_zero_values: false,
+        key_idle_ttl: None,
+        skip_tag_sort: false,
+        gauge_mode: rylv_metrics::GaugeMode::Average,
+        value_rounding: None,
+        gauge_min_update_intervals: std::collections::HashMap::with_hasher(
+            ahash::RandomState::new(),
+        ),
+    })
+}
+
+fn make_async() -> AsyncMetricCollector<SharedCollector<BenchHasher>> {
+    AsyncMetricCollector::new(make_shared(), AsyncMetricCollectorOptions::default())
+}
+
+// ---------------------------------------------------------------------------
+// Parallel helpers
+// ---------------------------------------------------------------------------
+
+/// Records from `thread_count` threads while a background thread keeps
+/// draining, mimicking the flush thread of `MetricCollector`.
+fn run_parallel_histogram_with_drainer<C>(collector: &C, iters: u64, thread_count: usize)
+where
+    C: MetricCollectorTrait + DrainMetricCollectorTrait + Sync,
+{
+    let total_iters = match usize::try_from(iters) {
+        Ok(v) => v,
+        Err(_) => usize::MAX / 2,
+    };
+    let base = total_iters / thread_count;
+    let remainder = total_iters % thread_count;
+    let done = AtomicBool::new(false);
+
+    std::thread::scope(|scope| {
+        let done = &done;
+        scope.spawn(move || {
+            while !done.load(Ordering::Relaxed) {
+                if let Some(drain) = collector.try_begin_drain() {
+                    drain.for_each(drop);
+                }
+            }
+        });
+
+        let workers: Vec<_> = (0..thread_count)
+            .map(|index| {
+                let work = base + usize::from(index < remainder);
+                scope.spawn(move || {
+                    let mut tags = [
+                        RylvStr::from_static("service:api"),
+                        RylvStr::from_static("env:bench"),
+                        RylvStr::from_static("region:us-east-1"),
+                    ];
+                    for _ in 0..work {
+                        collector.histogram(
+                            RylvStr::from_static("bench.parallel.histogram"),
+                            42,
+                            &mut tags,
+                        );
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            let _ = worker.join();
+        }
+        done.store(true, Ordering::Relaxed);
+    });
+}
+
+// ---------------------------------------------------------------------------
+// Multi-threaded: histogram — direct DashMap recording vs queued recording
+// ---------------------------------------------------------------------------
+
+fn benchmark_histogram_contended(c: &mut Criterion) {
+    let thread_count = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut group = c.benchmark_group("histogram_contended_async");
+    group.throughput(Throughput::Elements(1));
+
+    group.bench_function("shared_direct", |b| {
+        let collector = make_shared();
+        b.iter_custom(|iters| {
+            let start = Instant::now();
+            run_parallel_histogram_with_drainer(&collector, iters, thread_count);
+            start.elapsed()
+        });
+    });
+
+    group.bench_function("shared_queued", |b| {
+        let collector = make_async();
+        b.iter_custom(|iters| {
+            let start = Instant::now();
+            run_parallel_histogram_with_drainer(&collector, iters, thread_count);
+            start.elapsed()
+        });
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_histogram_contended);
+criterion_main!(benches);
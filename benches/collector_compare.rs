@@ -10,18 +10,46 @@ type BenchHasher = ahash::RandomState;
 fn make_shared() -> SharedCollector<BenchHasher> {
     SharedCollector::new(SharedCollectorOptions {
         stats_prefix: String::new(),
+        prefix_separator: None,
+        histogram_prefix: String::new(),
+        count_prefix: String::new(),
+        gauge_prefix: String::new(),
         histogram_configs: std::collections::HashMap::with_hasher(ahash::RandomState::new()),
         default_histogram_config: rylv_metrics::HistogramConfig::default(),
         hasher_builder: ahash::RandomState::new(),
+        prewarm_histograms: 0,
+        max_pooled_histograms_per_sigfig: None,
+        max_pending_aggregators: 1,
+        normalize_names: false,
+        metric_filter: None,
+        keep_zero_values: false,
+        key_idle_ttl: None,
+        skip_tag_sort: false,
+        gauge_mode: rylv_metrics::GaugeMode::Average,
+        value_rounding: None,
+        gauge_min_update_intervals: std::collections::HashMap::with_hasher(
+            ahash::RandomState::new(),
+        ),
     })
 }
 
 fn make_tls() -> TLSCollector<BenchHasher> {
     TLSCollector::new(TLSCollectorOptions {
         stats_prefix: String::new(),
+        prefix_separator: None,
+        histogram_prefix: String::new(),
+        count_prefix: String::new(),
+        gauge_prefix: String::new(),
         histogram_configs: std::collections::HashMap::with_hasher(ahash::RandomState::new()),
         default_histogram_config: rylv_metrics::HistogramConfig::default(),
         hasher_builder: ahash::RandomState::new(),
+        normalize_names: false,
+        metric_filter: None,
+        keep_zero_values: false,
+        key_idle_ttl: None,
+        skip_tag_sort: false,
+        gauge_mode: rylv_metrics::GaugeMode::Average,
+        value_rounding: None,
     })
 }
 
@@ -0,0 +1,71 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rylv_metrics::{
+    MetricCollector, MetricCollectorOptions, MetricCollectorTrait, RylvStr, SharedCollector,
+    StatsWriterType,
+};
+use std::net::UdpSocket;
+use std::time::{Duration, Instant};
+
+/// Records `metrics` as gauges, then times the final flush performed when the
+/// collector is dropped.
+fn final_flush(
+    datadog_addr: std::net::SocketAddr,
+    entity_id: Option<&str>,
+    metrics: &[String],
+) -> Duration {
+    let options = MetricCollectorOptions {
+        flush_interval: Duration::from_secs(3600),
+        writer_type: StatsWriterType::Simple,
+        entity_id: entity_id.map(str::to_owned),
+        ..Default::default()
+    };
+    let collector = MetricCollector::new(
+        "127.0.0.1:0".parse().unwrap(),
+        datadog_addr,
+        options,
+        SharedCollector::default(),
+    )
+    .unwrap();
+    let mut tags = [
+        RylvStr::from_static("service:api"),
+        RylvStr::from_static("env:bench"),
+    ];
+    for metric in metrics {
+        collector.gauge(RylvStr::from(metric.as_str()), 42, &mut tags);
+    }
+
+    let start = Instant::now();
+    drop(collector);
+    start.elapsed()
+}
+
+// ---------------------------------------------------------------------------
+// Per-line flush cost with and without the static tag suffix, across key counts
+// ---------------------------------------------------------------------------
+
+fn benchmark_flush_suffix(c: &mut Criterion) {
+    // Nobody reads from this socket, the kernel drops datagrams once its buffer is full
+    let sink = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let datadog_addr = sink.local_addr().unwrap();
+
+    let mut group = c.benchmark_group("flush_suffix");
+    for keys in [256_usize, 4096, 16_384] {
+        let metrics: Vec<String> = (0..keys)
+            .map(|i| format!("bench.suffix.gauge{i}"))
+            .collect();
+        group.throughput(Throughput::Elements(keys as u64));
+        for (name, entity_id) in [("no_suffix", None), ("entity_id", Some("pod-1234"))] {
+            group.bench_with_input(BenchmarkId::new(name, keys), &metrics, |b, metrics| {
+                b.iter_custom(|iters| {
+                    (0..iters)
+                        .map(|_| final_flush(datadog_addr, entity_id, metrics))
+                        .sum()
+                });
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_flush_suffix);
+criterion_main!(benches);
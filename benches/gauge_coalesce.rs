@@ -0,0 +1,68 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rylv_metrics::{
+    GaugeMode, MetricCollectorTrait, RylvStr, SharedCollector, SharedCollectorOptions,
+};
+use std::time::{Duration, Instant};
+
+/// Threads writing the same gauge series concurrently.
+const THREADS: usize = 4;
+
+/// Gauge writes per thread and iteration.
+const WRITES: u64 = 10_000;
+
+/// Times `THREADS` threads writing `pool.size` `WRITES` times each, the value
+/// changing on every write when `changing` is set.
+fn write_gauge(collector: &SharedCollector, changing: bool) -> Duration {
+    let start = Instant::now();
+    std::thread::scope(|scope| {
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                let mut tags = [RylvStr::from_static("pool:db")];
+                for i in 0..WRITES {
+                    let value = if changing { i } else { 10 };
+                    collector.gauge(RylvStr::from_static("pool.size"), value, &mut tags);
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+// ---------------------------------------------------------------------------
+// Gauge from several threads: coalesced `Last` writes vs stored writes, and
+// writes dropped by a minimum update interval
+// ---------------------------------------------------------------------------
+
+fn benchmark_gauge_coalesce(c: &mut Criterion) {
+    let mut group = c.benchmark_group("gauge_coalesce");
+    group.throughput(Throughput::Elements(THREADS as u64 * WRITES));
+    let cases = [
+        ("last_steady_coalesced", GaugeMode::Last, false, None),
+        ("last_changing_stored", GaugeMode::Last, true, None),
+        (
+            "last_changing_min_interval",
+            GaugeMode::Last,
+            true,
+            Some(Duration::from_millis(10)),
+        ),
+        ("average_steady", GaugeMode::Average, false, None),
+    ];
+    for (name, gauge_mode, changing, min_interval) in cases {
+        let gauge_min_update_intervals = min_interval
+            .map(|interval| ("pool.size".to_string(), interval))
+            .into_iter()
+            .collect();
+        let collector = SharedCollector::new(SharedCollectorOptions {
+            gauge_mode,
+            gauge_min_update_intervals,
+            ..Default::default()
+        });
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            b.iter_custom(|iters| (0..iters).map(|_| write_gauge(&collector, changing)).sum());
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_gauge_coalesce);
+criterion_main!(benches);
@@ -0,0 +1,35 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rylv_metrics::{MetricCollectorTrait, RylvStr, SharedCollector};
+
+/// Gauge writes per iteration.
+const WRITES: u64 = 10_000;
+
+// ---------------------------------------------------------------------------
+// Tight loop: averaging `gauge` (two atomic adds) vs `gauge_last` (one store)
+// ---------------------------------------------------------------------------
+
+fn benchmark_gauge_last(c: &mut Criterion) {
+    let mut group = c.benchmark_group("gauge_last");
+    group.throughput(Throughput::Elements(WRITES));
+    let collector = SharedCollector::default();
+    let mut tags = [RylvStr::from_static("pool:db")];
+
+    group.bench_function(BenchmarkId::from_parameter("gauge"), |b| {
+        b.iter(|| {
+            for i in 0..WRITES {
+                collector.gauge(RylvStr::from_static("pool.size"), black_box(i), &mut tags);
+            }
+        });
+    });
+    group.bench_function(BenchmarkId::from_parameter("gauge_last"), |b| {
+        b.iter(|| {
+            for i in 0..WRITES {
+                collector.gauge_last(RylvStr::from_static("pool.size"), black_box(i), &mut tags);
+            }
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_gauge_last);
+criterion_main!(benches);
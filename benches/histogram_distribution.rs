@@ -0,0 +1,136 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rylv_metrics::{
+    HistogramConfig, MetricCollector, MetricCollectorOptions, MetricCollectorTrait, MetricKind,
+    MetricResult, RylvStr, SharedCollector, SharedCollectorOptions, StatsWriterTrait,
+    StatsWriterType,
+};
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Counts the bytes of the lines it is given instead of sending them.
+struct ByteCountingWriter {
+    bytes: Arc<AtomicU64>,
+    pending: usize,
+}
+
+impl StatsWriterTrait for ByteCountingWriter {
+    fn metric_copied(&self) -> bool {
+        true
+    }
+
+    fn write(
+        &mut self,
+        metrics: &[&str],
+        tags: &str,
+        value: &str,
+        metric_type: MetricKind,
+    ) -> MetricResult<()> {
+        let kind = match metric_type {
+            MetricKind::Timing => 2,
+            _ => 1,
+        };
+        let tags = if tags.is_empty() { 0 } else { tags.len() + 2 };
+        // `name:value|kind|#tags\n`
+        self.pending +=
+            metrics.iter().map(|part| part.len()).sum::<usize>() + value.len() + kind + tags + 3;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> MetricResult<usize> {
+        let flushed = std::mem::take(&mut self.pending);
+        self.bytes.fetch_add(flushed as u64, Ordering::Relaxed);
+        Ok(flushed)
+    }
+
+    fn reset(&mut self) {
+        self.pending = 0;
+    }
+
+    fn buffered_bytes(&self) -> usize {
+        self.pending
+    }
+}
+
+/// Records `samples` latencies for every metric, then times the final flush
+/// performed when the collector is dropped. Returns the elapsed time and the
+/// bytes written.
+fn final_flush(
+    datadog_addr: std::net::SocketAddr,
+    histogram_config: &HistogramConfig,
+    metrics: &[String],
+    samples: u64,
+) -> (Duration, u64) {
+    let bytes = Arc::new(AtomicU64::new(0));
+    let options = MetricCollectorOptions {
+        flush_interval: Duration::from_secs(3600),
+        writer_type: StatsWriterType::Custom(Box::new(ByteCountingWriter {
+            bytes: Arc::clone(&bytes),
+            pending: 0,
+        })),
+        ..Default::default()
+    };
+    let inner = SharedCollector::new(SharedCollectorOptions {
+        default_histogram_config: histogram_config.clone(),
+        ..Default::default()
+    });
+    let collector =
+        MetricCollector::new("127.0.0.1:0".parse().unwrap(), datadog_addr, options, inner).unwrap();
+    let mut tags = [
+        RylvStr::from_static("service:api"),
+        RylvStr::from_static("env:bench"),
+    ];
+    for metric in metrics {
+        for sample in 0..samples {
+            collector.histogram(RylvStr::from(metric.as_str()), 40 + sample * 7, &mut tags);
+        }
+    }
+
+    let start = Instant::now();
+    drop(collector);
+    (start.elapsed(), bytes.load(Ordering::Relaxed))
+}
+
+// ---------------------------------------------------------------------------
+// Bytes on the wire: five aggregate lines per key vs one packed `|d` line
+// ---------------------------------------------------------------------------
+
+fn benchmark_histogram_distribution(c: &mut Criterion) {
+    let sink = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let datadog_addr = sink.local_addr().unwrap();
+    let configs = [
+        ("aggregates", HistogramConfig::latency_millis()),
+        (
+            "distribution",
+            HistogramConfig::latency_millis().with_distribution(true),
+        ),
+    ];
+
+    let mut group = c.benchmark_group("histogram_distribution");
+    let keys = 4096_usize;
+    let metrics: Vec<String> = (0..keys)
+        .map(|i| format!("bench.distribution.latency{i}"))
+        .collect();
+    group.throughput(Throughput::Elements(keys as u64));
+    for samples in [1_u64, 4, 16] {
+        for (name, config) in &configs {
+            let (_, bytes) = final_flush(datadog_addr, config, &metrics, samples);
+            println!(
+                "{name}, {samples} samples per key: {bytes} bytes for {keys} keys ({} per key)",
+                bytes / keys as u64
+            );
+            group.bench_with_input(BenchmarkId::new(*name, samples), &samples, |b, samples| {
+                b.iter_custom(|iters| {
+                    (0..iters)
+                        .map(|_| final_flush(datadog_addr, config, &metrics, *samples).0)
+                        .sum()
+                });
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_histogram_distribution);
+criterion_main!(benches);
@@ -0,0 +1,208 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use rylv_metrics::{
+    DrainMetricCollectorTrait, MetricCollectorTrait, RylvStr, SharedCollector,
+    SharedCollectorOptions,
+};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+type BenchHasher = ahash::RandomState;
+
+/// Number of distinct histogram metrics touched during the first flush window.
+const UNIQUE_HISTOGRAMS: usize = 1024;
+
+/// Allocations at least this large are histogram count arrays; keys and map
+/// buckets stay well below it.
+const HISTOGRAM_ALLOCATION_SIZE: usize = 16 * 1024;
+
+/// Counts allocations so the benchmark can report what prewarming saves.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static HISTOGRAM_ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        if layout.size() >= HISTOGRAM_ALLOCATION_SIZE {
+            HISTOGRAM_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        }
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+fn make_shared(prewarm_histograms: usize) -> SharedCollector<BenchHasher> {
+    SharedCollector::new(SharedCollectorOptions {
+        stats_prefix: String::new(),
+        prefix_separator: None,
+        histogram_prefix: String::new(),
+        count_prefix: String::new(),
+        gauge_prefix: String::new(),
+        histogram_configs: std::collections::HashMap::with_hasher(ahash::RandomState::new()),
+        default_histogram_config: rylv_metrics::HistogramConfig::default(),
+        hasher_builder: ahash::RandomState::new(),
+        prewarm_histograms,
+        max_pooled_histograms_per_sigfig: None,
+        max_pending_aggregators: 1,
+        normalize_names: false,
+        metric_filter: None,
+        keep_zero_values: false,
+        key_idle_ttl: None,
+        skip_tag_sort: false,
+        gauge_mode: rylv_metrics::GaugeMode::Average,
+        value_rounding: None,
+        gauge_min_update_intervals: std::collections::HashMap::with_hasher(
+            ahash::RandomState::new(),
+        ),
+    })
+}
+
+/// Records every metric once, returning the elapsed time and allocation count.
+fn first_flush_window(
+    collector: &SharedCollector<BenchHasher>,
+    metrics: &[String],
+) -> (Duration, u64) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let mut tags = [
+        RylvStr::from_static("service:api"),
+        RylvStr::from_static("env:bench"),
+    ];
+    for metric in metrics {
+        collector.histogram(RylvStr::from(metric.as_str()), 42, &mut tags);
+    }
+    (
+        start.elapsed(),
+        ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+    )
+}
+
+// ---------------------------------------------------------------------------
+// First flush window: cold histogram pool vs prewarmed histogram pool
+// ---------------------------------------------------------------------------
+
+fn benchmark_first_flush_window(c: &mut Criterion) {
+    let metrics: Vec<String> = (0..UNIQUE_HISTOGRAMS)
+        .map(|i| format!("bench.prewarm.histogram{i}"))
+        .collect();
+    let mut group = c.benchmark_group("histogram_first_flush_window");
+    group.throughput(Throughput::Elements(UNIQUE_HISTOGRAMS as u64));
+
+    for (name, prewarm_histograms) in [("cold", 0), ("prewarmed", UNIQUE_HISTOGRAMS)] {
+        let mut total_allocations = 0u64;
+        let mut total_windows = 0u64;
+        group.bench_function(name, |b| {
+            b.iter_custom(|iters| {
+                let mut elapsed = Duration::ZERO;
+                for _ in 0..iters {
+                    let collector = make_shared(prewarm_histograms);
+                    let (window, allocations) = first_flush_window(&collector, &metrics);
+                    elapsed += window;
+                    total_allocations += allocations;
+                    total_windows += 1;
+                }
+                elapsed
+            });
+        });
+        println!(
+            "{name}: {} allocations per first flush window",
+            total_allocations / total_windows.max(1)
+        );
+    }
+
+    group.finish();
+}
+
+/// Drains the collector, recycling histograms that saw no recording since the last drain.
+fn flush(collector: &SharedCollector<BenchHasher>) {
+    loop {
+        if let Some(drain) = collector.try_begin_drain() {
+            drain.for_each(drop);
+            return;
+        }
+    }
+}
+
+/// Records `metrics_per_window` histograms whose names change every window, as with
+/// per-job or per-route metrics, and flushes. Returns the elapsed time and the
+/// number of histograms allocated.
+fn churn_window(
+    collector: &SharedCollector<BenchHasher>,
+    metrics: &[String],
+    window: usize,
+) -> (Duration, u64) {
+    let histogram_allocations = HISTOGRAM_ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let mut tags = [RylvStr::from_static("env:bench")];
+    let offset = (window % CHURN_SETS) * CHURN_METRICS;
+    for metric in &metrics[offset..offset + CHURN_METRICS] {
+        collector.histogram(RylvStr::from(metric.as_str()), 42, &mut tags);
+    }
+    flush(collector);
+    (
+        start.elapsed(),
+        HISTOGRAM_ALLOCATIONS.load(Ordering::Relaxed) - histogram_allocations,
+    )
+}
+
+/// Number of histograms recorded per churn window.
+const CHURN_METRICS: usize = 256;
+
+/// Number of distinct metric name sets the churn windows rotate through.
+const CHURN_SETS: usize = 4;
+
+// ---------------------------------------------------------------------------
+// Steady state: histogram pools shared across aggregator swaps
+// ---------------------------------------------------------------------------
+
+fn benchmark_steady_state_window(c: &mut Criterion) {
+    // Each set goes idle long enough for its histograms to be recycled before it returns
+    let metrics: Vec<String> = (0..CHURN_METRICS * CHURN_SETS)
+        .map(|i| format!("bench.churn.histogram{i}"))
+        .collect();
+    let mut group = c.benchmark_group("histogram_steady_state_window");
+    group.throughput(Throughput::Elements(CHURN_METRICS as u64));
+
+    let collector = make_shared(0);
+    for window in 0..4 {
+        let (_, allocations) = churn_window(&collector, &metrics, window);
+        println!("window {window}: {allocations} histogram allocations");
+    }
+
+    let mut total_allocations = 0u64;
+    let mut total_windows = 0u64;
+    let mut window = 4;
+    group.bench_function("shared_pools", |b| {
+        b.iter_custom(|iters| {
+            let mut elapsed = Duration::ZERO;
+            for _ in 0..iters {
+                let (duration, allocations) = churn_window(&collector, &metrics, window);
+                elapsed += duration;
+                total_allocations += allocations;
+                total_windows += 1;
+                window += 1;
+            }
+            elapsed
+        });
+    });
+    println!(
+        "steady state: {total_allocations} histogram allocations over {total_windows} windows"
+    );
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    benchmark_first_flush_window,
+    benchmark_steady_state_window
+);
+criterion_main!(benches);
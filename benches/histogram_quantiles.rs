@@ -0,0 +1,73 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use rylv_metrics::{
+    DrainMetricCollectorTrait, HistogramConfig, MetricCollectorTrait, RylvStr, SharedCollector,
+    SharedCollectorOptions,
+};
+use std::hint::black_box;
+
+/// Number of distinct histogram series flushed per iteration.
+const HISTOGRAM_KEYS: usize = 4096;
+
+/// Samples recorded into every series before the flush.
+const SAMPLES_PER_KEY: u64 = 16;
+
+fn make_shared(default_histogram_config: HistogramConfig) -> SharedCollector {
+    SharedCollector::new(SharedCollectorOptions {
+        default_histogram_config,
+        ..Default::default()
+    })
+}
+
+fn record(collector: &SharedCollector, metrics: &[String]) {
+    let mut tags = [RylvStr::from_static("env:bench")];
+    for metric in metrics {
+        for value in 1..=SAMPLES_PER_KEY {
+            collector.histogram(RylvStr::from(metric.as_str()), value * 100, &mut tags);
+        }
+    }
+}
+
+fn flush(collector: &SharedCollector) {
+    loop {
+        if let Some(drain) = collector.try_begin_drain() {
+            drain.for_each(|frame| {
+                black_box(frame.value);
+            });
+            return;
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Flush of many histogram keys: percentiles on vs off
+// ---------------------------------------------------------------------------
+
+fn benchmark_flush_histogram_quantiles(c: &mut Criterion) {
+    let metrics: Vec<String> = (0..HISTOGRAM_KEYS)
+        .map(|i| format!("bench.quantiles.histogram{i}"))
+        .collect();
+    let mut group = c.benchmark_group("histogram_flush_quantiles");
+    group.throughput(Throughput::Elements(HISTOGRAM_KEYS as u64));
+
+    for (name, config) in [
+        ("percentiles", HistogramConfig::latency_millis()),
+        (
+            "without_quantiles",
+            HistogramConfig::latency_millis().without_quantiles(),
+        ),
+    ] {
+        let collector = make_shared(config);
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || record(&collector, &metrics),
+                |()| flush(&collector),
+                BatchSize::PerIteration,
+            );
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_flush_histogram_quantiles);
+criterion_main!(benches);
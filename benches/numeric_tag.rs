@@ -0,0 +1,72 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use rylv_metrics::{MetricCollectorTrait, NumericTag, RylvStr, SharedCollector};
+use std::hint::black_box;
+
+/// HTTP status codes cycled through by every benchmark iteration.
+const STATUS_CODES: [u64; 8] = [200, 201, 204, 301, 400, 404, 500, 503];
+
+// ---------------------------------------------------------------------------
+// Building the tag alone: format! vs NumericTag
+// ---------------------------------------------------------------------------
+
+fn benchmark_build_numeric_tag(c: &mut Criterion) {
+    let mut group = c.benchmark_group("numeric_tag_build");
+    group.throughput(Throughput::Elements(STATUS_CODES.len() as u64));
+    group.bench_function("format", |b| {
+        b.iter(|| {
+            for code in STATUS_CODES {
+                black_box(format!("status:{}", black_box(code)));
+            }
+        });
+    });
+    group.bench_function("numeric_tag", |b| {
+        b.iter(|| {
+            for code in STATUS_CODES {
+                black_box(NumericTag::new("status", black_box(code)));
+            }
+        });
+    });
+    group.finish();
+}
+
+// ---------------------------------------------------------------------------
+// Recording into existing series: format! vs NumericTag
+// ---------------------------------------------------------------------------
+
+fn benchmark_record_numeric_tag(c: &mut Criterion) {
+    let collector = SharedCollector::default();
+    let mut group = c.benchmark_group("numeric_tag_record");
+    group.throughput(Throughput::Elements(STATUS_CODES.len() as u64));
+    group.bench_function("format", |b| {
+        b.iter(|| {
+            for code in STATUS_CODES {
+                collector.count(
+                    RylvStr::from_static("bench.numeric_tag.count"),
+                    &mut [
+                        RylvStr::from_static("env:bench"),
+                        RylvStr::from(format!("status:{code}")),
+                    ],
+                );
+            }
+        });
+    });
+    group.bench_function("numeric_tag", |b| {
+        b.iter(|| {
+            for code in STATUS_CODES {
+                let status = NumericTag::new("status", code);
+                collector.count(
+                    RylvStr::from_static("bench.numeric_tag.count"),
+                    &mut [RylvStr::from_static("env:bench"), status.as_rylv_str()],
+                );
+            }
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    benchmark_build_numeric_tag,
+    benchmark_record_numeric_tag
+);
+criterion_main!(benches);
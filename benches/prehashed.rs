@@ -0,0 +1,27 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use rylv_metrics::{MetricCollectorTrait, RylvStr, SharedCollector, SharedCollectorOptions};
+
+const METRIC: &str = "bench.prehashed.count";
+const TAGS: [&str; 4] = ["az:use1-az1", "env:prod", "region:us-east-1", "service:api"];
+
+// ---------------------------------------------------------------------------
+// Recording into an existing 4-tag series: sorted vs prehashed
+// ---------------------------------------------------------------------------
+
+fn benchmark_prehashed(c: &mut Criterion) {
+    let mut group = c.benchmark_group("prehashed_record");
+    let collector = SharedCollector::new(SharedCollectorOptions::default());
+    let tags = collector.prepare_sorted_tags(TAGS.map(RylvStr::from_static));
+    let hash = collector.prehash(METRIC, &tags);
+
+    group.bench_function("sorted", |b| {
+        b.iter(|| collector.count_add_sorted(RylvStr::from_static(METRIC), 1, &tags));
+    });
+    group.bench_function("prehashed", |b| {
+        b.iter(|| collector.count_add_prehashed(hash, METRIC, 1, &tags));
+    });
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_prehashed);
+criterion_main!(benches);
@@ -0,0 +1,43 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rylv_metrics::{MetricCollectorTrait, RylvStr, SharedCollector, SharedCollectorOptions};
+
+/// Four tags as a call site would pass them, in canonical and shuffled order.
+const TAG_ORDERS: [(&str, [&str; 4]); 2] = [
+    (
+        "canonical",
+        ["az:use1-az1", "env:prod", "region:us-east-1", "service:api"],
+    ),
+    (
+        "shuffled",
+        ["service:api", "env:prod", "az:use1-az1", "region:us-east-1"],
+    ),
+];
+
+// ---------------------------------------------------------------------------
+// Recording into an existing 4-tag series: sorted vs skip_tag_sort
+// ---------------------------------------------------------------------------
+
+fn benchmark_skip_tag_sort(c: &mut Criterion) {
+    let mut group = c.benchmark_group("skip_tag_sort_record");
+    for skip_tag_sort in [false, true] {
+        let collector = SharedCollector::new(SharedCollectorOptions {
+            skip_tag_sort,
+            ..Default::default()
+        });
+        let mode = if skip_tag_sort { "skip_sort" } else { "sort" };
+        for (order, tags) in TAG_ORDERS {
+            group.bench_with_input(BenchmarkId::new(mode, order), &tags, |b, tags| {
+                b.iter(|| {
+                    collector.count(
+                        RylvStr::from_static("bench.skip_tag_sort.count"),
+                        &mut tags.map(RylvStr::from_static),
+                    );
+                });
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_skip_tag_sort);
+criterion_main!(benches);
@@ -51,12 +51,30 @@ fn benchmark_record_histogram(c: &mut Criterion) {
         max_udp_packet_size: 1400,
         flush_interval: Duration::from_millis(10000),
         writer_type: DEFAULT_STATS_WRITER_TYPE,
+        ..Default::default()
     };
     let inner_options = SharedCollectorOptions {
         stats_prefix: String::new(),
+        prefix_separator: None,
+        histogram_prefix: String::new(),
+        count_prefix: String::new(),
+        gauge_prefix: String::new(),
         histogram_configs: std::collections::HashMap::with_hasher(ahash::RandomState::new()),
         default_histogram_config: rylv_metrics::HistogramConfig::default(),
         hasher_builder: ahash::RandomState::new(),
+        prewarm_histograms: 0,
+        max_pooled_histograms_per_sigfig: None,
+        max_pending_aggregators: 1,
+        normalize_names: false,
+        metric_filter: None,
+        keep_zero_values: false,
+        key_idle_ttl: None,
+        skip_tag_sort: false,
+        gauge_mode: rylv_metrics::GaugeMode::Average,
+        value_rounding: None,
+        gauge_min_update_intervals: std::collections::HashMap::with_hasher(
+            ahash::RandomState::new(),
+        ),
     };
 
     let inner = SharedCollector::new(inner_options);
@@ -168,13 +186,31 @@ fn make_udp_collector(
         max_udp_packet_size: 1400,
         flush_interval: Duration::from_millis(10000),
         writer_type: DEFAULT_STATS_WRITER_TYPE,
+        ..Default::default()
     };
     let bind_addr = "0.0.0.0:0".parse().unwrap();
     let inner_options = SharedCollectorOptions {
         stats_prefix: String::new(),
+        prefix_separator: None,
+        histogram_prefix: String::new(),
+        count_prefix: String::new(),
+        gauge_prefix: String::new(),
         histogram_configs: std::collections::HashMap::with_hasher(ahash::RandomState::new()),
         default_histogram_config: rylv_metrics::HistogramConfig::default(),
         hasher_builder: ahash::RandomState::new(),
+        prewarm_histograms: 0,
+        max_pooled_histograms_per_sigfig: None,
+        max_pending_aggregators: 1,
+        normalize_names: false,
+        metric_filter: None,
+        keep_zero_values: false,
+        key_idle_ttl: None,
+        skip_tag_sort: false,
+        gauge_mode: rylv_metrics::GaugeMode::Average,
+        value_rounding: None,
+        gauge_min_update_intervals: std::collections::HashMap::with_hasher(
+            ahash::RandomState::new(),
+        ),
     };
     let inner = SharedCollector::new(inner_options);
     MetricCollector::new(bind_addr, datadog_addr, options, inner)
@@ -190,13 +226,25 @@ fn make_tls_collector(
         max_udp_packet_size: 1400,
         flush_interval: Duration::from_millis(10000),
         writer_type: DEFAULT_STATS_WRITER_TYPE,
+        ..Default::default()
     };
     let bind_addr = "0.0.0.0:0".parse().unwrap();
     let inner_options = TLSCollectorOptions {
         stats_prefix: String::new(),
+        prefix_separator: None,
+        histogram_prefix: String::new(),
+        count_prefix: String::new(),
+        gauge_prefix: String::new(),
         histogram_configs: std::collections::HashMap::with_hasher(ahash::RandomState::new()),
         default_histogram_config: rylv_metrics::HistogramConfig::default(),
         hasher_builder: ahash::RandomState::new(),
+        normalize_names: false,
+        metric_filter: None,
+        keep_zero_values: false,
+        key_idle_ttl: None,
+        skip_tag_sort: false,
+        gauge_mode: rylv_metrics::GaugeMode::Average,
+        value_rounding: None,
     };
     let inner = TLSCollector::new(inner_options);
     MetricCollector::new(bind_addr, datadog_addr, options, inner)
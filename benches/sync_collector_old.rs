@@ -42,16 +42,28 @@ fn benchmark_record_histogram(c: &mut Criterion) {
         max_udp_packet_size: 1400,
         flush_interval: Duration::from_millis(10000),
         writer_type: DEFAULT_STATS_WRITER_TYPE,
+        ..Default::default()
     };
 
     let inner = TLSCollector::new(TLSCollectorOptions {
         stats_prefix: String::new(),
+        prefix_separator: None,
+        histogram_prefix: String::new(),
+        count_prefix: String::new(),
+        gauge_prefix: String::new(),
         histogram_configs: std::collections::HashMap::with_hasher(ahash::RandomState::new()),
         default_histogram_config: HistogramConfig::new(SigFig::ZERO, vec![])
             .unwrap()
             .with_bounds(100, 2_000_000)
             .unwrap(),
         hasher_builder: ahash::RandomState::new(),
+        normalize_names: false,
+        metric_filter: None,
+        keep_zero_values: false,
+        key_idle_ttl: None,
+        skip_tag_sort: false,
+        gauge_mode: rylv_metrics::GaugeMode::Average,
+        value_rounding: None,
     });
 
     // let inner = SharedCollector::new(SharedCollectorOptions {
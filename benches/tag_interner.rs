@@ -0,0 +1,130 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use rylv_metrics::{
+    DrainMetricCollectorTrait, MetricCollectorTrait, RylvStr, SharedCollector,
+    SharedCollectorOptions,
+};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+type BenchHasher = ahash::RandomState;
+
+/// Number of distinct dynamic tags, one per simulated thread.
+const DYNAMIC_TAGS: usize = 16;
+
+/// Counts allocations so the benchmark can report what interning saves.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+fn make_shared() -> SharedCollector<BenchHasher> {
+    SharedCollector::new(SharedCollectorOptions {
+        stats_prefix: String::new(),
+        prefix_separator: None,
+        histogram_prefix: String::new(),
+        count_prefix: String::new(),
+        gauge_prefix: String::new(),
+        histogram_configs: std::collections::HashMap::with_hasher(ahash::RandomState::new()),
+        default_histogram_config: rylv_metrics::HistogramConfig::default(),
+        hasher_builder: ahash::RandomState::new(),
+        prewarm_histograms: 0,
+        max_pooled_histograms_per_sigfig: None,
+        max_pending_aggregators: 1,
+        normalize_names: false,
+        metric_filter: None,
+        keep_zero_values: false,
+        key_idle_ttl: None,
+        skip_tag_sort: false,
+        gauge_mode: rylv_metrics::GaugeMode::Average,
+        value_rounding: None,
+        gauge_min_update_intervals: std::collections::HashMap::with_hasher(
+            ahash::RandomState::new(),
+        ),
+    })
+}
+
+/// Records one count per dynamic tag, then drains the collector until its idle
+/// keys are removed, so the next window materializes every tag into a new
+/// aggregation key again, as it does for tags recorded on and off.
+fn flush_window(collector: &SharedCollector<BenchHasher>, tags: &[String]) -> (Duration, u64) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for tag in tags {
+        let mut tags = [
+            RylvStr::from_static("env:bench"),
+            RylvStr::from(tag.as_str()),
+        ];
+        collector.count(RylvStr::from_static("bench.interner.count"), &mut tags);
+    }
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    // emitted, then zeroed, then removed as idle
+    for _ in 0..3 {
+        flush(collector);
+    }
+    (elapsed, allocations)
+}
+
+fn flush(collector: &SharedCollector<BenchHasher>) {
+    loop {
+        if let Some(drain) = collector.try_begin_drain() {
+            drain.for_each(|frame| {
+                black_box(frame.value);
+            });
+            return;
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Same dynamic tags recorded window after window: first window vs warmed up
+// ---------------------------------------------------------------------------
+
+fn benchmark_repeated_dynamic_tag(c: &mut Criterion) {
+    let tags: Vec<String> = (0..DYNAMIC_TAGS).map(|id| format!("thread:{id}")).collect();
+    let mut group = c.benchmark_group("tag_interner_repeated_dynamic_tag");
+    group.throughput(Throughput::Elements(DYNAMIC_TAGS as u64));
+
+    let collector = make_shared();
+    let (_, first_window_allocations) = flush_window(&collector, &tags);
+    println!("first window: {first_window_allocations} allocations");
+
+    let mut total_allocations = 0u64;
+    let mut total_windows = 0u64;
+    group.bench_function("warm", |b| {
+        b.iter_custom(|iters| {
+            let mut elapsed = Duration::ZERO;
+            for _ in 0..iters {
+                let (window, allocations) = flush_window(&collector, &tags);
+                elapsed += window;
+                total_allocations += allocations;
+                total_windows += 1;
+            }
+            elapsed
+        });
+    });
+    println!(
+        "warm: {} allocations per window",
+        total_allocations / total_windows.max(1)
+    );
+
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_repeated_dynamic_tag);
+criterion_main!(benches);
@@ -0,0 +1,91 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use rylv_metrics::{RylvStr, SortedTags};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+type BenchHasher = ahash::RandomState;
+
+/// Counts allocations so the benchmark can report them per materialization.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+/// Previous layout: every tag owned on its own next to the joined buffer.
+fn materialize_separate(tags: &[RylvStr<'_>]) -> (Box<[RylvStr<'static>]>, Arc<str>) {
+    let owned: Box<[RylvStr<'static>]> = tags
+        .iter()
+        .map(|tag| RylvStr::from(tag.as_ref().to_owned()))
+        .collect();
+    let joined = owned
+        .iter()
+        .map(AsRef::as_ref)
+        .collect::<Vec<&str>>()
+        .join(",");
+    (owned, Arc::from(joined))
+}
+
+fn allocations_per_call(mut f: impl FnMut()) -> u64 {
+    const CALLS: u64 = 1024;
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..CALLS {
+        f();
+    }
+    (ALLOCATIONS.load(Ordering::Relaxed) - before) / CALLS
+}
+
+// ---------------------------------------------------------------------------
+// Materializing 4 borrowed tags: separate tags vs joined buffer with offsets
+// ---------------------------------------------------------------------------
+
+fn benchmark_materialize_four_tags(c: &mut Criterion) {
+    let dynamic = [
+        "env:bench".to_owned(),
+        "service:api".to_owned(),
+        "route:/users".to_owned(),
+        "status:200".to_owned(),
+    ];
+    let tags: Vec<RylvStr<'_>> = dynamic
+        .iter()
+        .map(|tag| RylvStr::from(tag.as_str()))
+        .collect();
+    let hasher = BenchHasher::new();
+
+    println!(
+        "separate: {} allocations, joined: {} allocations",
+        allocations_per_call(|| {
+            black_box(materialize_separate(&tags));
+        }),
+        allocations_per_call(|| {
+            black_box(SortedTags::new(tags.iter().cloned(), &hasher));
+        }),
+    );
+
+    let mut group = c.benchmark_group("tag_materialization_four_tags");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("separate", |b| {
+        b.iter(|| black_box(materialize_separate(black_box(&tags))));
+    });
+    group.bench_function("joined", |b| {
+        b.iter(|| black_box(SortedTags::new(black_box(&tags).iter().cloned(), &hasher)));
+    });
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_materialize_four_tags);
+criterion_main!(benches);
@@ -0,0 +1,69 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use rylv_metrics::{
+    MetricCollector, MetricCollectorOptions, MetricCollectorTrait, RylvStr, SharedCollector,
+};
+use std::net::UdpSocket;
+use std::time::{Duration, Instant};
+
+/// Number of distinct gauges recorded before each final flush.
+const UNIQUE_GAUGES: usize = 4096;
+
+/// Records every gauge, then times the final flush performed when the collector is dropped.
+fn final_flush(
+    datadog_addr: std::net::SocketAddr,
+    connect_socket: bool,
+    metrics: &[String],
+) -> Duration {
+    let options = MetricCollectorOptions {
+        flush_interval: Duration::from_secs(3600),
+        connect_socket,
+        ..Default::default()
+    };
+    let collector = MetricCollector::new(
+        "127.0.0.1:0".parse().unwrap(),
+        datadog_addr,
+        options,
+        SharedCollector::default(),
+    )
+    .unwrap();
+    let mut tags = [
+        RylvStr::from_static("service:api"),
+        RylvStr::from_static("env:bench"),
+    ];
+    for metric in metrics {
+        collector.gauge(RylvStr::from(metric.as_str()), 42, &mut tags);
+    }
+
+    let start = Instant::now();
+    drop(collector);
+    start.elapsed()
+}
+
+// ---------------------------------------------------------------------------
+// Simple writer: send_to on an unconnected socket vs send on a connected one
+// ---------------------------------------------------------------------------
+
+fn benchmark_simple_writer_connect(c: &mut Criterion) {
+    // Nobody reads from this socket, the kernel drops datagrams once its buffer is full
+    let sink = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let datadog_addr = sink.local_addr().unwrap();
+    let metrics: Vec<String> = (0..UNIQUE_GAUGES)
+        .map(|i| format!("bench.connect.gauge{i}"))
+        .collect();
+
+    let mut group = c.benchmark_group("simple_writer_final_flush");
+    group.throughput(Throughput::Elements(UNIQUE_GAUGES as u64));
+    for (name, connect_socket) in [("unconnected", false), ("connected", true)] {
+        group.bench_function(name, |b| {
+            b.iter_custom(|iters| {
+                (0..iters)
+                    .map(|_| final_flush(datadog_addr, connect_socket, &metrics))
+                    .sum()
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_simple_writer_connect);
+criterion_main!(benches);
@@ -0,0 +1,84 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use rylv_metrics::{
+    MetricCollector, MetricCollectorOptions, MetricCollectorTrait, RylvStr, SharedCollector,
+    StatsWriterType, DEFAULT_STATS_WRITER_TYPE,
+};
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+/// Number of distinct gauges recorded before each final flush.
+const UNIQUE_GAUGES: usize = 16_384;
+
+/// Records every gauge, then times the final flush performed when the collector is dropped.
+fn final_flush(
+    datadog_addr: SocketAddr,
+    writer_type: StatsWriterType,
+    writer_threads: usize,
+    metrics: &[String],
+) -> Duration {
+    let options = MetricCollectorOptions {
+        flush_interval: Duration::from_secs(3600),
+        writer_type,
+        writer_threads,
+        ..Default::default()
+    };
+    let collector = MetricCollector::new(
+        "127.0.0.1:0".parse().unwrap(),
+        datadog_addr,
+        options,
+        SharedCollector::default(),
+    )
+    .unwrap();
+    let mut tags = [
+        RylvStr::from_static("service:api"),
+        RylvStr::from_static("env:bench"),
+    ];
+    for metric in metrics {
+        collector.gauge(RylvStr::from(metric.as_str()), 42, &mut tags);
+    }
+
+    let start = Instant::now();
+    drop(collector);
+    start.elapsed()
+}
+
+// ---------------------------------------------------------------------------
+// Final flush sent by the flush thread alone vs two sender threads
+// ---------------------------------------------------------------------------
+
+fn benchmark_writer_threads(c: &mut Criterion) {
+    // Nobody reads from this socket, the kernel drops datagrams once its buffer is full
+    let sink = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let datadog_addr = sink.local_addr().unwrap();
+    let metrics: Vec<String> = (0..UNIQUE_GAUGES)
+        .map(|i| format!("bench.writer_threads.gauge{i}"))
+        .collect();
+
+    for (group_name, default_writer) in [
+        ("writer_threads_simple", false),
+        ("writer_threads_default", true),
+    ] {
+        let writer_type = || {
+            if default_writer {
+                DEFAULT_STATS_WRITER_TYPE
+            } else {
+                StatsWriterType::Simple
+            }
+        };
+        let mut group = c.benchmark_group(group_name);
+        group.throughput(Throughput::Elements(UNIQUE_GAUGES as u64));
+        for writer_threads in [1, 2] {
+            group.bench_function(format!("{writer_threads}_threads"), |b| {
+                b.iter_custom(|iters| {
+                    (0..iters)
+                        .map(|_| final_flush(datadog_addr, writer_type(), writer_threads, &metrics))
+                        .sum()
+                });
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, benchmark_writer_threads);
+criterion_main!(benches);
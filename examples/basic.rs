@@ -4,17 +4,11 @@
 
 use rylv_metrics::{
     MetricCollector, MetricCollectorOptions, MetricCollectorTrait, RylvStr, SharedCollector,
-    SharedCollectorOptions, StatsWriterType,
+    SharedCollectorOptions,
 };
-use std::time::Duration;
 
 fn main() {
-    let options = MetricCollectorOptions {
-        max_udp_packet_size: 1432,
-        max_udp_batch_size: 10,
-        flush_interval: Duration::from_secs(10),
-        writer_type: StatsWriterType::Simple,
-    };
+    let options = MetricCollectorOptions::default();
     let inner_options = SharedCollectorOptions {
         stats_prefix: "myapp.".to_string(),
         ..Default::default()
@@ -29,6 +29,9 @@ impl StatsWriterTrait for InMemoryWriter {
         let metric_type = match metric_type {
             MetricKind::Count => "c",
             MetricKind::Gauge => "g",
+            MetricKind::Timing => "ms",
+            MetricKind::Distribution => "d",
+            kind => return Err(format!("unsupported metric kind {kind:?}").into()),
         };
         let metric_name: String = metrics.iter().copied().collect();
         let line = if tags.is_empty() {
@@ -54,10 +57,9 @@ fn main() {
     };
 
     let options = MetricCollectorOptions {
-        max_udp_packet_size: 1432,
-        max_udp_batch_size: 10,
         flush_interval: Duration::from_millis(50),
         writer_type: StatsWriterType::Custom(Box::new(writer)),
+        ..Default::default()
     };
     let inner_options = SharedCollectorOptions {
         stats_prefix: "app.".to_string(),
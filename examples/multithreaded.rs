@@ -4,18 +4,12 @@
 
 use rylv_metrics::{
     count, MetricCollector, MetricCollectorOptions, MetricCollectorTrait, RylvStr, SharedCollector,
-    SharedCollectorOptions, StatsWriterType,
+    SharedCollectorOptions,
 };
 use std::sync::Arc;
-use std::time::Duration;
 
 fn main() {
-    let options = MetricCollectorOptions {
-        max_udp_packet_size: 1432,
-        max_udp_batch_size: 10,
-        flush_interval: Duration::from_secs(10),
-        writer_type: StatsWriterType::Simple,
-    };
+    let options = MetricCollectorOptions::default();
     let inner_options = SharedCollectorOptions {
         stats_prefix: "myapp.".to_string(),
         ..Default::default()
@@ -57,11 +51,12 @@ fn main() {
         handle.join().unwrap();
     }
 
-    // Dropping requires ownership — unwrap the Arc
-    // (only succeeds when no other Arc references remain)
-    match Arc::try_unwrap(collector) {
-        Ok(c) => drop(c),
-        Err(_) => eprintln!("Warning: other Arc references still alive, cannot shutdown cleanly"),
+    // Flush everything recorded so far, even if other Arc references remain;
+    // later recordings are dropped and counted
+    match collector.quiesce() {
+        Ok(report) if report.flushed_cleanly => {}
+        Ok(report) => eprintln!("Warning: {} metrics not sent", report.pending_metrics),
+        Err(err) => eprintln!("Warning: final flush failed: {err}"),
     }
 
     println!("All threads finished. Metrics flushed.");
@@ -8,16 +8,14 @@
 
 use rylv_metrics::{
     count_add_sorted, histogram_sorted, sorted_tags, MetricCollector, MetricCollectorOptions,
-    MetricCollectorTrait, RylvStr, SharedCollector, SharedCollectorOptions, StatsWriterType,
+    MetricCollectorTrait, RylvStr, SharedCollector, SharedCollectorOptions,
 };
 use std::time::Duration;
 
 fn main() {
     let options = MetricCollectorOptions {
-        max_udp_packet_size: 1432,
-        max_udp_batch_size: 10,
         flush_interval: Duration::from_secs(2),
-        writer_type: StatsWriterType::Simple,
+        ..Default::default()
     };
     let inner_options = SharedCollectorOptions {
         stats_prefix: "myapp.".to_string(),
@@ -32,12 +32,28 @@ fuzz_target!(|data: &[u8]| {
         max_udp_batch_size: 100,
         flush_interval: Duration::from_millis(100),
         writer_type,
+        ..Default::default()
     };
     let inner_options = SharedCollectorOptions {
         stats_prefix: String::new(),
+        prefix_separator: None,
+        histogram_prefix: String::new(),
+        count_prefix: String::new(),
+        gauge_prefix: String::new(),
         histogram_configs: std::collections::HashMap::new(),
         default_histogram_config: HistogramConfig::default(),
         hasher_builder: std::hash::RandomState::new(),
+        prewarm_histograms: 0,
+        max_pooled_histograms_per_sigfig: None,
+        max_pending_aggregators: 1,
+        normalize_names: false,
+        metric_filter: None,
+        keep_zero_values: false,
+        key_idle_ttl: None,
+        skip_tag_sort: false,
+        gauge_mode: rylv_metrics::GaugeMode::Average,
+        value_rounding: None,
+        gauge_min_update_intervals: std::collections::HashMap::new(),
     };
 
     let bind_addr = "0.0.0.0:0".parse().unwrap();
@@ -0,0 +1,271 @@
+//! In-memory ring buffer of recently emitted lines, served over HTTP for debugging.
+//!
+//! [`RingBufferWriter`] is a [`StatsWriterTrait`] that keeps the last lines sent
+//! by a [`crate::MetricCollector`] instead of sending them anywhere, which helps
+//! debugging in environments without an agent. [`RecentLines`] reads them from
+//! any thread, and [`RecentLines::handle_request`] answers `GET /metrics/recent`
+//! so it can be mounted in an existing HTTP server without pulling a framework.
+//!
+//! ```no_run
+//! # #[cfg(any(feature = "shared-collector", feature = "simple-map"))] {
+//! use rylv_metrics::admin_http::RingBufferWriter;
+//! use rylv_metrics::{MetricCollector, MetricCollectorOptions, SharedCollector, StatsWriterType};
+//! use std::net::TcpListener;
+//!
+//! let writer = RingBufferWriter::new(1024);
+//! let recent = writer.recent_lines();
+//! let collector = MetricCollector::new(
+//!     "0.0.0.0:0".parse().unwrap(),
+//!     "127.0.0.1:8125".parse().unwrap(),
+//!     MetricCollectorOptions {
+//!         writer_type: StatsWriterType::Custom(Box::new(writer)),
+//!         ..Default::default()
+//!     },
+//!     SharedCollector::default(),
+//! );
+//!
+//! let listener = TcpListener::bind("127.0.0.1:9000").unwrap();
+//! for mut stream in listener.incoming().flatten() {
+//!     // A real server parses the request line; this one always serves the lines.
+//!     recent.handle_request("GET", "/metrics/recent").write_to(&mut stream).ok();
+//! }
+//! # drop(collector);
+//! # }
+//! ```
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::dogstats::writer::format_line;
+use crate::{MetricKind, MetricResult, StatsWriterTrait, TagSectionStyle};
+
+/// Path served by [`RecentLines::handle_request`].
+pub const RECENT_LINES_PATH: &str = "/metrics/recent";
+
+/// Writer keeping the most recent emitted lines in memory.
+///
+/// Lines written during a flush are published to the shared buffer together
+/// when the flush completes; once `capacity` lines are retained, the oldest are
+/// dropped.
+pub struct RingBufferWriter {
+    recent: RecentLines,
+    pending: Vec<String>,
+}
+
+impl RingBufferWriter {
+    /// Creates a writer retaining up to `capacity` lines.
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            recent: RecentLines {
+                inner: Arc::new(RecentLinesInner {
+                    lines: Mutex::new(VecDeque::with_capacity(capacity)),
+                    capacity,
+                }),
+            },
+            pending: Vec::new(),
+        }
+    }
+
+    /// Returns a handle reading the retained lines, usable from any thread.
+    #[must_use]
+    pub fn recent_lines(&self) -> RecentLines {
+        self.recent.clone()
+    }
+}
+
+impl StatsWriterTrait for RingBufferWriter {
+    fn metric_copied(&self) -> bool {
+        true
+    }
+
+    fn write(
+        &mut self,
+        metrics: &[&str],
+        tags: &str,
+        value: &str,
+        metric_type: MetricKind,
+    ) -> MetricResult<()> {
+        let mut line = String::new();
+        format_line(
+            &mut line,
+            metrics,
+            tags,
+            value,
+            metric_type,
+            TagSectionStyle::Omit,
+        );
+        // lines are stored without their terminator
+        line.pop();
+        self.pending.push(line);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> MetricResult<usize> {
+        let bytes = self.pending.iter().map(String::len).sum();
+        self.recent.push(self.pending.drain(..));
+        Ok(bytes)
+    }
+
+    fn reset(&mut self) {
+        self.pending.clear();
+    }
+
+    fn buffered_bytes(&self) -> usize {
+        self.pending.iter().map(String::len).sum()
+    }
+}
+
+/// Shared handle to the lines retained by a [`RingBufferWriter`].
+#[derive(Clone)]
+pub struct RecentLines {
+    inner: Arc<RecentLinesInner>,
+}
+
+struct RecentLinesInner {
+    lines: Mutex<VecDeque<String>>,
+    capacity: usize,
+}
+
+impl RecentLines {
+    /// Returns the retained lines, oldest first.
+    #[must_use]
+    pub fn lines(&self) -> Vec<String> {
+        self.lock().iter().cloned().collect()
+    }
+
+    /// Renders the retained lines in `DogStatsD` format, one per line, oldest first.
+    #[must_use]
+    pub fn render(&self) -> String {
+        let mut rendered = String::new();
+        for line in self.lock().iter() {
+            rendered.push_str(line);
+            rendered.push('\n');
+        }
+        rendered
+    }
+
+    /// Answers an HTTP request for [`RECENT_LINES_PATH`].
+    ///
+    /// Returns the rendered lines for `GET` on that path, `405` for other
+    /// methods on it and `404` for any other path, so it can be mounted as is.
+    #[must_use]
+    pub fn handle_request(&self, method: &str, path: &str) -> AdminResponse {
+        match (method, path) {
+            ("GET", RECENT_LINES_PATH) => AdminResponse {
+                status: 200,
+                body: self.render(),
+            },
+            (_, RECENT_LINES_PATH) => AdminResponse {
+                status: 405,
+                body: "method not allowed\n".to_owned(),
+            },
+            _ => AdminResponse {
+                status: 404,
+                body: "not found\n".to_owned(),
+            },
+        }
+    }
+
+    fn push(&self, new_lines: impl ExactSizeIterator<Item = String>) {
+        let capacity = self.inner.capacity;
+        // only the newest `capacity` lines of this batch can be kept
+        let skipped = new_lines.len().saturating_sub(capacity);
+        let mut lines = self.lock();
+        for line in new_lines.skip(skipped) {
+            if lines.len() == capacity {
+                lines.pop_front();
+            }
+            lines.push_back(line);
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, VecDeque<String>> {
+        self.inner
+            .lines
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Plain-text response produced by [`RecentLines::handle_request`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdminResponse {
+    /// HTTP status code.
+    pub status: u16,
+    /// `text/plain` body.
+    pub body: String,
+}
+
+impl AdminResponse {
+    /// Writes the response as an HTTP/1.1 message that closes the connection.
+    ///
+    /// # Errors
+    /// Returns the error of the underlying writer.
+    pub fn write_to(&self, writer: &mut impl io::Write) -> io::Result<()> {
+        let reason = match self.status {
+            200 => "OK",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            _ => "",
+        };
+        write!(
+            writer,
+            "HTTP/1.1 {} {reason}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.status,
+            self.body.len(),
+            self.body
+        )?;
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_buffer_keeps_only_the_newest_lines() {
+        let mut writer = RingBufferWriter::new(2);
+        let recent = writer.recent_lines();
+        writer
+            .write(&["app.", "hits"], "env:dev", "1", MetricKind::Count)
+            .unwrap();
+        assert!(recent.lines().is_empty());
+        assert_eq!(writer.flush().unwrap(), "app.hits:1|c|#env:dev".len());
+
+        for value in ["2", "3", "4"] {
+            writer
+                .write(&["load"], "", value, MetricKind::Gauge)
+                .unwrap();
+        }
+        writer.flush().unwrap();
+        assert_eq!(recent.render(), "load:3|g\nload:4|g\n");
+
+        writer.write(&["lat"], "", "5", MetricKind::Timing).unwrap();
+        writer.reset();
+        writer.flush().unwrap();
+        assert_eq!(recent.lines(), vec!["load:3|g", "load:4|g"]);
+    }
+
+    #[test]
+    fn handle_request_serves_recent_lines_only() {
+        let mut writer = RingBufferWriter::new(4);
+        writer.write(&["hits"], "", "1", MetricKind::Count).unwrap();
+        writer.flush().unwrap();
+        let recent = writer.recent_lines();
+
+        let response = recent.handle_request("GET", RECENT_LINES_PATH);
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, "hits:1|c\n");
+        assert_eq!(recent.handle_request("POST", RECENT_LINES_PATH).status, 405);
+        assert_eq!(recent.handle_request("GET", "/").status, 404);
+
+        let mut http = Vec::new();
+        response.write_to(&mut http).unwrap();
+        let http = String::from_utf8(http).unwrap();
+        assert!(http.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(http.ends_with("Content-Length: 9\r\nConnection: close\r\n\r\nhits:1|c\n"));
+    }
+}
@@ -1,23 +1,44 @@
-use super::histogram_config::{HistogramBaseMetric, HistogramBaseMetrics};
+#[cfg(any(
+    feature = "shared-collector",
+    feature = "simple-map",
+    feature = "tls-collector",
+    test
+))]
+use super::collector::{MetricFrameRef, MetricKind, MetricSuffix};
+use super::histogram_config::{HistogramBaseMetric, HistogramBaseMetrics, OutOfBoundsPolicy};
 use super::slice_utils::equal_slice;
 use super::sorted_tags::{
     metric_tags_fingerprint, metric_tags_fingerprint_from_tags, next_metric_id, to_static_metric,
     SortedTags,
 };
+#[cfg(any(feature = "shared-collector", feature = "simple-map"))]
+use super::tag_interner::TagInterner;
 use super::RylvStr;
-use crate::{DefaultMetricHasher, PreparedMetric};
+use crate::{DefaultMetricHasher, MetricsError, PreparedMetric};
 use hdrhistogram::Histogram;
 use std::cmp::{max, min};
 use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-
-#[cfg(feature = "shared-collector")]
+#[cfg(any(
+    feature = "shared-collector",
+    feature = "simple-map",
+    feature = "tls-collector"
+))]
+use std::time::{Duration, Instant};
+
+#[cfg(any(feature = "shared-collector", feature = "simple-map"))]
 mod shared;
+#[cfg(all(feature = "simple-map", not(feature = "shared-collector")))]
+mod simple_map;
 
-#[cfg(feature = "shared-collector")]
-pub use shared::Aggregator;
+#[cfg(any(feature = "shared-collector", feature = "simple-map"))]
+pub use shared::{
+    histogram_pools, Aggregator, HistogramPool, HistogramPools, ShardedMap, SharedValue,
+};
+#[cfg(all(feature = "simple-map", not(feature = "shared-collector")))]
+pub use simple_map::{Iter as ShardedMapIter, IterMut as ShardedMapIterMut, RefMutMulti};
 
-#[derive(Clone)]
 /// Internal benchmark-facing representation of an aggregated metric key.
 pub struct AggregatorEntryKey<S: BuildHasher + Clone = DefaultMetricHasher> {
     /// Metric name.
@@ -30,6 +51,23 @@ pub struct AggregatorEntryKey<S: BuildHasher + Clone = DefaultMetricHasher> {
     pub fingerprint: u64,
     /// Prepared metric identifier.
     pub id: u64,
+    /// Drain time, in milliseconds since the collector was created, of the last
+    /// flush window with recordings for this key. Only maintained when idle
+    /// eviction is enabled.
+    pub last_active: AtomicU64,
+}
+
+impl<S: BuildHasher + Clone> Clone for AggregatorEntryKey<S> {
+    fn clone(&self) -> Self {
+        Self {
+            metric: self.metric.clone(),
+            tags: self.tags.clone(),
+            hash: self.hash,
+            fingerprint: self.fingerprint,
+            id: self.id,
+            last_active: AtomicU64::new(self.last_active.load(Ordering::Relaxed)),
+        }
+    }
 }
 
 pub fn to_agg_entry_key<S: BuildHasher + Clone>(
@@ -41,6 +79,7 @@ pub fn to_agg_entry_key<S: BuildHasher + Clone>(
         hash: prepared_metric.hash(),
         fingerprint: prepared_metric.fingerprint(),
         id: prepared_metric.prepared_id(),
+        last_active: AtomicU64::new(0),
     }
 }
 
@@ -60,6 +99,67 @@ impl<S: BuildHasher + Clone> AggregatorEntryKey<S> {
     }
 }
 
+#[cfg(any(
+    feature = "shared-collector",
+    feature = "simple-map",
+    feature = "tls-collector"
+))]
+impl<S: BuildHasher + Clone> AggregatorEntryKey<S> {
+    /// Moves the last activity of this key forward to that of `carried`, a copy
+    /// of the same series from the drained generation.
+    pub fn carry_last_active(&self, carried: &Self) {
+        self.last_active.fetch_max(
+            carried.last_active.load(Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
+    }
+}
+
+/// Idle eviction of carried-over `keep_zero_values` series, evaluated with one
+/// timestamp per drain so recordings never read the clock.
+#[cfg(any(
+    feature = "shared-collector",
+    feature = "simple-map",
+    feature = "tls-collector"
+))]
+#[derive(Debug, Copy, Clone)]
+pub struct IdleEviction {
+    now_millis: u64,
+    ttl_millis: u64,
+}
+
+#[cfg(any(
+    feature = "shared-collector",
+    feature = "simple-map",
+    feature = "tls-collector"
+))]
+impl IdleEviction {
+    /// Returns the eviction state of a drain starting now, or `None` without a TTL.
+    pub fn at(created: Instant, ttl: Option<Duration>) -> Option<Self> {
+        let millis = |duration: Duration| u64::try_from(duration.as_millis()).unwrap_or(u64::MAX);
+        ttl.map(|ttl| Self {
+            now_millis: millis(created.elapsed()),
+            ttl_millis: millis(ttl),
+        })
+    }
+
+    /// Stamps `key` with this drain when it saw recordings, otherwise returns
+    /// whether it has been idle for at least the TTL.
+    pub fn is_expired<S: BuildHasher + Clone>(
+        self,
+        key: &AggregatorEntryKey<S>,
+        active: bool,
+    ) -> bool {
+        if active {
+            key.last_active.store(self.now_millis, Ordering::Relaxed);
+            return false;
+        }
+        self.now_millis
+            .saturating_sub(key.last_active.load(Ordering::Relaxed))
+            >= self.ttl_millis
+    }
+}
+
 impl<S: BuildHasher + Clone> Eq for AggregatorEntryKey<S> {}
 
 impl<S: BuildHasher + Clone> PartialEq for AggregatorEntryKey<S> {
@@ -79,27 +179,62 @@ impl<S: BuildHasher + Clone> PartialEq for AggregatorEntryKey<S> {
 pub struct LookupKey<'a> {
     /// Metric name.
     pub metric: RylvStr<'a>,
-    /// Sorted tag slice.
+    /// Tag slice, sorted unless `sorted` is `false`.
     pub tags: &'a [RylvStr<'a>],
-    /// Hash of the sorted tag set.
+    /// Order-independent hash of the tag set.
     pub tags_hash: u64,
     /// Combined metric-and-tags hash.
     pub hash: u64,
+    /// Whether `tags` is sorted. Unsorted tags are compared regardless of
+    /// order and only sorted, on a copy, when the key is created.
+    pub sorted: bool,
 }
 
 impl LookupKey<'_> {
+    #[cfg(feature = "tls-collector")]
     pub(crate) fn into_key<S: BuildHasher + Clone>(self) -> AggregatorEntryKey<S> {
         self.into_key_with_id(next_metric_id())
     }
 
+    #[cfg(any(feature = "tls-collector", test))]
     pub(crate) fn into_key_with_id<S: BuildHasher + Clone>(self, id: u64) -> AggregatorEntryKey<S> {
-        let fingerprint = metric_tags_fingerprint_from_tags(self.metric.as_ref(), self.tags);
+        self.into_key_with(id, SortedTags::from_sorted_tags_with_hash)
+    }
+
+    /// Like [`Self::into_key`], taking the joined tags from `interner` so a tag
+    /// set recorded again in a later flush window reuses its buffers.
+    #[cfg(any(feature = "shared-collector", feature = "simple-map"))]
+    pub(crate) fn into_key_interned<S: BuildHasher + Clone>(
+        self,
+        interner: &TagInterner,
+    ) -> AggregatorEntryKey<S> {
+        self.into_key_with(next_metric_id(), |tags, tags_hash| {
+            SortedTags::from_interned(interner.intern(tags, tags_hash), tags_hash)
+        })
+    }
+
+    fn into_key_with<S: BuildHasher + Clone>(
+        self,
+        id: u64,
+        sorted_tags: impl FnOnce(&[RylvStr<'_>], u64) -> SortedTags<S>,
+    ) -> AggregatorEntryKey<S> {
+        let sorted_copy;
+        let tags = if self.sorted {
+            self.tags
+        } else {
+            let mut tags = self.tags.to_vec();
+            tags.sort_unstable();
+            sorted_copy = tags;
+            &sorted_copy
+        };
+        let fingerprint = metric_tags_fingerprint_from_tags(self.metric.as_ref(), tags);
         AggregatorEntryKey {
             metric: to_static_metric(self.metric),
-            tags: SortedTags::from_sorted_tags_with_hash(self.tags, self.tags_hash),
+            tags: sorted_tags(tags, self.tags_hash),
             hash: self.hash,
             fingerprint,
             id,
+            last_active: AtomicU64::new(0),
         }
     }
 
@@ -111,41 +246,23 @@ impl LookupKey<'_> {
         c.hash == self.hash
             && m1.len() == m2.len()
             && equal_slice(m1.as_bytes(), m2.as_bytes())
-            && self.compare_tags_joined(c.tags.joined_tags(), c.tags.len())
+            && self.compare_tags(&c.tags)
     }
 
-    fn compare_tags_joined(&self, joined_tags: &str, tag_count: usize) -> bool {
+    fn compare_tags<S: BuildHasher + Clone>(&self, tags: &SortedTags<S>) -> bool {
         let compare = self.tags;
-        if tag_count != compare.len() {
+        if tags.len() != compare.len() {
             return false;
         }
-        if joined_tags.len() != Self::joined_tags_len(compare) {
+        if tags.joined_tags().len() != Self::joined_tags_len(compare) {
             return false;
         }
-        if compare.is_empty() {
-            return joined_tags.is_empty();
-        }
-
-        let joined = joined_tags.as_bytes();
-        let mut offset = 0usize;
-        let last_index = compare.len() - 1;
-        for (index, tag) in compare.iter().enumerate() {
-            let tag_bytes = tag.as_ref().as_bytes();
-            let next_offset = offset + tag_bytes.len();
-            if next_offset > joined.len() || !equal_slice(&joined[offset..next_offset], tag_bytes) {
-                return false;
-            }
-            offset = next_offset;
-
-            if index < last_index {
-                if offset >= joined.len() || joined[offset] != b',' {
-                    return false;
-                }
-                offset += 1;
-            }
-        }
 
-        offset == joined.len()
+        let in_order = compare
+            .iter()
+            .zip(tags.iter())
+            .all(|(tag, stored)| equal_slice(tag.as_ref().as_bytes(), stored.as_bytes()));
+        in_order || !self.sorted && tags.matches_unordered(compare)
     }
 
     fn joined_tags_len(tags: &[RylvStr<'_>]) -> usize {
@@ -182,18 +299,38 @@ impl<S: BuildHasher + Clone> LookupKeySorted<'_, S> {
             hash: self.hash,
             fingerprint,
             id,
+            last_active: AtomicU64::new(0),
         }
     }
 }
 
+/// Position of a drain within the samples of a distribution histogram.
+#[cfg(any(
+    feature = "shared-collector",
+    feature = "simple-map",
+    feature = "tls-collector",
+    test
+))]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DistributionCursor {
+    /// Lowest value of the bucket being returned, `None` before the first one.
+    bucket: Option<u64>,
+    /// Samples of that bucket left to return.
+    remaining: u64,
+}
+
 #[derive(Clone)]
 pub struct HistogramWrapper {
     pub pool_id: usize,
     pub min: u64,
     pub max: u64,
+    pub sum: u64,
+    /// Values above the upper bound left out under `OutOfBoundsPolicy::CountOverflow`.
+    pub overflow_count: u64,
     pub histogram: Histogram<u64>,
     pub percentiles: Arc<[f64]>,
     pub emit_base_metrics: HistogramBaseMetrics,
+    pub unit: Option<&'static str>,
 }
 
 impl HistogramWrapper {
@@ -201,15 +338,196 @@ impl HistogramWrapper {
         self.emit_base_metrics.contains(metric)
     }
 
+    /// Returns `true` when nothing was recorded or counted as overflow.
+    #[cfg(any(
+        feature = "shared-collector",
+        feature = "simple-map",
+        feature = "tls-collector"
+    ))]
+    pub fn is_empty(&self) -> bool {
+        self.histogram.is_empty() && self.overflow_count == 0
+    }
+
+    /// Returns `true` when the samples are sent as a distribution.
+    #[cfg(any(
+        feature = "shared-collector",
+        feature = "simple-map",
+        feature = "tls-collector",
+        test
+    ))]
+    pub const fn is_distribution(&self) -> bool {
+        self.emit_base_metrics.is_distribution()
+    }
+
+    /// Returns the number of percentile frames to emit, none when only
+    /// overflows were counted or the samples are sent as a distribution.
+    #[cfg(any(
+        feature = "shared-collector",
+        feature = "simple-map",
+        feature = "tls-collector",
+        test
+    ))]
+    pub fn emitted_percentiles(&self) -> usize {
+        if self.histogram.is_empty() || self.is_distribution() {
+            0
+        } else {
+            self.percentiles.len()
+        }
+    }
+
+    /// Returns the value at `quantile` computed with the configured `PercentileMethod`.
+    #[cfg(any(
+        feature = "shared-collector",
+        feature = "simple-map",
+        feature = "tls-collector",
+        test
+    ))]
+    pub fn value_at_quantile(&self, quantile: f64) -> u64 {
+        self.emit_base_metrics
+            .percentile_method()
+            .value_at_quantile(&self.histogram, quantile)
+    }
+
     pub fn reset(&mut self) {
         self.min = u64::MAX;
         self.max = u64::MIN;
+        self.sum = 0;
+        self.overflow_count = 0;
         self.histogram.reset();
     }
 
-    pub fn record(&mut self, value: u64) -> Result<(), hdrhistogram::RecordError> {
+    /// Builds the frame for `base` when this histogram emits it.
+    #[cfg(any(
+        feature = "shared-collector",
+        feature = "simple-map",
+        feature = "tls-collector",
+        test
+    ))]
+    pub fn base_metric_frame<'a>(
+        &self,
+        base: HistogramBaseMetric,
+        prefix: &'a str,
+        metric: &'a str,
+        tags: &'a str,
+    ) -> Option<MetricFrameRef<'a>> {
+        if !self.emits(base) || self.histogram.is_empty() || self.is_distribution() {
+            return None;
+        }
+        let mut unit = self.unit;
+        let (suffix, value, kind) = match base {
+            HistogramBaseMetric::Count => {
+                unit = None;
+                (".count", self.histogram.len(), MetricKind::Count)
+            }
+            HistogramBaseMetric::Min => (".min", self.min, MetricKind::Gauge),
+            HistogramBaseMetric::Avg => (".avg", self.value_at_quantile(0.50), MetricKind::Gauge),
+            HistogramBaseMetric::Max => (".max", self.max, MetricKind::Gauge),
+            HistogramBaseMetric::Sum => (
+                ".sum",
+                self.sum,
+                self.emit_base_metrics.sum_kind().metric_kind(),
+            ),
+        };
+        Some(MetricFrameRef {
+            prefix,
+            metric,
+            suffix: MetricSuffix::Static(suffix),
+            tags,
+            unit,
+            value,
+            float_value: None,
+            kind,
+        })
+    }
+
+    /// Builds the `|d` frame of the next recorded sample after `cursor` and
+    /// advances it, or returns `None` once every sample was returned.
+    ///
+    /// Samples are returned in ascending order, as the highest value of their
+    /// bucket capped by the exact maximum.
+    #[cfg(any(
+        feature = "shared-collector",
+        feature = "simple-map",
+        feature = "tls-collector",
+        test
+    ))]
+    pub fn next_distribution_frame<'a>(
+        &self,
+        cursor: &mut DistributionCursor,
+        prefix: &'a str,
+        metric: &'a str,
+        tags: &'a str,
+    ) -> Option<MetricFrameRef<'a>> {
+        if !self.is_distribution() {
+            return None;
+        }
+        while cursor.remaining == 0 {
+            let next = cursor.bucket.map_or_else(
+                || self.histogram.min(),
+                |bucket| self.histogram.next_non_equivalent(bucket),
+            );
+            // `next_non_equivalent` saturates at the last bucket
+            if next > self.histogram.max() || cursor.bucket == Some(next) {
+                return None;
+            }
+            cursor.bucket = Some(next);
+            cursor.remaining = self.histogram.count_at(next);
+        }
+        cursor.remaining -= 1;
+        let bucket = cursor.bucket?;
+        Some(MetricFrameRef {
+            prefix,
+            metric,
+            suffix: MetricSuffix::None,
+            tags,
+            unit: self.unit,
+            value: self.histogram.highest_equivalent(bucket).min(self.max),
+            float_value: None,
+            kind: MetricKind::Distribution,
+        })
+    }
+
+    /// Builds the `.overflow_count` frame when values above the upper bound were counted.
+    #[cfg(any(
+        feature = "shared-collector",
+        feature = "simple-map",
+        feature = "tls-collector"
+    ))]
+    pub fn overflow_frame<'a>(
+        &self,
+        prefix: &'a str,
+        metric: &'a str,
+        tags: &'a str,
+    ) -> Option<MetricFrameRef<'a>> {
+        (self.overflow_count > 0).then_some(MetricFrameRef {
+            prefix,
+            metric,
+            suffix: MetricSuffix::Static(".overflow_count"),
+            tags,
+            unit: None,
+            value: self.overflow_count,
+            float_value: None,
+            kind: MetricKind::Count,
+        })
+    }
+
+    pub fn record(&mut self, mut value: u64) -> Result<(), hdrhistogram::RecordError> {
+        let high = self.histogram.high();
+        if value > high {
+            match self.emit_base_metrics.out_of_bounds() {
+                OutOfBoundsPolicy::Drop => {
+                    return Err(hdrhistogram::RecordError::ValueOutOfRangeResizeDisabled)
+                }
+                OutOfBoundsPolicy::Clamp => value = high,
+                OutOfBoundsPolicy::CountOverflow => {
+                    self.overflow_count += 1;
+                    return Ok(());
+                }
+            }
+        }
         self.min = min(self.min, value);
         self.max = max(self.max, value);
+        self.sum = self.sum.saturating_add(value);
         self.histogram.record(value)
     }
 }
@@ -223,10 +541,21 @@ const _: () = assert!(SIG_FIG_DEF <= SIG_FIG_MAX);
 /// Higher values increase precision but also memory usage.
 /// Use [`SigFig::default()`] for the default value of 3.
 ///
-/// For compile-time validated constants, use the associated constants:
+/// For compile-time validated constants, use the associated constants or
+/// [`SigFig::new_const`]; runtime values go through `TryFrom<u8>`:
 /// ```
 /// use rylv_metrics::SigFig;
 /// const MY_SIG_FIG: SigFig = SigFig::THREE;
+/// const FROM_LITERAL: SigFig = SigFig::new_const::<2>();
+/// assert_eq!(SigFig::try_from(2).ok(), Some(FROM_LITERAL));
+/// assert!(SigFig::try_from(6).is_err());
+/// assert_eq!(u8::from(MY_SIG_FIG), 3);
+/// ```
+///
+/// Out-of-range literals are rejected at compile time:
+/// ```compile_fail
+/// use rylv_metrics::SigFig;
+/// const TOO_PRECISE: SigFig = SigFig::new_const::<6>();
 /// ```
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 #[repr(transparent)]
@@ -248,6 +577,15 @@ impl SigFig {
     /// 5 significant figures (maximum).
     pub const FIVE: Self = Self { value: 5 };
 
+    /// Creates a `SigFig` from a literal, checked at compile time against the `0..=5` range.
+    #[must_use]
+    pub const fn new_const<const N: u8>() -> Self {
+        const {
+            assert!(N <= SIG_FIG_MAX, "SigFig must be in range 0..=5");
+        }
+        Self { value: N }
+    }
+
     /// Returns the number of significant figures.
     #[must_use]
     pub const fn value(self) -> u8 {
@@ -255,6 +593,25 @@ impl SigFig {
     }
 }
 
+impl TryFrom<u8> for SigFig {
+    type Error = MetricsError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        if value > SIG_FIG_MAX {
+            return Err(
+                format!("Invalid SigFig {value}: must be in range 0..={SIG_FIG_MAX}").into(),
+            );
+        }
+        Ok(Self { value })
+    }
+}
+
+impl From<SigFig> for u8 {
+    fn from(sig_fig: SigFig) -> Self {
+        sig_fig.value
+    }
+}
+
 impl Default for SigFig {
     fn default() -> Self {
         Self::THREE
@@ -264,12 +621,15 @@ impl Default for SigFig {
 #[cfg(test)]
 mod tests {
     use super::{
-        to_agg_entry_key, AggregatorEntryKey, HistogramWrapper, LookupKey, LookupKeySorted, SigFig,
+        to_agg_entry_key, AggregatorEntryKey, DistributionCursor, HistogramWrapper, LookupKey,
+        LookupKeySorted, SigFig,
     };
+    use crate::dogstats::collector::MetricKind;
     use crate::dogstats::histogram_config::{HistogramBaseMetric, HistogramBaseMetrics};
     use crate::dogstats::sorted_tags::{combine_metric_tags_hash, hash_tags, SortedTags};
     use crate::{PreparedMetric, RylvStr};
     use hdrhistogram::Histogram;
+    use std::sync::atomic::AtomicU64;
     use std::sync::Arc;
 
     type TestHasher = std::hash::BuildHasherDefault<std::collections::hash_map::DefaultHasher>;
@@ -305,6 +665,7 @@ mod tests {
             hash: 1,
             fingerprint: 10,
             id: 99,
+            last_active: AtomicU64::new(0),
         };
         let same_id_right = AggregatorEntryKey {
             metric: RylvStr::from_static("right"),
@@ -312,6 +673,7 @@ mod tests {
             hash: 2,
             fingerprint: 11,
             id: 99,
+            last_active: AtomicU64::new(0),
         };
         assert!(same_id_left == same_id_right);
 
@@ -321,6 +683,7 @@ mod tests {
             hash: 44,
             fingerprint: 55,
             id: 1,
+            last_active: AtomicU64::new(0),
         };
         let signature_right = AggregatorEntryKey {
             metric: RylvStr::from_static("metric"),
@@ -328,6 +691,7 @@ mod tests {
             hash: 44,
             fingerprint: 55,
             id: 2,
+            last_active: AtomicU64::new(0),
         };
         assert!(signature_left == signature_right);
     }
@@ -346,6 +710,7 @@ mod tests {
             tags: &tags,
             tags_hash,
             hash,
+            sorted: true,
         };
 
         let entry = lookup.into_key_with_id::<TestHasher>(7);
@@ -358,6 +723,7 @@ mod tests {
             tags: &tags,
             tags_hash,
             hash,
+            sorted: true,
         };
         assert!(matching_lookup.compare(&entry));
 
@@ -366,6 +732,7 @@ mod tests {
             tags: &tags,
             tags_hash,
             hash,
+            sorted: true,
         };
         assert!(!mismatched_metric.compare(&entry));
 
@@ -375,6 +742,7 @@ mod tests {
             tags: &short_tags,
             tags_hash: hash_tags(&hasher, &short_tags),
             hash,
+            sorted: true,
         };
         assert!(!mismatched_tag_count.compare(&entry));
 
@@ -384,12 +752,14 @@ mod tests {
             hash,
             fingerprint: entry.fingerprint,
             id: entry.id + 1,
+            last_active: AtomicU64::new(0),
         };
         let lookup_for_separator = LookupKey {
             metric: RylvStr::from_static("bench.metric"),
             tags: &tags,
             tags_hash,
             hash,
+            sorted: true,
         };
         assert!(!lookup_for_separator.compare(&bad_separator_entry));
     }
@@ -431,12 +801,15 @@ mod tests {
             pool_id: 0,
             min: u64::MAX,
             max: u64::MIN,
+            sum: 0,
+            overflow_count: 0,
             histogram: Histogram::new_with_bounds(1, 1_000, 3).unwrap(),
             percentiles: Arc::from([0.95_f64]),
             emit_base_metrics: HistogramBaseMetrics::from([
                 HistogramBaseMetric::Count,
                 HistogramBaseMetric::Max,
             ]),
+            unit: Some("ms"),
         };
 
         assert!(wrapper.emits(HistogramBaseMetric::Count));
@@ -446,6 +819,13 @@ mod tests {
         assert_eq!(wrapper.min, 42);
         assert_eq!(wrapper.max, 84);
         assert_eq!(wrapper.histogram.len(), 2);
+        let count = wrapper
+            .base_metric_frame(HistogramBaseMetric::Count, "", "latency", "")
+            .unwrap();
+        let max = wrapper
+            .base_metric_frame(HistogramBaseMetric::Max, "", "latency", "")
+            .unwrap();
+        assert_eq!((count.unit, max.unit), (None, Some("ms")));
 
         wrapper.reset();
         assert_eq!(wrapper.min, u64::MAX);
@@ -453,10 +833,57 @@ mod tests {
         assert_eq!(wrapper.histogram.len(), 0);
     }
 
+    #[test]
+    fn distribution_frames_return_every_sample_once() {
+        let mut wrapper = HistogramWrapper {
+            pool_id: 0,
+            min: u64::MAX,
+            max: u64::MIN,
+            sum: 0,
+            overflow_count: 0,
+            histogram: Histogram::new_with_bounds(1, 100_000, 2).unwrap(),
+            percentiles: Arc::from([0.95_f64]),
+            emit_base_metrics: HistogramBaseMetrics::DEFAULT.with_distribution(true),
+            unit: Some("ms"),
+        };
+        let mut cursor = DistributionCursor::default();
+        assert!(wrapper
+            .next_distribution_frame(&mut cursor, "", "latency", "")
+            .is_none());
+
+        for value in [7, 3, 7, 12_345] {
+            wrapper.record(value).unwrap();
+        }
+        let mut cursor = DistributionCursor::default();
+        let frames = std::iter::from_fn(|| {
+            wrapper.next_distribution_frame(&mut cursor, "app.", "latency", "env:dev")
+        })
+        .collect::<Vec<_>>();
+        let values = frames.iter().map(|frame| frame.value).collect::<Vec<_>>();
+        // 12_345 falls in a 128-wide bucket at 2 significant figures, capped by the max
+        assert_eq!(values, vec![3, 7, 7, 12_345]);
+        assert!(frames
+            .iter()
+            .all(|frame| frame.kind == MetricKind::Distribution
+                && frame.unit == Some("ms")
+                && frame.tags == "env:dev"));
+
+        assert!(wrapper
+            .base_metric_frame(HistogramBaseMetric::Count, "", "latency", "")
+            .is_none());
+        assert_eq!(wrapper.emitted_percentiles(), 0);
+    }
+
     #[test]
     fn sig_fig_validates_range() {
         assert_eq!(SigFig::ZERO.value(), 0);
         assert_eq!(SigFig::FIVE.value(), 5);
         assert_eq!(SigFig::default().value(), 3);
+        assert_eq!(SigFig::new_const::<4>(), SigFig::FOUR);
+        for value in 0..=5 {
+            assert_eq!(u8::from(SigFig::try_from(value).unwrap()), value);
+        }
+        assert!(SigFig::try_from(6).is_err());
+        assert!(SigFig::try_from(u8::MAX).is_err());
     }
 }
@@ -1,44 +1,155 @@
 use super::{AggregatorEntryKey, HistogramWrapper};
 use crate::dogstats::collector::GaugeState;
-use crate::dogstats::histogram_config::ResolvedHistogramConfig;
+use crate::dogstats::histogram_config::{
+    HistogramBaseMetrics, HistogramPoolSpec, ResolvedHistogramConfig,
+};
+use crate::dogstats::tag_interner::TagInterner;
 use crate::DefaultMetricHasher;
 use crossbeam::queue::SegQueue;
-use dashmap::DashMap;
 use hdrhistogram::Histogram;
 use std::hash::{BuildHasher, Hash, Hasher};
-use std::sync::atomic::AtomicU64;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::error;
 
 impl<S: BuildHasher + Clone> Hash for AggregatorEntryKey<S> {
     fn hash<H: Hasher>(&self, _state: &mut H) {
-        // SAFETY: DashMap uses the raw hash stored in `AggregatorEntryKey::hash`
+        // SAFETY: the sharded map uses the raw hash stored in `AggregatorEntryKey::hash`
         // via `determine_shard`; this `Hash` impl is never called.
         unsafe { std::hint::unreachable_unchecked() }
     }
 }
 
+#[cfg(not(feature = "shared-collector"))]
+pub use super::simple_map::{SharedValue, SimpleMap as ShardedMap};
+/// Sharded map holding one metric kind of an aggregator generation: `DashMap`, or
+/// the minimal `Mutex`-sharded map when only the `simple-map` feature is enabled.
+#[cfg(feature = "shared-collector")]
+pub use dashmap::{DashMap as ShardedMap, SharedValue};
+
+/// Histogram pools indexed by pool id.
+///
+/// A collector shares one set of pools between all of its aggregator generations,
+/// so histograms recycled while draining one generation are reused by the next.
+pub type HistogramPools = Arc<[HistogramPool]>;
+
+/// Recycled histograms of one precision/bounds pair, holding at most `capacity`.
+///
+/// `SegQueue` cannot be bounded, so the number of pooled histograms is tracked
+/// next to it and histograms returned to a full pool are dropped.
+pub struct HistogramPool {
+    histograms: SegQueue<HistogramWrapper>,
+    len: AtomicUsize,
+    capacity: usize,
+}
+
+impl HistogramPool {
+    pub const fn new(capacity: usize) -> Self {
+        Self {
+            histograms: SegQueue::new(),
+            len: AtomicUsize::new(0),
+            capacity,
+        }
+    }
+
+    /// Number of pooled histograms.
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    pub fn pop(&self) -> Option<HistogramWrapper> {
+        let histogram = self.histograms.pop()?;
+        self.len.fetch_sub(1, Ordering::AcqRel);
+        Some(histogram)
+    }
+
+    /// Pools `histogram`, or drops it if the pool is full. Returns whether it was pooled.
+    pub fn push(&self, histogram: HistogramWrapper) -> bool {
+        let reserved = self
+            .len
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |len| {
+                (len < self.capacity).then_some(len + 1)
+            })
+            .is_ok();
+        if reserved {
+            self.histograms.push(histogram);
+        }
+        reserved
+    }
+}
+
 pub struct Aggregator<S = DefaultMetricHasher>
 where
     S: BuildHasher + Clone,
 {
-    pub histograms: DashMap<AggregatorEntryKey<S>, HistogramWrapper, S>,
-    pub count: DashMap<AggregatorEntryKey<S>, AtomicU64, S>,
-    pub gauge: DashMap<AggregatorEntryKey<S>, GaugeState, S>,
-    pub pool_histograms: Vec<SegQueue<HistogramWrapper>>,
+    pub histograms: ShardedMap<AggregatorEntryKey<S>, HistogramWrapper, S>,
+    pub count: ShardedMap<AggregatorEntryKey<S>, AtomicU64, S>,
+    /// Totals recorded through `count_add_f64`, stored as `f64` bits.
+    pub float_counts: ShardedMap<AggregatorEntryKey<S>, AtomicU64, S>,
+    pub gauge: ShardedMap<AggregatorEntryKey<S>, GaugeState, S>,
+    /// Values recorded through `gauge_last`, `UNSET_LAST_GAUGE` until written
+    /// in the current window.
+    pub last_gauges: ShardedMap<AggregatorEntryKey<S>, AtomicU64, S>,
+    /// Deltas recorded through `rate`, divided by the window length on drain.
+    pub rates: ShardedMap<AggregatorEntryKey<S>, AtomicU64, S>,
+    /// When this generation started recording.
+    pub window_start: Instant,
+    /// When this generation was swapped out; set once per recording window.
+    pub window_end: OnceLock<Instant>,
+    pub pool_histograms: HistogramPools,
+    /// Histograms that could not be created, shared by every aggregator generation.
+    pub histogram_creation_failures: Arc<AtomicU64>,
+    /// Joined tags of the keys, shared by every aggregator generation.
+    pub tag_interner: Arc<TagInterner>,
 }
 
 impl<S> Aggregator<S>
 where
     S: BuildHasher + Clone,
 {
-    pub(crate) fn with_hasher_builder(hasher_builder: &S, pool_count: usize) -> Self {
+    pub(crate) fn with_hasher_builder(
+        hasher_builder: &S,
+        pool_histograms: HistogramPools,
+        histogram_creation_failures: Arc<AtomicU64>,
+        tag_interner: Arc<TagInterner>,
+    ) -> Self {
         Self {
-            histograms: DashMap::with_hasher(hasher_builder.clone()),
-            count: DashMap::with_hasher(hasher_builder.clone()),
-            gauge: DashMap::with_hasher(hasher_builder.clone()),
-            pool_histograms: (0..pool_count).map(|_| SegQueue::new()).collect(),
+            histograms: ShardedMap::with_hasher(hasher_builder.clone()),
+            count: ShardedMap::with_hasher(hasher_builder.clone()),
+            float_counts: ShardedMap::with_hasher(hasher_builder.clone()),
+            gauge: ShardedMap::with_hasher(hasher_builder.clone()),
+            last_gauges: ShardedMap::with_hasher(hasher_builder.clone()),
+            rates: ShardedMap::with_hasher(hasher_builder.clone()),
+            window_start: Instant::now(),
+            window_end: OnceLock::new(),
+            pool_histograms,
+            histogram_creation_failures,
+            tag_interner,
         }
     }
 
+    /// Starts a new recording window before the generation is installed again.
+    pub(crate) fn open_window(&mut self) {
+        self.window_start = Instant::now();
+        self.window_end = OnceLock::new();
+    }
+
+    /// Ends the recording window once the generation is swapped out.
+    pub(crate) fn close_window(&self) {
+        let _ = self.window_end.set(Instant::now());
+    }
+
+    /// Returns how long this generation recorded, up to now if still open.
+    pub(crate) fn window(&self) -> Duration {
+        self.window_end
+            .get()
+            .copied()
+            .unwrap_or_else(Instant::now)
+            .saturating_duration_since(self.window_start)
+    }
+
     pub(crate) fn get_histogram(
         &self,
         pool_id: usize,
@@ -50,52 +161,141 @@ where
         if let Some(mut h) = unsafe { self.pool_histograms.get_unchecked(pool_id) }.pop() {
             h.percentiles = config.percentiles().clone();
             h.emit_base_metrics = config.emit_base_metrics();
+            h.unit = config.unit();
             return Some(h);
         }
 
         let bounds = config.bounds();
-        if let Ok(histo) =
-            Histogram::new_with_bounds(bounds.min(), bounds.max(), config.sig_fig().value())
-        {
-            return Some(HistogramWrapper {
+        match Histogram::new_with_bounds(bounds.min(), bounds.max(), config.sig_fig().value()) {
+            Ok(histo) => Some(HistogramWrapper {
                 pool_id,
                 histogram: histo,
                 min: u64::MAX,
                 max: u64::MIN,
+                sum: 0,
+                overflow_count: 0,
                 percentiles: config.percentiles().clone(),
                 emit_base_metrics: config.emit_base_metrics(),
-            });
+                unit: config.unit(),
+            }),
+            Err(err) => {
+                self.histogram_creation_failures
+                    .fetch_add(1, Ordering::Relaxed);
+                error!(
+                    "Fail to create histogram with bounds [{}, {}]: {err:?}",
+                    bounds.min(),
+                    bounds.max()
+                );
+                None
+            }
         }
+    }
+}
+
+/// Builds one histogram pool per entry of `pool_specs`, each holding
+/// `prewarm_histograms` empty histograms and bounded to `capacity`.
+#[cold]
+pub fn histogram_pools(
+    pool_specs: &[HistogramPoolSpec],
+    prewarm_histograms: usize,
+    capacity: usize,
+) -> HistogramPools {
+    pool_specs
+        .iter()
+        .enumerate()
+        .map(|(pool_id, pool_spec)| {
+            prewarmed_pool(
+                pool_id,
+                *pool_spec,
+                prewarm_histograms.min(capacity),
+                capacity,
+            )
+        })
+        .collect()
+}
 
-        None
+/// Builds a histogram pool holding `prewarm_histograms` empty histograms for `pool_spec`.
+///
+/// Percentiles and base metrics are placeholders; they are refreshed from the
+/// metric config every time a histogram is taken out of the pool.
+#[cold]
+fn prewarmed_pool(
+    pool_id: usize,
+    pool_spec: HistogramPoolSpec,
+    prewarm_histograms: usize,
+    capacity: usize,
+) -> HistogramPool {
+    let pool = HistogramPool::new(capacity);
+    if prewarm_histograms == 0 {
+        return pool;
     }
+    let percentiles: Arc<[f64]> = Arc::from([]);
+    let bounds = pool_spec.bounds;
+    for _ in 0..prewarm_histograms {
+        let Ok(histogram) =
+            Histogram::new_with_bounds(bounds.min(), bounds.max(), pool_spec.sig_fig.value())
+        else {
+            break;
+        };
+        pool.push(HistogramWrapper {
+            pool_id,
+            histogram,
+            min: u64::MAX,
+            max: u64::MIN,
+            sum: 0,
+            overflow_count: 0,
+            percentiles: percentiles.clone(),
+            emit_base_metrics: HistogramBaseMetrics::DEFAULT,
+            unit: None,
+        });
+    }
+    pool
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Aggregator;
+    use super::{histogram_pools, Aggregator};
     use crate::dogstats::aggregator::HistogramWrapper;
     use crate::dogstats::histogram_config::HistogramBaseMetrics;
-    use crate::dogstats::histogram_config::{HistogramConfig, ResolvedHistogramConfig};
+    use crate::dogstats::histogram_config::{
+        HistogramConfig, HistogramPoolSpec, ResolvedHistogramConfig,
+    };
     use crate::{HistogramBaseMetric, SigFig};
     use hdrhistogram::Histogram;
     use std::sync::Arc;
 
     type TestHasher = std::hash::RandomState;
 
+    fn default_pool_specs(pool_count: usize) -> Vec<HistogramPoolSpec> {
+        vec![HistogramPoolSpec::from_config(&HistogramConfig::default()); pool_count]
+    }
+
     #[test]
     fn with_hasher_builder_creates_requested_pool_count() {
-        let aggregator = Aggregator::<TestHasher>::with_hasher_builder(&TestHasher::new(), 3);
+        let aggregator = Aggregator::<TestHasher>::with_hasher_builder(
+            &TestHasher::new(),
+            histogram_pools(&default_pool_specs(3), 0, usize::MAX),
+            Arc::default(),
+            Arc::default(),
+        );
 
         assert_eq!(aggregator.pool_histograms.len(), 3);
         assert!(aggregator.histograms.is_empty());
         assert!(aggregator.count.is_empty());
+        assert!(aggregator.float_counts.is_empty());
         assert!(aggregator.gauge.is_empty());
+        assert!(aggregator.last_gauges.is_empty());
+        assert!(aggregator.rates.is_empty());
     }
 
     #[test]
     fn get_histogram_creates_new_wrapper_when_pool_is_empty() {
-        let aggregator = Aggregator::<TestHasher>::with_hasher_builder(&TestHasher::new(), 1);
+        let aggregator = Aggregator::<TestHasher>::with_hasher_builder(
+            &TestHasher::new(),
+            histogram_pools(&default_pool_specs(1), 0, usize::MAX),
+            Arc::default(),
+            Arc::default(),
+        );
         let config = ResolvedHistogramConfig::from_config(HistogramConfig::default(), 0);
 
         let wrapper = aggregator.get_histogram(0, &config).unwrap();
@@ -108,7 +308,12 @@ mod tests {
 
     #[test]
     fn get_histogram_reuses_pool_entry_and_refreshes_metadata() {
-        let aggregator = Aggregator::<TestHasher>::with_hasher_builder(&TestHasher::new(), 1);
+        let aggregator = Aggregator::<TestHasher>::with_hasher_builder(
+            &TestHasher::new(),
+            histogram_pools(&default_pool_specs(1), 0, usize::MAX),
+            Arc::default(),
+            Arc::default(),
+        );
         let config = ResolvedHistogramConfig::from_config(
             HistogramConfig::new(SigFig::default(), vec![0.5])
                 .unwrap()
@@ -124,9 +329,12 @@ mod tests {
             pool_id: 0,
             min: 1,
             max: 2,
+            sum: 3,
+            overflow_count: 0,
             histogram: Histogram::new_with_bounds(1, u64::MAX, 3).unwrap(),
             percentiles: Arc::from([0.95_f64, 0.99_f64]),
-            emit_base_metrics: HistogramBaseMetrics::ALL,
+            emit_base_metrics: HistogramBaseMetrics::DEFAULT,
+            unit: None,
         });
 
         let wrapper = aggregator.get_histogram(0, &config).unwrap();
@@ -135,4 +343,25 @@ mod tests {
         assert_eq!(wrapper.percentiles.as_ref(), &[0.5]);
         assert_eq!(wrapper.emit_base_metrics, config.emit_base_metrics());
     }
+
+    #[test]
+    fn histogram_pools_prewarm_every_pool() {
+        let aggregator = Aggregator::<TestHasher>::with_hasher_builder(
+            &TestHasher::new(),
+            histogram_pools(&default_pool_specs(2), 4, usize::MAX),
+            Arc::default(),
+            Arc::default(),
+        );
+        let config = ResolvedHistogramConfig::from_config(HistogramConfig::default(), 1);
+
+        assert_eq!(aggregator.pool_histograms[0].len(), 4);
+        assert_eq!(aggregator.pool_histograms[1].len(), 4);
+
+        let wrapper = aggregator.get_histogram(1, &config).unwrap();
+
+        assert_eq!(aggregator.pool_histograms[1].len(), 3);
+        assert_eq!(wrapper.pool_id, 1);
+        assert_eq!(wrapper.percentiles.as_ref(), &[0.95, 0.99]);
+        assert_eq!(wrapper.emit_base_metrics, config.emit_base_metrics());
+    }
 }
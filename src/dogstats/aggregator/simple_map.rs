@@ -0,0 +1,232 @@
+use crossbeam::utils::CachePadded;
+use hashbrown_raw::raw::{Bucket, RawIter, RawTable};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// Number of shards of a [`SimpleMap`]; sized for low-concurrency targets.
+const SHARD_AMOUNT: usize = 8;
+
+type ShardEntry<K, V> = (K, SharedValue<V>);
+type Shard<K, V> = CachePadded<Mutex<RawTable<ShardEntry<K, V>>>>;
+
+/// Minimal sharded map used by the shared collector with the `simple-map` feature.
+///
+/// Provides the part of the `DashMap` raw API the collector relies on: entries
+/// live in `hashbrown` raw tables behind one `Mutex` per shard, and callers pick
+/// the shard themselves through [`SimpleMap::determine_shard`] and
+/// [`SimpleMap::shards`] using the hash stored in the key.
+pub struct SimpleMap<K, V, S> {
+    shift: u32,
+    shards: Box<[Shard<K, V>]>,
+    hasher: S,
+}
+
+impl<K, V, S> SimpleMap<K, V, S> {
+    /// Creates an empty map using `hasher` to hash lookup keys.
+    pub fn with_hasher(hasher: S) -> Self {
+        Self {
+            shift: usize::BITS - SHARD_AMOUNT.trailing_zeros(),
+            shards: (0..SHARD_AMOUNT)
+                .map(|_| CachePadded::new(Mutex::new(RawTable::new())))
+                .collect(),
+            hasher,
+        }
+    }
+
+    pub const fn hasher(&self) -> &S {
+        &self.hasher
+    }
+
+    /// Returns the shard index for `hash`.
+    pub const fn determine_shard(&self, hash: usize) -> usize {
+        // Leave the high 7 bits for the hashbrown SIMD tag, like `DashMap`.
+        (hash << 7) >> self.shift
+    }
+
+    pub fn shards(&self) -> &[Shard<K, V>] {
+        &self.shards
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| lock(shard).len() == 0)
+    }
+
+    /// Iterates over all entries, locking one shard at a time.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            buckets: self.buckets(),
+        }
+    }
+
+    /// Iterates mutably over all entries, locking one shard at a time.
+    pub fn iter_mut(&self) -> IterMut<'_, K, V> {
+        IterMut {
+            buckets: self.buckets(),
+        }
+    }
+
+    fn buckets(&self) -> ShardBuckets<'_, K, V> {
+        ShardBuckets {
+            shards: self.shards.iter(),
+            current: None,
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Value stored in a shard table, mirroring `dashmap::SharedValue`.
+pub struct SharedValue<T> {
+    value: T,
+}
+
+impl<T> SharedValue<T> {
+    pub const fn new(value: T) -> Self {
+        Self { value }
+    }
+
+    pub const fn get(&self) -> &T {
+        &self.value
+    }
+
+    pub const fn get_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+type ShardGuard<'a, K, V> = Arc<MutexGuard<'a, RawTable<ShardEntry<K, V>>>>;
+
+/// Walks the buckets of every shard, keeping the current shard locked.
+struct ShardBuckets<'a, K, V> {
+    shards: std::slice::Iter<'a, Shard<K, V>>,
+    current: Option<LockedShard<'a, K, V>>,
+}
+
+struct LockedShard<'a, K, V> {
+    guard: ShardGuard<'a, K, V>,
+    iter: RawIter<ShardEntry<K, V>>,
+}
+
+impl<'a, K, V> Iterator for ShardBuckets<'a, K, V> {
+    type Item = (ShardGuard<'a, K, V>, Bucket<ShardEntry<K, V>>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(shard) = self.current.as_mut() {
+                if let Some(bucket) = shard.iter.next() {
+                    return Some((Arc::clone(&shard.guard), bucket));
+                }
+            }
+
+            let guard = lock(self.shards.next()?);
+            // SAFETY: the table is locked, and the guard is kept alive next to the
+            // raw iterator and by every bucket it yields
+            let iter = unsafe { guard.iter() };
+            self.current = Some(LockedShard {
+                guard: Arc::new(guard),
+                iter,
+            });
+        }
+    }
+}
+
+/// Iterator returned by [`SimpleMap::iter`].
+pub struct Iter<'a, K, V> {
+    buckets: ShardBuckets<'a, K, V>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = RefMulti<'a, K, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (guard, bucket) = self.buckets.next()?;
+        Some(RefMulti {
+            _guard: guard,
+            // SAFETY: the shard lock is held by `guard`, which the item keeps alive
+            entry: unsafe { bucket.as_ref() },
+        })
+    }
+}
+
+/// Iterator returned by [`SimpleMap::iter_mut`].
+pub struct IterMut<'a, K, V> {
+    buckets: ShardBuckets<'a, K, V>,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = RefMutMulti<'a, K, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (guard, bucket) = self.buckets.next()?;
+        Some(RefMutMulti {
+            _guard: guard,
+            // SAFETY: the shard lock is exclusive and every bucket is yielded once
+            entry: unsafe { bucket.as_mut() },
+        })
+    }
+}
+
+/// Shared reference to an entry yielded by [`SimpleMap::iter`].
+pub struct RefMulti<'a, K, V> {
+    _guard: ShardGuard<'a, K, V>,
+    entry: &'a ShardEntry<K, V>,
+}
+
+impl<K, V> RefMulti<'_, K, V> {
+    pub const fn key(&self) -> &K {
+        &self.entry.0
+    }
+
+    pub const fn value(&self) -> &V {
+        self.entry.1.get()
+    }
+}
+
+impl<K, V> Deref for RefMulti<'_, K, V> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        self.value()
+    }
+}
+
+/// Mutable reference to an entry yielded by [`SimpleMap::iter_mut`].
+pub struct RefMutMulti<'a, K, V> {
+    _guard: ShardGuard<'a, K, V>,
+    entry: &'a mut ShardEntry<K, V>,
+}
+
+impl<K, V> RefMutMulti<'_, K, V> {
+    pub const fn key(&self) -> &K {
+        &self.entry.0
+    }
+
+    pub const fn value(&self) -> &V {
+        self.entry.1.get()
+    }
+
+    pub const fn value_mut(&mut self) -> &mut V {
+        self.entry.1.get_mut()
+    }
+}
+
+impl<K, V> Deref for RefMutMulti<'_, K, V> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        self.value()
+    }
+}
+
+impl<K, V> DerefMut for RefMutMulti<'_, K, V> {
+    fn deref_mut(&mut self) -> &mut V {
+        self.value_mut()
+    }
+}
@@ -0,0 +1,479 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use crossbeam::queue::ArrayQueue;
+
+use super::{DrainMetricCollectorTrait, MetricCollectorTrait, Snapshot};
+use crate::dogstats::sorted_tags::{to_static_metric, to_static_tag};
+use crate::dogstats::{PreparedMetric, RylvStr, SortedTags};
+
+/// Configuration options for [`AsyncMetricCollector`].
+#[derive(Debug, Copy, Clone)]
+pub struct AsyncMetricCollectorOptions {
+    /// Maximum number of recordings buffered between drains.
+    ///
+    /// Recordings that do not fit are dropped and counted in
+    /// [`AsyncMetricCollector::dropped_count`]. A capacity of `0` is treated as `1`.
+    pub queue_capacity: usize,
+}
+
+impl Default for AsyncMetricCollectorOptions {
+    fn default() -> Self {
+        Self {
+            queue_capacity: 65_536,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum QueuedKind {
+    Histogram,
+    Count,
+    // The value holds the bits of the `f64` increment
+    FloatCount,
+    Gauge,
+    GaugeLast,
+    Rate,
+}
+
+enum QueuedTags<S: std::hash::BuildHasher + Clone> {
+    Unsorted(Box<[RylvStr<'static>]>),
+    Sorted(SortedTags<S>),
+}
+
+struct QueuedMetric<S: std::hash::BuildHasher + Clone> {
+    kind: QueuedKind,
+    metric: RylvStr<'static>,
+    value: u64,
+    tags: QueuedTags<S>,
+}
+
+/// Queue-backed front-end over another collector.
+///
+/// Recording methods never touch the inner collector: they push an owned entry
+/// onto a bounded lock-free queue and return immediately, so async runtime
+/// workers are never parked on an aggregation lock. The queue is moved into the
+/// inner collector at the start of every drain, which means that when wrapped by
+/// [`crate::MetricCollector`] the existing flush thread does the aggregation work.
+///
+/// This trades a bit of latency and one allocation per recording for a
+/// non-blocking hot path. When the queue is full the recording is dropped and
+/// counted instead of blocking.
+pub struct AsyncMetricCollector<MC>
+where
+    MC: MetricCollectorTrait,
+{
+    inner: MC,
+    queue: ArrayQueue<QueuedMetric<MC::Hasher>>,
+    dropped: AtomicU64,
+}
+
+impl<MC> AsyncMetricCollector<MC>
+where
+    MC: MetricCollectorTrait,
+{
+    /// Wraps `inner` with a bounded recording queue.
+    #[cold]
+    #[must_use]
+    pub fn new(inner: MC, options: AsyncMetricCollectorOptions) -> Self {
+        Self {
+            inner,
+            queue: ArrayQueue::new(options.queue_capacity.max(1)),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the wrapped collector.
+    #[must_use]
+    pub const fn inner(&self) -> &MC {
+        &self.inner
+    }
+
+    /// Returns how many recordings were dropped because the queue was full.
+    #[must_use]
+    pub fn dropped_count(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of recordings currently waiting to be drained.
+    #[must_use]
+    pub fn queued_len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Moves every queued recording into the inner collector.
+    pub fn drain_queue(&self) {
+        while let Some(queued) = self.queue.pop() {
+            let QueuedMetric {
+                kind,
+                metric,
+                value,
+                tags,
+            } = queued;
+            match tags {
+                QueuedTags::Unsorted(mut tags) => match kind {
+                    QueuedKind::Histogram => self.inner.histogram(metric, value, &mut tags[..]),
+                    QueuedKind::Count => self.inner.count_add(metric, value, &mut tags[..]),
+                    QueuedKind::FloatCount => {
+                        let value = f64::from_bits(value);
+                        self.inner.count_add_f64(metric, value, &mut tags[..]);
+                    }
+                    QueuedKind::Gauge => self.inner.gauge(metric, value, &mut tags[..]),
+                    QueuedKind::GaugeLast => self.inner.gauge_last(metric, value, &mut tags[..]),
+                    QueuedKind::Rate => self.inner.rate(metric, value, &mut tags[..]),
+                },
+                QueuedTags::Sorted(tags) => match kind {
+                    QueuedKind::Histogram => self.inner.histogram_sorted(metric, value, &tags),
+                    QueuedKind::Count => self.inner.count_add_sorted(metric, value, &tags),
+                    QueuedKind::Gauge => self.inner.gauge_sorted(metric, value, &tags),
+                    QueuedKind::FloatCount => {
+                        let mut tags = tags.iter().map(RylvStr::Borrowed).collect::<Vec<_>>();
+                        self.inner
+                            .count_add_f64(metric, f64::from_bits(value), &mut tags[..]);
+                    }
+                    QueuedKind::GaugeLast => {
+                        let mut tags = tags.iter().map(RylvStr::Borrowed).collect::<Vec<_>>();
+                        self.inner.gauge_last(metric, value, &mut tags[..]);
+                    }
+                    QueuedKind::Rate => {
+                        let mut tags = tags.iter().map(RylvStr::Borrowed).collect::<Vec<_>>();
+                        self.inner.rate(metric, value, &mut tags[..]);
+                    }
+                },
+            }
+        }
+    }
+
+    #[inline]
+    fn enqueue(&self, queued: QueuedMetric<MC::Hasher>) {
+        if self.queue.push(queued).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[inline]
+    fn enqueue_unsorted(
+        &self,
+        kind: QueuedKind,
+        metric: RylvStr<'_>,
+        value: u64,
+        tags: &[RylvStr<'_>],
+    ) {
+        if self.queue.is_full() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let tags = tags.iter().cloned().map(to_static_tag).collect();
+        self.enqueue(QueuedMetric {
+            kind,
+            metric: to_static_metric(metric),
+            value,
+            tags: QueuedTags::Unsorted(tags),
+        });
+    }
+
+    #[inline]
+    fn enqueue_sorted(
+        &self,
+        kind: QueuedKind,
+        metric: RylvStr<'_>,
+        value: u64,
+        tags: &SortedTags<MC::Hasher>,
+    ) {
+        if self.queue.is_full() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        self.enqueue(QueuedMetric {
+            kind,
+            metric: to_static_metric(metric),
+            value,
+            tags: QueuedTags::Sorted(tags.clone()),
+        });
+    }
+}
+
+impl<MC> MetricCollectorTrait for AsyncMetricCollector<MC>
+where
+    MC: MetricCollectorTrait,
+{
+    type Hasher = MC::Hasher;
+
+    #[inline]
+    fn histogram<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, mut tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        let metric = metric.into();
+        self.enqueue_unsorted(QueuedKind::Histogram, metric, value, tags.as_mut());
+    }
+
+    #[inline]
+    fn count<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        self.count_add(metric, 1, tags);
+    }
+
+    #[inline]
+    fn count_add<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, mut tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        let metric = metric.into();
+        self.enqueue_unsorted(QueuedKind::Count, metric, value, tags.as_mut());
+    }
+
+    #[inline]
+    fn count_add_f64<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: f64, mut tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        let metric = metric.into();
+        let value = value.to_bits();
+        self.enqueue_unsorted(QueuedKind::FloatCount, metric, value, tags.as_mut());
+    }
+
+    #[inline]
+    fn gauge<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, mut tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        let metric = metric.into();
+        self.enqueue_unsorted(QueuedKind::Gauge, metric, value, tags.as_mut());
+    }
+
+    #[inline]
+    fn gauge_last<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, mut tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        let metric = metric.into();
+        self.enqueue_unsorted(QueuedKind::GaugeLast, metric, value, tags.as_mut());
+    }
+
+    #[inline]
+    fn rate<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, delta: u64, mut tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        let metric = metric.into();
+        self.enqueue_unsorted(QueuedKind::Rate, metric, delta, tags.as_mut());
+    }
+
+    #[inline]
+    fn histogram_sorted<'m>(
+        &self,
+        metric: impl Into<RylvStr<'m>>,
+        value: u64,
+        tags: &SortedTags<Self::Hasher>,
+    ) {
+        let metric = metric.into();
+        self.enqueue_sorted(QueuedKind::Histogram, metric, value, tags);
+    }
+
+    #[inline]
+    fn count_add_sorted<'m>(
+        &self,
+        metric: impl Into<RylvStr<'m>>,
+        value: u64,
+        tags: &SortedTags<Self::Hasher>,
+    ) {
+        let metric = metric.into();
+        self.enqueue_sorted(QueuedKind::Count, metric, value, tags);
+    }
+
+    #[inline]
+    fn gauge_sorted<'m>(
+        &self,
+        metric: impl Into<RylvStr<'m>>,
+        value: u64,
+        tags: &SortedTags<Self::Hasher>,
+    ) {
+        let metric = metric.into();
+        self.enqueue_sorted(QueuedKind::Gauge, metric, value, tags);
+    }
+
+    #[cold]
+    fn prepare_sorted_tags<'a>(
+        &self,
+        tags: impl IntoIterator<Item = RylvStr<'a>>,
+    ) -> SortedTags<Self::Hasher> {
+        self.inner.prepare_sorted_tags(tags)
+    }
+
+    #[cold]
+    fn prepare_metric<'m>(
+        &self,
+        metric: impl Into<RylvStr<'m>>,
+        tags: SortedTags<Self::Hasher>,
+    ) -> PreparedMetric<Self::Hasher> {
+        self.inner.prepare_metric(metric, tags)
+    }
+
+    #[inline]
+    fn histogram_prepared(&self, prepared: &PreparedMetric<Self::Hasher>, value: u64) {
+        self.enqueue_sorted(
+            QueuedKind::Histogram,
+            prepared.metric().clone(),
+            value,
+            prepared.tags(),
+        );
+    }
+
+    #[inline]
+    fn count_add_prepared(&self, prepared: &PreparedMetric<Self::Hasher>, value: u64) {
+        self.enqueue_sorted(
+            QueuedKind::Count,
+            prepared.metric().clone(),
+            value,
+            prepared.tags(),
+        );
+    }
+
+    #[inline]
+    fn gauge_prepared(&self, prepared: &PreparedMetric<Self::Hasher>, value: u64) {
+        self.enqueue_sorted(
+            QueuedKind::Gauge,
+            prepared.metric().clone(),
+            value,
+            prepared.tags(),
+        );
+    }
+}
+
+impl<MC> DrainMetricCollectorTrait for AsyncMetricCollector<MC>
+where
+    MC: DrainMetricCollectorTrait,
+{
+    type Drain<'a>
+        = MC::Drain<'a>
+    where
+        Self: 'a;
+
+    #[cold]
+    fn try_begin_drain(&self) -> Option<Self::Drain<'_>> {
+        self.drain_queue();
+        self.inner.try_begin_drain()
+    }
+
+    #[cold]
+    fn try_begin_drain_until(&self, deadline: Instant) -> Option<Self::Drain<'_>> {
+        self.drain_queue();
+        self.inner.try_begin_drain_until(deadline)
+    }
+
+    fn stats_prefix(&self) -> &str {
+        self.inner.stats_prefix()
+    }
+
+    fn has_pending_drain(&self) -> bool {
+        self.inner.has_pending_drain()
+    }
+
+    /// Queued recordings that have not been moved into the inner collector yet
+    /// are not visible here.
+    fn peek_percentile<'t, TT>(&self, metric: RylvStr<'_>, tags: TT, quantile: f64) -> Option<u64>
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        self.inner.peek_percentile(metric, tags, quantile)
+    }
+
+    /// Queued recordings that have not been moved into the inner collector yet
+    /// are not visible here.
+    fn snapshot(&self) -> Snapshot {
+        self.inner.snapshot()
+    }
+}
+
+#[cfg(all(test, any(feature = "shared-collector", feature = "simple-map")))]
+mod tests {
+    use super::{AsyncMetricCollector, AsyncMetricCollectorOptions};
+    use crate::dogstats::collector::{DrainMetricCollectorTrait, MetricKind, MetricSuffix};
+    use crate::{MetricCollectorTrait, RylvStr, SharedCollector};
+
+    fn drain_lines(collector: &AsyncMetricCollector<SharedCollector>) -> Vec<String> {
+        let mut lines = Vec::new();
+        for frame in collector.try_begin_drain().unwrap() {
+            let suffix = match frame.suffix {
+                MetricSuffix::None => "",
+                MetricSuffix::Static(suffix) => suffix,
+                MetricSuffix::Percentile(_) => continue,
+            };
+            let kind = match frame.kind {
+                MetricKind::Count => "c",
+                MetricKind::Gauge => "g",
+                MetricKind::Timing => "ms",
+                MetricKind::Distribution => "d",
+            };
+            lines.push(format!(
+                "{}{suffix}:{}|{kind}|#{}",
+                frame.metric, frame.value, frame.tags
+            ));
+        }
+        lines.sort_unstable();
+        lines
+    }
+
+    #[test]
+    fn queued_recordings_reach_inner_collector_on_drain() {
+        let collector = AsyncMetricCollector::new(
+            SharedCollector::default(),
+            AsyncMetricCollectorOptions::default(),
+        );
+        let sorted = collector.prepare_sorted_tags([RylvStr::from_static("a:1")]);
+        let prepared = collector.prepare_metric(RylvStr::from_static("prepared"), sorted.clone());
+
+        collector.count(
+            RylvStr::from_static("requests"),
+            &mut [RylvStr::from_static("b:2"), RylvStr::from_static("a:1")],
+        );
+        collector.count_add_sorted(RylvStr::from_static("requests"), 2, &sorted);
+        collector.gauge(
+            RylvStr::from_static("load"),
+            10,
+            &mut [RylvStr::from_static("a:1")],
+        );
+        collector.histogram_prepared(&prepared, 7);
+        collector.count_add_prepared(&prepared, 3);
+
+        assert_eq!(collector.queued_len(), 5);
+        assert_eq!(
+            drain_lines(&collector),
+            vec![
+                "load:10|g|#a:1".to_string(),
+                "prepared.avg:7|g|#a:1".to_string(),
+                "prepared.count:1|c|#a:1".to_string(),
+                "prepared.max:7|g|#a:1".to_string(),
+                "prepared.min:7|g|#a:1".to_string(),
+                "prepared:3|c|#a:1".to_string(),
+                "requests:1|c|#a:1,b:2".to_string(),
+                "requests:2|c|#a:1".to_string(),
+            ]
+        );
+        assert_eq!(collector.queued_len(), 0);
+    }
+
+    #[test]
+    fn full_queue_drops_and_counts_recordings() {
+        let collector = AsyncMetricCollector::new(
+            SharedCollector::default(),
+            AsyncMetricCollectorOptions { queue_capacity: 2 },
+        );
+
+        for _ in 0..5 {
+            collector.count(
+                RylvStr::from_static("requests"),
+                &mut [RylvStr::from_static("a:1")],
+            );
+        }
+
+        assert_eq!(collector.dropped_count(), 3);
+        assert_eq!(
+            drain_lines(&collector),
+            vec!["requests:2|c|#a:1".to_string()]
+        );
+    }
+}
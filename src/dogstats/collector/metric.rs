@@ -0,0 +1,54 @@
+use crate::dogstats::RylvStr;
+
+/// Owned recording dispatched by [`MetricCollectorTrait::record`](super::MetricCollectorTrait::record).
+///
+/// Suits call sites that build metrics dynamically, e.g. from configuration or
+/// deserialized instructions, with one entry point instead of a match on the
+/// kind at every call site. Sets (`|s`) are not aggregated by the collectors, so
+/// there is no `Set` variant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Metric {
+    /// Adds `value` to a counter, like
+    /// [`MetricCollectorTrait::count_add`](super::MetricCollectorTrait::count_add).
+    Count {
+        /// Metric name.
+        name: RylvStr<'static>,
+        /// Value added to the counter.
+        value: u64,
+        /// Tags, sorted in-place when recorded.
+        tags: Vec<RylvStr<'static>>,
+    },
+    /// Records a gauge value, like
+    /// [`MetricCollectorTrait::gauge`](super::MetricCollectorTrait::gauge).
+    Gauge {
+        /// Metric name.
+        name: RylvStr<'static>,
+        /// Gauge value.
+        value: u64,
+        /// Tags, sorted in-place when recorded.
+        tags: Vec<RylvStr<'static>>,
+    },
+    /// Records a histogram value, like
+    /// [`MetricCollectorTrait::histogram`](super::MetricCollectorTrait::histogram).
+    Histogram {
+        /// Metric name.
+        name: RylvStr<'static>,
+        /// Recorded value.
+        value: u64,
+        /// Tags, sorted in-place when recorded.
+        tags: Vec<RylvStr<'static>>,
+    },
+    /// Records a histogram value meant to be sent as a distribution.
+    ///
+    /// It is recorded like [`Metric::Histogram`] and sent as `|d` when the
+    /// histogram config of `name` enables
+    /// [`HistogramConfig::with_distribution`](crate::HistogramConfig::with_distribution).
+    Distribution {
+        /// Metric name.
+        name: RylvStr<'static>,
+        /// Recorded value.
+        value: u64,
+        /// Tags, sorted in-place when recorded.
+        tags: Vec<RylvStr<'static>>,
+    },
+}
@@ -1,17 +1,34 @@
 use std::hash::BuildHasher;
+#[cfg(any(
+    feature = "shared-collector",
+    feature = "simple-map",
+    feature = "tls-collector"
+))]
+use std::num::NonZeroU64;
+use std::time::{Duration, Instant};
 
 use crate::dogstats::RylvStr;
 use crate::dogstats::{PreparedMetric, SortedTags};
 
-#[cfg(feature = "shared-collector")]
+mod async_collector;
+mod metric;
+mod noop_collector;
+mod scope_timer;
+#[cfg(any(feature = "shared-collector", feature = "simple-map"))]
 mod shared_collector;
+mod snapshot;
 #[cfg(feature = "tls-collector")]
 mod tls_collector;
 
-#[cfg(feature = "shared-collector")]
+pub use async_collector::{AsyncMetricCollector, AsyncMetricCollectorOptions};
+pub use metric::Metric;
+pub use noop_collector::NoopCollector;
+pub use scope_timer::ScopeTimer;
+#[cfg(any(feature = "shared-collector", feature = "simple-map"))]
 pub(super) use shared_collector::GaugeState;
-#[cfg(feature = "shared-collector")]
+#[cfg(any(feature = "shared-collector", feature = "simple-map"))]
 pub use shared_collector::{SharedCollector, SharedCollectorOptions};
+pub use snapshot::{HistogramSnapshot, Snapshot, SnapshotValue};
 #[cfg(feature = "tls-collector")]
 pub use tls_collector::{TLSCollector, TLSCollectorOptions};
 
@@ -19,6 +36,12 @@ pub use tls_collector::{TLSCollector, TLSCollectorOptions};
 ///
 /// Implementations of this trait can record histograms, counters, and gauges
 /// with associated tags.
+///
+/// Metric names are taken as `impl Into<RylvStr>`, so a `&str`, `String` or
+/// `Arc<str>` can be passed directly. A plain `&str` becomes
+/// [`RylvStr::Borrowed`] and is copied once when its series is first stored;
+/// pass [`RylvStr::from_static`] to keep a literal name zero-copy, since a
+/// `RylvStr` converts into itself unchanged.
 pub trait MetricCollectorTrait {
     /// The hasher used to produce [`PreparedMetric`] keys.
     type Hasher: BuildHasher + Clone;
@@ -29,7 +52,7 @@ pub trait MetricCollectorTrait {
     /// before being sent to the server.
     ///
     /// **Note:** The `tags` slice is sorted in-place for consistent aggregation keys.
-    fn histogram<'m, 't, TT>(&self, metric: RylvStr<'m>, value: u64, tags: TT)
+    fn histogram<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, tags: TT)
     where
         TT: AsMut<[RylvStr<'t>]>;
 
@@ -38,7 +61,7 @@ pub trait MetricCollectorTrait {
     /// Counters are aggregated client-side and the total is sent on flush.
     ///
     /// **Note:** The `tags` slice is sorted in-place for consistent aggregation keys.
-    fn count<'m, 't, TT>(&self, metric: RylvStr<'m>, tags: TT)
+    fn count<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, tags: TT)
     where
         TT: AsMut<[RylvStr<'t>]>;
 
@@ -47,32 +70,325 @@ pub trait MetricCollectorTrait {
     /// Counters are aggregated client-side and the total is sent on flush.
     ///
     /// **Note:** The `tags` slice is sorted in-place for consistent aggregation keys.
-    fn count_add<'m, 't, TT>(&self, metric: RylvStr<'m>, value: u64, tags: TT)
+    fn count_add<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, tags: TT)
     where
         TT: AsMut<[RylvStr<'t>]>;
 
+    /// Increments a counter by a fractional value, e.g. for sampled or weighted
+    /// events, sending the total as a decimal (`name:1.5|c`).
+    ///
+    /// Collectors without a floating-point accumulator round `value` to the
+    /// nearest integer and record it with [`MetricCollectorTrait::count_add`].
+    /// Negative, NaN and infinite values are dropped, like negative increments
+    /// cannot be passed to `count_add`.
+    ///
+    /// **Note:** The `tags` slice is sorted in-place for consistent aggregation keys.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn count_add_f64<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: f64, tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        if is_valid_count_f64(value) {
+            self.count_add(metric, value.round() as u64, tags);
+        }
+    }
+
+    /// Records `latency` in the `<metric_base>.latency` histogram and increments
+    /// the `<metric_base>.count` counter, for the common "count + time" pattern.
+    ///
+    /// The shared collector sorts and hashes `tags` once for both series.
+    ///
+    /// **Note:** The `tags` slice is sorted in-place for consistent aggregation keys.
+    fn timed_count<'m, 't, TT>(
+        &self,
+        metric_base: impl Into<RylvStr<'m>>,
+        latency: u64,
+        mut tags: TT,
+    ) where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        let metric_base = metric_base.into();
+        let tags = tags.as_mut();
+        with_prefixed_name(metric_base.as_ref(), TIMED_LATENCY_SUFFIX, |name| {
+            self.histogram(RylvStr::Borrowed(name), latency, &mut *tags);
+        });
+        with_prefixed_name(metric_base.as_ref(), TIMED_COUNT_SUFFIX, |name| {
+            self.count(RylvStr::Borrowed(name), tags);
+        });
+    }
+
     /// Records a gauge value representing a point-in-time measurement.
     ///
     /// Multiple gauge values for the same metric/tags are averaged on flush.
     ///
     /// **Note:** The `tags` slice is sorted in-place for consistent aggregation keys.
-    fn gauge<'m, 't, TT>(&self, metric: RylvStr<'m>, value: u64, tags: TT)
+    fn gauge<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, tags: TT)
     where
         TT: AsMut<[RylvStr<'t>]>;
 
+    /// Records a gauge value, keeping only the last one recorded in the flush window.
+    ///
+    /// Unlike [`Self::gauge`], nothing is averaged, whatever the [`GaugeMode`]:
+    /// the shared collector records it with a single atomic store. Series
+    /// recorded through both methods are emitted once by each. Across threads
+    /// of a [`TLSCollector`](crate::TLSCollector), the last value of one of the
+    /// threads is kept. Collectors without a last-value gauge record it with
+    /// [`Self::gauge`].
+    ///
+    /// **Note:** The `tags` slice is sorted in-place for consistent aggregation keys.
+    fn gauge_last<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        self.gauge(metric, value, tags);
+    }
+
+    /// Accumulates a delta that is emitted as a per-second gauge on flush.
+    ///
+    /// Deltas for the same metric/tags are summed like a counter, then divided
+    /// by the time actually elapsed since the previous flush and sent as a
+    /// fractional value, e.g. bytes sent reported as bytes/sec. Collectors
+    /// without rate support count the delta with [`Self::count_add`] instead.
+    ///
+    /// **Note:** The `tags` slice is sorted in-place for consistent aggregation keys.
+    fn rate<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, delta: u64, tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        self.count_add(metric, delta, tags);
+    }
+
+    /// Records a boolean as a `1`/`0` gauge, e.g. a feature flag or a circuit
+    /// breaker being open.
+    ///
+    /// Pair it with [`GaugeMode::Last`] so the latest state of the window is
+    /// emitted instead of an average.
+    ///
+    /// **Note:** The `tags` slice is sorted in-place for consistent aggregation keys.
+    fn gauge_bool<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: bool, tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        self.gauge(metric, u64::from(value), tags);
+    }
+
+    /// Records `metric`, dispatching to the method of its kind.
+    ///
+    /// **Note:** The tags of `metric` are sorted in-place before recording.
+    fn record(&self, metric: Metric) {
+        match metric {
+            Metric::Count { name, value, tags } => self.count_add(name, value, tags),
+            Metric::Gauge { name, value, tags } => self.gauge(name, value, tags),
+            Metric::Histogram { name, value, tags }
+            | Metric::Distribution { name, value, tags } => {
+                self.histogram(name, value, tags);
+            }
+        }
+    }
+
+    /// Records a duration in microseconds as a histogram value.
+    ///
+    /// Durations above `u64::MAX` microseconds are clamped. Pair the metric with
+    /// [`HistogramConfig::latency_micros`](crate::HistogramConfig::latency_micros)
+    /// so the histogram bounds match the unit.
+    ///
+    /// **Note:** The `tags` slice is sorted in-place for consistent aggregation keys.
+    fn timing_micros<'m, 't, TT>(
+        &self,
+        metric: impl Into<RylvStr<'m>>,
+        duration: Duration,
+        tags: TT,
+    ) where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        let micros = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+        self.histogram(metric, micros, tags);
+    }
+
+    /// Starts a [`ScopeTimer`] that records the elapsed microseconds under
+    /// `metric` and `tags` when dropped, like [`MetricCollectorTrait::timing_micros`].
+    ///
+    /// The name and tags are copied into the guard, so they only need to live
+    /// for this call.
+    fn scope_timer<'m>(
+        &self,
+        metric: impl Into<RylvStr<'m>>,
+        tags: &[RylvStr<'_>],
+    ) -> ScopeTimer<'_, Self> {
+        ScopeTimer::new(self, metric.into(), tags)
+    }
+
+    /// Records a histogram under an additional per-call prefix.
+    ///
+    /// The wire name is `stats_prefix + prefix + metric`, so one collector can
+    /// emit metrics for several subsystems (e.g. `db.` and `http.`) without
+    /// baking the prefix into every name. Names up to 256 bytes are joined on
+    /// the stack; the collector only allocates when it sees a new series.
+    ///
+    /// **Note:** The `tags` slice is sorted in-place for consistent aggregation keys.
+    fn histogram_prefixed<'m, 't, TT>(
+        &self,
+        prefix: &str,
+        metric: impl Into<RylvStr<'m>>,
+        value: u64,
+        tags: TT,
+    ) where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        let metric = metric.into();
+        with_prefixed_name(prefix, metric.as_ref(), |name| {
+            self.histogram(RylvStr::Borrowed(name), value, tags);
+        });
+    }
+
+    /// Increments a counter by one under an additional per-call prefix.
+    ///
+    /// See [`MetricCollectorTrait::histogram_prefixed`] for how the prefix is applied.
+    fn count_prefixed<'m, 't, TT>(&self, prefix: &str, metric: impl Into<RylvStr<'m>>, tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        self.count_add_prefixed(prefix, metric, 1, tags);
+    }
+
+    /// Increments a counter by value under an additional per-call prefix.
+    ///
+    /// See [`MetricCollectorTrait::histogram_prefixed`] for how the prefix is applied.
+    fn count_add_prefixed<'m, 't, TT>(
+        &self,
+        prefix: &str,
+        metric: impl Into<RylvStr<'m>>,
+        value: u64,
+        tags: TT,
+    ) where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        let metric = metric.into();
+        with_prefixed_name(prefix, metric.as_ref(), |name| {
+            self.count_add(RylvStr::Borrowed(name), value, tags);
+        });
+    }
+
+    /// Records a gauge under an additional per-call prefix.
+    ///
+    /// See [`MetricCollectorTrait::histogram_prefixed`] for how the prefix is applied.
+    fn gauge_prefixed<'m, 't, TT>(
+        &self,
+        prefix: &str,
+        metric: impl Into<RylvStr<'m>>,
+        value: u64,
+        tags: TT,
+    ) where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        let metric = metric.into();
+        with_prefixed_name(prefix, metric.as_ref(), |name| {
+            self.gauge(RylvStr::Borrowed(name), value, tags);
+        });
+    }
+
+    /// Increments a counter by value once for every tag set in `tag_sets`.
+    ///
+    /// Each tag set is its own aggregation key, so recording under
+    /// `[["region:eu"], []]` updates both the per-region and the untagged series.
+    /// Each tag set is copied onto the stack and sorted there, so the caller's
+    /// slices are left untouched.
+    fn count_multi<'m>(
+        &self,
+        metric: impl Into<RylvStr<'m>>,
+        value: u64,
+        tag_sets: &[&[RylvStr<'_>]],
+    ) {
+        let metric = metric.into();
+        for_each_tag_set(tag_sets, |tags| self.count_add(metric.clone(), value, tags));
+    }
+
+    /// Records a gauge once for every tag set in `tag_sets`.
+    ///
+    /// See [`MetricCollectorTrait::count_multi`] for how tag sets are handled.
+    fn gauge_multi<'m>(
+        &self,
+        metric: impl Into<RylvStr<'m>>,
+        value: u64,
+        tag_sets: &[&[RylvStr<'_>]],
+    ) {
+        let metric = metric.into();
+        for_each_tag_set(tag_sets, |tags| self.gauge(metric.clone(), value, tags));
+    }
+
+    /// Records a histogram without mutating `tags`.
+    ///
+    /// Use this for `const`, `static`, or otherwise shared tag arrays that cannot
+    /// be passed as `&mut`. Up to 16 tags are copied onto the stack and sorted
+    /// there; longer slices are copied into a temporary `Vec`. When the tags are
+    /// already owned and mutable, [`MetricCollectorTrait::histogram`] avoids the copy.
+    fn histogram_ref<'m>(&self, metric: impl Into<RylvStr<'m>>, value: u64, tags: &[RylvStr<'_>]) {
+        with_tag_copy(tags, |tags| self.histogram(metric, value, tags));
+    }
+
+    /// Increments a counter by one without mutating `tags`.
+    ///
+    /// See [`MetricCollectorTrait::histogram_ref`] for how tags are copied.
+    fn count_ref<'m>(&self, metric: impl Into<RylvStr<'m>>, tags: &[RylvStr<'_>]) {
+        with_tag_copy(tags, |tags| self.count(metric, tags));
+    }
+
+    /// Increments a counter by value without mutating `tags`.
+    ///
+    /// See [`MetricCollectorTrait::histogram_ref`] for how tags are copied.
+    fn count_add_ref<'m>(&self, metric: impl Into<RylvStr<'m>>, value: u64, tags: &[RylvStr<'_>]) {
+        with_tag_copy(tags, |tags| self.count_add(metric, value, tags));
+    }
+
+    /// Records a gauge without mutating `tags`.
+    ///
+    /// See [`MetricCollectorTrait::histogram_ref`] for how tags are copied.
+    fn gauge_ref<'m>(&self, metric: impl Into<RylvStr<'m>>, value: u64, tags: &[RylvStr<'_>]) {
+        with_tag_copy(tags, |tags| self.gauge(metric, value, tags));
+    }
+
+    /// Records a histogram with tags given as `(key, value)` pairs.
+    ///
+    /// Every pair is joined into a `key:value` tag by the client, so code
+    /// building tags at runtime does not need a `format!` per tag. The joined
+    /// tags share one buffer and are sorted like [`MetricCollectorTrait::histogram_ref`].
+    fn histogram_kv<'m>(
+        &self,
+        metric: impl Into<RylvStr<'m>>,
+        value: u64,
+        tags: &[(RylvStr<'_>, RylvStr<'_>)],
+    ) {
+        with_joined_tags(tags, |tags| self.histogram(metric, value, tags));
+    }
+
     /// Records a histogram using pre-sorted tags.
-    fn histogram_sorted(&self, metric: RylvStr<'_>, value: u64, tags: &SortedTags<Self::Hasher>);
+    fn histogram_sorted<'m>(
+        &self,
+        metric: impl Into<RylvStr<'m>>,
+        value: u64,
+        tags: &SortedTags<Self::Hasher>,
+    );
 
     /// Increments a counter by one using pre-sorted tags.
-    fn count_sorted(&self, metric: RylvStr<'_>, tags: &SortedTags<Self::Hasher>) {
+    fn count_sorted<'m>(&self, metric: impl Into<RylvStr<'m>>, tags: &SortedTags<Self::Hasher>) {
         self.count_add_sorted(metric, 1, tags);
     }
 
     /// Increments a counter by value using pre-sorted tags.
-    fn count_add_sorted(&self, metric: RylvStr<'_>, value: u64, tags: &SortedTags<Self::Hasher>);
+    fn count_add_sorted<'m>(
+        &self,
+        metric: impl Into<RylvStr<'m>>,
+        value: u64,
+        tags: &SortedTags<Self::Hasher>,
+    );
 
     /// Records a gauge using pre-sorted tags.
-    fn gauge_sorted(&self, metric: RylvStr<'_>, value: u64, tags: &SortedTags<Self::Hasher>);
+    fn gauge_sorted<'m>(
+        &self,
+        metric: impl Into<RylvStr<'m>>,
+        value: u64,
+        tags: &SortedTags<Self::Hasher>,
+    );
 
     /// Builds a [`SortedTags`] bound to this collector's hasher.
     fn prepare_sorted_tags<'a>(
@@ -84,9 +400,9 @@ pub trait MetricCollectorTrait {
     ///
     /// The returned [`PreparedMetric`] caches the metric name, pre-sorted tags,
     /// and a pre-computed hash, making subsequent `*_prepared` calls faster.
-    fn prepare_metric(
+    fn prepare_metric<'m>(
         &self,
-        metric: RylvStr<'_>,
+        metric: impl Into<RylvStr<'m>>,
         tags: SortedTags<Self::Hasher>,
     ) -> PreparedMetric<Self::Hasher>;
 
@@ -105,6 +421,260 @@ pub trait MetricCollectorTrait {
     fn gauge_prepared(&self, prepared: &PreparedMetric<Self::Hasher>, value: u64);
 }
 
+/// Suffix of the histogram recorded by [`MetricCollectorTrait::timed_count`].
+pub const TIMED_LATENCY_SUFFIX: &str = ".latency";
+/// Suffix of the counter incremented by [`MetricCollectorTrait::timed_count`].
+pub const TIMED_COUNT_SUFFIX: &str = ".count";
+
+/// Longest `prefix + metric` joined on the stack by the `*_prefixed` methods.
+const STACK_PREFIXED_NAME_LEN: usize = 256;
+
+/// Calls `f` with `prefix` and `metric` joined, avoiding a heap allocation for short names.
+#[inline]
+pub fn with_prefixed_name<R>(prefix: &str, metric: &str, f: impl FnOnce(&str) -> R) -> R {
+    let len = prefix.len() + metric.len();
+    if len > STACK_PREFIXED_NAME_LEN {
+        let mut name = String::with_capacity(len);
+        name.push_str(prefix);
+        name.push_str(metric);
+        return f(&name);
+    }
+
+    let mut buffer = [0u8; STACK_PREFIXED_NAME_LEN];
+    buffer[..prefix.len()].copy_from_slice(prefix.as_bytes());
+    buffer[prefix.len()..len].copy_from_slice(metric.as_bytes());
+    // SAFETY: the buffer holds two valid UTF-8 strings back to back, which is valid UTF-8.
+    f(unsafe { std::str::from_utf8_unchecked(&buffer[..len]) })
+}
+
+/// Appends `separator` to a non-empty `stats_prefix` unless it already ends with it.
+#[cfg(any(
+    feature = "shared-collector",
+    feature = "simple-map",
+    feature = "tls-collector"
+))]
+pub fn join_stats_prefix(mut stats_prefix: String, separator: Option<&str>) -> String {
+    if let Some(separator) = separator {
+        if !stats_prefix.is_empty() && !stats_prefix.ends_with(separator) {
+            stats_prefix.push_str(separator);
+        }
+    }
+    stats_prefix
+}
+
+/// Prefixes of the emitted names of each aggregated metric type:
+/// `stats_prefix` followed by the type prefix.
+#[cfg(any(
+    feature = "shared-collector",
+    feature = "simple-map",
+    feature = "tls-collector"
+))]
+#[derive(Debug, Default)]
+pub struct TypePrefixes {
+    pub histogram: String,
+    pub count: String,
+    pub gauge: String,
+}
+
+#[cfg(any(
+    feature = "shared-collector",
+    feature = "simple-map",
+    feature = "tls-collector"
+))]
+impl TypePrefixes {
+    pub fn new(
+        stats_prefix: &str,
+        histogram_prefix: &str,
+        count_prefix: &str,
+        gauge_prefix: &str,
+    ) -> Self {
+        Self {
+            histogram: format!("{stats_prefix}{histogram_prefix}"),
+            count: format!("{stats_prefix}{count_prefix}"),
+            gauge: format!("{stats_prefix}{gauge_prefix}"),
+        }
+    }
+}
+
+/// Lowercases the ASCII letters of `metric` when `normalize` is set.
+///
+/// Names without uppercase ASCII letters are returned unchanged; others are
+/// copied into an owned string.
+#[cfg(any(
+    feature = "shared-collector",
+    feature = "simple-map",
+    feature = "tls-collector"
+))]
+#[inline]
+pub fn normalize_metric_name(metric: RylvStr<'_>, normalize: bool) -> RylvStr<'_> {
+    if normalize
+        && metric
+            .as_ref()
+            .bytes()
+            .any(|byte| byte.is_ascii_uppercase())
+    {
+        RylvStr::from(metric.as_ref().to_ascii_lowercase())
+    } else {
+        metric
+    }
+}
+
+/// Divides a rate delta by the recording window.
+///
+/// A window too short to measure emits the delta unchanged.
+#[cfg(any(
+    feature = "shared-collector",
+    feature = "simple-map",
+    feature = "tls-collector"
+))]
+#[allow(clippy::cast_precision_loss)]
+pub fn per_second(delta: u64, window_secs: f64) -> f64 {
+    if window_secs > 0.0 {
+        delta as f64 / window_secs
+    } else {
+        delta as f64
+    }
+}
+
+/// Rounds `value` down to a multiple of `granularity`, when set.
+#[cfg(any(
+    feature = "shared-collector",
+    feature = "simple-map",
+    feature = "tls-collector"
+))]
+#[inline]
+pub const fn round_value(value: u64, granularity: Option<NonZeroU64>) -> u64 {
+    match granularity {
+        Some(granularity) => value - value % granularity.get(),
+        None => value,
+    }
+}
+
+/// Calls `f` with a mutable copy of every tag set, made on the stack for short sets.
+fn for_each_tag_set<'t>(tag_sets: &[&[RylvStr<'t>]], mut f: impl FnMut(&mut [RylvStr<'t>])) {
+    for tags in tag_sets {
+        with_tag_copy(tags, &mut f);
+    }
+}
+
+/// Longest tag slice copied onto the stack by the `*_ref` methods.
+const STACK_TAGS_LEN: usize = 16;
+
+/// Calls `f` with a mutable copy of `tags`, avoiding a heap allocation for short slices.
+#[inline]
+fn with_tag_copy<'t, R>(tags: &[RylvStr<'t>], f: impl FnOnce(&mut [RylvStr<'t>]) -> R) -> R {
+    if tags.len() > STACK_TAGS_LEN {
+        return f(&mut tags.to_vec());
+    }
+
+    let mut buffer: [RylvStr<'t>; STACK_TAGS_LEN] = std::array::from_fn(|_| RylvStr::Static(""));
+    buffer[..tags.len()].clone_from_slice(tags);
+    f(&mut buffer[..tags.len()])
+}
+
+/// Calls `f` with every `(key, value)` pair joined into a `key:value` tag.
+///
+/// All tags are written into a single buffer and borrowed from it, so a call
+/// allocates once regardless of the tag count.
+fn with_joined_tags<R>(
+    pairs: &[(RylvStr<'_>, RylvStr<'_>)],
+    f: impl FnOnce(&mut [RylvStr<'_>]) -> R,
+) -> R {
+    let len = pairs
+        .iter()
+        .map(|(key, value)| key.as_ref().len() + 1 + value.as_ref().len())
+        .sum();
+    let mut joined = String::with_capacity(len);
+    for (key, value) in pairs {
+        joined.push_str(key.as_ref());
+        joined.push(':');
+        joined.push_str(value.as_ref());
+    }
+
+    let mut start = 0;
+    let tags = pairs.iter().map(|(key, value)| {
+        let end = start + key.as_ref().len() + 1 + value.as_ref().len();
+        let tag = RylvStr::Borrowed(&joined[start..end]);
+        start = end;
+        tag
+    });
+    if pairs.len() > STACK_TAGS_LEN {
+        return f(&mut tags.collect::<Vec<_>>());
+    }
+
+    let mut buffer: [RylvStr<'_>; STACK_TAGS_LEN] = std::array::from_fn(|_| RylvStr::Static(""));
+    for (slot, tag) in buffer.iter_mut().zip(tags) {
+        *slot = tag;
+    }
+    f(&mut buffer[..pairs.len()])
+}
+
+/// Returns whether `value` can be added to a counter: finite and not negative.
+#[inline]
+pub fn is_valid_count_f64(value: f64) -> bool {
+    value.is_finite() && value >= 0.0
+}
+
+/// Entries a drain visits between two deadline checks.
+#[cfg(any(
+    feature = "shared-collector",
+    feature = "simple-map",
+    feature = "tls-collector"
+))]
+const DRAIN_BUDGET_CHECK_INTERVAL: u32 = 64;
+
+/// Soft deadline of a drain, checked between entries.
+///
+/// Once exceeded the drain stops at the next entry boundary, never in the
+/// middle of a histogram, and stays stopped.
+#[cfg(any(
+    feature = "shared-collector",
+    feature = "simple-map",
+    feature = "tls-collector"
+))]
+#[derive(Debug, Default)]
+pub struct DrainBudget {
+    deadline: Option<Instant>,
+    visited: u32,
+    exceeded: bool,
+}
+
+#[cfg(any(
+    feature = "shared-collector",
+    feature = "simple-map",
+    feature = "tls-collector"
+))]
+impl DrainBudget {
+    pub const fn until(deadline: Instant) -> Self {
+        Self {
+            deadline: Some(deadline),
+            visited: 0,
+            exceeded: false,
+        }
+    }
+
+    /// Returns `true` if the drain must stop before visiting the next entry.
+    #[inline]
+    pub fn exhausted(&mut self) -> bool {
+        if self.exceeded {
+            return true;
+        }
+        let Some(deadline) = self.deadline else {
+            return false;
+        };
+        self.visited = self.visited.wrapping_add(1);
+        if self.visited % DRAIN_BUDGET_CHECK_INTERVAL == 0 && Instant::now() >= deadline {
+            self.exceeded = true;
+        }
+        self.exceeded
+    }
+
+    /// Returns `true` if the drain stopped before visiting every entry.
+    pub const fn exceeded(&self) -> bool {
+        self.exceeded
+    }
+}
+
 /// Trait for collectors that support draining aggregated metrics.
 pub trait DrainMetricCollectorTrait: MetricCollectorTrait {
     /// Drain iterator returned by this collector.
@@ -115,10 +685,68 @@ pub trait DrainMetricCollectorTrait: MetricCollectorTrait {
     /// Tries to begin a drain cycle, returning a handle to iterate over
     /// aggregated metric frames.
     fn try_begin_drain(&self) -> Option<Self::Drain<'_>>;
+
+    /// Like [`Self::try_begin_drain`], but the drain stops early once `deadline`
+    /// passes.
+    ///
+    /// The deadline is checked between series, so a histogram is always emitted
+    /// in full. A drain that stopped early keeps its remaining series, and the
+    /// next drain resumes them before anything recorded since; series already
+    /// emitted are not emitted again, including the `0` of series kept by
+    /// `keep_zero_values`. [`Self::has_pending_drain`] holds until they are
+    /// drained. Defaults to [`Self::try_begin_drain`], which ignores the deadline.
+    fn try_begin_drain_until(&self, deadline: Instant) -> Option<Self::Drain<'_>> {
+        let _ = deadline;
+        self.try_begin_drain()
+    }
+
+    /// Returns the prefix prepended to every metric name emitted by this collector.
+    ///
+    /// Used for lines that bypass aggregation, such as
+    /// [`crate::MetricCollector::timing_raw`]. Defaults to no prefix.
+    #[allow(clippy::unnecessary_literal_bound)]
+    fn stats_prefix(&self) -> &str {
+        ""
+    }
+
+    /// Returns `true` while metrics swapped out by an earlier drain attempt are
+    /// still waiting to be drained.
+    ///
+    /// The flush thread keeps draining on shutdown while this holds. Defaults to `false`.
+    fn has_pending_drain(&self) -> bool {
+        false
+    }
+
+    /// Returns the value at `quantile` (`0.0..=1.0`) of a histogram recorded
+    /// since the last drain, without resetting it.
+    ///
+    /// Returns `None` if the histogram has no samples in the current generation
+    /// or the collector does not support live queries. Defaults to `None`.
+    ///
+    /// **Note:** The `tags` slice is sorted in-place, matching the recording methods.
+    fn peek_percentile<'t, TT>(&self, metric: RylvStr<'_>, tags: TT, quantile: f64) -> Option<u64>
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        let _ = (metric, tags, quantile);
+        None
+    }
+
+    /// Returns an owned copy of the counts, gauges and histograms recorded
+    /// since the last drain, without resetting them.
+    ///
+    /// See [`Snapshot`] for what it contains. Defaults to an empty snapshot for
+    /// collectors that do not support live queries.
+    fn snapshot(&self) -> Snapshot {
+        Snapshot::default()
+    }
 }
 
 /// Borrowed representation of a drained metric frame.
+///
+/// Built outside the crate with [`MetricFrameRef::new`], as fields may be added.
 #[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub struct MetricFrameRef<'a> {
     /// Prefix configured in collector options.
     pub prefix: &'a str,
@@ -128,12 +756,57 @@ pub struct MetricFrameRef<'a> {
     pub suffix: MetricSuffix<'a>,
     /// Tags in joined `DogStatsD` format.
     pub tags: &'a str,
+    /// Unit sent as a `unit:<unit>` tag after `tags`, set by
+    /// [`HistogramConfig::with_unit`](crate::HistogramConfig::with_unit).
+    pub unit: Option<&'a str>,
     /// Numeric value.
     pub value: u64,
+    /// Fractional value sent instead of `value` when set, for counters recorded
+    /// with [`MetricCollectorTrait::count_add_f64`] and gauges recorded with
+    /// [`MetricCollectorTrait::rate`].
+    pub float_value: Option<f64>,
     /// Metric kind.
     pub kind: MetricKind,
 }
 
+impl<'a> MetricFrameRef<'a> {
+    /// Creates a frame without a unit or a fractional value.
+    #[must_use]
+    pub const fn new(
+        prefix: &'a str,
+        metric: &'a str,
+        suffix: MetricSuffix<'a>,
+        tags: &'a str,
+        value: u64,
+        kind: MetricKind,
+    ) -> Self {
+        Self {
+            prefix,
+            metric,
+            suffix,
+            tags,
+            unit: None,
+            value,
+            float_value: None,
+            kind,
+        }
+    }
+
+    /// Sets the unit sent as a `unit:<unit>` tag.
+    #[must_use]
+    pub const fn with_unit(mut self, unit: &'a str) -> Self {
+        self.unit = Some(unit);
+        self
+    }
+
+    /// Sets the fractional value sent instead of `value`.
+    #[must_use]
+    pub const fn with_float_value(mut self, float_value: f64) -> Self {
+        self.float_value = Some(float_value);
+        self
+    }
+}
+
 /// Suffix descriptor for a borrowed metric frame.
 #[derive(Debug, Clone, PartialEq)]
 pub enum MetricSuffix<'a> {
@@ -145,11 +818,31 @@ pub enum MetricSuffix<'a> {
     Percentile(f64),
 }
 
+/// How the gauge values recorded for one series within a flush window are combined.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub enum GaugeMode {
+    /// Emits the average of the recorded values.
+    #[default]
+    Average,
+    /// Emits the most recently recorded value, e.g. for states such as
+    /// [`MetricCollectorTrait::gauge_bool`] where an average is meaningless.
+    Last,
+}
+
 /// Metric kind emitted by the drain APIs.
+///
+/// New kinds may be added in minor releases, so matches need a wildcard arm.
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[non_exhaustive]
 pub enum MetricKind {
     /// Counter metric (`|c`).
     Count,
     /// Gauge metric (`|g`).
     Gauge,
+    /// Timing metric (`|ms`), sent un-aggregated so the agent computes the distribution.
+    Timing,
+    /// Distribution sample (`|d`) of a histogram configured with
+    /// [`HistogramConfig::with_distribution`](crate::HistogramConfig::with_distribution);
+    /// one frame is drained per recorded sample.
+    Distribution,
 }
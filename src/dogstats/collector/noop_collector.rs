@@ -0,0 +1,246 @@
+use std::time::Instant;
+
+use super::{DrainMetricCollectorTrait, MetricCollectorTrait, MetricFrameRef};
+use crate::dogstats::{PreparedMetric, RylvStr, SortedTags};
+use crate::DefaultMetricHasher;
+
+/// Collector that records nothing, for builds or deployments with metrics
+/// turned off.
+///
+/// Every recording method is an empty inlinable body, so call sites written
+/// against [`MetricCollectorTrait`] stay unchanged and compile down to nothing
+/// when given a `NoopCollector`; it also gives benchmarks a baseline without
+/// any aggregation cost. Drains are always empty, so wrapping it in a
+/// [`crate::MetricCollector`] sends nothing.
+///
+/// ```
+/// use rylv_metrics::{MetricCollectorTrait, NoopCollector, RylvStr};
+///
+/// fn handle_request(metrics: &impl MetricCollectorTrait) {
+///     metrics.count(RylvStr::from_static("requests"), &mut [RylvStr::from_static("env:dev")]);
+/// }
+///
+/// handle_request(&NoopCollector::default());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct NoopCollector {
+    // Only used by the cold `prepare_*` methods, which must still return keys
+    hasher_builder: DefaultMetricHasher,
+}
+
+impl MetricCollectorTrait for NoopCollector {
+    type Hasher = DefaultMetricHasher;
+
+    #[inline]
+    fn histogram<'m, 't, TT>(&self, _metric: impl Into<RylvStr<'m>>, _value: u64, _tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+    }
+
+    #[inline]
+    fn count<'m, 't, TT>(&self, _metric: impl Into<RylvStr<'m>>, _tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+    }
+
+    #[inline]
+    fn count_add<'m, 't, TT>(&self, _metric: impl Into<RylvStr<'m>>, _value: u64, _tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+    }
+
+    #[inline]
+    fn timed_count<'m, 't, TT>(
+        &self,
+        _metric_base: impl Into<RylvStr<'m>>,
+        _latency: u64,
+        _tags: TT,
+    ) where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+    }
+
+    #[inline]
+    fn gauge<'m, 't, TT>(&self, _metric: impl Into<RylvStr<'m>>, _value: u64, _tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+    }
+
+    #[inline]
+    fn gauge_last<'m, 't, TT>(&self, _metric: impl Into<RylvStr<'m>>, _value: u64, _tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+    }
+
+    #[inline]
+    fn rate<'m, 't, TT>(&self, _metric: impl Into<RylvStr<'m>>, _delta: u64, _tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+    }
+
+    #[inline]
+    fn histogram_prefixed<'m, 't, TT>(
+        &self,
+        _prefix: &str,
+        _metric: impl Into<RylvStr<'m>>,
+        _value: u64,
+        _tags: TT,
+    ) where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+    }
+
+    #[inline]
+    fn count_add_prefixed<'m, 't, TT>(
+        &self,
+        _prefix: &str,
+        _metric: impl Into<RylvStr<'m>>,
+        _value: u64,
+        _tags: TT,
+    ) where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+    }
+
+    #[inline]
+    fn gauge_prefixed<'m, 't, TT>(
+        &self,
+        _prefix: &str,
+        _metric: impl Into<RylvStr<'m>>,
+        _value: u64,
+        _tags: TT,
+    ) where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+    }
+
+    #[inline]
+    fn count_multi<'m>(
+        &self,
+        _metric: impl Into<RylvStr<'m>>,
+        _value: u64,
+        _tag_sets: &[&[RylvStr<'_>]],
+    ) {
+    }
+
+    #[inline]
+    fn gauge_multi<'m>(
+        &self,
+        _metric: impl Into<RylvStr<'m>>,
+        _value: u64,
+        _tag_sets: &[&[RylvStr<'_>]],
+    ) {
+    }
+
+    #[inline]
+    fn histogram_kv<'m>(
+        &self,
+        _metric: impl Into<RylvStr<'m>>,
+        _value: u64,
+        _tags: &[(RylvStr<'_>, RylvStr<'_>)],
+    ) {
+    }
+
+    #[inline]
+    fn histogram_sorted<'m>(
+        &self,
+        _metric: impl Into<RylvStr<'m>>,
+        _value: u64,
+        _tags: &SortedTags<Self::Hasher>,
+    ) {
+    }
+
+    #[inline]
+    fn count_add_sorted<'m>(
+        &self,
+        _metric: impl Into<RylvStr<'m>>,
+        _value: u64,
+        _tags: &SortedTags<Self::Hasher>,
+    ) {
+    }
+
+    #[inline]
+    fn gauge_sorted<'m>(
+        &self,
+        _metric: impl Into<RylvStr<'m>>,
+        _value: u64,
+        _tags: &SortedTags<Self::Hasher>,
+    ) {
+    }
+
+    #[cold]
+    fn prepare_sorted_tags<'a>(
+        &self,
+        tags: impl IntoIterator<Item = RylvStr<'a>>,
+    ) -> SortedTags<Self::Hasher> {
+        SortedTags::new(tags, &self.hasher_builder)
+    }
+
+    #[cold]
+    fn prepare_metric<'m>(
+        &self,
+        metric: impl Into<RylvStr<'m>>,
+        tags: SortedTags<Self::Hasher>,
+    ) -> PreparedMetric<Self::Hasher> {
+        let metric = crate::dogstats::sorted_tags::to_static_metric(metric.into());
+        PreparedMetric::new(metric, tags, 0)
+    }
+
+    #[inline]
+    fn histogram_prepared(&self, _prepared: &PreparedMetric<Self::Hasher>, _value: u64) {}
+
+    #[inline]
+    fn count_add_prepared(&self, _prepared: &PreparedMetric<Self::Hasher>, _value: u64) {}
+
+    #[inline]
+    fn gauge_prepared(&self, _prepared: &PreparedMetric<Self::Hasher>, _value: u64) {}
+}
+
+impl DrainMetricCollectorTrait for NoopCollector {
+    type Drain<'a> = std::iter::Empty<MetricFrameRef<'a>>;
+
+    #[cold]
+    fn try_begin_drain(&self) -> Option<Self::Drain<'_>> {
+        Some(std::iter::empty())
+    }
+
+    #[cold]
+    fn try_begin_drain_until(&self, _deadline: Instant) -> Option<Self::Drain<'_>> {
+        self.try_begin_drain()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records one of every kind, the way an instrumented call site would.
+    fn instrumented<MC: MetricCollectorTrait>(metrics: &MC) {
+        let mut tags = [RylvStr::from_static("env:dev"), RylvStr::from_static("a:1")];
+        metrics.count(RylvStr::from_static("requests"), &mut tags);
+        metrics.gauge(RylvStr::from_static("load"), 3, &mut tags);
+        metrics.histogram(RylvStr::from_static("latency"), 40, &mut tags);
+        metrics.timed_count(RylvStr::from_static("query"), 5, &mut tags);
+        metrics.count_multi(RylvStr::from_static("hits"), 1, &[&tags, &[]]);
+        let sorted = metrics.prepare_sorted_tags(tags);
+        metrics.count_add_sorted(RylvStr::from_static("requests"), 2, &sorted);
+        let prepared = metrics.prepare_metric(RylvStr::from_static("latency"), sorted);
+        metrics.histogram_prepared(&prepared, 50);
+    }
+
+    #[test]
+    fn noop_collector_is_a_drop_in_that_records_nothing() {
+        let collector = NoopCollector::default();
+        instrumented(&collector);
+
+        assert_eq!(collector.try_begin_drain().unwrap().count(), 0);
+        let snapshot = collector.snapshot();
+        assert!(snapshot.counts.is_empty() && snapshot.histograms.is_empty());
+    }
+}
@@ -0,0 +1,74 @@
+use std::time::{Duration, Instant};
+
+use crate::dogstats::sorted_tags::to_static_metric;
+use crate::dogstats::RylvStr;
+
+use super::MetricCollectorTrait;
+
+/// Guard returned by [`MetricCollectorTrait::scope_timer`] that records the
+/// time since its creation, in microseconds, when dropped.
+///
+/// The metric name and tags are copied when the timer starts, so the guard
+/// does not borrow them. Dropping the guard while a panic unwinds still
+/// records; call [`Self::cancel`] on paths that should not be timed.
+///
+/// ```
+/// # #[cfg(any(feature = "shared-collector", feature = "simple-map"))] {
+/// use rylv_metrics::{MetricCollectorTrait, RylvStr, SharedCollector};
+///
+/// let collector = SharedCollector::default();
+/// {
+///     let _timer = collector.scope_timer("db.query", &[RylvStr::from_static("table:users")]);
+///     // timed work
+/// }
+/// # }
+/// ```
+#[must_use = "the timer records when dropped, binding it to `_` drops it immediately"]
+pub struct ScopeTimer<'c, C>
+where
+    C: MetricCollectorTrait + ?Sized,
+{
+    collector: &'c C,
+    metric: RylvStr<'static>,
+    tags: Vec<RylvStr<'static>>,
+    start: Instant,
+    armed: bool,
+}
+
+impl<'c, C> ScopeTimer<'c, C>
+where
+    C: MetricCollectorTrait + ?Sized,
+{
+    pub(crate) fn new(collector: &'c C, metric: RylvStr<'_>, tags: &[RylvStr<'_>]) -> Self {
+        Self {
+            collector,
+            metric: to_static_metric(metric),
+            tags: tags.iter().cloned().map(to_static_metric).collect(),
+            start: Instant::now(),
+            armed: true,
+        }
+    }
+
+    /// Returns the time elapsed since the timer started.
+    #[must_use]
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Stops the timer without recording.
+    pub fn cancel(mut self) {
+        self.armed = false;
+    }
+}
+
+impl<C> Drop for ScopeTimer<'_, C>
+where
+    C: MetricCollectorTrait + ?Sized,
+{
+    fn drop(&mut self) {
+        if self.armed {
+            self.collector
+                .timing_micros(self.metric.clone(), self.elapsed(), &mut self.tags);
+        }
+    }
+}
@@ -1,29 +1,104 @@
+use std::collections::VecDeque;
 use std::hash::BuildHasher;
+use std::num::NonZeroU64;
+use std::ops::{Deref, DerefMut};
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
 use std::{cmp::Ordering as CmpOrdering, collections::HashMap};
 
-use super::{DrainMetricCollectorTrait, MetricCollectorTrait};
+use super::{
+    is_valid_count_f64, join_stats_prefix, normalize_metric_name, per_second, round_value,
+    with_prefixed_name, DrainBudget, DrainMetricCollectorTrait, GaugeMode, MetricCollectorTrait,
+    TypePrefixes, TIMED_COUNT_SUFFIX, TIMED_LATENCY_SUFFIX,
+};
+#[cfg(not(feature = "shared-collector"))]
+use crate::dogstats::aggregator::RefMutMulti;
 use crate::dogstats::aggregator::{
-    to_agg_entry_key, AggregatorEntryKey, HistogramWrapper, LookupKey, LookupKeySorted, RemoveKey,
+    histogram_pools, to_agg_entry_key, AggregatorEntryKey, DistributionCursor, HistogramPool,
+    HistogramPools, HistogramWrapper, IdleEviction, LookupKey, LookupKeySorted, RemoveKey,
+    ShardedMap, SharedValue,
+};
+use crate::dogstats::collector::{
+    HistogramSnapshot, MetricFrameRef, MetricKind, MetricSuffix, Snapshot, SnapshotValue,
 };
-use crate::dogstats::collector::{MetricFrameRef, MetricKind, MetricSuffix};
 use crate::dogstats::histogram_config::{
-    resolve_histogram_configs, HistogramBaseMetric, HistogramConfig, ResolvedHistogramConfig,
-    ResolvedHistogramConfigs,
+    lowercase_histogram_config_keys, resolve_histogram_configs, HistogramBaseMetric,
+    HistogramConfig, ResolvedHistogramConfig, ResolvedHistogramConfigs,
 };
+use crate::dogstats::metric_filter::MetricFilterState;
 use crate::dogstats::sorted_tags::{combine_metric_tags_hash, hash_tags, PreparedMetric};
-use crate::dogstats::{Aggregator, RylvStr, SortedTags};
+use crate::dogstats::tag_interner::TagInterner;
+use crate::dogstats::{Aggregator, MetricFilter, RylvStr, SortedTags};
 use crate::DefaultMetricHasher;
 use arc_swap::ArcSwap;
-use dashmap::{DashMap, SharedValue};
+#[cfg(feature = "shared-collector")]
+use dashmap::mapref::multiple::RefMutMulti;
 use tracing::error;
 
 pub struct GaugeState {
     pub sum: AtomicU64,
     pub count: AtomicU64,
+    /// Nanoseconds since the collector started, plus one, of the last write
+    /// let through by a [`GaugeThrottle`]; `0` until then.
+    pub updated_at: AtomicU64,
+}
+
+/// Minimum interval between two recorded writes of a gauge series, from
+/// [`SharedCollectorOptions::gauge_min_update_intervals`].
+#[derive(Clone, Copy)]
+pub struct GaugeThrottle {
+    clock: Instant,
+    min_interval: u64,
+}
+
+impl GaugeState {
+    /// Returns `true` when `throttle` drops this write because the previous
+    /// recorded one is too recent; otherwise marks the series as updated now.
+    #[inline]
+    pub fn throttled(&self, throttle: Option<GaugeThrottle>) -> bool {
+        let Some(throttle) = throttle else {
+            return false;
+        };
+        let now = u64::try_from(throttle.clock.elapsed().as_nanos())
+            .unwrap_or(u64::MAX)
+            .saturating_add(1);
+        let updated_at = self.updated_at.load(Ordering::Relaxed);
+        if updated_at != 0 && now.saturating_sub(updated_at) < throttle.min_interval {
+            return true;
+        }
+        self.updated_at.store(now, Ordering::Relaxed);
+        false
+    }
+
+    #[inline]
+    pub fn record(&self, value: u64, mode: GaugeMode) {
+        match mode {
+            GaugeMode::Average => {
+                self.count.fetch_add(1, Ordering::Relaxed);
+                self.sum.fetch_add(value, Ordering::Relaxed);
+            }
+            GaugeMode::Last => {
+                // Loads keep the cache line shared between threads writing a
+                // steady gauge; only a changed value is stored.
+                if self.count.load(Ordering::Relaxed) == 1
+                    && self.sum.load(Ordering::Relaxed) == value
+                {
+                    return;
+                }
+                self.sum.store(value, Ordering::Relaxed);
+                self.count.store(1, Ordering::Relaxed);
+            }
+        }
+    }
 }
 
+/// Value of a `gauge_last` series without a recording in the current window.
+///
+/// A recorded `u64::MAX` is stored as `u64::MAX - 1`, which the agent parses
+/// to the same `f64`.
+pub const UNSET_LAST_GAUGE: u64 = u64::MAX;
+
 /// Configuration options for the metric collector.
 ///
 /// This variant does not spawn a background job and does not perform network I/O.
@@ -33,23 +108,129 @@ pub struct SharedCollectorOptions<S = DefaultMetricHasher>
 where
     S: BuildHasher + Clone,
 {
-    /// Prefix prepended verbatim to all metric names.
+    /// Prefix prepended verbatim to all metric names, unless
+    /// `prefix_separator` is set.
     pub stats_prefix: String,
+    /// Separator joining a non-empty `stats_prefix` and the metric name, e.g.
+    /// `Some(".")` turns `app` into `app.requests`. Not doubled when
+    /// `stats_prefix` already ends with it. Defaults to `None`, which prepends
+    /// `stats_prefix` verbatim.
+    pub prefix_separator: Option<&'static str>,
+    /// Prefix appended after `stats_prefix` to aggregated histogram names, so
+    /// `app` + `timing.` emits `app.timing.request.duration.count`. Raw timings
+    /// and emergency counters only take `stats_prefix`. Defaults to empty.
+    pub histogram_prefix: String,
+    /// Prefix appended after `stats_prefix` to count names. Defaults to empty.
+    pub count_prefix: String,
+    /// Prefix appended after `stats_prefix` to gauge names. Defaults to empty.
+    pub gauge_prefix: String,
     /// Per-metric histogram configuration for custom precision settings.
     pub histogram_configs: std::collections::HashMap<String, HistogramConfig, S>,
     /// Default histogram configuration when metric-specific config is absent.
     pub default_histogram_config: HistogramConfig,
     /// Hasher builder used by internal aggregation maps.
     pub hasher_builder: S,
+    /// Number of histograms allocated up front for every histogram pool.
+    ///
+    /// The pools start with this many histograms per distinct precision/bounds
+    /// pair, so the first recordings of new histogram metrics do not pay the
+    /// allocation cost. Pools are shared by every aggregator generation, and
+    /// histograms recycled by a drain go back to them. Defaults to `0`.
+    pub prewarm_histograms: usize,
+    /// Most histograms kept per histogram pool, i.e. per distinct
+    /// precision/bounds pair, for reuse.
+    ///
+    /// Histograms of evicted series go back to their pool; once it holds this
+    /// many they are freed instead, so a burst of short-lived histogram series
+    /// does not pin their memory forever. `prewarm_histograms` is capped to it
+    /// as well. Defaults to `None` (unbounded).
+    pub max_pooled_histograms_per_sigfig: Option<usize>,
+    /// Maximum number of swapped-out aggregators waiting to be drained.
+    ///
+    /// An aggregator swapped out for draining can still be referenced by recorders
+    /// that loaded it just before the swap. With a value above `1`, later drains
+    /// keep swapping in fresh aggregators and flush whichever pending one is released
+    /// first, instead of waiting on the oldest. Once the limit is reached drains
+    /// return `None` until a pending aggregator is released. Defaults to `1`; `0`
+    /// is treated as `1`.
+    pub max_pending_aggregators: usize,
+    /// ASCII-lowercases metric names before they become aggregation keys, so
+    /// `Request.Count` and `request.count` aggregate together.
+    ///
+    /// Names containing uppercase letters are copied into an owned string on
+    /// every call, so keep hot-path literals lowercase or use prepared metrics.
+    /// `histogram_configs` keys are lowercased as well. Defaults to `false`.
+    pub normalize_names: bool,
+    /// Drops metrics rejected by the filter before they are hashed or aggregated,
+    /// counting them in [`SharedCollector::filtered_count`]. Defaults to `None`.
+    pub metric_filter: Option<MetricFilter>,
+    /// Keeps count and gauge series without recordings in a flush window and
+    /// emits them as `0` instead of dropping them, so a healthy `errors:0`
+    /// signal keeps reporting.
+    ///
+    /// Kept series are only evicted after `key_idle_ttl`, so only enable this
+    /// with bounded tag cardinality. Histograms are still recycled once empty.
+    /// Defaults to `false`.
+    pub keep_zero_values: bool,
+    /// Evicts series kept by `keep_zero_values` once they have seen no
+    /// recordings for this long, checked at each drain. Defaults to `None`,
+    /// which keeps them forever.
+    pub key_idle_ttl: Option<Duration>,
+    /// Looks series up without sorting the `tags` slice of each call.
+    ///
+    /// Tag sets are hashed and compared regardless of order, and only sorted
+    /// once, on a copy, when a new series is created; the caller's slice is
+    /// left untouched. Faster for call sites passing a few tags in a stable
+    /// order, slower when the same tags come in varying orders. Defaults to
+    /// `false`.
+    pub skip_tag_sort: bool,
+    /// How gauge values recorded within one flush window are combined.
+    /// Defaults to [`GaugeMode::Average`].
+    pub gauge_mode: GaugeMode,
+    /// Rounds gauge and histogram values down to a multiple of this
+    /// granularity before they are recorded, e.g. `10` records `73` and `75`
+    /// as `70`.
+    ///
+    /// Trades precision for fewer distinct values, so histograms touch fewer
+    /// buckets. Gauges recorded with `gauge_bool` are rounded as well, so keep
+    /// it off for collectors recording flags. Counts are never rounded.
+    /// Defaults to `None`.
+    pub value_rounding: Option<NonZeroU64>,
+    /// Per-metric minimum interval between two recorded gauge writes, keyed by
+    /// metric name.
+    ///
+    /// A write to a listed gauge series arriving sooner than this after the
+    /// last recorded one is dropped before touching the shared value, trading
+    /// freshness for less contention on gauges updated from many threads.
+    /// The first write of each flush window is always recorded, so the flushed
+    /// value is the last write let through, which can be up to this old. Applies
+    /// to `gauge`, `gauge_sorted` and `gauge_prepared` in either [`GaugeMode`];
+    /// `gauge_last` is not throttled. Defaults to empty.
+    pub gauge_min_update_intervals: std::collections::HashMap<String, Duration, S>,
 }
 
 impl Default for SharedCollectorOptions<DefaultMetricHasher> {
     fn default() -> Self {
         Self {
             stats_prefix: String::new(),
+            prefix_separator: None,
+            histogram_prefix: String::new(),
+            count_prefix: String::new(),
+            gauge_prefix: String::new(),
             histogram_configs: std::collections::HashMap::new(),
             default_histogram_config: HistogramConfig::default(),
             hasher_builder: DefaultMetricHasher::new(),
+            prewarm_histograms: 0,
+            max_pooled_histograms_per_sigfig: None,
+            max_pending_aggregators: 1,
+            normalize_names: false,
+            metric_filter: None,
+            keep_zero_values: false,
+            key_idle_ttl: None,
+            skip_tag_sort: false,
+            gauge_mode: GaugeMode::Average,
+            value_rounding: None,
+            gauge_min_update_intervals: std::collections::HashMap::new(),
         }
     }
 }
@@ -63,13 +244,31 @@ where
     S: BuildHasher + Clone,
 {
     current_aggregator: Arc<ArcSwap<Aggregator<S>>>,
-    pending_to_process_aggregator: Mutex<Option<Arc<Aggregator<S>>>>,
+    pending_to_process_aggregators: Mutex<VecDeque<Arc<Aggregator<S>>>>,
     available_aggregator: Mutex<Option<Aggregator<S>>>,
+    // Aggregator whose drain stopped at its deadline, resumed by the next drain
+    interrupted_aggregator: Mutex<Option<Aggregator<S>>>,
     hasher_builder: S,
-    pool_count: usize,
+    // Shared by every aggregator generation so recycled histograms survive a swap
+    pool_histograms: HistogramPools,
+    histogram_creation_failures: Arc<AtomicU64>,
+    // Joined tags of the keys, shared by every aggregator generation
+    tag_interner: Arc<TagInterner>,
+    max_pending_aggregators: usize,
+    normalize_names: bool,
+    metric_filter: MetricFilterState,
+    keep_zero_values: bool,
+    key_idle_ttl: Option<Duration>,
+    skip_tag_sort: bool,
+    gauge_mode: GaugeMode,
+    value_rounding: Option<NonZeroU64>,
+    // Nanoseconds per normalized metric name
+    gauge_min_update_intervals: std::collections::HashMap<String, u64, S>,
+    created_at: Instant,
     default_histogram_config: ResolvedHistogramConfig,
     histogram_configs: std::collections::HashMap<String, ResolvedHistogramConfig, S>,
     stats_prefix: String,
+    type_prefixes: TypePrefixes,
 }
 
 impl Default for SharedCollector {
@@ -95,49 +294,68 @@ where
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum DrainStage {
     Count,
+    FloatCount,
     Gauge,
+    LastGauge,
+    Rate,
     Histogram,
     Done,
 }
 
-type CountDrainIter<'a, S> = dashmap::iter::Iter<
-    'a,
-    AggregatorEntryKey<S>,
-    AtomicU64,
-    S,
-    DashMap<AggregatorEntryKey<S>, AtomicU64, S>,
->;
-type GaugeDrainIter<'a, S> = dashmap::iter::Iter<
+#[cfg(feature = "shared-collector")]
+type DrainIter<'a, V, S> =
+    dashmap::iter::Iter<'a, AggregatorEntryKey<S>, V, S, ShardedMap<AggregatorEntryKey<S>, V, S>>;
+#[cfg(feature = "shared-collector")]
+type DrainIterMut<'a, V, S> = dashmap::iter::IterMut<
     'a,
     AggregatorEntryKey<S>,
-    GaugeState,
+    V,
     S,
-    DashMap<AggregatorEntryKey<S>, GaugeState, S>,
->;
-type HistogramDrainIter<'a, S> = dashmap::iter::IterMut<
-    'a,
-    AggregatorEntryKey<S>,
-    HistogramWrapper,
-    S,
-    DashMap<AggregatorEntryKey<S>, HistogramWrapper, S>,
+    ShardedMap<AggregatorEntryKey<S>, V, S>,
 >;
+#[cfg(not(feature = "shared-collector"))]
+type DrainIter<'a, V, S> =
+    crate::dogstats::aggregator::ShardedMapIter<'a, AggregatorEntryKey<S>, V>;
+#[cfg(not(feature = "shared-collector"))]
+type DrainIterMut<'a, V, S> =
+    crate::dogstats::aggregator::ShardedMapIterMut<'a, AggregatorEntryKey<S>, V>;
+
+type CountDrainIter<'a, S> = DrainIter<'a, AtomicU64, S>;
+type GaugeDrainIter<'a, S> = DrainIter<'a, GaugeState, S>;
+type HistogramDrainIter<'a, S> = DrainIterMut<'a, HistogramWrapper, S>;
 
 /// Stateful borrowed frame drainer.
 pub struct Frames<'a, S>
 where
     S: BuildHasher + Clone,
 {
-    prefix: &'a str,
+    prefixes: &'a TypePrefixes,
     stage: DrainStage,
     count_iter: Option<CountDrainIter<'a, S>>,
+    float_count_iter: Option<CountDrainIter<'a, S>>,
     gauge_iter: Option<GaugeDrainIter<'a, S>>,
+    last_gauge_iter: Option<CountDrainIter<'a, S>>,
+    rate_iter: Option<CountDrainIter<'a, S>>,
     histogram_iter: Option<HistogramDrainIter<'a, S>>,
-    count: &'a DashMap<AggregatorEntryKey<S>, AtomicU64, S>,
-    gauge: &'a DashMap<AggregatorEntryKey<S>, GaugeState, S>,
-    histogram: &'a DashMap<AggregatorEntryKey<S>, HistogramWrapper, S>,
-    pool_histograms: &'a [crossbeam::queue::SegQueue<HistogramWrapper>],
+    count: &'a ShardedMap<AggregatorEntryKey<S>, AtomicU64, S>,
+    float_counts: &'a ShardedMap<AggregatorEntryKey<S>, AtomicU64, S>,
+    gauge: &'a ShardedMap<AggregatorEntryKey<S>, GaugeState, S>,
+    last_gauges: &'a ShardedMap<AggregatorEntryKey<S>, AtomicU64, S>,
+    rates: &'a ShardedMap<AggregatorEntryKey<S>, AtomicU64, S>,
+    // Length of the drained generation's recording window, in seconds
+    rate_window_secs: f64,
+    histogram: &'a ShardedMap<AggregatorEntryKey<S>, HistogramWrapper, S>,
+    pool_histograms: &'a [HistogramPool],
     keys_to_remove: Vec<RemoveKey>,
     pending_histogram: Option<PendingHistogram<'a, S>>,
+    // Aggregator recording the next window; drained count and gauge keys are copied
+    // into it so they are emitted (as `0` if idle) by every later drain
+    carry_over: Option<&'a ArcSwap<Aggregator<S>>>,
+    // Drops carried-over keys once idle for longer than `key_idle_ttl`
+    idle_eviction: Option<IdleEviction>,
+    budget: DrainBudget,
+    // Set when resuming an interrupted drain, whose zeroed series were already emitted
+    resumed: bool,
 }
 
 struct PendingHistogram<'a, S>
@@ -146,8 +364,9 @@ where
 {
     metric: &'a str,
     tags: &'a str,
-    entry: dashmap::mapref::multiple::RefMutMulti<'a, AggregatorEntryKey<S>, HistogramWrapper>,
+    entry: RefMutMulti<'a, AggregatorEntryKey<S>, HistogramWrapper>,
     step: usize,
+    samples: DistributionCursor,
 }
 
 impl<S> SharedCollector<S>
@@ -159,65 +378,335 @@ where
     #[must_use]
     pub fn new(options: SharedCollectorOptions<S>) -> Self {
         let hasher_builder = options.hasher_builder.clone();
+        let stats_prefix = join_stats_prefix(options.stats_prefix, options.prefix_separator);
+        let type_prefixes = TypePrefixes::new(
+            &stats_prefix,
+            &options.histogram_prefix,
+            &options.count_prefix,
+            &options.gauge_prefix,
+        );
+        let histogram_configs = if options.normalize_names {
+            lowercase_histogram_config_keys(options.histogram_configs, &hasher_builder)
+        } else {
+            options.histogram_configs
+        };
         let ResolvedHistogramConfigs {
             default_histogram_config,
             histogram_configs,
-            pool_specs: _,
-            pool_count,
+            pool_specs,
         } = resolve_histogram_configs(
             options.default_histogram_config,
-            options.histogram_configs,
+            histogram_configs,
             &hasher_builder,
         );
+        let pool_histograms = histogram_pools(
+            &pool_specs,
+            options.prewarm_histograms,
+            options
+                .max_pooled_histograms_per_sigfig
+                .unwrap_or(usize::MAX),
+        );
+        let histogram_creation_failures = Arc::new(AtomicU64::new(0));
+        let tag_interner = Arc::new(TagInterner::default());
+        let mut gauge_min_update_intervals = std::collections::HashMap::with_capacity_and_hasher(
+            options.gauge_min_update_intervals.len(),
+            hasher_builder.clone(),
+        );
+        gauge_min_update_intervals.extend(options.gauge_min_update_intervals.into_iter().map(
+            |(metric, interval)| {
+                let metric = if options.normalize_names {
+                    metric.to_ascii_lowercase()
+                } else {
+                    metric
+                };
+                let nanos = u64::try_from(interval.as_nanos()).unwrap_or(u64::MAX);
+                (metric, nanos)
+            },
+        ));
         Self {
             current_aggregator: Arc::new(ArcSwap::new(Arc::new(Aggregator::with_hasher_builder(
                 &hasher_builder,
-                pool_count,
+                Arc::clone(&pool_histograms),
+                Arc::clone(&histogram_creation_failures),
+                Arc::clone(&tag_interner),
             )))),
-            pending_to_process_aggregator: Mutex::new(None),
+            pending_to_process_aggregators: Mutex::new(VecDeque::new()),
             available_aggregator: Mutex::new(None),
+            interrupted_aggregator: Mutex::new(None),
             hasher_builder,
-            pool_count,
+            pool_histograms,
+            histogram_creation_failures,
+            tag_interner,
+            max_pending_aggregators: options.max_pending_aggregators.max(1),
+            normalize_names: options.normalize_names,
+            metric_filter: MetricFilterState::new(options.metric_filter),
+            keep_zero_values: options.keep_zero_values,
+            key_idle_ttl: options.key_idle_ttl,
+            skip_tag_sort: options.skip_tag_sort,
+            gauge_mode: options.gauge_mode,
+            value_rounding: options.value_rounding,
+            gauge_min_update_intervals,
+            created_at: Instant::now(),
             default_histogram_config,
             histogram_configs,
-            stats_prefix: options.stats_prefix,
+            stats_prefix,
+            type_prefixes,
+        }
+    }
+
+    /// Returns how many recordings were dropped by the configured metric filter.
+    #[must_use]
+    pub fn filtered_count(&self) -> u64 {
+        self.metric_filter.filtered_count()
+    }
+
+    /// Returns how many histograms could not be created, e.g. because their
+    /// bounds are rejected by the histogram backend.
+    ///
+    /// Each failure drops the recording that needed the histogram and is logged;
+    /// a value that keeps growing points at a bad histogram configuration.
+    #[must_use]
+    pub fn histogram_creation_failures(&self) -> u64 {
+        self.histogram_creation_failures.load(Ordering::Relaxed)
+    }
+
+    /// Returns the series hash the collector uses for `metric` and `tags`.
+    ///
+    /// Adapters that already track series identities can compute this once per
+    /// series and feed it to [`Self::histogram_prehashed`],
+    /// [`Self::count_add_prehashed`] and [`Self::gauge_prehashed`] to skip
+    /// hashing on every recording. The hash depends on the collector's hasher,
+    /// so it is only valid for this collector (and clones sharing its hasher).
+    #[must_use]
+    pub fn prehash(&self, metric: &str, tags: &SortedTags<S>) -> u64 {
+        let metric = normalize_metric_name(RylvStr::Borrowed(metric), self.normalize_names);
+        combine_metric_tags_hash(&self.hasher_builder, metric.as_ref(), tags.tags_hash())
+    }
+
+    /// Records a histogram value like [`MetricCollectorTrait::histogram_sorted`],
+    /// reusing `hash` from [`Self::prehash`] instead of hashing the series.
+    ///
+    /// A `hash` that does not match the collector's hasher splits the series
+    /// into entries that never merge; debug builds assert that it matches.
+    #[inline]
+    pub fn histogram_prehashed(&self, hash: u64, metric: &str, value: u64, tags: &SortedTags<S>) {
+        let value = round_value(value, self.value_rounding);
+        let Some(metric) = self.accepted_name(metric) else {
+            return;
+        };
+        let aggregator = self.current_aggregator.load();
+        record_histogram_in_aggregator_sorted(
+            &aggregator,
+            &self.histogram_configs,
+            &self.default_histogram_config,
+            metric,
+            value,
+            tags,
+            Some(hash),
+        );
+    }
+
+    /// Adds to a count like [`MetricCollectorTrait::count_add_sorted`],
+    /// reusing `hash` from [`Self::prehash`] instead of hashing the series.
+    ///
+    /// See [`Self::histogram_prehashed`] for the requirements on `hash`.
+    #[inline]
+    pub fn count_add_prehashed(&self, hash: u64, metric: &str, value: u64, tags: &SortedTags<S>) {
+        let Some(metric) = self.accepted_name(metric) else {
+            return;
+        };
+        let aggregator = self.current_aggregator.load();
+        record_count_add_in_aggregator_sorted(&aggregator, metric, value, tags, Some(hash));
+    }
+
+    /// Records a gauge like [`MetricCollectorTrait::gauge_sorted`], reusing
+    /// `hash` from [`Self::prehash`] instead of hashing the series.
+    ///
+    /// See [`Self::histogram_prehashed`] for the requirements on `hash`.
+    #[inline]
+    pub fn gauge_prehashed(&self, hash: u64, metric: &str, value: u64, tags: &SortedTags<S>) {
+        let value = round_value(value, self.value_rounding);
+        let Some(metric) = self.accepted_name(metric) else {
+            return;
+        };
+        let throttle = self.gauge_throttle(metric.as_ref());
+        let aggregator = self.current_aggregator.load();
+        record_gauge_in_aggregator_sorted(
+            &aggregator,
+            metric,
+            value,
+            tags,
+            Some(hash),
+            self.gauge_mode,
+            throttle,
+        );
+    }
+
+    /// Returns the throttle of the gauge `metric`, by its normalized name.
+    #[inline]
+    fn gauge_throttle(&self, metric: &str) -> Option<GaugeThrottle> {
+        if self.gauge_min_update_intervals.is_empty() {
+            return None;
+        }
+        self.gauge_min_update_intervals
+            .get(metric)
+            .map(|&min_interval| GaugeThrottle {
+                clock: self.created_at,
+                min_interval,
+            })
+    }
+
+    /// Returns `metric` normalized, or `None` when the metric filter rejects it.
+    fn accepted_name<'m>(&self, metric: &'m str) -> Option<RylvStr<'m>> {
+        if self.metric_filter.rejects(metric) {
+            return None;
+        }
+        Some(normalize_metric_name(
+            RylvStr::Borrowed(metric),
+            self.normalize_names,
+        ))
+    }
+
+    /// Records the series of [`MetricCollectorTrait::timed_count`], sorting and
+    /// hashing `mut_tags` once for both lookups.
+    fn record_timed_count(
+        &self,
+        latency_metric: Option<RylvStr<'_>>,
+        count_metric: Option<RylvStr<'_>>,
+        latency: u64,
+        mut_tags: &mut [RylvStr<'_>],
+    ) {
+        let sort_tags = !self.skip_tag_sort;
+        if sort_tags {
+            mut_tags.sort_unstable();
+        }
+        let mut_tags = &*mut_tags;
+        let aggregator = self.current_aggregator.load();
+        let tags_hash = hash_tags(&self.hasher_builder, mut_tags);
+        if let Some(metric) = latency_metric {
+            let lookup_key = build_lookup_key_with_tags_hash(
+                metric,
+                mut_tags,
+                tags_hash,
+                sort_tags,
+                &aggregator.histograms,
+            );
+            record_histogram_with_key(
+                &aggregator,
+                &self.histogram_configs,
+                &self.default_histogram_config,
+                lookup_key,
+                latency,
+            );
+        }
+        if let Some(metric) = count_metric {
+            let hashmap = &aggregator.count;
+            let lookup_key =
+                build_lookup_key_with_tags_hash(metric, mut_tags, tags_hash, sort_tags, hashmap);
+            #[allow(clippy::cast_possible_truncation)]
+            let shard = hashmap.determine_shard(lookup_key.hash as usize);
+            let shard_lock = unsafe { hashmap.shards().get_unchecked(shard) };
+            let lookup = InterningLookup {
+                lookup_key,
+                interner: &aggregator.tag_interner,
+            };
+            add_or_insert_in_shard(
+                &**shard_lock,
+                lookup,
+                1,
+                |v, value| {
+                    v.fetch_add(value, Ordering::Relaxed);
+                    Ok(())
+                },
+                || Some(AtomicU64::new(0)),
+            );
         }
     }
 
     #[cold]
-    fn begin_drain(&self) -> Option<SharedDrain<'_, S>> {
-        let mut pending = self.pending_to_process_aggregator.try_lock().ok()?;
-        let alloc_agg = if let Some(alloc_agg) = pending.take() {
-            alloc_agg
-        } else {
+    fn begin_drain(&self, budget: DrainBudget) -> Option<SharedDrain<'_, S>> {
+        let interrupted = self.interrupted_aggregator.try_lock().ok()?.take();
+        if let Some(aggregator) = interrupted {
+            return Some(self.drain_aggregator(aggregator, budget, true));
+        }
+        let mut pending = self.pending_to_process_aggregators.try_lock().ok()?;
+        // Oldest pending aggregator that no recorder references anymore, otherwise swap
+        // out the current one while the pending ring has room.
+        let index = if let Some(index) = pending
+            .iter()
+            .position(|alloc_agg| Arc::strong_count(alloc_agg) == 1)
+        {
+            index
+        } else if pending.len() < self.max_pending_aggregators {
             let aggregator = self
                 .available_aggregator
                 .try_lock()
                 .ok()?
                 .take()
-                .unwrap_or_else(|| {
-                    Aggregator::with_hasher_builder(&self.hasher_builder, self.pool_count)
-                });
-            self.current_aggregator.swap(Arc::new(aggregator))
+                .map_or_else(
+                    || {
+                        Aggregator::with_hasher_builder(
+                            &self.hasher_builder,
+                            Arc::clone(&self.pool_histograms),
+                            Arc::clone(&self.histogram_creation_failures),
+                            Arc::clone(&self.tag_interner),
+                        )
+                    },
+                    |mut aggregator| {
+                        aggregator.open_window();
+                        aggregator
+                    },
+                );
+            let previous = self.current_aggregator.swap(Arc::new(aggregator));
+            previous.close_window();
+            pending.push_back(previous);
+            pending.len() - 1
+        } else {
+            return None;
         };
+        let alloc_agg = pending.remove(index)?;
         match Arc::try_unwrap(alloc_agg) {
-            Ok(aggregator) => {
-                let agg_ptr = Box::into_raw(Box::new(aggregator));
-                Some(SharedDrain {
-                    collector: self,
-                    frames: drain_aggregator_frames(
-                        unsafe { &*agg_ptr },
-                        self.stats_prefix.as_str(),
-                    ),
-                    aggregator: Some(agg_ptr),
-                })
-            }
+            Ok(aggregator) => Some(self.drain_aggregator(aggregator, budget, false)),
             Err(alloc_agg) => {
-                *pending = Some(alloc_agg);
+                pending.insert(index, alloc_agg);
                 None
             }
         }
     }
+
+    fn has_undrained_aggregator(&self) -> bool {
+        self.interrupted_aggregator
+            .lock()
+            .is_ok_and(|interrupted| interrupted.is_some())
+            || self
+                .pending_to_process_aggregators
+                .lock()
+                .is_ok_and(|pending| !pending.is_empty())
+    }
+
+    fn drain_aggregator(
+        &self,
+        aggregator: Aggregator<S>,
+        budget: DrainBudget,
+        resumed: bool,
+    ) -> SharedDrain<'_, S> {
+        let agg_ptr = Box::into_raw(Box::new(aggregator));
+        let mut frames = drain_aggregator_frames(
+            unsafe { &*agg_ptr },
+            &self.type_prefixes,
+            self.keep_zero_values.then_some(&*self.current_aggregator),
+            self.keep_zero_values
+                .then(|| IdleEviction::at(self.created_at, self.key_idle_ttl))
+                .flatten(),
+        );
+        frames.budget = budget;
+        frames.resumed = resumed;
+        SharedDrain {
+            collector: self,
+            frames,
+            aggregator: Some(agg_ptr),
+        }
+    }
 }
 
 impl<'a, S> Iterator for SharedDrain<'a, S>
@@ -240,36 +729,213 @@ where
         // SAFETY: because we only add not mutable alias,
         // There is no order in drop issues here
         if let Some(aggregator) = self.aggregator.take() {
-            if let Ok(mut available) = self.collector.available_aggregator.try_lock() {
-                *available = Some(unsafe { *Box::from_raw(aggregator) });
+            let aggregator = unsafe { *Box::from_raw(aggregator) };
+            if self.frames.budget.exceeded() {
+                *self
+                    .collector
+                    .interrupted_aggregator
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner) = Some(aggregator);
+            } else if let Ok(mut available) = self.collector.available_aggregator.try_lock() {
+                *available = Some(aggregator);
             }
         }
     }
 }
 
-fn add_or_insert_entry_read_first<V, S>(
-    metric: RylvStr<'_>,
-    tags: &[RylvStr<'_>],
+/// Raw table stored behind each `ShardedMap` shard lock.
+pub type ShardTable<K, V> = hashbrown_raw::raw::RawTable<(K, SharedValue<V>)>;
+
+/// Read/write lock guarding one shard table.
+///
+/// Implemented for the `DashMap` shard lock and the `simple-map` shard mutex; the
+/// `loom` tests implement it for `loom::sync::RwLock` so the insert paths run
+/// under the model checker.
+pub trait ShardLock<T> {
+    type ReadGuard<'a>: Deref<Target = T>
+    where
+        Self: 'a;
+    type WriteGuard<'a>: DerefMut<Target = T>
+    where
+        Self: 'a;
+
+    fn read(&self) -> Self::ReadGuard<'_>;
+
+    fn write(&self) -> Self::WriteGuard<'_>;
+}
+
+#[cfg(feature = "shared-collector")]
+impl<T> ShardLock<T> for dashmap::RwLock<T> {
+    type ReadGuard<'a>
+        = dashmap::RwLockReadGuard<'a, T>
+    where
+        T: 'a;
+    type WriteGuard<'a>
+        = dashmap::RwLockWriteGuard<'a, T>
+    where
+        T: 'a;
+
+    #[inline]
+    fn read(&self) -> Self::ReadGuard<'_> {
+        Self::read(self)
+    }
+
+    #[inline]
+    fn write(&self) -> Self::WriteGuard<'_> {
+        Self::write(self)
+    }
+}
+
+#[cfg(not(feature = "shared-collector"))]
+impl<T> ShardLock<T> for Mutex<T> {
+    type ReadGuard<'a>
+        = std::sync::MutexGuard<'a, T>
+    where
+        T: 'a;
+    type WriteGuard<'a>
+        = std::sync::MutexGuard<'a, T>
+    where
+        T: 'a;
+
+    #[inline]
+    fn read(&self) -> Self::ReadGuard<'_> {
+        self.lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    #[inline]
+    fn write(&self) -> Self::WriteGuard<'_> {
+        self.lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+/// Owned key stored in a shard table.
+pub trait ShardKey {
+    /// Hash the entry was inserted with, used when the table grows.
+    fn hash(&self) -> u64;
+}
+
+impl<S: BuildHasher + Clone> ShardKey for AggregatorEntryKey<S> {
+    #[inline]
+    fn hash(&self) -> u64 {
+        self.hash
+    }
+}
+
+/// Borrowed lookup that finds a shard entry and materializes its owned key on insert.
+pub trait ShardLookup<K> {
+    fn hash(&self) -> u64;
+
+    fn matches(&self, key: &K) -> bool;
+
+    fn into_key(self) -> K;
+}
+
+/// Borrowed lookup whose key, once inserted, takes its joined tags from the
+/// collector's tag interner.
+struct InterningLookup<'a> {
+    lookup_key: LookupKey<'a>,
+    interner: &'a TagInterner,
+}
+
+impl<S: BuildHasher + Clone> ShardLookup<AggregatorEntryKey<S>> for InterningLookup<'_> {
+    #[inline]
+    fn hash(&self) -> u64 {
+        self.lookup_key.hash
+    }
+
+    #[inline]
+    fn matches(&self, key: &AggregatorEntryKey<S>) -> bool {
+        self.lookup_key.compare(key)
+    }
+
+    #[inline]
+    fn into_key(self) -> AggregatorEntryKey<S> {
+        self.lookup_key.into_key_interned(self.interner)
+    }
+}
+
+impl<S: BuildHasher + Clone> ShardLookup<AggregatorEntryKey<S>> for LookupKeySorted<'_, S> {
+    #[inline]
+    fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    #[inline]
+    fn matches(&self, key: &AggregatorEntryKey<S>) -> bool {
+        self.compare(key)
+    }
+
+    #[inline]
+    fn into_key(self) -> AggregatorEntryKey<S> {
+        LookupKeySorted::into_key(self)
+    }
+}
+
+impl<S: BuildHasher + Clone> ShardLookup<AggregatorEntryKey<S>> for &AggregatorEntryKey<S> {
+    #[inline]
+    fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    #[inline]
+    fn matches(&self, key: &AggregatorEntryKey<S>) -> bool {
+        *self == key
+    }
+
+    #[inline]
+    fn into_key(self) -> AggregatorEntryKey<S> {
+        self.clone()
+    }
+}
+
+/// Inserts an entry for `key` into `map` unless it already has one, in which
+/// case the last activity of `key` is carried into it.
+fn add_or_insert_in_aggregator_map<V, S>(
+    map: &ShardedMap<AggregatorEntryKey<S>, V, S>,
+    key: &AggregatorEntryKey<S>,
+    new_fn: impl FnOnce() -> V,
+) where
+    S: BuildHasher + Clone,
+{
+    #[allow(clippy::cast_possible_truncation)]
+    let shard = map.determine_shard(key.hash as usize);
+    let shard_lock = unsafe { map.shards().get_unchecked(shard) };
+    {
+        let read_guard = shard_lock.read();
+        if let Some(bucket) = read_guard.find(key.hash, |(k, _)| k == key) {
+            // SAFETY: the read guard is alive, so there are no concurrent writers in the shard
+            unsafe { bucket.as_ref() }.0.carry_last_active(key);
+            return;
+        }
+    }
+    add_or_insert_in_shard(&**shard_lock, key, 0, |_, _| Ok(()), || Some(new_fn()));
+}
+
+/// Records `value` into the entry of `shard_lock` matching `lookup`, inserting a
+/// new entry from `new_fn` when there is none.
+///
+/// Existing entries are updated under the read lock. Only a miss takes the write
+/// lock, where the lookup is repeated because another recorder may have inserted
+/// the same key between the two locks.
+fn add_or_insert_in_shard<L, K, V>(
+    shard_lock: &impl ShardLock<ShardTable<K, V>>,
+    lookup: L,
     value: u64,
-    hashmap: &DashMap<AggregatorEntryKey<S>, V, impl BuildHasher + Clone>,
     record_fn: impl FnOnce(&V, u64) -> Result<(), String>,
     new_fn: impl FnOnce() -> Option<V>,
 ) where
-    S: BuildHasher + Clone,
+    L: ShardLookup<K>,
+    K: ShardKey,
 {
-    let lookup_key = build_lookup_key(metric, tags, hashmap);
-
-    #[allow(clippy::cast_possible_truncation)]
-    let shard = hashmap.determine_shard(lookup_key.hash as usize);
-    let shard_lock = unsafe { hashmap.shards().get_unchecked(shard) };
+    let hash = lookup.hash();
 
     // fast path using read lock only
     {
-        let search_result = shard_lock
-            .read()
-            .find(lookup_key.hash, |(k, _)| lookup_key.compare(k));
-        if let Some(bucket) = search_result {
-            // SAFETY: because we have a shard_lock with read access, there are no concurrent writer in the shard
+        let read_guard = shard_lock.read();
+        if let Some(bucket) = read_guard.find(hash, |(k, _)| lookup.matches(k)) {
+            // SAFETY: the read guard is alive, so there are no concurrent writers in the shard
             let x = unsafe { bucket.as_ref() }.1.get();
             if let Err(err) = record_fn(x, value) {
                 error!("Fail to record: {err}");
@@ -281,11 +947,8 @@ fn add_or_insert_entry_read_first<V, S>(
     let mut guard = shard_lock.write();
 
     // lookup again
-    let search_result = guard.find_or_find_insert_slot(
-        lookup_key.hash,
-        |(k, _)| lookup_key.compare(k),
-        |(k, _)| k.hash,
-    );
+    let search_result =
+        guard.find_or_find_insert_slot(hash, |(k, _)| lookup.matches(k), |(k, _)| k.hash());
 
     match search_result {
         // fast path using write lock
@@ -303,27 +966,74 @@ fn add_or_insert_entry_read_first<V, S>(
                     error!("Fail to record: {err}");
                 }
 
-                let agg_key = lookup_key.into_key();
+                let key = lookup.into_key();
 
+                // SAFETY: `insert_slot` was found under this write guard and the table
+                // has not been modified since.
                 unsafe {
-                    guard.insert_in_slot(agg_key.hash, insert_slot, (agg_key, SharedValue::new(v)));
+                    guard.insert_in_slot(hash, insert_slot, (key, SharedValue::new(v)));
                 }
             }
         }
     }
 }
 
+fn add_or_insert_entry_read_first<V, S>(
+    metric: RylvStr<'_>,
+    tags: &[RylvStr<'_>],
+    sorted: bool,
+    value: u64,
+    (hashmap, interner): (&ShardedMap<AggregatorEntryKey<S>, V, S>, &TagInterner),
+    record_fn: impl FnOnce(&V, u64) -> Result<(), String>,
+    new_fn: impl FnOnce() -> Option<V>,
+) where
+    S: BuildHasher + Clone,
+{
+    let lookup_key = build_lookup_key(metric, tags, sorted, hashmap);
+
+    #[allow(clippy::cast_possible_truncation)]
+    let shard = hashmap.determine_shard(lookup_key.hash as usize);
+    let shard_lock = unsafe { hashmap.shards().get_unchecked(shard) };
+    let lookup = InterningLookup {
+        lookup_key,
+        interner,
+    };
+    add_or_insert_in_shard(&**shard_lock, lookup, value, record_fn, new_fn);
+}
+
+/// Returns the series hash of `metric` and `sorted_tags`, trusting `prehash`
+/// when the caller already computed it.
+fn sorted_series_hash<S: BuildHasher + Clone>(
+    hasher_builder: &S,
+    metric: &str,
+    sorted_tags: &SortedTags<S>,
+    prehash: Option<u64>,
+) -> u64 {
+    prehash.map_or_else(
+        || combine_metric_tags_hash(hasher_builder, metric, sorted_tags.tags_hash()),
+        |hash| {
+            debug_assert_eq!(
+                hash,
+                combine_metric_tags_hash(hasher_builder, metric, sorted_tags.tags_hash()),
+                "prehashed series hash does not match the collector hasher for {metric}"
+            );
+            hash
+        },
+    )
+}
+
 fn add_or_insert_entry_read_first_sorted<V, S>(
     metric: RylvStr<'_>,
     sorted_tags: &SortedTags<S>,
+    prehash: Option<u64>,
     value: u64,
-    hashmap: &DashMap<AggregatorEntryKey<S>, V, S>,
+    hashmap: &ShardedMap<AggregatorEntryKey<S>, V, S>,
     record_fn: impl FnOnce(&V, u64) -> Result<(), String>,
     new_fn: impl FnOnce() -> Option<V>,
 ) where
     S: BuildHasher + Clone,
 {
-    let hash = combine_metric_tags_hash(hashmap.hasher(), metric.as_ref(), sorted_tags.tags_hash());
+    let hash = sorted_series_hash(hashmap.hasher(), metric.as_ref(), sorted_tags, prehash);
     let lookup_key = LookupKeySorted {
         metric,
         sorted_tags,
@@ -333,64 +1043,26 @@ fn add_or_insert_entry_read_first_sorted<V, S>(
     #[allow(clippy::cast_possible_truncation)]
     let shard = hashmap.determine_shard(lookup_key.hash as usize);
     let shard_lock = unsafe { hashmap.shards().get_unchecked(shard) };
+    add_or_insert_in_shard(&**shard_lock, lookup_key, value, record_fn, new_fn);
+}
+
+fn add_or_insert_entry_read_first_prepared<V, S>(
+    prepared: &PreparedMetric<S>,
+    value: u64,
+    hashmap: &ShardedMap<AggregatorEntryKey<S>, V, S>,
+    record_fn: impl FnOnce(&V, u64) -> Result<(), String>,
+    new_fn: impl FnOnce() -> Option<V>,
+) where
+    S: BuildHasher + Clone,
+{
+    let entry_id = prepared.prepared_id();
+    #[allow(clippy::cast_possible_truncation)]
+    let shard = hashmap.determine_shard(prepared.hash() as usize);
+    let shard_lock = unsafe { hashmap.shards().get_unchecked(shard) };
 
     {
-        let search_result = shard_lock
-            .read()
-            .find(lookup_key.hash, |(k, _)| lookup_key.compare(k));
-        if let Some(bucket) = search_result {
-            let x = unsafe { bucket.as_ref() }.1.get();
-            if let Err(err) = record_fn(x, value) {
-                error!("Fail to record: {err}");
-            }
-            return;
-        }
-    }
-
-    let mut guard = shard_lock.write();
-    let search_result = guard.find_or_find_insert_slot(
-        lookup_key.hash,
-        |(k, _)| lookup_key.compare(k),
-        |(k, _)| k.hash,
-    );
-
-    match search_result {
-        Ok(bucket) => {
-            if let Err(err) = record_fn(unsafe { bucket.as_ref() }.1.get(), value) {
-                error!("Fail to record: {err}");
-            }
-        }
-        Err(insert_slot) => {
-            if let Some(v) = new_fn() {
-                if let Err(err) = record_fn(&v, value) {
-                    error!("Fail to record: {err}");
-                }
-                let agg_key = lookup_key.into_key();
-                unsafe {
-                    guard.insert_in_slot(agg_key.hash, insert_slot, (agg_key, SharedValue::new(v)));
-                }
-            }
-        }
-    }
-}
-
-fn add_or_insert_entry_read_first_prepared<V, S>(
-    prepared: &PreparedMetric<S>,
-    value: u64,
-    hashmap: &DashMap<AggregatorEntryKey<S>, V, S>,
-    record_fn: impl FnOnce(&V, u64) -> Result<(), String>,
-    new_fn: impl FnOnce() -> Option<V>,
-) where
-    S: BuildHasher + Clone,
-{
-    let entry_id = prepared.prepared_id();
-    #[allow(clippy::cast_possible_truncation)]
-    let shard = hashmap.determine_shard(prepared.hash() as usize);
-    let shard_lock = unsafe { hashmap.shards().get_unchecked(shard) };
-
-    {
-        let read_guard = shard_lock.read();
-        let search_result = read_guard.find(prepared.hash(), |(k, _)| k.id == entry_id);
+        let read_guard = shard_lock.read();
+        let search_result = read_guard.find(prepared.hash(), |(k, _)| k.id == entry_id);
         if let Some(bucket) = search_result {
             let x = unsafe { bucket.as_ref() }.1.get();
             if let Err(err) = record_fn(x, value) {
@@ -449,20 +1121,35 @@ fn add_or_insert_entry_read_first_prepared<V, S>(
 fn build_lookup_key<'a, V, S>(
     metric: RylvStr<'a>,
     tags: &'a [RylvStr<'a>],
-    hashmap: &DashMap<AggregatorEntryKey<S>, V, impl BuildHasher + Clone>,
+    sorted: bool,
+    hashmap: &ShardedMap<AggregatorEntryKey<S>, V, impl BuildHasher + Clone>,
+) -> LookupKey<'a>
+where
+    S: BuildHasher + Clone,
+{
+    let tags_hash = hash_tags(hashmap.hasher(), tags);
+    build_lookup_key_with_tags_hash(metric, tags, tags_hash, sorted, hashmap)
+}
+
+/// Same as [`build_lookup_key`], reusing a `tags_hash` already computed for `tags`.
+fn build_lookup_key_with_tags_hash<'a, V, S>(
+    metric: RylvStr<'a>,
+    tags: &'a [RylvStr<'a>],
+    tags_hash: u64,
+    sorted: bool,
+    hashmap: &ShardedMap<AggregatorEntryKey<S>, V, impl BuildHasher + Clone>,
 ) -> LookupKey<'a>
 where
     S: BuildHasher + Clone,
 {
-    let hasher_builder = hashmap.hasher();
-    let tags_hash = hash_tags(hasher_builder, tags);
-    let hash = combine_metric_tags_hash(hasher_builder, metric.as_ref(), tags_hash);
+    let hash = combine_metric_tags_hash(hashmap.hasher(), metric.as_ref(), tags_hash);
 
     LookupKey {
         metric,
         tags,
         tags_hash,
         hash,
+        sorted,
     }
 }
 
@@ -477,7 +1164,7 @@ fn match_prepared_signature<S: BuildHasher + Clone>(
 }
 
 pub fn remove_from_map<V, SH, S>(
-    map: &DashMap<AggregatorEntryKey<S>, V, SH>,
+    map: &ShardedMap<AggregatorEntryKey<S>, V, SH>,
     key: &RemoveKey,
     mut on_removed: impl FnMut(V),
 ) where
@@ -494,35 +1181,57 @@ pub fn remove_from_map<V, SH, S>(
     }
 }
 
+// Memory ordering: the drain only reads counters and gauges of an aggregator
+// obtained through `Arc::try_unwrap`, after every recorder dropped its
+// `ArcSwap` guard. Whether a released debt slot orders the recorders'
+// `Relaxed` increments before `try_unwrap` is an `ArcSwap` implementation
+// detail, so the drain does not rely on it: loads are `Acquire` and the resets
+// to zero are `Release`, pairing with the `available_aggregator` mutex and the
+// `ArcSwap` store that installs the aggregator again. Neither needs the total
+// order of `SeqCst`.
 impl<'a, S> Frames<'a, S>
 where
     S: BuildHasher + Clone,
 {
     fn emit_count_metric(&mut self) -> Option<MetricFrameRef<'a>> {
         if let Some(iter) = self.count_iter.as_mut() {
-            for entry in iter.by_ref() {
-                let value = entry.value().load(Ordering::SeqCst);
-                if value == 0 {
-                    self.keys_to_remove.push(entry.key().remove_key());
+            while !self.budget.exhausted() {
+                let Some(entry) = iter.next() else {
+                    break;
+                };
+                let value = entry.value().load(Ordering::Acquire);
+                let key = entry.key();
+                let expired = self
+                    .idle_eviction
+                    .is_some_and(|eviction| eviction.is_expired(key, value != 0));
+                let carry_over = self.carry_over.filter(|_| !expired);
+                if let Some(carry_over) = carry_over {
+                    add_or_insert_in_aggregator_map(&carry_over.load().count, key, || {
+                        AtomicU64::new(0)
+                    });
+                }
+                if value == 0 && (self.resumed || carry_over.is_none()) {
+                    self.keys_to_remove.push(key.remove_key());
                     continue;
                 }
 
-                let key = entry.key();
-                // SAFETY: key metric/tags are stored in `Cow<'static, str>`. Entries with
-                // value > 0 are not removed in this drain cycle, so references remain valid.
+                // SAFETY: key metric/tags are stored in `Cow<'static, str>`. Emitted entries
+                // are not removed in this drain cycle, so references remain valid.
                 let (metric, tags) = unsafe {
                     (
                         std::mem::transmute::<&str, &'a str>(key.metric.as_ref()),
                         std::mem::transmute::<&str, &'a str>(key.tags.joined_tags()),
                     )
                 };
-                entry.value().store(0, Ordering::SeqCst);
+                entry.value().store(0, Ordering::Release);
                 return Some(MetricFrameRef {
-                    prefix: self.prefix,
+                    prefix: &self.prefixes.count,
                     metric,
                     suffix: MetricSuffix::None,
                     tags,
+                    unit: None,
                     value,
+                    float_value: None,
                     kind: MetricKind::Count,
                 });
             }
@@ -533,37 +1242,110 @@ where
             remove_from_map(self.count, key, |_| ());
         }
         self.keys_to_remove.clear();
+        self.stage = DrainStage::FloatCount;
+        None
+    }
+
+    fn emit_float_count_metric(&mut self) -> Option<MetricFrameRef<'a>> {
+        if let Some(iter) = self.float_count_iter.as_mut() {
+            while !self.budget.exhausted() {
+                let Some(entry) = iter.next() else {
+                    break;
+                };
+                let value = f64::from_bits(entry.value().load(Ordering::Acquire));
+                let key = entry.key();
+                let expired = self
+                    .idle_eviction
+                    .is_some_and(|eviction| eviction.is_expired(key, value != 0.0));
+                let carry_over = self.carry_over.filter(|_| !expired);
+                if let Some(carry_over) = carry_over {
+                    add_or_insert_in_aggregator_map(&carry_over.load().float_counts, key, || {
+                        AtomicU64::new(0)
+                    });
+                }
+                if value == 0.0 && (self.resumed || carry_over.is_none()) {
+                    self.keys_to_remove.push(key.remove_key());
+                    continue;
+                }
+
+                // SAFETY: key metric/tags are stored in `Cow<'static, str>`. Emitted entries
+                // are not removed in this drain cycle, so references remain valid.
+                let (metric, tags) = unsafe {
+                    (
+                        std::mem::transmute::<&str, &'a str>(key.metric.as_ref()),
+                        std::mem::transmute::<&str, &'a str>(key.tags.joined_tags()),
+                    )
+                };
+                entry.value().store(0, Ordering::Release);
+                return Some(MetricFrameRef {
+                    prefix: &self.prefixes.count,
+                    metric,
+                    suffix: MetricSuffix::None,
+                    tags,
+                    unit: None,
+                    value: 0,
+                    float_value: Some(value),
+                    kind: MetricKind::Count,
+                });
+            }
+        }
+
+        self.float_count_iter = None;
+        for key in &self.keys_to_remove {
+            remove_from_map(self.float_counts, key, |_| ());
+        }
+        self.keys_to_remove.clear();
         self.stage = DrainStage::Gauge;
         None
     }
 
     fn emit_gauge_metric(&mut self) -> Option<MetricFrameRef<'a>> {
         if let Some(iter) = self.gauge_iter.as_mut() {
-            for entry in iter.by_ref() {
-                let count = entry.count.load(Ordering::SeqCst);
-                if count == 0 {
-                    self.keys_to_remove.push(entry.key().remove_key());
+            while !self.budget.exhausted() {
+                let Some(entry) = iter.next() else {
+                    break;
+                };
+                let count = entry.count.load(Ordering::Acquire);
+                let key = entry.key();
+                let expired = self
+                    .idle_eviction
+                    .is_some_and(|eviction| eviction.is_expired(key, count != 0));
+                let carry_over = self.carry_over.filter(|_| !expired);
+                if let Some(carry_over) = carry_over {
+                    add_or_insert_in_aggregator_map(&carry_over.load().gauge, key, || GaugeState {
+                        sum: AtomicU64::new(0),
+                        count: AtomicU64::new(0),
+                        updated_at: AtomicU64::new(0),
+                    });
+                }
+                if count == 0 && (self.resumed || carry_over.is_none()) {
+                    self.keys_to_remove.push(key.remove_key());
                     continue;
                 }
 
-                let value = entry.sum.load(Ordering::SeqCst) / count;
-                let key = entry.key();
-                // SAFETY: key metric/tags are stored in `Cow<'static, str>`. Entries with
-                // count > 0 are not removed in this drain cycle, so references remain valid.
+                let value = entry
+                    .sum
+                    .load(Ordering::Acquire)
+                    .checked_div(count)
+                    .unwrap_or(0);
+                // SAFETY: key metric/tags are stored in `Cow<'static, str>`. Emitted entries
+                // are not removed in this drain cycle, so references remain valid.
                 let (metric, tags) = unsafe {
                     (
                         std::mem::transmute::<&str, &'a str>(key.metric.as_ref()),
                         std::mem::transmute::<&str, &'a str>(key.tags.joined_tags()),
                     )
                 };
-                entry.sum.store(0, Ordering::SeqCst);
-                entry.count.store(0, Ordering::SeqCst);
+                entry.sum.store(0, Ordering::Release);
+                entry.count.store(0, Ordering::Release);
                 return Some(MetricFrameRef {
-                    prefix: self.prefix,
+                    prefix: &self.prefixes.gauge,
                     metric,
                     suffix: MetricSuffix::None,
                     tags,
+                    unit: None,
                     value,
+                    float_value: None,
                     kind: MetricKind::Gauge,
                 });
             }
@@ -574,14 +1356,124 @@ where
             remove_from_map(self.gauge, key, |_k| ());
         }
         self.keys_to_remove.clear();
+        self.stage = DrainStage::LastGauge;
+        None
+    }
+
+    fn emit_last_gauge_metric(&mut self) -> Option<MetricFrameRef<'a>> {
+        if let Some(iter) = self.last_gauge_iter.as_mut() {
+            while !self.budget.exhausted() {
+                let Some(entry) = iter.next() else {
+                    break;
+                };
+                let value = entry.value().load(Ordering::Acquire);
+                let recorded = value != UNSET_LAST_GAUGE;
+                let key = entry.key();
+                let expired = self
+                    .idle_eviction
+                    .is_some_and(|eviction| eviction.is_expired(key, recorded));
+                let carry_over = self.carry_over.filter(|_| !expired);
+                if let Some(carry_over) = carry_over {
+                    add_or_insert_in_aggregator_map(&carry_over.load().last_gauges, key, || {
+                        AtomicU64::new(UNSET_LAST_GAUGE)
+                    });
+                }
+                if !recorded && (self.resumed || carry_over.is_none()) {
+                    self.keys_to_remove.push(key.remove_key());
+                    continue;
+                }
+
+                // SAFETY: key metric/tags are stored in `Cow<'static, str>`. Emitted entries
+                // are not removed in this drain cycle, so references remain valid.
+                let (metric, tags) = unsafe {
+                    (
+                        std::mem::transmute::<&str, &'a str>(key.metric.as_ref()),
+                        std::mem::transmute::<&str, &'a str>(key.tags.joined_tags()),
+                    )
+                };
+                entry.value().store(UNSET_LAST_GAUGE, Ordering::Release);
+                return Some(MetricFrameRef {
+                    prefix: &self.prefixes.gauge,
+                    metric,
+                    suffix: MetricSuffix::None,
+                    tags,
+                    unit: None,
+                    value: if recorded { value } else { 0 },
+                    float_value: None,
+                    kind: MetricKind::Gauge,
+                });
+            }
+        }
+
+        self.last_gauge_iter = None;
+        for key in &self.keys_to_remove {
+            remove_from_map(self.last_gauges, key, |_| ());
+        }
+        self.keys_to_remove.clear();
+        self.stage = DrainStage::Rate;
+        None
+    }
+
+    fn emit_rate_metric(&mut self) -> Option<MetricFrameRef<'a>> {
+        if let Some(iter) = self.rate_iter.as_mut() {
+            while !self.budget.exhausted() {
+                let Some(entry) = iter.next() else {
+                    break;
+                };
+                let delta = entry.value().load(Ordering::Acquire);
+                let key = entry.key();
+                let expired = self
+                    .idle_eviction
+                    .is_some_and(|eviction| eviction.is_expired(key, delta != 0));
+                let carry_over = self.carry_over.filter(|_| !expired);
+                if let Some(carry_over) = carry_over {
+                    add_or_insert_in_aggregator_map(&carry_over.load().rates, key, || {
+                        AtomicU64::new(0)
+                    });
+                }
+                if delta == 0 && (self.resumed || carry_over.is_none()) {
+                    self.keys_to_remove.push(key.remove_key());
+                    continue;
+                }
+
+                // SAFETY: key metric/tags are stored in `Cow<'static, str>`. Emitted entries
+                // are not removed in this drain cycle, so references remain valid.
+                let (metric, tags) = unsafe {
+                    (
+                        std::mem::transmute::<&str, &'a str>(key.metric.as_ref()),
+                        std::mem::transmute::<&str, &'a str>(key.tags.joined_tags()),
+                    )
+                };
+                entry.value().store(0, Ordering::Release);
+                return Some(MetricFrameRef {
+                    prefix: &self.prefixes.gauge,
+                    metric,
+                    suffix: MetricSuffix::None,
+                    tags,
+                    unit: None,
+                    value: 0,
+                    float_value: Some(per_second(delta, self.rate_window_secs)),
+                    kind: MetricKind::Gauge,
+                });
+            }
+        }
+
+        self.rate_iter = None;
+        for key in &self.keys_to_remove {
+            remove_from_map(self.rates, key, |_| ());
+        }
+        self.keys_to_remove.clear();
         self.stage = DrainStage::Histogram;
         None
     }
 
     fn load_next_histogram(&mut self) -> bool {
         if let Some(iter) = self.histogram_iter.as_mut() {
-            for histogram_entry in iter.by_ref() {
-                if histogram_entry.value().histogram.is_empty() {
+            while !self.budget.exhausted() {
+                let Some(histogram_entry) = iter.next() else {
+                    break;
+                };
+                if histogram_entry.value().is_empty() {
                     self.keys_to_remove.push(histogram_entry.key().remove_key());
                     continue;
                 }
@@ -607,6 +1499,7 @@ where
                     tags,
                     entry: histogram_entry,
                     step: 0,
+                    samples: DistributionCursor::default(),
                 };
                 self.pending_histogram = Some(pending);
                 return true;
@@ -620,80 +1513,79 @@ where
         let mut pending = self.pending_histogram.take()?;
         loop {
             let entry = pending.entry.value_mut();
-            let percentile_count = entry.percentiles.len();
+            let percentile_count = entry.emitted_percentiles();
             let frame = match pending.step {
-                0 => {
-                    pending.step += 1;
-                    if entry.emits(HistogramBaseMetric::Count) {
-                        Some(MetricFrameRef {
-                            prefix: self.prefix,
-                            metric: pending.metric,
-                            suffix: MetricSuffix::Static(".count"),
-                            tags: pending.tags,
-                            value: entry.histogram.len(),
-                            kind: MetricKind::Count,
-                        })
-                    } else {
-                        None
-                    }
-                }
+                // distribution samples replace every line but `.overflow_count`
+                0 => entry
+                    .next_distribution_frame(
+                        &mut pending.samples,
+                        &self.prefixes.histogram,
+                        pending.metric,
+                        pending.tags,
+                    )
+                    .or_else(|| {
+                        pending.step += 1;
+                        entry.base_metric_frame(
+                            HistogramBaseMetric::Count,
+                            &self.prefixes.histogram,
+                            pending.metric,
+                            pending.tags,
+                        )
+                    }),
                 1 => {
                     pending.step += 1;
-                    if entry.emits(HistogramBaseMetric::Min) {
-                        Some(MetricFrameRef {
-                            prefix: self.prefix,
-                            metric: pending.metric,
-                            suffix: MetricSuffix::Static(".min"),
-                            tags: pending.tags,
-                            value: entry.min,
-                            kind: MetricKind::Gauge,
-                        })
-                    } else {
-                        None
-                    }
+                    entry.base_metric_frame(
+                        HistogramBaseMetric::Min,
+                        &self.prefixes.histogram,
+                        pending.metric,
+                        pending.tags,
+                    )
                 }
                 2 => {
                     pending.step += 1;
-                    if entry.emits(HistogramBaseMetric::Avg) {
-                        Some(MetricFrameRef {
-                            prefix: self.prefix,
-                            metric: pending.metric,
-                            suffix: MetricSuffix::Static(".avg"),
-                            tags: pending.tags,
-                            value: entry.histogram.value_at_quantile(0.50),
-                            kind: MetricKind::Gauge,
-                        })
-                    } else {
-                        None
-                    }
+                    entry.base_metric_frame(
+                        HistogramBaseMetric::Avg,
+                        &self.prefixes.histogram,
+                        pending.metric,
+                        pending.tags,
+                    )
                 }
                 index if index < 3 + percentile_count => {
                     pending.step += 1;
                     let percentile_index = index - 3;
                     let percentile = entry.percentiles[percentile_index];
                     Some(MetricFrameRef {
-                        prefix: self.prefix,
+                        prefix: &self.prefixes.histogram,
                         metric: pending.metric,
                         suffix: MetricSuffix::Percentile(percentile),
                         tags: pending.tags,
-                        value: entry.histogram.value_at_quantile(percentile),
+                        unit: entry.unit,
+                        value: entry.value_at_quantile(percentile),
+                        float_value: None,
                         kind: MetricKind::Gauge,
                     })
                 }
                 index if index == 3 + percentile_count => {
                     pending.step += 1;
-                    if entry.emits(HistogramBaseMetric::Max) {
-                        Some(MetricFrameRef {
-                            prefix: self.prefix,
-                            metric: pending.metric,
-                            suffix: MetricSuffix::Static(".max"),
-                            tags: pending.tags,
-                            value: entry.max,
-                            kind: MetricKind::Gauge,
-                        })
-                    } else {
-                        None
-                    }
+                    entry.base_metric_frame(
+                        HistogramBaseMetric::Max,
+                        &self.prefixes.histogram,
+                        pending.metric,
+                        pending.tags,
+                    )
+                }
+                index if index == 4 + percentile_count => {
+                    pending.step += 1;
+                    entry.base_metric_frame(
+                        HistogramBaseMetric::Sum,
+                        &self.prefixes.histogram,
+                        pending.metric,
+                        pending.tags,
+                    )
+                }
+                index if index == 5 + percentile_count => {
+                    pending.step += 1;
+                    entry.overflow_frame(&self.prefixes.histogram, pending.metric, pending.tags)
                 }
                 _ => {
                     entry.reset();
@@ -717,11 +1609,26 @@ where
                         return Some(frame);
                     }
                 }
+                DrainStage::FloatCount => {
+                    if let Some(frame) = self.emit_float_count_metric() {
+                        return Some(frame);
+                    }
+                }
                 DrainStage::Gauge => {
                     if let Some(frame) = self.emit_gauge_metric() {
                         return Some(frame);
                     }
                 }
+                DrainStage::LastGauge => {
+                    if let Some(frame) = self.emit_last_gauge_metric() {
+                        return Some(frame);
+                    }
+                }
+                DrainStage::Rate => {
+                    if let Some(frame) = self.emit_rate_metric() {
+                        return Some(frame);
+                    }
+                }
                 DrainStage::Histogram => {
                     if self.pending_histogram.is_none() && !self.load_next_histogram() {
                         self.histogram_iter = None;
@@ -729,6 +1636,7 @@ where
                             remove_from_map(self.histogram, key, |v: HistogramWrapper| {
                                 let index = v.pool_id;
                                 debug_assert!(index < self.pool_histograms.len());
+                                // dropped instead once the pool is full
                                 unsafe { self.pool_histograms.get_unchecked(index) }.push(v);
                             });
                         }
@@ -750,23 +1658,36 @@ where
 #[cold]
 pub fn drain_aggregator_frames<'a, S>(
     aggregator: &'a Aggregator<S>,
-    prefix: &'a str,
+    prefixes: &'a TypePrefixes,
+    carry_over: Option<&'a ArcSwap<Aggregator<S>>>,
+    idle_eviction: Option<IdleEviction>,
 ) -> Frames<'a, S>
 where
     S: BuildHasher + Clone,
 {
     Frames {
-        prefix,
+        prefixes,
         stage: DrainStage::Count,
         count_iter: Some(aggregator.count.iter()),
+        float_count_iter: Some(aggregator.float_counts.iter()),
         gauge_iter: Some(aggregator.gauge.iter()),
+        last_gauge_iter: Some(aggregator.last_gauges.iter()),
+        rate_iter: Some(aggregator.rates.iter()),
         histogram_iter: Some(aggregator.histograms.iter_mut()),
         count: &aggregator.count,
+        float_counts: &aggregator.float_counts,
         gauge: &aggregator.gauge,
+        last_gauges: &aggregator.last_gauges,
+        rates: &aggregator.rates,
+        rate_window_secs: aggregator.window().as_secs_f64(),
         histogram: &aggregator.histograms,
         pool_histograms: &aggregator.pool_histograms,
         keys_to_remove: Vec::new(),
         pending_histogram: None,
+        carry_over,
+        idle_eviction,
+        budget: DrainBudget::default(),
+        resumed: false,
     }
 }
 
@@ -777,10 +1698,16 @@ where
     type Hasher = S;
 
     #[inline]
-    fn histogram<'m, 't, TT>(&self, metric: RylvStr<'m>, value: u64, mut tags: TT)
+    fn histogram<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, mut tags: TT)
     where
         TT: AsMut<[RylvStr<'t>]>,
     {
+        let value = round_value(value, self.value_rounding);
+        let metric = metric.into();
+        if self.metric_filter.rejects(metric.as_ref()) {
+            return;
+        }
+        let metric = normalize_metric_name(metric, self.normalize_names);
         let mut_tags = tags.as_mut();
         let aggregator = self.current_aggregator.load();
         record_histogram_in_aggregator(
@@ -790,11 +1717,12 @@ where
             metric,
             value,
             mut_tags,
+            !self.skip_tag_sort,
         );
     }
 
     #[inline]
-    fn count<'m, 't, TT>(&self, metric: RylvStr<'m>, tags: TT)
+    fn count<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, tags: TT)
     where
         TT: AsMut<[RylvStr<'t>]>,
     {
@@ -802,27 +1730,137 @@ where
     }
 
     #[inline]
-    fn count_add<'m, 't, TT>(&self, metric: RylvStr<'m>, value: u64, mut tags: TT)
+    fn count_add<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, mut tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        let metric = metric.into();
+        if self.metric_filter.rejects(metric.as_ref()) {
+            return;
+        }
+        let metric = normalize_metric_name(metric, self.normalize_names);
+        let mut_tags = tags.as_mut();
+        let aggregator = self.current_aggregator.load();
+        record_count_add_in_aggregator(&aggregator, metric, value, mut_tags, !self.skip_tag_sort);
+    }
+
+    #[inline]
+    fn count_add_f64<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: f64, mut tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        let metric = metric.into();
+        if self.metric_filter.rejects(metric.as_ref()) {
+            return;
+        }
+        let metric = normalize_metric_name(metric, self.normalize_names);
+        let mut_tags = tags.as_mut();
+        let aggregator = self.current_aggregator.load();
+        record_count_add_f64_in_aggregator(
+            &aggregator,
+            metric,
+            value,
+            mut_tags,
+            !self.skip_tag_sort,
+        );
+    }
+
+    #[inline]
+    fn timed_count<'m, 't, TT>(
+        &self,
+        metric_base: impl Into<RylvStr<'m>>,
+        latency: u64,
+        mut tags: TT,
+    ) where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        let latency = round_value(latency, self.value_rounding);
+        let metric_base = metric_base.into();
+        with_prefixed_name(
+            metric_base.as_ref(),
+            TIMED_LATENCY_SUFFIX,
+            |latency_metric| {
+                with_prefixed_name(metric_base.as_ref(), TIMED_COUNT_SUFFIX, |count_metric| {
+                    self.record_timed_count(
+                        self.accepted_name(latency_metric),
+                        self.accepted_name(count_metric),
+                        latency,
+                        tags.as_mut(),
+                    );
+                });
+            },
+        );
+    }
+
+    #[inline]
+    fn gauge<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, mut tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        let value = round_value(value, self.value_rounding);
+        let metric = metric.into();
+        if self.metric_filter.rejects(metric.as_ref()) {
+            return;
+        }
+        let metric = normalize_metric_name(metric, self.normalize_names);
+        let mut_tags = tags.as_mut();
+        let throttle = self.gauge_throttle(metric.as_ref());
+        let aggregator = self.current_aggregator.load();
+        record_gauge_in_aggregator(
+            &aggregator,
+            metric,
+            value,
+            mut_tags,
+            !self.skip_tag_sort,
+            self.gauge_mode,
+            throttle,
+        );
+    }
+
+    #[inline]
+    fn gauge_last<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, mut tags: TT)
     where
         TT: AsMut<[RylvStr<'t>]>,
     {
+        let value = round_value(value, self.value_rounding);
+        let metric = metric.into();
+        if self.metric_filter.rejects(metric.as_ref()) {
+            return;
+        }
+        let metric = normalize_metric_name(metric, self.normalize_names);
         let mut_tags = tags.as_mut();
         let aggregator = self.current_aggregator.load();
-        record_count_add_in_aggregator(&aggregator, metric, value, mut_tags);
+        record_gauge_last_in_aggregator(&aggregator, metric, value, mut_tags, !self.skip_tag_sort);
     }
 
     #[inline]
-    fn gauge<'m, 't, TT>(&self, metric: RylvStr<'m>, value: u64, mut tags: TT)
+    fn rate<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, delta: u64, mut tags: TT)
     where
         TT: AsMut<[RylvStr<'t>]>,
     {
+        let metric = metric.into();
+        if self.metric_filter.rejects(metric.as_ref()) {
+            return;
+        }
+        let metric = normalize_metric_name(metric, self.normalize_names);
         let mut_tags = tags.as_mut();
         let aggregator = self.current_aggregator.load();
-        record_gauge_in_aggregator(&aggregator, metric, value, mut_tags);
+        record_rate_in_aggregator(&aggregator, metric, delta, mut_tags, !self.skip_tag_sort);
     }
 
     #[inline]
-    fn histogram_sorted(&self, metric: RylvStr<'_>, value: u64, tags: &SortedTags<S>) {
+    fn histogram_sorted<'m>(
+        &self,
+        metric: impl Into<RylvStr<'m>>,
+        value: u64,
+        tags: &SortedTags<S>,
+    ) {
+        let value = round_value(value, self.value_rounding);
+        let metric = metric.into();
+        if self.metric_filter.rejects(metric.as_ref()) {
+            return;
+        }
+        let metric = normalize_metric_name(metric, self.normalize_names);
         let aggregator = self.current_aggregator.load();
         record_histogram_in_aggregator_sorted(
             &aggregator,
@@ -831,27 +1869,60 @@ where
             metric,
             value,
             tags,
+            None,
         );
     }
 
     #[inline]
-    fn count_add_sorted(&self, metric: RylvStr<'_>, value: u64, tags: &SortedTags<S>) {
+    fn count_add_sorted<'m>(
+        &self,
+        metric: impl Into<RylvStr<'m>>,
+        value: u64,
+        tags: &SortedTags<S>,
+    ) {
+        let metric = metric.into();
+        if self.metric_filter.rejects(metric.as_ref()) {
+            return;
+        }
+        let metric = normalize_metric_name(metric, self.normalize_names);
         let aggregator = self.current_aggregator.load();
-        record_count_add_in_aggregator_sorted(&aggregator, metric, value, tags);
+        record_count_add_in_aggregator_sorted(&aggregator, metric, value, tags, None);
     }
 
     #[inline]
-    fn gauge_sorted(&self, metric: RylvStr<'_>, value: u64, tags: &SortedTags<S>) {
+    fn gauge_sorted<'m>(&self, metric: impl Into<RylvStr<'m>>, value: u64, tags: &SortedTags<S>) {
+        let value = round_value(value, self.value_rounding);
+        let metric = metric.into();
+        if self.metric_filter.rejects(metric.as_ref()) {
+            return;
+        }
+        let metric = normalize_metric_name(metric, self.normalize_names);
+        let throttle = self.gauge_throttle(metric.as_ref());
         let aggregator = self.current_aggregator.load();
-        record_gauge_in_aggregator_sorted(&aggregator, metric, value, tags);
+        record_gauge_in_aggregator_sorted(
+            &aggregator,
+            metric,
+            value,
+            tags,
+            None,
+            self.gauge_mode,
+            throttle,
+        );
     }
 
     #[cold]
-    fn prepare_metric(&self, metric: RylvStr<'_>, tags: SortedTags<S>) -> PreparedMetric<S> {
+    fn prepare_metric<'m>(
+        &self,
+        metric: impl Into<RylvStr<'m>>,
+        tags: SortedTags<S>,
+    ) -> PreparedMetric<S> {
+        let metric = metric.into();
+        let filtered = !self.metric_filter.allows(metric.as_ref());
+        let metric = normalize_metric_name(metric, self.normalize_names);
         let metric = crate::dogstats::sorted_tags::to_static_metric(metric);
         let hash =
             combine_metric_tags_hash(&self.hasher_builder, metric.as_ref(), tags.tags_hash());
-        PreparedMetric::new(metric, tags, hash)
+        PreparedMetric::new(metric, tags, hash).with_filtered(filtered)
     }
 
     #[cold]
@@ -864,6 +1935,10 @@ where
 
     #[inline]
     fn histogram_prepared(&self, prepared: &PreparedMetric<S>, value: u64) {
+        let value = round_value(value, self.value_rounding);
+        if self.metric_filter.count_rejected(prepared.is_filtered()) {
+            return;
+        }
         let aggregator = self.current_aggregator.load();
         record_histogram_in_aggregator_prepared(
             &aggregator,
@@ -876,14 +1951,27 @@ where
 
     #[inline]
     fn count_add_prepared(&self, prepared: &PreparedMetric<S>, value: u64) {
+        if self.metric_filter.count_rejected(prepared.is_filtered()) {
+            return;
+        }
         let aggregator = self.current_aggregator.load();
         record_count_add_in_aggregator_prepared(&aggregator, prepared, value);
     }
 
     #[inline]
     fn gauge_prepared(&self, prepared: &PreparedMetric<S>, value: u64) {
+        let value = round_value(value, self.value_rounding);
+        if self.metric_filter.count_rejected(prepared.is_filtered()) {
+            return;
+        }
         let aggregator = self.current_aggregator.load();
-        record_gauge_in_aggregator_prepared(&aggregator, prepared, value);
+        record_gauge_in_aggregator_prepared(
+            &aggregator,
+            prepared,
+            value,
+            self.gauge_mode,
+            self.gauge_throttle(prepared.metric().as_ref()),
+        );
     }
 }
 
@@ -894,7 +1982,7 @@ where
     type Hasher = S;
 
     #[inline]
-    fn histogram<'m, 't, TT>(&self, metric: RylvStr<'m>, value: u64, tags: TT)
+    fn histogram<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, tags: TT)
     where
         TT: AsMut<[RylvStr<'t>]>,
     {
@@ -902,7 +1990,7 @@ where
     }
 
     #[inline]
-    fn count<'m, 't, TT>(&self, metric: RylvStr<'m>, tags: TT)
+    fn count<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, tags: TT)
     where
         TT: AsMut<[RylvStr<'t>]>,
     {
@@ -910,7 +1998,7 @@ where
     }
 
     #[inline]
-    fn count_add<'m, 't, TT>(&self, metric: RylvStr<'m>, value: u64, tags: TT)
+    fn count_add<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, tags: TT)
     where
         TT: AsMut<[RylvStr<'t>]>,
     {
@@ -918,32 +2006,74 @@ where
     }
 
     #[inline]
-    fn gauge<'m, 't, TT>(&self, metric: RylvStr<'m>, value: u64, tags: TT)
+    fn count_add_f64<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: f64, tags: TT)
     where
         TT: AsMut<[RylvStr<'t>]>,
     {
-        (*self).gauge(metric, value, tags);
+        (*self).count_add_f64(metric, value, tags);
     }
 
     #[inline]
-    fn histogram_sorted(&self, metric: RylvStr<'_>, value: u64, tags: &SortedTags<S>) {
-        (*self).histogram_sorted(metric, value, tags);
+    fn timed_count<'m, 't, TT>(&self, metric_base: impl Into<RylvStr<'m>>, latency: u64, tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        (*self).timed_count(metric_base, latency, tags);
     }
 
     #[inline]
-    fn count_add_sorted(&self, metric: RylvStr<'_>, value: u64, tags: &SortedTags<S>) {
-        (*self).count_add_sorted(metric, value, tags);
+    fn gauge<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        (*self).gauge(metric, value, tags);
     }
 
     #[inline]
-    fn gauge_sorted(&self, metric: RylvStr<'_>, value: u64, tags: &SortedTags<S>) {
+    fn gauge_last<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        (*self).gauge_last(metric, value, tags);
+    }
+
+    #[inline]
+    fn rate<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, delta: u64, tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        (*self).rate(metric, delta, tags);
+    }
+
+    #[inline]
+    fn histogram_sorted<'m>(
+        &self,
+        metric: impl Into<RylvStr<'m>>,
+        value: u64,
+        tags: &SortedTags<S>,
+    ) {
+        (*self).histogram_sorted(metric, value, tags);
+    }
+
+    #[inline]
+    fn count_add_sorted<'m>(
+        &self,
+        metric: impl Into<RylvStr<'m>>,
+        value: u64,
+        tags: &SortedTags<S>,
+    ) {
+        (*self).count_add_sorted(metric, value, tags);
+    }
+
+    #[inline]
+    fn gauge_sorted<'m>(&self, metric: impl Into<RylvStr<'m>>, value: u64, tags: &SortedTags<S>) {
         (*self).gauge_sorted(metric, value, tags);
     }
 
     #[cold]
-    fn prepare_metric(
+    fn prepare_metric<'m>(
         &self,
-        metric: RylvStr<'_>,
+        metric: impl Into<RylvStr<'m>>,
         tags: SortedTags<Self::Hasher>,
     ) -> PreparedMetric<Self::Hasher> {
         (*self).prepare_metric(metric, tags)
@@ -984,7 +2114,38 @@ where
 
     #[cold]
     fn try_begin_drain(&self) -> Option<Self::Drain<'_>> {
-        (*self).begin_drain()
+        (*self).begin_drain(DrainBudget::default())
+    }
+
+    #[cold]
+    fn try_begin_drain_until(&self, deadline: Instant) -> Option<Self::Drain<'_>> {
+        (*self).begin_drain(DrainBudget::until(deadline))
+    }
+
+    fn stats_prefix(&self) -> &str {
+        self.stats_prefix.as_str()
+    }
+
+    fn has_pending_drain(&self) -> bool {
+        self.has_undrained_aggregator()
+    }
+
+    fn peek_percentile<'t, TT>(
+        &self,
+        metric: RylvStr<'_>,
+        mut tags: TT,
+        quantile: f64,
+    ) -> Option<u64>
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        let metric = normalize_metric_name(metric, self.normalize_names);
+        let aggregator = self.current_aggregator.load();
+        peek_histogram_percentile_in_aggregator(&aggregator, metric, tags.as_mut(), quantile)
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        snapshot_aggregator(&self.current_aggregator.load())
     }
 }
 
@@ -999,7 +2160,131 @@ where
 
     #[cold]
     fn try_begin_drain(&self) -> Option<Self::Drain<'_>> {
-        self.begin_drain()
+        self.begin_drain(DrainBudget::default())
+    }
+
+    #[cold]
+    fn try_begin_drain_until(&self, deadline: Instant) -> Option<Self::Drain<'_>> {
+        self.begin_drain(DrainBudget::until(deadline))
+    }
+
+    fn stats_prefix(&self) -> &str {
+        self.stats_prefix.as_str()
+    }
+
+    fn has_pending_drain(&self) -> bool {
+        self.has_undrained_aggregator()
+    }
+
+    fn peek_percentile<'t, TT>(
+        &self,
+        metric: RylvStr<'_>,
+        mut tags: TT,
+        quantile: f64,
+    ) -> Option<u64>
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        let metric = normalize_metric_name(metric, self.normalize_names);
+        let aggregator = self.current_aggregator.load();
+        peek_histogram_percentile_in_aggregator(&aggregator, metric, tags.as_mut(), quantile)
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        snapshot_aggregator(&self.current_aggregator.load())
+    }
+}
+
+/// Reads `quantile` from the live histogram for `metric`/`tags` under the shard read lock.
+pub fn peek_histogram_percentile_in_aggregator<S>(
+    aggregator: &Aggregator<S>,
+    metric: RylvStr<'_>,
+    mut_tags: &mut [RylvStr<'_>],
+    quantile: f64,
+) -> Option<u64>
+where
+    S: BuildHasher + Clone,
+{
+    mut_tags.sort_unstable();
+
+    let hashmap = &aggregator.histograms;
+    let lookup_key = build_lookup_key(metric, mut_tags, true, hashmap);
+
+    #[allow(clippy::cast_possible_truncation)]
+    let shard = hashmap.determine_shard(lookup_key.hash as usize);
+    let shard_lock = unsafe { hashmap.shards().get_unchecked(shard) };
+    let guard = shard_lock.read();
+    let bucket = guard.find(lookup_key.hash, |(k, _)| lookup_key.compare(k))?;
+    // SAFETY: the shard read lock is held while the histogram is read
+    let wrapper = unsafe { bucket.as_ref() }.1.get();
+    let value = (!wrapper.histogram.is_empty()).then(|| wrapper.value_at_quantile(quantile));
+    drop(guard);
+    value
+}
+
+/// Copies the non-empty series of `aggregator`, locking one shard at a time.
+pub fn snapshot_aggregator<S>(aggregator: &Aggregator<S>) -> Snapshot
+where
+    S: BuildHasher + Clone,
+{
+    let counts = aggregator
+        .count
+        .iter()
+        .filter_map(|entry| {
+            let value = entry.value().load(Ordering::Relaxed);
+            (value != 0).then(|| snapshot_value(entry.key(), value))
+        })
+        .collect();
+    let gauges = aggregator
+        .gauge
+        .iter()
+        .filter_map(|entry| {
+            let count = entry.value().count.load(Ordering::Relaxed);
+            let sum = entry.value().sum.load(Ordering::Relaxed);
+            let value = sum.checked_div(count)?;
+            Some(snapshot_value(entry.key(), value))
+        })
+        .chain(aggregator.last_gauges.iter().filter_map(|entry| {
+            let value = entry.value().load(Ordering::Relaxed);
+            (value != UNSET_LAST_GAUGE).then(|| snapshot_value(entry.key(), value))
+        }))
+        .collect();
+    let histograms = aggregator
+        .histograms
+        .iter()
+        .filter(|entry| !entry.value().histogram.is_empty())
+        .map(|entry| {
+            let (key, wrapper) = (entry.key(), entry.value());
+            HistogramSnapshot {
+                metric: key.metric.as_ref().to_owned(),
+                tags: key.tags.joined_tags().to_owned(),
+                count: wrapper.histogram.len(),
+                min: wrapper.min,
+                max: wrapper.max,
+                sum: wrapper.sum,
+                percentiles: wrapper
+                    .percentiles
+                    .iter()
+                    .map(|&quantile| (quantile, wrapper.value_at_quantile(quantile)))
+                    .collect(),
+            }
+        })
+        .collect();
+    Snapshot {
+        counts,
+        gauges,
+        histograms,
+    }
+}
+
+fn snapshot_value<S>(key: &AggregatorEntryKey<S>, value: u64) -> SnapshotValue
+where
+    S: BuildHasher + Clone,
+{
+    SnapshotValue {
+        metric: key.metric.as_ref().to_owned(),
+        tags: key.tags.joined_tags().to_owned(),
+        value,
     }
 }
 
@@ -1010,20 +2295,40 @@ pub fn record_histogram_in_aggregator<S>(
     metric: RylvStr<'_>,
     value: u64,
     mut_tags: &mut [RylvStr<'_>],
+    sort_tags: bool,
 ) where
     S: BuildHasher + Clone,
 {
-    mut_tags.sort_unstable_by(|a, b| {
-        if a == b {
-            CmpOrdering::Equal
-        } else {
-            a.as_ref().cmp(b.as_ref())
-        }
-    });
+    if sort_tags {
+        mut_tags.sort_unstable_by(|a, b| {
+            if a == b {
+                CmpOrdering::Equal
+            } else {
+                a.as_ref().cmp(b.as_ref())
+            }
+        });
+    }
 
-    let hashmap = &aggregator.histograms;
-    let lookup_key = build_lookup_key(metric, mut_tags, hashmap);
+    let lookup_key = build_lookup_key(metric, mut_tags, sort_tags, &aggregator.histograms);
+    record_histogram_with_key(
+        aggregator,
+        histogram_configs,
+        default_histogram_config,
+        lookup_key,
+        value,
+    );
+}
 
+fn record_histogram_with_key<S>(
+    aggregator: &Aggregator<S>,
+    histogram_configs: &HashMap<String, ResolvedHistogramConfig, S>,
+    default_histogram_config: &ResolvedHistogramConfig,
+    lookup_key: LookupKey<'_>,
+    value: u64,
+) where
+    S: BuildHasher + Clone,
+{
+    let hashmap = &aggregator.histograms;
     #[allow(clippy::cast_possible_truncation)]
     let shard = hashmap.determine_shard(lookup_key.hash as usize);
     let shard_lock = unsafe { hashmap.shards().get_unchecked(shard) };
@@ -1056,7 +2361,7 @@ pub fn record_histogram_in_aggregator<S>(
                     error!("Fail to record: {err}");
                 }
 
-                let agg_key = lookup_key.into_key();
+                let agg_key = lookup_key.into_key_interned(&aggregator.tag_interner);
                 unsafe {
                     guard.insert_in_slot(agg_key.hash, insert_slot, (agg_key, SharedValue::new(v)));
                 }
@@ -1072,11 +2377,12 @@ pub fn record_histogram_in_aggregator_sorted<S>(
     metric: RylvStr<'_>,
     value: u64,
     sorted_tags: &SortedTags<S>,
+    prehash: Option<u64>,
 ) where
     S: BuildHasher + Clone,
 {
     let hashmap = &aggregator.histograms;
-    let hash = combine_metric_tags_hash(hashmap.hasher(), metric.as_ref(), sorted_tags.tags_hash());
+    let hash = sorted_series_hash(hashmap.hasher(), metric.as_ref(), sorted_tags, prehash);
     let lookup_key = LookupKeySorted {
         metric,
         sorted_tags,
@@ -1188,15 +2494,19 @@ pub fn record_count_add_in_aggregator<S>(
     metric: RylvStr<'_>,
     value: u64,
     mut_tags: &mut [RylvStr<'_>],
+    sort_tags: bool,
 ) where
     S: BuildHasher + Clone,
 {
-    mut_tags.sort_unstable();
+    if sort_tags {
+        mut_tags.sort_unstable();
+    }
     add_or_insert_entry_read_first(
         metric,
         mut_tags,
+        sort_tags,
         value,
-        &aggregator.count,
+        (&aggregator.count, &aggregator.tag_interner),
         |v, value| {
             v.fetch_add(value, Ordering::Relaxed);
             Ok(())
@@ -1205,17 +2515,108 @@ pub fn record_count_add_in_aggregator<S>(
     );
 }
 
+pub fn record_count_add_f64_in_aggregator<S>(
+    aggregator: &Aggregator<S>,
+    metric: RylvStr<'_>,
+    value: f64,
+    mut_tags: &mut [RylvStr<'_>],
+    sort_tags: bool,
+) where
+    S: BuildHasher + Clone,
+{
+    // NaN, infinite or negative increments would make the total an invalid counter line
+    if !is_valid_count_f64(value) {
+        return;
+    }
+    if sort_tags {
+        mut_tags.sort_unstable();
+    }
+    add_or_insert_entry_read_first(
+        metric,
+        mut_tags,
+        sort_tags,
+        value.to_bits(),
+        (&aggregator.float_counts, &aggregator.tag_interner),
+        |v, value| {
+            let value = f64::from_bits(value);
+            // There is no atomic float add, so the sum is retried until no other
+            // recorder changed the total in between. A sum overflowing to
+            // infinity keeps the previous total.
+            let _ = v.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |total| {
+                let total = f64::from_bits(total) + value;
+                total.is_finite().then(|| total.to_bits())
+            });
+            Ok(())
+        },
+        || Some(AtomicU64::new(0)),
+    );
+}
+
+pub fn record_gauge_last_in_aggregator<S>(
+    aggregator: &Aggregator<S>,
+    metric: RylvStr<'_>,
+    value: u64,
+    mut_tags: &mut [RylvStr<'_>],
+    sort_tags: bool,
+) where
+    S: BuildHasher + Clone,
+{
+    if sort_tags {
+        mut_tags.sort_unstable();
+    }
+    add_or_insert_entry_read_first(
+        metric,
+        mut_tags,
+        sort_tags,
+        value.min(UNSET_LAST_GAUGE - 1),
+        (&aggregator.last_gauges, &aggregator.tag_interner),
+        |v, value| {
+            v.store(value, Ordering::Relaxed);
+            Ok(())
+        },
+        || Some(AtomicU64::new(UNSET_LAST_GAUGE)),
+    );
+}
+
+pub fn record_rate_in_aggregator<S>(
+    aggregator: &Aggregator<S>,
+    metric: RylvStr<'_>,
+    delta: u64,
+    mut_tags: &mut [RylvStr<'_>],
+    sort_tags: bool,
+) where
+    S: BuildHasher + Clone,
+{
+    if sort_tags {
+        mut_tags.sort_unstable();
+    }
+    add_or_insert_entry_read_first(
+        metric,
+        mut_tags,
+        sort_tags,
+        delta,
+        (&aggregator.rates, &aggregator.tag_interner),
+        |v, delta| {
+            v.fetch_add(delta, Ordering::Relaxed);
+            Ok(())
+        },
+        || Some(AtomicU64::new(0)),
+    );
+}
+
 pub fn record_count_add_in_aggregator_sorted<S>(
     aggregator: &Aggregator<S>,
     metric: RylvStr<'_>,
     value: u64,
     sorted_tags: &SortedTags<S>,
+    prehash: Option<u64>,
 ) where
     S: BuildHasher + Clone,
 {
     add_or_insert_entry_read_first_sorted(
         metric,
         sorted_tags,
+        prehash,
         value,
         &aggregator.count,
         |v, value| {
@@ -1250,24 +2651,32 @@ pub fn record_gauge_in_aggregator<S>(
     metric: RylvStr<'_>,
     value: u64,
     mut_tags: &mut [RylvStr<'_>],
+    sort_tags: bool,
+    mode: GaugeMode,
+    throttle: Option<GaugeThrottle>,
 ) where
     S: BuildHasher + Clone,
 {
-    mut_tags.sort_unstable();
+    if sort_tags {
+        mut_tags.sort_unstable();
+    }
     add_or_insert_entry_read_first(
         metric,
         mut_tags,
+        sort_tags,
         value,
-        &aggregator.gauge,
+        (&aggregator.gauge, &aggregator.tag_interner),
         |v, value| {
-            v.count.fetch_add(1, Ordering::Relaxed);
-            v.sum.fetch_add(value, Ordering::Relaxed);
+            if !v.throttled(throttle) {
+                v.record(value, mode);
+            }
             Ok(())
         },
         || {
             Some(GaugeState {
                 count: AtomicU64::new(0),
                 sum: AtomicU64::new(0),
+                updated_at: AtomicU64::new(0),
             })
         },
     );
@@ -1278,23 +2687,29 @@ pub fn record_gauge_in_aggregator_sorted<S>(
     metric: RylvStr<'_>,
     value: u64,
     sorted_tags: &SortedTags<S>,
+    prehash: Option<u64>,
+    mode: GaugeMode,
+    throttle: Option<GaugeThrottle>,
 ) where
     S: BuildHasher + Clone,
 {
     add_or_insert_entry_read_first_sorted(
         metric,
         sorted_tags,
+        prehash,
         value,
         &aggregator.gauge,
         |v, value| {
-            v.count.fetch_add(1, Ordering::Relaxed);
-            v.sum.fetch_add(value, Ordering::Relaxed);
+            if !v.throttled(throttle) {
+                v.record(value, mode);
+            }
             Ok(())
         },
         || {
             Some(GaugeState {
                 count: AtomicU64::new(0),
                 sum: AtomicU64::new(0),
+                updated_at: AtomicU64::new(0),
             })
         },
     );
@@ -1304,6 +2719,8 @@ pub fn record_gauge_in_aggregator_prepared<S>(
     aggregator: &Aggregator<S>,
     prepared: &PreparedMetric<S>,
     value: u64,
+    mode: GaugeMode,
+    throttle: Option<GaugeThrottle>,
 ) where
     S: BuildHasher + Clone,
 {
@@ -1312,14 +2729,16 @@ pub fn record_gauge_in_aggregator_prepared<S>(
         value,
         &aggregator.gauge,
         |v, value| {
-            v.count.fetch_add(1, Ordering::Relaxed);
-            v.sum.fetch_add(value, Ordering::Relaxed);
+            if !v.throttled(throttle) {
+                v.record(value, mode);
+            }
             Ok(())
         },
         || {
             Some(GaugeState {
                 count: AtomicU64::new(0),
                 sum: AtomicU64::new(0),
+                updated_at: AtomicU64::new(0),
             })
         },
     );
@@ -1333,14 +2752,22 @@ mod tests {
         record_gauge_in_aggregator, record_gauge_in_aggregator_prepared,
         record_gauge_in_aggregator_sorted, record_histogram_in_aggregator,
         record_histogram_in_aggregator_prepared, record_histogram_in_aggregator_sorted,
-        remove_from_map, SharedCollector, SharedCollectorOptions,
+        record_rate_in_aggregator, remove_from_map, GaugeState, SharedCollector,
+        SharedCollectorOptions,
+    };
+    use crate::dogstats::aggregator::{histogram_pools, Aggregator};
+    use crate::dogstats::collector::{
+        DrainMetricCollectorTrait, GaugeMode, MetricKind, MetricSuffix, TypePrefixes,
     };
-    use crate::dogstats::aggregator::Aggregator;
-    use crate::dogstats::collector::{DrainMetricCollectorTrait, MetricKind, MetricSuffix};
-    use crate::dogstats::histogram_config::{resolve_histogram_configs, HistogramConfig};
-    use crate::{MetricCollectorTrait, RylvStr};
+    use crate::dogstats::histogram_config::{
+        resolve_histogram_configs, HistogramBaseMetric, HistogramConfig, OutOfBoundsPolicy,
+        PercentileMethod,
+    };
+    use crate::{MetricCollectorTrait, RylvStr, SigFig};
     use std::collections::HashMap;
-    use std::sync::atomic::Ordering;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
 
     fn percentile_suffix(percentile: f64) -> String {
         let mut percentile_number = (percentile * 100.0).to_string();
@@ -1373,14 +2800,16 @@ mod tests {
             let metric_type = match frame.kind {
                 MetricKind::Count => "c",
                 MetricKind::Gauge => "g",
+                MetricKind::Timing => "ms",
+                MetricKind::Distribution => "d",
             };
+            let value = frame
+                .float_value
+                .map_or_else(|| frame.value.to_string(), |value| value.to_string());
             if frame.tags.is_empty() {
-                lines.push(format!("{metric}:{}|{metric_type}\n", frame.value));
+                lines.push(format!("{metric}:{value}|{metric_type}\n"));
             } else {
-                lines.push(format!(
-                    "{metric}:{}|{metric_type}|#{}\n",
-                    frame.value, frame.tags
-                ));
+                lines.push(format!("{metric}:{value}|{metric_type}|#{}\n", frame.tags));
             }
         }
         lines.sort_unstable();
@@ -1406,14 +2835,16 @@ mod tests {
             let metric_type = match frame.kind {
                 MetricKind::Count => "c",
                 MetricKind::Gauge => "g",
+                MetricKind::Timing => "ms",
+                MetricKind::Distribution => "d",
             };
+            let value = frame
+                .float_value
+                .map_or_else(|| frame.value.to_string(), |value| value.to_string());
             if frame.tags.is_empty() {
-                lines.push(format!("{metric}:{}|{metric_type}\n", frame.value));
+                lines.push(format!("{metric}:{value}|{metric_type}\n"));
             } else {
-                lines.push(format!(
-                    "{metric}:{}|{metric_type}|#{}\n",
-                    frame.value, frame.tags
-                ));
+                lines.push(format!("{metric}:{value}|{metric_type}|#{}\n", frame.tags));
             }
         }
         lines.sort_unstable();
@@ -1469,12 +2900,14 @@ mod tests {
             RylvStr::from_static("requests"),
             2,
             &mut [RylvStr::from_static("b:2"), RylvStr::from_static("a:1")],
+            true,
         );
         record_count_add_in_aggregator_sorted(
             aggregator,
             RylvStr::from_static("requests_sorted"),
             3,
             prepared_count_a.tags(),
+            None,
         );
         record_count_add_in_aggregator_prepared(aggregator, &prepared_count_a, 4);
         record_count_add_in_aggregator_prepared(aggregator, &prepared_count_b, 5);
@@ -1484,15 +2917,33 @@ mod tests {
             RylvStr::from_static("load"),
             10,
             &mut [RylvStr::from_static("b:2"), RylvStr::from_static("a:1")],
+            true,
+            GaugeMode::Average,
+            None,
         );
         record_gauge_in_aggregator_sorted(
             aggregator,
             RylvStr::from_static("load_sorted"),
             20,
             prepared_gauge_a.tags(),
+            None,
+            GaugeMode::Average,
+            None,
+        );
+        record_gauge_in_aggregator_prepared(
+            aggregator,
+            &prepared_gauge_a,
+            30,
+            GaugeMode::Average,
+            None,
+        );
+        record_gauge_in_aggregator_prepared(
+            aggregator,
+            &prepared_gauge_b,
+            50,
+            GaugeMode::Average,
+            None,
         );
-        record_gauge_in_aggregator_prepared(aggregator, &prepared_gauge_a, 30);
-        record_gauge_in_aggregator_prepared(aggregator, &prepared_gauge_b, 50);
 
         record_histogram_in_aggregator(
             aggregator,
@@ -1501,6 +2952,7 @@ mod tests {
             RylvStr::from_static("latency"),
             40,
             &mut [RylvStr::from_static("b:2"), RylvStr::from_static("a:1")],
+            true,
         );
         record_histogram_in_aggregator_sorted(
             aggregator,
@@ -1509,6 +2961,7 @@ mod tests {
             RylvStr::from_static("latency_sorted"),
             50,
             prepared_hist_a.tags(),
+            None,
         );
         record_histogram_in_aggregator_prepared(
             aggregator,
@@ -1583,61 +3036,960 @@ mod tests {
     }
 
     #[test]
-    fn shared_reference_trait_impls_cover_regular_paths() {
+    fn shared_drain_until_deadline_yields_and_resumes_remaining_series() {
+        let collector = SharedCollector::new(SharedCollectorOptions::default());
+        let series = 20_000;
+        for id in 0..series {
+            let tag = format!("id:{id}");
+            collector.count(
+                RylvStr::from_static("requests"),
+                &mut [RylvStr::from(tag.as_str())],
+            );
+        }
+        collector.histogram(RylvStr::from_static("latency"), 5, &mut []);
+
+        let expired = Instant::now();
+        let first = drain_to_lines(collector.try_begin_drain_until(expired).unwrap());
+        assert!(!first.is_empty() && first.len() < series);
+        assert!(collector.has_pending_drain());
+
+        // recorded after the yield, so only emitted once the resumed series are done
+        collector.count(RylvStr::from_static("late"), &mut []);
+        let mut lines = first;
+        let mut drains = 1;
+        while collector.has_pending_drain() {
+            lines.extend(drain_to_lines(
+                collector.try_begin_drain_until(Instant::now()).unwrap(),
+            ));
+            drains += 1;
+        }
+        assert!(drains > 2);
+        assert!(!lines.iter().any(|line| line.starts_with("late:")));
+
+        let count_lines = lines
+            .iter()
+            .filter(|line| line.starts_with("requests:1|c|#id:"));
+        assert_eq!(count_lines.count(), series);
+        assert!(lines.contains(&"latency.count:1|c\n".to_string()));
+        let total = lines.len();
+        lines.sort_unstable();
+        lines.dedup();
+        assert_eq!(lines.len(), total, "no series is emitted twice");
+
+        assert_eq!(
+            drain_metrics_now(&collector),
+            vec!["late:1|c\n".to_string()]
+        );
+    }
+
+    #[test]
+    fn shared_max_pending_aggregators_drains_released_generation_first() {
         let collector = SharedCollector::new(SharedCollectorOptions {
-            stats_prefix: "ref.".to_string(),
+            max_pending_aggregators: 2,
             ..Default::default()
         });
-        let collector_ref = &collector;
+        collector.count(
+            RylvStr::from_static("requests"),
+            &mut [RylvStr::from_static("generation:1")],
+        );
 
-        collector_ref.count(
+        let held = collector.current_aggregator.load_full();
+        assert!(collector.try_begin_drain().is_none());
+
+        collector.count(
             RylvStr::from_static("requests"),
-            &mut [RylvStr::from_static("b:2"), RylvStr::from_static("a:1")],
+            &mut [RylvStr::from_static("generation:2")],
         );
-        collector_ref.count_add(
+        assert_eq!(
+            drain_metrics_now(&collector),
+            vec!["requests:1|c|#generation:2\n".to_string()]
+        );
+
+        // Both pending generations stay referenced, so the ring fills up
+        collector.count(
             RylvStr::from_static("requests"),
-            4,
-            &mut [RylvStr::from_static("a:1"), RylvStr::from_static("b:2")],
+            &mut [RylvStr::from_static("generation:3")],
         );
-        collector_ref.gauge(
-            RylvStr::from_static("load"),
-            10,
-            &mut [RylvStr::from_static("b:2"), RylvStr::from_static("a:1")],
+        let held_third = collector.current_aggregator.load_full();
+        assert!(collector.try_begin_drain().is_none());
+
+        collector.count(
+            RylvStr::from_static("requests"),
+            &mut [RylvStr::from_static("generation:4")],
         );
-        collector_ref.gauge(
-            RylvStr::from_static("load"),
-            20,
-            &mut [RylvStr::from_static("a:1"), RylvStr::from_static("b:2")],
+        assert!(collector.try_begin_drain().is_none());
+
+        drop(held_third);
+        assert_eq!(
+            drain_metrics_now(&collector),
+            vec!["requests:1|c|#generation:3\n".to_string()]
         );
-        collector_ref.histogram(
-            RylvStr::from_static("latency"),
-            40,
-            &mut [RylvStr::from_static("b:2"), RylvStr::from_static("a:1")],
+        drop(held);
+        assert_eq!(
+            drain_metrics_now(&collector),
+            vec!["requests:1|c|#generation:1\n".to_string()]
         );
-        collector_ref.histogram(
-            RylvStr::from_static("latency"),
-            60,
-            &mut [RylvStr::from_static("a:1"), RylvStr::from_static("b:2")],
+        assert_eq!(
+            drain_metrics_now(&collector),
+            vec!["requests:1|c|#generation:4\n".to_string()]
         );
-
-        let drain =
-            <&SharedCollector as DrainMetricCollectorTrait>::try_begin_drain(&collector_ref)
-                .unwrap();
-        assert_reference_lines(&drain_to_lines(drain));
     }
 
     #[test]
-    fn raw_aggregator_record_helpers_cover_regular_sorted_and_prepared_paths() {
-        let collector = SharedCollector::new(SharedCollectorOptions::default());
-        let aggregator =
-            Aggregator::with_hasher_builder(&collector.hasher_builder, collector.pool_count);
+    fn shared_prewarm_histograms_fills_pools_shared_by_every_generation() {
+        let collector = SharedCollector::new(SharedCollectorOptions {
+            prewarm_histograms: 2,
+            ..Default::default()
+        });
+        assert_eq!(
+            collector.current_aggregator.load().pool_histograms[0].len(),
+            2
+        );
 
-        record_all_helper_variants(&collector, &aggregator);
+        collector.histogram(
+            RylvStr::from_static("latency"),
+            10,
+            &mut [RylvStr::from_static("scope:test")],
+        );
+        assert_eq!(
+            collector.current_aggregator.load().pool_histograms[0].len(),
+            1
+        );
 
-        let lines = frames_to_lines(drain_aggregator_frames(&aggregator, "agg."));
-        assert!(lines.contains(&"agg.requests:2|c|#a:1,b:2\n".to_string()));
-        assert!(lines.contains(&"agg.requests_sorted:3|c|#a:1,b:2\n".to_string()));
-        assert!(lines.contains(&"agg.requests_prepared:9|c|#a:1,b:2\n".to_string()));
+        let lines = drain_metrics_now(&collector);
+        assert!(lines.contains(&"latency.count:1|c|#scope:test\n".to_string()));
+        // The swapped-in generation draws from the same pools instead of prewarming again
+        assert_eq!(
+            collector.current_aggregator.load().pool_histograms[0].len(),
+            1
+        );
+        assert!(Arc::ptr_eq(
+            &collector.current_aggregator.load().pool_histograms,
+            &collector.pool_histograms
+        ));
+    }
+
+    #[test]
+    fn shared_histogram_pool_survives_aggregator_swap() {
+        let collector = SharedCollector::default();
+        let mut tags = [RylvStr::from_static("scope:test")];
+
+        collector.histogram(RylvStr::from_static("stale"), 10, &mut tags);
+        let lines = drain_metrics_now(&collector);
+        assert!(lines.contains(&"stale.count:1|c|#scope:test\n".to_string()));
+        // The other generation, then the first one again: the idle histogram is recycled
+        assert!(drain_metrics_now(&collector).is_empty());
+        assert!(drain_metrics_now(&collector).is_empty());
+
+        // The recycled histogram is available to the swapped-in generation
+        assert_eq!(
+            collector.current_aggregator.load().pool_histograms[0].len(),
+            1
+        );
+        collector.histogram(RylvStr::from_static("fresh"), 20, &mut tags);
+        assert_eq!(
+            collector.current_aggregator.load().pool_histograms[0].len(),
+            0
+        );
+    }
+
+    #[test]
+    fn shared_histogram_pool_is_bounded_after_a_cardinality_spike() {
+        let collector = SharedCollector::new(SharedCollectorOptions {
+            prewarm_histograms: 8,
+            max_pooled_histograms_per_sigfig: Some(4),
+            ..Default::default()
+        });
+        let pool_len = || collector.current_aggregator.load().pool_histograms[0].len();
+        assert_eq!(pool_len(), 4, "prewarming stops at the cap");
+
+        for id in 0..100 {
+            let tag = format!("id:{id}");
+            collector.histogram(
+                RylvStr::from_static("burst"),
+                id,
+                &mut [RylvStr::from(tag.as_str())],
+            );
+        }
+        assert_eq!(pool_len(), 0);
+        let lines = drain_metrics_now(&collector);
+        assert!(lines.contains(&"burst.count:1|c|#id:99\n".to_string()));
+        // The other generation, then the first one again: the idle histograms are evicted
+        assert!(drain_metrics_now(&collector).is_empty());
+        assert!(drain_metrics_now(&collector).is_empty());
+
+        assert_eq!(pool_len(), 4, "evicted histograms beyond the cap are freed");
+        collector.histogram(RylvStr::from_static("fresh"), 1, []);
+        assert_eq!(pool_len(), 3);
+    }
+
+    #[test]
+    fn shared_peek_percentile_reads_live_histogram_without_reset() {
+        let collector = SharedCollector::default();
+        for value in 1..=1000 {
+            collector.histogram(
+                RylvStr::from_static("latency"),
+                value,
+                &mut [RylvStr::from_static("b:2"), RylvStr::from_static("a:1")],
+            );
+        }
+
+        let mut tags = [RylvStr::from_static("a:1"), RylvStr::from_static("b:2")];
+        assert_eq!(
+            collector.peek_percentile(RylvStr::from_static("latency"), &mut tags, 0.99),
+            Some(990)
+        );
+        assert_eq!(
+            collector.peek_percentile(RylvStr::from_static("latency"), &mut tags, 0.5),
+            Some(500)
+        );
+        assert_eq!(
+            collector.peek_percentile(RylvStr::from_static("latency"), &mut [], 0.99),
+            None
+        );
+        assert_eq!(
+            collector.peek_percentile(RylvStr::from_static("other"), &mut tags, 0.99),
+            None
+        );
+
+        let mut drain = collector.try_begin_drain().unwrap();
+        let frame_count = drain
+            .by_ref()
+            .find(|frame| frame.suffix == MetricSuffix::Static(".count"))
+            .unwrap();
+        assert_eq!(frame_count.value, 1000);
+        drop(drain);
+
+        assert_eq!(
+            collector.peek_percentile(RylvStr::from_static("latency"), &mut tags, 0.99),
+            None
+        );
+    }
+
+    #[test]
+    fn shared_multi_methods_record_each_tag_set_separately() {
+        let collector = SharedCollector::default();
+        let region = [
+            RylvStr::from_static("region:eu"),
+            RylvStr::from_static("az:1"),
+        ];
+        let az_only = [RylvStr::from_static("az:1")];
+
+        collector.count_multi(
+            RylvStr::from_static("requests"),
+            3,
+            &[&region, &az_only, &[]],
+        );
+        collector.gauge_multi(RylvStr::from_static("load"), 7, &[&region, &[]]);
+
+        // the caller's tag order is left untouched
+        assert_eq!(region[0].as_ref(), "region:eu");
+
+        let mut lines: Vec<String> = collector
+            .try_begin_drain()
+            .unwrap()
+            .map(|frame| format!("{}:{}|#{}", frame.metric, frame.value, frame.tags))
+            .collect();
+        lines.sort();
+        assert_eq!(
+            lines,
+            vec![
+                "load:7|#".to_string(),
+                "load:7|#az:1,region:eu".to_string(),
+                "requests:3|#".to_string(),
+                "requests:3|#az:1".to_string(),
+                "requests:3|#az:1,region:eu".to_string(),
+            ]
+        );
+    }
+
+    fn scope_timer_counts(collector: &SharedCollector) -> Vec<String> {
+        drain_metrics_now(collector)
+            .into_iter()
+            .filter(|line| line.contains(".count:"))
+            .collect()
+    }
+
+    #[test]
+    fn shared_scope_timer_records_on_drop_with_owned_tags() {
+        let collector = SharedCollector::default();
+        {
+            let table = String::from("table:users");
+            let _timer = collector.scope_timer("db.query", &[RylvStr::from(table.as_str())]);
+            drop(table);
+        }
+
+        assert_eq!(
+            scope_timer_counts(&collector),
+            ["db.query.count:1|c|#table:users\n"]
+        );
+    }
+
+    #[test]
+    fn shared_scope_timer_cancel_skips_recording() {
+        let collector = SharedCollector::default();
+        let timer = collector.scope_timer("db.query", &[]);
+        timer.cancel();
+        assert!(drain_metrics_now(&collector).is_empty());
+    }
+
+    #[test]
+    fn shared_scope_timer_records_when_dropped_during_unwind() {
+        let collector = SharedCollector::default();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _timer = collector.scope_timer("db.query", &[RylvStr::from_static("op:read")]);
+            panic!("query failed");
+        }));
+        assert!(result.is_err());
+        assert_eq!(
+            scope_timer_counts(&collector),
+            ["db.query.count:1|c|#op:read\n"]
+        );
+    }
+
+    #[test]
+    fn shared_timing_micros_records_into_latency_micros_preset() {
+        let mut histogram_configs = HashMap::with_hasher(crate::DefaultMetricHasher::new());
+        for metric in ["latency.fast", "latency.slow"] {
+            histogram_configs.insert(metric.to_string(), HistogramConfig::latency_micros());
+        }
+        let collector = SharedCollector::new(SharedCollectorOptions {
+            histogram_configs,
+            ..Default::default()
+        });
+
+        collector.timing_micros(
+            RylvStr::from_static("latency.fast"),
+            Duration::from_micros(500),
+            &mut [],
+        );
+        collector.timing_micros(
+            RylvStr::from_static("latency.slow"),
+            Duration::from_micros(12_345_678),
+            &mut [],
+        );
+
+        let percentiles: Vec<(String, u64)> = collector
+            .try_begin_drain()
+            .unwrap()
+            .filter(|frame| matches!(frame.suffix, MetricSuffix::Percentile(_)))
+            .map(|frame| (frame.metric.to_string(), frame.value))
+            .collect();
+        assert_eq!(percentiles.len(), 4);
+        for (metric, value) in percentiles {
+            if metric == "latency.fast" {
+                // 3 significant figures keep sub-millisecond values exact
+                assert_eq!(value, 500);
+            } else {
+                assert!(value.abs_diff(12_345_678) <= 12_345_678 / 1000, "{value}");
+            }
+        }
+    }
+
+    #[test]
+    fn shared_ref_methods_leave_const_tags_untouched() {
+        const TAGS: [RylvStr<'static>; 2] = [
+            RylvStr::from_static("region:eu"),
+            RylvStr::from_static("az:1"),
+        ];
+        // longer than the stack buffer, so it takes the heap fallback
+        let long_tags: Vec<RylvStr<'_>> = (0..18)
+            .map(|i| RylvStr::from(format!("{}:{i}", if i == 17 { "a" } else { "z" })))
+            .collect();
+        let collector = SharedCollector::default();
+
+        collector.count_ref(RylvStr::from_static("requests"), &TAGS);
+        collector.count_add_ref(RylvStr::from_static("requests"), 2, &TAGS);
+        collector.gauge_ref(RylvStr::from_static("load"), 7, &TAGS);
+        collector.histogram_ref(RylvStr::from_static("latency"), 5, &TAGS);
+        collector.count_ref(RylvStr::from_static("wide"), &long_tags);
+
+        assert_eq!(TAGS[0].as_ref(), "region:eu");
+        assert_eq!(long_tags[0].as_ref(), "z:0");
+        assert_eq!(long_tags[17].as_ref(), "a:17");
+
+        let lines: Vec<String> = collector
+            .try_begin_drain()
+            .unwrap()
+            .filter(|frame| frame.metric != "latency")
+            .map(|frame| format!("{}:{}|#{}", frame.metric, frame.value, frame.tags))
+            .collect();
+        assert!(lines.contains(&"requests:3|#az:1,region:eu".to_string()));
+        assert!(lines.contains(&"load:7|#az:1,region:eu".to_string()));
+        assert!(lines
+            .iter()
+            .any(|line| line.starts_with("wide:1|#a:17,z:0,z:1")));
+    }
+
+    #[test]
+    fn shared_histogram_kv_joins_and_sorts_tag_pairs() {
+        let collector = SharedCollector::default();
+        let region = String::from("us");
+
+        collector.histogram_kv(
+            RylvStr::from_static("latency"),
+            5,
+            &[
+                (
+                    RylvStr::from_static("region"),
+                    RylvStr::from(region.as_str()),
+                ),
+                (RylvStr::from_static("env"), RylvStr::from_static("prod")),
+            ],
+        );
+
+        let tags: Vec<String> = collector
+            .try_begin_drain()
+            .unwrap()
+            .map(|frame| frame.tags.to_string())
+            .collect();
+        assert!(!tags.is_empty());
+        assert!(tags.iter().all(|tags| tags == "env:prod,region:us"));
+    }
+
+    #[test]
+    fn shared_histogram_creation_failure_is_counted_instead_of_silently_dropped() {
+        // passes `Bounds` validation, but hdrhistogram needs max >= 2 * min
+        let bad_config = crate::HistogramConfig::default()
+            .with_bounds(10, 15)
+            .unwrap();
+        let mut histogram_configs = HashMap::with_hasher(crate::DefaultMetricHasher::new());
+        histogram_configs.insert("bad".to_string(), bad_config);
+        let collector = SharedCollector::new(SharedCollectorOptions {
+            histogram_configs,
+            ..Default::default()
+        });
+        let sorted = collector.prepare_sorted_tags([]);
+        let prepared = collector.prepare_metric(RylvStr::from_static("bad"), sorted.clone());
+
+        collector.histogram(RylvStr::from_static("bad"), 12, &mut []);
+        collector.histogram_sorted(RylvStr::from_static("bad"), 12, &sorted);
+        collector.histogram_prepared(&prepared, 12);
+        collector.histogram(RylvStr::from_static("good"), 12, &mut []);
+        assert_eq!(collector.histogram_creation_failures(), 3);
+
+        // the counter survives aggregator swaps
+        let metrics: Vec<String> = collector
+            .try_begin_drain()
+            .unwrap()
+            .map(|frame| frame.metric.to_string())
+            .collect();
+        assert!(metrics.iter().all(|metric| metric.starts_with("good")));
+        collector.histogram(RylvStr::from_static("bad"), 12, &mut []);
+        assert_eq!(collector.histogram_creation_failures(), 4);
+    }
+
+    #[test]
+    fn shared_metric_names_convert_into_keys_keeping_static_names_zero_copy() {
+        let static_name: &'static str = "static.hits";
+        let collector = SharedCollector::default();
+        collector.count(RylvStr::from_static(static_name), &mut []);
+        collector.count("borrowed.hits", &mut []);
+        collector.count(String::from("owned.hits"), &mut []);
+        collector.count(Arc::<str>::from("shared.hits"), &mut []);
+
+        let aggregator = collector.current_aggregator.load();
+        let mut keys: Vec<(String, bool)> = aggregator
+            .count
+            .iter()
+            .map(|entry| {
+                let metric = &entry.key().metric;
+                if let RylvStr::Static(name) = metric {
+                    // the stored key points at the literal, nothing was copied
+                    assert_eq!(name.as_ptr(), static_name.as_ptr());
+                }
+                (
+                    metric.as_ref().to_owned(),
+                    matches!(metric, RylvStr::Static(_)),
+                )
+            })
+            .collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec![
+                ("borrowed.hits".to_owned(), false),
+                ("owned.hits".to_owned(), false),
+                ("shared.hits".to_owned(), false),
+                ("static.hits".to_owned(), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn shared_normalize_names_aggregates_case_variants_together() {
+        let collector = SharedCollector::new(SharedCollectorOptions {
+            normalize_names: true,
+            ..Default::default()
+        });
+        let sorted = collector.prepare_sorted_tags([RylvStr::from_static("env:test")]);
+        let prepared =
+            collector.prepare_metric(RylvStr::from_static("REQUEST.COUNT"), sorted.clone());
+
+        collector.count_add(RylvStr::from_static("Request.Count"), 1, &mut []);
+        collector.count_add(RylvStr::from_static("request.count"), 2, &mut []);
+        collector.count_add_sorted(RylvStr::from_static("Request.count"), 3, &sorted);
+        collector.count_add_prepared(&prepared, 4);
+
+        let mut lines: Vec<String> = collector
+            .try_begin_drain()
+            .unwrap()
+            .map(|frame| format!("{}:{}|#{}", frame.metric, frame.value, frame.tags))
+            .collect();
+        lines.sort();
+        assert_eq!(
+            lines,
+            vec![
+                "request.count:3|#".to_string(),
+                "request.count:7|#env:test".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn shared_gauge_bool_in_last_mode_emits_the_latest_state() {
+        let collector = SharedCollector::new(SharedCollectorOptions {
+            gauge_mode: GaugeMode::Last,
+            ..Default::default()
+        });
+        let mut tags = [RylvStr::from_static("breaker:db")];
+        collector.gauge_bool(RylvStr::from_static("breaker.open"), true, &mut tags);
+        collector.gauge_bool(RylvStr::from_static("breaker.open"), false, &mut tags);
+        collector.gauge(RylvStr::from_static("pool.size"), 10, &mut []);
+        collector.gauge(RylvStr::from_static("pool.size"), 4, &mut []);
+
+        let mut lines = drain_metrics_now(&collector);
+        lines.sort();
+        assert_eq!(
+            lines,
+            vec!["breaker.open:0|g|#breaker:db\n", "pool.size:4|g\n"]
+        );
+
+        let averaged = SharedCollector::default();
+        averaged.gauge_bool(RylvStr::from_static("breaker.open"), true, &mut []);
+        averaged.gauge_bool(RylvStr::from_static("breaker.open"), true, &mut []);
+        averaged.gauge_bool(RylvStr::from_static("breaker.open"), false, &mut []);
+        assert_eq!(drain_metrics_now(&averaged), vec!["breaker.open:0|g\n"]);
+    }
+
+    #[test]
+    fn last_mode_gauge_skips_unchanged_values_but_not_drained_ones() {
+        let state = GaugeState {
+            sum: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+            updated_at: AtomicU64::new(0),
+        };
+        let read = |state: &GaugeState| {
+            (
+                state.sum.load(Ordering::Relaxed),
+                state.count.load(Ordering::Relaxed),
+            )
+        };
+        // a drained state reads as `0` but holds no value, so `0` is stored
+        state.record(0, GaugeMode::Last);
+        assert_eq!(read(&state), (0, 1));
+        state.record(7, GaugeMode::Last);
+        state.record(7, GaugeMode::Last);
+        assert_eq!(read(&state), (7, 1));
+        state.record(7, GaugeMode::Average);
+        state.record(14, GaugeMode::Last);
+        assert_eq!(read(&state), (14, 1));
+
+        let collector = SharedCollector::new(SharedCollectorOptions {
+            gauge_mode: GaugeMode::Last,
+            ..Default::default()
+        });
+        for _ in 0..2 {
+            for _ in 0..3 {
+                collector.gauge(RylvStr::from_static("pool.size"), 10, &mut []);
+            }
+            assert_eq!(drain_metrics_now(&collector), vec!["pool.size:10|g\n"]);
+        }
+    }
+
+    #[test]
+    fn gauge_min_update_interval_keeps_the_last_write_let_through() {
+        let mut gauge_min_update_intervals =
+            HashMap::with_hasher(crate::DefaultMetricHasher::new());
+        gauge_min_update_intervals.insert("pool.size".to_string(), Duration::from_secs(3600));
+        let collector = SharedCollector::new(SharedCollectorOptions {
+            gauge_mode: GaugeMode::Last,
+            gauge_min_update_intervals,
+            ..Default::default()
+        });
+
+        for value in [10, 20, 30] {
+            collector.gauge(RylvStr::from_static("pool.size"), value, &mut []);
+            collector.gauge(RylvStr::from_static("queue.depth"), value, &mut []);
+        }
+        // the first write of each window is let through, later ones are dropped
+        assert_eq!(
+            drain_metrics_now(&collector),
+            vec!["pool.size:10|g\n", "queue.depth:30|g\n"]
+        );
+        let sorted = collector.prepare_sorted_tags([]);
+        let prepared = collector.prepare_metric(RylvStr::from_static("pool.size"), sorted.clone());
+        collector.gauge_sorted(RylvStr::from_static("pool.size"), 40, &sorted);
+        collector.gauge_prepared(&prepared, 50);
+        assert_eq!(drain_metrics_now(&collector), vec!["pool.size:40|g\n"]);
+    }
+
+    #[test]
+    fn gauge_last_keeps_the_last_value_of_the_window() {
+        let collector = SharedCollector::default();
+        let mut tags = [
+            RylvStr::from_static("zone:b"),
+            RylvStr::from_static("host:a"),
+        ];
+        for value in [10, 30, 20] {
+            collector.gauge_last(RylvStr::from_static("temperature"), value, &mut tags);
+            collector.gauge(RylvStr::from_static("load"), value, &mut []);
+        }
+        assert_eq!(
+            drain_metrics_now(&collector),
+            vec!["load:20|g\n", "temperature:20|g|#host:a,zone:b\n"]
+        );
+        assert!(drain_metrics_now(&collector).is_empty());
+
+        // `u64::MAX` marks an unset series, so it is kept one below
+        collector.gauge_last(RylvStr::from_static("temperature"), u64::MAX, &mut []);
+        assert_eq!(
+            drain_metrics_now(&collector),
+            vec![format!("temperature:{}|g\n", u64::MAX - 1)]
+        );
+
+        let collector = SharedCollector::new(SharedCollectorOptions {
+            keep_zero_values: true,
+            ..Default::default()
+        });
+        collector.gauge_last(RylvStr::from_static("temperature"), 5, &mut []);
+        assert_eq!(drain_metrics_now(&collector), vec!["temperature:5|g\n"]);
+        assert_eq!(drain_metrics_now(&collector), vec!["temperature:0|g\n"]);
+    }
+
+    #[test]
+    fn shared_skip_tag_sort_aggregates_tag_orders_into_one_series() {
+        let record = |collector: &SharedCollector| {
+            for tags in [["a:1", "b:2"], ["b:2", "a:1"]] {
+                let mut tags = tags.map(RylvStr::from_static);
+                collector.count_add(RylvStr::from_static("requests"), 1, &mut tags);
+                collector.gauge(RylvStr::from_static("load"), 4, &mut tags);
+                collector.histogram(RylvStr::from_static("latency"), 10, &mut tags);
+            }
+            let mut lines: Vec<String> = collector
+                .try_begin_drain()
+                .unwrap()
+                .map(|frame| format!("{}:{}|#{}", frame.metric, frame.value, frame.tags))
+                .collect();
+            lines.sort();
+            lines
+        };
+
+        let unsorted = SharedCollector::new(SharedCollectorOptions {
+            skip_tag_sort: true,
+            ..Default::default()
+        });
+        let lines = record(&unsorted);
+        assert!(lines.contains(&"requests:2|#a:1,b:2".to_string()));
+        assert!(lines.contains(&"latency:2|#a:1,b:2".to_string()));
+        assert_eq!(lines, record(&SharedCollector::default()));
+
+        let mut tags = ["b:2", "a:1"].map(RylvStr::from_static);
+        unsorted.count_add(RylvStr::from_static("requests"), 1, &mut tags);
+        assert_eq!(tags.map(|tag| tag.as_ref().to_owned()), ["b:2", "a:1"]);
+    }
+
+    #[test]
+    fn shared_histogram_without_quantiles_emits_count_and_extent_only() {
+        let mut histogram_configs = HashMap::new();
+        histogram_configs.insert(
+            "request.size".to_string(),
+            HistogramConfig::default().without_quantiles(),
+        );
+        let collector = SharedCollector::new(SharedCollectorOptions {
+            histogram_configs,
+            ..Default::default()
+        });
+        for value in [10, 30, 20] {
+            collector.histogram(RylvStr::from_static("request.size"), value, []);
+            collector.histogram(RylvStr::from_static("request.latency"), value, []);
+        }
+
+        let lines = drain_metrics_now(&collector);
+        let size_lines = lines
+            .iter()
+            .filter(|line| line.starts_with("request.size"))
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            size_lines,
+            vec![
+                "request.size.count:3|c\n".to_string(),
+                "request.size.max:30|g\n".to_string(),
+                "request.size.min:10|g\n".to_string(),
+            ]
+        );
+        assert!(lines.contains(&"request.latency.avg:20|g\n".to_string()));
+        assert!(lines
+            .iter()
+            .any(|line| line.starts_with("request.latency.99percentile:")));
+    }
+
+    #[test]
+    fn shared_prefix_separator_joins_non_empty_prefix_once() {
+        let line = |stats_prefix: &str, prefix_separator| {
+            let collector = SharedCollector::new(SharedCollectorOptions {
+                stats_prefix: stats_prefix.to_string(),
+                prefix_separator,
+                ..Default::default()
+            });
+            collector.count(RylvStr::from_static("requests"), []);
+            drain_metrics_now(&collector)
+        };
+
+        assert_eq!(line("", Some(".")), vec!["requests:1|c\n".to_string()]);
+        assert_eq!(
+            line("app", Some(".")),
+            vec!["app.requests:1|c\n".to_string()]
+        );
+        assert_eq!(
+            line("app.", Some(".")),
+            vec!["app.requests:1|c\n".to_string()]
+        );
+        assert_eq!(
+            line("app::", Some("::")),
+            vec!["app::requests:1|c\n".to_string()]
+        );
+        assert_eq!(line("app", None), vec!["apprequests:1|c\n".to_string()]);
+    }
+
+    #[test]
+    fn shared_type_prefixes_compose_after_stats_prefix() {
+        let collector = SharedCollector::new(SharedCollectorOptions {
+            stats_prefix: "myapp".to_string(),
+            prefix_separator: Some("."),
+            histogram_prefix: "timing.".to_string(),
+            count_prefix: "count.".to_string(),
+            gauge_prefix: "gauge.".to_string(),
+            ..Default::default()
+        });
+        collector.histogram(RylvStr::from_static("request.duration"), 7, []);
+        collector.count(RylvStr::from_static("requests"), []);
+        collector.gauge(RylvStr::from_static("queue"), 3, []);
+
+        let lines = drain_metrics_now(&collector);
+        assert!(lines.contains(&"myapp.timing.request.duration.count:1|c\n".to_string()));
+        assert!(lines.contains(&"myapp.timing.request.duration.max:7|g\n".to_string()));
+        assert!(lines.contains(&"myapp.count.requests:1|c\n".to_string()));
+        assert!(lines.contains(&"myapp.gauge.queue:3|g\n".to_string()));
+        assert!(lines.iter().all(|line| line.starts_with("myapp.timing.")
+            || line.starts_with("myapp.count.")
+            || line.starts_with("myapp.gauge.")));
+    }
+
+    fn out_of_bounds_lines(policy: OutOfBoundsPolicy) -> Vec<String> {
+        let collector = SharedCollector::new(SharedCollectorOptions {
+            default_histogram_config: HistogramConfig::default()
+                .with_bounds(1, 1_000)
+                .unwrap()
+                .with_out_of_bounds(policy),
+            ..Default::default()
+        });
+        collector.histogram(RylvStr::from_static("latency"), 10, []);
+        collector.histogram(RylvStr::from_static("latency"), 5_000, []);
+        let mut lines = drain_metrics_now(&collector);
+        lines.retain(|line| !line.starts_with("latency.95") && !line.starts_with("latency.99"));
+        lines
+    }
+
+    #[test]
+    fn shared_out_of_bounds_policy_handles_values_above_high() {
+        assert_eq!(
+            out_of_bounds_lines(OutOfBoundsPolicy::Drop),
+            [
+                "latency.avg:10|g\n",
+                "latency.count:1|c\n",
+                "latency.max:10|g\n",
+                "latency.min:10|g\n"
+            ]
+        );
+        assert_eq!(
+            out_of_bounds_lines(OutOfBoundsPolicy::Clamp),
+            [
+                "latency.avg:10|g\n",
+                "latency.count:2|c\n",
+                "latency.max:1000|g\n",
+                "latency.min:10|g\n"
+            ]
+        );
+        assert_eq!(
+            out_of_bounds_lines(OutOfBoundsPolicy::CountOverflow),
+            [
+                "latency.avg:10|g\n",
+                "latency.count:1|c\n",
+                "latency.max:10|g\n",
+                "latency.min:10|g\n",
+                "latency.overflow_count:1|c\n"
+            ]
+        );
+    }
+
+    #[test]
+    fn shared_percentile_method_applies_to_percentiles_and_avg() {
+        let lines_with = |method| {
+            let collector = SharedCollector::new(SharedCollectorOptions {
+                default_histogram_config: HistogramConfig::new(SigFig::default(), vec![0.5])
+                    .unwrap()
+                    .with_base_metrics([HistogramBaseMetric::Avg])
+                    .with_percentile_method(method),
+                ..Default::default()
+            });
+            for value in 1..=4 {
+                collector.histogram(RylvStr::from_static("latency"), value, []);
+            }
+            drain_metrics_now(&collector)
+        };
+
+        assert_eq!(
+            lines_with(PercentileMethod::NearestRank),
+            ["latency.50percentile:2|g\n", "latency.avg:2|g\n"]
+        );
+        assert_eq!(
+            lines_with(PercentileMethod::Linear),
+            ["latency.50percentile:3|g\n", "latency.avg:3|g\n"]
+        );
+    }
+
+    #[test]
+    fn shared_overflow_only_histogram_emits_just_the_overflow_count() {
+        let collector = SharedCollector::new(SharedCollectorOptions {
+            default_histogram_config: HistogramConfig::default()
+                .with_bounds(1, 1_000)
+                .unwrap()
+                .with_out_of_bounds(OutOfBoundsPolicy::CountOverflow),
+            ..Default::default()
+        });
+        collector.histogram(RylvStr::from_static("latency"), 5_000, []);
+        collector.histogram(RylvStr::from_static("latency"), 6_000, []);
+        assert_eq!(
+            drain_metrics_now(&collector),
+            ["latency.overflow_count:2|c\n"]
+        );
+        assert!(drain_metrics_now(&collector).is_empty());
+    }
+
+    #[test]
+    fn shared_prefixed_methods_keep_prefixes_apart() {
+        let collector = SharedCollector::new(SharedCollectorOptions {
+            stats_prefix: "app.".to_string(),
+            ..Default::default()
+        });
+        let long_prefix = "subsystem.".repeat(30);
+
+        collector.count_prefixed(
+            "db.",
+            RylvStr::from_static("requests"),
+            &mut [RylvStr::from_static("a:1")],
+        );
+        collector.count_add_prefixed(
+            "http.",
+            RylvStr::from_static("requests"),
+            2,
+            &mut [RylvStr::from_static("a:1")],
+        );
+        collector.count_add_prefixed(
+            "db.",
+            RylvStr::from_static("requests"),
+            3,
+            &mut [RylvStr::from_static("a:1")],
+        );
+        collector.gauge_prefixed(
+            "http.",
+            RylvStr::from_static("load"),
+            7,
+            &mut [RylvStr::from_static("a:1")],
+        );
+        collector.histogram_prefixed(
+            "db.",
+            RylvStr::from_static("latency"),
+            9,
+            &mut [RylvStr::from_static("a:1")],
+        );
+        collector.count_prefixed(
+            &long_prefix,
+            RylvStr::from_static("requests"),
+            &mut [RylvStr::from_static("a:1")],
+        );
+
+        let lines = drain_metrics_now(&collector);
+        assert!(lines.contains(&"app.db.requests:4|c|#a:1\n".to_string()));
+        assert!(lines.contains(&"app.http.requests:2|c|#a:1\n".to_string()));
+        assert!(lines.contains(&"app.http.load:7|g|#a:1\n".to_string()));
+        assert!(lines.contains(&"app.db.latency.count:1|c|#a:1\n".to_string()));
+        assert!(lines.contains(&format!("app.{long_prefix}requests:1|c|#a:1\n")));
+    }
+
+    #[test]
+    fn shared_reference_trait_impls_cover_regular_paths() {
+        let collector = SharedCollector::new(SharedCollectorOptions {
+            stats_prefix: "ref.".to_string(),
+            ..Default::default()
+        });
+        let collector_ref = &collector;
+
+        collector_ref.count(
+            RylvStr::from_static("requests"),
+            &mut [RylvStr::from_static("b:2"), RylvStr::from_static("a:1")],
+        );
+        collector_ref.count_add(
+            RylvStr::from_static("requests"),
+            4,
+            &mut [RylvStr::from_static("a:1"), RylvStr::from_static("b:2")],
+        );
+        collector_ref.gauge(
+            RylvStr::from_static("load"),
+            10,
+            &mut [RylvStr::from_static("b:2"), RylvStr::from_static("a:1")],
+        );
+        collector_ref.gauge(
+            RylvStr::from_static("load"),
+            20,
+            &mut [RylvStr::from_static("a:1"), RylvStr::from_static("b:2")],
+        );
+        collector_ref.histogram(
+            RylvStr::from_static("latency"),
+            40,
+            &mut [RylvStr::from_static("b:2"), RylvStr::from_static("a:1")],
+        );
+        collector_ref.histogram(
+            RylvStr::from_static("latency"),
+            60,
+            &mut [RylvStr::from_static("a:1"), RylvStr::from_static("b:2")],
+        );
+
+        let drain =
+            <&SharedCollector as DrainMetricCollectorTrait>::try_begin_drain(&collector_ref)
+                .unwrap();
+        assert_reference_lines(&drain_to_lines(drain));
+    }
+
+    #[test]
+    fn raw_aggregator_record_helpers_cover_regular_sorted_and_prepared_paths() {
+        let collector = SharedCollector::new(SharedCollectorOptions::default());
+        let aggregator = Aggregator::with_hasher_builder(
+            &collector.hasher_builder,
+            Arc::clone(&collector.pool_histograms),
+            Arc::default(),
+            Arc::default(),
+        );
+
+        record_all_helper_variants(&collector, &aggregator);
+
+        let prefixes = TypePrefixes::new("agg.", "", "", "");
+        let lines = frames_to_lines(drain_aggregator_frames(&aggregator, &prefixes, None, None));
+        assert!(lines.contains(&"agg.requests:2|c|#a:1,b:2\n".to_string()));
+        assert!(lines.contains(&"agg.requests_sorted:3|c|#a:1,b:2\n".to_string()));
+        assert!(lines.contains(&"agg.requests_prepared:9|c|#a:1,b:2\n".to_string()));
         assert!(lines.contains(&"agg.load:10|g|#a:1,b:2\n".to_string()));
         assert!(lines.contains(&"agg.load_sorted:20|g|#a:1,b:2\n".to_string()));
         assert!(lines.contains(&"agg.load_prepared:40|g|#a:1,b:2\n".to_string()));
@@ -1646,6 +3998,65 @@ mod tests {
         assert!(lines.contains(&"agg.latency_prepared.count:2|c|#a:1,b:2\n".to_string()));
     }
 
+    #[test]
+    fn rate_is_divided_by_the_drained_window() {
+        let collector = SharedCollector::new(SharedCollectorOptions::default());
+        let mut aggregator = Aggregator::with_hasher_builder(
+            &collector.hasher_builder,
+            Arc::clone(&collector.pool_histograms),
+            Arc::default(),
+            Arc::default(),
+        );
+        aggregator.close_window();
+        aggregator.window_start = aggregator
+            .window_end
+            .get()
+            .and_then(|end| end.checked_sub(Duration::from_secs(2)))
+            .unwrap();
+
+        let mut tags = [RylvStr::from_static("b:2"), RylvStr::from_static("a:1")];
+        record_rate_in_aggregator(
+            &aggregator,
+            RylvStr::from_static("bytes"),
+            200,
+            &mut tags,
+            true,
+        );
+        record_rate_in_aggregator(
+            &aggregator,
+            RylvStr::from_static("bytes"),
+            100,
+            &mut tags,
+            true,
+        );
+
+        let prefixes = TypePrefixes::new("agg.", "", "", "");
+        let lines = frames_to_lines(drain_aggregator_frames(&aggregator, &prefixes, None, None));
+        assert_eq!(lines, vec!["agg.bytes:150|g|#a:1,b:2\n".to_string()]);
+        let lines = frames_to_lines(drain_aggregator_frames(&aggregator, &prefixes, None, None));
+        assert!(lines.is_empty());
+        assert!(aggregator.rates.is_empty());
+    }
+
+    #[test]
+    fn shared_rate_uses_the_elapsed_time_between_drains() {
+        let collector = SharedCollector::default();
+        assert!(drain_metrics_now(&collector).is_empty());
+
+        collector.rate(RylvStr::from_static("bytes"), 600, &mut []);
+        std::thread::sleep(Duration::from_millis(500));
+        let lines = drain_metrics_now(&collector);
+
+        assert_eq!(lines.len(), 1);
+        let rate = lines[0]
+            .strip_prefix("bytes:")
+            .and_then(|line| line.strip_suffix("|g\n"))
+            .and_then(|value| value.parse::<f64>().ok())
+            .unwrap();
+        // at least the 500ms slept, with room for a slow scheduler
+        assert!((200.0..=1200.0).contains(&rate), "unexpected rate {rate}");
+    }
+
     #[test]
     fn drain_frames_remove_empty_entries_and_recycle_histograms() {
         let hasher = crate::DefaultMetricHasher::new();
@@ -1654,7 +4065,12 @@ mod tests {
             HashMap::with_hasher(hasher.clone()),
             &hasher,
         );
-        let aggregator = Aggregator::with_hasher_builder(&hasher, resolved.pool_count);
+        let aggregator = Aggregator::with_hasher_builder(
+            &hasher,
+            histogram_pools(&resolved.pool_specs, 0, usize::MAX),
+            Arc::default(),
+            Arc::default(),
+        );
         let empty_configs = HashMap::with_hasher(hasher);
 
         record_count_add_in_aggregator(
@@ -1662,6 +4078,7 @@ mod tests {
             RylvStr::from_static("to_remove"),
             1,
             &mut [RylvStr::from_static("a:1")],
+            true,
         );
         let remove_key = aggregator
             .count
@@ -1678,6 +4095,7 @@ mod tests {
             RylvStr::from_static("requests"),
             1,
             &mut [RylvStr::from_static("a:1")],
+            true,
         );
         aggregator
             .count
@@ -1692,6 +4110,9 @@ mod tests {
             RylvStr::from_static("load"),
             10,
             &mut [RylvStr::from_static("a:1")],
+            true,
+            GaugeMode::Average,
+            None,
         );
         let gauge = aggregator.gauge.iter().next().unwrap();
         gauge.sum.store(0, Ordering::SeqCst);
@@ -1705,6 +4126,7 @@ mod tests {
             RylvStr::from_static("latency"),
             10,
             &mut [RylvStr::from_static("a:1")],
+            true,
         );
         aggregator
             .histograms
@@ -1714,7 +4136,8 @@ mod tests {
             .value_mut()
             .reset();
 
-        let mut frames = drain_aggregator_frames(&aggregator, "");
+        let prefixes = TypePrefixes::default();
+        let mut frames = drain_aggregator_frames(&aggregator, &prefixes, None, None);
         assert!(frames.next_frame().is_none());
         drop(frames);
 
@@ -1727,4 +4150,248 @@ mod tests {
                 .is_some()
         );
     }
+
+    #[cfg(not(feature = "shared-collector"))]
+    #[test]
+    fn simple_map_records_and_drains_every_metric_kind() {
+        let collector = SharedCollector::default();
+        let shards: Vec<_> = (0..32).map(|shard| format!("shard:{shard}")).collect();
+        for _ in 0..2 {
+            for shard in &shards {
+                let mut tags = [RylvStr::from(shard.as_str())];
+                collector.count(RylvStr::from_static("requests"), &mut tags);
+                collector.gauge(RylvStr::from_static("load"), 7, &mut tags);
+                collector.histogram(RylvStr::from_static("latency"), 40, &mut tags);
+            }
+
+            let lines = drain_metrics_now(&collector);
+            assert_eq!(lines.len(), shards.len() * 8);
+            for shard in &shards {
+                for expected in [
+                    format!("requests:1|c|#{shard}\n"),
+                    format!("load:7|g|#{shard}\n"),
+                    format!("latency.count:1|c|#{shard}\n"),
+                    format!("latency.max:40|g|#{shard}\n"),
+                ] {
+                    assert!(lines.contains(&expected), "missing {expected}");
+                }
+            }
+        }
+        assert!(drain_metrics_now(&collector).is_empty());
+    }
+}
+
+// Models the hand-off of one generation with loom: recorders update it with
+// `Relaxed` atomics and drop their `Arc`, the drain takes it with
+// `Arc::try_unwrap`, reads and resets it with the orderings of `Frames`, and
+// hands it back for the next generation. `ArcSwap`'s debt slots are not
+// modelled, which is why `Frames` does not rely on them for ordering.
+#[cfg(all(test, feature = "loom"))]
+mod loom_tests {
+    use super::{add_or_insert_in_shard, ShardKey, ShardLock, ShardLookup, ShardTable};
+    use loom::sync::atomic::{AtomicU64, Ordering};
+    use loom::sync::{Arc, Mutex, RwLock};
+    use loom::thread;
+
+    #[derive(Default)]
+    struct Generation {
+        count: AtomicU64,
+        gauge_sum: AtomicU64,
+        gauge_count: AtomicU64,
+    }
+
+    fn record(generation: &Generation, value: u64) {
+        generation.count.fetch_add(value, Ordering::Relaxed);
+        generation.gauge_count.fetch_add(1, Ordering::Relaxed);
+        generation.gauge_sum.fetch_add(value, Ordering::Relaxed);
+    }
+
+    /// Spins until every recorder released `generation`, like `begin_drain` retrying.
+    fn take(mut generation: Arc<Generation>) -> Generation {
+        loop {
+            match Arc::try_unwrap(generation) {
+                Ok(generation) => return generation,
+                Err(shared) => {
+                    generation = shared;
+                    thread::yield_now();
+                }
+            }
+        }
+    }
+
+    /// Reads and resets a generation the way `Frames` does.
+    fn drain(generation: &Generation) -> (u64, u64, u64) {
+        let drained = (
+            generation.count.load(Ordering::Acquire),
+            generation.gauge_sum.load(Ordering::Acquire),
+            generation.gauge_count.load(Ordering::Acquire),
+        );
+        generation.count.store(0, Ordering::Release);
+        generation.gauge_sum.store(0, Ordering::Release);
+        generation.gauge_count.store(0, Ordering::Release);
+        drained
+    }
+
+    #[test]
+    fn drain_observes_every_released_recording() {
+        loom::model(|| {
+            let generation = Arc::new(Generation::default());
+            let recorders: Vec<_> = [3, 4]
+                .into_iter()
+                .map(|value| {
+                    let generation = Arc::clone(&generation);
+                    thread::spawn(move || record(&generation, value))
+                })
+                .collect();
+
+            assert_eq!(drain(&take(generation)), (7, 7, 2));
+            for recorder in recorders {
+                recorder.join().unwrap();
+            }
+        });
+    }
+
+    #[test]
+    fn resets_are_visible_to_the_next_generation() {
+        loom::model(|| {
+            let available = Arc::new(Mutex::new(None));
+            let generation = Arc::new(Generation::default());
+            record(&generation, 5);
+            let drained = take(generation);
+            assert_eq!(drain(&drained), (5, 5, 1));
+            *available.lock().unwrap() = Some(drained);
+
+            let reused = {
+                let available = Arc::clone(&available);
+                thread::spawn(move || {
+                    let generation = Arc::new(available.lock().unwrap().take().unwrap());
+                    record(&generation, 2);
+                    drain(&take(generation))
+                })
+            };
+            assert_eq!(reused.join().unwrap(), (2, 2, 1));
+        });
+    }
+
+    impl<T> ShardLock<T> for RwLock<T> {
+        type ReadGuard<'a>
+            = loom::sync::RwLockReadGuard<'a, T>
+        where
+            T: 'a;
+        type WriteGuard<'a>
+            = loom::sync::RwLockWriteGuard<'a, T>
+        where
+            T: 'a;
+
+        fn read(&self) -> Self::ReadGuard<'_> {
+            Self::read(self).unwrap()
+        }
+
+        fn write(&self) -> Self::WriteGuard<'_> {
+            Self::write(self).unwrap()
+        }
+    }
+
+    struct TestKey {
+        id: u64,
+        hash: u64,
+    }
+
+    impl ShardKey for TestKey {
+        fn hash(&self) -> u64 {
+            self.hash
+        }
+    }
+
+    impl ShardLookup<Self> for TestKey {
+        fn hash(&self) -> u64 {
+            self.hash
+        }
+
+        fn matches(&self, key: &Self) -> bool {
+            self.id == key.id
+        }
+
+        fn into_key(self) -> Self {
+            self
+        }
+    }
+
+    type TestShard = RwLock<ShardTable<TestKey, AtomicU64>>;
+
+    fn record_in_shard(shard: &TestShard, id: u64, hash: u64) {
+        add_or_insert_in_shard(
+            shard,
+            TestKey { id, hash },
+            1,
+            |counter: &AtomicU64, value| {
+                counter.fetch_add(value, Ordering::Relaxed);
+                Ok(())
+            },
+            || Some(AtomicU64::new(0)),
+        );
+    }
+
+    /// Returns `(id, value)` for every entry of the shard, sorted by id.
+    fn shard_entries(shard: &TestShard) -> Vec<(u64, u64)> {
+        let table = ShardLock::read(shard);
+        // SAFETY: the read guard outlives the iteration and the buckets.
+        let mut entries: Vec<(u64, u64)> = unsafe { table.iter() }
+            .map(|bucket| {
+                let (key, value) = unsafe { bucket.as_ref() };
+                (key.id, value.get().load(Ordering::Relaxed))
+            })
+            .collect();
+        entries.sort_unstable();
+        entries
+    }
+
+    /// Records `ids` (all under `hash`) from one thread each, then returns the shard entries.
+    fn record_concurrently(ids: [u64; 2], hash: u64) -> Vec<(u64, u64)> {
+        let shard: Arc<TestShard> = Arc::new(RwLock::new(ShardTable::new()));
+        let recorders: Vec<_> = ids
+            .into_iter()
+            .map(|id| {
+                let shard = Arc::clone(&shard);
+                thread::spawn(move || record_in_shard(&shard, id, hash))
+            })
+            .collect();
+        for recorder in recorders {
+            recorder.join().unwrap();
+        }
+        shard_entries(&shard)
+    }
+
+    #[test]
+    fn concurrent_inserts_of_the_same_key_create_one_entry() {
+        loom::model(|| {
+            assert_eq!(record_concurrently([1, 1], 7), vec![(1, 2)]);
+        });
+    }
+
+    #[test]
+    fn concurrent_inserts_of_colliding_keys_keep_both_entries() {
+        loom::model(|| {
+            assert_eq!(record_concurrently([1, 2], 7), vec![(1, 1), (2, 1)]);
+        });
+    }
+
+    #[test]
+    fn recording_into_an_existing_entry_races_with_a_new_insert() {
+        loom::model(|| {
+            let shard: Arc<TestShard> = Arc::new(RwLock::new(ShardTable::new()));
+            record_in_shard(&shard, 1, 7);
+            let recorders: Vec<_> = [1, 2]
+                .into_iter()
+                .map(|id| {
+                    let shard = Arc::clone(&shard);
+                    thread::spawn(move || record_in_shard(&shard, id, 7))
+                })
+                .collect();
+            for recorder in recorders {
+                recorder.join().unwrap();
+            }
+            assert_eq!(shard_entries(&shard), vec![(1, 2), (2, 1)]);
+        });
+    }
 }
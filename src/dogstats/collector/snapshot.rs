@@ -0,0 +1,74 @@
+/// Owned copy of the metrics aggregated since the last drain, returned by
+/// [`DrainMetricCollectorTrait::snapshot`](super::DrainMetricCollectorTrait::snapshot).
+///
+/// Taking a snapshot does not reset anything, so the next flush still emits
+/// every value it contains. It is a point-in-time view: recordings racing with
+/// it may or may not be included. Names are the aggregation keys, without the
+/// configured prefixes, and tags are in joined `DogStatsD` format.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    /// Counters with a non-zero value.
+    pub counts: Vec<SnapshotValue>,
+    /// Gauges with at least one recorded value, combined per their `GaugeMode`.
+    pub gauges: Vec<SnapshotValue>,
+    /// Histograms with at least one recorded sample.
+    pub histograms: Vec<HistogramSnapshot>,
+}
+
+/// Value of one count or gauge series in a [`Snapshot`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotValue {
+    /// Metric name.
+    pub metric: String,
+    /// Sorted tags joined with `,`.
+    pub tags: String,
+    /// Aggregated value, as the next flush would emit it.
+    pub value: u64,
+}
+
+/// Summary of one histogram series in a [`Snapshot`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistogramSnapshot {
+    /// Metric name.
+    pub metric: String,
+    /// Sorted tags joined with `,`.
+    pub tags: String,
+    /// Number of recorded samples.
+    pub count: u64,
+    /// Smallest recorded value.
+    pub min: u64,
+    /// Largest recorded value.
+    pub max: u64,
+    /// Sum of the recorded values.
+    pub sum: u64,
+    /// Value at each configured percentile, as `(quantile, value)` pairs.
+    pub percentiles: Vec<(f64, u64)>,
+}
+
+impl Snapshot {
+    /// Returns the value of the counter `metric` with joined `tags`.
+    #[must_use]
+    pub fn count(&self, metric: &str, tags: &str) -> Option<u64> {
+        find(&self.counts, metric, tags).map(|entry| entry.value)
+    }
+
+    /// Returns the value of the gauge `metric` with joined `tags`.
+    #[must_use]
+    pub fn gauge(&self, metric: &str, tags: &str) -> Option<u64> {
+        find(&self.gauges, metric, tags).map(|entry| entry.value)
+    }
+
+    /// Returns the summary of the histogram `metric` with joined `tags`.
+    #[must_use]
+    pub fn histogram(&self, metric: &str, tags: &str) -> Option<&HistogramSnapshot> {
+        self.histograms
+            .iter()
+            .find(|entry| entry.metric == metric && entry.tags == tags)
+    }
+}
+
+fn find<'a>(entries: &'a [SnapshotValue], metric: &str, tags: &str) -> Option<&'a SnapshotValue> {
+    entries
+        .iter()
+        .find(|entry| entry.metric == metric && entry.tags == tags)
+}
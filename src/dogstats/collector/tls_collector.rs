@@ -1,26 +1,33 @@
 use crate::dogstats::collector::{MetricFrameRef, MetricKind, MetricSuffix};
 use crate::dogstats::histogram_config::{
-    resolve_histogram_configs, Bounds, HistogramBaseMetric, HistogramBaseMetrics, HistogramConfig,
-    HistogramPoolSpec, ResolvedHistogramConfig, ResolvedHistogramConfigs,
+    lowercase_histogram_config_keys, resolve_histogram_configs, Bounds, HistogramBaseMetric,
+    HistogramBaseMetrics, HistogramConfig, HistogramPoolSpec, ResolvedHistogramConfig,
+    ResolvedHistogramConfigs,
 };
+use crate::dogstats::metric_filter::MetricFilterState;
 use crate::dogstats::sorted_tags::{
     combine_metric_tags_hash, hash_tags, to_static_metric, PreparedMetric,
 };
 use crate::dogstats::{
     aggregator::{
-        to_agg_entry_key, AggregatorEntryKey, HistogramWrapper, LookupKey, LookupKeySorted,
-        RemoveKey,
+        to_agg_entry_key, AggregatorEntryKey, DistributionCursor, HistogramWrapper, IdleEviction,
+        LookupKey, LookupKeySorted, RemoveKey,
     },
-    RylvStr, SortedTags,
+    MetricFilter, RylvStr, SortedTags,
 };
 use crate::DefaultMetricHasher;
 use std::cmp::{max, min};
 use std::collections::HashMap;
 use std::hash::BuildHasher;
+use std::num::NonZeroU64;
 use std::ptr::addr_of_mut;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use super::{DrainMetricCollectorTrait, MetricCollectorTrait};
+use super::{
+    join_stats_prefix, normalize_metric_name, per_second, round_value, DrainBudget,
+    DrainMetricCollectorTrait, GaugeMode, MetricCollectorTrait, TypePrefixes,
+};
 use crossbeam::utils::CachePadded;
 use hashbrown::hash_table::Entry::{Occupied, Vacant};
 use hashbrown::HashTable;
@@ -35,10 +42,39 @@ struct GaugeStateHb {
     count: u64,
 }
 
+impl GaugeStateHb {
+    #[inline]
+    const fn record(&mut self, value: u64, mode: GaugeMode) {
+        match mode {
+            GaugeMode::Average => {
+                self.count += 1;
+                self.sum += value;
+            }
+            GaugeMode::Last => {
+                self.sum = value;
+                self.count = 1;
+            }
+        }
+    }
+
+    /// Folds the state of a later recording window into this one.
+    #[inline]
+    const fn merge(&mut self, later: Self, mode: GaugeMode) {
+        match mode {
+            GaugeMode::Average => {
+                self.sum += later.sum;
+                self.count += later.count;
+            }
+            GaugeMode::Last => *self = later,
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 enum DrainStage {
     Count,
     Gauge,
+    Rate,
     Histogram,
     Done,
 }
@@ -50,6 +86,9 @@ where
     histograms: HashTable<(AggregatorEntryKey<S>, HistogramWrapper)>,
     count: HashTable<(AggregatorEntryKey<S>, u64)>,
     gauge: HashTable<(AggregatorEntryKey<S>, GaugeStateHb)>,
+    // Recorded through `gauge_last`, always in `GaugeMode::Last`
+    last_gauges: HashTable<(AggregatorEntryKey<S>, GaugeStateHb)>,
+    rates: HashTable<(AggregatorEntryKey<S>, u64)>,
     pool_histograms: Vec<Vec<HistogramWrapper>>,
 }
 
@@ -68,6 +107,8 @@ where
             histograms: HashTable::new(),
             count: HashTable::new(),
             gauge: HashTable::new(),
+            last_gauges: HashTable::new(),
+            rates: HashTable::new(),
             pool_histograms: (0..pool_count).map(|_| Vec::new()).collect(),
         }
     }
@@ -77,6 +118,8 @@ where
             histograms: HashTable::with_capacity(self.histograms.len()),
             count: HashTable::with_capacity(self.count.len()),
             gauge: HashTable::with_capacity(self.gauge.len()),
+            last_gauges: HashTable::with_capacity(self.last_gauges.len()),
+            rates: HashTable::with_capacity(self.rates.len()),
             pool_histograms: self
                 .pool_histograms
                 .iter()
@@ -104,25 +147,36 @@ fn get_histogram_from_pool(
     bounds: Bounds,
     percentiles: Arc<[f64]>,
     emit_base_metrics: HistogramBaseMetrics,
+    unit: Option<&'static str>,
 ) -> Option<HistogramWrapper> {
     if let Some(mut histogram) = pool_histograms[pool_id].pop() {
         histogram.percentiles = percentiles;
         histogram.emit_base_metrics = emit_base_metrics;
+        histogram.unit = unit;
         return Some(histogram);
     }
 
-    if let Ok(histogram) = Histogram::new_with_bounds(bounds.min(), bounds.max(), sig_fig.value()) {
-        return Some(HistogramWrapper {
+    match Histogram::new_with_bounds(bounds.min(), bounds.max(), sig_fig.value()) {
+        Ok(histogram) => Some(HistogramWrapper {
             pool_id,
             histogram,
             min: u64::MAX,
             max: u64::MIN,
+            sum: 0,
+            overflow_count: 0,
             percentiles,
             emit_base_metrics,
-        });
+            unit,
+        }),
+        Err(err) => {
+            error!(
+                "Fail to create histogram with bounds [{}, {}]: {err:?}",
+                bounds.min(),
+                bounds.max()
+            );
+            None
+        }
     }
-
-    None
 }
 
 fn get_histogram_from_pool_config(
@@ -136,6 +190,7 @@ fn get_histogram_from_pool_config(
         config.bounds(),
         config.percentiles().clone(),
         config.emit_base_metrics(),
+        config.unit(),
     )
 }
 
@@ -146,8 +201,13 @@ where
     histograms: HashTable<(AggregatorEntryKey<S>, HistogramWrapper)>,
     count: HashTable<(AggregatorEntryKey<S>, u64)>,
     gauge: HashTable<(AggregatorEntryKey<S>, GaugeStateHb)>,
+    // Recorded through `gauge_last`, always in `GaugeMode::Last`
+    last_gauges: HashTable<(AggregatorEntryKey<S>, GaugeStateHb)>,
+    rates: HashTable<(AggregatorEntryKey<S>, u64)>,
     pool_histograms: Vec<Vec<HistogramWrapper>>,
     key_to_remove: Vec<RemoveKey>,
+    // Time between the flush that closed this generation and the previous one
+    rate_window: Duration,
 }
 
 impl<S> GlobalAggregatorHb<S>
@@ -160,8 +220,11 @@ where
             histograms: HashTable::new(),
             count: HashTable::new(),
             gauge: HashTable::new(),
+            last_gauges: HashTable::new(),
+            rates: HashTable::new(),
             pool_histograms: (0..pool_count).map(|_| Vec::new()).collect(),
             key_to_remove: Vec::new(),
+            rate_window: Duration::ZERO,
         }
     }
 
@@ -170,12 +233,15 @@ where
             histograms: HashTable::with_capacity(self.histograms.len()),
             count: HashTable::with_capacity(self.count.len()),
             gauge: HashTable::with_capacity(self.gauge.len()),
+            last_gauges: HashTable::with_capacity(self.last_gauges.len()),
+            rates: HashTable::with_capacity(self.rates.len()),
             pool_histograms: self
                 .pool_histograms
                 .iter()
                 .map(|pool| Vec::with_capacity(pool.len()))
                 .collect(),
             key_to_remove: Vec::with_capacity(self.key_to_remove.capacity()),
+            rate_window: Duration::ZERO,
         }
     }
 }
@@ -189,14 +255,27 @@ where
     S: BuildHasher + Clone + Send,
 {
     stats_prefix: String,
+    type_prefixes: TypePrefixes,
     buffers: ThreadLocal<CachePadded<Mutex<LocalAggregatorHb<S>>>>,
     hasher_builder: S,
     pool_count: usize,
     histogram_configs: HashMap<String, ResolvedHistogramConfig, S>,
+    normalize_names: bool,
+    metric_filter: MetricFilterState,
+    keep_zero_values: bool,
+    key_idle_ttl: Option<Duration>,
+    skip_tag_sort: bool,
+    gauge_mode: GaugeMode,
+    value_rounding: Option<NonZeroU64>,
+    created_at: Instant,
+    // Start of the window whose rates the next flush divides by
+    last_flush: Mutex<Instant>,
     pool_specs: Arc<[HistogramPoolSpec]>,
     default_histogram_config: ResolvedHistogramConfig,
     global_aggregator: Mutex<GlobalAggregatorHb<S>>,
     recycled_global_aggregators: Mutex<Vec<GlobalAggregatorHb<S>>>,
+    // Generation whose drain stopped at its deadline, resumed by the next drain
+    interrupted_global: Mutex<Option<GlobalAggregatorHb<S>>>,
     recycled_local_aggregators: Mutex<Vec<LocalAggregatorHb<S>>>,
     recycled_remove_keys: Mutex<Vec<Vec<RemoveKey>>>,
 }
@@ -209,18 +288,30 @@ where
     #[cold]
     #[must_use]
     pub fn new(options: TLSCollectorOptions<S>) -> Self {
+        let histogram_configs = if options.normalize_names {
+            lowercase_histogram_config_keys(options.histogram_configs, &options.hasher_builder)
+        } else {
+            options.histogram_configs
+        };
         let ResolvedHistogramConfigs {
             default_histogram_config,
             histogram_configs,
             pool_specs,
-            pool_count,
         } = resolve_histogram_configs(
             options.default_histogram_config,
-            options.histogram_configs,
+            histogram_configs,
             &options.hasher_builder,
         );
+        let pool_count = pool_specs.len();
+        let stats_prefix = join_stats_prefix(options.stats_prefix, options.prefix_separator);
         Self {
-            stats_prefix: options.stats_prefix,
+            type_prefixes: TypePrefixes::new(
+                &stats_prefix,
+                &options.histogram_prefix,
+                &options.count_prefix,
+                &options.gauge_prefix,
+            ),
+            stats_prefix,
             buffers: ThreadLocal::new(),
             pool_count,
             pool_specs,
@@ -229,14 +320,30 @@ where
                 pool_count,
             )),
             histogram_configs,
+            normalize_names: options.normalize_names,
+            metric_filter: MetricFilterState::new(options.metric_filter),
+            keep_zero_values: options.keep_zero_values,
+            key_idle_ttl: options.key_idle_ttl,
+            skip_tag_sort: options.skip_tag_sort,
+            gauge_mode: options.gauge_mode,
+            value_rounding: options.value_rounding,
+            created_at: Instant::now(),
+            last_flush: Mutex::new(Instant::now()),
             default_histogram_config,
             hasher_builder: options.hasher_builder,
             recycled_global_aggregators: Mutex::new(Vec::new()),
+            interrupted_global: Mutex::new(None),
             recycled_local_aggregators: Mutex::new(Vec::new()),
             recycled_remove_keys: Mutex::new(Vec::new()),
         }
     }
 
+    /// Returns how many recordings were dropped by the configured metric filter.
+    #[must_use]
+    pub fn filtered_count(&self) -> u64 {
+        self.metric_filter.filtered_count()
+    }
+
     fn get_or_create_thread_local_aggregator(&self) -> &CachePadded<Mutex<LocalAggregatorHb<S>>> {
         self.buffers.get_or(|| {
             CachePadded::new(Mutex::new(LocalAggregatorHb::with_pool_count(
@@ -247,7 +354,7 @@ where
     }
 
     #[cold]
-    fn flush_all_to_global(&self) -> GlobalAggregatorHb<S> {
+    fn flush_all_to_global(&self, idle_eviction: Option<IdleEviction>) -> GlobalAggregatorHb<S> {
         // Lock global only to swap the active generation with a fresh recycled one.
         let mut global_to_merge = {
             let mut global_guard = self.global_aggregator.lock();
@@ -258,6 +365,9 @@ where
                 .unwrap_or_else(|| global_guard.empty_like());
             std::mem::replace(&mut *global_guard, fresh_global)
         };
+        let now = Instant::now();
+        global_to_merge.rate_window =
+            now.saturating_duration_since(std::mem::replace(&mut *self.last_flush.lock(), now));
 
         // Swap each local quickly and merge into exclusive global.
         for buffer in &self.buffers {
@@ -275,12 +385,21 @@ where
                 &mut global_to_merge,
                 &self.pool_specs,
                 &mut to_remove,
+                self.gauge_mode,
             );
             to_remove.clear();
             self.recycled_remove_keys.lock().push(to_remove);
             self.recycle(local);
         }
 
+        if self.keep_zero_values {
+            carry_over_series(
+                &global_to_merge,
+                &mut self.global_aggregator.lock(),
+                idle_eviction,
+            );
+        }
+
         global_to_merge
     }
 
@@ -295,16 +414,25 @@ where
     }
 
     #[cold]
-    fn begin_drain(&self) -> TLSDrain<'_, S> {
-        let global = self.flush_all_to_global();
-        TLSDrain::new(self, global)
+    fn begin_drain(&self, budget: DrainBudget) -> TLSDrain<'_, S> {
+        let idle_eviction = self
+            .keep_zero_values
+            .then(|| IdleEviction::at(self.created_at, self.key_idle_ttl))
+            .flatten();
+        let interrupted = self.interrupted_global.lock().take();
+        let resumed = interrupted.is_some();
+        let global = interrupted.unwrap_or_else(|| self.flush_all_to_global(idle_eviction));
+        let mut drain = TLSDrain::new(self, global, idle_eviction);
+        drain.budget = budget;
+        drain.resumed = resumed;
+        drain
     }
 
     fn record_histogram(&self, metric: RylvStr<'_>, value: u64, tags: &mut [RylvStr<'_>]) {
-        if tags.len() > 1 {
+        if !self.skip_tag_sort && tags.len() > 1 {
             tags.sort_unstable();
         }
-        let lookup = build_lookup_key(metric, tags, &self.hasher_builder);
+        let lookup = build_lookup_key(metric, tags, &self.hasher_builder, !self.skip_tag_sort);
         let buffer = self.get_or_create_thread_local_aggregator();
 
         {
@@ -427,10 +555,10 @@ where
     }
 
     fn record_count_add(&self, metric: RylvStr<'_>, value: u64, tags: &mut [RylvStr<'_>]) {
-        if tags.len() > 1 {
+        if !self.skip_tag_sort && tags.len() > 1 {
             tags.sort_unstable();
         }
-        let lookup = build_lookup_key(metric, tags, &self.hasher_builder);
+        let lookup = build_lookup_key(metric, tags, &self.hasher_builder, !self.skip_tag_sort);
         let buffer = self.get_or_create_thread_local_aggregator();
         let mut aggregator = buffer.lock();
 
@@ -448,6 +576,28 @@ where
         }
     }
 
+    fn record_rate(&self, metric: RylvStr<'_>, delta: u64, tags: &mut [RylvStr<'_>]) {
+        if !self.skip_tag_sort && tags.len() > 1 {
+            tags.sort_unstable();
+        }
+        let lookup = build_lookup_key(metric, tags, &self.hasher_builder, !self.skip_tag_sort);
+        let buffer = self.get_or_create_thread_local_aggregator();
+        let mut aggregator = buffer.lock();
+
+        match aggregator.rates.entry(
+            lookup.hash,
+            |(key, _)| lookup.compare(key),
+            |(key, _)| key.hash,
+        ) {
+            Occupied(mut entry) => {
+                entry.get_mut().1 += delta;
+            }
+            Vacant(entry) => {
+                entry.insert((lookup.into_key(), delta));
+            }
+        }
+    }
+
     fn record_count_add_sorted(&self, metric: RylvStr<'_>, value: u64, tags: &SortedTags<S>) {
         let hash =
             combine_metric_tags_hash(&self.hasher_builder, metric.as_ref(), tags.tags_hash());
@@ -501,10 +651,10 @@ where
     }
 
     fn record_gauge(&self, metric: RylvStr<'_>, value: u64, tags: &mut [RylvStr<'_>]) {
-        if tags.len() > 1 {
+        if !self.skip_tag_sort && tags.len() > 1 {
             tags.sort_unstable();
         }
-        let lookup = build_lookup_key(metric, tags, &self.hasher_builder);
+        let lookup = build_lookup_key(metric, tags, &self.hasher_builder, !self.skip_tag_sort);
         let buffer = self.get_or_create_thread_local_aggregator();
         let mut aggregator = buffer.lock();
 
@@ -514,9 +664,35 @@ where
             |(key, _)| key.hash,
         ) {
             Occupied(mut entry) => {
-                let gauge = &mut entry.get_mut().1;
-                gauge.count += 1;
-                gauge.sum += value;
+                entry.get_mut().1.record(value, self.gauge_mode);
+            }
+            Vacant(entry) => {
+                entry.insert((
+                    lookup.into_key(),
+                    GaugeStateHb {
+                        sum: value,
+                        count: 1,
+                    },
+                ));
+            }
+        }
+    }
+
+    fn record_gauge_last(&self, metric: RylvStr<'_>, value: u64, tags: &mut [RylvStr<'_>]) {
+        if !self.skip_tag_sort && tags.len() > 1 {
+            tags.sort_unstable();
+        }
+        let lookup = build_lookup_key(metric, tags, &self.hasher_builder, !self.skip_tag_sort);
+        let buffer = self.get_or_create_thread_local_aggregator();
+        let mut aggregator = buffer.lock();
+
+        match aggregator.last_gauges.entry(
+            lookup.hash,
+            |(key, _)| lookup.compare(key),
+            |(key, _)| key.hash,
+        ) {
+            Occupied(mut entry) => {
+                entry.get_mut().1.record(value, GaugeMode::Last);
             }
             Vacant(entry) => {
                 entry.insert((
@@ -548,9 +724,7 @@ where
             |(key, _)| key.hash,
         ) {
             Occupied(mut entry) => {
-                let gauge = &mut entry.get_mut().1;
-                gauge.count += 1;
-                gauge.sum += value;
+                entry.get_mut().1.record(value, self.gauge_mode);
             }
             Vacant(entry) => {
                 entry.insert((
@@ -573,8 +747,7 @@ where
             .gauge
             .find_mut(prepared.hash(), |(key, _)| key.id == entry_id)
         {
-            gauge.count += 1;
-            gauge.sum += value;
+            gauge.record(value, self.gauge_mode);
             return;
         }
         match aggregator.gauge.entry(
@@ -583,9 +756,7 @@ where
             |(key, _)| key.hash,
         ) {
             Occupied(mut entry) => {
-                let gauge = &mut entry.get_mut().1;
-                gauge.count += 1;
-                gauge.sum += value;
+                entry.get_mut().1.record(value, self.gauge_mode);
             }
             Vacant(entry) => {
                 entry.insert((
@@ -607,23 +778,92 @@ pub struct TLSCollectorOptions<S = DefaultMetricHasher>
 where
     S: BuildHasher + Clone,
 {
-    /// Prefix prepended verbatim to all metric names.
+    /// Prefix prepended verbatim to all metric names, unless
+    /// `prefix_separator` is set.
     pub stats_prefix: String,
+    /// Separator joining a non-empty `stats_prefix` and the metric name, e.g.
+    /// `Some(".")` turns `app` into `app.requests`. Not doubled when
+    /// `stats_prefix` already ends with it. Defaults to `None`, which prepends
+    /// `stats_prefix` verbatim.
+    pub prefix_separator: Option<&'static str>,
+    /// Prefix appended after `stats_prefix` to aggregated histogram names, so
+    /// `app` + `timing.` emits `app.timing.request.duration.count`. Raw timings
+    /// and emergency counters only take `stats_prefix`. Defaults to empty.
+    pub histogram_prefix: String,
+    /// Prefix appended after `stats_prefix` to count names. Defaults to empty.
+    pub count_prefix: String,
+    /// Prefix appended after `stats_prefix` to gauge names. Defaults to empty.
+    pub gauge_prefix: String,
     /// Per-metric histogram configuration for custom precision settings.
     pub histogram_configs: HashMap<String, HistogramConfig, S>,
     /// Default histogram configuration when metric-specific config is absent.
     pub default_histogram_config: HistogramConfig,
     /// Hasher builder used by internal aggregation maps.
     pub hasher_builder: S,
+    /// ASCII-lowercases metric names before they become aggregation keys.
+    ///
+    /// Names containing uppercase letters are copied into an owned string on
+    /// every call. `histogram_configs` keys are lowercased as well. Defaults to
+    /// `false`.
+    pub normalize_names: bool,
+    /// Drops metrics rejected by the filter before they are hashed or aggregated,
+    /// counting them in [`TLSCollector::filtered_count`]. Defaults to `None`.
+    pub metric_filter: Option<MetricFilter>,
+    /// Keeps count and gauge series without recordings in a flush window and
+    /// emits them as `0` instead of dropping them, so a healthy `errors:0`
+    /// signal keeps reporting.
+    ///
+    /// Kept series are only evicted after `key_idle_ttl`, so only enable this
+    /// with bounded tag cardinality. Histograms are still recycled once empty.
+    /// Defaults to `false`.
+    pub keep_zero_values: bool,
+    /// Evicts series kept by `keep_zero_values` once they have seen no
+    /// recordings for this long, checked at each drain. Defaults to `None`,
+    /// which keeps them forever.
+    pub key_idle_ttl: Option<Duration>,
+    /// Looks series up without sorting the `tags` slice of each call.
+    ///
+    /// Tag sets are hashed and compared regardless of order, and only sorted
+    /// once, on a copy, when a new series is created; the caller's slice is
+    /// left untouched. Faster for call sites passing a few tags in a stable
+    /// order, slower when the same tags come in varying orders. Defaults to
+    /// `false`.
+    pub skip_tag_sort: bool,
+    /// How gauge values recorded within one flush window are combined.
+    /// Defaults to [`GaugeMode::Average`].
+    ///
+    /// With [`GaugeMode::Last`], each thread keeps its own latest value and the
+    /// flush keeps the one of the thread merged last.
+    pub gauge_mode: GaugeMode,
+    /// Rounds gauge and histogram values down to a multiple of this
+    /// granularity before they are recorded, e.g. `10` records `73` and `75`
+    /// as `70`.
+    ///
+    /// Trades precision for fewer distinct values, so histograms touch fewer
+    /// buckets. Gauges recorded with `gauge_bool` are rounded as well, so keep
+    /// it off for collectors recording flags. Counts are never rounded.
+    /// Defaults to `None`.
+    pub value_rounding: Option<NonZeroU64>,
 }
 
 impl Default for TLSCollectorOptions<DefaultMetricHasher> {
     fn default() -> Self {
         Self {
             stats_prefix: String::new(),
+            prefix_separator: None,
+            histogram_prefix: String::new(),
+            count_prefix: String::new(),
+            gauge_prefix: String::new(),
             histogram_configs: HashMap::new(),
             default_histogram_config: HistogramConfig::default(),
             hasher_builder: DefaultMetricHasher::new(),
+            normalize_names: false,
+            metric_filter: None,
+            keep_zero_values: false,
+            key_idle_ttl: None,
+            skip_tag_sort: false,
+            gauge_mode: GaugeMode::Average,
+            value_rounding: None,
         }
     }
 }
@@ -635,15 +875,24 @@ where
     type Hasher = S;
 
     #[inline]
-    fn histogram<'m, 't, TT>(&self, metric: RylvStr<'m>, value: u64, mut tags: TT)
+    fn histogram<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, mut tags: TT)
     where
         TT: AsMut<[RylvStr<'t>]>,
     {
-        self.record_histogram(metric, value, tags.as_mut());
+        let value = round_value(value, self.value_rounding);
+        let metric = metric.into();
+        if self.metric_filter.rejects(metric.as_ref()) {
+            return;
+        }
+        self.record_histogram(
+            normalize_metric_name(metric, self.normalize_names),
+            value,
+            tags.as_mut(),
+        );
     }
 
     #[inline]
-    fn count<'m, 't, TT>(&self, metric: RylvStr<'m>, tags: TT)
+    fn count<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, tags: TT)
     where
         TT: AsMut<[RylvStr<'t>]>,
     {
@@ -651,34 +900,120 @@ where
     }
 
     #[inline]
-    fn count_add<'m, 't, TT>(&self, metric: RylvStr<'m>, value: u64, mut tags: TT)
+    fn count_add<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, mut tags: TT)
     where
         TT: AsMut<[RylvStr<'t>]>,
     {
-        self.record_count_add(metric, value, tags.as_mut());
+        let metric = metric.into();
+        if self.metric_filter.rejects(metric.as_ref()) {
+            return;
+        }
+        self.record_count_add(
+            normalize_metric_name(metric, self.normalize_names),
+            value,
+            tags.as_mut(),
+        );
     }
 
     #[inline]
-    fn gauge<'m, 't, TT>(&self, metric: RylvStr<'m>, value: u64, mut tags: TT)
+    fn gauge<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, mut tags: TT)
     where
         TT: AsMut<[RylvStr<'t>]>,
     {
-        self.record_gauge(metric, value, tags.as_mut());
+        let value = round_value(value, self.value_rounding);
+        let metric = metric.into();
+        if self.metric_filter.rejects(metric.as_ref()) {
+            return;
+        }
+        self.record_gauge(
+            normalize_metric_name(metric, self.normalize_names),
+            value,
+            tags.as_mut(),
+        );
+    }
+
+    #[inline]
+    fn gauge_last<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, mut tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        let value = round_value(value, self.value_rounding);
+        let metric = metric.into();
+        if self.metric_filter.rejects(metric.as_ref()) {
+            return;
+        }
+        self.record_gauge_last(
+            normalize_metric_name(metric, self.normalize_names),
+            value,
+            tags.as_mut(),
+        );
     }
 
     #[inline]
-    fn histogram_sorted(&self, metric: RylvStr<'_>, value: u64, tags: &SortedTags<S>) {
-        self.record_histogram_sorted(metric, value, tags);
+    fn rate<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, delta: u64, mut tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        let metric = metric.into();
+        if self.metric_filter.rejects(metric.as_ref()) {
+            return;
+        }
+        self.record_rate(
+            normalize_metric_name(metric, self.normalize_names),
+            delta,
+            tags.as_mut(),
+        );
+    }
+
+    #[inline]
+    fn histogram_sorted<'m>(
+        &self,
+        metric: impl Into<RylvStr<'m>>,
+        value: u64,
+        tags: &SortedTags<S>,
+    ) {
+        let value = round_value(value, self.value_rounding);
+        let metric = metric.into();
+        if self.metric_filter.rejects(metric.as_ref()) {
+            return;
+        }
+        self.record_histogram_sorted(
+            normalize_metric_name(metric, self.normalize_names),
+            value,
+            tags,
+        );
     }
 
     #[inline]
-    fn count_add_sorted(&self, metric: RylvStr<'_>, value: u64, tags: &SortedTags<S>) {
-        self.record_count_add_sorted(metric, value, tags);
+    fn count_add_sorted<'m>(
+        &self,
+        metric: impl Into<RylvStr<'m>>,
+        value: u64,
+        tags: &SortedTags<S>,
+    ) {
+        let metric = metric.into();
+        if self.metric_filter.rejects(metric.as_ref()) {
+            return;
+        }
+        self.record_count_add_sorted(
+            normalize_metric_name(metric, self.normalize_names),
+            value,
+            tags,
+        );
     }
 
     #[inline]
-    fn gauge_sorted(&self, metric: RylvStr<'_>, value: u64, tags: &SortedTags<S>) {
-        self.record_gauge_sorted(metric, value, tags);
+    fn gauge_sorted<'m>(&self, metric: impl Into<RylvStr<'m>>, value: u64, tags: &SortedTags<S>) {
+        let value = round_value(value, self.value_rounding);
+        let metric = metric.into();
+        if self.metric_filter.rejects(metric.as_ref()) {
+            return;
+        }
+        self.record_gauge_sorted(
+            normalize_metric_name(metric, self.normalize_names),
+            value,
+            tags,
+        );
     }
 
     #[cold]
@@ -690,29 +1025,43 @@ where
     }
 
     #[cold]
-    fn prepare_metric(
+    fn prepare_metric<'m>(
         &self,
-        metric: RylvStr<'_>,
+        metric: impl Into<RylvStr<'m>>,
         tags: SortedTags<Self::Hasher>,
     ) -> PreparedMetric<Self::Hasher> {
+        let metric = metric.into();
+        let filtered = !self.metric_filter.allows(metric.as_ref());
+        let metric = normalize_metric_name(metric, self.normalize_names);
         let metric = to_static_metric(metric);
         let hash =
             combine_metric_tags_hash(&self.hasher_builder, metric.as_ref(), tags.tags_hash());
-        PreparedMetric::new(metric, tags, hash)
+        PreparedMetric::new(metric, tags, hash).with_filtered(filtered)
     }
 
     #[inline]
     fn histogram_prepared(&self, prepared: &PreparedMetric<Self::Hasher>, value: u64) {
+        let value = round_value(value, self.value_rounding);
+        if self.metric_filter.count_rejected(prepared.is_filtered()) {
+            return;
+        }
         self.record_histogram_prepared(prepared, value);
     }
 
     #[inline]
     fn count_add_prepared(&self, prepared: &PreparedMetric<Self::Hasher>, value: u64) {
+        if self.metric_filter.count_rejected(prepared.is_filtered()) {
+            return;
+        }
         self.record_count_add_prepared(prepared, value);
     }
 
     #[inline]
     fn gauge_prepared(&self, prepared: &PreparedMetric<Self::Hasher>, value: u64) {
+        let value = round_value(value, self.value_rounding);
+        if self.metric_filter.count_rejected(prepared.is_filtered()) {
+            return;
+        }
         self.record_gauge_prepared(prepared, value);
     }
 }
@@ -724,7 +1073,7 @@ where
     type Hasher = S;
 
     #[inline]
-    fn histogram<'m, 't, TT>(&self, metric: RylvStr<'m>, value: u64, tags: TT)
+    fn histogram<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, tags: TT)
     where
         TT: AsMut<[RylvStr<'t>]>,
     {
@@ -732,7 +1081,7 @@ where
     }
 
     #[inline]
-    fn count<'m, 't, TT>(&self, metric: RylvStr<'m>, tags: TT)
+    fn count<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, tags: TT)
     where
         TT: AsMut<[RylvStr<'t>]>,
     {
@@ -740,7 +1089,7 @@ where
     }
 
     #[inline]
-    fn count_add<'m, 't, TT>(&self, metric: RylvStr<'m>, value: u64, tags: TT)
+    fn count_add<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, tags: TT)
     where
         TT: AsMut<[RylvStr<'t>]>,
     {
@@ -748,7 +1097,7 @@ where
     }
 
     #[inline]
-    fn gauge<'m, 't, TT>(&self, metric: RylvStr<'m>, value: u64, tags: TT)
+    fn gauge<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, tags: TT)
     where
         TT: AsMut<[RylvStr<'t>]>,
     {
@@ -756,17 +1105,43 @@ where
     }
 
     #[inline]
-    fn histogram_sorted(&self, metric: RylvStr<'_>, value: u64, tags: &SortedTags<S>) {
+    fn gauge_last<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        (*self).gauge_last(metric, value, tags);
+    }
+
+    #[inline]
+    fn rate<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, delta: u64, tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        (*self).rate(metric, delta, tags);
+    }
+
+    #[inline]
+    fn histogram_sorted<'m>(
+        &self,
+        metric: impl Into<RylvStr<'m>>,
+        value: u64,
+        tags: &SortedTags<S>,
+    ) {
         (*self).histogram_sorted(metric, value, tags);
     }
 
     #[inline]
-    fn count_add_sorted(&self, metric: RylvStr<'_>, value: u64, tags: &SortedTags<S>) {
+    fn count_add_sorted<'m>(
+        &self,
+        metric: impl Into<RylvStr<'m>>,
+        value: u64,
+        tags: &SortedTags<S>,
+    ) {
         (*self).count_add_sorted(metric, value, tags);
     }
 
     #[inline]
-    fn gauge_sorted(&self, metric: RylvStr<'_>, value: u64, tags: &SortedTags<S>) {
+    fn gauge_sorted<'m>(&self, metric: impl Into<RylvStr<'m>>, value: u64, tags: &SortedTags<S>) {
         (*self).gauge_sorted(metric, value, tags);
     }
 
@@ -779,9 +1154,9 @@ where
     }
 
     #[cold]
-    fn prepare_metric(
+    fn prepare_metric<'m>(
         &self,
-        metric: RylvStr<'_>,
+        metric: impl Into<RylvStr<'m>>,
         tags: SortedTags<Self::Hasher>,
     ) -> PreparedMetric<Self::Hasher> {
         (*self).prepare_metric(metric, tags)
@@ -814,7 +1189,20 @@ where
 
     #[cold]
     fn try_begin_drain(&self) -> Option<Self::Drain<'_>> {
-        Some(self.begin_drain())
+        Some(self.begin_drain(DrainBudget::default()))
+    }
+
+    #[cold]
+    fn try_begin_drain_until(&self, deadline: Instant) -> Option<Self::Drain<'_>> {
+        Some(self.begin_drain(DrainBudget::until(deadline)))
+    }
+
+    fn stats_prefix(&self) -> &str {
+        self.stats_prefix.as_str()
+    }
+
+    fn has_pending_drain(&self) -> bool {
+        self.interrupted_global.lock().is_some()
     }
 }
 
@@ -829,7 +1217,20 @@ where
 
     #[cold]
     fn try_begin_drain(&self) -> Option<Self::Drain<'_>> {
-        Some((*self).begin_drain())
+        Some((*self).begin_drain(DrainBudget::default()))
+    }
+
+    #[cold]
+    fn try_begin_drain_until(&self, deadline: Instant) -> Option<Self::Drain<'_>> {
+        Some((*self).begin_drain(DrainBudget::until(deadline)))
+    }
+
+    fn stats_prefix(&self) -> &str {
+        self.stats_prefix.as_str()
+    }
+
+    fn has_pending_drain(&self) -> bool {
+        self.interrupted_global.lock().is_some()
     }
 }
 
@@ -837,6 +1238,7 @@ fn build_lookup_key<'a, S>(
     metric: RylvStr<'a>,
     tags: &'a [RylvStr<'a>],
     hasher_builder: &S,
+    sorted: bool,
 ) -> LookupKey<'a>
 where
     S: BuildHasher,
@@ -848,6 +1250,7 @@ where
         tags,
         tags_hash,
         hash,
+        sorted,
     }
 }
 
@@ -861,53 +1264,95 @@ where
     key.metric.as_ref() == prepared.metric().as_ref() && &key.tags == prepared.tags()
 }
 
-fn merge_local_aggregator_into_global_hashbrown<S>(
-    local: &mut LocalAggregatorHb<S>,
-    global: &mut GlobalAggregatorHb<S>,
-    pool_specs: &[HistogramPoolSpec],
-    to_remove: &mut Vec<RemoveKey>,
+/// Seeds the active generation with every count and gauge key of `drained` at zero,
+/// so `keep_zero_values` series are still emitted by the next drain.
+///
+/// Keys idle for longer than the `idle_eviction` TTL are not carried over.
+fn carry_over_series<S>(
+    drained: &GlobalAggregatorHb<S>,
+    active: &mut GlobalAggregatorHb<S>,
+    idle_eviction: Option<IdleEviction>,
 ) where
     S: BuildHasher + Clone,
 {
-    to_remove.clear();
-    for (key, value) in &mut local.count {
-        let val = *value;
-        if val == 0 {
-            to_remove.push(key.remove_key());
+    let is_expired = |key: &AggregatorEntryKey<S>, active: bool| {
+        idle_eviction.is_some_and(|eviction| eviction.is_expired(key, active))
+    };
+    for (key, value) in &drained.count {
+        if is_expired(key, *value != 0) {
             continue;
         }
-
-        *value = 0;
-        match global
+        match active
             .count
             .entry(key.hash, |(existing, _)| existing == key, |(k, _)| k.hash)
         {
-            Occupied(mut entry) => {
-                entry.get_mut().1 += val;
+            Occupied(entry) => entry.get().0.carry_last_active(key),
+            Vacant(entry) => {
+                entry.insert((key.clone(), 0));
+            }
+        }
+    }
+    for (drained_gauges, active_gauges) in [
+        (&drained.gauge, &mut active.gauge),
+        (&drained.last_gauges, &mut active.last_gauges),
+    ] {
+        for (key, gauge) in drained_gauges {
+            if is_expired(key, gauge.count != 0) {
+                continue;
             }
+            match active_gauges.entry(key.hash, |(existing, _)| existing == key, |(k, _)| k.hash) {
+                Occupied(entry) => entry.get().0.carry_last_active(key),
+                Vacant(entry) => {
+                    entry.insert((key.clone(), GaugeStateHb::default()));
+                }
+            }
+        }
+    }
+    for (key, delta) in &drained.rates {
+        if is_expired(key, *delta != 0) {
+            continue;
+        }
+        match active
+            .rates
+            .entry(key.hash, |(existing, _)| existing == key, |(k, _)| k.hash)
+        {
+            Occupied(entry) => entry.get().0.carry_last_active(key),
             Vacant(entry) => {
-                entry.insert((key.clone(), val));
+                entry.insert((key.clone(), 0));
             }
         }
     }
+}
 
-    remove_from_table(&mut local.count, to_remove);
+/// Returns whether `key`, without recordings in the drained window, is emitted
+/// as `0` rather than removed.
+fn keeps_idle<S>(
+    keep_zero_values: bool,
+    idle_eviction: Option<IdleEviction>,
+    key: &AggregatorEntryKey<S>,
+) -> bool
+where
+    S: BuildHasher + Clone,
+{
+    keep_zero_values && !idle_eviction.is_some_and(|eviction| eviction.is_expired(key, false))
+}
 
-    for (key, value) in &mut local.gauge {
+fn merge_gauge_table<S>(
+    local: &mut HashTable<(AggregatorEntryKey<S>, GaugeStateHb)>,
+    global: &mut HashTable<(AggregatorEntryKey<S>, GaugeStateHb)>,
+    to_remove: &mut Vec<RemoveKey>,
+    gauge_mode: GaugeMode,
+) where
+    S: BuildHasher + Clone,
+{
+    for (key, value) in &mut *local {
         if value.count == 0 {
             to_remove.push(key.remove_key());
             continue;
         }
 
-        match global
-            .gauge
-            .entry(key.hash, |(existing, _)| existing == key, |(k, _)| k.hash)
-        {
-            Occupied(mut entry) => {
-                let existing = &mut entry.get_mut().1;
-                existing.sum += value.sum;
-                existing.count += value.count;
-            }
+        match global.entry(key.hash, |(existing, _)| existing == key, |(k, _)| k.hash) {
+            Occupied(mut entry) => entry.get_mut().1.merge(*value, gauge_mode),
             Vacant(entry) => {
                 entry.insert((key.clone(), *value));
             }
@@ -917,11 +1362,33 @@ fn merge_local_aggregator_into_global_hashbrown<S>(
         value.sum = 0;
     }
 
-    remove_from_table(&mut local.gauge, to_remove);
+    remove_from_table(local, to_remove);
+}
+
+fn merge_local_aggregator_into_global_hashbrown<S>(
+    local: &mut LocalAggregatorHb<S>,
+    global: &mut GlobalAggregatorHb<S>,
+    pool_specs: &[HistogramPoolSpec],
+    to_remove: &mut Vec<RemoveKey>,
+    gauge_mode: GaugeMode,
+) where
+    S: BuildHasher + Clone,
+{
+    to_remove.clear();
+    merge_counter_table(&mut local.count, &mut global.count, to_remove);
+
+    merge_gauge_table(&mut local.gauge, &mut global.gauge, to_remove, gauge_mode);
+    merge_gauge_table(
+        &mut local.last_gauges,
+        &mut global.last_gauges,
+        to_remove,
+        GaugeMode::Last,
+    );
+    merge_counter_table(&mut local.rates, &mut global.rates, to_remove);
 
     to_remove.clear();
     for (key, local_histogram) in &mut local.histograms {
-        if local_histogram.histogram.is_empty() {
+        if local_histogram.is_empty() {
             to_remove.push(key.remove_key());
             continue;
         }
@@ -934,6 +1401,8 @@ fn merge_local_aggregator_into_global_hashbrown<S>(
                 let global_histogram = &mut entry.get_mut().1;
                 global_histogram.min = min(global_histogram.min, local_histogram.min);
                 global_histogram.max = max(global_histogram.max, local_histogram.max);
+                global_histogram.sum = global_histogram.sum.saturating_add(local_histogram.sum);
+                global_histogram.overflow_count += local_histogram.overflow_count;
                 if let Err(err) = global_histogram.histogram.add(&local_histogram.histogram) {
                     error!("Fail to merge histogram: {err}");
                 }
@@ -946,6 +1415,7 @@ fn merge_local_aggregator_into_global_hashbrown<S>(
                     pool_specs[local_histogram.pool_id].bounds,
                     local_histogram.percentiles.clone(),
                     local_histogram.emit_base_metrics,
+                    local_histogram.unit,
                 ) {
                     let owned_histogram = std::mem::replace(local_histogram, fresh_histogram);
                     entry.insert((key.clone(), owned_histogram));
@@ -965,6 +1435,33 @@ fn merge_local_aggregator_into_global_hashbrown<S>(
     });
 }
 
+/// Adds the non-zero values of `local` into `global`, dropping the idle local keys.
+fn merge_counter_table<S: BuildHasher + Clone>(
+    local: &mut HashTable<(AggregatorEntryKey<S>, u64)>,
+    global: &mut HashTable<(AggregatorEntryKey<S>, u64)>,
+    to_remove: &mut Vec<RemoveKey>,
+) {
+    for (key, value) in &mut *local {
+        let val = *value;
+        if val == 0 {
+            to_remove.push(key.remove_key());
+            continue;
+        }
+
+        *value = 0;
+        match global.entry(key.hash, |(existing, _)| existing == key, |(k, _)| k.hash) {
+            Occupied(mut entry) => {
+                entry.get_mut().1 += val;
+            }
+            Vacant(entry) => {
+                entry.insert((key.clone(), val));
+            }
+        }
+    }
+
+    remove_from_table(local, to_remove);
+}
+
 fn remove_from_table<S: BuildHasher + Clone, V>(
     table: &mut HashTable<(AggregatorEntryKey<S>, V)>,
     to_remove: &mut Vec<RemoveKey>,
@@ -991,15 +1488,23 @@ where
     S: BuildHasher + Clone + Send + Sync + 'static,
 {
     collector: &'a TLSCollector<S>,
-    prefix: &'a str,
+    prefixes: &'a TypePrefixes,
     stage: DrainStage,
     count_iter: Option<MyIterMut<'a, (AggregatorEntryKey<S>, u64)>>,
     gauge_iter: Option<MyIterMut<'a, (AggregatorEntryKey<S>, GaugeStateHb)>>,
+    // Drained by the gauge stage once `gauge_iter` is done
+    last_gauge_table: Option<*mut HashTable<(AggregatorEntryKey<S>, GaugeStateHb)>>,
+    rate_iter: Option<MyIterMut<'a, (AggregatorEntryKey<S>, u64)>>,
+    rate_window_secs: f64,
     histogram_iter: Option<MyIterMut<'a, (AggregatorEntryKey<S>, HistogramWrapper)>>,
 
     pool_histograms: &'a mut [Vec<HistogramWrapper>],
     keys_to_remove: &'a mut Vec<RemoveKey>,
     pending_histogram: Option<PendingHistogram<'a, S>>,
+    idle_eviction: Option<IdleEviction>,
+    budget: DrainBudget,
+    // Set when resuming an interrupted drain, whose zeroed series were already emitted
+    resumed: bool,
 
     // SAFETY:
     // `TLSDrain` is self-referential: the iterators and borrowed slices above point into this
@@ -1013,21 +1518,31 @@ impl<'a, S> TLSDrain<'a, S>
 where
     S: BuildHasher + Clone + Send + Sync,
 {
-    fn new(collector: &'a TLSCollector<S>, aggregator: GlobalAggregatorHb<S>) -> Self {
+    fn new(
+        collector: &'a TLSCollector<S>,
+        aggregator: GlobalAggregatorHb<S>,
+        idle_eviction: Option<IdleEviction>,
+    ) -> Self {
         let global = Box::new(aggregator);
         let global_ptr = Box::into_raw(global);
         Self {
             collector,
-            prefix: collector.stats_prefix.as_str(),
+            prefixes: &collector.type_prefixes,
             stage: DrainStage::Count,
             count_iter: Some(MyIterMut::new(unsafe { addr_of_mut!((*global_ptr).count) })),
             gauge_iter: Some(MyIterMut::new(unsafe { addr_of_mut!((*global_ptr).gauge) })),
+            last_gauge_table: Some(unsafe { addr_of_mut!((*global_ptr).last_gauges) }),
+            rate_iter: Some(MyIterMut::new(unsafe { addr_of_mut!((*global_ptr).rates) })),
+            rate_window_secs: unsafe { (*global_ptr).rate_window.as_secs_f64() },
             histogram_iter: Some(MyIterMut::new(unsafe {
                 addr_of_mut!((*global_ptr).histograms)
             })),
             pool_histograms: unsafe { &mut *addr_of_mut!((*global_ptr).pool_histograms) },
             keys_to_remove: unsafe { &mut *addr_of_mut!((*global_ptr).key_to_remove) },
             pending_histogram: None,
+            idle_eviction,
+            budget: DrainBudget::default(),
+            resumed: false,
 
             aggregator: Some(global_ptr),
         }
@@ -1035,17 +1550,23 @@ where
 
     fn emit_count_metric(&mut self) -> Option<MetricFrameRef<'a>> {
         if let Some(iter) = self.count_iter.as_mut() {
-            for entry in iter.by_ref() {
+            while !self.budget.exhausted() {
+                let Some(entry) = iter.next() else {
+                    break;
+                };
                 let key = &mut entry.0;
                 let value = entry.1;
-                if value == 0 {
+                if value == 0
+                    && (self.resumed
+                        || !keeps_idle(self.collector.keep_zero_values, self.idle_eviction, key))
+                {
                     self.keys_to_remove.push(key.remove_key());
                     continue;
                 }
 
                 // SAFETY: `AggregatorEntryKey` stores owned `'static` metric/tag data.
                 // During drain we borrow those strings for `'a`, where `'a` is bounded by the
-                // lifetime of `TLSDrain`. Non-empty count entries are not removed until the
+                // lifetime of `TLSDrain`. Emitted count entries are not removed until the
                 // current count stage finishes, and the backing `GlobalAggregatorHb` is owned by
                 // `TLSDrain`, so the borrowed strings remain valid for the yielded frame.
                 let (metric, tags) = unsafe {
@@ -1057,11 +1578,13 @@ where
 
                 entry.1 = 0;
                 return Some(MetricFrameRef {
-                    prefix: self.prefix,
+                    prefix: &self.prefixes.count,
                     metric,
                     suffix: MetricSuffix::None,
                     tags,
+                    unit: None,
                     value,
+                    float_value: None,
                     kind: MetricKind::Count,
                 });
             }
@@ -1077,19 +1600,25 @@ where
 
     fn emit_gauge_metric(&mut self) -> Option<MetricFrameRef<'a>> {
         if let Some(iter) = self.gauge_iter.as_mut() {
-            for entry in iter.by_ref() {
+            while !self.budget.exhausted() {
+                let Some(entry) = iter.next() else {
+                    break;
+                };
                 let key = &mut entry.0;
                 let gauge = &mut entry.1;
                 let count = gauge.count;
-                if count == 0 {
+                if count == 0
+                    && (self.resumed
+                        || !keeps_idle(self.collector.keep_zero_values, self.idle_eviction, key))
+                {
                     self.keys_to_remove.push(key.remove_key());
                     continue;
                 }
 
-                let value = gauge.sum / count;
+                let value = gauge.sum.checked_div(count).unwrap_or(0);
                 // SAFETY: `AggregatorEntryKey` stores owned `'static` metric/tag data.
                 // During drain we borrow those strings for `'a`, where `'a` is bounded by the
-                // lifetime of `TLSDrain`. Non-empty gauge entries are reset in place but are not
+                // lifetime of `TLSDrain`. Emitted gauge entries are reset in place but are not
                 // removed until after the gauge stage, and the backing aggregator allocation stays
                 // owned by `TLSDrain`, so the borrowed strings remain valid for the yielded frame.
                 let (metric, tags) = unsafe {
@@ -1102,11 +1631,13 @@ where
                 gauge.count = 0;
 
                 return Some(MetricFrameRef {
-                    prefix: self.prefix,
+                    prefix: &self.prefixes.gauge,
                     metric,
                     suffix: MetricSuffix::None,
                     tags,
+                    unit: None,
                     value,
+                    float_value: None,
                     kind: MetricKind::Gauge,
                 });
             }
@@ -1116,17 +1647,72 @@ where
             let table = unsafe { &mut *table };
             remove_from_table(table, self.keys_to_remove);
         }
+        if let Some(table) = self.last_gauge_table.take() {
+            self.gauge_iter = Some(MyIterMut::new(table));
+            return None;
+        }
+
+        self.stage = DrainStage::Rate;
+        None
+    }
 
+    fn emit_rate_metric(&mut self) -> Option<MetricFrameRef<'a>> {
+        if let Some(iter) = self.rate_iter.as_mut() {
+            while !self.budget.exhausted() {
+                let Some(entry) = iter.next() else {
+                    break;
+                };
+                let key = &mut entry.0;
+                let delta = entry.1;
+                if delta == 0
+                    && (self.resumed
+                        || !keeps_idle(self.collector.keep_zero_values, self.idle_eviction, key))
+                {
+                    self.keys_to_remove.push(key.remove_key());
+                    continue;
+                }
+
+                // SAFETY: `AggregatorEntryKey` stores owned `'static` metric/tag data, and
+                // emitted rate entries are not removed until the rate stage finishes, so the
+                // borrowed strings stay valid for the yielded frame like in the count stage.
+                let (metric, tags) = unsafe {
+                    (
+                        std::mem::transmute::<&str, &'a str>(key.metric.as_ref()),
+                        std::mem::transmute::<&str, &'a str>(key.tags.joined_tags()),
+                    )
+                };
+
+                entry.1 = 0;
+                return Some(MetricFrameRef {
+                    prefix: &self.prefixes.gauge,
+                    metric,
+                    suffix: MetricSuffix::None,
+                    tags,
+                    unit: None,
+                    value: 0,
+                    float_value: Some(per_second(delta, self.rate_window_secs)),
+                    kind: MetricKind::Gauge,
+                });
+            }
+        }
+
+        if let Some(table) = self.rate_iter.take().map(|iter| iter.table) {
+            let table = unsafe { &mut *table };
+            remove_from_table(table, self.keys_to_remove);
+        }
         self.stage = DrainStage::Histogram;
         None
     }
 
     fn load_next_histogram(&mut self) -> bool {
         if let Some(iter) = self.histogram_iter.as_mut() {
-            for histogram_entry in iter.by_ref() {
+            while !self.budget.exhausted() {
+                let Some(histogram_entry) = iter.next() else {
+                    break;
+                };
                 let key = &mut histogram_entry.0;
                 let histo_wrapper = &mut histogram_entry.1;
-                if histo_wrapper.histogram.is_empty() {
+                if histo_wrapper.is_empty() {
                     self.keys_to_remove.push(key.remove_key());
                     continue;
                 }
@@ -1150,6 +1736,7 @@ where
                     tags,
                     entry: histogram_entry,
                     step: 0,
+                    samples: DistributionCursor::default(),
                 };
                 self.pending_histogram = Some(pending);
                 return true;
@@ -1163,80 +1750,83 @@ where
         let mut pending = self.pending_histogram.take()?;
         loop {
             let histo_wrapper = &mut pending.entry.1;
-            let percentile_count = histo_wrapper.percentiles.len();
+            let percentile_count = histo_wrapper.emitted_percentiles();
             let frame = match pending.step {
-                0 => {
-                    pending.step += 1;
-                    if histo_wrapper.emits(HistogramBaseMetric::Count) {
-                        Some(MetricFrameRef {
-                            prefix: self.prefix,
-                            metric: pending.metric,
-                            suffix: MetricSuffix::Static(".count"),
-                            tags: pending.tags,
-                            value: histo_wrapper.histogram.len(),
-                            kind: MetricKind::Count,
-                        })
-                    } else {
-                        None
-                    }
-                }
+                // distribution samples replace every line but `.overflow_count`
+                0 => histo_wrapper
+                    .next_distribution_frame(
+                        &mut pending.samples,
+                        &self.prefixes.histogram,
+                        pending.metric,
+                        pending.tags,
+                    )
+                    .or_else(|| {
+                        pending.step += 1;
+                        histo_wrapper.base_metric_frame(
+                            HistogramBaseMetric::Count,
+                            &self.prefixes.histogram,
+                            pending.metric,
+                            pending.tags,
+                        )
+                    }),
                 1 => {
                     pending.step += 1;
-                    if histo_wrapper.emits(HistogramBaseMetric::Min) {
-                        Some(MetricFrameRef {
-                            prefix: self.prefix,
-                            metric: pending.metric,
-                            suffix: MetricSuffix::Static(".min"),
-                            tags: pending.tags,
-                            value: histo_wrapper.min,
-                            kind: MetricKind::Gauge,
-                        })
-                    } else {
-                        None
-                    }
+                    histo_wrapper.base_metric_frame(
+                        HistogramBaseMetric::Min,
+                        &self.prefixes.histogram,
+                        pending.metric,
+                        pending.tags,
+                    )
                 }
                 2 => {
                     pending.step += 1;
-                    if histo_wrapper.emits(HistogramBaseMetric::Avg) {
-                        Some(MetricFrameRef {
-                            prefix: self.prefix,
-                            metric: pending.metric,
-                            suffix: MetricSuffix::Static(".avg"),
-                            tags: pending.tags,
-                            value: histo_wrapper.histogram.value_at_quantile(0.50),
-                            kind: MetricKind::Gauge,
-                        })
-                    } else {
-                        None
-                    }
+                    histo_wrapper.base_metric_frame(
+                        HistogramBaseMetric::Avg,
+                        &self.prefixes.histogram,
+                        pending.metric,
+                        pending.tags,
+                    )
                 }
                 index if index < 3 + percentile_count => {
                     pending.step += 1;
                     let percentile_index = index - 3;
                     let percentile = histo_wrapper.percentiles[percentile_index];
                     Some(MetricFrameRef {
-                        prefix: self.prefix,
+                        prefix: &self.prefixes.histogram,
                         metric: pending.metric,
                         suffix: MetricSuffix::Percentile(percentile),
                         tags: pending.tags,
-                        value: histo_wrapper.histogram.value_at_quantile(percentile),
+                        unit: histo_wrapper.unit,
+                        value: histo_wrapper.value_at_quantile(percentile),
+                        float_value: None,
                         kind: MetricKind::Gauge,
                     })
                 }
                 index if index == 3 + percentile_count => {
                     pending.step += 1;
-                    if histo_wrapper.emits(HistogramBaseMetric::Max) {
-                        Some(MetricFrameRef {
-                            prefix: self.prefix,
-                            metric: pending.metric,
-                            suffix: MetricSuffix::Static(".max"),
-                            tags: pending.tags,
-                            value: histo_wrapper.max,
-                            kind: MetricKind::Gauge,
-                        })
-                    } else {
-                        None
-                    }
+                    histo_wrapper.base_metric_frame(
+                        HistogramBaseMetric::Max,
+                        &self.prefixes.histogram,
+                        pending.metric,
+                        pending.tags,
+                    )
+                }
+                index if index == 4 + percentile_count => {
+                    pending.step += 1;
+                    histo_wrapper.base_metric_frame(
+                        HistogramBaseMetric::Sum,
+                        &self.prefixes.histogram,
+                        pending.metric,
+                        pending.tags,
+                    )
+                }
+                index if index == 5 + percentile_count => {
+                    pending.step += 1;
+                    histo_wrapper.overflow_frame(
+                        &self.prefixes.histogram,
+                        pending.metric,
+                        pending.tags,
+                    )
                 }
                 _ => {
                     histo_wrapper.reset();
@@ -1264,7 +1854,11 @@ where
 
         if let Some(aggregator) = self.aggregator.take() {
             let agg = *unsafe { Box::from_raw(aggregator) };
-            self.collector.recycle_global(agg);
+            if self.budget.exceeded() {
+                *self.collector.interrupted_global.lock() = Some(agg);
+            } else {
+                self.collector.recycle_global(agg);
+            }
         }
     }
 }
@@ -1277,6 +1871,7 @@ where
     tags: &'a str,
     entry: &'a mut (AggregatorEntryKey<S>, HistogramWrapper),
     step: usize,
+    samples: DistributionCursor,
 }
 
 struct MyIterMut<'a, T>
@@ -1335,6 +1930,11 @@ where
                         return Some(frame);
                     }
                 }
+                DrainStage::Rate => {
+                    if let Some(frame) = self.emit_rate_metric() {
+                        return Some(frame);
+                    }
+                }
                 DrainStage::Histogram => {
                     if self.pending_histogram.is_none() && !self.load_next_histogram() {
                         if let Some(table) = self.histogram_iter.take().map(|iter| iter.table) {
@@ -1372,15 +1972,18 @@ mod tests {
         LocalAggregatorHb, TLSCollector, TLSCollectorOptions,
     };
     use crate::dogstats::aggregator::HistogramWrapper;
-    use crate::dogstats::collector::{DrainMetricCollectorTrait, MetricKind, MetricSuffix};
+    use crate::dogstats::collector::{
+        DrainMetricCollectorTrait, GaugeMode, MetricKind, MetricSuffix,
+    };
     use crate::dogstats::histogram_config::{
         resolve_histogram_configs, Bounds, HistogramBaseMetric, HistogramBaseMetrics,
-        HistogramConfig,
+        HistogramConfig, OutOfBoundsPolicy,
     };
     use crate::{MetricCollectorTrait, RylvStr};
     use hdrhistogram::Histogram;
     use std::collections::HashMap;
     use std::sync::Arc;
+    use std::time::{Duration, Instant};
 
     fn percentile_suffix(percentile: f64) -> String {
         let mut percentile_number = (percentile * 100.0).to_string();
@@ -1416,14 +2019,16 @@ mod tests {
             let metric_type = match frame.kind {
                 MetricKind::Count => "c",
                 MetricKind::Gauge => "g",
+                MetricKind::Timing => "ms",
+                MetricKind::Distribution => "d",
             };
+            let value = frame
+                .float_value
+                .map_or_else(|| frame.value.to_string(), |value| value.to_string());
             if frame.tags.is_empty() {
-                lines.push(format!("{metric}:{}|{metric_type}\n", frame.value));
+                lines.push(format!("{metric}:{value}|{metric_type}\n"));
             } else {
-                lines.push(format!(
-                    "{metric}:{}|{metric_type}|#{}\n",
-                    frame.value, frame.tags
-                ));
+                lines.push(format!("{metric}:{value}|{metric_type}|#{}\n", frame.tags));
             }
         }
         lines.sort_unstable();
@@ -1447,6 +2052,23 @@ mod tests {
         format_drained_lines::<S, _>(drain)
     }
 
+    fn drain_until_now<S>(collector: &TLSCollector<S>) -> Vec<String>
+    where
+        S: std::hash::BuildHasher + Clone + Send + Sync + 'static,
+    {
+        let drain = collector
+            .try_begin_drain_until(Instant::now())
+            .into_iter()
+            .flatten()
+            .map(|frame| unsafe {
+                std::mem::transmute::<
+                    crate::dogstats::collector::MetricFrameRef<'_>,
+                    crate::dogstats::collector::MetricFrameRef<'static>,
+                >(frame)
+            });
+        format_drained_lines::<S, _>(drain)
+    }
+
     fn assert_regular_reference_lines(lines: &[String]) {
         assert_eq!(
             lines,
@@ -1470,12 +2092,12 @@ mod tests {
         >,
         hasher: &crate::DefaultMetricHasher,
     ) {
-        let count_key = build_lookup_key(
-            RylvStr::from_static("requests"),
-            &[RylvStr::from_static("a:1")],
-            hasher,
-        )
-        .into_key_with_id(10);
+        let key = |metric: &'static str, id| {
+            let tags = [RylvStr::from_static("a:1")];
+            build_lookup_key(RylvStr::from_static(metric), &tags, hasher, true)
+                .into_key_with_id::<crate::DefaultMetricHasher>(id)
+        };
+        let count_key = key("requests", 10);
         local
             .count
             .entry(
@@ -1485,12 +2107,7 @@ mod tests {
             )
             .insert((count_key, 5));
 
-        let zero_count_key = build_lookup_key(
-            RylvStr::from_static("empty_requests"),
-            &[RylvStr::from_static("a:1")],
-            hasher,
-        )
-        .into_key_with_id(11);
+        let zero_count_key = key("empty_requests", 11);
         local
             .count
             .entry(
@@ -1500,12 +2117,7 @@ mod tests {
             )
             .insert((zero_count_key, 0));
 
-        let gauge_key = build_lookup_key(
-            RylvStr::from_static("load"),
-            &[RylvStr::from_static("a:1")],
-            hasher,
-        )
-        .into_key_with_id(12);
+        let gauge_key = key("load", 12);
         local
             .gauge
             .entry(
@@ -1515,12 +2127,7 @@ mod tests {
             )
             .insert((gauge_key, GaugeStateHb { sum: 30, count: 2 }));
 
-        let zero_gauge_key = build_lookup_key(
-            RylvStr::from_static("empty_load"),
-            &[RylvStr::from_static("a:1")],
-            hasher,
-        )
-        .into_key_with_id(13);
+        let zero_gauge_key = key("empty_load", 13);
         local
             .gauge
             .entry(
@@ -1530,12 +2137,7 @@ mod tests {
             )
             .insert((zero_gauge_key, GaugeStateHb { sum: 0, count: 0 }));
 
-        let hist_key = build_lookup_key(
-            RylvStr::from_static("latency"),
-            &[RylvStr::from_static("a:1")],
-            hasher,
-        )
-        .into_key_with_id(14);
+        let hist_key = key("latency", 14);
         let mut histogram = get_histogram_from_pool_config(
             &mut local.pool_histograms,
             &resolved.default_histogram_config,
@@ -1552,12 +2154,7 @@ mod tests {
             )
             .insert((hist_key, histogram));
 
-        let empty_hist_key = build_lookup_key(
-            RylvStr::from_static("empty_latency"),
-            &[RylvStr::from_static("a:1")],
-            hasher,
-        )
-        .into_key_with_id(15);
+        let empty_hist_key = key("empty_latency", 15);
         let empty_histogram = get_histogram_from_pool_config(
             &mut local.pool_histograms,
             &resolved.default_histogram_config,
@@ -1579,9 +2176,12 @@ mod tests {
             pool_id: 0,
             min: 10,
             max: 20,
+            sum: 30,
+            overflow_count: 0,
             histogram: Histogram::new_with_bounds(1, u64::MAX, 3).unwrap(),
             percentiles: Arc::from([0.99_f64]),
             emit_base_metrics: HistogramBaseMetrics::NONE,
+            unit: None,
         }]];
 
         let wrapper = get_histogram_from_pool(
@@ -1591,6 +2191,7 @@ mod tests {
             Bounds::default(),
             Arc::from([0.5_f64, 0.95_f64]),
             HistogramBaseMetrics::only(HistogramBaseMetric::Count),
+            Some("ms"),
         )
         .unwrap();
 
@@ -1600,6 +2201,110 @@ mod tests {
         assert_eq!(wrapper.max, 20);
         assert_eq!(wrapper.percentiles.as_ref(), &[0.5, 0.95]);
         assert!(wrapper.emits(HistogramBaseMetric::Count));
+        assert_eq!(wrapper.unit, Some("ms"));
+    }
+
+    #[test]
+    fn tls_type_prefixes_compose_after_stats_prefix() {
+        let collector = TLSCollector::new(TLSCollectorOptions {
+            stats_prefix: "myapp".to_string(),
+            prefix_separator: Some("."),
+            histogram_prefix: "timing.".to_string(),
+            count_prefix: "count.".to_string(),
+            gauge_prefix: "gauge.".to_string(),
+            ..Default::default()
+        });
+        collector.histogram(RylvStr::from_static("request.duration"), 7, []);
+        collector.count(RylvStr::from_static("requests"), []);
+        collector.gauge(RylvStr::from_static("queue"), 3, []);
+
+        let lines = drain_metrics_now(&collector);
+        assert!(lines.contains(&"myapp.timing.request.duration.count:1|c\n".to_string()));
+        assert!(lines.contains(&"myapp.timing.request.duration.max:7|g\n".to_string()));
+        assert!(lines.contains(&"myapp.count.requests:1|c\n".to_string()));
+        assert!(lines.contains(&"myapp.gauge.queue:3|g\n".to_string()));
+        assert!(lines.iter().all(|line| line.starts_with("myapp.timing.")
+            || line.starts_with("myapp.count.")
+            || line.starts_with("myapp.gauge.")));
+    }
+
+    #[test]
+    fn tls_out_of_bounds_policy_handles_values_above_high() {
+        let collector = TLSCollector::new(TLSCollectorOptions {
+            histogram_configs: HashMap::from([
+                (
+                    "clamped".to_string(),
+                    HistogramConfig::default()
+                        .with_bounds(1, 1_000)
+                        .unwrap()
+                        .with_out_of_bounds(OutOfBoundsPolicy::Clamp),
+                ),
+                (
+                    "counted".to_string(),
+                    HistogramConfig::default()
+                        .with_bounds(1, 1_000)
+                        .unwrap()
+                        .with_out_of_bounds(OutOfBoundsPolicy::CountOverflow),
+                ),
+            ]),
+            default_histogram_config: HistogramConfig::default().with_bounds(1, 1_000).unwrap(),
+            ..Default::default()
+        });
+        for metric in ["dropped", "clamped", "counted"] {
+            collector.histogram(RylvStr::from_static(metric), 10, []);
+            collector.histogram(RylvStr::from_static(metric), 5_000, []);
+        }
+
+        let lines = drain_metrics_now(&collector);
+        assert!(lines.contains(&"dropped.count:1|c\n".to_string()));
+        assert!(lines.contains(&"dropped.max:10|g\n".to_string()));
+        assert!(lines.contains(&"clamped.count:2|c\n".to_string()));
+        assert!(lines.contains(&"clamped.max:1000|g\n".to_string()));
+        assert!(lines.contains(&"counted.count:1|c\n".to_string()));
+        assert!(lines.contains(&"counted.overflow_count:1|c\n".to_string()));
+        assert_eq!(
+            lines
+                .iter()
+                .filter(|line| line.contains(".overflow_count"))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn tls_drain_until_deadline_resumes_without_emitting_zeroed_series_again() {
+        let collector = TLSCollector::new(TLSCollectorOptions {
+            keep_zero_values: true,
+            ..Default::default()
+        });
+        let series = 5_000;
+        for id in 0..series {
+            let tag = format!("id:{id}");
+            collector.count(
+                RylvStr::from_static("requests"),
+                &mut [RylvStr::from(tag.as_str())],
+            );
+        }
+
+        let first = drain_until_now(&collector);
+        assert!(!first.is_empty() && first.len() < series);
+        assert!(collector.has_pending_drain());
+
+        let mut lines = first;
+        while collector.has_pending_drain() {
+            lines.extend(drain_until_now(&collector));
+        }
+        assert_eq!(lines.len(), series);
+        assert!(lines
+            .iter()
+            .all(|line| line.starts_with("requests:1|c|#id:")));
+
+        // series kept by `keep_zero_values` report `0` from the next window on
+        let next = drain_metrics_now(&collector);
+        assert_eq!(next.len(), series);
+        assert!(next
+            .iter()
+            .all(|line| line.starts_with("requests:0|c|#id:")));
     }
 
     #[test]
@@ -1640,6 +2345,108 @@ mod tests {
         assert!(drain_metrics_now(&collector).is_empty());
     }
 
+    #[test]
+    fn tls_gauge_last_keeps_the_last_value_of_the_window() {
+        let collector = TLSCollector::new(TLSCollectorOptions::default());
+        for value in [10, 30, 20] {
+            collector.gauge_last(RylvStr::from_static("temperature"), value, &mut []);
+            collector.gauge(RylvStr::from_static("load"), value, &mut []);
+        }
+        assert_eq!(
+            drain_metrics_now(&collector),
+            vec!["load:20|g\n", "temperature:20|g\n"]
+        );
+
+        collector.gauge_last(RylvStr::from_static("temperature"), 7, &mut []);
+        assert_eq!(drain_metrics_now(&collector), vec!["temperature:7|g\n"]);
+        assert!(drain_metrics_now(&collector).is_empty());
+    }
+
+    #[test]
+    fn tls_skip_tag_sort_matches_tags_in_any_order() {
+        let collector = TLSCollector::new(TLSCollectorOptions {
+            skip_tag_sort: true,
+            ..Default::default()
+        });
+        let sorted = collector
+            .prepare_sorted_tags([RylvStr::from_static("a:1"), RylvStr::from_static("b:2")]);
+
+        let mut tags = [RylvStr::from_static("b:2"), RylvStr::from_static("a:1")];
+        collector.count_add(RylvStr::from_static("requests"), 1, &mut tags);
+        assert_eq!(tags[0].as_ref(), "b:2");
+        collector.count_add(
+            RylvStr::from_static("requests"),
+            2,
+            &mut [RylvStr::from_static("a:1"), RylvStr::from_static("b:2")],
+        );
+        collector.count_add_sorted(RylvStr::from_static("requests"), 4, &sorted);
+
+        assert_eq!(
+            drain_metrics_now(&collector),
+            vec!["requests:7|c|#a:1,b:2\n".to_string()]
+        );
+    }
+
+    #[test]
+    fn tls_rate_is_divided_by_the_time_since_the_previous_flush() {
+        let collector = TLSCollector::new(TLSCollectorOptions::default());
+        *collector.last_flush.lock() = Instant::now().checked_sub(Duration::from_secs(2)).unwrap();
+
+        let mut tags = [RylvStr::from_static("env:test")];
+        collector.rate(RylvStr::from_static("bytes"), 200, &mut tags);
+        collector.rate(RylvStr::from_static("bytes"), 100, &mut tags);
+
+        let lines = drain_metrics_now(&collector);
+        assert_eq!(lines.len(), 1);
+        let rate = lines[0]
+            .strip_prefix("bytes:")
+            .and_then(|line| line.strip_suffix("|g|#env:test\n"))
+            .and_then(|value| value.parse::<f64>().ok())
+            .unwrap();
+        // a little over the 2s window by the time it drains
+        assert!((140.0..=150.0).contains(&rate), "unexpected rate {rate}");
+        assert!(drain_metrics_now(&collector).is_empty());
+    }
+
+    #[test]
+    fn tls_rate_below_one_per_second_keeps_its_fraction() {
+        let collector = TLSCollector::new(TLSCollectorOptions::default());
+        *collector.last_flush.lock() = Instant::now().checked_sub(Duration::from_secs(4)).unwrap();
+
+        collector.rate(RylvStr::from_static("errors"), 1, &mut []);
+
+        let lines = drain_metrics_now(&collector);
+        assert_eq!(lines.len(), 1);
+        let rate = lines[0]
+            .strip_prefix("errors:")
+            .and_then(|line| line.strip_suffix("|g\n"))
+            .and_then(|value| value.parse::<f64>().ok())
+            .unwrap();
+        // a little over the 4s window by the time it drains
+        assert!((0.2..=0.25).contains(&rate), "unexpected rate {rate}");
+    }
+
+    #[test]
+    fn tls_gauge_bool_in_last_mode_emits_the_latest_state() {
+        let collector = TLSCollector::new(TLSCollectorOptions {
+            gauge_mode: GaugeMode::Last,
+            ..Default::default()
+        });
+        let mut tags = [RylvStr::from_static("flag:beta")];
+        collector.gauge_bool(RylvStr::from_static("feature.enabled"), true, &mut tags);
+        collector.gauge_bool(RylvStr::from_static("feature.enabled"), false, &mut tags);
+        assert_eq!(
+            drain_metrics_now(&collector),
+            vec!["feature.enabled:0|g|#flag:beta\n".to_string()]
+        );
+
+        collector.gauge_bool(RylvStr::from_static("feature.enabled"), true, &mut tags);
+        assert_eq!(
+            drain_metrics_now(&collector),
+            vec!["feature.enabled:1|g|#flag:beta\n".to_string()]
+        );
+    }
+
     #[test]
     fn tls_collector_drain_clears_metrics_on_second_drain() {
         let collector = TLSCollector::new(TLSCollectorOptions {
@@ -1720,8 +2527,8 @@ mod tests {
             HashMap::with_hasher(hasher.clone()),
             &hasher,
         );
-        let mut local = LocalAggregatorHb::with_pool_count(&hasher, resolved.pool_count);
-        let mut global = GlobalAggregatorHb::with_pool_count(&hasher, resolved.pool_count);
+        let mut local = LocalAggregatorHb::with_pool_count(&hasher, resolved.pool_specs.len());
+        let mut global = GlobalAggregatorHb::with_pool_count(&hasher, resolved.pool_specs.len());
         let mut to_remove = Vec::new();
 
         insert_local_entries(&mut local, &resolved, &hasher);
@@ -1730,6 +2537,7 @@ mod tests {
             &mut global,
             &resolved.pool_specs,
             &mut to_remove,
+            GaugeMode::Average,
         );
 
         assert_eq!(global.count.len(), 1);
@@ -1756,6 +2564,7 @@ mod tests {
             &mut global,
             &resolved.pool_specs,
             &mut to_remove,
+            GaugeMode::Average,
         );
 
         assert_eq!(global.count.iter().next().unwrap().1, 5);
@@ -1,9 +1,13 @@
 use std::{
+    env::VarError,
     hash::BuildHasher,
-    net::SocketAddr,
-    sync::Arc,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
     thread::{spawn, JoinHandle},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use crate::{
@@ -11,17 +15,23 @@ use crate::{
 };
 
 #[cfg(feature = "custom_writer")]
-use crate::StatsWriterTrait;
+use crate::{PacketHook, StatsWriterTrait};
 
-use super::collector::DrainMetricCollectorTrait;
-use super::job::initialize_job;
-use super::writer::UdpSocketWriter;
-use crate::MetricResult;
-use crossbeam::channel::{unbounded, Sender};
+use super::collector::{DrainMetricCollectorTrait, Snapshot};
+use super::emergency_counter::{EmergencyCounterId, EmergencyCounters, EmergencySeries};
+use super::job::{
+    initialize_job, DerivedMetrics, DestinationRefresh, FlushProgress, JobCommand, JobOptions,
+    RawTiming, SuffixTemplate,
+};
+use super::socket_options::SocketOptions;
+use super::sorted_tags::{build_joined_tags, to_static_metric};
+use super::writer::{NameTransform, TagSectionStyle, UdpSocketWriter, WireFormat};
+use crate::{MetricResult, MetricsError};
+use crossbeam::channel::{bounded, unbounded, Receiver, RecvTimeoutError, Sender};
+use crossbeam::queue::SegQueue;
 #[cfg(target_os = "linux")]
 use rustix::net::SocketAddrAny;
-use std::net::UdpSocket;
-use tracing::error;
+use tracing::{error, warn};
 
 /// Specifies the UDP writer backend to use for sending metrics.
 ///
@@ -39,6 +49,143 @@ pub enum StatsWriterType {
     /// User-provided writer implementation.
     #[cfg(feature = "custom_writer")]
     Custom(Box<dyn StatsWriterTrait + Send + Sync + 'static>),
+    /// `Simple` writer that passes every assembled packet through
+    /// [`PacketHook::on_packet_complete`] before sending it, e.g. to sign it.
+    #[cfg(feature = "custom_writer")]
+    PacketHook {
+        /// Hook called with every packet.
+        hook: Box<dyn PacketHook>,
+        /// Most bytes the hook adds to a packet, taken out of
+        /// [`MetricCollectorOptions::max_udp_packet_size`] when assembling
+        /// lines so the hooked packets still fit in it.
+        max_overhead: u16,
+    },
+    /// Best writer available on the running platform for the given preference,
+    /// resolved by [`MetricCollector::new`]. Lets portable code pick a batch
+    /// writer without `cfg` branches.
+    Preferred(WriterPreference),
+}
+
+/// Platform-independent writer choice for [`StatsWriterType::Preferred`].
+///
+/// The batch writer is `LinuxBatch` on Linux and `AppleBatch` on macOS; other
+/// platforms always use `Simple`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WriterPreference {
+    /// Batch writer when the platform has one and
+    /// [`MetricCollectorOptions::max_udp_batch_size`] is greater than 1,
+    /// otherwise `Simple`.
+    Auto,
+    /// Batch writer whenever the platform has one, otherwise `Simple`.
+    PreferBatch,
+    /// Always `Simple`.
+    ForceSimple,
+}
+
+impl WriterPreference {
+    /// Returns the concrete writer type chosen for the running platform.
+    #[must_use]
+    pub const fn resolve(self, max_udp_batch_size: u32) -> StatsWriterType {
+        let batch = match self {
+            Self::Auto => max_udp_batch_size > 1,
+            Self::PreferBatch => true,
+            Self::ForceSimple => false,
+        };
+        if batch {
+            #[cfg(target_os = "linux")]
+            return StatsWriterType::LinuxBatch;
+            #[cfg(target_vendor = "apple")]
+            return StatsWriterType::AppleBatch;
+        }
+        StatsWriterType::Simple
+    }
+}
+
+/// Address family used when a destination host name resolves to both, see
+/// [`MetricCollectorOptions::address_preference`].
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum AddressPreference {
+    /// First IPv4 address, falling back to the first IPv6 one.
+    #[default]
+    PreferIpv4,
+    /// First IPv6 address, falling back to the first IPv4 one.
+    PreferIpv6,
+}
+
+impl AddressPreference {
+    /// Picks the address of the preferred family out of `addrs`, else the first one.
+    fn select(self, addrs: impl IntoIterator<Item = SocketAddr>) -> Option<SocketAddr> {
+        let mut fallback = None;
+        for addr in addrs {
+            if addr.is_ipv4() == (self == Self::PreferIpv4) {
+                return Some(addr);
+            }
+            fallback = fallback.or(Some(addr));
+        }
+        fallback
+    }
+}
+
+/// Writer a [`MetricCollector`] sends with, see [`MetricCollector::active_writer_kind`].
+///
+/// Unlike [`StatsWriterType`], every variant exists on every platform.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StatsWriterKind {
+    /// [`StatsWriterType::Simple`].
+    Simple,
+    /// `StatsWriterType::LinuxBatch`.
+    LinuxBatch,
+    /// `StatsWriterType::AppleBatch`.
+    AppleBatch,
+    /// `StatsWriterType::Custom`.
+    Custom,
+    /// `StatsWriterType::PacketHook`.
+    PacketHook,
+}
+
+/// Outcome of [`MetricCollector::try_shutdown`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// The final flush finished in time and the writer accepted and flushed
+    /// every line of it.
+    pub flushed_cleanly: bool,
+    /// Lines of the final flush that were not sent: rejected by the writer,
+    /// lost by a failed flush, or still buffered when the timeout was hit. On
+    /// timeout, lines the flush thread had not reached yet are not counted.
+    pub pending_metrics: usize,
+    /// [`MetricCollectorOptions::shutdown_timeout`] elapsed before the flush
+    /// thread finished.
+    pub timed_out: bool,
+}
+
+impl StatsWriterType {
+    /// Resolves [`Self::Preferred`] to a concrete writer type.
+    // only `const` without the boxed `Custom` writer, which cannot be dropped in a `const fn`
+    #[cfg_attr(not(feature = "custom_writer"), allow(clippy::missing_const_for_fn))]
+    fn resolve(self, max_udp_batch_size: u32) -> Self {
+        match self {
+            Self::Preferred(preference) => preference.resolve(max_udp_batch_size),
+            writer_type => writer_type,
+        }
+    }
+
+    /// Returns the writer kind this type sends with, resolving [`Self::Preferred`].
+    pub(crate) fn kind(&self, max_udp_batch_size: u32) -> StatsWriterKind {
+        match self {
+            #[cfg(target_os = "linux")]
+            Self::LinuxBatch => StatsWriterKind::LinuxBatch,
+            Self::Simple => StatsWriterKind::Simple,
+            #[cfg(target_vendor = "apple")]
+            Self::AppleBatch => StatsWriterKind::AppleBatch,
+            #[cfg(feature = "custom_writer")]
+            Self::Custom(_) => StatsWriterKind::Custom,
+            #[cfg(feature = "custom_writer")]
+            Self::PacketHook { .. } => StatsWriterKind::PacketHook,
+            Self::Preferred(preference) => preference
+                .resolve(max_udp_batch_size)
+                .kind(max_udp_batch_size),
+        }
+    }
 }
 
 impl std::fmt::Debug for StatsWriterType {
@@ -51,6 +198,11 @@ impl std::fmt::Debug for StatsWriterType {
             Self::AppleBatch => write!(f, "AppleBatch"),
             #[cfg(feature = "custom_writer")]
             Self::Custom(_) => write!(f, "Custom(...)"),
+            #[cfg(feature = "custom_writer")]
+            Self::PacketHook { max_overhead, .. } => {
+                write!(f, "PacketHook {{ max_overhead: {max_overhead}, .. }}")
+            }
+            Self::Preferred(preference) => write!(f, "Preferred({preference:?})"),
         }
     }
 }
@@ -69,8 +221,103 @@ pub struct MetricCollectorOptions {
     pub max_udp_batch_size: u32,
     /// How often to flush aggregated metrics to the server.
     pub flush_interval: Duration,
+    /// Randomizes every flush interval within `[flush_interval - flush_jitter,
+    /// flush_interval + flush_jitter]` so instances started together do not flush
+    /// in lockstep. Capped at `flush_interval`. Defaults to zero (fixed interval).
+    pub flush_jitter: Duration,
     /// The UDP writer backend to use.
     pub writer_type: StatsWriterType,
+    /// Connects the UDP socket to the destination so datagrams are sent without
+    /// an address on every call. If `connect` fails, the collector logs a warning
+    /// and falls back to addressed sends.
+    pub connect_socket: bool,
+    /// Socket-level tuning such as `SO_SNDBUF`. Non-default values require the
+    /// `socket2` feature.
+    pub socket_options: SocketOptions,
+    /// Container or pod id sent as a `dd.internal.entity_id` tag on every metric,
+    /// which the Datadog agent uses for origin detection.
+    pub entity_id: Option<String>,
+    /// Restarts the flush loop when it panics instead of letting the flush thread
+    /// die. Each restart is logged, counted in
+    /// [`MetricCollector::flush_thread_restarts`] and recorded as a
+    /// `rylv_metrics.flush_thread.restart` count; metrics of the aborted flush are
+    /// lost. The restarted loop keeps the socket and the writer, whose buffered
+    /// lines are discarded. Defaults to `true`.
+    pub restart_on_panic: bool,
+    /// Number of counters that can be registered with
+    /// [`MetricCollector::register_emergency_counter`]. The table is allocated
+    /// up front. Defaults to `16`.
+    pub emergency_counter_capacity: usize,
+    /// How lines without tags render their tag section. Defaults to
+    /// [`TagSectionStyle::Omit`], the format Datadog expects; other `StatsD`
+    /// receivers may need [`TagSectionStyle::Always`].
+    pub tag_section_style: TagSectionStyle,
+    /// Line format sent to the receiver. Defaults to [`WireFormat::DogStatsD`];
+    /// [`WireFormat::PlainStatsD`] folds tag values into the metric name for
+    /// receivers without tag support and never writes a `|#` section, whatever
+    /// [`Self::tag_section_style`] says.
+    pub wire_format: WireFormat,
+    /// Rewrites every metric name on the flush thread before it is written,
+    /// e.g. to add a tenant prefix decided at runtime. It sees the full name,
+    /// prefixes and suffixes included, and runs before [`WireFormat::PlainStatsD`]
+    /// folds tag values in. Defaults to `None`.
+    pub name_transform: Option<NameTransform>,
+    /// Replaces every occurrence of the first character of the pair with the
+    /// second in metric names on the flush thread, e.g. `Some(('.', '_'))`
+    /// sends `request.latency.count` as `request_latency_count` to backends
+    /// using another namespace separator. Applies after
+    /// [`Self::name_transform`], to the full name, and never to tags. The
+    /// replacement cannot be a character of the wire format (`:`, `|`, `#`,
+    /// `,` or a newline). Defaults to `None`.
+    pub name_separator_replace: Option<(char, char)>,
+    /// Longest time dropping the collector or [`MetricCollector::try_shutdown`]
+    /// waits for the final flush. Once it elapses the flush thread is detached
+    /// and its remaining metrics are lost. Defaults to `None` (wait until done).
+    pub shutdown_timeout: Option<Duration>,
+    /// Soft time budget of one flush. Once it is spent the flush stops between
+    /// two series and the next flush resumes the rest before newer metrics, so
+    /// a huge flush cannot delay shutdown or the next tick by seconds. Only
+    /// collectors overriding
+    /// [`DrainMetricCollectorTrait::try_begin_drain_until`] honor it. Defaults
+    /// to `None` (no budget).
+    pub max_flush_duration: Option<Duration>,
+    /// Name of a tag carrying a sequence number, e.g. `Some("seq")` appends
+    /// `seq:<n>` to every line. The number starts at `0` and grows by one per
+    /// flush that writes lines, so every packet of a flush carries the same
+    /// value and a receiver can spot flushes that never arrived. Ignored with
+    /// [`WireFormat::PlainStatsD`]. Defaults to `None`.
+    pub packet_sequence_tag: Option<&'static str>,
+    /// Computes extra metrics from the aggregates of every flush, e.g. ratios
+    /// of two counters, and sends them in the same flush. See
+    /// [`DerivedMetrics`]. Defaults to `None`.
+    pub derived_metrics: Option<DerivedMetrics>,
+    /// Address family picked by [`MetricCollector::connect`] and
+    /// [`Self::resolve_destination`] when the host resolves to both. Defaults
+    /// to [`AddressPreference::PreferIpv4`].
+    pub address_preference: AddressPreference,
+    /// How often the flush thread resolves again the host name given to
+    /// [`MetricCollector::connect`], moving to the new address when it
+    /// changed, e.g. after the agent pod of a Kubernetes `DaemonSet` restarts.
+    /// Lookups run between flushes on the flush thread; a failed lookup keeps
+    /// the current address. [`MetricCollector::set_destination`] stops it.
+    /// Ignored by the other constructors. Defaults to `None` (resolve once).
+    pub resolve_interval: Option<Duration>,
+    /// Threads sending the packets of a flush. With `1` the flush thread sends
+    /// them itself; with more, the flush thread only formats lines into packets
+    /// and `writer_threads` background threads send them, so formatting and
+    /// the send syscalls overlap on large flushes. Lines are copied into owned
+    /// packets to cross threads. Only the built-in UDP writers use it; custom
+    /// writers and packet hooks ignore it. Defaults to `1`.
+    pub writer_threads: usize,
+    /// How many of the latest error and warning messages of the flush thread,
+    /// such as failed sends, [`MetricCollector::recent_errors`] keeps so a
+    /// health endpoint can show why metrics are not flowing. Defaults to `0`,
+    /// which keeps none and never formats them.
+    pub recent_errors_capacity: usize,
+    /// Most [`MetricCollector::timing_raw`] samples kept until the next flush.
+    /// Once reached, further samples are dropped and counted by
+    /// [`MetricCollector::raw_timing_drops`]. Defaults to `65536`.
+    pub max_pending_raw_timings: usize,
 }
 
 impl Default for MetricCollectorOptions {
@@ -79,9 +326,240 @@ impl Default for MetricCollectorOptions {
             max_udp_packet_size: 1432,
             max_udp_batch_size: 10,
             flush_interval: Duration::from_secs(10),
+            flush_jitter: Duration::ZERO,
             writer_type: DEFAULT_STATS_WRITER_TYPE,
+            connect_socket: true,
+            socket_options: SocketOptions::default(),
+            entity_id: None,
+            restart_on_panic: true,
+            emergency_counter_capacity: 16,
+            tag_section_style: TagSectionStyle::Omit,
+            wire_format: WireFormat::DogStatsD,
+            name_transform: None,
+            name_separator_replace: None,
+            shutdown_timeout: None,
+            max_flush_duration: None,
+            packet_sequence_tag: None,
+            derived_metrics: None,
+            address_preference: AddressPreference::PreferIpv4,
+            resolve_interval: None,
+            writer_threads: 1,
+            recent_errors_capacity: 0,
+            max_pending_raw_timings: 65_536,
+        }
+    }
+}
+
+impl MetricCollectorOptions {
+    /// Default agent address used when `DD_AGENT_HOST`/`DD_DOGSTATSD_PORT` are unset.
+    pub const DEFAULT_DESTINATION: SocketAddr =
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), DEFAULT_DOGSTATSD_PORT);
+
+    /// Options for a Datadog agent on the same host: 1432-byte packets, which
+    /// fit the loopback and most network MTUs, batches of up to 10 packets and
+    /// a 10 s flush matching the agent's own interval. The batch writer is
+    /// used where the platform has one.
+    #[must_use]
+    pub fn datadog_agent_localhost() -> Self {
+        Self {
+            max_udp_packet_size: 1432,
+            max_udp_batch_size: 10,
+            flush_interval: Duration::from_secs(10),
+            writer_type: StatsWriterType::Preferred(WriterPreference::Auto),
+            ..Default::default()
+        }
+    }
+
+    /// Options for processes recording many series: batches of up to 64
+    /// packets sent with the platform batch writer and a 1 s flush, so each
+    /// flush stays small and reaches the agent with few syscalls. Costs more
+    /// flush thread wakeups than the other presets.
+    #[must_use]
+    pub fn high_throughput() -> Self {
+        Self {
+            max_udp_packet_size: 1432,
+            max_udp_batch_size: 64,
+            flush_interval: Duration::from_secs(1),
+            writer_type: StatsWriterType::Preferred(WriterPreference::PreferBatch),
+            ..Default::default()
         }
     }
+
+    /// Options for processes where metrics should cost as little as possible,
+    /// e.g. sidecars and CLI tools: a 30 s flush and the `Simple` writer
+    /// without batching. Values reach the agent later, and a crash loses up
+    /// to 30 s of them.
+    #[must_use]
+    pub fn low_overhead() -> Self {
+        Self {
+            max_udp_packet_size: 1432,
+            max_udp_batch_size: 1,
+            flush_interval: Duration::from_secs(30),
+            writer_type: StatsWriterType::Simple,
+            ..Default::default()
+        }
+    }
+
+    /// Builds default options, taking [`Self::entity_id`] from `DD_ENTITY_ID`
+    /// as Datadog client libraries do.
+    ///
+    /// # Errors
+    /// Returns an error if `DD_ENTITY_ID` is not valid Unicode.
+    pub fn from_env() -> MetricResult<Self> {
+        Self::from_env_with(|name| std::env::var(name))
+    }
+
+    /// Like [`Self::from_env`], reading variables through `lookup` instead of
+    /// the process environment.
+    ///
+    /// # Errors
+    /// Returns an error if `lookup` reports `DD_ENTITY_ID` as not valid Unicode.
+    pub fn from_env_with(lookup: impl Fn(&str) -> Result<String, VarError>) -> MetricResult<Self> {
+        Ok(Self {
+            entity_id: env_var(&lookup, "DD_ENTITY_ID")?,
+            ..Default::default()
+        })
+    }
+
+    /// Resolves the agent address from `DD_AGENT_HOST` and `DD_DOGSTATSD_PORT`,
+    /// falling back to [`Self::DEFAULT_DESTINATION`] for unset variables.
+    ///
+    /// `DD_AGENT_HOST` may be an IP address or a host name.
+    ///
+    /// # Errors
+    /// Returns an error if a variable is not valid Unicode, the port is not a
+    /// number, or the host cannot be resolved.
+    pub fn destination_from_env() -> MetricResult<SocketAddr> {
+        Self::destination_from_env_with(|name| std::env::var(name))
+    }
+
+    /// Like [`Self::destination_from_env`], reading variables through `lookup`
+    /// instead of the process environment.
+    ///
+    /// # Errors
+    /// Returns an error if a variable is not valid Unicode, the port is not a
+    /// number, or the host cannot be resolved.
+    pub fn destination_from_env_with(
+        lookup: impl Fn(&str) -> Result<String, VarError>,
+    ) -> MetricResult<SocketAddr> {
+        let port = match env_var(&lookup, "DD_DOGSTATSD_PORT")? {
+            Some(port) => port.parse::<u16>().map_err(|err| {
+                MetricsError::from(format!("Invalid DD_DOGSTATSD_PORT '{port}': {err}"))
+            })?,
+            None => DEFAULT_DOGSTATSD_PORT,
+        };
+        let Some(host) = env_var(&lookup, "DD_AGENT_HOST")? else {
+            return Ok(SocketAddr::new(Self::DEFAULT_DESTINATION.ip(), port));
+        };
+        resolve_host(&host, port, AddressPreference::default())
+    }
+
+    /// Resolves `host`, an IP address or a host name, into the address of the
+    /// agent listening on `port`, honoring [`Self::address_preference`].
+    ///
+    /// # Errors
+    /// Returns [`MetricsError::Resolve`] if the lookup fails or returns no address.
+    pub fn resolve_destination(&self, host: &str, port: u16) -> MetricResult<SocketAddr> {
+        resolve_host(host, port, self.address_preference)
+    }
+}
+
+/// Parses `host` as an IP address, optionally in brackets, or looks it up in DNS.
+fn resolve_host(host: &str, port: u16, preference: AddressPreference) -> MetricResult<SocketAddr> {
+    let host = host.trim();
+    if let Ok(ip) = host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+    {
+        return Ok(SocketAddr::new(ip, port));
+    }
+    let resolve_error = |reason: String| MetricsError::Resolve {
+        host: host.to_owned(),
+        port,
+        reason,
+    };
+    let addrs = (host, port)
+        .to_socket_addrs()
+        .map_err(|err| resolve_error(err.to_string()))?;
+    preference
+        .select(addrs)
+        .ok_or_else(|| resolve_error("no address returned".to_owned()))
+}
+
+/// Unspecified address of the family of `destination`, on an ephemeral port.
+const fn ephemeral_bind_addr(destination: SocketAddr) -> SocketAddr {
+    let bind_ip = if destination.is_ipv6() {
+        IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+    } else {
+        IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+    };
+    SocketAddr::new(bind_ip, 0)
+}
+
+const DEFAULT_DOGSTATSD_PORT: u16 = 8125;
+
+/// Ethernet MTU, the largest datagram most networks carry without fragmenting.
+const ETHERNET_MTU: u16 = 1500;
+
+/// Connects `sock` to `destination`, returning whether it succeeded; on
+/// failure the collector falls back to unconnected sends.
+fn connect_or_warn(sock: &UdpSocket, destination: SocketAddr) -> bool {
+    match sock.connect(destination) {
+        Ok(()) => true,
+        Err(err) => {
+            warn!("Fail to connect UDP socket to {destination}, using unconnected sends: {err}");
+            false
+        }
+    }
+}
+
+/// Returns the warning logged at startup when packets of `max_udp_packet_size`
+/// bytes would be IP-fragmented on the way to `destination`.
+///
+/// Fragmented datagrams are often dropped by the network or the agent without
+/// a trace, but jumbo frames make larger packets valid, so this only warns.
+/// Loopback destinations are exempt.
+fn oversized_packet_warning(max_udp_packet_size: u16, destination: SocketAddr) -> Option<String> {
+    let headers = if destination.is_ipv6() {
+        40 + 8
+    } else {
+        20 + 8
+    };
+    let max_payload = ETHERNET_MTU - headers;
+    (max_udp_packet_size > max_payload && !destination.ip().is_loopback()).then(|| {
+        format!(
+            "max_udp_packet_size {max_udp_packet_size} exceeds the {max_payload} bytes that fit \
+             a {ETHERNET_MTU}-byte MTU to {destination}; packets may be fragmented and dropped, \
+             1432 is recommended"
+        )
+    })
+}
+
+/// Rejects a [`MetricCollectorOptions::name_separator_replace`] replacement
+/// that would be read as part of the line syntax.
+fn check_name_separator_replace(replace: Option<(char, char)>) -> MetricResult<()> {
+    match replace {
+        Some((_, to @ (':' | '|' | '#' | ',' | '\n'))) => Err(format!(
+            "name_separator_replace cannot replace with {to:?}, which is part of the wire format"
+        )
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+/// Reads an environment variable through `lookup`, treating unset and blank
+/// values alike.
+fn env_var(
+    lookup: impl Fn(&str) -> Result<String, VarError>,
+    name: &str,
+) -> MetricResult<Option<String>> {
+    match lookup(name) {
+        Ok(value) if value.trim().is_empty() => Ok(None),
+        Ok(value) => Ok(Some(value.trim().to_owned())),
+        Err(VarError::NotPresent) => Ok(None),
+        Err(VarError::NotUnicode(_)) => Err(format!("{name} is not valid Unicode").into()),
+    }
 }
 /// UDP-backed collector that composes an inner drainable metric collector with
 /// a background flush/runtime layer.
@@ -91,16 +569,34 @@ impl Default for MetricCollectorOptions {
 ///
 /// Delivery remains best-effort:
 /// - individual send failures are logged by the worker thread
-/// - drop waits for the worker to finish a final drain attempt
-/// - worker failures are logged on drop but are not returned to the caller
+/// - drop waits for the worker to finish a final drain attempt, up to
+///   [`MetricCollectorOptions::shutdown_timeout`]
+/// - worker failures are logged on drop; [`MetricCollector::try_shutdown`]
+///   returns them instead
 pub struct MetricCollector<MC>
 where
     MC: DrainMetricCollectorTrait + Send + Sync + 'static,
     MC::Hasher: BuildHasher + Clone + Send + Sync + 'static,
 {
     inner: Arc<MC>,
-    sender: Option<Sender<()>>,
+    raw_timings: Arc<SegQueue<RawTiming>>,
+    max_pending_raw_timings: usize,
+    raw_timing_drops: AtomicU64,
+    emergency_counters: Arc<EmergencyCounters>,
+    sender: Option<Sender<JobCommand>>,
     job_handle: Option<JoinHandle<MetricResult<()>>>,
+    /// Disconnected once the flush thread exits.
+    job_done: Receiver<()>,
+    flush_progress: Arc<FlushProgress>,
+    shutdown_timeout: Option<Duration>,
+    flush_thread_restarts: Arc<AtomicU64>,
+    resolved_writer_kind: StatsWriterKind,
+    /// Set by the flush thread once its writer is built.
+    writer_kind: Arc<OnceLock<StatsWriterKind>>,
+    /// Set by [`MetricCollector::quiesce`]; recordings are dropped from then on.
+    quiesced: AtomicBool,
+    post_shutdown_drops: AtomicU64,
+    flushing_paused: Arc<AtomicBool>,
 }
 
 impl<MC> MetricCollector<MC>
@@ -117,38 +613,557 @@ where
     pub fn new(
         bind_addr: SocketAddr,
         dst_addr: SocketAddr,
+        mut options: MetricCollectorOptions,
+        inner: MC,
+    ) -> MetricResult<Self> {
+        let sock = options.socket_options.bind(bind_addr)?;
+        options.socket_options = SocketOptions::default();
+        Self::from_socket(sock, dst_addr, options, inner)
+    }
+
+    /// Builds a UDP collector from the raw descriptor of a bound UDP socket, as
+    /// passed by systemd socket activation.
+    ///
+    /// See [`Self::from_socket`].
+    ///
+    /// # Safety
+    /// `fd` must be an open UDP socket that nothing else uses or closes; the
+    /// collector takes ownership of it and closes it on drop.
+    ///
+    /// # Errors
+    /// Returns the errors of [`Self::from_socket`].
+    #[cfg(all(unix, feature = "raw-fd"))]
+    #[cold]
+    pub unsafe fn from_raw_fd(
+        fd: std::os::fd::RawFd,
+        dst_addr: SocketAddr,
+        options: MetricCollectorOptions,
+        inner: MC,
+    ) -> MetricResult<Self> {
+        use std::os::fd::FromRawFd;
+
+        // SAFETY: the caller hands over ownership of an open UDP socket.
+        let sock = unsafe { UdpSocket::from_raw_fd(fd) };
+        Self::from_socket(sock, dst_addr, options, inner)
+    }
+
+    /// Builds a UDP collector sending from a socket the caller already created
+    /// and bound, e.g. one handed over by systemd socket activation in a
+    /// sandbox that cannot create sockets.
+    ///
+    /// The socket is used as is: [`MetricCollectorOptions::connect_socket`] is
+    /// still honored, but socket options must be applied by the caller.
+    ///
+    /// # Errors
+    /// Returns an error if [`MetricCollectorOptions::socket_options`] is not
+    /// the default, since it cannot be applied to a bound socket, or if
+    /// [`MetricCollectorOptions::name_separator_replace`] would write a
+    /// character of the wire format.
+    #[cold]
+    pub fn from_socket(
+        sock: UdpSocket,
+        dst_addr: SocketAddr,
         options: MetricCollectorOptions,
         inner: MC,
     ) -> MetricResult<Self> {
-        let flush_interval = options.flush_interval;
+        if options.socket_options != SocketOptions::default() {
+            return Err("SocketOptions cannot be applied to a caller-supplied socket".into());
+        }
+        check_name_separator_replace(options.name_separator_replace)?;
+        Ok(Self::start(sock, dst_addr, options, inner, None))
+    }
+
+    /// Starts the flush thread sending from `sock`, optionally following a
+    /// re-resolved host name.
+    fn start(
+        sock: UdpSocket,
+        dst_addr: SocketAddr,
+        options: MetricCollectorOptions,
+        inner: MC,
+        destination_refresh: Option<DestinationRefresh>,
+    ) -> Self {
+        if let Some(warning) = oversized_packet_warning(options.max_udp_packet_size, dst_addr) {
+            warn!("{warning}");
+        }
+        let flush_thread_restarts = Arc::new(AtomicU64::new(0));
+        let flush_progress = Arc::new(FlushProgress::with_recent_errors(
+            options.recent_errors_capacity,
+        ));
+        let flushing_paused = Arc::new(AtomicBool::new(false));
+        let job_options = JobOptions {
+            flush_interval: options.flush_interval,
+            flush_jitter: options.flush_jitter,
+            suffix: SuffixTemplate::new(
+                options
+                    .entity_id
+                    .map(|id| format!("dd.internal.entity_id:{id}"))
+                    .as_deref(),
+            ),
+            wire_format: options.wire_format,
+            name_transform: options.name_transform,
+            name_separator_replace: options.name_separator_replace,
+            max_flush_duration: options.max_flush_duration,
+            packet_sequence_tag: options.packet_sequence_tag,
+            derived_metrics: options.derived_metrics,
+            destination_refresh,
+            restart_on_panic: options.restart_on_panic,
+            restarts: Arc::clone(&flush_thread_restarts),
+            progress: Arc::clone(&flush_progress),
+            paused: Arc::clone(&flushing_paused),
+        };
+        let connected = options.connect_socket && connect_or_warn(&sock, dst_addr);
         let writer = UdpSocketWriter {
-            sock: UdpSocket::bind(bind_addr)?,
+            sock,
             destination_addr: dst_addr,
             #[cfg(target_os = "linux")]
             destination: SocketAddrAny::from(dst_addr),
+            connected,
         };
-        let writer_type = options.writer_type;
         let max_udp_packet_size = options.max_udp_packet_size;
         let max_udp_batch_size = options.max_udp_batch_size;
+        let writer_threads = options.writer_threads;
+        let tag_section_style = match options.wire_format {
+            WireFormat::DogStatsD => options.tag_section_style,
+            WireFormat::PlainStatsD => TagSectionStyle::Omit,
+        };
+        let writer_type = options.writer_type.resolve(max_udp_batch_size);
+        let resolved_writer_kind = writer_type.kind(max_udp_batch_size);
+        let writer_kind = Arc::new(OnceLock::new());
+        let runtime_writer_kind = Arc::clone(&writer_kind);
         let inner = Arc::new(inner);
-        let (sender, receiver) = unbounded::<()>();
+        let raw_timings = Arc::new(SegQueue::new());
+        let emergency_counters = Arc::new(EmergencyCounters::with_capacity(
+            options.emergency_counter_capacity,
+        ));
+        let (sender, receiver) = unbounded::<JobCommand>();
         let runtime_inner = Arc::clone(&inner);
+        let runtime_raw_timings = Arc::clone(&raw_timings);
+        let runtime_emergency_counters = Arc::clone(&emergency_counters);
+        let (job_done_sender, job_done) = bounded::<()>(0);
         let job_handle = spawn(move || {
-            let holder = StatsWriterHolder::new(
+            // dropped when the thread exits, even by panicking
+            let _job_done = job_done_sender;
+            let holder = StatsWriterHolder::with_writer_threads(
                 writer,
                 writer_type,
                 max_udp_packet_size,
                 max_udp_batch_size,
+                tag_section_style,
+                writer_threads,
             );
+            let _ = runtime_writer_kind.set(holder.kind());
 
-            initialize_job(flush_interval, &receiver, runtime_inner, holder)
+            initialize_job(
+                job_options,
+                &receiver,
+                runtime_inner,
+                runtime_raw_timings,
+                runtime_emergency_counters,
+                holder,
+            )
         });
-        Ok(Self {
+        Self {
             inner,
+            raw_timings,
+            max_pending_raw_timings: options.max_pending_raw_timings,
+            raw_timing_drops: AtomicU64::new(0),
+            emergency_counters,
             sender: Some(sender),
             job_handle: Some(job_handle),
+            job_done,
+            flush_progress,
+            shutdown_timeout: options.shutdown_timeout,
+            flush_thread_restarts,
+            resolved_writer_kind,
+            writer_kind,
+            quiesced: AtomicBool::new(false),
+            post_shutdown_drops: AtomicU64::new(0),
+            flushing_paused,
+        }
+    }
+
+    /// Returns the writer the flush thread sends with, after resolving
+    /// [`StatsWriterType::Preferred`] for the running platform.
+    ///
+    /// The flush thread reports the writer it built; until it has started, the
+    /// writer resolved from the options is returned.
+    #[must_use]
+    pub fn active_writer_kind(&self) -> StatsWriterKind {
+        self.writer_kind
+            .get()
+            .copied()
+            .unwrap_or(self.resolved_writer_kind)
+    }
+
+    /// Returns how many times the flush thread recovered from a panic.
+    ///
+    /// Always `0` when [`MetricCollectorOptions::restart_on_panic`] is disabled.
+    #[must_use]
+    pub fn flush_thread_restarts(&self) -> u64 {
+        self.flush_thread_restarts.load(Ordering::Relaxed)
+    }
+
+    /// Stops the collector like dropping it does, reporting whether the final
+    /// flush sent every metric.
+    ///
+    /// Waits at most [`MetricCollectorOptions::shutdown_timeout`] for the flush
+    /// thread, so deployment tooling can tell a clean shutdown from one that
+    /// dropped metrics.
+    ///
+    /// # Errors
+    /// Returns an error if the flush thread panicked or exited with an error.
+    #[cold]
+    pub fn try_shutdown(mut self) -> MetricResult<ShutdownReport> {
+        self.stop_flush_thread()
+    }
+
+    /// Stops accepting metrics and sends everything recorded so far, while
+    /// other threads may still hold the collector, e.g. through an `Arc`.
+    ///
+    /// Recordings made after it are dropped and counted by
+    /// [`Self::post_shutdown_drops`]. Recordings racing with the call may still
+    /// be aggregated; they are sent by the final flush when the collector is
+    /// dropped. Waits at most [`MetricCollectorOptions::shutdown_timeout`] for
+    /// the flush, which is reported like [`Self::try_shutdown`] reports the
+    /// final one.
+    ///
+    /// # Errors
+    /// Returns an error if the flush thread is not running or exits before flushing.
+    #[cold]
+    pub fn quiesce(&self) -> MetricResult<ShutdownReport> {
+        self.quiesced.store(true, Ordering::Release);
+        let dropped_before = self.flush_progress.dropped_lines();
+        let (ack, flushed) = bounded(1);
+        self.sender
+            .as_ref()
+            .and_then(|sender| sender.send(JobCommand::Flush(ack)).ok())
+            .ok_or_else(|| MetricsError::from("metric collector worker is not running"))?;
+
+        let flush = self.shutdown_timeout.map_or_else(
+            || flushed.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            |timeout| flushed.recv_timeout(timeout),
+        );
+        let timed_out = match flush {
+            Ok(()) => false,
+            Err(RecvTimeoutError::Timeout) => true,
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err("metric collector worker exited before flushing".into())
+            }
+        };
+        let pending_metrics = self.flush_progress.dropped_lines() - dropped_before
+            + self.flush_progress.buffered_lines();
+        Ok(ShutdownReport {
+            flushed_cleanly: !timed_out && pending_metrics == 0,
+            pending_metrics,
+            timed_out,
+        })
+    }
+
+    /// Returns how many recordings were dropped because they were made after
+    /// [`Self::quiesce`].
+    #[must_use]
+    pub fn post_shutdown_drops(&self) -> u64 {
+        self.post_shutdown_drops.load(Ordering::Relaxed)
+    }
+
+    /// Returns when the flush thread last completed a flush successfully, or
+    /// `None` before the first one.
+    ///
+    /// Stays fresh while the thread is alive even when nothing is recorded, so
+    /// a value older than a few flush intervals means the flushes are stuck or
+    /// failing.
+    #[must_use]
+    pub fn last_flush_at(&self) -> Option<Instant> {
+        self.flush_progress.last_flush_at()
+    }
+
+    /// Returns how long the last successful flush took, or `None` before the
+    /// first one.
+    #[must_use]
+    pub fn last_flush_duration(&self) -> Option<Duration> {
+        self.flush_progress.last_flush_duration()
+    }
+
+    /// Returns the latest error and warning messages of the flush thread,
+    /// oldest first, up to [`MetricCollectorOptions::recent_errors_capacity`].
+    /// Always empty when no capacity is configured.
+    #[must_use]
+    pub fn recent_errors(&self) -> Vec<String> {
+        self.flush_progress.recent_errors()
+    }
+
+    /// Returns a handle recording under `prefix`, placed after the
+    /// `stats_prefix` of the inner collector, e.g. `billing.` for one of several
+    /// services sharing this collector. See [`NamespacedCollector`].
+    #[must_use]
+    pub const fn namespaced<'a>(&'a self, prefix: &'a str) -> NamespacedCollector<'a, MC> {
+        NamespacedCollector {
+            collector: self,
+            prefix,
+        }
+    }
+
+    /// Stops the scheduled flushes until [`Self::resume_flushing`], e.g. during
+    /// a configuration reload. Metrics keep accumulating in the inner
+    /// collector, within its cardinality limits, and a warning is logged once
+    /// the pause lasts 30 flush intervals. [`Self::quiesce`] and the final
+    /// flush still send.
+    pub fn pause_flushing(&self) {
+        self.flushing_paused.store(true, Ordering::Release);
+    }
+
+    /// Resumes the flushes stopped by [`Self::pause_flushing`]; the next tick
+    /// sends everything accumulated meanwhile.
+    pub fn resume_flushing(&self) {
+        self.flushing_paused.store(false, Ordering::Release);
+    }
+
+    /// Returns whether flushing is paused by [`Self::pause_flushing`].
+    #[must_use]
+    pub fn is_flushing_paused(&self) -> bool {
+        self.flushing_paused.load(Ordering::Acquire)
+    }
+
+    /// Returns whether [`Self::quiesce`] was called, counting the rejected recording.
+    #[inline]
+    fn rejects_after_quiesce(&self) -> bool {
+        if !self.quiesced.load(Ordering::Acquire) {
+            return false;
+        }
+        self.post_shutdown_drops.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    /// Disconnects the flush thread and waits for its final flush.
+    fn stop_flush_thread(&mut self) -> MetricResult<ShutdownReport> {
+        let dropped_before = self.flush_progress.dropped_lines();
+        drop(self.sender.take());
+
+        let timed_out = if let Some(timeout) = self.shutdown_timeout {
+            matches!(
+                self.job_done.recv_timeout(timeout),
+                Err(RecvTimeoutError::Timeout)
+            )
+        } else {
+            let _ = self.job_done.recv();
+            false
+        };
+        let pending_metrics = self.flush_progress.dropped_lines() - dropped_before
+            + self.flush_progress.buffered_lines();
+        if !timed_out {
+            if let Some(handle) = self.job_handle.take() {
+                match handle.join() {
+                    Ok(Ok(())) => {}
+                    Ok(Err(err)) => {
+                        return Err(
+                            format!("metric collector worker exited with error: {err}").into()
+                        )
+                    }
+                    Err(_) => return Err("metric collector worker panicked during shutdown".into()),
+                }
+            }
+        }
+        // on timeout the handle is dropped with `self`, detaching the flush thread
+        Ok(ShutdownReport {
+            flushed_cleanly: !timed_out && pending_metrics == 0,
+            pending_metrics,
+            timed_out,
         })
     }
+
+    /// Records a timing sample that is sent as-is as a `DogStatsD` `|ms` line.
+    ///
+    /// Unlike [`MetricCollectorTrait::histogram`], the value is not aggregated
+    /// client-side: every call produces one `name:millis|ms` line on the next
+    /// flush, and the agent computes the distribution. Each sample is kept in
+    /// memory until then, up to [`MetricCollectorOptions::max_pending_raw_timings`],
+    /// so prefer histograms for very high-rate timers.
+    ///
+    /// **Note:** The `tags` slice is sorted in-place, matching the other recording methods.
+    pub fn timing_raw<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, millis: u64, mut tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        if self.rejects_after_quiesce() {
+            return;
+        }
+        // checked without a lock, so racing recorders may overshoot by a few samples
+        if self.raw_timings.len() >= self.max_pending_raw_timings {
+            self.raw_timing_drops.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let tags = tags.as_mut();
+        tags.sort_unstable();
+        self.raw_timings.push(RawTiming {
+            metric: to_static_metric(metric.into()),
+            tags: build_joined_tags(tags),
+            value: millis,
+        });
+    }
+
+    /// Returns how many [`Self::timing_raw`] samples were dropped because
+    /// [`MetricCollectorOptions::max_pending_raw_timings`] samples were
+    /// already waiting for the next flush.
+    #[must_use]
+    pub fn raw_timing_drops(&self) -> u64 {
+        self.raw_timing_drops.load(Ordering::Relaxed)
+    }
+
+    /// Registers a counter that can later be incremented from a signal handler
+    /// with [`Self::emergency_inc`].
+    ///
+    /// Call it at setup time: registration allocates and sorts `tags` in-place.
+    /// The counter is sent as a `name:value|c` line on the next flush in which
+    /// it was incremented, including the final flush on drop.
+    ///
+    /// # Errors
+    /// Returns an error once [`MetricCollectorOptions::emergency_counter_capacity`]
+    /// counters are registered.
+    pub fn register_emergency_counter<'m, 't, TT>(
+        &self,
+        metric: RylvStr<'m>,
+        mut tags: TT,
+    ) -> MetricResult<EmergencyCounterId>
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        let tags = tags.as_mut();
+        tags.sort_unstable();
+        self.emergency_counters.register(EmergencySeries {
+            metric: to_static_metric(metric),
+            tags: build_joined_tags(tags),
+        })
+    }
+
+    /// Adds one to an emergency counter.
+    ///
+    /// Async-signal-safe: this is a single lock-free atomic add on a slot
+    /// allocated when the collector was built. It does not allocate, take a
+    /// lock, or call any non-reentrant function, so it can run in a signal or
+    /// crash handler. The metric filter, name normalization and aggregation of
+    /// the regular recording path are skipped. An id from another collector
+    /// is ignored or increments an unrelated counter.
+    #[inline]
+    pub fn emergency_inc(&self, id: EmergencyCounterId) {
+        self.emergency_counters.increment(id);
+    }
+
+    /// Repoints the collector at a new destination without losing aggregated metrics.
+    ///
+    /// The flush thread applies the change between flushes, so a flush already
+    /// in progress still goes to the old destination and the next one goes to
+    /// the new one. If the socket cannot be connected to the new destination,
+    /// the flush thread logs a warning and falls back to unconnected sends.
+    /// It also stops the re-resolution enabled by
+    /// [`MetricCollectorOptions::resolve_interval`].
+    ///
+    /// # Errors
+    /// Returns an error if the flush thread is no longer running.
+    pub fn set_destination(&self, destination: SocketAddr) -> MetricResult<()> {
+        self.sender
+            .as_ref()
+            .and_then(|sender| sender.send(JobCommand::SetDestination(destination)).ok())
+            .ok_or_else(|| "metric collector worker is not running".into())
+    }
+
+    /// Returns the value at `quantile` (`0.0..=1.0`) of a histogram recorded
+    /// since the last flush, without resetting it.
+    ///
+    /// Useful as a local signal between flushes, e.g. to shed load when p99
+    /// latency exceeds a threshold. Returns `None` if the histogram has no
+    /// samples yet or the inner collector does not support live queries.
+    ///
+    /// **Note:** The `tags` slice is sorted in-place, matching the recording methods.
+    pub fn peek_percentile<'t, TT>(
+        &self,
+        metric: RylvStr<'_>,
+        tags: TT,
+        quantile: f64,
+    ) -> Option<u64>
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        self.inner.peek_percentile(metric, tags, quantile)
+    }
+
+    /// Returns an owned copy of the counts, gauges and histograms recorded
+    /// since the last flush, without resetting them.
+    ///
+    /// Meant for debug endpoints: the snapshot can be held and inspected while
+    /// recording goes on, and the next flush still emits everything it contains.
+    /// It is a point-in-time view that may miss recordings racing with it.
+    /// Empty if the inner collector does not support live queries.
+    #[must_use]
+    pub fn snapshot(&self) -> Snapshot {
+        self.inner.snapshot()
+    }
+}
+
+impl<MC> MetricCollector<MC>
+where
+    MC: DrainMetricCollectorTrait + Default + Send + Sync + 'static,
+    MC::Hasher: BuildHasher + Clone + Send + Sync + 'static,
+{
+    /// Builds a collector from the standard Datadog environment variables.
+    ///
+    /// The destination comes from [`MetricCollectorOptions::destination_from_env`]
+    /// and the options from [`MetricCollectorOptions::from_env`]; the socket binds
+    /// an ephemeral port on the unspecified address of the destination's family.
+    ///
+    /// # Errors
+    /// Returns an error if the environment is invalid or the collector cannot
+    /// be created.
+    #[cold]
+    pub fn from_env() -> MetricResult<Self> {
+        let destination = MetricCollectorOptions::destination_from_env()?;
+        Self::new(
+            ephemeral_bind_addr(destination),
+            destination,
+            MetricCollectorOptions::from_env()?,
+            MC::default(),
+        )
+    }
+
+    /// Builds a collector sending to `host`, an IP address or a DNS name such
+    /// as a Kubernetes service, on `port`.
+    ///
+    /// The host is resolved with [`MetricCollectorOptions::resolve_destination`],
+    /// and again every [`MetricCollectorOptions::resolve_interval`] if set; the
+    /// socket binds an ephemeral port on the unspecified address of the family
+    /// first resolved.
+    ///
+    /// # Errors
+    /// Returns [`MetricsError::Resolve`] if the host cannot be resolved, or the
+    /// errors of [`Self::new`].
+    #[cold]
+    pub fn connect(
+        host: &str,
+        port: u16,
+        mut options: MetricCollectorOptions,
+    ) -> MetricResult<Self> {
+        check_name_separator_replace(options.name_separator_replace)?;
+        let destination = options.resolve_destination(host, port)?;
+        let destination_refresh = options.resolve_interval.map(|interval| {
+            let host = host.to_owned();
+            let preference = options.address_preference;
+            DestinationRefresh::new(
+                move || resolve_host(&host, port, preference),
+                destination,
+                interval,
+            )
+        });
+        let sock = options
+            .socket_options
+            .bind(ephemeral_bind_addr(destination))?;
+        options.socket_options = SocketOptions::default();
+        Ok(Self::start(
+            sock,
+            destination,
+            options,
+            MC::default(),
+            destination_refresh,
+        ))
+    }
 }
 
 impl<MC> Drop for MetricCollector<MC>
@@ -158,18 +1173,114 @@ where
 {
     #[cold]
     fn drop(&mut self) {
-        drop(self.sender.take());
-
-        if let Some(handle) = self.job_handle.take() {
-            match handle.join() {
-                Ok(Ok(())) => {}
-                Ok(Err(err)) => error!("metric collector worker exited with error: {err}"),
-                Err(_) => error!("metric collector worker panicked during shutdown"),
-            }
+        if self.sender.is_none() {
+            return;
         }
+        match self.stop_flush_thread() {
+            Ok(report) if report.timed_out => warn!(
+                "metric collector worker did not finish within the shutdown timeout, {} metrics pending",
+                report.pending_metrics
+            ),
+            Ok(_) => {}
+            Err(err) => error!("{err}"),
+        }
+    }
+}
+
+/// Handle recording into a [`MetricCollector`] under an extra name prefix, so
+/// several logical services of one process share its socket, aggregator and
+/// flush thread while keeping their own namespace.
+///
+/// Created by [`MetricCollector::namespaced`]. Names are sent as
+/// `stats_prefix + prefix + metric`, like the `*_prefixed` methods of
+/// [`MetricCollectorTrait`] it forwards to, so series recorded through
+/// handles with the same prefix aggregate together.
+pub struct NamespacedCollector<'a, MC>
+where
+    MC: DrainMetricCollectorTrait + Send + Sync + 'static,
+    MC::Hasher: BuildHasher + Clone + Send + Sync + 'static,
+{
+    collector: &'a MetricCollector<MC>,
+    prefix: &'a str,
+}
+
+impl<MC> NamespacedCollector<'_, MC>
+where
+    MC: DrainMetricCollectorTrait + Send + Sync + 'static,
+    MC::Hasher: BuildHasher + Clone + Send + Sync + 'static,
+{
+    /// Returns the prefix prepended to every metric name.
+    #[must_use]
+    pub const fn prefix(&self) -> &str {
+        self.prefix
+    }
+
+    /// Records a histogram in this namespace.
+    ///
+    /// **Note:** The `tags` slice is sorted in-place for consistent aggregation keys.
+    #[inline]
+    pub fn histogram<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        self.collector
+            .histogram_prefixed(self.prefix, metric, value, tags);
+    }
+
+    /// Increments a counter by one in this namespace.
+    ///
+    /// **Note:** The `tags` slice is sorted in-place for consistent aggregation keys.
+    #[inline]
+    pub fn count<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        self.collector
+            .count_add_prefixed(self.prefix, metric, 1, tags);
+    }
+
+    /// Increments a counter by `value` in this namespace.
+    ///
+    /// **Note:** The `tags` slice is sorted in-place for consistent aggregation keys.
+    #[inline]
+    pub fn count_add<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        self.collector
+            .count_add_prefixed(self.prefix, metric, value, tags);
+    }
+
+    /// Records a gauge in this namespace.
+    ///
+    /// **Note:** The `tags` slice is sorted in-place for consistent aggregation keys.
+    #[inline]
+    pub fn gauge<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        self.collector
+            .gauge_prefixed(self.prefix, metric, value, tags);
     }
 }
 
+impl<MC> Clone for NamespacedCollector<'_, MC>
+where
+    MC: DrainMetricCollectorTrait + Send + Sync + 'static,
+    MC::Hasher: BuildHasher + Clone + Send + Sync + 'static,
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<MC> Copy for NamespacedCollector<'_, MC>
+where
+    MC: DrainMetricCollectorTrait + Send + Sync + 'static,
+    MC::Hasher: BuildHasher + Clone + Send + Sync + 'static,
+{
+}
+
 impl<MC> MetricCollectorTrait for MetricCollector<MC>
 where
     MC: DrainMetricCollectorTrait + Send + Sync + 'static,
@@ -178,49 +1289,129 @@ where
     type Hasher = MC::Hasher;
 
     #[inline]
-    fn histogram<'m, 't, TT>(&self, metric: RylvStr<'m>, value: u64, tags: TT)
+    fn histogram<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, tags: TT)
     where
         TT: AsMut<[RylvStr<'t>]>,
     {
+        if self.rejects_after_quiesce() {
+            return;
+        }
         self.inner.histogram(metric, value, tags);
     }
 
     #[inline]
-    fn count<'m, 't, TT>(&self, metric: RylvStr<'m>, tags: TT)
+    fn count<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, tags: TT)
     where
         TT: AsMut<[RylvStr<'t>]>,
     {
+        if self.rejects_after_quiesce() {
+            return;
+        }
         self.inner.count(metric, tags);
     }
 
     #[inline]
-    fn count_add<'m, 't, TT>(&self, metric: RylvStr<'m>, value: u64, tags: TT)
+    fn count_add<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, tags: TT)
     where
         TT: AsMut<[RylvStr<'t>]>,
     {
+        if self.rejects_after_quiesce() {
+            return;
+        }
         self.inner.count_add(metric, value, tags);
     }
 
     #[inline]
-    fn gauge<'m, 't, TT>(&self, metric: RylvStr<'m>, value: u64, tags: TT)
+    fn count_add_f64<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: f64, tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        if self.rejects_after_quiesce() {
+            return;
+        }
+        self.inner.count_add_f64(metric, value, tags);
+    }
+
+    #[inline]
+    fn timed_count<'m, 't, TT>(&self, metric_base: impl Into<RylvStr<'m>>, latency: u64, tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        if self.rejects_after_quiesce() {
+            return;
+        }
+        self.inner.timed_count(metric_base, latency, tags);
+    }
+
+    #[inline]
+    fn gauge<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, tags: TT)
     where
         TT: AsMut<[RylvStr<'t>]>,
     {
+        if self.rejects_after_quiesce() {
+            return;
+        }
         self.inner.gauge(metric, value, tags);
     }
 
     #[inline]
-    fn histogram_sorted(&self, metric: RylvStr<'_>, value: u64, tags: &SortedTags<Self::Hasher>) {
+    fn gauge_last<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        if self.rejects_after_quiesce() {
+            return;
+        }
+        self.inner.gauge_last(metric, value, tags);
+    }
+
+    #[inline]
+    fn rate<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, delta: u64, tags: TT)
+    where
+        TT: AsMut<[RylvStr<'t>]>,
+    {
+        if self.rejects_after_quiesce() {
+            return;
+        }
+        self.inner.rate(metric, delta, tags);
+    }
+
+    #[inline]
+    fn histogram_sorted<'m>(
+        &self,
+        metric: impl Into<RylvStr<'m>>,
+        value: u64,
+        tags: &SortedTags<Self::Hasher>,
+    ) {
+        if self.rejects_after_quiesce() {
+            return;
+        }
         self.inner.histogram_sorted(metric, value, tags);
     }
 
     #[inline]
-    fn count_add_sorted(&self, metric: RylvStr<'_>, value: u64, tags: &SortedTags<Self::Hasher>) {
+    fn count_add_sorted<'m>(
+        &self,
+        metric: impl Into<RylvStr<'m>>,
+        value: u64,
+        tags: &SortedTags<Self::Hasher>,
+    ) {
+        if self.rejects_after_quiesce() {
+            return;
+        }
         self.inner.count_add_sorted(metric, value, tags);
     }
 
     #[inline]
-    fn gauge_sorted(&self, metric: RylvStr<'_>, value: u64, tags: &SortedTags<Self::Hasher>) {
+    fn gauge_sorted<'m>(
+        &self,
+        metric: impl Into<RylvStr<'m>>,
+        value: u64,
+        tags: &SortedTags<Self::Hasher>,
+    ) {
+        if self.rejects_after_quiesce() {
+            return;
+        }
         self.inner.gauge_sorted(metric, value, tags);
     }
 
@@ -233,9 +1424,9 @@ where
     }
 
     #[cold]
-    fn prepare_metric(
+    fn prepare_metric<'m>(
         &self,
-        metric: RylvStr<'_>,
+        metric: impl Into<RylvStr<'m>>,
         tags: SortedTags<Self::Hasher>,
     ) -> PreparedMetric<Self::Hasher> {
         self.inner.prepare_metric(metric, tags)
@@ -243,27 +1434,43 @@ where
 
     #[inline]
     fn histogram_prepared(&self, prepared: &PreparedMetric<Self::Hasher>, value: u64) {
+        if self.rejects_after_quiesce() {
+            return;
+        }
         self.inner.histogram_prepared(prepared, value);
     }
 
     #[inline]
     fn count_add_prepared(&self, prepared: &PreparedMetric<Self::Hasher>, value: u64) {
+        if self.rejects_after_quiesce() {
+            return;
+        }
         self.inner.count_add_prepared(prepared, value);
     }
 
     #[inline]
     fn gauge_prepared(&self, prepared: &PreparedMetric<Self::Hasher>, value: u64) {
+        if self.rejects_after_quiesce() {
+            return;
+        }
         self.inner.gauge_prepared(prepared, value);
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{MetricCollector, MetricCollectorOptions, StatsWriterType};
-    use crate::dogstats::collector::{DrainMetricCollectorTrait, MetricFrameRef};
+    use super::{
+        oversized_packet_warning, AddressPreference, DerivedMetrics, EmergencyCounters,
+        MetricCollector, MetricCollectorOptions, StatsWriterKind, StatsWriterType,
+        WriterPreference,
+    };
+    use crate::dogstats::collector::{DrainMetricCollectorTrait, Metric, MetricFrameRef};
     use crate::{MetricCollectorTrait, PreparedMetric, RylvStr, SortedTags};
-    use crossbeam::channel::unbounded;
+    use crossbeam::channel::{bounded, unbounded};
+    use std::env::VarError;
     use std::hash::BuildHasher;
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
     use std::sync::{Arc, Mutex};
     use std::thread;
     use std::time::Duration;
@@ -271,6 +1478,8 @@ mod tests {
     #[derive(Default)]
     struct FakeInner {
         calls: Mutex<Vec<String>>,
+        // Drains refused before one starts, like a contended aggregator lock.
+        refused_drains: AtomicUsize,
     }
 
     impl FakeInner {
@@ -286,53 +1495,81 @@ mod tests {
     impl MetricCollectorTrait for FakeInner {
         type Hasher = std::hash::BuildHasherDefault<std::collections::hash_map::DefaultHasher>;
 
-        fn histogram<'m, 't, TT>(&self, metric: RylvStr<'m>, value: u64, _tags: TT)
+        fn histogram<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, _tags: TT)
         where
             TT: AsMut<[RylvStr<'t>]>,
         {
+            let metric = metric.into();
             self.record(format!("histogram:{}:{value}", metric.as_ref()));
         }
 
-        fn count<'m, 't, TT>(&self, metric: RylvStr<'m>, _tags: TT)
+        fn count<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, _tags: TT)
         where
             TT: AsMut<[RylvStr<'t>]>,
         {
+            let metric = metric.into();
             self.record(format!("count:{}", metric.as_ref()));
         }
 
-        fn count_add<'m, 't, TT>(&self, metric: RylvStr<'m>, value: u64, _tags: TT)
+        fn count_add<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, _tags: TT)
         where
             TT: AsMut<[RylvStr<'t>]>,
         {
+            let metric = metric.into();
             self.record(format!("count_add:{}:{value}", metric.as_ref()));
         }
 
-        fn gauge<'m, 't, TT>(&self, metric: RylvStr<'m>, value: u64, _tags: TT)
+        fn gauge<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, _tags: TT)
         where
             TT: AsMut<[RylvStr<'t>]>,
         {
+            let metric = metric.into();
             self.record(format!("gauge:{}:{value}", metric.as_ref()));
         }
 
-        fn histogram_sorted(
+        fn gauge_last<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, value: u64, _tags: TT)
+        where
+            TT: AsMut<[RylvStr<'t>]>,
+        {
+            let metric = metric.into();
+            self.record(format!("gauge_last:{}:{value}", metric.as_ref()));
+        }
+
+        fn rate<'m, 't, TT>(&self, metric: impl Into<RylvStr<'m>>, delta: u64, _tags: TT)
+        where
+            TT: AsMut<[RylvStr<'t>]>,
+        {
+            let metric = metric.into();
+            self.record(format!("rate:{}:{delta}", metric.as_ref()));
+        }
+
+        fn histogram_sorted<'m>(
             &self,
-            metric: RylvStr<'_>,
+            metric: impl Into<RylvStr<'m>>,
             value: u64,
             _tags: &SortedTags<Self::Hasher>,
         ) {
+            let metric = metric.into();
             self.record(format!("histogram_sorted:{}:{value}", metric.as_ref()));
         }
 
-        fn count_add_sorted(
+        fn count_add_sorted<'m>(
             &self,
-            metric: RylvStr<'_>,
+            metric: impl Into<RylvStr<'m>>,
             value: u64,
             _tags: &SortedTags<Self::Hasher>,
         ) {
+            let metric = metric.into();
             self.record(format!("count_add_sorted:{}:{value}", metric.as_ref()));
         }
 
-        fn gauge_sorted(&self, metric: RylvStr<'_>, value: u64, _tags: &SortedTags<Self::Hasher>) {
+        fn gauge_sorted<'m>(
+            &self,
+            metric: impl Into<RylvStr<'m>>,
+            value: u64,
+            _tags: &SortedTags<Self::Hasher>,
+        ) {
+            let metric = metric.into();
             self.record(format!("gauge_sorted:{}:{value}", metric.as_ref()));
         }
 
@@ -343,11 +1580,12 @@ mod tests {
             SortedTags::new(tags, &std::hash::BuildHasherDefault::default())
         }
 
-        fn prepare_metric(
+        fn prepare_metric<'m>(
             &self,
-            metric: RylvStr<'_>,
+            metric: impl Into<RylvStr<'m>>,
             tags: SortedTags<Self::Hasher>,
         ) -> PreparedMetric<Self::Hasher> {
+            let metric = metric.into();
             let metric = match metric {
                 RylvStr::Static(s) => RylvStr::Static(s),
                 RylvStr::Borrowed(s) => RylvStr::from(s.to_owned()),
@@ -385,7 +1623,13 @@ mod tests {
         type Drain<'a> = std::vec::IntoIter<MetricFrameRef<'a>>;
 
         fn try_begin_drain(&self) -> Option<Self::Drain<'_>> {
-            Some(Vec::new().into_iter())
+            let refused = self
+                .refused_drains
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| {
+                    left.checked_sub(1)
+                })
+                .is_ok();
+            (!refused).then(|| Vec::new().into_iter())
         }
     }
 
@@ -393,11 +1637,40 @@ mod tests {
         let (sender, _receiver) = unbounded();
         MetricCollector {
             inner,
+            raw_timings: Arc::default(),
+            max_pending_raw_timings: 0,
+            raw_timing_drops: AtomicU64::new(0),
+            emergency_counters: Arc::new(EmergencyCounters::with_capacity(0)),
             sender: Some(sender),
             job_handle: Some(thread::spawn(|| Ok(()))),
+            job_done: bounded(0).1,
+            flush_progress: Arc::default(),
+            shutdown_timeout: None,
+            flush_thread_restarts: Arc::default(),
+            resolved_writer_kind: StatsWriterKind::Simple,
+            writer_kind: Arc::default(),
+            quiesced: AtomicBool::new(false),
+            post_shutdown_drops: AtomicU64::new(0),
+            flushing_paused: Arc::default(),
         }
     }
 
+    #[test]
+    fn oversized_packets_warn_only_for_non_loopback_destinations() {
+        let remote_v4: SocketAddr = "10.0.0.5:8125".parse().unwrap();
+        let remote_v6: SocketAddr = "[2001:db8::1]:8125".parse().unwrap();
+        let loopback: SocketAddr = "127.0.0.1:8125".parse().unwrap();
+
+        let warning = oversized_packet_warning(65_000, remote_v4).expect("should warn");
+        assert!(warning.contains("1432"), "{warning}");
+        assert_eq!(oversized_packet_warning(1432, remote_v4), None);
+        assert_eq!(oversized_packet_warning(1472, remote_v4), None);
+        assert!(oversized_packet_warning(1473, remote_v4).is_some());
+        assert_eq!(oversized_packet_warning(1452, remote_v6), None);
+        assert!(oversized_packet_warning(1472, remote_v6).is_some());
+        assert_eq!(oversized_packet_warning(65_000, loopback), None);
+    }
+
     #[test]
     fn metric_collector_options_default_values_match_documented_defaults() {
         let options = MetricCollectorOptions::default();
@@ -406,11 +1679,220 @@ mod tests {
         assert_eq!(options.max_udp_batch_size, 10);
         assert_eq!(options.flush_interval, Duration::from_secs(10));
         assert!(matches!(options.writer_type, StatsWriterType::Simple));
+        assert!(options.restart_on_panic);
+        assert_eq!(options.emergency_counter_capacity, 16);
+        assert_eq!(options.shutdown_timeout, None);
+        assert_eq!(options.max_flush_duration, None);
+        assert_eq!(options.packet_sequence_tag, None);
+        assert!(options.derived_metrics.is_none());
+        assert_eq!(options.resolve_interval, None);
+        assert!(options.name_transform.is_none());
+        assert!(options.name_separator_replace.is_none());
+        assert_eq!(options.recent_errors_capacity, 0);
+        assert_eq!(options.max_pending_raw_timings, 65_536);
+    }
+
+    #[test]
+    fn metric_collector_option_presets_match_documented_values() {
+        let localhost = MetricCollectorOptions::datadog_agent_localhost();
+        assert_eq!(localhost.max_udp_packet_size, 1432);
+        assert_eq!(localhost.max_udp_batch_size, 10);
+        assert_eq!(localhost.flush_interval, Duration::from_secs(10));
+        assert!(matches!(
+            localhost.writer_type,
+            StatsWriterType::Preferred(WriterPreference::Auto)
+        ));
+
+        let high_throughput = MetricCollectorOptions::high_throughput();
+        assert_eq!(high_throughput.max_udp_packet_size, 1432);
+        assert_eq!(high_throughput.max_udp_batch_size, 64);
+        assert_eq!(high_throughput.flush_interval, Duration::from_secs(1));
+        assert!(matches!(
+            high_throughput.writer_type,
+            StatsWriterType::Preferred(WriterPreference::PreferBatch)
+        ));
+
+        let low_overhead = MetricCollectorOptions::low_overhead();
+        assert_eq!(low_overhead.max_udp_packet_size, 1432);
+        assert_eq!(low_overhead.max_udp_batch_size, 1);
+        assert_eq!(low_overhead.flush_interval, Duration::from_secs(30));
+        assert!(matches!(low_overhead.writer_type, StatsWriterType::Simple));
+
+        for preset in [localhost, high_throughput, low_overhead] {
+            assert!(preset.restart_on_panic);
+            assert!(preset.connect_socket);
+            assert_eq!(preset.flush_jitter, Duration::ZERO);
+        }
+    }
+
+    #[test]
+    fn address_preference_picks_the_preferred_family_first() {
+        let v4: SocketAddr = "10.0.0.1:8125".parse().unwrap();
+        let v6: SocketAddr = "[fd00::1]:8125".parse().unwrap();
+
+        assert_eq!(AddressPreference::PreferIpv4.select([v6, v4]), Some(v4));
+        assert_eq!(AddressPreference::PreferIpv6.select([v4, v6]), Some(v6));
+        assert_eq!(AddressPreference::PreferIpv4.select([v6]), Some(v6));
+        assert_eq!(AddressPreference::PreferIpv6.select([]), None);
+
+        let options = MetricCollectorOptions::default();
+        assert_eq!(options.resolve_destination("[fd00::1]", 8125).unwrap(), v6);
+        assert_eq!(options.resolve_destination(" 10.0.0.1 ", 8125).unwrap(), v4);
+    }
+
+    #[cfg(any(feature = "shared-collector", feature = "simple-map"))]
+    #[test]
+    fn resolve_interval_moves_metrics_to_the_re_resolved_address() {
+        use super::DestinationRefresh;
+        use crate::SharedCollector;
+        use std::net::UdpSocket;
+
+        let receiver = || {
+            let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            socket
+                .set_read_timeout(Some(Duration::from_secs(2)))
+                .unwrap();
+            socket
+        };
+        let recv = |socket: &UdpSocket| {
+            let mut buf = [0; 512];
+            let len = socket.recv(&mut buf).expect("expected a packet");
+            String::from_utf8(buf[..len].to_vec()).unwrap()
+        };
+        let (first, second) = (receiver(), receiver());
+        let resolved = Arc::new(Mutex::new(first.local_addr().unwrap()));
+        let refresh = DestinationRefresh::new(
+            {
+                let resolved = Arc::clone(&resolved);
+                move || Ok(*resolved.lock().unwrap())
+            },
+            first.local_addr().unwrap(),
+            Duration::from_millis(10),
+        );
+        let options = MetricCollectorOptions {
+            flush_interval: Duration::from_millis(20),
+            ..Default::default()
+        };
+        let collector = MetricCollector::start(
+            UdpSocket::bind("127.0.0.1:0").unwrap(),
+            first.local_addr().unwrap(),
+            options,
+            SharedCollector::default(),
+            Some(refresh),
+        );
+
+        collector.gauge(RylvStr::from_static("before.move"), 1, []);
+        assert_eq!(recv(&first), "before.move:1|g\n");
+
+        // the "DNS record" changes; following flushes resolve the new address
+        *resolved.lock().unwrap() = second.local_addr().unwrap();
+        thread::sleep(Duration::from_millis(100));
+        collector.gauge(RylvStr::from_static("after.move"), 2, []);
+        assert_eq!(recv(&second), "after.move:2|g\n");
+    }
+
+    #[test]
+    fn metric_collector_options_read_datadog_env() {
+        fn env<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Result<String, VarError> + 'a {
+            |name| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| (*value).to_owned())
+                    .ok_or(VarError::NotPresent)
+            }
+        }
+
+        assert_eq!(
+            MetricCollectorOptions::destination_from_env_with(env(&[("DD_AGENT_HOST", " ")]))
+                .unwrap(),
+            "127.0.0.1:8125".parse().unwrap()
+        );
+        assert_eq!(
+            MetricCollectorOptions::from_env_with(env(&[]))
+                .unwrap()
+                .entity_id,
+            None
+        );
+
+        let vars = [
+            ("DD_AGENT_HOST", "10.1.2.3"),
+            ("DD_DOGSTATSD_PORT", "9125"),
+            ("DD_ENTITY_ID", "pod-1234"),
+        ];
+        assert_eq!(
+            MetricCollectorOptions::destination_from_env_with(env(&vars)).unwrap(),
+            "10.1.2.3:9125".parse().unwrap()
+        );
+        assert_eq!(
+            MetricCollectorOptions::from_env_with(env(&vars))
+                .unwrap()
+                .entity_id
+                .as_deref(),
+            Some("pod-1234")
+        );
+
+        let vars = [("DD_AGENT_HOST", "[::1]"), ("DD_DOGSTATSD_PORT", "9125")];
+        assert_eq!(
+            MetricCollectorOptions::destination_from_env_with(env(&vars)).unwrap(),
+            "[::1]:9125".parse().unwrap()
+        );
+
+        let resolved = MetricCollectorOptions::destination_from_env_with(env(&[(
+            "DD_AGENT_HOST",
+            "localhost",
+        )]))
+        .unwrap();
+        assert!(resolved.ip().is_loopback());
+        assert_eq!(resolved.port(), 8125);
+
+        let vars = [("DD_DOGSTATSD_PORT", "not-a-port")];
+        let err = MetricCollectorOptions::destination_from_env_with(env(&vars)).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Invalid DD_DOGSTATSD_PORT 'not-a-port'"));
+
+        let err = MetricCollectorOptions::from_env_with(|_| {
+            Err(VarError::NotUnicode(std::ffi::OsString::new()))
+        })
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("DD_ENTITY_ID is not valid Unicode"));
     }
 
     #[test]
     fn stats_writer_type_debug_matches_variant_name() {
         assert_eq!(format!("{:?}", StatsWriterType::Simple), "Simple");
+        assert_eq!(
+            format!("{:?}", StatsWriterType::Preferred(WriterPreference::Auto)),
+            "Preferred(Auto)"
+        );
+    }
+
+    #[test]
+    fn writer_preference_resolves_to_platform_writer() {
+        let batch_kind = if cfg!(target_os = "linux") {
+            StatsWriterKind::LinuxBatch
+        } else if cfg!(target_vendor = "apple") {
+            StatsWriterKind::AppleBatch
+        } else {
+            StatsWriterKind::Simple
+        };
+        let kind = |preference: WriterPreference, max_udp_batch_size| {
+            StatsWriterType::Preferred(preference).kind(max_udp_batch_size)
+        };
+
+        assert_eq!(kind(WriterPreference::Auto, 10), batch_kind);
+        assert_eq!(kind(WriterPreference::Auto, 1), StatsWriterKind::Simple);
+        assert_eq!(kind(WriterPreference::PreferBatch, 1), batch_kind);
+        assert_eq!(
+            kind(WriterPreference::ForceSimple, 10),
+            StatsWriterKind::Simple
+        );
+        assert!(!matches!(
+            StatsWriterType::Preferred(WriterPreference::Auto).resolve(10),
+            StatsWriterType::Preferred(_)
+        ));
     }
 
     #[test]
@@ -440,6 +1922,11 @@ mod tests {
             7,
             &mut [RylvStr::from_static("tag:test")],
         );
+        collector.rate(
+            RylvStr::from_static("bytes"),
+            12,
+            &mut [RylvStr::from_static("tag:test")],
+        );
         collector.count_add_sorted(RylvStr::from_static("sorted_count"), 2, &sorted);
         collector.gauge_sorted(RylvStr::from_static("sorted_gauge"), 5, &sorted);
         collector.histogram_sorted(RylvStr::from_static("sorted_hist"), 11, &sorted);
@@ -455,6 +1942,7 @@ mod tests {
                 "count_add:requests:3".to_string(),
                 "gauge:load:9".to_string(),
                 "histogram:latency:7".to_string(),
+                "rate:bytes:12".to_string(),
                 "count_add_sorted:sorted_count:2".to_string(),
                 "gauge_sorted:sorted_gauge:5".to_string(),
                 "histogram_sorted:sorted_hist:11".to_string(),
@@ -464,4 +1952,37 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn derived_metrics_are_recorded_once_while_the_drain_is_retried() {
+        let options = MetricCollectorOptions {
+            flush_interval: Duration::from_secs(60),
+            derived_metrics: Some(DerivedMetrics::new(|_| {
+                vec![Metric::Count {
+                    name: RylvStr::from_static("derived"),
+                    value: 1,
+                    tags: Vec::new(),
+                }]
+            })),
+            ..Default::default()
+        };
+        let inner = FakeInner {
+            refused_drains: AtomicUsize::new(3),
+            ..Default::default()
+        };
+        let collector = MetricCollector::new(
+            "127.0.0.1:0".parse().unwrap(),
+            "127.0.0.1:8125".parse().unwrap(),
+            options,
+            inner,
+        )
+        .unwrap();
+        let inner = Arc::clone(&collector.inner);
+
+        // the final flush retries the drain until it starts
+        drop(collector);
+
+        assert_eq!(inner.refused_drains.load(Ordering::Relaxed), 0);
+        assert_eq!(inner.take_calls(), vec!["count_add:derived:1".to_string()]);
+    }
 }
@@ -0,0 +1,113 @@
+use super::RylvStr;
+use crate::MetricResult;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+
+/// Handle to a counter registered with
+/// [`crate::MetricCollector::register_emergency_counter`].
+///
+/// Only valid for the collector that returned it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct EmergencyCounterId(usize);
+
+/// Series of an emergency counter, written once at registration.
+pub struct EmergencySeries {
+    pub metric: RylvStr<'static>,
+    pub tags: Arc<str>,
+}
+
+struct EmergencySlot {
+    series: OnceLock<EmergencySeries>,
+    value: AtomicU64,
+}
+
+/// Fixed-size table of emergency counters, shared between the collector and
+/// the flush thread.
+///
+/// The table is allocated up front so incrementing never allocates or locks:
+/// [`Self::increment`] is a single atomic add on an existing slot.
+pub struct EmergencyCounters {
+    slots: Box<[EmergencySlot]>,
+    registered: AtomicUsize,
+}
+
+impl EmergencyCounters {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            slots: (0..capacity)
+                .map(|_| EmergencySlot {
+                    series: OnceLock::new(),
+                    value: AtomicU64::new(0),
+                })
+                .collect(),
+            registered: AtomicUsize::new(0),
+        }
+    }
+
+    /// Claims the next free slot for `series`.
+    pub fn register(&self, series: EmergencySeries) -> MetricResult<EmergencyCounterId> {
+        let capacity = self.slots.len();
+        let index = self
+            .registered
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |registered| {
+                (registered < capacity).then_some(registered + 1)
+            })
+            .map_err(|_| format!("All {capacity} emergency counters are already registered"))?;
+        // The slot was claimed above, so nobody else initializes it
+        let _ = self.slots[index].series.set(series);
+        Ok(EmergencyCounterId(index))
+    }
+
+    /// Adds one to the counter; async-signal-safe.
+    #[inline]
+    pub fn increment(&self, id: EmergencyCounterId) {
+        if let Some(slot) = self.slots.get(id.0) {
+            slot.value.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Takes the value of every registered counter incremented since the last
+    /// call, resetting it to zero.
+    pub fn take(&self) -> impl Iterator<Item = (&EmergencySeries, u64)> {
+        let registered = self.registered.load(Ordering::Acquire);
+        self.slots[..registered].iter().filter_map(|slot| {
+            let series = slot.series.get()?;
+            match slot.value.swap(0, Ordering::Relaxed) {
+                0 => None,
+                value => Some((series, value)),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(metric: &'static str) -> EmergencySeries {
+        EmergencySeries {
+            metric: RylvStr::from_static(metric),
+            tags: Arc::from(""),
+        }
+    }
+
+    #[test]
+    fn take_resets_incremented_counters() {
+        let counters = EmergencyCounters::with_capacity(2);
+        let crashes = counters.register(series("crashes")).unwrap();
+        let aborts = counters.register(series("aborts")).unwrap();
+        assert!(counters.register(series("overflow")).is_err());
+
+        counters.increment(crashes);
+        counters.increment(crashes);
+        let taken = counters
+            .take()
+            .map(|(series, value)| (series.metric.as_ref().to_string(), value))
+            .collect::<Vec<_>>();
+        assert_eq!(taken, vec![("crashes".to_string(), 2)]);
+        assert_eq!(counters.take().count(), 0);
+
+        counters.increment(aborts);
+        assert_eq!(counters.take().map(|(_, value)| value).sum::<u64>(), 1);
+    }
+}
@@ -4,8 +4,22 @@ use std::iter::FromIterator;
 use std::sync::Arc;
 
 use crate::dogstats::aggregator::SigFig;
+#[cfg(any(
+    feature = "shared-collector",
+    feature = "simple-map",
+    feature = "tls-collector",
+    test
+))]
+use crate::dogstats::collector::MetricKind;
 use crate::DefaultMetricHasher;
 use crate::MetricResult;
+#[cfg(any(
+    feature = "shared-collector",
+    feature = "simple-map",
+    feature = "tls-collector",
+    test
+))]
+use hdrhistogram::Histogram;
 
 /// Base histogram metrics that can be emitted alongside configured percentiles.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
@@ -18,26 +32,155 @@ pub enum HistogramBaseMetric {
     Avg,
     /// Emit the `.max` metric.
     Max,
+    /// Emit the `.sum` metric, the total of all recorded values. Off by default.
+    Sum,
 }
 
 impl HistogramBaseMetric {
-    const fn mask(self) -> u8 {
+    const fn mask(self) -> u16 {
         match self {
             Self::Count => 1 << 0,
             Self::Min => 1 << 1,
             Self::Avg => 1 << 2,
             Self::Max => 1 << 3,
+            Self::Sum => 1 << 4,
         }
     }
 }
 
+/// Metric type used to emit a histogram's `.sum`.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub enum HistogramSumKind {
+    /// Emit `.sum` as a gauge (`|g`).
+    #[default]
+    Gauge,
+    /// Emit `.sum` as a counter (`|c`), so it can be summed across tags and
+    /// shown as a rate.
+    Count,
+}
+
+impl HistogramSumKind {
+    #[cfg(any(
+        feature = "shared-collector",
+        feature = "simple-map",
+        feature = "tls-collector",
+        test
+    ))]
+    pub(crate) const fn metric_kind(self) -> MetricKind {
+        match self {
+            Self::Gauge => MetricKind::Gauge,
+            Self::Count => MetricKind::Count,
+        }
+    }
+}
+
+/// How flushed percentiles are computed from the recorded samples.
+///
+/// The methods only differ in how a quantile maps to a rank; the values they
+/// return are still bucketed to the histogram's precision.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub enum PercentileMethod {
+    /// The highest value equivalent to the bucket holding the quantile, as
+    /// returned by `hdrhistogram`.
+    #[default]
+    HdrBucket,
+    /// The smallest sample with at least `quantile` of all samples at or below
+    /// it, the sample at rank `ceil(quantile * count)`.
+    NearestRank,
+    /// Linear interpolation between the two samples around rank
+    /// `quantile * (count - 1)`, rounded to the nearest integer; the default
+    /// method of most statistics packages.
+    Linear,
+}
+
+impl PercentileMethod {
+    /// Returns the value at `quantile` of `histogram`, `0` when it is empty.
+    #[cfg(any(
+        feature = "shared-collector",
+        feature = "simple-map",
+        feature = "tls-collector",
+        test
+    ))]
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    pub(crate) fn value_at_quantile(self, histogram: &Histogram<u64>, quantile: f64) -> u64 {
+        let len = histogram.len();
+        if len == 0 {
+            return 0;
+        }
+        match self {
+            Self::HdrBucket => histogram.value_at_quantile(quantile),
+            Self::NearestRank => {
+                let rank = (quantile * len as f64).ceil() as u64;
+                value_at_rank(histogram, rank.clamp(1, len) - 1)
+            }
+            Self::Linear => {
+                let position = quantile * (len - 1) as f64;
+                let lower_rank = position.floor() as u64;
+                let lower = value_at_rank(histogram, lower_rank);
+                let fraction = position - position.floor();
+                if fraction <= 0.0 {
+                    return lower;
+                }
+                let upper = value_at_rank(histogram, lower_rank + 1);
+                lower + ((upper - lower) as f64 * fraction).round() as u64
+            }
+        }
+    }
+}
+
+/// Returns the sample at the 0-based `rank` in ascending order.
+#[cfg(any(
+    feature = "shared-collector",
+    feature = "simple-map",
+    feature = "tls-collector",
+    test
+))]
+fn value_at_rank(histogram: &Histogram<u64>, rank: u64) -> u64 {
+    let mut seen = 0;
+    for value in histogram.iter_recorded() {
+        seen += value.count_at_value();
+        if seen > rank {
+            return value.value_iterated_to();
+        }
+    }
+    histogram.max()
+}
+
+/// What a histogram does with values above its upper bound.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub enum OutOfBoundsPolicy {
+    /// Drop the value and log the record error.
+    #[default]
+    Drop,
+    /// Record the upper bound in place of the value.
+    Clamp,
+    /// Leave the value out of the histogram and count it in a separate
+    /// `.overflow_count` counter.
+    CountOverflow,
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 #[repr(transparent)]
-pub struct HistogramBaseMetrics(u8);
+pub struct HistogramBaseMetrics(u16);
 
 impl HistogramBaseMetrics {
+    // Not base metrics: select the `OutOfBoundsPolicy` other than `Drop`.
+    const OUT_OF_BOUNDS_CLAMP: u16 = 1 << 5;
+    const OUT_OF_BOUNDS_COUNT: u16 = 1 << 6;
+    // Not a base metric: selects `HistogramSumKind::Count` for `.sum`.
+    const SUM_AS_COUNT: u16 = 1 << 7;
+    // Not base metrics: select the `PercentileMethod` other than `HdrBucket`.
+    const PERCENTILE_NEAREST_RANK: u16 = 1 << 8;
+    const PERCENTILE_LINEAR: u16 = 1 << 9;
+    // Not a base metric: sends the samples as a distribution instead.
+    const DISTRIBUTION: u16 = 1 << 10;
+
     pub(crate) const NONE: Self = Self(0);
-    pub(crate) const ALL: Self = Self(
+    pub(crate) const DEFAULT: Self = Self(
         HistogramBaseMetric::Count.mask()
             | HistogramBaseMetric::Min.mask()
             | HistogramBaseMetric::Avg.mask()
@@ -59,6 +202,71 @@ impl HistogramBaseMetrics {
     pub(crate) const fn without(self, metric: HistogramBaseMetric) -> Self {
         Self(self.0 & !metric.mask())
     }
+
+    pub(crate) const fn sum_kind(self) -> HistogramSumKind {
+        if self.0 & Self::SUM_AS_COUNT == 0 {
+            HistogramSumKind::Gauge
+        } else {
+            HistogramSumKind::Count
+        }
+    }
+
+    pub(crate) const fn with_sum_kind(self, kind: HistogramSumKind) -> Self {
+        match kind {
+            HistogramSumKind::Gauge => Self(self.0 & !Self::SUM_AS_COUNT),
+            HistogramSumKind::Count => Self(self.0 | Self::SUM_AS_COUNT),
+        }
+    }
+
+    pub(crate) const fn out_of_bounds(self) -> OutOfBoundsPolicy {
+        if self.0 & Self::OUT_OF_BOUNDS_CLAMP != 0 {
+            OutOfBoundsPolicy::Clamp
+        } else if self.0 & Self::OUT_OF_BOUNDS_COUNT != 0 {
+            OutOfBoundsPolicy::CountOverflow
+        } else {
+            OutOfBoundsPolicy::Drop
+        }
+    }
+
+    pub(crate) const fn with_out_of_bounds(self, policy: OutOfBoundsPolicy) -> Self {
+        let cleared = self.0 & !(Self::OUT_OF_BOUNDS_CLAMP | Self::OUT_OF_BOUNDS_COUNT);
+        match policy {
+            OutOfBoundsPolicy::Drop => Self(cleared),
+            OutOfBoundsPolicy::Clamp => Self(cleared | Self::OUT_OF_BOUNDS_CLAMP),
+            OutOfBoundsPolicy::CountOverflow => Self(cleared | Self::OUT_OF_BOUNDS_COUNT),
+        }
+    }
+
+    pub(crate) const fn percentile_method(self) -> PercentileMethod {
+        if self.0 & Self::PERCENTILE_NEAREST_RANK != 0 {
+            PercentileMethod::NearestRank
+        } else if self.0 & Self::PERCENTILE_LINEAR != 0 {
+            PercentileMethod::Linear
+        } else {
+            PercentileMethod::HdrBucket
+        }
+    }
+
+    pub(crate) const fn with_percentile_method(self, method: PercentileMethod) -> Self {
+        let cleared = self.0 & !(Self::PERCENTILE_NEAREST_RANK | Self::PERCENTILE_LINEAR);
+        match method {
+            PercentileMethod::HdrBucket => Self(cleared),
+            PercentileMethod::NearestRank => Self(cleared | Self::PERCENTILE_NEAREST_RANK),
+            PercentileMethod::Linear => Self(cleared | Self::PERCENTILE_LINEAR),
+        }
+    }
+
+    pub(crate) const fn is_distribution(self) -> bool {
+        self.0 & Self::DISTRIBUTION != 0
+    }
+
+    pub(crate) const fn with_distribution(self, enabled: bool) -> Self {
+        if enabled {
+            Self(self.0 | Self::DISTRIBUTION)
+        } else {
+            Self(self.0 & !Self::DISTRIBUTION)
+        }
+    }
 }
 
 impl From<HistogramBaseMetric> for HistogramBaseMetrics {
@@ -96,6 +304,7 @@ pub struct HistogramConfig {
     bounds: Bounds,
     percentiles: Arc<[f64]>,
     emit_base_metrics: HistogramBaseMetrics,
+    unit: Option<&'static str>,
 }
 
 /// Inclusive lower and upper bounds for recorded histogram values.
@@ -167,7 +376,8 @@ impl HistogramConfig {
             sig_fig,
             bounds: Bounds::default(),
             percentiles: percentiles.into(),
-            emit_base_metrics: HistogramBaseMetrics::ALL,
+            emit_base_metrics: HistogramBaseMetrics::DEFAULT,
+            unit: None,
         })
     }
 
@@ -177,7 +387,11 @@ impl HistogramConfig {
         mut self,
         emit_base_metrics: impl IntoIterator<Item = HistogramBaseMetric>,
     ) -> Self {
-        self.emit_base_metrics = HistogramBaseMetrics::from_iter(emit_base_metrics);
+        self.emit_base_metrics = HistogramBaseMetrics::from_iter(emit_base_metrics)
+            .with_sum_kind(self.emit_base_metrics.sum_kind())
+            .with_out_of_bounds(self.emit_base_metrics.out_of_bounds())
+            .with_percentile_method(self.emit_base_metrics.percentile_method())
+            .with_distribution(self.emit_base_metrics.is_distribution());
         self
     }
 
@@ -211,6 +425,112 @@ impl HistogramConfig {
         self
     }
 
+    /// Enables or disables the `.sum` histogram metric, the total of all
+    /// recorded values. Disabled by default.
+    #[must_use]
+    pub const fn with_sum(mut self, emit: bool) -> Self {
+        self.set_emit_base_metric(HistogramBaseMetric::Sum, emit);
+        self
+    }
+
+    /// Sets whether `.sum` is emitted as a gauge (default) or a counter.
+    ///
+    /// Only takes effect when `.sum` is enabled.
+    #[must_use]
+    pub const fn with_sum_kind(mut self, kind: HistogramSumKind) -> Self {
+        self.emit_base_metrics = self.emit_base_metrics.with_sum_kind(kind);
+        self
+    }
+
+    /// Sets what happens to values above the upper bound; they are dropped by default.
+    ///
+    /// ```
+    /// use rylv_metrics::{HistogramConfig, OutOfBoundsPolicy};
+    ///
+    /// let config = HistogramConfig::latency_millis().with_out_of_bounds(OutOfBoundsPolicy::Clamp);
+    /// ```
+    #[must_use]
+    pub const fn with_out_of_bounds(mut self, policy: OutOfBoundsPolicy) -> Self {
+        self.emit_base_metrics = self.emit_base_metrics.with_out_of_bounds(policy);
+        self
+    }
+
+    /// Sets how flushed percentiles and `.avg` are computed; defaults to
+    /// [`PercentileMethod::HdrBucket`].
+    ///
+    /// Pick the method of the system the values are compared with, e.g. a
+    /// server-side distribution of the same data.
+    ///
+    /// ```
+    /// use rylv_metrics::{HistogramConfig, PercentileMethod};
+    ///
+    /// let config =
+    ///     HistogramConfig::latency_millis().with_percentile_method(PercentileMethod::NearestRank);
+    /// ```
+    #[must_use]
+    pub const fn with_percentile_method(mut self, method: PercentileMethod) -> Self {
+        self.emit_base_metrics = self.emit_base_metrics.with_percentile_method(method);
+        self
+    }
+
+    /// Sends the recorded samples as a `DogStatsD` distribution (`|d`) instead
+    /// of the `.count`, `.min`, `.avg`, percentile, `.max` and `.sum` lines,
+    /// leaving the aggregation to the agent.
+    ///
+    /// Every sample is sent once, bucketed to the histogram precision, and the
+    /// samples of one series are packed as `name:v1:v2:...|d` lines. This cuts
+    /// the bytes on the wire for histograms recording a few samples per flush,
+    /// while the aggregate lines stay cheaper for very busy ones. Values above
+    /// the upper bound counted under [`OutOfBoundsPolicy::CountOverflow`] are
+    /// still sent as `.overflow_count`.
+    ///
+    /// ```
+    /// use rylv_metrics::HistogramConfig;
+    ///
+    /// let config = HistogramConfig::latency_millis().with_distribution(true);
+    /// ```
+    #[must_use]
+    pub const fn with_distribution(mut self, enabled: bool) -> Self {
+        self.emit_base_metrics = self.emit_base_metrics.with_distribution(enabled);
+        self
+    }
+
+    /// Skips every quantile query at flush: no percentile lines and no `.avg`
+    /// (which reads p50), keeping `.count`, `.min`, `.max` and `.sum` as
+    /// configured.
+    ///
+    /// Quantile lookups are most of the flush cost of large histogram sets, so
+    /// use this for histograms only charted by count and extent.
+    ///
+    /// ```
+    /// use rylv_metrics::HistogramConfig;
+    ///
+    /// let config = HistogramConfig::latency_millis().without_quantiles();
+    /// ```
+    #[must_use]
+    pub fn without_quantiles(mut self) -> Self {
+        self.percentiles = Arc::new([]);
+        self.set_emit_base_metric(HistogramBaseMetric::Avg, false);
+        self
+    }
+
+    /// Tags the emitted lines of this histogram with `unit:<unit>` (e.g. `"ms"`),
+    /// appended after the recorded tags.
+    ///
+    /// The tag is added when the histogram is written, so it is not part of the
+    /// aggregation key. `.count` is a number of samples and is left untagged.
+    ///
+    /// ```
+    /// use rylv_metrics::HistogramConfig;
+    ///
+    /// let config = HistogramConfig::latency_millis().with_unit("ms");
+    /// ```
+    #[must_use]
+    pub const fn with_unit(mut self, unit: &'static str) -> Self {
+        self.unit = Some(unit);
+        self
+    }
+
     pub(crate) const fn sig_fig(&self) -> SigFig {
         self.sig_fig
     }
@@ -227,11 +547,77 @@ impl HistogramConfig {
         self.emit_base_metrics
     }
 
+    #[cfg(any(
+        feature = "shared-collector",
+        feature = "simple-map",
+        feature = "tls-collector"
+    ))]
+    pub(crate) const fn unit(&self) -> Option<&'static str> {
+        self.unit
+    }
+
     const fn with_bounds_checked(mut self, bounds: Bounds) -> Self {
         self.bounds = bounds;
         self
     }
 
+    /// Preset for latencies recorded in microseconds, e.g. with
+    /// [`MetricCollectorTrait::timing_micros`](crate::MetricCollectorTrait::timing_micros).
+    ///
+    /// Tracks 1µs to 60s with 3 significant figures.
+    ///
+    /// ```
+    /// use rylv_metrics::HistogramConfig;
+    /// use std::collections::HashMap;
+    ///
+    /// let mut histogram_configs = HashMap::new();
+    /// histogram_configs.insert("db.query".to_string(), HistogramConfig::latency_micros());
+    /// ```
+    #[must_use]
+    pub fn latency_micros() -> Self {
+        Self::preset(SigFig::THREE, 1, 60_000_000)
+    }
+
+    /// Preset for latencies recorded in milliseconds.
+    ///
+    /// Tracks 1ms to 1h with 3 significant figures.
+    ///
+    /// ```
+    /// use rylv_metrics::HistogramConfig;
+    /// use std::collections::HashMap;
+    ///
+    /// let mut histogram_configs = HashMap::new();
+    /// histogram_configs.insert("http.request".to_string(), HistogramConfig::latency_millis());
+    /// ```
+    #[must_use]
+    pub fn latency_millis() -> Self {
+        Self::preset(SigFig::THREE, 1, 3_600_000)
+    }
+
+    /// Preset for sizes recorded in bytes.
+    ///
+    /// Tracks 1B to 1TiB with 2 significant figures.
+    ///
+    /// ```
+    /// use rylv_metrics::HistogramConfig;
+    /// use std::collections::HashMap;
+    ///
+    /// let mut histogram_configs = HashMap::new();
+    /// histogram_configs.insert("upload.size".to_string(), HistogramConfig::sizes_bytes());
+    /// ```
+    #[must_use]
+    pub fn sizes_bytes() -> Self {
+        Self::preset(SigFig::TWO, 1, 1 << 40)
+    }
+
+    fn preset(sig_fig: SigFig, min: u64, max: u64) -> Self {
+        Self {
+            sig_fig,
+            ..Self::default()
+        }
+        .with_bounds_checked(Bounds { min, max })
+    }
+
     /// Sets histogram recording bounds.
     ///
     /// These bounds determine the compatible pool and the histogram allocation shape.
@@ -249,7 +635,8 @@ impl Default for HistogramConfig {
             sig_fig: SigFig::default(),
             bounds: Bounds::default(),
             percentiles: vec![0.95, 0.99].into(),
-            emit_base_metrics: HistogramBaseMetrics::ALL,
+            emit_base_metrics: HistogramBaseMetrics::DEFAULT,
+            unit: None,
         }
     }
 }
@@ -299,6 +686,15 @@ impl ResolvedHistogramConfig {
     pub(crate) const fn emit_base_metrics(&self) -> HistogramBaseMetrics {
         self.config.emit_base_metrics()
     }
+
+    #[cfg(any(
+        feature = "shared-collector",
+        feature = "simple-map",
+        feature = "tls-collector"
+    ))]
+    pub(crate) const fn unit(&self) -> Option<&'static str> {
+        self.config.unit()
+    }
 }
 
 pub struct ResolvedHistogramConfigs<S = DefaultMetricHasher>
@@ -307,8 +703,28 @@ where
 {
     pub default_histogram_config: ResolvedHistogramConfig,
     pub histogram_configs: HashMap<String, ResolvedHistogramConfig, S>,
+    /// One spec per histogram pool, indexed by pool id.
     pub pool_specs: Arc<[HistogramPoolSpec]>,
-    pub pool_count: usize,
+}
+
+/// Rebuilds per-metric histogram configs with ASCII-lowercased metric names.
+#[cfg(any(
+    feature = "shared-collector",
+    feature = "simple-map",
+    feature = "tls-collector"
+))]
+pub fn lowercase_histogram_config_keys<S: BuildHasher + Clone>(
+    histogram_configs: HashMap<String, HistogramConfig, S>,
+    hasher_builder: &S,
+) -> HashMap<String, HistogramConfig, S> {
+    let mut lowercased =
+        HashMap::with_capacity_and_hasher(histogram_configs.len(), hasher_builder.clone());
+    lowercased.extend(
+        histogram_configs
+            .into_iter()
+            .map(|(metric, config)| (metric.to_ascii_lowercase(), config)),
+    );
+    lowercased
 }
 
 pub fn resolve_histogram_configs<S>(
@@ -358,14 +774,17 @@ where
         ),
         histogram_configs: resolved_histogram_configs,
         pool_specs: pool_specs.into(),
-        pool_count: next_pool_id,
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{resolve_histogram_configs, Bounds, HistogramBaseMetric, HistogramConfig};
+    use super::{
+        resolve_histogram_configs, Bounds, HistogramBaseMetric, HistogramConfig, HistogramSumKind,
+        OutOfBoundsPolicy, PercentileMethod,
+    };
     use crate::dogstats::aggregator::SigFig;
+    use hdrhistogram::Histogram;
     use std::collections::HashMap;
 
     #[test]
@@ -374,6 +793,24 @@ mod tests {
         assert!(config.is_ok());
     }
 
+    #[test]
+    fn without_quantiles_drops_percentiles_and_avg() {
+        let config = HistogramConfig::default()
+            .with_sum(true)
+            .without_quantiles();
+        assert!(config.percentiles().is_empty());
+        let emitted = config.emit_base_metrics();
+        assert!(!emitted.contains(HistogramBaseMetric::Avg));
+        for metric in [
+            HistogramBaseMetric::Count,
+            HistogramBaseMetric::Min,
+            HistogramBaseMetric::Max,
+            HistogramBaseMetric::Sum,
+        ] {
+            assert!(emitted.contains(metric));
+        }
+    }
+
     #[test]
     fn histogram_config_rejects_invalid_percentiles() {
         let invalid = [f64::NAN, f64::INFINITY, -0.1, 1.0];
@@ -383,6 +820,25 @@ mod tests {
         }
     }
 
+    #[test]
+    fn presets_use_valid_histogram_bounds() {
+        for config in [
+            HistogramConfig::latency_micros(),
+            HistogramConfig::latency_millis(),
+            HistogramConfig::sizes_bytes(),
+        ] {
+            let bounds = config.bounds();
+            assert!(Bounds::new(bounds.min(), bounds.max()).is_ok());
+            assert!(hdrhistogram::Histogram::<u64>::new_with_bounds(
+                bounds.min(),
+                bounds.max(),
+                config.sig_fig().value()
+            )
+            .is_ok());
+        }
+        assert_eq!(HistogramConfig::latency_micros().bounds().max(), 60_000_000);
+    }
+
     #[test]
     fn bounds_reject_invalid_values() {
         assert!(Bounds::new(0, 10).is_err());
@@ -401,6 +857,129 @@ mod tests {
         assert!(metrics.contains(HistogramBaseMetric::Max));
     }
 
+    #[test]
+    fn histogram_config_sum_is_opt_in_and_keeps_its_kind() {
+        let config = HistogramConfig::default();
+        assert!(!config
+            .emit_base_metrics()
+            .contains(HistogramBaseMetric::Sum));
+        assert_eq!(
+            config.emit_base_metrics().sum_kind(),
+            HistogramSumKind::Gauge
+        );
+
+        let config = config
+            .with_sum(true)
+            .with_sum_kind(HistogramSumKind::Count)
+            .with_base_metrics([HistogramBaseMetric::Sum]);
+        assert!(config
+            .emit_base_metrics()
+            .contains(HistogramBaseMetric::Sum));
+        assert!(!config
+            .emit_base_metrics()
+            .contains(HistogramBaseMetric::Count));
+        assert_eq!(
+            config.emit_base_metrics().sum_kind(),
+            HistogramSumKind::Count
+        );
+    }
+
+    #[test]
+    fn out_of_bounds_policy_survives_base_metric_changes() {
+        let config = HistogramConfig::default();
+        assert_eq!(
+            config.emit_base_metrics().out_of_bounds(),
+            OutOfBoundsPolicy::Drop
+        );
+
+        for policy in [
+            OutOfBoundsPolicy::Clamp,
+            OutOfBoundsPolicy::CountOverflow,
+            OutOfBoundsPolicy::Drop,
+        ] {
+            let config = HistogramConfig::default()
+                .with_sum_kind(HistogramSumKind::Count)
+                .with_out_of_bounds(OutOfBoundsPolicy::Clamp)
+                .with_out_of_bounds(policy)
+                .with_base_metrics([HistogramBaseMetric::Max]);
+            let metrics = config.emit_base_metrics();
+            assert_eq!(metrics.out_of_bounds(), policy);
+            assert_eq!(metrics.sum_kind(), HistogramSumKind::Count);
+            assert!(metrics.contains(HistogramBaseMetric::Max));
+            assert!(!metrics.contains(HistogramBaseMetric::Count));
+        }
+    }
+
+    #[test]
+    fn percentile_methods_match_hand_computed_values() {
+        let mut histogram = Histogram::<u64>::new_with_bounds(1, 1_000, 3).unwrap();
+        for value in (10..=100).step_by(10) {
+            histogram.record(value).unwrap();
+        }
+
+        // nearest rank: the sample at rank ceil(q * 10)
+        let nearest = PercentileMethod::NearestRank;
+        assert_eq!(nearest.value_at_quantile(&histogram, 0.0), 10);
+        assert_eq!(nearest.value_at_quantile(&histogram, 0.25), 30);
+        assert_eq!(nearest.value_at_quantile(&histogram, 0.5), 50);
+        assert_eq!(nearest.value_at_quantile(&histogram, 0.95), 100);
+
+        // linear: interpolated at rank q * 9
+        let linear = PercentileMethod::Linear;
+        assert_eq!(linear.value_at_quantile(&histogram, 0.0), 10);
+        assert_eq!(linear.value_at_quantile(&histogram, 0.25), 33);
+        assert_eq!(linear.value_at_quantile(&histogram, 0.5), 55);
+        assert_eq!(linear.value_at_quantile(&histogram, 0.75), 78);
+        assert_eq!(linear.value_at_quantile(&histogram, 0.9), 91);
+
+        for quantile in [0.5, 0.95, 0.99] {
+            assert_eq!(
+                PercentileMethod::HdrBucket.value_at_quantile(&histogram, quantile),
+                histogram.value_at_quantile(quantile)
+            );
+        }
+
+        let empty = Histogram::<u64>::new_with_bounds(1, 1_000, 3).unwrap();
+        assert_eq!(linear.value_at_quantile(&empty, 0.5), 0);
+    }
+
+    #[test]
+    fn percentile_method_survives_base_metric_changes() {
+        assert_eq!(
+            HistogramConfig::default()
+                .emit_base_metrics()
+                .percentile_method(),
+            PercentileMethod::HdrBucket
+        );
+        let config = HistogramConfig::default()
+            .with_out_of_bounds(OutOfBoundsPolicy::Clamp)
+            .with_percentile_method(PercentileMethod::NearestRank)
+            .with_percentile_method(PercentileMethod::Linear)
+            .with_base_metrics([HistogramBaseMetric::Max]);
+        let metrics = config.emit_base_metrics();
+        assert_eq!(metrics.percentile_method(), PercentileMethod::Linear);
+        assert_eq!(metrics.out_of_bounds(), OutOfBoundsPolicy::Clamp);
+        assert!(metrics.contains(HistogramBaseMetric::Max));
+    }
+
+    #[test]
+    fn distribution_survives_base_metric_changes() {
+        assert!(!HistogramConfig::default()
+            .emit_base_metrics()
+            .is_distribution());
+        let config = HistogramConfig::default()
+            .with_distribution(true)
+            .with_percentile_method(PercentileMethod::Linear)
+            .with_base_metrics([HistogramBaseMetric::Count]);
+        let metrics = config.emit_base_metrics();
+        assert!(metrics.is_distribution());
+        assert_eq!(metrics.percentile_method(), PercentileMethod::Linear);
+        assert!(!config
+            .with_distribution(false)
+            .emit_base_metrics()
+            .is_distribution());
+    }
+
     #[test]
     fn histogram_config_with_base_metrics_replaces_selection() {
         let config = HistogramConfig::new(SigFig::default(), Vec::new())
@@ -427,7 +1006,7 @@ mod tests {
         let resolved =
             resolve_histogram_configs(default_config, configs, &std::hash::RandomState::new());
 
-        assert_eq!(resolved.pool_count, 2);
+        assert_eq!(resolved.pool_specs.len(), 2);
         assert_eq!(
             resolved.histogram_configs["metric.a"].pool_id(),
             resolved.histogram_configs["metric.b"].pool_id()
@@ -436,6 +1015,5 @@ mod tests {
             resolved.histogram_configs["metric.a"].pool_id(),
             resolved.histogram_configs["metric.c"].pool_id()
         );
-        assert_eq!(resolved.pool_specs.len(), resolved.pool_count);
     }
 }
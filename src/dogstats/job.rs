@@ -1,17 +1,344 @@
-use crate::dogstats::collector::{DrainMetricCollectorTrait, MetricKind, MetricSuffix};
-use crate::dogstats::writer::{StatsWriterHolder, StatsWriterTrait};
-use crate::MetricResult;
+use crate::dogstats::collector::{
+    DrainMetricCollectorTrait, Metric, MetricFrameRef, MetricKind, MetricSuffix, Snapshot,
+};
+use crate::dogstats::emergency_counter::EmergencyCounters;
+use crate::dogstats::writer::{
+    FlushStats, NameTransform, StatsWriterHolder, StatsWriterTrait, WireFormat,
+};
+use crate::{MetricResult, RylvStr};
 
 use bumpalo::Bump;
-use crossbeam::channel::{tick, Receiver};
+use crossbeam::channel::{after, tick, Receiver, Sender};
+use crossbeam::queue::SegQueue;
 use crossbeam::select;
 use itoa::Buffer;
+use std::borrow::Cow;
 use std::collections::hash_map::Entry::{Occupied, Vacant};
-use std::collections::HashMap;
-use std::hash::BuildHasher;
-use std::sync::Arc;
-use std::time::Duration;
-use tracing::error;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::hash::{BuildHasher, RandomState};
+use std::iter::Peekable;
+use std::net::SocketAddr;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use tracing::{error, warn};
+
+/// Requests sent to the flush thread, which applies them between flushes.
+pub enum JobCommand {
+    /// Repoint the UDP writer at a new destination.
+    SetDestination(SocketAddr),
+    /// Flush now, then acknowledge on the sender.
+    Flush(Sender<()>),
+}
+
+/// Closure computing metrics from the aggregates of a flush, e.g. an error
+/// rate from `errors` and `requests` counters.
+///
+/// The flush thread calls it with a [`Snapshot`] taken just before the
+/// aggregates are reset, and records the returned metrics with
+/// [`MetricCollectorTrait::record`](crate::MetricCollectorTrait::record)
+/// before draining, so they are aggregated and sent in the same flush like
+/// any other series: a [`Metric::Histogram`] emits its configured percentiles
+/// and base metrics. Collectors without
+/// [`DrainMetricCollectorTrait::snapshot`] support pass an empty snapshot.
+#[derive(Clone)]
+pub struct DerivedMetrics(Arc<DerivedMetricsFn>);
+
+type DerivedMetricsFn = dyn Fn(&Snapshot) -> Vec<Metric> + Send + Sync;
+
+impl DerivedMetrics {
+    /// Wraps `derive`.
+    pub fn new(derive: impl Fn(&Snapshot) -> Vec<Metric> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(derive))
+    }
+
+    /// Computes the derived metrics of `snapshot`.
+    #[must_use]
+    pub fn apply(&self, snapshot: &Snapshot) -> Vec<Metric> {
+        (self.0)(snapshot)
+    }
+}
+
+impl std::fmt::Debug for DerivedMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DerivedMetrics(...)")
+    }
+}
+
+/// Looks up the current address of the destination host.
+pub type ResolveFn = dyn FnMut() -> MetricResult<SocketAddr> + Send;
+
+/// Destination host name re-resolved by the flush thread, see
+/// `MetricCollectorOptions::resolve_interval`.
+pub struct DestinationRefresh {
+    resolve: Box<ResolveFn>,
+    interval: Duration,
+    current: SocketAddr,
+    next_resolve: Instant,
+}
+
+impl DestinationRefresh {
+    /// Re-resolves with `resolve` every `interval`, starting from `current`.
+    pub fn new(
+        resolve: impl FnMut() -> MetricResult<SocketAddr> + Send + 'static,
+        current: SocketAddr,
+        interval: Duration,
+    ) -> Self {
+        Self {
+            resolve: Box::new(resolve),
+            interval,
+            current,
+            next_resolve: Instant::now() + interval,
+        }
+    }
+
+    /// Runs the lookup once `interval` has elapsed and returns the new address
+    /// if it changed. A failed lookup keeps the current address.
+    fn poll(&mut self, now: Instant, progress: &FlushProgress) -> Option<SocketAddr> {
+        if now < self.next_resolve {
+            return None;
+        }
+        self.next_resolve = now + self.interval;
+        match (self.resolve)() {
+            Ok(resolved) if resolved != self.current => {
+                self.current = resolved;
+                Some(resolved)
+            }
+            Ok(_) => None,
+            Err(err) => {
+                progress.log_warning(format_args!("Keeping destination {}: {err}", self.current));
+                None
+            }
+        }
+    }
+}
+
+/// Count recorded into the collector each time the flush thread recovers from a panic.
+pub const FLUSH_THREAD_RESTART_METRIC: &str = "rylv_metrics.flush_thread.restart";
+
+/// Ticks skipped while paused before the flush thread warns that metrics keep
+/// accumulating.
+const PAUSED_TICKS_WARNING: u64 = 30;
+
+/// Flush thread settings taken from `MetricCollectorOptions`.
+pub struct JobOptions {
+    pub flush_interval: Duration,
+    pub flush_jitter: Duration,
+    pub suffix: SuffixTemplate,
+    pub wire_format: WireFormat,
+    pub name_transform: Option<NameTransform>,
+    pub name_separator_replace: Option<(char, char)>,
+    pub max_flush_duration: Option<Duration>,
+    pub packet_sequence_tag: Option<&'static str>,
+    pub derived_metrics: Option<DerivedMetrics>,
+    pub destination_refresh: Option<DestinationRefresh>,
+    pub restart_on_panic: bool,
+    /// Incremented every time the flush loop is restarted after a panic.
+    pub restarts: Arc<AtomicU64>,
+    pub progress: Arc<FlushProgress>,
+    /// Set while flushing is paused; ticks are skipped and metrics accumulate.
+    pub paused: Arc<AtomicBool>,
+}
+
+/// Lines handed to the writer by the flush thread, shared with the collector so
+/// shutdown can report what was not sent.
+pub struct FlushProgress {
+    /// Lines written into the writer during the running flush, not flushed yet.
+    buffered_lines: AtomicUsize,
+    /// Lines the writer rejected or failed to flush since the thread started.
+    dropped_lines: AtomicUsize,
+    /// Reference point of `last_flush_at`.
+    started: Instant,
+    /// Nanoseconds from `started` to the end of the last successful flush, plus
+    /// one so zero means no flush completed yet.
+    last_flush_at: AtomicU64,
+    /// Nanoseconds the last successful flush took.
+    last_flush_duration: AtomicU64,
+    /// Latest error and warning messages of the flush thread, oldest first.
+    recent_errors: Mutex<VecDeque<String>>,
+    /// Most messages kept in `recent_errors`; zero keeps none.
+    recent_errors_capacity: usize,
+}
+
+impl Default for FlushProgress {
+    fn default() -> Self {
+        Self::with_recent_errors(0)
+    }
+}
+
+impl FlushProgress {
+    /// Creates the progress of a flush thread keeping its last
+    /// `recent_errors_capacity` error messages.
+    pub fn with_recent_errors(recent_errors_capacity: usize) -> Self {
+        Self {
+            buffered_lines: AtomicUsize::new(0),
+            dropped_lines: AtomicUsize::new(0),
+            started: Instant::now(),
+            last_flush_at: AtomicU64::new(0),
+            last_flush_duration: AtomicU64::new(0),
+            recent_errors: Mutex::new(VecDeque::with_capacity(recent_errors_capacity)),
+            recent_errors_capacity,
+        }
+    }
+
+    pub fn recent_errors(&self) -> Vec<String> {
+        self.recent_errors
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Logs `message` as an error and keeps it among the recent errors.
+    fn log_error(&self, message: fmt::Arguments<'_>) {
+        error!("{message}");
+        self.keep_error(message);
+    }
+
+    /// Logs `message` as a warning and keeps it among the recent errors.
+    fn log_warning(&self, message: fmt::Arguments<'_>) {
+        warn!("{message}");
+        self.keep_error(message);
+    }
+
+    fn keep_error(&self, message: fmt::Arguments<'_>) {
+        if self.recent_errors_capacity == 0 {
+            return;
+        }
+        let message = message.to_string();
+        let mut errors = self
+            .recent_errors
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if errors.len() == self.recent_errors_capacity {
+            errors.pop_front();
+        }
+        errors.push_back(message);
+    }
+
+    pub fn last_flush_at(&self) -> Option<Instant> {
+        let at = self.last_flush_at.load(Ordering::Acquire).checked_sub(1)?;
+        Some(self.started + Duration::from_nanos(at))
+    }
+
+    pub fn last_flush_duration(&self) -> Option<Duration> {
+        // written before `last_flush_at`, so it belongs to that flush or a later one
+        self.last_flush_at.load(Ordering::Acquire).checked_sub(1)?;
+        Some(Duration::from_nanos(
+            self.last_flush_duration.load(Ordering::Acquire),
+        ))
+    }
+
+    /// Records a successful flush that began at `began`.
+    fn completed(&self, began: Instant) {
+        let now = Instant::now();
+        let nanos = |duration: Duration| u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        self.last_flush_duration
+            .store(nanos(now - began), Ordering::Release);
+        self.last_flush_at.store(
+            nanos(now - self.started).saturating_add(1),
+            Ordering::Release,
+        );
+    }
+
+    pub fn buffered_lines(&self) -> usize {
+        self.buffered_lines.load(Ordering::Acquire)
+    }
+
+    pub fn dropped_lines(&self) -> usize {
+        self.dropped_lines.load(Ordering::Acquire)
+    }
+
+    fn line_written(&self, accepted: bool) {
+        let lines = if accepted {
+            &self.buffered_lines
+        } else {
+            &self.dropped_lines
+        };
+        lines.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Ends the running flush; its buffered lines count as dropped unless `sent`.
+    /// Returns how many lines the flush had buffered.
+    fn flushed(&self, sent: bool) -> usize {
+        let buffered = self.buffered_lines.swap(0, Ordering::AcqRel);
+        if !sent {
+            self.dropped_lines.fetch_add(buffered, Ordering::AcqRel);
+        }
+        buffered
+    }
+}
+
+/// Static tags appended to every flushed line, joined once when the collector
+/// is built.
+///
+/// [`Self::apply`] copies a line's tags and the joined suffix into a single tag
+/// list, so the cost per line does not grow with the number of static tags or
+/// flushed keys.
+#[derive(Debug, Default)]
+pub struct SuffixTemplate {
+    tags: Option<Box<str>>,
+}
+
+impl SuffixTemplate {
+    /// Joins `tags` into the suffix appended to every line.
+    pub fn new<'t>(tags: impl IntoIterator<Item = &'t str>) -> Self {
+        let mut joined = String::new();
+        for tag in tags.into_iter().filter(|tag| !tag.is_empty()) {
+            if !joined.is_empty() {
+                joined.push(',');
+            }
+            joined.push_str(tag);
+        }
+        Self {
+            tags: (!joined.is_empty()).then(|| joined.into_boxed_str()),
+        }
+    }
+
+    /// Returns `tags` followed by the suffix, copying into `bump` only when both are present.
+    fn apply<'a>(&'a self, tags: &'a str, bump: &'a Bump) -> &'a str {
+        self.tags
+            .as_deref()
+            .map_or(tags, |suffix| append_tags(tags, suffix, bump))
+    }
+}
+
+/// Returns `tags` followed by `suffix`, copying into `bump` only when both are non-empty.
+fn append_tags<'a>(tags: &'a str, suffix: &'a str, bump: &'a Bump) -> &'a str {
+    if tags.is_empty() {
+        return suffix;
+    }
+    if suffix.is_empty() {
+        return tags;
+    }
+    let joined = bump.alloc_slice_fill_copy(tags.len() + 1 + suffix.len(), b',');
+    joined[..tags.len()].copy_from_slice(tags.as_bytes());
+    joined[tags.len() + 1..].copy_from_slice(suffix.as_bytes());
+    // SAFETY: two UTF-8 strings joined by an ASCII comma are valid UTF-8.
+    unsafe { std::str::from_utf8_unchecked(joined) }
+}
+
+/// State shared by every line of a flush.
+struct LineContext<'a> {
+    progress: &'a FlushProgress,
+    suffix: &'a SuffixTemplate,
+    /// `<tag>:<sequence>` of the running flush, when a sequence tag is configured.
+    sequence_tag: Option<&'a str>,
+    wire_format: WireFormat,
+    name_transform: Option<&'a NameTransform>,
+    name_separator_replace: Option<(char, char)>,
+    bump: &'a Bump,
+}
+
+/// Un-aggregated timing sample waiting to be written as a `|ms` line.
+pub struct RawTiming {
+    pub metric: RylvStr<'static>,
+    pub tags: Arc<str>,
+    pub value: u64,
+}
 
 struct MetricCollectorJob<MC>
 where
@@ -19,9 +346,30 @@ where
     MC::Hasher: BuildHasher + Clone + Send + Sync + 'static,
 {
     collector: Arc<MC>,
+    raw_timings: Arc<SegQueue<RawTiming>>,
+    // Popped samples stay alive until the writer is flushed, batch writers keep references.
+    pending_raw_timings: Vec<RawTiming>,
+    emergency_counters: Arc<EmergencyCounters>,
 
     buffer: Buffer,
+    float_buffer: ryu::Buffer,
+    // Reused to join the values of packed distribution lines.
+    packed_values: String,
     bump: Bump,
+    suffix: SuffixTemplate,
+    wire_format: WireFormat,
+    name_transform: Option<NameTransform>,
+    name_separator_replace: Option<(char, char)>,
+    max_flush_duration: Option<Duration>,
+    packet_sequence_tag: Option<&'static str>,
+    // Sequence number tagged on the lines of the next flush.
+    sequence: u64,
+    derived_metrics: Option<DerivedMetrics>,
+    // Set once the derived metrics of a flush are recorded, until its drain starts.
+    derived_recorded: bool,
+    destination_refresh: Option<DestinationRefresh>,
+    progress: Arc<FlushProgress>,
+    paused: Arc<AtomicBool>,
 
     stats_writer: StatsWriterHolder,
 }
@@ -36,71 +384,224 @@ where
     MC: DrainMetricCollectorTrait + Send + Sync + 'static,
     MC::Hasher: BuildHasher + Clone + Send + Sync + 'static,
 {
+    fn begin_drain(collector: &MC, max_flush_duration: Option<Duration>) -> Option<MC::Drain<'_>> {
+        max_flush_duration.map_or_else(
+            || collector.try_begin_drain(),
+            |budget| collector.try_begin_drain_until(Instant::now() + budget),
+        )
+    }
+
     fn send_metrics(&mut self) -> SendResult {
-        let Some(drain) = self.collector.try_begin_drain() else {
+        let began = Instant::now();
+        // Derived from the aggregates before the drain resets them, and
+        // recorded so this drain sends them; only once when the drain is retried.
+        if !self.derived_recorded {
+            for metric in self.derive_metrics() {
+                self.collector.record(metric);
+            }
+            self.derived_recorded = true;
+        }
+        let Some(drain) = Self::begin_drain(&self.collector, self.max_flush_duration) else {
             return SendResult::WouldBlock;
         };
+        self.derived_recorded = false;
 
         let mut percentile_suffix_cache = HashMap::<u64, &str>::new();
         let mut stats_writer = self.stats_writer.acquire();
         let can_use_stack = stats_writer.metric_copied();
-        for metric in drain {
-            let value = if can_use_stack {
-                self.buffer.format(metric.value)
+        let lines = LineContext {
+            progress: &self.progress,
+            suffix: &self.suffix,
+            sequence_tag: Self::sequence_tag(
+                self.packet_sequence_tag,
+                self.sequence,
+                self.wire_format,
+                &self.bump,
+            ),
+            wire_format: self.wire_format,
+            name_transform: self.name_transform.as_ref(),
+            name_separator_replace: self.name_separator_replace,
+            bump: &self.bump,
+        };
+        let mut frames = drain.into_iter().peekable();
+        while let Some(metric) = frames.next() {
+            let value = if metric.kind == MetricKind::Distribution {
+                pack_distribution_values(
+                    &metric,
+                    &mut frames,
+                    &mut self.buffer,
+                    &mut self.packed_values,
+                    &self.bump,
+                )
+            } else if let Some(value) = metric.float_value {
+                Self::format_float_value(value, can_use_stack, &self.bump, &mut self.float_buffer)
             } else {
-                Self::get_value(metric.value, &self.bump, &mut self.buffer)
+                Self::format_value(metric.value, can_use_stack, &self.bump, &mut self.buffer)
             };
 
-            let mut metric_parts = ["", "", ""];
-            let mut part_count = 0usize;
-            if !metric.prefix.is_empty() {
-                metric_parts[part_count] = metric.prefix;
-                part_count += 1;
-            }
-            metric_parts[part_count] = metric.metric;
-            part_count += 1;
-            match metric.suffix {
-                MetricSuffix::None => {}
-                MetricSuffix::Static(suffix) => {
-                    metric_parts[part_count] = suffix;
-                    part_count += 1;
-                }
-                MetricSuffix::Percentile(percentile) => {
-                    let key = percentile.to_bits();
-                    let suffix = match percentile_suffix_cache.entry(key) {
-                        Occupied(occupied_entry) => *occupied_entry.get(),
-                        Vacant(vacant_entry) => {
-                            vacant_entry.insert(Self::get_percentile_suffix(percentile, &self.bump))
-                        }
-                    };
-                    metric_parts[part_count] = suffix;
-                    part_count += 1;
-                }
-            }
+            let suffix =
+                Self::suffix_part(&metric.suffix, &mut percentile_suffix_cache, &self.bump);
+            let (metric_parts, part_count) = name_parts(metric.prefix, metric.metric, suffix);
 
             Self::send_metric(
                 &mut stats_writer,
+                &lines,
                 &metric_parts[..part_count],
-                metric.tags,
+                with_unit_tag(metric.tags, metric.unit, &self.bump),
                 value,
                 metric.kind,
             );
         }
 
-        if let Err(err) = stats_writer.flush() {
-            error!("Error sending metrics: {err}");
+        let prefix = self.collector.stats_prefix();
+        self.pending_raw_timings
+            .extend(std::iter::from_fn(|| self.raw_timings.pop()));
+        for timing in &self.pending_raw_timings {
+            let value =
+                Self::format_value(timing.value, can_use_stack, &self.bump, &mut self.buffer);
+            Self::send_prefixed_metric(
+                &mut stats_writer,
+                &lines,
+                prefix,
+                timing.metric.as_ref(),
+                &timing.tags,
+                value,
+                MetricKind::Timing,
+            );
+        }
+
+        for (series, value) in self.emergency_counters.take() {
+            let value = Self::format_value(value, can_use_stack, &self.bump, &mut self.buffer);
+            Self::send_prefixed_metric(
+                &mut stats_writer,
+                &lines,
+                prefix,
+                series.metric.as_ref(),
+                &series.tags,
+                value,
+                MetricKind::Count,
+            );
+        }
+
+        // empty flushes send nothing, so they must not look like a gap
+        if Self::finish_flush(&mut stats_writer, &self.progress, began) > 0 {
+            self.sequence = self.sequence.wrapping_add(1);
         }
 
+        drop(stats_writer);
+        self.pending_raw_timings.clear();
         drop(percentile_suffix_cache);
         self.bump.reset();
         SendResult::Ok
     }
 
+    /// Flushes the writer, notifying it when the cycle that began at `began`
+    /// completed. Returns how many lines the cycle had buffered.
+    fn finish_flush(
+        stats_writer: &mut dyn StatsWriterTrait,
+        progress: &FlushProgress,
+        began: Instant,
+    ) -> usize {
+        let flushed = stats_writer.flush();
+        let lines = progress.flushed(flushed.is_ok());
+        match flushed {
+            Ok(bytes_sent) => {
+                progress.completed(began);
+                stats_writer.on_flush_complete(FlushStats {
+                    lines,
+                    bytes_sent,
+                    messages_sent: stats_writer.messages_sent(),
+                    duration: began.elapsed(),
+                });
+            }
+            Err(err) => progress.log_error(format_args!("Error sending metrics: {err}")),
+        }
+        lines
+    }
+
+    /// Formats `<name>:<sequence>` into `bump`. Never set for
+    /// [`WireFormat::PlainStatsD`], which would fold it into a new name per flush.
+    fn sequence_tag<'a>(
+        name: Option<&str>,
+        sequence: u64,
+        wire_format: WireFormat,
+        bump: &'a Bump,
+    ) -> Option<&'a str> {
+        let name = name.filter(|_| wire_format == WireFormat::DogStatsD)?;
+        Some(bump.alloc_str(&format!("{name}:{sequence}")))
+    }
+
+    /// Computes the derived metrics of the flush about to start. A flush
+    /// resuming a drain stopped by `max_flush_duration` derives nothing, its
+    /// aggregates were already used.
+    fn derive_metrics(&self) -> Vec<Metric> {
+        match &self.derived_metrics {
+            Some(derived) if !self.collector.has_pending_drain() => {
+                derived.apply(&self.collector.snapshot())
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Formats `value` in `buffer`, or copies it into `bump` when the writer
+    /// keeps references to the lines until it is flushed.
+    fn format_value<'a>(
+        value: u64,
+        can_use_stack: bool,
+        bump: &'a Bump,
+        buffer: &'a mut Buffer,
+    ) -> &'a str {
+        if can_use_stack {
+            buffer.format(value)
+        } else {
+            Self::get_value(value, bump, buffer)
+        }
+    }
+
     fn get_value<'a>(value: u64, bump: &'a Bump, buffer: &mut Buffer) -> &'a str {
         let value = buffer.format(value);
         bump.alloc_str(value)
     }
 
+    /// Formats a fractional `value` like [`Self::format_value`], dropping the
+    /// `.0` of whole numbers so they are sent as integers.
+    fn format_float_value<'a>(
+        value: f64,
+        can_use_stack: bool,
+        bump: &'a Bump,
+        buffer: &'a mut ryu::Buffer,
+    ) -> &'a str {
+        let formatted = buffer.format(value);
+        let value = formatted.strip_suffix(".0").unwrap_or(formatted);
+        if can_use_stack {
+            value
+        } else {
+            bump.alloc_str(value)
+        }
+    }
+
+    /// Returns the name part written for `suffix`, formatting percentile
+    /// suffixes once per flush.
+    fn suffix_part<'s>(
+        suffix: &MetricSuffix<'s>,
+        percentile_suffix_cache: &mut HashMap<u64, &'s str>,
+        bump: &'s Bump,
+    ) -> Option<&'s str> {
+        match *suffix {
+            MetricSuffix::None => None,
+            MetricSuffix::Static(suffix) => Some(suffix),
+            MetricSuffix::Percentile(percentile) => {
+                let suffix = match percentile_suffix_cache.entry(percentile.to_bits()) {
+                    Occupied(occupied_entry) => *occupied_entry.get(),
+                    Vacant(vacant_entry) => {
+                        vacant_entry.insert(Self::get_percentile_suffix(percentile, bump))
+                    }
+                };
+                Some(suffix)
+            }
+        }
+    }
+
     fn get_percentile_suffix(percentile: f64, bump: &Bump) -> &str {
         let mut percentile_number = (percentile * 100.0).to_string();
         if percentile_number.contains('.') {
@@ -116,24 +617,308 @@ where
         bump.alloc_str(&suffix)
     }
 
+    /// Sends a line that bypassed aggregation, prepending the collector prefix.
+    fn send_prefixed_metric<'data>(
+        stats_writer: &mut dyn StatsWriterTrait,
+        lines: &LineContext<'data>,
+        prefix: &'data str,
+        metric: &'data str,
+        tags: &'data str,
+        value: &'data str,
+        metric_type: MetricKind,
+    ) {
+        let metric_parts = [prefix, metric];
+        let metric_parts = if prefix.is_empty() {
+            &metric_parts[1..]
+        } else {
+            &metric_parts[..]
+        };
+        Self::send_metric(stats_writer, lines, metric_parts, tags, value, metric_type);
+    }
+
+    /// Writes one line, renaming it with the name transform and separator
+    /// replacement, appending the
+    /// static tag suffix to `tags` and folding them into the name for
+    /// [`WireFormat::PlainStatsD`].
     fn send_metric<'data>(
         stats_writer: &mut dyn StatsWriterTrait,
+        lines: &LineContext<'data>,
         metric: &[&'data str],
         tags: &'data str,
         value: &'data str,
         metric_type: MetricKind,
     ) {
-        match stats_writer.write(metric, tags, value, metric_type) {
-            Ok(()) => {}
-            Err(err) => error!("Error sending metrics. Error {err}"),
+        let transformed = lines
+            .name_transform
+            .map(|transform| [transform_name(transform, metric, lines.bump)]);
+        let metric = transformed.as_ref().map_or(metric, |name| &name[..]);
+        let replaced = lines
+            .name_separator_replace
+            .and_then(|(from, to)| replace_separator(metric, from, to, lines.bump))
+            .map(|name| [name]);
+        let metric = replaced.as_ref().map_or(metric, |name| &name[..]);
+        let tags = lines.suffix.apply(tags, lines.bump);
+        let tags = lines
+            .sequence_tag
+            .map_or(tags, |sequence| append_tags(tags, sequence, lines.bump));
+        let written = if lines.wire_format == WireFormat::PlainStatsD && !tags.is_empty() {
+            let name = fold_tags_into_name(metric, tags, lines.bump);
+            stats_writer.write(&[name], "", value, metric_type)
+        } else {
+            stats_writer.write(metric, tags, value, metric_type)
+        };
+        if let Err(err) = &written {
+            lines
+                .progress
+                .log_error(format_args!("Error sending metrics. Error {err}"));
+        }
+        lines.progress.line_written(written.is_ok());
+    }
+}
+
+/// Returns the non-empty parts of a frame name and how many there are.
+const fn name_parts<'a>(
+    prefix: &'a str,
+    metric: &'a str,
+    suffix: Option<&'a str>,
+) -> ([&'a str; 3], usize) {
+    let mut parts = ["", "", ""];
+    let mut count = 0usize;
+    if !prefix.is_empty() {
+        parts[count] = prefix;
+        count += 1;
+    }
+    parts[count] = metric;
+    count += 1;
+    if let Some(suffix) = suffix {
+        parts[count] = suffix;
+        count += 1;
+    }
+    (parts, count)
+}
+
+/// Longest value section of a packed `|d` line, leaving room for the name and
+/// tags in a default 1432-byte datagram.
+const MAX_PACKED_VALUES_LEN: usize = 1024;
+
+/// Joins the value of `first` and of the frames of the same distribution series
+/// right after it with `:` into `bump`, so they are sent as one multi-value line.
+fn pack_distribution_values<'a, 'f>(
+    first: &MetricFrameRef<'f>,
+    frames: &mut Peekable<impl Iterator<Item = MetricFrameRef<'f>>>,
+    buffer: &mut Buffer,
+    values: &mut String,
+    bump: &'a Bump,
+) -> &'a str {
+    let same_series = |next: &MetricFrameRef<'f>| {
+        next.kind == first.kind
+            && next.prefix == first.prefix
+            && next.metric == first.metric
+            && next.suffix == first.suffix
+            && next.tags == first.tags
+            && next.unit == first.unit
+    };
+    values.clear();
+    values.push_str(buffer.format(first.value));
+    while values.len() < MAX_PACKED_VALUES_LEN {
+        let Some(next) = frames.next_if(same_series) else {
+            break;
+        };
+        values.push(':');
+        values.push_str(buffer.format(next.value));
+    }
+    bump.alloc_str(values)
+}
+
+/// Applies `transform` to the name joined from `metric`, keeping owned results in `bump`.
+fn transform_name<'a>(transform: &NameTransform, metric: &[&'a str], bump: &'a Bump) -> &'a str {
+    let name = match metric {
+        [name] => name,
+        parts => {
+            let joined = bump.alloc_slice_fill_copy(parts.iter().map(|part| part.len()).sum(), 0);
+            let mut offset = 0;
+            for part in parts {
+                joined[offset..offset + part.len()].copy_from_slice(part.as_bytes());
+                offset += part.len();
+            }
+            // SAFETY: concatenated UTF-8 strings are valid UTF-8.
+            unsafe { std::str::from_utf8_unchecked(joined) }
+        }
+    };
+    match transform.apply(name) {
+        Cow::Borrowed(name) => name,
+        Cow::Owned(name) => bump.alloc_str(&name),
+    }
+}
+
+/// Returns the name joined from `metric` with every `from` replaced by `to`,
+/// allocated in `bump`, or `None` when no part contains `from`.
+fn replace_separator<'a>(metric: &[&str], from: char, to: char, bump: &'a Bump) -> Option<&'a str> {
+    if !metric.iter().any(|part| part.contains(from)) {
+        return None;
+    }
+    let len = metric.iter().map(|part| part.len()).sum::<usize>();
+    let mut name = bumpalo::collections::String::with_capacity_in(len, bump);
+    for part in metric {
+        name.extend(part.chars().map(|c| if c == from { to } else { c }));
+    }
+    Some(name.into_bump_str())
+}
+
+/// Appends a `unit:<unit>` tag to `tags`, copying into `bump` only when a unit is set.
+fn with_unit_tag<'a>(tags: &'a str, unit: Option<&str>, bump: &'a Bump) -> &'a str {
+    const UNIT_TAG_PREFIX: &str = "unit:";
+    let Some(unit) = unit else {
+        return tags;
+    };
+    let unit_tag_start = if tags.is_empty() { 0 } else { tags.len() + 1 };
+    let unit_start = unit_tag_start + UNIT_TAG_PREFIX.len();
+    let joined = bump.alloc_slice_fill_copy(unit_start + unit.len(), b',');
+    joined[..tags.len()].copy_from_slice(tags.as_bytes());
+    joined[unit_tag_start..unit_start].copy_from_slice(UNIT_TAG_PREFIX.as_bytes());
+    joined[unit_start..].copy_from_slice(unit.as_bytes());
+    // SAFETY: UTF-8 strings joined by ASCII bytes are valid UTF-8.
+    unsafe { std::str::from_utf8_unchecked(joined) }
+}
+
+/// Joins `metric` and the values of `tags` with dots into `bump`, e.g.
+/// `request.count` and `endpoint:api,method:get` into `request.count.api.get`.
+///
+/// Tags without a `:` are used whole and empty values are skipped.
+fn fold_tags_into_name<'a>(metric: &[&str], tags: &str, bump: &'a Bump) -> &'a str {
+    let values = || {
+        tags.split(',')
+            .map(|tag| tag.split_once(':').map_or(tag, |(_, value)| value))
+            .filter(|value| !value.is_empty())
+    };
+    let len = metric.iter().map(|part| part.len()).sum::<usize>()
+        + values().map(|value| value.len() + 1).sum::<usize>();
+    let name = bump.alloc_slice_fill_copy(len, b'.');
+    let mut offset = 0;
+    for part in metric {
+        name[offset..offset + part.len()].copy_from_slice(part.as_bytes());
+        offset += part.len();
+    }
+    for value in values() {
+        // the byte at `offset` stays the separating dot
+        offset += 1;
+        name[offset..offset + value.len()].copy_from_slice(value.as_bytes());
+        offset += value.len();
+    }
+    // SAFETY: UTF-8 strings joined by ASCII dots are valid UTF-8.
+    unsafe { std::str::from_utf8_unchecked(name) }
+}
+
+/// Work driven by [`run_flush_loop`], split out so the loop can be exercised without a socket.
+trait FlushJob {
+    fn send_metrics(&mut self) -> SendResult;
+    fn has_pending_drain(&self) -> bool;
+    fn apply(&mut self, command: JobCommand);
+    /// Drops state left behind by a flush that panicked part way through.
+    fn recover(&mut self);
+    /// Returns whether scheduled flushes are skipped. Explicit flushes and the
+    /// final one still run.
+    fn is_paused(&self) -> bool {
+        false
+    }
+    /// Returns whether the writer asks to skip this flush cycle. Not checked
+    /// for the final flush.
+    fn would_block(&self) -> bool {
+        false
+    }
+}
+
+impl<MC> FlushJob for MetricCollectorJob<MC>
+where
+    MC: DrainMetricCollectorTrait + Send + Sync + 'static,
+    MC::Hasher: BuildHasher + Clone + Send + Sync + 'static,
+{
+    fn send_metrics(&mut self) -> SendResult {
+        if let Some(destination) = self
+            .destination_refresh
+            .as_mut()
+            .and_then(|refresh| refresh.poll(Instant::now(), &self.progress))
+        {
+            self.stats_writer.set_destination(destination);
+        }
+        Self::send_metrics(self)
+    }
+
+    fn has_pending_drain(&self) -> bool {
+        self.collector.has_pending_drain()
+    }
+
+    fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Acquire)
+    }
+
+    fn would_block(&self) -> bool {
+        self.stats_writer.would_block()
+    }
+
+    fn apply(&mut self, command: JobCommand) {
+        match command {
+            JobCommand::SetDestination(destination) => {
+                // an explicit destination replaces the one resolved from the host name
+                self.destination_refresh = None;
+                self.stats_writer.set_destination(destination);
+            }
+            // acknowledged by `run_flush_loop` once the flush is done
+            JobCommand::Flush(_) => {}
+        }
+    }
+
+    fn recover(&mut self) {
+        // The writer may still reference the bump and the drained entries of
+        // the aborted flush, reset it before anything it points to is freed
+        // rather than relying on the guard having been dropped while unwinding.
+        self.stats_writer.reset();
+        self.pending_raw_timings.clear();
+        self.bump.reset();
+        self.progress.flushed(false);
+        self.collector
+            .count(RylvStr::from_static(FLUSH_THREAD_RESTART_METRIC), []);
+    }
+}
+
+/// Flush interval randomized within `[interval - jitter, interval + jitter]`.
+///
+/// Each instance is seeded independently, so collectors started together drift
+/// apart instead of flushing in lockstep.
+struct JitteredInterval {
+    interval: Duration,
+    jitter: Duration,
+    state: u64,
+}
+
+impl JitteredInterval {
+    fn new(interval: Duration, jitter: Duration) -> Self {
+        Self {
+            interval,
+            jitter: jitter.min(interval),
+            // xorshift needs a non-zero state
+            state: RandomState::new().hash_one(Instant::now()) | 1,
         }
     }
+
+    fn next_interval(&mut self) -> Duration {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        let span = self.jitter.as_nanos() * 2 + 1;
+        let offset = u64::try_from(u128::from(self.state) % span).unwrap_or(u64::MAX);
+        self.interval
+            .saturating_sub(self.jitter)
+            .saturating_add(Duration::from_nanos(offset))
+    }
 }
 
 pub fn initialize_job<MC>(
-    flush_interval: Duration,
-    receiver: &Receiver<()>,
+    options: JobOptions,
+    receiver: &Receiver<JobCommand>,
     collector: Arc<MC>,
+    raw_timings: Arc<SegQueue<RawTiming>>,
+    emergency_counters: Arc<EmergencyCounters>,
     stats_writer: StatsWriterHolder,
 ) -> MetricResult<()>
 where
@@ -143,34 +928,582 @@ where
     let mut job = MetricCollectorJob {
         stats_writer,
         collector,
+        raw_timings,
+        pending_raw_timings: Vec::new(),
+        emergency_counters,
         buffer: Buffer::new(),
+        float_buffer: ryu::Buffer::new(),
+        packed_values: String::new(),
         bump: Bump::with_capacity(20 * 1024),
+        suffix: options.suffix,
+        wire_format: options.wire_format,
+        name_transform: options.name_transform,
+        name_separator_replace: options.name_separator_replace,
+        max_flush_duration: options.max_flush_duration,
+        packet_sequence_tag: options.packet_sequence_tag,
+        sequence: 0,
+        derived_metrics: options.derived_metrics,
+        derived_recorded: false,
+        destination_refresh: options.destination_refresh,
+        progress: Arc::clone(&options.progress),
+        paused: options.paused,
     };
 
-    let large_tick = tick(flush_interval);
     let shorter_tick = tick(Duration::from_millis(10));
+    let restart_on_panic = options.restart_on_panic;
+    let restarts = &options.restarts;
+    let progress = &options.progress;
+    if options.flush_jitter.is_zero() {
+        let large_tick = tick(options.flush_interval);
+        run_restarting(&mut job, restart_on_panic, restarts, progress, |job| {
+            run_flush_loop(job, || large_tick.clone(), &shorter_tick, receiver)
+        })
+    } else {
+        // A fixed-period ticker cannot vary its period, so arm a one-shot timer per flush
+        let mut interval = JitteredInterval::new(options.flush_interval, options.flush_jitter);
+        run_restarting(&mut job, restart_on_panic, restarts, progress, |job| {
+            run_flush_loop(
+                job,
+                || after(interval.next_interval()),
+                &shorter_tick,
+                receiver,
+            )
+        })
+    }
+}
+
+/// Runs `flush_loop` until it returns. When `restart_on_panic` is set, a panic is
+/// logged, kept in `progress` and counted, the job recovers and the loop starts over, so a single bad
+/// flush cannot stop metrics from being sent for the rest of the process.
+fn run_restarting<J: FlushJob>(
+    job: &mut J,
+    restart_on_panic: bool,
+    restarts: &AtomicU64,
+    progress: &FlushProgress,
+    mut flush_loop: impl FnMut(&mut J) -> MetricResult<()>,
+) -> MetricResult<()> {
+    loop {
+        match catch_unwind(AssertUnwindSafe(|| flush_loop(job))) {
+            Ok(result) => return result,
+            Err(panic) if restart_on_panic => {
+                let message = panic
+                    .downcast_ref::<&str>()
+                    .copied()
+                    .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+                    .unwrap_or("unknown panic");
+                progress.log_error(format_args!("Flush thread panicked, restarting: {message}"));
+                restarts.fetch_add(1, Ordering::Relaxed);
+                job.recover();
+            }
+            Err(panic) => resume_unwind(panic),
+        }
+    }
+}
+
+/// Flushes whenever the tick returned by `next_flush_tick` fires, until `receiver`
+/// is disconnected, retrying blocked drains on `shorter_tick`. A new flush tick is
+/// requested after every flush, and after a flush skipped because the writer would block.
+/// Commands are applied as they arrive, between flushes; [`JobCommand::Flush`] starts a
+/// flush immediately and is acknowledged once a flush is done.
+///
+/// A failing ticker ends the loop with an error instead of retrying without waiting.
+fn run_flush_loop(
+    job: &mut impl FlushJob,
+    mut next_flush_tick: impl FnMut() -> Receiver<Instant>,
+    shorter_tick: &Receiver<Instant>,
+    receiver: &Receiver<JobCommand>,
+) -> MetricResult<()> {
     let mut finish = false;
+    let mut flush_acks = Vec::new();
+    let mut paused_ticks = 0;
+    let mut large_tick = next_flush_tick();
 
     loop {
         select! {
-            recv(large_tick) -> _ => (),
-            recv(receiver) -> _ => finish = true,
+            recv(large_tick) -> tick => {
+                if let Err(err) = tick {
+                    return Err(format!("Error awaiting flush tick: {err}").into());
+                }
+            }
+            recv(receiver) -> command => match command {
+                Ok(JobCommand::Flush(ack)) => flush_acks.push(ack),
+                Ok(command) => {
+                    job.apply(command);
+                    continue;
+                }
+                Err(_) => finish = true,
+            },
+        }
+
+        if !finish && flush_acks.is_empty() && job.is_paused() {
+            paused_ticks += 1;
+            if paused_ticks == PAUSED_TICKS_WARNING {
+                warn!("Flushing paused for {paused_ticks} intervals, metrics keep accumulating");
+            }
+            large_tick = next_flush_tick();
+            continue;
+        }
+        paused_ticks = 0;
+
+        // The final flush and explicit flushes send everything recorded so far
+        let complete = finish || !flush_acks.is_empty();
+
+        // ... so they go ahead, a writer that keeps blocking must not hold up
+        // shutdown or `quiesce`
+        if !complete && job.would_block() {
+            large_tick = next_flush_tick();
+            continue;
         }
 
         loop {
+            // A drain that starts with pending aggregators may leave the current one behind
+            let had_pending = complete && job.has_pending_drain();
             match job.send_metrics() {
                 SendResult::WouldBlock => {
                     if let Err(err) = shorter_tick.recv() {
-                        error!("Error awaiting shorter tick: {err}");
+                        return Err(format!("Error awaiting shorter tick: {err}").into());
                     }
                 }
                 SendResult::Ok => {
+                    // A drain stopped by `max_flush_duration` leaves work pending
+                    if had_pending || complete && job.has_pending_drain() {
+                        continue;
+                    }
+                    for ack in std::mem::take(&mut flush_acks) {
+                        let _ = ack.send(());
+                    }
                     if finish {
                         return Ok(());
                     }
+                    large_tick = next_flush_tick();
                     break;
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        fold_tags_into_name, run_flush_loop, run_restarting, transform_name, with_unit_tag,
+        DestinationRefresh, FlushJob, FlushProgress, JitteredInterval, JobCommand, NameTransform,
+        SendResult, SuffixTemplate,
+    };
+    use bumpalo::Bump;
+    use crossbeam::channel::{after, bounded, never, unbounded, Receiver};
+    use std::borrow::Cow;
+    use std::cell::Cell;
+    use std::net::SocketAddr;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    struct BlockedJob {
+        attempts: usize,
+    }
+
+    impl FlushJob for BlockedJob {
+        fn send_metrics(&mut self) -> SendResult {
+            self.attempts += 1;
+            SendResult::WouldBlock
+        }
+
+        fn has_pending_drain(&self) -> bool {
+            false
+        }
+
+        fn apply(&mut self, _command: JobCommand) {}
+
+        fn recover(&mut self) {}
+    }
+
+    fn disconnected_tick() -> Receiver<Instant> {
+        bounded(0).1
+    }
+
+    #[test]
+    fn flush_loop_stops_when_shorter_tick_fails() {
+        let (large_sender, large_tick) = unbounded();
+        large_sender.send(Instant::now()).unwrap();
+        let (_shutdown_sender, receiver) = unbounded();
+        let mut job = BlockedJob { attempts: 0 };
+
+        let result = run_flush_loop(
+            &mut job,
+            || large_tick.clone(),
+            &disconnected_tick(),
+            &receiver,
+        );
+
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Error awaiting shorter tick"));
+        assert_eq!(job.attempts, 1);
+    }
+
+    #[test]
+    fn flush_loop_stops_when_flush_tick_fails() {
+        let (_shutdown_sender, receiver) = unbounded();
+        let mut job = BlockedJob { attempts: 0 };
+
+        let result = run_flush_loop(&mut job, disconnected_tick, &disconnected_tick(), &receiver);
+
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Error awaiting flush tick"));
+        assert_eq!(job.attempts, 0);
+    }
+
+    #[test]
+    fn flush_loop_restarts_after_panic_only_when_enabled() {
+        let restarts = AtomicU64::new(0);
+        let progress = FlushProgress::with_recent_errors(4);
+        let mut job = BlockedJob { attempts: 0 };
+
+        let result = run_restarting(&mut job, true, &restarts, &progress, |job| {
+            job.attempts += 1;
+            assert!(job.attempts > 1, "first flush loop panics");
+            Ok(())
+        });
+
+        assert!(result.is_ok());
+        assert_eq!(job.attempts, 2);
+        assert_eq!(restarts.load(Ordering::Relaxed), 1);
+        assert_eq!(
+            progress.recent_errors(),
+            ["Flush thread panicked, restarting: first flush loop panics"]
+        );
+
+        let outcome = catch_unwind(AssertUnwindSafe(|| {
+            run_restarting(&mut job, false, &restarts, &progress, |_| {
+                panic!("flush loop panics")
+            })
+        }));
+        assert!(outcome.is_err());
+        assert_eq!(restarts.load(Ordering::Relaxed), 1);
+    }
+
+    struct RecordingJob {
+        flushes: Vec<Instant>,
+    }
+
+    impl FlushJob for RecordingJob {
+        fn send_metrics(&mut self) -> SendResult {
+            self.flushes.push(Instant::now());
+            SendResult::Ok
+        }
+
+        fn has_pending_drain(&self) -> bool {
+            false
+        }
+
+        fn apply(&mut self, _command: JobCommand) {}
+
+        fn recover(&mut self) {}
+    }
+
+    #[test]
+    fn flush_command_flushes_before_the_next_tick_and_acknowledges() {
+        let (command_sender, receiver) = unbounded();
+        let handle = std::thread::spawn(move || {
+            let mut job = RecordingJob {
+                flushes: Vec::new(),
+            };
+            run_flush_loop(&mut job, never, &never(), &receiver).unwrap();
+            job.flushes
+        });
+
+        let (ack, flushed) = bounded(1);
+        command_sender.send(JobCommand::Flush(ack)).unwrap();
+        flushed.recv_timeout(Duration::from_secs(5)).unwrap();
+        drop(command_sender);
+
+        // the requested flush, then the final one on disconnect
+        assert_eq!(handle.join().unwrap().len(), 2);
+    }
+
+    struct BlockingWriterJob {
+        checks: Cell<usize>,
+        flushes: usize,
+    }
+
+    impl FlushJob for BlockingWriterJob {
+        fn send_metrics(&mut self) -> SendResult {
+            self.flushes += 1;
+            SendResult::Ok
+        }
+
+        fn has_pending_drain(&self) -> bool {
+            false
+        }
+
+        fn apply(&mut self, _command: JobCommand) {}
+
+        fn recover(&mut self) {}
+
+        fn would_block(&self) -> bool {
+            self.checks.set(self.checks.get() + 1);
+            true
+        }
+    }
+
+    #[test]
+    fn flush_loop_skips_cycles_while_the_writer_blocks_but_not_the_final_flush() {
+        let (command_sender, receiver) = unbounded::<JobCommand>();
+        let command_sender = Cell::new(Some(command_sender));
+        let mut ticks = 0;
+        let mut job = BlockingWriterJob {
+            checks: Cell::new(0),
+            flushes: 0,
+        };
+
+        // a skipped cycle waiting on the disconnected shorter tick would fail the loop
+        let result = run_flush_loop(
+            &mut job,
+            || {
+                ticks += 1;
+                if ticks > 3 {
+                    drop(command_sender.take());
+                    return never();
+                }
+                after(Duration::ZERO)
+            },
+            &disconnected_tick(),
+            &receiver,
+        );
+
+        assert!(result.is_ok());
+        assert_eq!(job.checks.get(), 3);
+        assert_eq!(job.flushes, 1);
+    }
+
+    #[test]
+    fn flush_command_goes_ahead_while_the_writer_blocks() {
+        let (command_sender, receiver) = unbounded();
+        let mut job = BlockingWriterJob {
+            checks: Cell::new(0),
+            flushes: 0,
+        };
+
+        let (ack, flushed) = bounded(1);
+        command_sender.send(JobCommand::Flush(ack)).unwrap();
+        drop(command_sender);
+        let result = run_flush_loop(&mut job, never, &disconnected_tick(), &receiver);
+
+        assert!(result.is_ok());
+        assert!(flushed.try_recv().is_ok());
+        assert_eq!(job.checks.get(), 0);
+        // the requested flush, then the final one on disconnect
+        assert_eq!(job.flushes, 2);
+    }
+
+    struct PendingDrainJob {
+        pending: usize,
+        flushes: usize,
+        flushed: Receiver<()>,
+        acked_after: Option<usize>,
+    }
+
+    impl FlushJob for PendingDrainJob {
+        fn send_metrics(&mut self) -> SendResult {
+            if self.acked_after.is_none() && self.flushed.try_recv().is_ok() {
+                self.acked_after = Some(self.flushes);
+            }
+            self.flushes += 1;
+            self.pending = self.pending.saturating_sub(1);
+            SendResult::Ok
+        }
+
+        fn has_pending_drain(&self) -> bool {
+            self.pending > 0
+        }
+
+        fn apply(&mut self, _command: JobCommand) {}
+
+        fn recover(&mut self) {}
+    }
+
+    #[test]
+    fn flush_command_acknowledges_once_pending_drains_are_sent() {
+        let (command_sender, receiver) = unbounded();
+        let (ack, flushed) = bounded(1);
+        command_sender.send(JobCommand::Flush(ack)).unwrap();
+        drop(command_sender);
+        // two drains left behind, e.g. older aggregators or a drain cut short
+        let mut job = PendingDrainJob {
+            pending: 2,
+            flushes: 0,
+            flushed,
+            acked_after: None,
+        };
+
+        let result = run_flush_loop(&mut job, never, &disconnected_tick(), &receiver);
+
+        assert!(result.is_ok());
+        // both pending drains and the current window, then the final flush
+        assert_eq!(job.acked_after, Some(3));
+        assert_eq!(job.flushes, 4);
+    }
+
+    #[test]
+    fn jittered_interval_stays_within_bounds_and_varies() {
+        let interval = Duration::from_millis(100);
+        let jitter = Duration::from_millis(40);
+        let mut jittered = JitteredInterval::new(interval, jitter);
+
+        let samples: Vec<Duration> = (0..1_000).map(|_| jittered.next_interval()).collect();
+
+        assert!(samples.iter().all(|sample| (Duration::from_millis(60)
+            ..=Duration::from_millis(140))
+            .contains(sample)));
+        let below = samples.iter().filter(|sample| **sample < interval).count();
+        assert!((300..=700).contains(&below), "skewed jitter: {below} below");
+
+        // jitter larger than the interval is capped so intervals never underflow
+        let mut capped = JitteredInterval::new(interval, Duration::from_secs(10));
+        assert!((0..100).all(|_| capped.next_interval() <= interval * 2));
+    }
+
+    #[test]
+    fn flush_loop_spacing_varies_with_jitter() {
+        let (shutdown_sender, receiver) = unbounded();
+        let handle = std::thread::spawn(move || {
+            let mut job = RecordingJob {
+                flushes: Vec::new(),
+            };
+            let mut interval =
+                JitteredInterval::new(Duration::from_millis(10), Duration::from_millis(8));
+            run_flush_loop(
+                &mut job,
+                || after(interval.next_interval()),
+                &never(),
+                &receiver,
+            )
+            .unwrap();
+            job.flushes
+        });
+        std::thread::sleep(Duration::from_millis(400));
+        drop(shutdown_sender);
+        let flushes = handle.join().unwrap();
+
+        let spacings: Vec<Duration> = flushes.windows(2).map(|pair| pair[1] - pair[0]).collect();
+        assert!(spacings.len() >= 10, "too few flushes: {}", spacings.len());
+        let shortest = spacings.iter().min().unwrap();
+        let longest = spacings.iter().max().unwrap();
+        assert!(
+            longest.saturating_sub(*shortest) >= Duration::from_millis(5),
+            "flush spacing did not vary: {shortest:?}..{longest:?}"
+        );
+    }
+
+    #[test]
+    fn destination_refresh_reports_only_changed_addresses() {
+        let first: SocketAddr = "10.0.0.1:8125".parse().unwrap();
+        let second: SocketAddr = "10.0.0.2:8125".parse().unwrap();
+        let lookups = Arc::new(Mutex::new(vec![
+            Ok(second),
+            Err("lookup failed".into()),
+            Ok(second),
+            Ok(first),
+        ]));
+        let mut refresh = DestinationRefresh::new(
+            {
+                let lookups = Arc::clone(&lookups);
+                move || lookups.lock().unwrap().pop().unwrap()
+            },
+            first,
+            Duration::from_secs(30),
+        );
+        let progress = FlushProgress::default();
+
+        let start = Instant::now();
+        assert_eq!(refresh.poll(start, &progress), None, "not due yet");
+        let due = start + Duration::from_secs(30);
+        assert_eq!(refresh.poll(due, &progress), None, "same address");
+        assert_eq!(refresh.poll(due, &progress), None, "next lookup not due");
+        let due = due + Duration::from_secs(30);
+        assert_eq!(refresh.poll(due, &progress), Some(second));
+        let due = due + Duration::from_secs(30);
+        assert_eq!(
+            refresh.poll(due, &progress),
+            None,
+            "failed lookup keeps the address"
+        );
+        let due = due + Duration::from_secs(30);
+        assert_eq!(
+            refresh.poll(due, &progress),
+            None,
+            "already moved to this address"
+        );
+        assert!(lookups.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn suffix_template_is_appended_to_every_tag_set() {
+        let bump = Bump::new();
+        let entity = SuffixTemplate::new(Some("dd.internal.entity_id:pod-1"));
+
+        assert_eq!(SuffixTemplate::default().apply("a:1,b:2", &bump), "a:1,b:2");
+        assert_eq!(entity.apply("", &bump), "dd.internal.entity_id:pod-1");
+        assert_eq!(
+            entity.apply("a:1,b:2", &bump),
+            "a:1,b:2,dd.internal.entity_id:pod-1"
+        );
+
+        let joined = SuffixTemplate::new(["env:prod", "", "region:eu"]);
+        assert_eq!(joined.apply("a:1", &bump), "a:1,env:prod,region:eu");
+        assert_eq!(SuffixTemplate::new([""]).apply("", &bump), "");
+    }
+
+    #[test]
+    fn plain_statsd_folds_tag_values_into_the_name() {
+        let bump = Bump::new();
+
+        assert_eq!(
+            fold_tags_into_name(&["app.", "request.count"], "endpoint:api,method:get", &bump),
+            "app.request.count.api.get"
+        );
+        assert_eq!(
+            fold_tags_into_name(&["jobs"], "critical,env:,queue:email", &bump),
+            "jobs.critical.email"
+        );
+    }
+
+    #[test]
+    fn name_transform_sees_the_joined_name() {
+        let bump = Bump::new();
+        let tenant = NameTransform::new(|name| {
+            if name.starts_with("rylv_metrics.") {
+                Cow::Borrowed(name)
+            } else {
+                Cow::Owned(format!("tenant-a.{name}"))
+            }
+        });
+
+        assert_eq!(
+            transform_name(&tenant, &["app.", "latency", ".count"], &bump),
+            "tenant-a.app.latency.count"
+        );
+        let internal = "rylv_metrics.flush_thread.restart";
+        assert!(std::ptr::eq(
+            transform_name(&tenant, &[internal], &bump),
+            internal
+        ));
+    }
+
+    #[test]
+    fn unit_tag_is_appended_after_the_recorded_tags() {
+        let bump = Bump::new();
+
+        assert_eq!(with_unit_tag("a:1,b:2", None, &bump), "a:1,b:2");
+        assert_eq!(with_unit_tag("", Some("ms"), &bump), "unit:ms");
+        assert_eq!(
+            with_unit_tag("a:1,b:2", Some("ms"), &bump),
+            "a:1,b:2,unit:ms"
+        );
+    }
+}
@@ -2,21 +2,16 @@
 ///
 /// # Performance
 ///
-/// **This macro is less efficient than calling the trait methods directly.**
-/// It uses `RylvStr::from()` for metric names and tags, which converts `&str` literals
-/// into `RylvStr::Borrowed`. When the aggregator stores a new metric key, `Borrowed`
-/// values require a heap allocation via `RylvStr::to_cow()` (`Cow::Owned`).
-///
-/// In contrast, calling the trait methods directly with `RylvStr::from_static()` produces
-/// `RylvStr::Static`, which converts to `Cow::Borrowed` — **zero-copy, no allocation**.
-///
-/// For hot paths where metric names and tags are known at compile time, prefer the direct API:
+/// String literals passed as the metric name or as tags are wrapped in
+/// `RylvStr::from_static()`, so they take the same zero-copy path as the direct
+/// API when the aggregator stores a new key. Any other expression goes through
+/// `RylvStr::from()`: a borrowed `&str` variable becomes `RylvStr::Borrowed`
+/// and is copied on first key insertion.
 ///
 /// ```ignore
-/// // Preferred: zero-copy on aggregator storage
+/// // Equivalent: both store the key without copying the literals
 /// collector.histogram(RylvStr::from_static("latency"), 42, &mut [RylvStr::from_static("env:prod")]);
 ///
-/// // Macro: convenient but allocates when storing new keys
 /// histogram!(collector, "latency", 42, "env:prod");
 /// ```
 ///
@@ -52,19 +47,18 @@
 /// ```
 #[macro_export]
 macro_rules! histogram {
-    // With tags
-    ($collector:expr, $metric:expr, $value:expr $(, $tag:expr)+) => {
+    // Literal metric name, stored without copying
+    ($collector:expr, $metric:literal, $value:expr $(, $($tag:tt)+)?) => {
         {
             #[allow(unused_mut)]
-            let mut tags = [$($crate::RylvStr::from($tag)),*];
-            $collector.histogram($crate::RylvStr::from($metric), $value, &mut tags)
+            let mut tags = $crate::__rylv_tags!($($($tag)+)?);
+            $collector.histogram($crate::RylvStr::from_static($metric), $value, &mut tags)
         }
     };
-    // Without tags
-    ($collector:expr, $metric:expr, $value:expr) => {
+    ($collector:expr, $metric:expr, $value:expr $(, $($tag:tt)+)?) => {
         {
             #[allow(unused_mut)]
-            let mut tags: [$crate::RylvStr<'static>; 0] = [];
+            let mut tags = $crate::__rylv_tags!($($($tag)+)?);
             $collector.histogram($crate::RylvStr::from($metric), $value, &mut tags)
         }
     };
@@ -74,21 +68,16 @@ macro_rules! histogram {
 ///
 /// # Performance
 ///
-/// **This macro is less efficient than calling the trait methods directly.**
-/// It uses `RylvStr::from()` for metric names and tags, which converts `&str` literals
-/// into `RylvStr::Borrowed`. When the aggregator stores a new metric key, `Borrowed`
-/// values require a heap allocation via `RylvStr::to_cow()` (`Cow::Owned`).
-///
-/// In contrast, calling the trait methods directly with `RylvStr::from_static()` produces
-/// `RylvStr::Static`, which converts to `Cow::Borrowed` — **zero-copy, no allocation**.
-///
-/// For hot paths where metric names and tags are known at compile time, prefer the direct API:
+/// String literals passed as the metric name or as tags are wrapped in
+/// `RylvStr::from_static()`, so they take the same zero-copy path as the direct
+/// API when the aggregator stores a new key. Any other expression goes through
+/// `RylvStr::from()`: a borrowed `&str` variable becomes `RylvStr::Borrowed`
+/// and is copied on first key insertion.
 ///
 /// ```ignore
-/// // Preferred: zero-copy on aggregator storage
+/// // Equivalent: both store the key without copying the literals
 /// collector.count(RylvStr::from_static("requests"), &mut [RylvStr::from_static("env:prod")]);
 ///
-/// // Macro: convenient but allocates when storing new keys
 /// count!(collector, "requests", "env:prod");
 /// ```
 ///
@@ -118,19 +107,18 @@ macro_rules! histogram {
 /// ```
 #[macro_export]
 macro_rules! count {
-    // With tags
-    ($collector:expr, $metric:expr $(, $tag:expr)+) => {
+    // Literal metric name, stored without copying
+    ($collector:expr, $metric:literal $(, $($tag:tt)+)?) => {
         {
             #[allow(unused_mut)]
-            let mut tags = [$($crate::RylvStr::from($tag)),*];
-            $collector.count($crate::RylvStr::from($metric), &mut tags)
+            let mut tags = $crate::__rylv_tags!($($($tag)+)?);
+            $collector.count($crate::RylvStr::from_static($metric), &mut tags)
         }
     };
-    // Without tags
-    ($collector:expr, $metric:expr) => {
+    ($collector:expr, $metric:expr $(, $($tag:tt)+)?) => {
         {
             #[allow(unused_mut)]
-            let mut tags: [$crate::RylvStr<'static>; 0] = [];
+            let mut tags = $crate::__rylv_tags!($($($tag)+)?);
             $collector.count($crate::RylvStr::from($metric), &mut tags)
         }
     };
@@ -140,21 +128,16 @@ macro_rules! count {
 ///
 /// # Performance
 ///
-/// **This macro is less efficient than calling the trait methods directly.**
-/// It uses `RylvStr::from()` for metric names and tags, which converts `&str` literals
-/// into `RylvStr::Borrowed`. When the aggregator stores a new metric key, `Borrowed`
-/// values require a heap allocation via `RylvStr::to_cow()` (`Cow::Owned`).
-///
-/// In contrast, calling the trait methods directly with `RylvStr::from_static()` produces
-/// `RylvStr::Static`, which converts to `Cow::Borrowed` — **zero-copy, no allocation**.
-///
-/// For hot paths where metric names and tags are known at compile time, prefer the direct API:
+/// String literals passed as the metric name or as tags are wrapped in
+/// `RylvStr::from_static()`, so they take the same zero-copy path as the direct
+/// API when the aggregator stores a new key. Any other expression goes through
+/// `RylvStr::from()`: a borrowed `&str` variable becomes `RylvStr::Borrowed`
+/// and is copied on first key insertion.
 ///
 /// ```ignore
-/// // Preferred: zero-copy on aggregator storage
+/// // Equivalent: both store the key without copying the literals
 /// collector.count_add(RylvStr::from_static("bytes.sent"), 1024, &mut [RylvStr::from_static("env:prod")]);
 ///
-/// // Macro: convenient but allocates when storing new keys
 /// count_add!(collector, "bytes.sent", 1024, "env:prod");
 /// ```
 ///
@@ -184,19 +167,18 @@ macro_rules! count {
 /// ```
 #[macro_export]
 macro_rules! count_add {
-    // With tags
-    ($collector:expr, $metric:expr, $value:expr $(, $tag:expr)+) => {
+    // Literal metric name, stored without copying
+    ($collector:expr, $metric:literal, $value:expr $(, $($tag:tt)+)?) => {
         {
             #[allow(unused_mut)]
-            let mut tags = [$($crate::RylvStr::from($tag)),*];
-            $collector.count_add($crate::RylvStr::from($metric), $value, &mut tags)
+            let mut tags = $crate::__rylv_tags!($($($tag)+)?);
+            $collector.count_add($crate::RylvStr::from_static($metric), $value, &mut tags)
         }
     };
-    // Without tags
-    ($collector:expr, $metric:expr, $value:expr) => {
+    ($collector:expr, $metric:expr, $value:expr $(, $($tag:tt)+)?) => {
         {
             #[allow(unused_mut)]
-            let mut tags: [$crate::RylvStr<'static>; 0] = [];
+            let mut tags = $crate::__rylv_tags!($($($tag)+)?);
             $collector.count_add($crate::RylvStr::from($metric), $value, &mut tags)
         }
     };
@@ -206,21 +188,16 @@ macro_rules! count_add {
 ///
 /// # Performance
 ///
-/// **This macro is less efficient than calling the trait methods directly.**
-/// It uses `RylvStr::from()` for metric names and tags, which converts `&str` literals
-/// into `RylvStr::Borrowed`. When the aggregator stores a new metric key, `Borrowed`
-/// values require a heap allocation via `RylvStr::to_cow()` (`Cow::Owned`).
-///
-/// In contrast, calling the trait methods directly with `RylvStr::from_static()` produces
-/// `RylvStr::Static`, which converts to `Cow::Borrowed` — **zero-copy, no allocation**.
-///
-/// For hot paths where metric names and tags are known at compile time, prefer the direct API:
+/// String literals passed as the metric name or as tags are wrapped in
+/// `RylvStr::from_static()`, so they take the same zero-copy path as the direct
+/// API when the aggregator stores a new key. Any other expression goes through
+/// `RylvStr::from()`: a borrowed `&str` variable becomes `RylvStr::Borrowed`
+/// and is copied on first key insertion.
 ///
 /// ```ignore
-/// // Preferred: zero-copy on aggregator storage
+/// // Equivalent: both store the key without copying the literals
 /// collector.gauge(RylvStr::from_static("connections"), 42, &mut [RylvStr::from_static("pool:main")]);
 ///
-/// // Macro: convenient but allocates when storing new keys
 /// gauge!(collector, "connections", 42, "pool:main");
 /// ```
 ///
@@ -250,30 +227,91 @@ macro_rules! count_add {
 /// ```
 #[macro_export]
 macro_rules! gauge {
-    // With tags
-    ($collector:expr, $metric:expr, $value:expr $(, $tag:expr)+) => {
+    // Literal metric name, stored without copying
+    ($collector:expr, $metric:literal, $value:expr $(, $($tag:tt)+)?) => {
         {
             #[allow(unused_mut)]
-            let mut tags = [$($crate::RylvStr::from($tag)),*];
-            $collector.gauge($crate::RylvStr::from($metric), $value, &mut tags)
+            let mut tags = $crate::__rylv_tags!($($($tag)+)?);
+            $collector.gauge($crate::RylvStr::from_static($metric), $value, &mut tags)
         }
     };
-    // Without tags
-    ($collector:expr, $metric:expr, $value:expr) => {
+    ($collector:expr, $metric:expr, $value:expr $(, $($tag:tt)+)?) => {
         {
             #[allow(unused_mut)]
-            let mut tags: [$crate::RylvStr<'static>; 0] = [];
+            let mut tags = $crate::__rylv_tags!($($($tag)+)?);
             $collector.gauge($crate::RylvStr::from($metric), $value, &mut tags)
         }
     };
 }
 
+/// Macro building a [`RylvStr::Static`](crate::RylvStr::Static) metric name from
+/// a literal checked at compile time.
+///
+/// The literal must be non-empty and must not contain `:`, `|`, `#` or a
+/// newline, which would corrupt the line on the wire. The result takes the
+/// zero-copy path when the aggregator stores a new key.
+///
+/// ```
+/// use rylv_metrics::{metric, RylvStr};
+///
+/// const LATENCY: RylvStr<'static> = metric!("request.latency");
+/// assert!(matches!(LATENCY, RylvStr::Static("request.latency")));
+/// assert!(matches!(metric!("jobs.done"), RylvStr::Static(_)));
+/// ```
+///
+/// Names that would break the wire format do not compile:
+/// ```compile_fail
+/// let name = rylv_metrics::metric!("request|latency");
+/// ```
+///
+/// ```compile_fail
+/// let name = rylv_metrics::metric!("");
+/// ```
+#[macro_export]
+macro_rules! metric {
+    ($name:literal) => {{
+        const NAME: &str = $name;
+        const {
+            assert!(
+                $crate::RylvStr::is_valid_metric_name(NAME),
+                "metric names must be non-empty and free of `:`, `|`, `#` and newlines"
+            );
+        }
+        $crate::RylvStr::from_static(NAME)
+    }};
+}
+
+/// Builds the tag array of the recording macros from comma-separated tags.
+///
+/// String literals become `RylvStr::Static` and other expressions go through
+/// `RylvStr::from`; no tags yield an empty array.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __rylv_tags {
+    (@acc []) => {{
+        let tags: [$crate::RylvStr<'static>; 0] = [];
+        tags
+    }};
+    (@acc [$($out:expr),+]) => {
+        [$($out),+]
+    };
+    (@acc [$($out:expr),*] $tag:literal $(, $($rest:tt)*)?) => {
+        $crate::__rylv_tags!(@acc [$($out,)* $crate::RylvStr::from_static($tag)] $($($rest)*)?)
+    };
+    (@acc [$($out:expr),*] $tag:expr $(, $($rest:tt)*)?) => {
+        $crate::__rylv_tags!(@acc [$($out,)* $crate::RylvStr::from($tag)] $($($rest)*)?)
+    };
+    ($($tag:tt)*) => {
+        $crate::__rylv_tags!(@acc [] $($tag)*)
+    };
+}
+
 /// Macro for building reusable [`SortedTags`](crate::SortedTags) bound to a collector's hasher.
 ///
 /// # Example
 ///
 /// ```
-/// # #[cfg(feature = "shared-collector")]
+/// # #[cfg(any(feature = "shared-collector", feature = "simple-map"))]
 /// # {
 /// use rylv_metrics::{sorted_tags, MetricCollectorTrait, SharedCollector, SharedCollectorOptions};
 ///
@@ -292,6 +330,9 @@ macro_rules! sorted_tags {
 /// Macro for recording histogram values with pre-sorted tags.
 #[macro_export]
 macro_rules! histogram_sorted {
+    ($collector:expr, $metric:literal, $value:expr, $tags:expr) => {{
+        $collector.histogram_sorted($crate::RylvStr::from_static($metric), $value, $tags)
+    }};
     ($collector:expr, $metric:expr, $value:expr, $tags:expr) => {{
         $collector.histogram_sorted($crate::RylvStr::from($metric), $value, $tags)
     }};
@@ -300,6 +341,9 @@ macro_rules! histogram_sorted {
 /// Macro for incrementing a counter by one with pre-sorted tags.
 #[macro_export]
 macro_rules! count_sorted {
+    ($collector:expr, $metric:literal, $tags:expr) => {{
+        $collector.count_sorted($crate::RylvStr::from_static($metric), $tags)
+    }};
     ($collector:expr, $metric:expr, $tags:expr) => {{
         $collector.count_sorted($crate::RylvStr::from($metric), $tags)
     }};
@@ -308,6 +352,9 @@ macro_rules! count_sorted {
 /// Macro for incrementing a counter by value with pre-sorted tags.
 #[macro_export]
 macro_rules! count_add_sorted {
+    ($collector:expr, $metric:literal, $value:expr, $tags:expr) => {{
+        $collector.count_add_sorted($crate::RylvStr::from_static($metric), $value, $tags)
+    }};
     ($collector:expr, $metric:expr, $value:expr, $tags:expr) => {{
         $collector.count_add_sorted($crate::RylvStr::from($metric), $value, $tags)
     }};
@@ -316,6 +363,9 @@ macro_rules! count_add_sorted {
 /// Macro for recording a gauge value with pre-sorted tags.
 #[macro_export]
 macro_rules! gauge_sorted {
+    ($collector:expr, $metric:literal, $value:expr, $tags:expr) => {{
+        $collector.gauge_sorted($crate::RylvStr::from_static($metric), $value, $tags)
+    }};
     ($collector:expr, $metric:expr, $value:expr, $tags:expr) => {{
         $collector.gauge_sorted($crate::RylvStr::from($metric), $value, $tags)
     }};
@@ -0,0 +1,243 @@
+#[cfg(any(
+    feature = "shared-collector",
+    feature = "simple-map",
+    feature = "tls-collector",
+    test
+))]
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[cfg(feature = "regex")]
+use crate::MetricResult;
+
+/// Allow/deny rules checked against metric names before they are recorded.
+///
+/// Filtering happens before hashing and aggregation, so a filtered metric costs
+/// nothing beyond the name check. Names are matched as passed to the collector,
+/// without the collector-wide `stats_prefix` and before `normalize_names`
+/// lowercasing. Prepared metrics are matched once, when they are prepared.
+///
+/// A name is recorded when it matches no deny rule and, if any allow rule is
+/// configured, at least one allow rule. Deny rules win over allow rules.
+///
+/// Prefix rules are plain `starts_with` checks: `"debug."` drops `debug.*`. With
+/// the `regex` feature, regular expressions can be added as well; they are
+/// checked after the prefix rules of the same kind.
+///
+/// ```
+/// use rylv_metrics::MetricFilter;
+///
+/// let filter = MetricFilter::default()
+///     .with_allow_prefix("app.")
+///     .with_deny_prefix("app.debug.");
+/// assert!(filter.allows("app.requests"));
+/// assert!(!filter.allows("app.debug.cache"));
+/// assert!(!filter.allows("other.requests"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MetricFilter {
+    allow_prefixes: Vec<Box<str>>,
+    deny_prefixes: Vec<Box<str>>,
+    #[cfg(feature = "regex")]
+    allow_regexes: Vec<regex::Regex>,
+    #[cfg(feature = "regex")]
+    deny_regexes: Vec<regex::Regex>,
+}
+
+impl MetricFilter {
+    /// Only records metrics starting with `prefix` (or matching another allow rule).
+    #[must_use]
+    pub fn with_allow_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.allow_prefixes.push(prefix.into().into_boxed_str());
+        self
+    }
+
+    /// Drops metrics starting with `prefix`.
+    #[must_use]
+    pub fn with_deny_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.deny_prefixes.push(prefix.into().into_boxed_str());
+        self
+    }
+
+    /// Only records metrics matching `pattern` (or another allow rule).
+    ///
+    /// # Errors
+    /// Returns an error if `pattern` is not a valid regular expression.
+    #[cfg(feature = "regex")]
+    pub fn with_allow_regex(mut self, pattern: &str) -> MetricResult<Self> {
+        self.allow_regexes.push(compile(pattern)?);
+        Ok(self)
+    }
+
+    /// Drops metrics matching `pattern`.
+    ///
+    /// # Errors
+    /// Returns an error if `pattern` is not a valid regular expression.
+    #[cfg(feature = "regex")]
+    pub fn with_deny_regex(mut self, pattern: &str) -> MetricResult<Self> {
+        self.deny_regexes.push(compile(pattern)?);
+        Ok(self)
+    }
+
+    /// Returns `true` when `metric` passes the filter and should be recorded.
+    #[must_use]
+    pub fn allows(&self, metric: &str) -> bool {
+        if self.denies(metric) {
+            return false;
+        }
+        if !self.has_allow_rules() {
+            return true;
+        }
+        if self
+            .allow_prefixes
+            .iter()
+            .any(|prefix| metric.starts_with(&**prefix))
+        {
+            return true;
+        }
+        #[cfg(feature = "regex")]
+        if self
+            .allow_regexes
+            .iter()
+            .any(|regex| regex.is_match(metric))
+        {
+            return true;
+        }
+        false
+    }
+
+    fn denies(&self, metric: &str) -> bool {
+        if self
+            .deny_prefixes
+            .iter()
+            .any(|prefix| metric.starts_with(&**prefix))
+        {
+            return true;
+        }
+        #[cfg(feature = "regex")]
+        if self.deny_regexes.iter().any(|regex| regex.is_match(metric)) {
+            return true;
+        }
+        false
+    }
+
+    fn has_allow_rules(&self) -> bool {
+        #[cfg(feature = "regex")]
+        if !self.allow_regexes.is_empty() {
+            return true;
+        }
+        !self.allow_prefixes.is_empty()
+    }
+}
+
+#[cfg(feature = "regex")]
+fn compile(pattern: &str) -> MetricResult<regex::Regex> {
+    regex::Regex::new(pattern)
+        .map_err(|err| format!("Invalid metric filter regex '{pattern}': {err}").into())
+}
+
+/// Collector-side filter state: the optional filter and how many metrics it dropped.
+#[cfg(any(
+    feature = "shared-collector",
+    feature = "simple-map",
+    feature = "tls-collector",
+    test
+))]
+#[derive(Debug)]
+pub struct MetricFilterState {
+    filter: Option<MetricFilter>,
+    filtered: AtomicU64,
+}
+
+#[cfg(any(
+    feature = "shared-collector",
+    feature = "simple-map",
+    feature = "tls-collector",
+    test
+))]
+impl MetricFilterState {
+    pub const fn new(filter: Option<MetricFilter>) -> Self {
+        Self {
+            filter,
+            filtered: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns `true` when `metric` passes the filter, without counting it.
+    #[inline]
+    pub fn allows(&self, metric: &str) -> bool {
+        self.filter
+            .as_ref()
+            .is_none_or(|filter| filter.allows(metric))
+    }
+
+    /// Returns `true` when `metric` must be dropped, counting it as filtered.
+    #[inline]
+    pub fn rejects(&self, metric: &str) -> bool {
+        self.count_rejected(!self.allows(metric))
+    }
+
+    /// Counts a recording found `rejected` beforehand, e.g. when its metric was
+    /// prepared, and returns `rejected`.
+    #[inline]
+    pub fn count_rejected(&self, rejected: bool) -> bool {
+        if rejected {
+            self.filtered.fetch_add(1, Ordering::Relaxed);
+        }
+        rejected
+    }
+
+    pub fn filtered_count(&self) -> u64 {
+        self.filtered.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_filter_allows_everything() {
+        let filter = MetricFilter::default();
+        assert!(filter.allows("debug.cache"));
+        assert!(filter.allows(""));
+    }
+
+    #[test]
+    fn deny_rules_win_over_allow_rules() {
+        let filter = MetricFilter::default()
+            .with_allow_prefix("app.")
+            .with_deny_prefix("app.debug.");
+        assert!(filter.allows("app.requests"));
+        assert!(!filter.allows("app.debug.cache"));
+        assert!(!filter.allows("debug.cache"));
+    }
+
+    #[test]
+    fn filter_state_counts_rejected_metrics() {
+        let state =
+            MetricFilterState::new(Some(MetricFilter::default().with_deny_prefix("debug.")));
+        assert!(state.rejects("debug.cache"));
+        assert!(!state.rejects("app.requests"));
+        assert_eq!(state.filtered_count(), 1);
+
+        let disabled = MetricFilterState::new(None);
+        assert!(!disabled.rejects("debug.cache"));
+        assert_eq!(disabled.filtered_count(), 0);
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn regex_rules_apply_alongside_prefixes() {
+        let filter = MetricFilter::default()
+            .with_allow_prefix("app.")
+            .with_allow_regex(r"^worker\.\d+\.")
+            .unwrap()
+            .with_deny_regex(r"\.tmp$")
+            .unwrap();
+        assert!(filter.allows("app.requests"));
+        assert!(filter.allows("worker.3.jobs"));
+        assert!(!filter.allows("worker.x.jobs"));
+        assert!(!filter.allows("app.requests.tmp"));
+        assert!(MetricFilter::default().with_deny_regex("(").is_err());
+    }
+}
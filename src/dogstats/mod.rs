@@ -1,20 +1,28 @@
 use std::{borrow::Cow, cmp::Ordering, sync::Arc};
 
-#[cfg(feature = "shared-collector")]
+#[cfg(any(feature = "shared-collector", feature = "simple-map"))]
 pub use aggregator::Aggregator;
 
 mod aggregator;
 pub mod collector;
 #[cfg(feature = "udp")]
 mod collector_udp;
+#[cfg(feature = "udp")]
+mod emergency_counter;
 mod histogram_config;
 #[cfg(feature = "udp")]
 mod job;
 pub mod macros;
+mod metric_filter;
 #[cfg(feature = "udp")]
 mod net;
+mod numeric_tag;
 mod slice_utils;
+#[cfg(feature = "udp")]
+mod socket_options;
 mod sorted_tags;
+#[cfg(any(feature = "shared-collector", feature = "simple-map"))]
+mod tag_interner;
 #[cfg(feature = "udp")]
 pub mod writer;
 #[cfg(feature = "udp")]
@@ -24,17 +32,32 @@ pub use aggregator::SigFig;
 pub use aggregator::{AggregatorEntryKey, LookupKey};
 pub use collector::DrainMetricCollectorTrait;
 pub use collector::MetricCollectorTrait;
-pub use collector::{MetricFrameRef, MetricKind, MetricSuffix};
-#[cfg(feature = "shared-collector")]
+pub use collector::{AsyncMetricCollector, AsyncMetricCollectorOptions};
+pub use collector::{GaugeMode, Metric, MetricFrameRef, MetricKind, MetricSuffix, ScopeTimer};
+pub use collector::{HistogramSnapshot, NoopCollector, Snapshot, SnapshotValue};
+#[cfg(any(feature = "shared-collector", feature = "simple-map"))]
 pub use collector::{SharedCollector, SharedCollectorOptions};
 #[cfg(feature = "tls-collector")]
 pub use collector::{TLSCollector, TLSCollectorOptions};
 #[cfg(feature = "udp")]
 pub use collector_udp::{
-    MetricCollector, MetricCollectorOptions, StatsWriterType, DEFAULT_STATS_WRITER_TYPE,
+    AddressPreference, MetricCollector, MetricCollectorOptions, NamespacedCollector,
+    ShutdownReport, StatsWriterKind, StatsWriterType, WriterPreference, DEFAULT_STATS_WRITER_TYPE,
+};
+#[cfg(feature = "udp")]
+pub use emergency_counter::EmergencyCounterId;
+pub use histogram_config::{
+    HistogramBaseMetric, HistogramConfig, HistogramSumKind, OutOfBoundsPolicy, PercentileMethod,
 };
-pub use histogram_config::{HistogramBaseMetric, HistogramConfig};
+#[cfg(feature = "udp")]
+pub use job::DerivedMetrics;
+pub use metric_filter::MetricFilter;
+pub use numeric_tag::NumericTag;
+#[cfg(feature = "udp")]
+pub use socket_options::SocketOptions;
 pub use sorted_tags::{PreparedMetric, SortedTags};
+#[cfg(feature = "udp")]
+pub use writer::{NameTransform, TagSectionStyle, WireFormat};
 
 /// A flexible string type that can hold static references, borrowed references, or owned values.
 /// Used for metric names and tags.
@@ -49,6 +72,10 @@ pub use sorted_tags::{PreparedMetric, SortedTags};
 ///
 /// For best performance, use `RylvStr::from_static()` whenever the string is known
 /// at compile time. This avoids heap allocation when the aggregator stores a new metric key.
+///
+/// The collector methods accept any `impl Into<RylvStr>` as metric name. `From<&str>`
+/// cannot tell a `&'static str` from a shorter borrow, so a literal passed directly
+/// becomes `Borrowed`; wrap it in `from_static()` to get `Static`.
 #[derive(Debug, Clone)]
 pub enum RylvStr<'a> {
     /// A borrowed `&'static str`. Zero-copy on `to_cow()`.
@@ -65,6 +92,24 @@ impl RylvStr<'_> {
     pub const fn from_static(s: &'static str) -> RylvStr<'static> {
         RylvStr::Static(s)
     }
+
+    /// Returns `true` when `name` can be sent as a metric name: non-empty and
+    /// free of the `:`, `|`, `#` and newline bytes that delimit a line.
+    ///
+    /// Usable in const context; [`crate::metric!`] checks literals with it at
+    /// compile time.
+    #[must_use]
+    pub const fn is_valid_metric_name(name: &str) -> bool {
+        let bytes = name.as_bytes();
+        let mut index = 0;
+        while index < bytes.len() {
+            if matches!(bytes[index], b':' | b'|' | b'#' | b'\n') {
+                return false;
+            }
+            index += 1;
+        }
+        !bytes.is_empty()
+    }
 }
 
 impl AsRef<str> for RylvStr<'_> {
@@ -0,0 +1,123 @@
+use super::RylvStr;
+
+/// Longest `key:value` tag kept inline by [`NumericTag`].
+const NUMERIC_TAG_INLINE_LEN: usize = 64;
+
+/// A `key:number` tag (e.g. `status:200`) formatted without a heap allocation.
+///
+/// `format!("status:{code}")` allocates a `String` on every call even when the
+/// tag is already part of an aggregation key. `NumericTag` formats into an
+/// inline buffer instead and is passed to the collector as a borrowed tag, so
+/// only a new series materializes an owned copy. Tags longer than 64 bytes fall
+/// back to a heap-allocated string.
+///
+/// ```
+/// use rylv_metrics::{NumericTag, RylvStr};
+///
+/// let status = NumericTag::new("status", 200);
+/// let tags = [RylvStr::from_static("env:prod"), status.as_rylv_str()];
+/// assert_eq!(tags[1].as_ref(), "status:200");
+/// ```
+#[derive(Debug, Clone)]
+pub struct NumericTag {
+    repr: Repr,
+}
+
+#[derive(Debug, Clone)]
+enum Repr {
+    Inline {
+        buffer: [u8; NUMERIC_TAG_INLINE_LEN],
+        len: u8,
+    },
+    Heap(String),
+}
+
+impl NumericTag {
+    /// Formats `key:value`.
+    #[must_use]
+    pub fn new(key: &str, value: u64) -> Self {
+        let mut digits = itoa::Buffer::new();
+        let value = digits.format(value);
+        let len = key.len() + 1 + value.len();
+        if len > NUMERIC_TAG_INLINE_LEN {
+            let mut tag = String::with_capacity(len);
+            tag.push_str(key);
+            tag.push(':');
+            tag.push_str(value);
+            return Self {
+                repr: Repr::Heap(tag),
+            };
+        }
+
+        let mut buffer = [0u8; NUMERIC_TAG_INLINE_LEN];
+        buffer[..key.len()].copy_from_slice(key.as_bytes());
+        buffer[key.len()] = b':';
+        buffer[key.len() + 1..len].copy_from_slice(value.as_bytes());
+        #[allow(clippy::cast_possible_truncation)]
+        Self {
+            repr: Repr::Inline {
+                buffer,
+                len: len as u8,
+            },
+        }
+    }
+
+    /// Returns the formatted tag.
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        match &self.repr {
+            Repr::Inline { buffer, len } => {
+                // SAFETY: the buffer holds a valid UTF-8 key, an ASCII colon and ASCII digits.
+                unsafe { std::str::from_utf8_unchecked(&buffer[..usize::from(*len)]) }
+            }
+            Repr::Heap(tag) => tag,
+        }
+    }
+
+    /// Borrows the tag for a recording call.
+    #[must_use]
+    pub fn as_rylv_str(&self) -> RylvStr<'_> {
+        RylvStr::Borrowed(self.as_str())
+    }
+}
+
+impl AsRef<str> for NumericTag {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<'a> From<&'a NumericTag> for RylvStr<'a> {
+    fn from(tag: &'a NumericTag) -> Self {
+        tag.as_rylv_str()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numeric_tag_matches_format() {
+        for (key, value) in [("status", 200), ("shard", 0), ("bytes", u64::MAX), ("", 7)] {
+            assert_eq!(
+                NumericTag::new(key, value).as_str(),
+                format!("{key}:{value}")
+            );
+        }
+    }
+
+    #[test]
+    fn long_numeric_tag_falls_back_to_heap() {
+        let key = "k".repeat(NUMERIC_TAG_INLINE_LEN);
+        let tag = NumericTag::new(&key, 42);
+        assert!(matches!(tag.repr, Repr::Heap(_)));
+        assert_eq!(tag.as_str(), format!("{key}:42"));
+
+        let fits = "k".repeat(NUMERIC_TAG_INLINE_LEN - 3);
+        assert!(matches!(
+            NumericTag::new(&fits, 42).repr,
+            Repr::Inline { .. }
+        ));
+    }
+}
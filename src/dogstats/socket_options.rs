@@ -0,0 +1,122 @@
+use std::net::{SocketAddr, UdpSocket};
+
+use crate::MetricResult;
+
+/// Socket-level tuning applied to the UDP send socket before it is bound.
+///
+/// Every option defaults to leaving the OS setting untouched, in which case the
+/// socket is created with [`UdpSocket::bind`] directly. Setting any option
+/// requires the `socket2` feature; without it, binding returns an error rather
+/// than silently ignoring the option.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SocketOptions {
+    /// Size of the kernel send buffer (`SO_SNDBUF`) in bytes. The OS may round
+    /// or double the value.
+    pub send_buffer_size: Option<usize>,
+    /// Sets `SO_REUSEADDR` before binding.
+    pub reuse_address: bool,
+    /// Sets `IPV6_V6ONLY`. Only valid when binding an IPv6 address.
+    pub only_v6: Option<bool>,
+    /// Binds the socket to a network interface (`SO_BINDTODEVICE`), e.g. `"eth0"`.
+    /// Linux only.
+    #[cfg(target_os = "linux")]
+    pub bind_device: Option<String>,
+}
+
+impl SocketOptions {
+    /// Creates a UDP socket bound to `bind_addr` with these options applied.
+    ///
+    /// # Errors
+    /// Returns an error if the socket cannot be created, an option cannot be
+    /// applied, or an option is set without the `socket2` feature enabled.
+    pub fn bind(&self, bind_addr: SocketAddr) -> MetricResult<UdpSocket> {
+        if *self == Self::default() {
+            return Ok(UdpSocket::bind(bind_addr)?);
+        }
+        self.bind_configured(bind_addr)
+    }
+
+    #[cfg(feature = "socket2")]
+    fn bind_configured(&self, bind_addr: SocketAddr) -> MetricResult<UdpSocket> {
+        use socket2::{Domain, Protocol, Socket, Type};
+
+        let socket = Socket::new(
+            Domain::for_address(bind_addr),
+            Type::DGRAM,
+            Some(Protocol::UDP),
+        )?;
+        if let Some(size) = self.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+        if self.reuse_address {
+            socket.set_reuse_address(true)?;
+        }
+        if let Some(only_v6) = self.only_v6 {
+            socket.set_only_v6(only_v6)?;
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(device) = &self.bind_device {
+            socket.bind_device(Some(device.as_bytes()))?;
+        }
+        socket.bind(&bind_addr.into())?;
+        Ok(socket.into())
+    }
+
+    #[cfg(not(feature = "socket2"))]
+    #[allow(clippy::unused_self)]
+    fn bind_configured(&self, _bind_addr: SocketAddr) -> MetricResult<UdpSocket> {
+        Err("SocketOptions require the `socket2` feature".into())
+    }
+}
+
+#[cfg(all(test, feature = "socket2"))]
+mod tests {
+    use super::*;
+    use socket2::SockRef;
+
+    #[test]
+    fn default_options_bind_plain_socket() {
+        let socket = SocketOptions::default()
+            .bind("127.0.0.1:0".parse().unwrap())
+            .unwrap();
+        assert!(socket.local_addr().unwrap().port() > 0);
+    }
+
+    #[test]
+    fn socket_options_are_applied_before_bind() {
+        let options = SocketOptions {
+            send_buffer_size: Some(256 * 1024),
+            reuse_address: true,
+            ..Default::default()
+        };
+        let socket = options.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let sock_ref = SockRef::from(&socket);
+
+        // Linux doubles the requested size, other platforms keep it as-is
+        assert!(sock_ref.send_buffer_size().unwrap() >= 256 * 1024);
+        assert!(sock_ref.reuse_address().unwrap());
+    }
+
+    #[test]
+    fn only_v6_is_applied_to_ipv6_sockets() {
+        let options = SocketOptions {
+            only_v6: Some(true),
+            ..Default::default()
+        };
+        let Ok(socket) = options.bind("[::1]:0".parse().unwrap()) else {
+            // IPv6 loopback is not available in every sandbox
+            return;
+        };
+        assert!(SockRef::from(&socket).only_v6().unwrap());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn bind_device_errors_for_unknown_interface() {
+        let options = SocketOptions {
+            bind_device: Some("rylv-missing0".to_string()),
+            ..Default::default()
+        };
+        assert!(options.bind("127.0.0.1:0".parse().unwrap()).is_err());
+    }
+}
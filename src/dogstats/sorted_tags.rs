@@ -1,9 +1,11 @@
 use crate::dogstats::slice_utils::equal_slice;
+#[cfg(any(feature = "shared-collector", feature = "simple-map"))]
+use crate::dogstats::tag_interner::InternedTags;
 use crate::dogstats::RylvStr;
 use std::hash::{BuildHasher, Hash, Hasher};
 use std::marker::PhantomData;
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
 /// Fast secondary fingerprint over metric + joined tags.
 #[must_use]
@@ -58,15 +60,16 @@ pub fn metric_tags_fingerprint_from_tags(metric: &str, tags: &[RylvStr<'_>]) ->
     hash
 }
 
-/// Compute a hash over a sorted tag slice.
+/// Compute an order-independent hash over a tag slice.
+///
+/// Tags are hashed one by one and the hashes summed, so a tag set hashes the
+/// same whether or not the slice was sorted first.
 #[must_use]
 #[inline]
 pub fn hash_tags<S: BuildHasher>(hasher_builder: &S, tags: &[RylvStr<'_>]) -> u64 {
-    let mut hasher = hasher_builder.build_hasher();
-    for tag in tags {
-        tag.as_ref().hash(&mut hasher);
-    }
-    hasher.finish()
+    tags.iter().fold(0, |hash, tag| {
+        hash.wrapping_add(hasher_builder.hash_one(tag.as_ref()))
+    })
 }
 
 /// Combine a metric name with a precomputed tags hash into a single lookup hash.
@@ -89,8 +92,12 @@ pub fn combine_metric_tags_hash<S: BuildHasher>(
 /// and hashing. The tags hash is precomputed at construction time.
 #[derive(Clone, Debug)]
 pub struct SortedTags<S: BuildHasher + Clone> {
-    tags: Box<[RylvStr<'static>]>,
     joined_tags: Arc<str>,
+    // End of each tag in `joined_tags`; tags are sliced out of the joined buffer
+    // instead of being stored separately.
+    tag_ends: Arc<[usize]>,
+    // Owned copies of the tags, only built when `tags` is called.
+    tags: OnceLock<Box<[RylvStr<'static>]>>,
     tags_hash: u64,
     id: u64,
     _hasher: PhantomData<S>,
@@ -118,47 +125,35 @@ impl<S: BuildHasher + Clone> SortedTags<S> {
     where
         I: IntoIterator<Item = RylvStr<'a>>,
     {
-        let mut tags_vec: Vec<RylvStr<'static>> = tags.into_iter().map(to_static_tag).collect();
+        let mut tags_vec: Vec<RylvStr<'a>> = tags.into_iter().collect();
         tags_vec.sort_unstable();
-
-        // TODO: route this through `build_joined_tags` too, so joined-tag construction lives in
-        // one place before we do any further hot-path tuning here.
-        let joined_tags = if tags_vec.is_empty() {
-            Arc::<str>::from("")
-        } else {
-            let joined_len =
-                tags_vec.iter().map(|tag| tag.as_ref().len()).sum::<usize>() + tags_vec.len() - 1;
-            let mut buffer = String::with_capacity(joined_len);
-            let mut iter = tags_vec.iter();
-            if let Some(tag) = iter.next() {
-                buffer.push_str(tag.as_ref());
-            }
-            for tag in iter {
-                buffer.push(',');
-                buffer.push_str(tag.as_ref());
-            }
-            Arc::<str>::from(buffer)
-        };
-
         let tags_hash = hash_tags(hasher_builder, &tags_vec);
+        Self::from_sorted_tags_with_hash(&tags_vec, tags_hash)
+    }
+
+    /// Builds `SortedTags` from already-sorted tags and a precomputed tags hash.
+    pub(crate) fn from_sorted_tags_with_hash(tags: &[RylvStr<'_>], tags_hash: u64) -> Self {
+        let mut tag_ends = Vec::with_capacity(tags.len());
+        let joined_tags = join_tags(tags, |end| tag_ends.push(end));
 
         Self {
-            tags: tags_vec.into_boxed_slice(),
             joined_tags,
+            tag_ends: tag_ends.into(),
+            tags: OnceLock::new(),
             tags_hash,
             id: next_sorted_tag_id(),
             _hasher: PhantomData,
         }
     }
 
-    /// Builds `SortedTags` from already-sorted tags and a precomputed tags hash.
-    pub(crate) fn from_sorted_tags_with_hash(tags: &[RylvStr<'_>], tags_hash: u64) -> Self {
-        let tags_vec: Vec<RylvStr<'static>> = tags.iter().cloned().map(to_static_tag).collect();
-        let joined_tags = build_joined_tags(&tags_vec);
-
+    /// Builds `SortedTags` sharing the buffers of a tag set taken from a
+    /// [`crate::dogstats::tag_interner::TagInterner`].
+    #[cfg(any(feature = "shared-collector", feature = "simple-map"))]
+    pub(crate) fn from_interned(interned: InternedTags, tags_hash: u64) -> Self {
         Self {
-            tags: tags_vec.into_boxed_slice(),
-            joined_tags,
+            joined_tags: interned.joined,
+            tag_ends: interned.ends,
+            tags: OnceLock::new(),
             tags_hash,
             id: next_sorted_tag_id(),
             _hasher: PhantomData,
@@ -172,9 +167,77 @@ impl<S: BuildHasher + Clone> SortedTags<S> {
     }
 
     /// Returns sorted tags.
+    ///
+    /// Only the joined tags are stored, so the first call copies every tag out
+    /// of them; [`Self::iter`] borrows them instead.
     #[must_use]
     pub fn tags(&self) -> &[RylvStr<'static>] {
-        &self.tags
+        self.tags.get_or_init(|| {
+            self.iter()
+                .map(|tag| RylvStr::Owned(Arc::from(tag)))
+                .collect()
+        })
+    }
+
+    /// Iterates over the sorted tags, sliced out of [`Self::joined_tags`].
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = &str> + '_ {
+        let joined = &*self.joined_tags;
+        let mut start = 0;
+        self.tag_ends.iter().map(move |&end| {
+            let tag = &joined[start..end];
+            start = end + 1;
+            tag
+        })
+    }
+
+    /// Returns `true` when `tags`, in any order, are exactly these tags.
+    ///
+    /// Each tag is binary searched and claims one stored slot, so duplicated
+    /// tags must appear as many times on both sides.
+    pub(crate) fn matches_unordered(&self, tags: &[RylvStr<'_>]) -> bool {
+        let len = self.len();
+        if tags.len() != len {
+            return false;
+        }
+        if len > u64::BITS as usize {
+            let mut sorted = tags.to_vec();
+            sorted.sort_unstable();
+            return sorted
+                .iter()
+                .zip(self.iter())
+                .all(|(tag, stored)| tag.as_ref() == stored);
+        }
+
+        let mut claimed = 0_u64;
+        for tag in tags {
+            let tag = tag.as_ref();
+            let (mut low, mut high) = (0, len);
+            while low < high {
+                let middle = low + (high - low) / 2;
+                if self.tag(middle) < tag {
+                    low = middle + 1;
+                } else {
+                    high = middle;
+                }
+            }
+            // skip the equal tags already claimed by an earlier duplicate
+            while low < len && claimed & (1 << low) != 0 && self.tag(low) == tag {
+                low += 1;
+            }
+            if low == len || self.tag(low) != tag {
+                return false;
+            }
+            claimed |= 1 << low;
+        }
+        true
+    }
+
+    fn tag(&self, index: usize) -> &str {
+        let start = match index {
+            0 => 0,
+            _ => self.tag_ends[index - 1] + 1,
+        };
+        &self.joined_tags[start..self.tag_ends[index]]
     }
 
     /// Returns pre-joined tags in `DogStatsD` format.
@@ -186,17 +249,18 @@ impl<S: BuildHasher + Clone> SortedTags<S> {
     /// Number of tags.
     #[must_use]
     pub fn len(&self) -> usize {
-        self.tags.len()
+        self.tag_ends.len()
     }
 
     /// Returns `true` when no tags are present.
     #[must_use]
     pub fn is_empty(&self) -> bool {
-        self.tags.is_empty()
+        self.tag_ends.is_empty()
     }
 }
 
-fn to_static_tag(tag: RylvStr<'_>) -> RylvStr<'static> {
+/// Converts a tag into a `'static` representation.
+pub fn to_static_tag(tag: RylvStr<'_>) -> RylvStr<'static> {
     match tag {
         RylvStr::Static(s) => RylvStr::Static(s),
         RylvStr::Borrowed(s) => RylvStr::Owned(Arc::from(s)),
@@ -204,21 +268,25 @@ fn to_static_tag(tag: RylvStr<'_>) -> RylvStr<'static> {
     }
 }
 
-fn build_joined_tags(tags_vec: &[RylvStr<'static>]) -> Arc<str> {
-    if tags_vec.is_empty() {
+#[cfg(feature = "udp")]
+pub fn build_joined_tags(tags_vec: &[RylvStr<'_>]) -> Arc<str> {
+    join_tags(tags_vec, |_| ())
+}
+
+/// Joins `tags` with `,` in one buffer, reporting where each tag ends to `on_tag_end`.
+pub fn join_tags(tags: &[RylvStr<'_>], mut on_tag_end: impl FnMut(usize)) -> Arc<str> {
+    if tags.is_empty() {
         return Arc::<str>::from("");
     }
 
-    let joined_len =
-        tags_vec.iter().map(|tag| tag.as_ref().len()).sum::<usize>() + tags_vec.len() - 1;
+    let joined_len = tags.iter().map(|tag| tag.as_ref().len()).sum::<usize>() + tags.len() - 1;
     let mut buffer = String::with_capacity(joined_len);
-    let mut iter = tags_vec.iter();
-    if let Some(tag) = iter.next() {
-        buffer.push_str(tag.as_ref());
-    }
-    for tag in iter {
-        buffer.push(',');
+    for (index, tag) in tags.iter().enumerate() {
+        if index > 0 {
+            buffer.push(',');
+        }
         buffer.push_str(tag.as_ref());
+        on_tag_end(buffer.len());
     }
     Arc::<str>::from(buffer)
 }
@@ -238,6 +306,13 @@ pub struct PreparedMetric<S: BuildHasher + Clone> {
     hash: u64,
     fingerprint: u64,
     prepared_id: u64,
+    // Rejected by the metric filter of the collector that prepared it
+    #[cfg(any(
+        feature = "shared-collector",
+        feature = "simple-map",
+        feature = "tls-collector"
+    ))]
+    filtered: bool,
 }
 
 impl<S: BuildHasher + Clone> PreparedMetric<S> {
@@ -252,9 +327,36 @@ impl<S: BuildHasher + Clone> PreparedMetric<S> {
             hash,
             fingerprint,
             prepared_id,
+            #[cfg(any(
+                feature = "shared-collector",
+                feature = "simple-map",
+                feature = "tls-collector"
+            ))]
+            filtered: false,
         }
     }
 
+    /// Marks the metric as rejected by the metric filter, which collectors
+    /// check against the name before normalizing it.
+    #[cfg(any(
+        feature = "shared-collector",
+        feature = "simple-map",
+        feature = "tls-collector"
+    ))]
+    pub(crate) const fn with_filtered(mut self, filtered: bool) -> Self {
+        self.filtered = filtered;
+        self
+    }
+
+    #[cfg(any(
+        feature = "shared-collector",
+        feature = "simple-map",
+        feature = "tls-collector"
+    ))]
+    pub(crate) const fn is_filtered(&self) -> bool {
+        self.filtered
+    }
+
     /// Returns metric name.
     #[must_use]
     pub const fn metric(&self) -> &RylvStr<'static> {
@@ -308,7 +410,7 @@ pub fn next_metric_id() -> u64 {
 
 #[cfg(test)]
 mod tests {
-    use super::SortedTags;
+    use super::{hash_tags, SortedTags};
     use crate::{DefaultMetricHasher, RylvStr};
 
     fn default_hasher() -> DefaultMetricHasher {
@@ -328,4 +430,52 @@ mod tests {
         assert_eq!(tags.joined_tags(), "az:use1,env:prod,service:api");
         assert_eq!(tags.len(), 3);
     }
+
+    #[test]
+    fn sorted_tags_slice_tags_out_of_the_joined_buffer() {
+        let tags = SortedTags::new(
+            [
+                RylvStr::from_static("env:prod"),
+                RylvStr::from_static(""),
+                RylvStr::from_static("path:a,b"),
+            ],
+            &default_hasher(),
+        );
+        assert_eq!(tags.joined_tags(), ",env:prod,path:a,b");
+        assert_eq!(
+            tags.iter().collect::<Vec<_>>(),
+            vec!["", "env:prod", "path:a,b"]
+        );
+        assert_eq!(tags.iter().len(), 3);
+        assert_eq!(
+            tags.tags(),
+            ["", "env:prod", "path:a,b"].map(RylvStr::from_static)
+        );
+
+        let empty = SortedTags::new([], &default_hasher());
+        assert!(empty.is_empty());
+        assert_eq!(empty.iter().count(), 0);
+        assert!(empty.tags().is_empty());
+    }
+
+    #[test]
+    fn unordered_match_ignores_order_but_not_multiplicity() {
+        let hasher = default_hasher();
+        let stored = SortedTags::new(["a:1", "b:2", "b:2"].map(RylvStr::from_static), &hasher);
+        let reordered = ["b:2", "a:1", "b:2"].map(RylvStr::from_static);
+        assert!(stored.matches_unordered(&reordered));
+        assert_eq!(hash_tags(&hasher, &reordered), stored.tags_hash());
+
+        assert!(!stored.matches_unordered(&["a:1", "a:1", "b:2"].map(RylvStr::from_static)));
+        assert!(!stored.matches_unordered(&["b:2", "a:1"].map(RylvStr::from_static)));
+        assert!(!stored.matches_unordered(&["b:2", "c:3", "a:1"].map(RylvStr::from_static)));
+
+        let many: Vec<RylvStr<'static>> = (0..70)
+            .rev()
+            .map(|i| RylvStr::from(format!("t:{i}")))
+            .collect();
+        let stored_many = SortedTags::new(many.clone(), &hasher);
+        assert!(stored_many.matches_unordered(&many));
+        assert!(!stored_many.matches_unordered(&many[..69]));
+    }
 }
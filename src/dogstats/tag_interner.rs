@@ -0,0 +1,171 @@
+use crate::dogstats::sorted_tags::join_tags;
+use crate::dogstats::RylvStr;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Tag sets kept by the interner of one collector before its oldest
+/// generation is dropped.
+const TAG_INTERNER_CAPACITY: usize = 4096;
+
+/// Joined tags and tag ends of an interned tag set, shared by every key built
+/// from the same tags.
+#[derive(Clone)]
+pub struct InternedTags {
+    pub joined: Arc<str>,
+    pub ends: Arc<[usize]>,
+}
+
+impl InternedTags {
+    fn matches(&self, tags: &[RylvStr<'_>]) -> bool {
+        self.ends.len() == tags.len()
+            && tags.iter().enumerate().all(|(index, tag)| {
+                let start = index
+                    .checked_sub(1)
+                    .map_or(0, |before| self.ends[before] + 1);
+                &self.joined[start..self.ends[index]] == tag.as_ref()
+            })
+    }
+}
+
+/// Bounded interner of the tag sets materialized into aggregation keys, shared
+/// by every aggregator generation of one collector.
+///
+/// Keys are built again in every flush window, so a tag set that keeps being
+/// recorded, such as a dynamic `thread:{id}` tag, reuses the joined buffer of
+/// its previous window instead of allocating it again.
+///
+/// Tag sets are kept in two generations. New ones go to the current
+/// generation; once it holds `capacity` sets it becomes the previous one and
+/// the generation before is dropped. A set found in the previous generation
+/// moves back to the current one, so sets in use survive the rotation while
+/// one-off sets are gone after two, and at most `2 * capacity` sets are kept.
+pub struct TagInterner {
+    generations: Mutex<Generations>,
+    capacity: usize,
+}
+
+#[derive(Default)]
+struct Generations {
+    // Tag sets by their order-independent tags hash
+    current: HashMap<u64, Vec<InternedTags>>,
+    current_len: usize,
+    previous: HashMap<u64, Vec<InternedTags>>,
+}
+
+impl Default for TagInterner {
+    fn default() -> Self {
+        Self::new(TAG_INTERNER_CAPACITY)
+    }
+}
+
+impl TagInterner {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            generations: Mutex::new(Generations::default()),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Returns the interned joined tags of the sorted `tags`, hashing to
+    /// `tags_hash`, joining them only when the set was not seen recently.
+    pub fn intern(&self, tags: &[RylvStr<'_>], tags_hash: u64) -> InternedTags {
+        let mut generations = self
+            .generations
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let found = generations
+            .current
+            .get(&tags_hash)
+            .and_then(|sets| sets.iter().find(|set| set.matches(tags)));
+        if let Some(interned) = found {
+            return interned.clone();
+        }
+        let interned = take_matching(&mut generations.previous, tags, tags_hash)
+            .unwrap_or_else(|| join_interned(tags));
+        if generations.current_len >= self.capacity {
+            generations.previous = std::mem::take(&mut generations.current);
+            generations.current_len = 0;
+        }
+        generations
+            .current
+            .entry(tags_hash)
+            .or_default()
+            .push(interned.clone());
+        generations.current_len += 1;
+        interned
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        let generations = self
+            .generations
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let count =
+            |sets: &HashMap<u64, Vec<InternedTags>>| -> usize { sets.values().map(Vec::len).sum() };
+        count(&generations.current) + count(&generations.previous)
+    }
+}
+
+/// Removes the set matching `tags` from `sets`.
+fn take_matching(
+    sets: &mut HashMap<u64, Vec<InternedTags>>,
+    tags: &[RylvStr<'_>],
+    tags_hash: u64,
+) -> Option<InternedTags> {
+    let candidates = sets.get_mut(&tags_hash)?;
+    let index = candidates.iter().position(|set| set.matches(tags))?;
+    Some(candidates.swap_remove(index))
+}
+
+fn join_interned(tags: &[RylvStr<'_>]) -> InternedTags {
+    let mut ends = Vec::with_capacity(tags.len());
+    let joined = join_tags(tags, |end| ends.push(end));
+    InternedTags {
+        joined,
+        ends: ends.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(tags: &[&str]) -> Vec<RylvStr<'static>> {
+        tags.iter()
+            .map(|tag| RylvStr::Owned(Arc::from(*tag)))
+            .collect()
+    }
+
+    #[test]
+    fn repeated_tag_sets_reuse_the_same_allocation() {
+        let interner = TagInterner::new(8);
+        let thread = format!("thread:{}", 7);
+        let first = interner.intern(&[RylvStr::from("env:a"), RylvStr::from(thread.as_str())], 1);
+        let second = interner.intern(&tags(&["env:a", "thread:7"]), 1);
+
+        assert!(Arc::ptr_eq(&first.joined, &second.joined));
+        assert_eq!(&*first.joined, "env:a,thread:7");
+        assert_eq!(&*first.ends, [5, 14]);
+        // same hash, other tags
+        let other = interner.intern(&tags(&["env:b", "thread:7"]), 1);
+        assert_eq!(&*other.joined, "env:b,thread:7");
+        assert_eq!(interner.len(), 2);
+    }
+
+    #[test]
+    fn tag_sets_in_use_survive_generation_rotations() {
+        let interner = TagInterner::new(4);
+        let hot = tags(&["thread:hot"]);
+        let first = interner.intern(&hot, 0);
+        for i in 1..64 {
+            let cold = tags(&[&format!("thread:{i}")]);
+            interner.intern(&cold, i);
+            assert!(interner.len() <= 8);
+            if i % 3 == 0 {
+                let again = interner.intern(&hot, 0);
+                assert!(Arc::ptr_eq(&first.joined, &again.joined));
+            }
+        }
+    }
+}
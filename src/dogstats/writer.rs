@@ -3,10 +3,18 @@ use rustix::net::SocketAddrAny;
 #[cfg(target_os = "linux")]
 use std::os::fd::AsFd;
 
+use std::borrow::Cow;
 use std::io::IoSlice;
 use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError, RwLock};
+use std::thread::{spawn, JoinHandle};
+use std::time::Duration;
 
-use crate::{MetricKind, MetricResult, StatsWriterType};
+use crossbeam::channel::{unbounded, Receiver, Sender};
+use tracing::warn;
+
+use crate::{MetricKind, MetricResult, MetricsError, StatsWriterKind, StatsWriterType};
 
 // Apple-specific imports for sendmmsg_x
 use std::mem::transmute;
@@ -19,14 +27,19 @@ use crate::dogstats::net::{msghdr_x, sendmsg_x};
 pub trait Writer {
     fn write(&self, buf: &[u8]) -> std::io::Result<usize>;
 
+    // Only socket-backed writers have a destination to update
+    fn set_destination(&mut self, _destination: SocketAddr) {}
+
     #[cfg(target_os = "linux")]
     fn write_mvec(&self, pool_msg_headers: &mut [rustix::net::MMsgHdr<'_>]) -> MetricResult<usize>;
 
+    /// Destination for each datagram, `None` when the socket is connected.
     #[cfg(target_os = "linux")]
-    fn get_destination(&self) -> &SocketAddrAny;
+    fn get_destination(&self) -> Option<&SocketAddrAny>;
 
+    /// Destination for each datagram, `None` when the socket is connected.
     #[cfg(target_vendor = "apple")]
-    fn get_destination_addr(&self) -> libc::sockaddr_in;
+    fn get_destination_addr(&self) -> Option<libc::sockaddr_in>;
 
     #[cfg(target_vendor = "apple")]
     fn as_raw_fd(&self) -> libc::c_int;
@@ -46,12 +59,12 @@ where
     }
 
     #[cfg(target_os = "linux")]
-    fn get_destination(&self) -> &SocketAddrAny {
+    fn get_destination(&self) -> Option<&SocketAddrAny> {
         (*self).get_destination()
     }
 
     #[cfg(target_vendor = "apple")]
-    fn get_destination_addr(&self) -> libc::sockaddr_in {
+    fn get_destination_addr(&self) -> Option<libc::sockaddr_in> {
         (*self).get_destination_addr()
     }
 
@@ -66,11 +79,33 @@ pub struct UdpSocketWriter {
     #[cfg(target_os = "linux")]
     pub destination: SocketAddrAny,
     pub destination_addr: SocketAddr,
+    // `sock` is connected to `destination_addr`, so datagrams are sent without an address
+    pub connected: bool,
 }
 
 impl Writer for UdpSocketWriter {
     fn write(&self, buf: &[u8]) -> std::io::Result<usize> {
-        self.sock.send_to(buf, self.destination_addr)
+        if self.connected {
+            self.sock.send(buf)
+        } else {
+            self.sock.send_to(buf, self.destination_addr)
+        }
+    }
+
+    fn set_destination(&mut self, destination: SocketAddr) {
+        self.destination_addr = destination;
+        #[cfg(target_os = "linux")]
+        {
+            self.destination = SocketAddrAny::from(destination);
+        }
+        if self.connected {
+            if let Err(err) = self.sock.connect(destination) {
+                warn!(
+                    "Fail to connect UDP socket to {destination}, using unconnected sends: {err}"
+                );
+                self.connected = false;
+            }
+        }
     }
 
     #[cfg(target_os = "linux")]
@@ -88,18 +123,21 @@ impl Writer for UdpSocketWriter {
     }
 
     #[cfg(target_os = "linux")]
-    fn get_destination(&self) -> &SocketAddrAny {
-        &self.destination
+    fn get_destination(&self) -> Option<&SocketAddrAny> {
+        (!self.connected).then_some(&self.destination)
     }
 
     #[cfg(target_vendor = "apple")]
-    fn get_destination_addr(&self) -> libc::sockaddr_in {
+    fn get_destination_addr(&self) -> Option<libc::sockaddr_in> {
         use std::net::SocketAddr;
+        if self.connected {
+            return None;
+        }
         match self.destination_addr {
             SocketAddr::V4(addr) => {
                 let octets = addr.ip().octets();
                 #[allow(clippy::cast_possible_truncation)]
-                libc::sockaddr_in {
+                Some(libc::sockaddr_in {
                     sin_len: size_of::<libc::sockaddr_in>() as u8,
                     sin_family: libc::AF_INET as u8,
                     sin_port: addr.port().to_be(),
@@ -107,7 +145,7 @@ impl Writer for UdpSocketWriter {
                         s_addr: u32::from_ne_bytes(octets),
                     },
                     sin_zero: [0; 8],
-                }
+                })
             }
             SocketAddr::V6(_) => {
                 unreachable!("IPv6 not supported for Apple batch writer")
@@ -144,18 +182,104 @@ pub trait StatsWriterTrait {
         metric_type: MetricKind,
     ) -> MetricResult<()>;
 
-    /// Flushes the writer.
+    /// Sends every buffered line.
+    ///
+    /// Returns the number of bytes sent since the previous successful flush,
+    /// including the packets a full buffer sent during [`Self::write`], so the
+    /// sum over flushes is the egress volume. Bytes sent before a failing flush
+    /// are reported by the next successful one.
     ///
     /// # Errors
     /// Returns `MetricResult::Err` on I/O failure.
     fn flush(&mut self) -> MetricResult<usize>;
 
+    /// Returns how many datagrams the bytes returned by the last successful
+    /// [`Self::flush`] were sent in.
+    ///
+    /// Writers that do not send datagrams can keep the default, which returns `0`.
+    fn messages_sent(&self) -> usize {
+        0
+    }
+
     /// Resets the writer state, clearing any internal buffers.
     fn reset(&mut self);
+
+    /// Points the writer at a new destination.
+    ///
+    /// Called on the flush thread between flushes after
+    /// [`crate::MetricCollector::set_destination`], so no packet is in flight.
+    /// Writers that do not send to the collector destination can ignore it,
+    /// which is the default.
+    fn set_destination(&mut self, destination: SocketAddr) {
+        let _ = destination;
+    }
+
+    /// Returns how many bytes are written but not yet sent.
+    ///
+    /// Useful to observe how full the writer is between flushes. Writers that do
+    /// not buffer can keep the default, which returns `0`.
+    #[cfg(any(feature = "custom_writer", test))]
+    fn buffered_bytes(&self) -> usize {
+        0
+    }
+
+    /// Returns whether the writer cannot take a flush right now, e.g. because
+    /// the bounded channel it forwards to is full.
+    ///
+    /// Checked by the flush thread before each scheduled flush: while it returns
+    /// `true` the flush cycle is skipped, leaving the metrics aggregated in the
+    /// collector until the next interval. The final flush made when the
+    /// collector is dropped or shut down, and the flush requested by
+    /// `MetricCollector::quiesce`, do not check it. The default never blocks.
+    fn would_block(&self) -> bool {
+        false
+    }
+
+    /// Called on the flush thread once a flush cycle completed, i.e. every line
+    /// of it was written and [`Self::flush`] succeeded, e.g. to commit an offset
+    /// or emit the writer's own telemetry.
+    ///
+    /// Not called for failed flushes. The default does nothing.
+    fn on_flush_complete(&mut self, stats: FlushStats) {
+        let _ = stats;
+    }
+}
+
+/// Transforms every packet of [`StatsWriterType::PacketHook`] right before it
+/// is sent, e.g. to sign it.
+///
+/// Only the `Simple` writer built for that variant calls the hook; the batch,
+/// threaded and custom writers never do.
+#[cfg(feature = "custom_writer")]
+pub trait PacketHook: Send + Sync {
+    /// Returns the bytes to send as one datagram in place of `packet`.
+    ///
+    /// Called each time a packet is complete: when the next line does not fit
+    /// during a write, or on flush. On error the packet is kept and the error
+    /// is returned from that write or flush.
+    ///
+    /// # Errors
+    /// Returns `MetricResult::Err` if the packet cannot be transformed.
+    fn on_packet_complete(&mut self, packet: &[u8]) -> MetricResult<Vec<u8>>;
+}
+
+/// What a completed flush cycle sent, passed to
+/// [`StatsWriterTrait::on_flush_complete`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FlushStats {
+    /// Lines the writer accepted during the cycle.
+    pub lines: usize,
+    /// Bytes returned by the cycle's [`StatsWriterTrait::flush`].
+    pub bytes_sent: usize,
+    /// Datagrams the bytes were sent in, from [`StatsWriterTrait::messages_sent`].
+    pub messages_sent: usize,
+    /// Time from the start of the drain to the end of the flush.
+    pub duration: Duration,
 }
 
 pub struct StatsWriterHolder {
     writer: Box<dyn StatsWriterTrait>,
+    kind: StatsWriterKind,
 }
 
 impl StatsWriterHolder {
@@ -165,18 +289,22 @@ impl StatsWriterHolder {
         writer_type: StatsWriterType,
         max_udp_packet_size: u16,
         max_udp_batch_size: u32,
+        tag_section_style: TagSectionStyle,
     ) -> Self {
+        let kind = writer_type.kind(max_udp_batch_size);
         let stats_writer = match writer_type {
-            StatsWriterType::Simple => {
-                Box::new(StatsWriterSimple::new(writer, max_udp_packet_size))
-                    as Box<dyn StatsWriterTrait>
-            }
+            StatsWriterType::Simple => Box::new(StatsWriterSimple::new(
+                writer,
+                max_udp_packet_size,
+                tag_section_style,
+            )) as Box<dyn StatsWriterTrait>,
 
             #[cfg(target_os = "linux")]
             StatsWriterType::LinuxBatch => Box::new(StatsWriterLinux::new(
                 writer,
                 max_udp_batch_size,
                 max_udp_packet_size,
+                tag_section_style,
             )) as Box<dyn StatsWriterTrait>,
 
             #[cfg(target_vendor = "apple")]
@@ -184,22 +312,106 @@ impl StatsWriterHolder {
                 writer,
                 max_udp_batch_size,
                 max_udp_packet_size,
+                tag_section_style,
             )) as Box<dyn StatsWriterTrait>,
 
             #[cfg(feature = "custom_writer")]
             StatsWriterType::Custom(writer) => writer,
+
+            #[cfg(feature = "custom_writer")]
+            StatsWriterType::PacketHook { hook, max_overhead } => {
+                Box::new(StatsWriterSimple::with_packet_hook(
+                    writer,
+                    max_udp_packet_size,
+                    tag_section_style,
+                    hook,
+                    max_overhead,
+                )) as Box<dyn StatsWriterTrait>
+            }
+
+            StatsWriterType::Preferred(preference) => {
+                return Self::new(
+                    writer,
+                    preference.resolve(max_udp_batch_size),
+                    max_udp_packet_size,
+                    max_udp_batch_size,
+                    tag_section_style,
+                );
+            }
         };
 
         Self {
             writer: stats_writer,
+            kind,
         }
     }
 
+    /// Like [`Self::new`], but with more than one `writer_threads` the built-in
+    /// UDP writers are replaced by a [`StatsWriterThreaded`] sending from
+    /// `writer_threads` background threads. Custom writers and packet hooks
+    /// are built as usual.
+    pub fn with_writer_threads<T: Writer + Send + Sync + 'static>(
+        writer: T,
+        writer_type: StatsWriterType,
+        max_udp_packet_size: u16,
+        max_udp_batch_size: u32,
+        tag_section_style: TagSectionStyle,
+        writer_threads: usize,
+    ) -> Self {
+        let kind = writer_type.kind(max_udp_batch_size);
+        let built_in = matches!(
+            kind,
+            StatsWriterKind::Simple | StatsWriterKind::LinuxBatch | StatsWriterKind::AppleBatch
+        );
+        if writer_threads <= 1 || !built_in {
+            return Self::new(
+                writer,
+                writer_type,
+                max_udp_packet_size,
+                max_udp_batch_size,
+                tag_section_style,
+            );
+        }
+        // the simple writer sends one datagram per call, so its senders do too
+        let max_udp_batch_size = match kind {
+            StatsWriterKind::Simple => 1,
+            _ => max_udp_batch_size,
+        };
+        Self {
+            writer: Box::new(StatsWriterThreaded::new(
+                writer,
+                writer_threads,
+                max_udp_batch_size,
+                max_udp_packet_size,
+                tag_section_style,
+            )),
+            kind,
+        }
+    }
+
+    pub const fn kind(&self) -> StatsWriterKind {
+        self.kind
+    }
+
     pub fn acquire(&mut self) -> StatsGuard<'_> {
         StatsGuard {
             writer: self.writer.as_mut(),
         }
     }
+
+    pub fn set_destination(&mut self, destination: SocketAddr) {
+        self.writer.set_destination(destination);
+    }
+
+    /// Drops whatever the writer still buffers, e.g. lines of a flush that
+    /// panicked before its guard was released.
+    pub fn reset(&mut self) {
+        self.writer.reset();
+    }
+
+    pub fn would_block(&self) -> bool {
+        self.writer.would_block()
+    }
 }
 
 pub struct StatsGuard<'a> {
@@ -231,14 +443,59 @@ impl StatsWriterTrait for StatsGuard<'_> {
         self.writer.flush()
     }
 
+    fn messages_sent(&self) -> usize {
+        self.writer.messages_sent()
+    }
+
     fn reset(&mut self) {
         self.writer.reset();
     }
+
+    fn set_destination(&mut self, destination: SocketAddr) {
+        self.writer.set_destination(destination);
+    }
+
+    #[cfg(any(feature = "custom_writer", test))]
+    fn buffered_bytes(&self) -> usize {
+        self.writer.buffered_bytes()
+    }
+
+    fn would_block(&self) -> bool {
+        self.writer.would_block()
+    }
+
+    fn on_flush_complete(&mut self, stats: FlushStats) {
+        self.writer.on_flush_complete(stats);
+    }
+}
+
+/// Bytes and datagrams sent since the last flush, for the return of
+/// [`StatsWriterTrait::flush`] and [`StatsWriterTrait::messages_sent`].
+#[derive(Debug, Default)]
+struct SentStats {
+    bytes: usize,
+    messages: usize,
+    last_flush_messages: usize,
+}
+
+impl SentStats {
+    const fn record(&mut self, bytes: usize, messages: usize) {
+        self.bytes += bytes;
+        self.messages += messages;
+    }
+
+    /// Closes the flush, returning the bytes sent since the previous one.
+    const fn finish_flush(&mut self) -> usize {
+        self.last_flush_messages = self.messages;
+        self.messages = 0;
+        std::mem::replace(&mut self.bytes, 0)
+    }
 }
 
 #[cfg(target_os = "linux")]
 pub struct StatsWriterLinux<T> {
     max_udp_packet_size: u16,
+    tag_section_style: TagSectionStyle,
     writer: T,
 
     // current state
@@ -248,14 +505,26 @@ pub struct StatsWriterLinux<T> {
     // for reuse in application lifetime
     pool_transmits: Vec<super::writer_utils::Transmit<'static>>,
     tmp_mmsghdrs: Vec<rustix::net::MMsgHdr<'static>>,
+
+    // set once sendmmsg is rejected by the kernel (ENOSYS/EPERM), from then on
+    // every queued transmit is sent with its own datagram
+    fallback: bool,
+    fallback_buffer: Vec<u8>,
+    sent: SentStats,
 }
 
 #[cfg(target_os = "linux")]
 impl<T: Writer> StatsWriterLinux<T> {
-    pub fn new(writer: T, max_udp_batch_size: u32, max_udp_packet_size: u16) -> Self {
+    pub fn new(
+        writer: T,
+        max_udp_batch_size: u32,
+        max_udp_packet_size: u16,
+        tag_section_style: TagSectionStyle,
+    ) -> Self {
         let max_udp_batch_size = max_udp_batch_size as usize;
         Self {
             max_udp_packet_size,
+            tag_section_style,
             writer,
 
             queued_transmits: Vec::with_capacity(max_udp_batch_size),
@@ -263,6 +532,10 @@ impl<T: Writer> StatsWriterLinux<T> {
 
             pool_transmits: Vec::with_capacity(max_udp_batch_size),
             tmp_mmsghdrs: Vec::with_capacity(max_udp_batch_size),
+
+            fallback: false,
+            fallback_buffer: Vec::new(),
+            sent: SentStats::default(),
         }
     }
 
@@ -275,9 +548,31 @@ impl<T: Writer> StatsWriterLinux<T> {
         self.queued_transmits.push(old_transmit);
     }
 
-    fn flush_queued_transmits(&mut self) -> MetricResult<usize> {
-        let res = if self.queued_transmits.is_empty() {
-            0
+    fn write_queued_transmits_one_by_one(&mut self) -> MetricResult<()> {
+        for (index, transmit) in self.queued_transmits.iter().enumerate() {
+            self.fallback_buffer.clear();
+            transmit.copy_to(&mut self.fallback_buffer);
+            match self.writer.write(&self.fallback_buffer) {
+                Ok(bytes) => self.sent.record(bytes, 1),
+                Err(err) => {
+                    // the sent transmits go back to the pool, so a retry does not send them twice
+                    for mut transmit in self.queued_transmits.drain(..index) {
+                        transmit.reset();
+                        self.pool_transmits.push(transmit);
+                    }
+                    return Err(err.into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn flush_queued_transmits(&mut self) -> MetricResult<()> {
+        if self.queued_transmits.is_empty() {
+            return Ok(());
+        }
+        if self.fallback {
+            self.write_queued_transmits_one_by_one()?;
         } else {
             let destination = self.writer.get_destination();
 
@@ -296,22 +591,40 @@ impl<T: Writer> StatsWriterLinux<T> {
 
             let result = self.writer.write_mvec(&mut self.tmp_mmsghdrs);
             self.tmp_mmsghdrs.clear();
-            result?
-        };
+            match result {
+                Ok(sent) => {
+                    // sendmmsg returns the number of messages sent
+                    let sent = sent.min(self.queued_transmits.len());
+                    let bytes = queued_bytes_of(&self.queued_transmits[..sent]);
+                    self.sent.record(bytes, sent);
+                }
+                Err(MetricsError::Errno(errno))
+                    if errno == rustix::io::Errno::NOSYS || errno == rustix::io::Errno::PERM =>
+                {
+                    warn!(
+                        "sendmmsg is not available ({errno}), falling back to one datagram per packet"
+                    );
+                    self.fallback = true;
+                    self.write_queued_transmits_one_by_one()?;
+                }
+                Err(err) => return Err(err),
+            }
+        }
 
         // return to queue for future reuse
         while let Some(mut transmit) = self.queued_transmits.pop() {
             transmit.reset();
             self.pool_transmits.push(transmit);
         }
-        Ok(res)
+        Ok(())
     }
 
     pub fn flush(&mut self) -> MetricResult<usize> {
         if self.current_transmit.len() > 0 {
             self.queue_current_transmit();
         }
-        self.flush_queued_transmits()
+        self.flush_queued_transmits()?;
+        Ok(self.sent.finish_flush())
     }
 }
 
@@ -328,26 +641,16 @@ impl<T: Writer> StatsWriterTrait for StatsWriterLinux<T> {
         value: &str,
         metric_type: MetricKind,
     ) -> MetricResult<()> {
-        let metric_type = metric_str(metric_type);
-
-        // Manually build this line
-        // format!("{}:{}|{}|#{}\n", metric, value, metric_type, tags);
-        let metric_len = metric_len(metrics, tags, value, metric_type);
+        let metric_len = metric_len(metrics, tags, value, metric_type, self.tag_section_style);
 
         // SAFETY: this value is not copied in this method (false in return of metric_copied method), so in
         // caller side must allocate and retain the correct value with a lifetime greater than the execution
         // of this method because here we only use a reference.
-        let (metrics, tags, value, metric_type): (
-            &[&'static str],
-            &'static str,
-            &'static str,
-            &'static str,
-        ) = unsafe {
+        let (metrics, tags, value): (&[&'static str], &'static str, &'static str) = unsafe {
             (
                 transmute::<&[&str], &[&str]>(metrics),
                 transmute::<&str, &str>(tags),
                 transmute::<&str, &str>(value),
-                transmute::<&str, &str>(metric_type),
             )
         };
 
@@ -355,25 +658,18 @@ impl<T: Writer> StatsWriterTrait for StatsWriterLinux<T> {
             return Err(format!("Metric is larger than {}", self.max_udp_packet_size).into());
         }
 
-        #[allow(clippy::cast_possible_truncation)]
-        if !self.current_transmit.enough_space_for(metric_len as u16) {
+        if !self.current_transmit.enough_space_for(metric_len) {
             self.queue_current_transmit();
         }
 
-        for metric in metrics {
-            self.current_transmit.push(IoSlice::new(metric.as_bytes()));
-        }
-
-        self.current_transmit.push(IoSlice::new(b":"));
-        self.current_transmit.push(IoSlice::new(value.as_bytes()));
-        self.current_transmit.push(IoSlice::new(b"|"));
-        self.current_transmit
-            .push(IoSlice::new(metric_type.as_bytes()));
-        if !tags.is_empty() {
-            self.current_transmit.push(IoSlice::new(b"|#"));
-            self.current_transmit.push(IoSlice::new(tags.as_bytes()));
-        }
-        self.current_transmit.push(IoSlice::new(b"\n"));
+        format_line(
+            &mut self.current_transmit,
+            metrics,
+            tags,
+            value,
+            metric_type,
+            self.tag_section_style,
+        );
 
         if self.queued_transmits.len() == self.queued_transmits.capacity() {
             self.flush_queued_transmits()?;
@@ -384,11 +680,28 @@ impl<T: Writer> StatsWriterTrait for StatsWriterLinux<T> {
     fn flush(&mut self) -> MetricResult<usize> {
         self.flush()
     }
+
+    fn messages_sent(&self) -> usize {
+        self.sent.last_flush_messages
+    }
+
     fn reset(&mut self) {
-        // SAFETY: stats writers have been dropped, so there are no pointers to bump after the bump is reset
+        // SAFETY NOTE: so there are no pointers to bump after the bump is reset.
+        // After a flush that panicked part way through, current_transmit may
+        // still hold lines of the aborted flush.
+        self.current_transmit.reset();
         self.queued_transmits.clear();
         self.tmp_mmsghdrs.clear();
     }
+
+    fn set_destination(&mut self, destination: SocketAddr) {
+        self.writer.set_destination(destination);
+    }
+
+    #[cfg(any(feature = "custom_writer", test))]
+    fn buffered_bytes(&self) -> usize {
+        queued_bytes(&self.current_transmit, &self.queued_transmits)
+    }
 }
 
 // ============================================================================
@@ -397,6 +710,7 @@ impl<T: Writer> StatsWriterTrait for StatsWriterLinux<T> {
 #[cfg(target_vendor = "apple")]
 pub struct StatsWriterApple<T> {
     max_udp_packet_size: u16,
+    tag_section_style: TagSectionStyle,
     writer: T,
 
     // Used in processing time
@@ -413,35 +727,101 @@ pub struct StatsWriterApple<T> {
 
     // Used in processing time to avoid allocations
     tmp_mmsghdrs: Vec<msghdr_x>,
+    sent: SentStats,
 }
 
-#[inline]
-fn metric_len(metrics: &[&str], tags: &str, value: &str, metric_type: &str) -> usize {
-    // format!("{}:{}|{}\n", metric, value, metric_type) when tags is empty
-    // format!("{}:{}|{}|#{}\n", metric, value, metric_type, tags) when tags is not empty
-    let mut metric_len = value.len() + metric_type.len() + tags.len() + 3; // ':' + '|' + '\n'
+/// Destination of [`format_line`]: the `String` of the simple writer or the
+/// iovec `Transmit` of the batch writers.
+pub trait LineBuffer<'data> {
+    /// Appends one piece of a line.
+    fn push_part(&mut self, part: &'data str);
+}
 
-    if !tags.is_empty() {
-        metric_len += 2; // '|#'
+impl<'data> LineBuffer<'data> for String {
+    #[inline]
+    fn push_part(&mut self, part: &'data str) {
+        self.push_str(part);
     }
+}
 
-    for metric in metrics {
-        metric_len += metric.len();
+#[cfg(any(target_os = "linux", target_vendor = "apple"))]
+impl<'data> LineBuffer<'data> for super::writer_utils::Transmit<'data> {
+    #[inline]
+    fn push_part(&mut self, part: &'data str) {
+        self.push(IoSlice::new(part.as_bytes()));
     }
-    metric_len
+}
+
+/// Writes one `DogStatsD` line, `{metrics}:{value}|{metric_type}[|#{tags}]\n`,
+/// into `buffer`.
+///
+/// Every built-in writer assembles its lines here, so wire format changes are
+/// made once; [`metric_len`] returns the length of the same line.
+#[inline]
+pub fn format_line<'data>(
+    buffer: &mut impl LineBuffer<'data>,
+    metrics: &[&'data str],
+    tags: &'data str,
+    value: &'data str,
+    metric_type: MetricKind,
+    tag_section_style: TagSectionStyle,
+) {
+    for part in line_parts(metrics, tags, value, metric_type, tag_section_style) {
+        buffer.push_part(part);
+    }
+}
+
+/// Returns the length of the line [`format_line`] writes for the same arguments.
+#[inline]
+pub fn metric_len(
+    metrics: &[&str],
+    tags: &str,
+    value: &str,
+    metric_type: MetricKind,
+    tag_section_style: TagSectionStyle,
+) -> usize {
+    line_parts(metrics, tags, value, metric_type, tag_section_style)
+        .map(str::len)
+        .sum()
+}
+
+#[inline]
+fn line_parts<'s, 'a: 's>(
+    metrics: &'s [&'a str],
+    tags: &'a str,
+    value: &'a str,
+    metric_type: MetricKind,
+    tag_section_style: TagSectionStyle,
+) -> impl Iterator<Item = &'a str> + 's {
+    let tag_section = tag_section_style
+        .writes_section(tags)
+        .then_some(["|#", tags]);
+    metrics
+        .iter()
+        .copied()
+        .chain([":", value, "|", metric_str(metric_type)])
+        .chain(tag_section.into_iter().flatten())
+        .chain(["\n"])
 }
 
 #[cfg(target_vendor = "apple")]
 impl<T: Writer> StatsWriterApple<T> {
-    pub fn new(writer: T, max_udp_batch_size: u32, max_udp_packet_size: u16) -> Self {
+    pub fn new(
+        writer: T,
+        max_udp_batch_size: u32,
+        max_udp_packet_size: u16,
+        tag_section_style: TagSectionStyle,
+    ) -> Self {
         let max_udp_batch_size = max_udp_batch_size as usize;
         Self {
             max_udp_packet_size,
+            tag_section_style,
             writer,
             queued_transmits: Vec::with_capacity(max_udp_batch_size),
             pool_transmits: Vec::with_capacity(max_udp_batch_size),
             tmp_mmsghdrs: Vec::with_capacity(max_udp_batch_size),
             current_transmit: super::writer_utils::Transmit::new(max_udp_packet_size),
+            sent: SentStats::default(),
         }
     }
 
@@ -454,16 +834,24 @@ impl<T: Writer> StatsWriterApple<T> {
         self.queued_transmits.push(old_transmit);
     }
 
-    fn flush_queued_transmits(&mut self) -> MetricResult<usize> {
+    fn flush_queued_transmits(&mut self) -> MetricResult<()> {
         if self.queued_transmits.is_empty() {
-            return Ok(0);
+            return Ok(());
         }
 
         let destination_addr = self.writer.get_destination_addr();
         let sock_fd = self.writer.as_raw_fd();
 
-        // Prepare msghdr_x structures for batch sending
+        // Prepare msghdr_x structures for batch sending, without an address on a connected socket
         let mut sockaddr_storage = destination_addr;
+        let (msg_name, msg_namelen) = match sockaddr_storage.as_mut() {
+            #[allow(clippy::cast_possible_truncation)]
+            Some(sockaddr) => (
+                std::ptr::from_mut(sockaddr).cast::<libc::c_void>(),
+                size_of::<libc::sockaddr_in>() as libc::socklen_t,
+            ),
+            None => (std::ptr::null_mut(), 0),
+        };
         assert!(self.tmp_mmsghdrs.is_empty());
 
         for transmit in &mut self.queued_transmits {
@@ -478,8 +866,8 @@ impl<T: Writer> StatsWriterApple<T> {
                 clippy::as_ptr_cast_mut
             )]
             self.tmp_mmsghdrs.push(msghdr_x {
-                msg_name: (&raw mut sockaddr_storage).cast::<libc::c_void>(),
-                msg_namelen: size_of_val(&sockaddr_storage) as libc::socklen_t,
+                msg_name,
+                msg_namelen,
                 // SAFETY: IoSlice is repr(transparent) over libc::iovec on Unix
                 msg_iov: iovecs.as_ptr() as *mut libc::iovec,
                 msg_iovlen: iovecs.len() as libc::c_int,
@@ -504,6 +892,11 @@ impl<T: Writer> StatsWriterApple<T> {
         if result < 0 {
             return Err(std::io::Error::last_os_error().into());
         }
+        // sendmsg_x returns the number of messages sent
+        #[allow(clippy::cast_sign_loss)]
+        let sent = result as usize;
+        let bytes = queued_bytes_of(&self.queued_transmits[..sent]);
+        self.sent.record(bytes, sent);
 
         // Return transmits to pool for reuse
         while let Some(mut transmit) = self.queued_transmits.pop() {
@@ -511,22 +904,108 @@ impl<T: Writer> StatsWriterApple<T> {
             self.pool_transmits.push(transmit);
         }
 
-        #[allow(clippy::cast_sign_loss)]
-        Ok(result as usize)
+        Ok(())
     }
 
     pub fn flush(&mut self) -> MetricResult<usize> {
         if self.current_transmit.len() > 0 {
             self.queue_current_transmit();
         }
-        self.flush_queued_transmits()
+        self.flush_queued_transmits()?;
+        Ok(self.sent.finish_flush())
     }
 }
 
-const fn metric_str(metric_type: MetricKind) -> &'static str {
+/// Bytes held by a batch writer: the transmit being filled plus the queued ones.
+#[cfg(all(
+    any(target_os = "linux", target_vendor = "apple"),
+    any(feature = "custom_writer", test)
+))]
+fn queued_bytes(
+    current_transmit: &super::writer_utils::Transmit<'_>,
+    queued_transmits: &[super::writer_utils::Transmit<'_>],
+) -> usize {
+    queued_bytes_of(queued_transmits) + current_transmit.len()
+}
+
+#[cfg(any(target_os = "linux", target_vendor = "apple"))]
+fn queued_bytes_of(transmits: &[super::writer_utils::Transmit<'_>]) -> usize {
+    transmits
+        .iter()
+        .map(super::writer_utils::Transmit::len)
+        .sum()
+}
+
+/// How a line without tags renders its tag section.
+///
+/// Datadog expects no tag section at all; some other `StatsD` receivers expect
+/// an empty `|#` instead.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum TagSectionStyle {
+    /// Omits `|#` when there are no tags: `requests:1|c`. What Datadog expects.
+    #[default]
+    Omit,
+    /// Always writes `|#`, leaving it empty when there are no tags: `requests:1|c|#`.
+    Always,
+}
+
+impl TagSectionStyle {
+    /// Returns whether a line with `tags` gets a `|#` tag section.
+    #[inline]
+    const fn writes_section(self, tags: &str) -> bool {
+        matches!(self, Self::Always) || !tags.is_empty()
+    }
+}
+
+/// Line format of the metrics sent by a collector.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum WireFormat {
+    /// Tags go in a `|#` section: `request.count:1|c|#endpoint:api,method:get`.
+    #[default]
+    DogStatsD,
+    /// For receivers without tag support, such as `StatsD` with Graphite: the
+    /// values of the sorted tags are appended to the name with dots and the `|#`
+    /// section is dropped: `request.count.api.get:1|c`.
+    PlainStatsD,
+}
+
+/// Closure rewriting every metric name on the flush thread, e.g. to add a tenant
+/// prefix only known at runtime.
+///
+/// It receives the assembled name, prefixes and suffixes included, and runs
+/// once per line written, off the recording hot path. Return
+/// [`Cow::Borrowed`] to keep the name unchanged without allocating.
+#[derive(Clone)]
+pub struct NameTransform(Arc<NameTransformFn>);
+
+type NameTransformFn = dyn for<'a> Fn(&'a str) -> Cow<'a, str> + Send + Sync;
+
+impl NameTransform {
+    /// Wraps `transform`.
+    pub fn new(transform: impl Fn(&str) -> Cow<'_, str> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(transform))
+    }
+
+    /// Applies the transform to `name`.
+    #[must_use]
+    pub fn apply<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        (self.0)(name)
+    }
+}
+
+impl std::fmt::Debug for NameTransform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "NameTransform(...)")
+    }
+}
+
+/// Returns the `DogStatsD` type suffix of `metric_type`, e.g. `c` for counts.
+pub const fn metric_str(metric_type: MetricKind) -> &'static str {
     match metric_type {
         MetricKind::Count => "c",
         MetricKind::Gauge => "g",
+        MetricKind::Timing => "ms",
+        MetricKind::Distribution => "d",
     }
 }
 
@@ -546,39 +1025,32 @@ impl<T: Writer> StatsWriterTrait for StatsWriterApple<T> {
         // SAFETY: this value is not copied in this method (false in return of metric_copied method), so in
         // caller side must allocate and retain the correct value with a lifetime greater than the execution
         // of this method because here we only use a reference.
-        let (metrics, tags, value, metric_type) = unsafe {
+        let (metrics, tags, value) = unsafe {
             (
                 transmute::<&[&str], &[&str]>(metrics),
                 transmute::<&str, &str>(tags),
                 transmute::<&str, &str>(value),
-                transmute::<&str, &str>(metric_str(metric_type)),
             )
         };
 
-        let metric_len = metric_len(metrics, tags, value, metric_type);
+        let metric_len = metric_len(metrics, tags, value, metric_type, self.tag_section_style);
 
         if metric_len > self.max_udp_packet_size as usize {
             return Err(format!("Metric is larger than {}", self.max_udp_packet_size).into());
         }
 
-        #[allow(clippy::cast_possible_truncation)]
-        if !self.current_transmit.enough_space_for(metric_len as u16) {
+        if !self.current_transmit.enough_space_for(metric_len) {
             self.queue_current_transmit();
         }
 
-        for metric in metrics {
-            self.current_transmit.push(IoSlice::new(metric.as_bytes()));
-        }
-        self.current_transmit.push(IoSlice::new(b":"));
-        self.current_transmit.push(IoSlice::new(value.as_bytes()));
-        self.current_transmit.push(IoSlice::new(b"|"));
-        self.current_transmit
-            .push(IoSlice::new(metric_type.as_bytes()));
-        if !tags.is_empty() {
-            self.current_transmit.push(IoSlice::new(b"|#"));
-            self.current_transmit.push(IoSlice::new(tags.as_bytes()));
-        }
-        self.current_transmit.push(IoSlice::new(b"\n"));
+        format_line(
+            &mut self.current_transmit,
+            metrics,
+            tags,
+            value,
+            metric_type,
+            self.tag_section_style,
+        );
 
         if self.queued_transmits.len() == self.queued_transmits.capacity() {
             self.flush_queued_transmits()?;
@@ -590,6 +1062,10 @@ impl<T: Writer> StatsWriterTrait for StatsWriterApple<T> {
         self.flush()
     }
 
+    fn messages_sent(&self) -> usize {
+        self.sent.last_flush_messages
+    }
+
     fn reset(&mut self) {
         // SAFETY NOTE: so there are no pointers to bump after the bump is reset
         // At this point current_transmit and queued_transmits should be empty because
@@ -599,31 +1075,82 @@ impl<T: Writer> StatsWriterTrait for StatsWriterApple<T> {
 
         self.tmp_mmsghdrs.clear();
     }
+
+    fn set_destination(&mut self, destination: SocketAddr) {
+        self.writer.set_destination(destination);
+    }
+
+    #[cfg(any(feature = "custom_writer", test))]
+    fn buffered_bytes(&self) -> usize {
+        queued_bytes(&self.current_transmit, &self.queued_transmits)
+    }
 }
 
 pub struct StatsWriterSimple<T> {
     max_udp_packet_size: u16,
+    tag_section_style: TagSectionStyle,
     writer: T,
     current_transmit: String,
+    #[cfg(feature = "custom_writer")]
+    packet_hook: Option<Box<dyn PacketHook>>,
+    sent: SentStats,
 }
 
 impl<T: Writer> StatsWriterSimple<T> {
-    pub fn new(writer: T, max_udp_packet_size: u16) -> Self {
+    pub fn new(writer: T, max_udp_packet_size: u16, tag_section_style: TagSectionStyle) -> Self {
         Self {
             max_udp_packet_size,
+            tag_section_style,
             writer,
             current_transmit: String::with_capacity(max_udp_packet_size as usize),
+            #[cfg(feature = "custom_writer")]
+            packet_hook: None,
+            sent: SentStats::default(),
+        }
+    }
+
+    /// Writer passing every packet through `packet_hook`, assembling packets
+    /// of up to `max_udp_packet_size - max_overhead` bytes so the hooked
+    /// packet still fits in `max_udp_packet_size`.
+    #[cfg(feature = "custom_writer")]
+    pub fn with_packet_hook(
+        writer: T,
+        max_udp_packet_size: u16,
+        tag_section_style: TagSectionStyle,
+        packet_hook: Box<dyn PacketHook>,
+        max_overhead: u16,
+    ) -> Self {
+        Self {
+            packet_hook: Some(packet_hook),
+            ..Self::new(
+                writer,
+                max_udp_packet_size.saturating_sub(max_overhead),
+                tag_section_style,
+            )
         }
     }
 
-    fn flush_current_transmit(&mut self) -> MetricResult<usize> {
+    #[cfg_attr(
+        not(feature = "custom_writer"),
+        allow(clippy::needless_pass_by_ref_mut)
+    )]
+    fn send_packet(&mut self) -> MetricResult<usize> {
+        #[cfg(feature = "custom_writer")]
+        if let Some(hook) = self.packet_hook.as_mut() {
+            let packet = hook.on_packet_complete(self.current_transmit.as_bytes())?;
+            return Ok(self.writer.write(&packet)?);
+        }
+        Ok(self.writer.write(self.current_transmit.as_bytes())?)
+    }
+
+    fn flush_current_transmit(&mut self) -> MetricResult<()> {
         if !self.current_transmit.is_empty() {
-            let result = self.writer.write(self.current_transmit.as_bytes())?;
+            let bytes = self.send_packet()?;
+            self.sent.record(bytes, 1);
             // only flush when no error occurs
             self.current_transmit.clear();
-            return Ok(result);
         }
-        Ok(0)
+        Ok(())
     }
 }
 
@@ -639,10 +1166,7 @@ impl<T: Writer> StatsWriterTrait for StatsWriterSimple<T> {
         value: &'data str,
         metric_type: MetricKind,
     ) -> MetricResult<()> {
-        let metric_type = metric_str(metric_type);
-
-        // Calculate the metric length
-        let metric_len = metric_len(metrics, tags, value, metric_type);
+        let metric_len = metric_len(metrics, tags, value, metric_type, self.tag_section_style);
 
         if metric_len > self.max_udp_packet_size as usize {
             return Err(format!("Metric is larger than {}", self.max_udp_packet_size).into());
@@ -653,28 +1177,923 @@ impl<T: Writer> StatsWriterTrait for StatsWriterSimple<T> {
             self.flush_current_transmit()?;
         }
 
-        // Build the metric string
-        for metric in metrics {
-            self.current_transmit.push_str(metric);
+        format_line(
+            &mut self.current_transmit,
+            metrics,
+            tags,
+            value,
+            metric_type,
+            self.tag_section_style,
+        );
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> MetricResult<usize> {
+        self.flush_current_transmit()?;
+        Ok(self.sent.finish_flush())
+    }
+
+    fn messages_sent(&self) -> usize {
+        self.sent.last_flush_messages
+    }
+
+    fn reset(&mut self) {
+        self.current_transmit.clear();
+    }
+
+    fn set_destination(&mut self, destination: SocketAddr) {
+        self.writer.set_destination(destination);
+    }
+
+    #[cfg(any(feature = "custom_writer", test))]
+    fn buffered_bytes(&self) -> usize {
+        self.current_transmit.len()
+    }
+}
+
+/// Writer formatting lines on the flush thread and sending the packets from
+/// background sender threads, so serializing a flush overlaps with the send
+/// syscalls of its earlier packets.
+///
+/// The batch writers reference the flush's bump allocator from their
+/// `Transmit`s, which is reset once the flush returns, so this writer copies
+/// every line into an owned packet instead. Packets go back to a pool shared
+/// with the sender threads after being sent, so the buffers are reused across
+/// flushes, and each sender thread keeps the `Transmit`s and `sendmmsg`
+/// headers of its batches. [`StatsWriterTrait::flush`] returns once every packet handed to the
+/// senders was sent or dropped.
+pub struct StatsWriterThreaded<T> {
+    max_udp_packet_size: u16,
+    tag_section_style: TagSectionStyle,
+    writer: Arc<RwLock<T>>,
+    current_packet: String,
+    // `None` once dropped, which stops the sender threads
+    packets: Option<Sender<String>>,
+    pool: Receiver<String>,
+    state: Arc<SenderState>,
+    senders: Vec<JoinHandle<()>>,
+    last_flush_messages: usize,
+}
+
+/// Progress of the packets handed to the sender threads.
+#[derive(Default)]
+struct SenderState {
+    /// Bytes handed to the sender threads and not sent or dropped yet.
+    in_flight: AtomicUsize,
+    results: Mutex<SenderResults>,
+    /// Notified when `in_flight` drops to zero.
+    idle: Condvar,
+}
+
+#[derive(Default)]
+struct SenderResults {
+    sent: SentStats,
+    // First send error since the last flush
+    error: Option<MetricsError>,
+}
+
+impl SenderState {
+    fn lock(&self) -> MutexGuard<'_, SenderResults> {
+        self.results.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Records a batch of `bytes` handed to a sender thread.
+    fn batch_done(&self, bytes: usize, sent: &SentStats, result: MetricResult<()>) {
+        let mut results = self.lock();
+        results.sent.record(sent.bytes, sent.messages);
+        if let Err(err) = result {
+            results.error.get_or_insert(err);
+        }
+        // decremented under the lock, so `wait_idle` cannot miss the wakeup
+        if self.in_flight.fetch_sub(bytes, Ordering::AcqRel) == bytes {
+            self.idle.notify_all();
+        }
+        drop(results);
+    }
+
+    /// Waits until every packet handed to the sender threads is done.
+    fn wait_idle(&self) -> MutexGuard<'_, SenderResults> {
+        self.idle
+            .wait_while(self.lock(), |_| self.in_flight.load(Ordering::Acquire) > 0)
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T: Writer + Send + Sync + 'static> StatsWriterThreaded<T> {
+    pub fn new(
+        writer: T,
+        sender_threads: usize,
+        max_udp_batch_size: u32,
+        max_udp_packet_size: u16,
+        tag_section_style: TagSectionStyle,
+    ) -> Self {
+        let writer = Arc::new(RwLock::new(writer));
+        let state = Arc::new(SenderState::default());
+        let (packets, queued) = unbounded::<String>();
+        let (pool_sender, pool) = unbounded::<String>();
+        let max_udp_batch_size = (max_udp_batch_size as usize).max(1);
+        let senders = (0..sender_threads.max(1))
+            .map(|_| {
+                let writer = Arc::clone(&writer);
+                let queued = queued.clone();
+                let pool_sender = pool_sender.clone();
+                let state = Arc::clone(&state);
+                spawn(move || {
+                    // built on its own thread, the cached headers are not `Send`
+                    let mut sender = PacketSender {
+                        #[cfg(target_os = "linux")]
+                        fallback: max_udp_batch_size == 1,
+                        #[cfg(target_os = "linux")]
+                        transmits: Vec::with_capacity(max_udp_batch_size),
+                        #[cfg(target_os = "linux")]
+                        tmp_mmsghdrs: Vec::with_capacity(max_udp_batch_size),
+                    };
+                    sender.run(&writer, &queued, &pool_sender, &state, max_udp_batch_size);
+                })
+            })
+            .collect();
+        Self {
+            max_udp_packet_size,
+            tag_section_style,
+            writer,
+            current_packet: String::with_capacity(max_udp_packet_size as usize),
+            packets: Some(packets),
+            pool,
+            state,
+            senders,
+            last_flush_messages: 0,
         }
-        self.current_transmit.push(':');
-        self.current_transmit.push_str(value);
-        self.current_transmit.push('|');
-        self.current_transmit.push_str(metric_type);
-        if !tags.is_empty() {
-            self.current_transmit.push_str("|#");
-            self.current_transmit.push_str(tags);
+    }
+
+    /// Hands the current packet to the sender threads.
+    fn dispatch_current_packet(&mut self) {
+        if self.current_packet.is_empty() {
+            return;
         }
-        self.current_transmit.push('\n');
+        let next = self
+            .pool
+            .try_recv()
+            .unwrap_or_else(|_| String::with_capacity(self.max_udp_packet_size as usize));
+        let packet = std::mem::replace(&mut self.current_packet, next);
+        let bytes = packet.len();
+        self.state.in_flight.fetch_add(bytes, Ordering::AcqRel);
+        let queued = self
+            .packets
+            .as_ref()
+            .is_some_and(|packets| packets.send(packet).is_ok());
+        if !queued {
+            self.state.batch_done(
+                bytes,
+                &SentStats::default(),
+                Err("metric writer threads stopped".into()),
+            );
+        }
+    }
+}
 
+impl<T> Drop for StatsWriterThreaded<T> {
+    fn drop(&mut self) {
+        // the sender threads send what is queued, then stop
+        drop(self.packets.take());
+        for sender in self.senders.drain(..) {
+            let _ = sender.join();
+        }
+    }
+}
+
+impl<T: Writer + Send + Sync + 'static> StatsWriterTrait for StatsWriterThreaded<T> {
+    fn metric_copied(&self) -> bool {
+        true
+    }
+
+    fn write(
+        &mut self,
+        metrics: &[&str],
+        tags: &str,
+        value: &str,
+        metric_type: MetricKind,
+    ) -> MetricResult<()> {
+        let metric_len = metric_len(metrics, tags, value, metric_type, self.tag_section_style);
+
+        if metric_len > self.max_udp_packet_size as usize {
+            return Err(format!("Metric is larger than {}", self.max_udp_packet_size).into());
+        }
+
+        if self.current_packet.len() + metric_len > self.max_udp_packet_size as usize {
+            self.dispatch_current_packet();
+        }
+
+        format_line(
+            &mut self.current_packet,
+            metrics,
+            tags,
+            value,
+            metric_type,
+            self.tag_section_style,
+        );
         Ok(())
     }
 
     fn flush(&mut self) -> MetricResult<usize> {
-        self.flush_current_transmit()
+        self.dispatch_current_packet();
+        let mut results = self.state.wait_idle();
+        let flushed = if let Some(err) = results.error.take() {
+            Err(err)
+        } else {
+            let bytes = results.sent.finish_flush();
+            self.last_flush_messages = results.sent.last_flush_messages;
+            Ok(bytes)
+        };
+        drop(results);
+        flushed
+    }
+
+    fn messages_sent(&self) -> usize {
+        self.last_flush_messages
     }
 
     fn reset(&mut self) {
-        self.current_transmit.clear();
+        self.current_packet.clear();
+    }
+
+    fn set_destination(&mut self, destination: SocketAddr) {
+        // no packet is in flight between flushes
+        self.writer
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .set_destination(destination);
+    }
+
+    #[cfg(any(feature = "custom_writer", test))]
+    fn buffered_bytes(&self) -> usize {
+        self.current_packet.len() + self.state.in_flight.load(Ordering::Acquire)
+    }
+}
+
+/// Sender thread of [`StatsWriterThreaded`].
+struct PacketSender {
+    // set once sendmmsg is rejected by the kernel (ENOSYS/EPERM), like `StatsWriterLinux`
+    #[cfg(target_os = "linux")]
+    fallback: bool,
+
+    // for reuse by every batch of this thread, like the pools of `StatsWriterLinux`;
+    // they only point into the packets of a batch while it is being sent
+    #[cfg(target_os = "linux")]
+    transmits: Vec<super::writer_utils::Transmit<'static>>,
+    #[cfg(target_os = "linux")]
+    tmp_mmsghdrs: Vec<rustix::net::MMsgHdr<'static>>,
+}
+
+impl PacketSender {
+    /// Sends batches of up to `max_udp_batch_size` queued packets until the
+    /// writer is dropped, returning every packet to `pool`.
+    fn run<T: Writer>(
+        &mut self,
+        writer: &RwLock<T>,
+        queued: &Receiver<String>,
+        pool: &Sender<String>,
+        state: &SenderState,
+        max_udp_batch_size: usize,
+    ) {
+        let mut batch = Vec::with_capacity(max_udp_batch_size);
+        while let Ok(packet) = queued.recv() {
+            batch.push(packet);
+            batch.extend(queued.try_iter().take(max_udp_batch_size - 1));
+
+            let mut sent = SentStats::default();
+            let result = self.send(
+                &*writer.read().unwrap_or_else(PoisonError::into_inner),
+                &batch,
+                &mut sent,
+            );
+            let bytes = batch.iter().map(String::len).sum();
+            state.batch_done(bytes, &sent, result);
+
+            while let Some(mut packet) = batch.pop() {
+                packet.clear();
+                let _ = pool.send(packet);
+            }
+        }
+    }
+
+    #[cfg_attr(not(target_os = "linux"), allow(clippy::unused_self))]
+    fn send<T: Writer>(
+        &mut self,
+        writer: &T,
+        packets: &[String],
+        sent: &mut SentStats,
+    ) -> MetricResult<()> {
+        #[cfg(target_os = "linux")]
+        let mut packets = packets;
+        #[cfg(target_os = "linux")]
+        while !self.fallback && packets.len() > 1 {
+            match self.send_mmsg(writer, packets) {
+                Ok(0) => return Err("sendmmsg sent none of the queued packets".into()),
+                Ok(messages) => {
+                    // sendmmsg returns the number of messages sent, the rest is sent again
+                    let messages = messages.min(packets.len());
+                    sent.record(packets[..messages].iter().map(String::len).sum(), messages);
+                    packets = &packets[messages..];
+                }
+                Err(MetricsError::Errno(errno))
+                    if errno == rustix::io::Errno::NOSYS || errno == rustix::io::Errno::PERM =>
+                {
+                    warn!(
+                        "sendmmsg is not available ({errno}), falling back to one datagram per packet"
+                    );
+                    self.fallback = true;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        for packet in packets {
+            let bytes = writer.write(packet.as_bytes())?;
+            sent.record(bytes, 1);
+        }
+        Ok(())
+    }
+
+    /// Sends `packets` with a single `sendmmsg`, returning how many were sent.
+    #[cfg(target_os = "linux")]
+    fn send_mmsg<T: Writer>(&mut self, writer: &T, packets: &[String]) -> MetricResult<usize> {
+        let destination = writer.get_destination();
+
+        assert!(self.tmp_mmsghdrs.is_empty());
+
+        if self.transmits.len() < packets.len() {
+            self.transmits.resize_with(packets.len(), || {
+                super::writer_utils::Transmit::with_parts(u16::MAX, 1)
+            });
+        }
+        for (transmit, packet) in self.transmits.iter_mut().zip(packets) {
+            // SAFETY: the transmits are reset below, before `packets` can be
+            // changed or dropped, so the slice is never read after that.
+            let packet = unsafe { transmute::<&[u8], &'static [u8]>(packet.as_bytes()) };
+            transmit.push(IoSlice::new(packet));
+        }
+        for transmit in &mut self.transmits[..packets.len()] {
+            // SAFETY: the headers are cleared below, before the transmits and the
+            // destination they point to are changed or dropped.
+            let mmsghdr = unsafe {
+                transmute::<rustix::net::MMsgHdr<'_>, rustix::net::MMsgHdr<'static>>(
+                    transmit.create_mmsghdr(destination),
+                )
+            };
+            self.tmp_mmsghdrs.push(mmsghdr);
+        }
+
+        let result = writer.write_mvec(&mut self.tmp_mmsghdrs);
+        self.tmp_mmsghdrs.clear();
+        for transmit in &mut self.transmits[..packets.len()] {
+            transmit.reset();
+        }
+        result
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::WriterPreference;
+    use std::cell::{Cell, RefCell};
+
+    struct NoSendmmsgWriter {
+        destination: SocketAddrAny,
+        mvec_calls: Cell<usize>,
+        datagrams: RefCell<Vec<Vec<u8>>>,
+    }
+
+    impl NoSendmmsgWriter {
+        fn new() -> Self {
+            let addr: SocketAddr = "127.0.0.1:8125".parse().unwrap();
+            Self {
+                destination: SocketAddrAny::from(addr),
+                mvec_calls: Cell::new(0),
+                datagrams: RefCell::new(Vec::new()),
+            }
+        }
+    }
+
+    impl Writer for NoSendmmsgWriter {
+        fn write(&self, buf: &[u8]) -> std::io::Result<usize> {
+            self.datagrams.borrow_mut().push(buf.to_vec());
+            Ok(buf.len())
+        }
+
+        fn write_mvec(&self, _: &mut [rustix::net::MMsgHdr<'_>]) -> MetricResult<usize> {
+            self.mvec_calls.set(self.mvec_calls.get() + 1);
+            Err(rustix::io::Errno::NOSYS.into())
+        }
+
+        fn get_destination(&self) -> Option<&SocketAddrAny> {
+            Some(&self.destination)
+        }
+    }
+
+    #[test]
+    fn linux_writer_falls_back_to_single_datagrams_on_enosys() {
+        let writer = NoSendmmsgWriter::new();
+        let mut stats_writer = StatsWriterLinux::new(&writer, 8, 1432, TagSectionStyle::Omit);
+
+        stats_writer
+            .write(&["test.count"], "env:a", "1", MetricKind::Count)
+            .unwrap();
+        assert_eq!(stats_writer.flush().unwrap(), 22);
+
+        stats_writer
+            .write(&["test.gauge"], "", "2", MetricKind::Gauge)
+            .unwrap();
+        assert_eq!(stats_writer.flush().unwrap(), 15);
+
+        // sendmmsg is only attempted once, afterwards the writer stays downgraded
+        assert_eq!(writer.mvec_calls.get(), 1);
+        assert_eq!(
+            *writer.datagrams.borrow(),
+            vec![
+                b"test.count:1|c|#env:a\n".to_vec(),
+                b"test.gauge:2|g\n".to_vec()
+            ]
+        );
+    }
+
+    #[test]
+    fn linux_writer_retry_after_a_failed_datagram_sends_only_the_unsent_ones() {
+        let writer = FailOnceWriter {
+            fail_after: 1,
+            failed: Cell::new(false),
+            inner: NoSendmmsgWriter::new(),
+        };
+        // one line per packet
+        let mut stats_writer = StatsWriterLinux::new(&writer, 8, 16, TagSectionStyle::Omit);
+        for name in ["test.a", "test.b", "test.c"] {
+            stats_writer
+                .write(&[name], "", "1", MetricKind::Count)
+                .unwrap();
+        }
+        assert!(stats_writer.flush().is_err());
+        assert_eq!(stats_writer.flush().unwrap(), 33);
+
+        assert_eq!(
+            *writer.inner.datagrams.borrow(),
+            vec![
+                b"test.a:1|c\n".to_vec(),
+                b"test.b:1|c\n".to_vec(),
+                b"test.c:1|c\n".to_vec()
+            ]
+        );
+    }
+
+    #[test]
+    fn linux_writer_reset_after_a_panicking_flush_sends_only_fresh_lines() {
+        let writer = PanicOnceWriter {
+            panicked: Cell::new(false),
+            inner: NoSendmmsgWriter::new(),
+        };
+        // one line per packet, and every queued packet is sent right away
+        let mut stats_writer = StatsWriterLinux::new(&writer, 1, 16, TagSectionStyle::Omit);
+        stats_writer
+            .write(&["stale.a"], "", "1", MetricKind::Count)
+            .unwrap();
+
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            // queues `stale.a`, keeps `stale.b` as the current packet and sends
+            let _ = stats_writer.write(&["stale.b"], "", "1", MetricKind::Count);
+        }));
+        assert!(panicked.is_err());
+        stats_writer.reset();
+
+        stats_writer
+            .write(&["fresh"], "", "1", MetricKind::Count)
+            .unwrap();
+        stats_writer.flush().unwrap();
+        assert_eq!(
+            *writer.inner.datagrams.borrow(),
+            vec![b"fresh:1|c\n".to_vec()]
+        );
+    }
+
+    /// Panics on the first send, like a bug part way through a flush.
+    struct PanicOnceWriter {
+        panicked: Cell<bool>,
+        inner: NoSendmmsgWriter,
+    }
+
+    impl Writer for PanicOnceWriter {
+        fn write(&self, buf: &[u8]) -> std::io::Result<usize> {
+            self.inner.write(buf)
+        }
+
+        fn write_mvec(&self, headers: &mut [rustix::net::MMsgHdr<'_>]) -> MetricResult<usize> {
+            assert!(self.panicked.replace(true), "send panics");
+            self.inner.write_mvec(headers)
+        }
+
+        fn get_destination(&self) -> Option<&SocketAddrAny> {
+            self.inner.get_destination()
+        }
+    }
+
+    /// Fails the single datagram sent after `fail_after` others, once.
+    struct FailOnceWriter {
+        fail_after: usize,
+        failed: Cell<bool>,
+        inner: NoSendmmsgWriter,
+    }
+
+    impl Writer for FailOnceWriter {
+        fn write(&self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.inner.datagrams.borrow().len() == self.fail_after && !self.failed.replace(true)
+            {
+                return Err(std::io::Error::other("send failed"));
+            }
+            self.inner.write(buf)
+        }
+
+        fn write_mvec(&self, headers: &mut [rustix::net::MMsgHdr<'_>]) -> MetricResult<usize> {
+            self.inner.write_mvec(headers)
+        }
+
+        fn get_destination(&self) -> Option<&SocketAddrAny> {
+            self.inner.get_destination()
+        }
+    }
+
+    /// Accepts at most `accepted` messages per `sendmmsg`, like a full socket buffer.
+    struct PartialSendmmsgWriter {
+        destination: SocketAddrAny,
+        accepted: usize,
+    }
+
+    impl Writer for PartialSendmmsgWriter {
+        fn write(&self, buf: &[u8]) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn write_mvec(&self, headers: &mut [rustix::net::MMsgHdr<'_>]) -> MetricResult<usize> {
+            Ok(headers.len().min(self.accepted))
+        }
+
+        fn get_destination(&self) -> Option<&SocketAddrAny> {
+            Some(&self.destination)
+        }
+    }
+
+    #[test]
+    fn writers_flush_returns_bytes_sent_since_the_previous_flush() {
+        const MAX: u16 = 32;
+        // two 20-byte lines do not share a packet
+        let name = "m".repeat(15);
+        let fallback_writer = NoSendmmsgWriter::new();
+        let mut simple = StatsWriterSimple::new(&fallback_writer, MAX, TagSectionStyle::Omit);
+        let mut linux = StatsWriterLinux::new(&fallback_writer, 8, MAX, TagSectionStyle::Omit);
+        let writers: [&mut dyn StatsWriterTrait; 2] = [&mut simple, &mut linux];
+        for stats_writer in writers {
+            assert_eq!(stats_writer.flush().unwrap(), 0);
+            assert_eq!(stats_writer.messages_sent(), 0);
+            for _ in 0..3 {
+                stats_writer
+                    .write(&[name.as_str()], "", "1", MetricKind::Count)
+                    .unwrap();
+            }
+            // the simple writer already sent two packets while writing
+            assert_eq!(stats_writer.flush().unwrap(), 60);
+            assert_eq!(stats_writer.messages_sent(), 3);
+            stats_writer.reset();
+            assert_eq!(stats_writer.messages_sent(), 3);
+            assert_eq!(stats_writer.flush().unwrap(), 0);
+            assert_eq!(stats_writer.messages_sent(), 0);
+        }
+
+        // sendmmsg reports messages; only the bytes of the sent ones count
+        let partial_writer = PartialSendmmsgWriter {
+            destination: SocketAddrAny::from("127.0.0.1:8125".parse::<SocketAddr>().unwrap()),
+            accepted: 2,
+        };
+        let mut linux = StatsWriterLinux::new(&partial_writer, 2, MAX, TagSectionStyle::Omit);
+        for _ in 0..3 {
+            linux
+                .write(&[name.as_str()], "", "1", MetricKind::Count)
+                .unwrap();
+        }
+        linux.write(&["n"], "", "1", MetricKind::Count).unwrap();
+        // the first batch of two packets was sent when the queue filled up
+        assert_eq!(linux.flush().unwrap(), 66);
+        assert_eq!(linux.messages_sent(), 3);
+    }
+
+    #[test]
+    fn packet_sender_sends_the_rest_of_a_partial_sendmmsg() {
+        let writer = PartialSendmmsgWriter {
+            destination: SocketAddrAny::from("127.0.0.1:8125".parse::<SocketAddr>().unwrap()),
+            accepted: 1,
+        };
+        let mut sender = PacketSender {
+            fallback: false,
+            transmits: Vec::new(),
+            tmp_mmsghdrs: Vec::new(),
+        };
+        let packets = ["a:1|c\n", "bb:1|c\n", "ccc:1|c\n", "dddd:1|c\n"].map(String::from);
+
+        let mut sent = SentStats::default();
+        sender.send(&writer, &packets, &mut sent).unwrap();
+        assert_eq!(sent.messages, 4);
+        assert_eq!(sent.bytes, 30);
+
+        let refusing_writer = PartialSendmmsgWriter {
+            accepted: 0,
+            ..writer
+        };
+        let mut sent = SentStats::default();
+        assert!(sender.send(&refusing_writer, &packets, &mut sent).is_err());
+        assert_eq!(sent.messages, 0);
+    }
+
+    #[test]
+    fn holder_reports_the_writer_it_built() {
+        let kind = |writer_type, max_udp_batch_size| {
+            StatsWriterHolder::new(
+                NoSendmmsgWriter::new(),
+                writer_type,
+                1432,
+                max_udp_batch_size,
+                TagSectionStyle::Omit,
+            )
+            .kind()
+        };
+
+        assert_eq!(kind(StatsWriterType::Simple, 10), StatsWriterKind::Simple);
+        assert_eq!(
+            kind(StatsWriterType::LinuxBatch, 10),
+            StatsWriterKind::LinuxBatch
+        );
+        assert_eq!(
+            kind(StatsWriterType::Preferred(WriterPreference::Auto), 10),
+            StatsWriterKind::LinuxBatch
+        );
+        assert_eq!(
+            kind(StatsWriterType::Preferred(WriterPreference::Auto), 1),
+            StatsWriterKind::Simple
+        );
+    }
+
+    #[test]
+    fn linux_writer_rejects_lines_longer_than_u16() {
+        let writer = NoSendmmsgWriter::new();
+        let mut stats_writer = StatsWriterLinux::new(&writer, 8, u16::MAX, TagSectionStyle::Omit);
+        let fragment = "a".repeat(20_000);
+        let fragments = [fragment.as_str(); 4];
+
+        let err = stats_writer
+            .write(&fragments, "", "1", MetricKind::Count)
+            .unwrap_err();
+        assert!(err.to_string().contains("Metric is larger than 65535"));
+
+        let big = "b".repeat(65_000);
+        let small = "c".repeat(1_000);
+        stats_writer
+            .write(&[big.as_str()], "", "1", MetricKind::Count)
+            .unwrap();
+        stats_writer
+            .write(&[small.as_str()], "", "1", MetricKind::Count)
+            .unwrap();
+        assert_eq!(stats_writer.flush().unwrap(), 66_010);
+        assert_eq!(stats_writer.messages_sent(), 2);
+
+        let datagrams = writer.datagrams.borrow();
+        assert_eq!(datagrams.len(), 2);
+        assert!(datagrams.iter().all(|datagram| datagram.len() <= 65_535));
+    }
+
+    #[test]
+    fn writers_pack_lines_up_to_exactly_the_packet_size() {
+        const MAX: u16 = 32;
+        // `<name>:1|c\n` is the name plus 5 bytes
+        let name = |line_len: usize| "m".repeat(line_len - 5);
+        let (below, exact, above) = (
+            name(usize::from(MAX) - 1),
+            name(usize::from(MAX)),
+            name(usize::from(MAX) + 1),
+        );
+        let filler = name(usize::from(MAX) - 6);
+
+        let simple_writer = NoSendmmsgWriter::new();
+        let linux_writer = NoSendmmsgWriter::new();
+        let mut simple = StatsWriterSimple::new(&simple_writer, MAX, TagSectionStyle::Omit);
+        let mut linux = StatsWriterLinux::new(&linux_writer, 8, MAX, TagSectionStyle::Omit);
+        let writers: [&mut dyn StatsWriterTrait; 2] = [&mut simple, &mut linux];
+        for stats_writer in writers {
+            stats_writer
+                .write(&[below.as_str()], "", "1", MetricKind::Count)
+                .unwrap();
+            // does not fit after `below`, so it fills a fresh packet on its own
+            stats_writer
+                .write(&[exact.as_str()], "", "1", MetricKind::Count)
+                .unwrap();
+            let err = stats_writer
+                .write(&[above.as_str()], "", "1", MetricKind::Count)
+                .unwrap_err();
+            assert!(err.to_string().contains("Metric is larger than 32"));
+
+            // two lines adding up to exactly `MAX` share a packet
+            stats_writer
+                .write(&[filler.as_str()], "", "1", MetricKind::Count)
+                .unwrap();
+            stats_writer
+                .write(&["n"], "", "1", MetricKind::Count)
+                .unwrap();
+            stats_writer.flush().unwrap();
+        }
+
+        let expected = vec![
+            format!("{below}:1|c\n").into_bytes(),
+            format!("{exact}:1|c\n").into_bytes(),
+            format!("{filler}:1|c\nn:1|c\n").into_bytes(),
+        ];
+        assert_eq!(*simple_writer.datagrams.borrow(), expected);
+        assert_eq!(*linux_writer.datagrams.borrow(), expected);
+    }
+
+    #[test]
+    fn writers_report_buffered_bytes_until_flush() {
+        let writer = NoSendmmsgWriter::new();
+        let mut simple = StatsWriterSimple::new(&writer, 1432, TagSectionStyle::Omit);
+        // small packets so the batch writer queues several transmits before flushing
+        let mut linux = StatsWriterLinux::new(&writer, 8, 24, TagSectionStyle::Omit);
+
+        let writers: [&mut dyn StatsWriterTrait; 2] = [&mut simple, &mut linux];
+        for stats_writer in writers {
+            assert_eq!(stats_writer.buffered_bytes(), 0);
+            let mut previous = 0;
+            for _ in 0..4 {
+                stats_writer
+                    .write(&["test.count"], "env:a", "1", MetricKind::Count)
+                    .unwrap();
+                assert!(stats_writer.buffered_bytes() > previous);
+                previous = stats_writer.buffered_bytes();
+            }
+            // four `test.count:1|c|#env:a\n` lines
+            assert_eq!(previous, 4 * 22);
+
+            stats_writer.flush().unwrap();
+            assert_eq!(stats_writer.buffered_bytes(), 0);
+        }
+    }
+
+    #[test]
+    fn writers_render_the_tag_section_style_alike() {
+        for (style, untagged) in [
+            (TagSectionStyle::Omit, "test.gauge:2|g\n"),
+            (TagSectionStyle::Always, "test.gauge:2|g|#\n"),
+        ] {
+            let simple_writer = NoSendmmsgWriter::new();
+            let linux_writer = NoSendmmsgWriter::new();
+            let mut simple = StatsWriterSimple::new(&simple_writer, 1432, style);
+            let mut linux = StatsWriterLinux::new(&linux_writer, 8, 1432, style);
+
+            let writers: [&mut dyn StatsWriterTrait; 2] = [&mut simple, &mut linux];
+            for stats_writer in writers {
+                stats_writer
+                    .write(&["test.count"], "env:a", "1", MetricKind::Count)
+                    .unwrap();
+                stats_writer
+                    .write(&["test.", "gauge"], "", "2", MetricKind::Gauge)
+                    .unwrap();
+                assert_eq!(
+                    stats_writer.buffered_bytes(),
+                    "test.count:1|c|#env:a\n".len() + untagged.len()
+                );
+                stats_writer.flush().unwrap();
+            }
+
+            let expected = format!("test.count:1|c|#env:a\n{untagged}");
+            assert_eq!(
+                *simple_writer.datagrams.borrow(),
+                vec![expected.clone().into_bytes()]
+            );
+            assert_eq!(
+                *linux_writer.datagrams.borrow(),
+                vec![expected.into_bytes()]
+            );
+        }
+    }
+
+    #[test]
+    fn writers_emit_format_line_output_byte_for_byte() {
+        let lines: [(&[&str], &str, &str, MetricKind); 3] = [
+            (&["app.", "hits"], "env:a,route:/", "12", MetricKind::Count),
+            (&["app.load"], "", "0.5", MetricKind::Gauge),
+            (
+                &["app.", "req.", "latency"],
+                "env:a",
+                "42",
+                MetricKind::Timing,
+            ),
+        ];
+        let mut expected = String::new();
+        for (metrics, tags, value, metric_type) in lines {
+            format_line(
+                &mut expected,
+                metrics,
+                tags,
+                value,
+                metric_type,
+                TagSectionStyle::Omit,
+            );
+        }
+        assert_eq!(
+            expected,
+            "app.hits:12|c|#env:a,route:/\napp.load:0.5|g\napp.req.latency:42|ms|#env:a\n"
+        );
+
+        let simple_writer = NoSendmmsgWriter::new();
+        let linux_writer = NoSendmmsgWriter::new();
+        let mut simple = StatsWriterSimple::new(&simple_writer, 1432, TagSectionStyle::Omit);
+        let mut linux = StatsWriterLinux::new(&linux_writer, 8, 1432, TagSectionStyle::Omit);
+        let writers: [&mut dyn StatsWriterTrait; 2] = [&mut simple, &mut linux];
+        for stats_writer in writers {
+            for (metrics, tags, value, metric_type) in lines {
+                stats_writer
+                    .write(metrics, tags, value, metric_type)
+                    .unwrap();
+            }
+            assert_eq!(stats_writer.buffered_bytes(), expected.len());
+            stats_writer.flush().unwrap();
+        }
+
+        assert_eq!(
+            *simple_writer.datagrams.borrow(),
+            vec![expected.as_bytes().to_vec()]
+        );
+        assert_eq!(
+            *simple_writer.datagrams.borrow(),
+            *linux_writer.datagrams.borrow()
+        );
+    }
+}
+
+#[cfg(all(test, target_os = "macos"))]
+mod apple_tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn apple_writer_packs_lines_up_to_exactly_the_packet_size() {
+        const MAX: u16 = 32;
+        // `<name>:1|c\n` is the name plus 5 bytes
+        let name = |line_len: usize| "m".repeat(line_len - 5);
+        let (below, exact, above) = (
+            name(usize::from(MAX) - 1),
+            name(usize::from(MAX)),
+            name(usize::from(MAX) + 1),
+        );
+        let filler = name(usize::from(MAX) - 6);
+
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let destination_addr = receiver.local_addr().unwrap();
+        let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sock.connect(destination_addr).unwrap();
+        let writer = UdpSocketWriter {
+            sock,
+            destination_addr,
+            connected: true,
+        };
+
+        let mut apple = StatsWriterApple::new(&writer, 8, MAX, TagSectionStyle::Omit);
+        apple
+            .write(&[below.as_str()], "", "1", MetricKind::Count)
+            .unwrap();
+        // does not fit after `below`, so it fills a fresh packet on its own
+        apple
+            .write(&[exact.as_str()], "", "1", MetricKind::Count)
+            .unwrap();
+        let err = apple
+            .write(&[above.as_str()], "", "1", MetricKind::Count)
+            .unwrap_err();
+        assert!(err.to_string().contains("Metric is larger than 32"));
+
+        // two lines adding up to exactly `MAX` share a packet
+        apple
+            .write(&[filler.as_str()], "", "1", MetricKind::Count)
+            .unwrap();
+        apple.write(&["n"], "", "1", MetricKind::Count).unwrap();
+        apple.flush().unwrap();
+
+        let expected = vec![
+            format!("{below}:1|c\n").into_bytes(),
+            format!("{exact}:1|c\n").into_bytes(),
+            format!("{filler}:1|c\nn:1|c\n").into_bytes(),
+        ];
+        let mut buffer = [0_u8; 64];
+        let datagrams = (0..expected.len())
+            .map(|_| {
+                let len = receiver.recv(&mut buffer).unwrap();
+                buffer[..len].to_vec()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(datagrams, expected);
     }
 }
@@ -6,53 +6,68 @@ pub struct Transmit<'data> {
     #[cfg(target_os = "linux")]
     ancilliary: rustix::net::SendAncillaryBuffer<'data, 'data, 'data>,
 
-    len: u16,
-    max_udp_package_size: u16,
+    len: usize,
+    max_udp_package_size: usize,
 }
 
 impl<'data> Transmit<'data> {
     pub fn new(max_udp_package_size: u16) -> Self {
         // A typical DogStatsD line is ~80-120 bytes and uses 4-5 IoSlice parts
         // (metric, value, type, tags, newline). Estimate capacity from packet size.
-        let estimated_parts = (max_udp_package_size as usize / 80) * 5;
-        let capacity = estimated_parts.max(32);
+        let estimated_parts = (usize::from(max_udp_package_size) / 80) * 5;
+        Self::with_parts(max_udp_package_size, estimated_parts.max(32))
+    }
+
+    /// Creates a transmit with room for `parts` parts, e.g. one for a packet
+    /// that is already assembled.
+    pub fn with_parts(max_udp_package_size: u16, parts: usize) -> Self {
         Self {
-            parts: Vec::with_capacity(capacity),
+            parts: Vec::with_capacity(parts),
             #[cfg(target_os = "linux")]
             ancilliary: rustix::net::SendAncillaryBuffer::default(),
             len: 0,
-            max_udp_package_size,
+            max_udp_package_size: usize::from(max_udp_package_size),
         }
     }
 
-    pub const fn enough_space_for(&self, space: u16) -> bool {
-        self.len + space <= self.max_udp_package_size
+    pub const fn enough_space_for(&self, space: usize) -> bool {
+        match self.len.checked_add(space) {
+            Some(total) => total <= self.max_udp_package_size,
+            None => false,
+        }
     }
 
     pub fn push(&mut self, part: IoSlice<'data>) {
-        debug_assert!(
-            u16::try_from(part.len()).is_ok(),
-            "IoSlice exceeds u16 capacity"
-        );
-        #[allow(clippy::cast_possible_truncation)]
-        {
-            self.len += part.len() as u16;
-        }
+        self.len = self.len.saturating_add(part.len());
         self.parts.push(part);
     }
 
     #[cfg(target_os = "linux")]
     pub fn create_mmsghdr<'s, 'c: 's>(
         &'s mut self,
-        dst_addr: &'c rustix::net::SocketAddrAny,
+        dst_addr: Option<&'c rustix::net::SocketAddrAny>,
     ) -> rustix::net::MMsgHdr<'s> {
-        rustix::net::MMsgHdr::new_with_addr(dst_addr, self.parts.as_slice(), &mut self.ancilliary)
+        match dst_addr {
+            Some(dst_addr) => rustix::net::MMsgHdr::new_with_addr(
+                dst_addr,
+                self.parts.as_slice(),
+                &mut self.ancilliary,
+            ),
+            None => rustix::net::MMsgHdr::new(self.parts.as_slice(), &mut self.ancilliary),
+        }
     }
 
-    pub const fn len(&self) -> u16 {
+    pub const fn len(&self) -> usize {
         self.len
     }
 
+    #[cfg(target_os = "linux")]
+    pub fn copy_to(&self, buffer: &mut Vec<u8>) {
+        for part in &self.parts {
+            buffer.extend_from_slice(part);
+        }
+    }
+
     #[cfg(target_vendor = "apple")]
     pub fn get_iovecs(&self) -> &[std::io::IoSlice<'data>] {
         &self.parts
@@ -63,3 +78,35 @@ impl<'data> Transmit<'data> {
         self.len = 0;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transmit_accepts_a_line_of_exactly_the_packet_size() {
+        let mut transmit = Transmit::new(32);
+        assert!(transmit.enough_space_for(32));
+        assert!(!transmit.enough_space_for(33));
+
+        let line = [b'a'; 31];
+        transmit.push(IoSlice::new(&line));
+        assert!(transmit.enough_space_for(1));
+        assert!(!transmit.enough_space_for(2));
+    }
+
+    #[test]
+    fn transmit_len_does_not_wrap_past_u16() {
+        let part = vec![b'a'; 40_000];
+        let mut transmit = Transmit::new(u16::MAX);
+        transmit.push(IoSlice::new(&part));
+        assert!(transmit.enough_space_for(25_535));
+        assert!(!transmit.enough_space_for(25_536));
+        assert!(!transmit.enough_space_for(usize::MAX));
+
+        transmit.push(IoSlice::new(&part));
+        assert_eq!(transmit.len(), 80_000);
+        assert!(!transmit.enough_space_for(0));
+        assert!(!transmit.enough_space_for(1));
+    }
+}
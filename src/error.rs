@@ -26,6 +26,17 @@ pub enum MetricsError {
     /// Failed to create a histogram.
     #[error("Histogram creation error: {0}")]
     HistogramCreation(#[from] CreationError),
+
+    /// A destination host name did not resolve to a usable address.
+    #[error("Cannot resolve destination {host}:{port}: {reason}")]
+    Resolve {
+        /// Host name that was looked up.
+        host: String,
+        /// Destination port.
+        port: u16,
+        /// Why no address was returned.
+        reason: String,
+    },
 }
 
 impl From<String> for MetricsError {
@@ -39,3 +50,115 @@ impl From<&str> for MetricsError {
         Self::Custom(value.to_string())
     }
 }
+
+impl MetricsError {
+    /// Returns whether retrying the failed operation later may succeed.
+    ///
+    /// I/O errors caused by a full socket buffer, an interrupted call or an
+    /// agent that is not reachable yet are transient. Configuration, resolution,
+    /// histogram and custom errors are permanent.
+    #[must_use]
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::StdIo(err) => is_transient_io(err),
+            #[cfg(feature = "udp")]
+            Self::Errno(errno) => is_transient_errno(*errno),
+            Self::Custom(_)
+            | Self::Histogram(_)
+            | Self::HistogramCreation(_)
+            | Self::Resolve { .. } => false,
+        }
+    }
+}
+
+fn is_transient_io(err: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+
+    // `ENOBUFS` has no dedicated kind, so OS errors are classified by errno
+    #[cfg(feature = "udp")]
+    if let Some(raw) = err.raw_os_error() {
+        return is_transient_errno(Errno::from_raw_os_error(raw));
+    }
+    matches!(
+        err.kind(),
+        ErrorKind::WouldBlock
+            | ErrorKind::Interrupted
+            | ErrorKind::TimedOut
+            | ErrorKind::ConnectionRefused
+            | ErrorKind::ConnectionReset
+            | ErrorKind::HostUnreachable
+            | ErrorKind::NetworkUnreachable
+    )
+}
+
+#[cfg(feature = "udp")]
+fn is_transient_errno(errno: Errno) -> bool {
+    [
+        Errno::AGAIN,
+        Errno::WOULDBLOCK,
+        Errno::INTR,
+        Errno::NOBUFS,
+        Errno::TIMEDOUT,
+        Errno::CONNREFUSED,
+        Errno::CONNRESET,
+        Errno::HOSTUNREACH,
+        Errno::NETUNREACH,
+    ]
+    .contains(&errno)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Error, ErrorKind};
+
+    #[test]
+    fn io_errors_are_classified_by_kind() {
+        for kind in [
+            ErrorKind::WouldBlock,
+            ErrorKind::Interrupted,
+            ErrorKind::TimedOut,
+            ErrorKind::ConnectionRefused,
+        ] {
+            assert!(
+                MetricsError::from(Error::from(kind)).is_transient(),
+                "{kind:?}"
+            );
+        }
+        for kind in [
+            ErrorKind::InvalidInput,
+            ErrorKind::PermissionDenied,
+            ErrorKind::AddrInUse,
+        ] {
+            assert!(
+                !MetricsError::from(Error::from(kind)).is_transient(),
+                "{kind:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn non_io_errors_are_permanent() {
+        assert!(!MetricsError::from("name too large").is_transient());
+        let creation = hdrhistogram::Histogram::<u64>::new_with_bounds(10, 1, 3).unwrap_err();
+        assert!(!MetricsError::from(creation).is_transient());
+        let mut histogram = hdrhistogram::Histogram::<u64>::new_with_bounds(1, 10, 3).unwrap();
+        let record = histogram.record(u64::MAX).unwrap_err();
+        assert!(!MetricsError::from(record).is_transient());
+    }
+
+    #[cfg(feature = "udp")]
+    #[test]
+    fn errno_values_are_classified() {
+        for errno in [Errno::AGAIN, Errno::INTR, Errno::NOBUFS, Errno::CONNREFUSED] {
+            assert!(MetricsError::from(errno).is_transient(), "{errno}");
+            let io = Error::from_raw_os_error(errno.raw_os_error());
+            assert!(MetricsError::from(io).is_transient(), "{errno}");
+        }
+        for errno in [Errno::MSGSIZE, Errno::INVAL, Errno::PERM, Errno::NOSYS] {
+            assert!(!MetricsError::from(errno).is_transient(), "{errno}");
+            let io = Error::from_raw_os_error(errno.raw_os_error());
+            assert!(!MetricsError::from(io).is_transient(), "{errno}");
+        }
+    }
+}
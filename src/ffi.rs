@@ -0,0 +1,230 @@
+//! C-compatible entry points for recording metrics from non-Rust services.
+//!
+//! The collector is exposed as an opaque [`RylvCollector`] pointer created by
+//! [`rylv_collector_new`] and released by [`rylv_shutdown`]. Metric names and
+//! tags are null-terminated UTF-8 strings that are only borrowed for the
+//! duration of the call.
+//!
+//! Every function reports failures through its return value and never lets a
+//! panic cross the FFI boundary. The crate is built as an `rlib`; to link it
+//! from C, build a static library with
+//! `cargo rustc --release --features ffi --crate-type staticlib`.
+
+use std::ffi::{c_char, c_int, CStr};
+use std::net::SocketAddr;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use std::time::Duration;
+
+use tracing::error;
+
+use crate::{
+    MetricCollector, MetricCollectorOptions, MetricCollectorTrait, RylvStr, SharedCollector,
+};
+
+/// Opaque collector handle handed out to C callers.
+pub type RylvCollector = MetricCollector<SharedCollector>;
+
+/// The call succeeded.
+pub const RYLV_OK: c_int = 0;
+/// A pointer argument was null or a string was not valid UTF-8.
+pub const RYLV_ERR_INVALID_ARGUMENT: c_int = -1;
+/// The call panicked internally; the panic was caught and the metric dropped.
+pub const RYLV_ERR_PANIC: c_int = -2;
+
+#[derive(Clone, Copy)]
+enum Recording {
+    Histogram,
+    Count,
+    Gauge,
+}
+
+/// Borrows a C string as `&str`, returning `None` for null or invalid UTF-8.
+///
+/// # Safety
+/// `value` must be null or point to a null-terminated string that stays valid for `'a`.
+unsafe fn borrow_str<'a>(value: *const c_char) -> Option<&'a str> {
+    if value.is_null() {
+        return None;
+    }
+    // SAFETY: non-null and null-terminated per the caller contract.
+    unsafe { CStr::from_ptr(value) }.to_str().ok()
+}
+
+/// Borrows `tags_len` C strings starting at `tags_ptr`.
+///
+/// # Safety
+/// When `tags_len` is non-zero, `tags_ptr` must point to `tags_len` readable
+/// pointers, each satisfying the contract of [`borrow_str`].
+unsafe fn borrow_tags<'a>(
+    tags_ptr: *const *const c_char,
+    tags_len: usize,
+) -> Option<Vec<RylvStr<'a>>> {
+    if tags_len == 0 {
+        return Some(Vec::new());
+    }
+    if tags_ptr.is_null() {
+        return None;
+    }
+    // SAFETY: non-null and valid for `tags_len` reads per the caller contract.
+    let raw_tags = unsafe { std::slice::from_raw_parts(tags_ptr, tags_len) };
+    raw_tags
+        .iter()
+        // SAFETY: each entry follows the `borrow_str` contract.
+        .map(|tag| unsafe { borrow_str(*tag) }.map(RylvStr::from))
+        .collect()
+}
+
+/// Shared body of the recording functions.
+///
+/// # Safety
+/// See [`rylv_histogram`].
+unsafe fn record(
+    handle: *const RylvCollector,
+    recording: Recording,
+    name: *const c_char,
+    value: u64,
+    tags_ptr: *const *const c_char,
+    tags_len: usize,
+) -> c_int {
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        // SAFETY: the handle was returned by `rylv_collector_new` and not yet shut down.
+        let Some(collector) = (unsafe { handle.as_ref() }) else {
+            return RYLV_ERR_INVALID_ARGUMENT;
+        };
+        // SAFETY: forwarded caller contract.
+        let (Some(name), Some(mut tags)) = (unsafe { borrow_str(name) }, unsafe {
+            borrow_tags(tags_ptr, tags_len)
+        }) else {
+            return RYLV_ERR_INVALID_ARGUMENT;
+        };
+        let metric = RylvStr::from(name);
+        match recording {
+            Recording::Histogram => collector.histogram(metric, value, &mut tags),
+            Recording::Count => collector.count_add(metric, value, &mut tags),
+            Recording::Gauge => collector.gauge(metric, value, &mut tags),
+        }
+        RYLV_OK
+    }));
+    result.unwrap_or(RYLV_ERR_PANIC)
+}
+
+/// Creates a UDP collector backed by a [`SharedCollector`].
+///
+/// `bind_addr` and `dst_addr` are `ip:port` strings, for example `"0.0.0.0:0"`
+/// and `"127.0.0.1:8125"`. Returns null if an argument is invalid or the
+/// socket cannot be set up. The handle must be released with [`rylv_shutdown`].
+///
+/// # Safety
+/// `bind_addr` and `dst_addr` must be null or point to null-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn rylv_collector_new(
+    bind_addr: *const c_char,
+    dst_addr: *const c_char,
+    flush_interval_ms: u64,
+) -> *mut RylvCollector {
+    let result = catch_unwind(|| {
+        // SAFETY: forwarded caller contract.
+        let parse = |addr| unsafe { borrow_str(addr) }?.parse::<SocketAddr>().ok();
+        let (Some(bind_addr), Some(dst_addr)) = (parse(bind_addr), parse(dst_addr)) else {
+            return ptr::null_mut();
+        };
+        let options = MetricCollectorOptions {
+            flush_interval: Duration::from_millis(flush_interval_ms),
+            ..Default::default()
+        };
+        match MetricCollector::new(bind_addr, dst_addr, options, SharedCollector::default()) {
+            Ok(collector) => Box::into_raw(Box::new(collector)),
+            Err(err) => {
+                error!("rylv_collector_new failed: {err}");
+                ptr::null_mut()
+            }
+        }
+    });
+    result.unwrap_or(ptr::null_mut())
+}
+
+/// Records a histogram value.
+///
+/// Returns [`RYLV_OK`], [`RYLV_ERR_INVALID_ARGUMENT`] or [`RYLV_ERR_PANIC`].
+///
+/// # Safety
+/// - `handle` must be null or a live pointer returned by [`rylv_collector_new`].
+/// - `name` must be null or point to a null-terminated string.
+/// - When `tags_len` is non-zero, `tags_ptr` must point to `tags_len`
+///   pointers, each null or pointing to a null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rylv_histogram(
+    handle: *const RylvCollector,
+    name: *const c_char,
+    value: u64,
+    tags_ptr: *const *const c_char,
+    tags_len: usize,
+) -> c_int {
+    // SAFETY: forwarded caller contract.
+    unsafe {
+        record(
+            handle,
+            Recording::Histogram,
+            name,
+            value,
+            tags_ptr,
+            tags_len,
+        )
+    }
+}
+
+/// Adds `value` to a counter.
+///
+/// Returns [`RYLV_OK`], [`RYLV_ERR_INVALID_ARGUMENT`] or [`RYLV_ERR_PANIC`].
+///
+/// # Safety
+/// Same contract as [`rylv_histogram`].
+#[no_mangle]
+pub unsafe extern "C" fn rylv_count(
+    handle: *const RylvCollector,
+    name: *const c_char,
+    value: u64,
+    tags_ptr: *const *const c_char,
+    tags_len: usize,
+) -> c_int {
+    // SAFETY: forwarded caller contract.
+    unsafe { record(handle, Recording::Count, name, value, tags_ptr, tags_len) }
+}
+
+/// Sets a gauge value.
+///
+/// Returns [`RYLV_OK`], [`RYLV_ERR_INVALID_ARGUMENT`] or [`RYLV_ERR_PANIC`].
+///
+/// # Safety
+/// Same contract as [`rylv_histogram`].
+#[no_mangle]
+pub unsafe extern "C" fn rylv_gauge(
+    handle: *const RylvCollector,
+    name: *const c_char,
+    value: u64,
+    tags_ptr: *const *const c_char,
+    tags_len: usize,
+) -> c_int {
+    // SAFETY: forwarded caller contract.
+    unsafe { record(handle, Recording::Gauge, name, value, tags_ptr, tags_len) }
+}
+
+/// Flushes pending metrics, stops the worker thread and frees the collector.
+///
+/// Passing null is a no-op.
+///
+/// # Safety
+/// `handle` must be null or a pointer returned by [`rylv_collector_new`] that
+/// has not been shut down yet. No other thread may use it during or after this call.
+#[no_mangle]
+pub unsafe extern "C" fn rylv_shutdown(handle: *mut RylvCollector) {
+    if handle.is_null() {
+        return;
+    }
+    // SAFETY: ownership is transferred back from `rylv_collector_new`.
+    let collector = unsafe { Box::from_raw(handle) };
+    if catch_unwind(AssertUnwindSafe(move || drop(collector))).is_err() {
+        error!("rylv_shutdown panicked while stopping the collector");
+    }
+}
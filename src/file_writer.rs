@@ -0,0 +1,217 @@
+//! Writer appending emitted lines to a local file, for offline collection.
+//!
+//! [`FileWriter`] is a [`StatsWriterTrait`] that appends the lines sent by a
+//! [`crate::MetricCollector`] to a file instead of the network, e.g. on
+//! air-gapped machines whose metrics are uploaded later. Lines are buffered
+//! during a flush and written to the file when it completes. With a rotation
+//! size set, the current file is moved to `<path>.1`, `<path>.2`, ... before a
+//! flush would grow it past that size; rotated files are never deleted.
+//!
+//! ```no_run
+//! # #[cfg(any(feature = "shared-collector", feature = "simple-map"))] {
+//! use rylv_metrics::file_writer::FileWriter;
+//! use rylv_metrics::{
+//!     MetricCollector, MetricCollectorOptions, SharedCollector, StatsWriterType, TagSectionStyle,
+//! };
+//!
+//! let writer = FileWriter::open(
+//!     "/var/lib/app/metrics.log",
+//!     Some(64 * 1024 * 1024),
+//!     TagSectionStyle::Omit,
+//! )
+//! .unwrap();
+//! let collector = MetricCollector::new(
+//!     "0.0.0.0:0".parse().unwrap(),
+//!     "127.0.0.1:8125".parse().unwrap(),
+//!     MetricCollectorOptions {
+//!         writer_type: StatsWriterType::Custom(Box::new(writer)),
+//!         ..Default::default()
+//!     },
+//!     SharedCollector::default(),
+//! );
+//! # drop(collector);
+//! # }
+//! ```
+
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::dogstats::writer::format_line;
+use crate::{MetricKind, MetricResult, StatsWriterTrait, TagSectionStyle};
+
+/// Writer appending lines to a file, optionally rotated by size.
+pub struct FileWriter {
+    path: PathBuf,
+    file: File,
+    // Bytes in the current file
+    len: u64,
+    rotate_at: Option<u64>,
+    tag_section_style: TagSectionStyle,
+    pending: String,
+}
+
+impl FileWriter {
+    /// Opens `path` for appending, creating it if needed.
+    ///
+    /// With `rotate_at` set, the file is rotated before a flush would grow it
+    /// past that many bytes. A single flush larger than `rotate_at` is still
+    /// written to one file. Lines without tags render their tag section as
+    /// `tag_section_style` says, like
+    /// [`MetricCollectorOptions::tag_section_style`](crate::MetricCollectorOptions::tag_section_style)
+    /// does for the built-in writers.
+    ///
+    /// # Errors
+    /// Returns `MetricsError::StdIo` if the file cannot be opened.
+    pub fn open(
+        path: impl Into<PathBuf>,
+        rotate_at: Option<u64>,
+        tag_section_style: TagSectionStyle,
+    ) -> MetricResult<Self> {
+        let path = path.into();
+        let file = open_append(&path)?;
+        let len = file.metadata()?.len();
+        Ok(Self {
+            path,
+            file,
+            len,
+            rotate_at,
+            tag_section_style,
+            pending: String::new(),
+        })
+    }
+
+    /// Returns the path lines are currently appended to.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Moves the current file to the first free `<path>.<n>` and starts a new one.
+    fn rotate(&mut self) -> MetricResult<()> {
+        let mut index = 1;
+        let rotated = loop {
+            let mut rotated = self.path.clone().into_os_string();
+            rotated.push(format!(".{index}"));
+            let rotated = PathBuf::from(rotated);
+            if !rotated.exists() {
+                break rotated;
+            }
+            index += 1;
+        };
+        fs::rename(&self.path, rotated)?;
+        self.file = open_append(&self.path)?;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+fn open_append(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+impl StatsWriterTrait for FileWriter {
+    fn metric_copied(&self) -> bool {
+        true
+    }
+
+    fn write(
+        &mut self,
+        metrics: &[&str],
+        tags: &str,
+        value: &str,
+        metric_type: MetricKind,
+    ) -> MetricResult<()> {
+        format_line(
+            &mut self.pending,
+            metrics,
+            tags,
+            value,
+            metric_type,
+            self.tag_section_style,
+        );
+        Ok(())
+    }
+
+    fn flush(&mut self) -> MetricResult<usize> {
+        if self.pending.is_empty() {
+            return Ok(0);
+        }
+        let bytes = self.pending.len();
+        let exceeds = self
+            .rotate_at
+            .is_some_and(|rotate_at| self.len + bytes as u64 > rotate_at);
+        if exceeds && self.len > 0 {
+            self.rotate()?;
+        }
+        self.file.write_all(self.pending.as_bytes())?;
+        self.len += bytes as u64;
+        self.pending.clear();
+        Ok(bytes)
+    }
+
+    fn reset(&mut self) {
+        self.pending.clear();
+    }
+
+    fn buffered_bytes(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rylv-file-writer-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn flush_appends_to_an_existing_file() {
+        let path = temp_path("append.log");
+        fs::write(&path, "old:1|c\n").unwrap();
+
+        let mut writer = FileWriter::open(&path, None, TagSectionStyle::Omit).unwrap();
+        writer
+            .write(&["app.", "hits"], "env:dev", "2", MetricKind::Count)
+            .unwrap();
+        assert_eq!(writer.buffered_bytes(), "app.hits:2|c|#env:dev\n".len());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old:1|c\n");
+
+        assert_eq!(writer.flush().unwrap(), "app.hits:2|c|#env:dev\n".len());
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "old:1|c\napp.hits:2|c|#env:dev\n"
+        );
+    }
+
+    #[test]
+    fn lines_follow_the_tag_section_style() {
+        let path = temp_path("style.log");
+        let mut writer = FileWriter::open(&path, None, TagSectionStyle::Always).unwrap();
+        writer.write(&["hits"], "", "1", MetricKind::Count).unwrap();
+        writer
+            .write(&["hits"], "env:dev", "1", MetricKind::Count)
+            .unwrap();
+        writer.flush().unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "hits:1|c|#\nhits:1|c|#env:dev\n"
+        );
+    }
+
+    #[test]
+    fn reset_drops_unflushed_lines() {
+        let path = temp_path("reset.log");
+        let mut writer = FileWriter::open(&path, None, TagSectionStyle::Omit).unwrap();
+        writer.write(&["hits"], "", "1", MetricKind::Count).unwrap();
+        writer.reset();
+        assert_eq!(writer.flush().unwrap(), 0);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+    }
+}
@@ -39,7 +39,10 @@
 //! collector.count_add(RylvStr::from_static("bytes.sent"), 1024, &mut [RylvStr::from_static("endpoint:api")]);
 //! collector.gauge(RylvStr::from_static("connections.active"), 100, &mut [RylvStr::from_static("pool:main")]);
 //!
-//! // Convenience macros — allocate on first key insertion, but more ergonomic
+//! // Names also accept `&str`, `String` or `Arc<str>` directly; they are copied on first key insertion
+//! collector.count(format!("queue.{}.depth", "email"), &mut [RylvStr::from_static("pool:main")]);
+//!
+//! // Convenience macros — string literals are stored without copying, like from_static()
 //! histogram!(collector, "request.latency", 42, "endpoint:api");
 //! count!(collector, "request.count", "endpoint:api");
 //! count_add!(collector, "bytes.sent", 1024, "endpoint:api");
@@ -68,21 +71,36 @@
 #![cfg_attr(test, allow(clippy::panic))]
 
 // https://docs.datadoghq.com/developers/dogstatsd/datagram_shell/?tab=metrics
+#[cfg(feature = "admin-http")]
+pub mod admin_http;
 mod dogstats;
 mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "file-writer")]
+pub mod file_writer;
+#[cfg(feature = "tcp-writer")]
+pub mod tcp_writer;
+#[cfg(feature = "tracing-layer")]
+pub mod tracing_layer;
 
 #[cfg(all(feature = "custom_writer", feature = "udp"))]
-pub use dogstats::writer::StatsWriterTrait;
+pub use dogstats::writer::{FlushStats, PacketHook, StatsWriterTrait};
+#[cfg(feature = "udp")]
 pub use dogstats::{
-    DrainMetricCollectorTrait, HistogramBaseMetric, HistogramConfig, MetricCollectorTrait,
-    MetricFrameRef, MetricKind, MetricSuffix, PreparedMetric, SortedTags,
+    AddressPreference, DerivedMetrics, EmergencyCounterId, MetricCollector, MetricCollectorOptions,
+    NameTransform, NamespacedCollector, ShutdownReport, SocketOptions, StatsWriterKind,
+    StatsWriterType, TagSectionStyle, WireFormat, WriterPreference, DEFAULT_STATS_WRITER_TYPE,
 };
-#[cfg(feature = "udp")]
 pub use dogstats::{
-    MetricCollector, MetricCollectorOptions, StatsWriterType, DEFAULT_STATS_WRITER_TYPE,
+    AsyncMetricCollector, AsyncMetricCollectorOptions, DrainMetricCollectorTrait, GaugeMode,
+    HistogramBaseMetric, HistogramConfig, HistogramSnapshot, HistogramSumKind, Metric,
+    MetricCollectorTrait, MetricFilter, MetricFrameRef, MetricKind, MetricSuffix, NoopCollector,
+    NumericTag, OutOfBoundsPolicy, PercentileMethod, PreparedMetric, ScopeTimer, Snapshot,
+    SnapshotValue, SortedTags,
 };
 pub use dogstats::{RylvStr, SigFig};
-#[cfg(feature = "shared-collector")]
+#[cfg(any(feature = "shared-collector", feature = "simple-map"))]
 pub use dogstats::{SharedCollector, SharedCollectorOptions};
 #[cfg(feature = "tls-collector")]
 pub use dogstats::{TLSCollector, TLSCollectorOptions};
@@ -124,6 +142,7 @@ pub fn benchmark_lookup_compare(
         hash,
         fingerprint: 0,
         id: 1,
+        last_active: std::sync::atomic::AtomicU64::new(0),
     };
 
     let lookup = LookupKey {
@@ -131,6 +150,7 @@ pub fn benchmark_lookup_compare(
         tags: &lookup_tags_owned,
         tags_hash: 0,
         hash,
+        sorted: true,
     };
     lookup.compare(&entry)
 }
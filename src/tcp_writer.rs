@@ -0,0 +1,382 @@
+//! Writer sending lines over TCP, for receivers that need reliable delivery.
+//!
+//! UDP drops datagrams under congestion without telling anyone. [`TcpWriter`]
+//! is a [`StatsWriterTrait`] that sends the lines of each flush, newline
+//! framed, over a TCP connection to a DogStatsD-compatible receiver instead.
+//! The connection is opened on the first flush and reopened after a failure,
+//! waiting between attempts with an exponential backoff. Lines that cannot be
+//! sent are kept for the next flush, up to
+//! [`TcpWriterOptions::max_buffered_bytes`]; past that the oldest lines are
+//! dropped and counted in [`TcpWriter::dropped_lines`].
+//!
+//! A line being written when the connection fails is sent again in full on the
+//! next connection, so the receiver may see it twice but never truncated.
+//!
+//! ```no_run
+//! # #[cfg(any(feature = "shared-collector", feature = "simple-map"))] {
+//! use rylv_metrics::tcp_writer::{TcpWriter, TcpWriterOptions};
+//! use rylv_metrics::{MetricCollector, MetricCollectorOptions, SharedCollector, StatsWriterType};
+//!
+//! let writer = TcpWriter::new("127.0.0.1:8126".parse().unwrap(), TcpWriterOptions::default());
+//! let collector = MetricCollector::new(
+//!     "0.0.0.0:0".parse().unwrap(),
+//!     "127.0.0.1:8125".parse().unwrap(),
+//!     MetricCollectorOptions {
+//!         writer_type: StatsWriterType::Custom(Box::new(writer)),
+//!         ..Default::default()
+//!     },
+//!     SharedCollector::default(),
+//! );
+//! # drop(collector);
+//! # }
+//! ```
+
+use std::io::{ErrorKind, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::{Duration, Instant};
+
+use crate::dogstats::writer::format_line;
+use crate::{MetricKind, MetricResult, StatsWriterTrait, TagSectionStyle};
+
+/// Connection and buffering settings of a [`TcpWriter`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcpWriterOptions {
+    /// Most bytes of unsent lines kept while the receiver is unreachable;
+    /// the oldest lines are dropped beyond it.
+    pub max_buffered_bytes: usize,
+    /// Wait before reconnecting after the first failed attempt, doubled after
+    /// every further failure.
+    pub initial_backoff: Duration,
+    /// Longest wait between two connection attempts.
+    pub max_backoff: Duration,
+    /// Longest a connection attempt may take.
+    pub connect_timeout: Duration,
+    /// Longest a flush may block writing to a slow receiver before the
+    /// connection is considered failed.
+    pub write_timeout: Duration,
+    /// How lines without tags render their tag section, like
+    /// [`MetricCollectorOptions::tag_section_style`](crate::MetricCollectorOptions::tag_section_style)
+    /// does for the built-in writers.
+    pub tag_section_style: TagSectionStyle,
+}
+
+impl Default for TcpWriterOptions {
+    fn default() -> Self {
+        Self {
+            max_buffered_bytes: 1024 * 1024,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            connect_timeout: Duration::from_secs(1),
+            write_timeout: Duration::from_secs(1),
+            tag_section_style: TagSectionStyle::default(),
+        }
+    }
+}
+
+/// Writer sending newline-framed lines over a reconnecting TCP connection.
+pub struct TcpWriter {
+    addr: SocketAddr,
+    options: TcpWriterOptions,
+    stream: Option<TcpStream>,
+    // Lines written since the last flush
+    pending: String,
+    // Lines flushed but not yet sent
+    backlog: String,
+    backoff: Duration,
+    next_attempt: Option<Instant>,
+    // Bytes sent by failed flushes, reported by the next successful one
+    unreported_sent: usize,
+    dropped_lines: u64,
+}
+
+impl TcpWriter {
+    /// Creates a writer sending to `addr`.
+    ///
+    /// No connection is made until the first flush, so the receiver does not
+    /// need to be up when the collector starts.
+    #[must_use]
+    pub const fn new(addr: SocketAddr, options: TcpWriterOptions) -> Self {
+        Self {
+            addr,
+            backoff: options.initial_backoff,
+            options,
+            stream: None,
+            pending: String::new(),
+            backlog: String::new(),
+            next_attempt: None,
+            unreported_sent: 0,
+            dropped_lines: 0,
+        }
+    }
+
+    /// Returns whether the writer holds an open connection.
+    #[must_use]
+    pub const fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// Returns how many lines were dropped because the buffer of unsent lines
+    /// exceeded [`TcpWriterOptions::max_buffered_bytes`].
+    #[must_use]
+    pub const fn dropped_lines(&self) -> u64 {
+        self.dropped_lines
+    }
+
+    /// Connects when no connection is open and the backoff allows it.
+    /// `Ok(false)` means the next attempt is not due yet.
+    fn ensure_connected(&mut self) -> MetricResult<bool> {
+        if self.stream.as_ref().is_some_and(peer_closed) {
+            self.stream = None;
+        }
+        if self.stream.is_none() {
+            let now = Instant::now();
+            if self.next_attempt.is_some_and(|next| now < next) {
+                return Ok(false);
+            }
+            match self.connect() {
+                Ok(stream) => {
+                    self.backoff = self.options.initial_backoff;
+                    self.next_attempt = None;
+                    self.stream = Some(stream);
+                }
+                Err(err) => {
+                    self.next_attempt = Some(now + self.backoff);
+                    self.backoff = (self.backoff * 2).min(self.options.max_backoff);
+                    return Err(err.into());
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    fn connect(&self) -> std::io::Result<TcpStream> {
+        let stream = TcpStream::connect_timeout(&self.addr, self.options.connect_timeout)?;
+        stream.set_write_timeout(Some(self.options.write_timeout))?;
+        Ok(stream)
+    }
+
+    /// Sends the backlog, keeping whatever could not be sent.
+    fn send_backlog(&mut self) -> MetricResult<usize> {
+        if !self.ensure_connected()? {
+            return Ok(0);
+        }
+        let Some(stream) = self.stream.as_mut() else {
+            return Ok(0);
+        };
+        let (written, failure) = write_counting(stream, self.backlog.as_bytes());
+        let Some(err) = failure else {
+            self.backlog.clear();
+            return Ok(written);
+        };
+        // keep the line the failure stopped in, so it is sent again in full
+        let sent = self.backlog[..written].rfind('\n').map_or(0, |end| end + 1);
+        self.backlog.drain(..sent);
+        self.unreported_sent += sent;
+        self.stream = None;
+        Err(err.into())
+    }
+
+    /// Drops the oldest lines until the backlog fits the configured bound.
+    fn trim_backlog(&mut self) {
+        let excess = self
+            .backlog
+            .len()
+            .saturating_sub(self.options.max_buffered_bytes);
+        if excess == 0 {
+            return;
+        }
+        let cut = self.backlog[excess - 1..]
+            .find('\n')
+            .map_or(self.backlog.len(), |end| excess + end);
+        let dropped = self.backlog[..cut].matches('\n').count();
+        self.backlog.drain(..cut);
+        self.dropped_lines += dropped as u64;
+    }
+}
+
+/// Writes `bytes` to `stream`, returning how many went out and the error that
+/// stopped it early, if any.
+fn write_counting(stream: &mut TcpStream, bytes: &[u8]) -> (usize, Option<std::io::Error>) {
+    let mut written = 0;
+    while written < bytes.len() {
+        match stream.write(&bytes[written..]) {
+            Ok(0) => return (written, Some(ErrorKind::WriteZero.into())),
+            Ok(sent) => written += sent,
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return (written, Some(err)),
+        }
+    }
+    (written, None)
+}
+
+/// Returns whether the receiver closed `stream`; it never sends anything, so a
+/// readable end of stream means the connection is gone.
+fn peer_closed(stream: &TcpStream) -> bool {
+    if stream.set_nonblocking(true).is_err() {
+        return true;
+    }
+    let mut byte = [0u8; 1];
+    let closed = match stream.peek(&mut byte) {
+        Ok(read) => read == 0,
+        Err(err) => err.kind() != ErrorKind::WouldBlock,
+    };
+    closed || stream.set_nonblocking(false).is_err()
+}
+
+impl StatsWriterTrait for TcpWriter {
+    fn metric_copied(&self) -> bool {
+        true
+    }
+
+    fn write(
+        &mut self,
+        metrics: &[&str],
+        tags: &str,
+        value: &str,
+        metric_type: MetricKind,
+    ) -> MetricResult<()> {
+        format_line(
+            &mut self.pending,
+            metrics,
+            tags,
+            value,
+            metric_type,
+            self.options.tag_section_style,
+        );
+        Ok(())
+    }
+
+    fn flush(&mut self) -> MetricResult<usize> {
+        self.backlog.push_str(&self.pending);
+        self.pending.clear();
+        if self.backlog.is_empty() {
+            return Ok(0);
+        }
+        let result = self.send_backlog();
+        self.trim_backlog();
+        let sent = result?;
+        Ok(sent + std::mem::take(&mut self.unreported_sent))
+    }
+
+    fn reset(&mut self) {
+        self.pending.clear();
+    }
+
+    fn buffered_bytes(&self) -> usize {
+        self.pending.len() + self.backlog.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+
+    /// Accepts one connection and reads `lines` lines from it, or everything
+    /// until the writer closes it when `lines` is `None`.
+    fn spawn_receiver(listener: TcpListener, lines: Option<usize>) -> JoinHandle<String> {
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut received = String::new();
+            match lines {
+                Some(lines) => {
+                    for _ in 0..lines {
+                        reader.read_line(&mut received).unwrap();
+                    }
+                }
+                None => {
+                    reader.read_to_string(&mut received).unwrap();
+                }
+            }
+            received
+        })
+    }
+
+    fn count(writer: &mut TcpWriter, metric: &str) {
+        writer.write(&[metric], "", "1", MetricKind::Count).unwrap();
+    }
+
+    #[test]
+    fn buffered_lines_arrive_after_the_receiver_restarts() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let receiver = spawn_receiver(listener, Some(1));
+        let mut writer = TcpWriter::new(
+            addr,
+            TcpWriterOptions {
+                // room for two `x:1|c` lines
+                max_buffered_bytes: 14,
+                initial_backoff: Duration::from_millis(1),
+                ..Default::default()
+            },
+        );
+
+        count(&mut writer, "a");
+        assert_eq!(writer.flush().unwrap(), "a:1|c\n".len());
+        // the receiver goes away, closing the listener and the connection
+        assert_eq!(receiver.join().unwrap(), "a:1|c\n");
+
+        count(&mut writer, "b");
+        assert!(writer.flush().is_err(), "nothing listens on {addr}");
+        assert!(!writer.is_connected());
+        count(&mut writer, "c");
+        count(&mut writer, "d");
+        thread::sleep(Duration::from_millis(10));
+        assert!(writer.flush().is_err());
+        assert_eq!(writer.buffered_bytes(), "c:1|c\nd:1|c\n".len());
+        assert_eq!(writer.dropped_lines(), 1);
+
+        let receiver = spawn_receiver(TcpListener::bind(addr).unwrap(), None);
+        thread::sleep(Duration::from_millis(10));
+        assert_eq!(writer.flush().unwrap(), "c:1|c\nd:1|c\n".len());
+        assert!(writer.is_connected());
+        drop(writer);
+        assert_eq!(receiver.join().unwrap(), "c:1|c\nd:1|c\n");
+    }
+
+    #[test]
+    fn lines_follow_the_tag_section_style() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let receiver = spawn_receiver(listener, Some(2));
+        let mut writer = TcpWriter::new(
+            addr,
+            TcpWriterOptions {
+                tag_section_style: TagSectionStyle::Always,
+                ..Default::default()
+            },
+        );
+
+        count(&mut writer, "a");
+        writer
+            .write(&["b"], "env:a", "1", MetricKind::Count)
+            .unwrap();
+        writer.flush().unwrap();
+        assert_eq!(receiver.join().unwrap(), "a:1|c|#\nb:1|c|#env:a\n");
+    }
+
+    #[test]
+    fn reconnect_attempts_back_off_until_due() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let mut writer = TcpWriter::new(
+            addr,
+            TcpWriterOptions {
+                initial_backoff: Duration::from_secs(60),
+                ..Default::default()
+            },
+        );
+
+        count(&mut writer, "a");
+        assert!(writer.flush().is_err());
+        // the next attempt is not due, so the line stays buffered without an error
+        count(&mut writer, "b");
+        assert_eq!(writer.flush().unwrap(), 0);
+        assert_eq!(writer.buffered_bytes(), "a:1|c\nb:1|c\n".len());
+        assert_eq!(writer.backoff, Duration::from_secs(10));
+    }
+}
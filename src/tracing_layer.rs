@@ -0,0 +1,250 @@
+//! A [`tracing_subscriber::Layer`] that records metrics from `tracing` events.
+//!
+//! Events carrying a `metric.name` field are translated into collector calls,
+//! so existing `tracing` instrumentation doubles as metrics. The other events
+//! are skipped before their fields are visited. The fields follow this
+//! convention:
+//!
+//! - `metric.name` (string, required): metric name.
+//! - `metric.kind` (string): `"count"` (the default), `"gauge"` or `"histogram"`.
+//! - `metric.value` (unsigned or non-negative integer): value to record;
+//!   defaults to `1` for counts and is required for gauges and histograms.
+//! - `metric.tags` (string): comma-separated tags, e.g. `"env:prod,route:/"`.
+//!
+//! Events with an unknown kind, a missing value or a value of another type
+//! are ignored. Up to 256 bytes of name, kind and tags are copied on the
+//! stack, so recording an existing series does not allocate.
+//!
+//! ```
+//! # #[cfg(any(feature = "shared-collector", feature = "simple-map"))] {
+//! use rylv_metrics::tracing_layer::MetricsLayer;
+//! use rylv_metrics::SharedCollector;
+//! use std::sync::Arc;
+//! use tracing_subscriber::layer::SubscriberExt;
+//!
+//! let collector = Arc::new(SharedCollector::default());
+//! let subscriber = tracing_subscriber::registry().with(MetricsLayer::new(Arc::clone(&collector)));
+//!
+//! tracing::subscriber::with_default(subscriber, || {
+//!     tracing::info!(metric.name = "jobs.done", metric.tags = "queue:email");
+//!     tracing::info!(metric.name = "job.latency", metric.kind = "histogram", metric.value = 42_u64);
+//! });
+//! # }
+//! ```
+
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
+
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+use crate::{MetricCollectorTrait, RylvStr};
+
+const NAME_FIELD: &str = "metric.name";
+const KIND_FIELD: &str = "metric.kind";
+const VALUE_FIELD: &str = "metric.value";
+const TAGS_FIELD: &str = "metric.tags";
+
+/// Bytes of `metric.name`, `metric.kind` and `metric.tags` copied on the stack.
+const INLINE_FIELDS_LEN: usize = 256;
+
+/// Longest tag list split on the stack.
+const STACK_TAGS_LEN: usize = 16;
+
+/// Layer recording the metric fields of `tracing` events into a collector.
+///
+/// See the [module documentation](self) for the field convention.
+pub struct MetricsLayer<C> {
+    collector: Arc<C>,
+}
+
+impl<C> MetricsLayer<C>
+where
+    C: MetricCollectorTrait + Send + Sync + 'static,
+{
+    /// Creates a layer recording into `collector`.
+    #[must_use]
+    pub const fn new(collector: Arc<C>) -> Self {
+        Self { collector }
+    }
+
+    /// Returns the collector metrics are recorded into.
+    #[must_use]
+    pub const fn collector(&self) -> &Arc<C> {
+        &self.collector
+    }
+
+    fn record(&self, fields: &MetricFields) {
+        let Some(name) = fields.get(&fields.name) else {
+            return;
+        };
+        let kind = fields.get(&fields.kind).unwrap_or("count");
+        with_split_tags(fields.get(&fields.tags).unwrap_or(""), |tags| {
+            let metric = RylvStr::Borrowed(name);
+            match (kind, fields.value) {
+                ("count", value) => self.collector.count_add(metric, value.unwrap_or(1), tags),
+                ("gauge", Some(value)) => self.collector.gauge(metric, value, tags),
+                ("histogram", Some(value)) => self.collector.histogram(metric, value, tags),
+                _ => {}
+            }
+        });
+    }
+}
+
+impl<C> fmt::Debug for MetricsLayer<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MetricsLayer").finish_non_exhaustive()
+    }
+}
+
+impl<C, S> Layer<S> for MetricsLayer<C>
+where
+    C: MetricCollectorTrait + Send + Sync + 'static,
+    S: Subscriber,
+{
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if event.metadata().fields().field(NAME_FIELD).is_none() {
+            return;
+        }
+        let mut fields = MetricFields::new();
+        event.record(&mut fields);
+        self.record(&fields);
+    }
+}
+
+/// Splits comma-separated `tags` and calls `f` with them, on the stack for short lists.
+fn with_split_tags<R>(tags: &str, f: impl FnOnce(&mut [RylvStr<'_>]) -> R) -> R {
+    let mut split = tags
+        .split(',')
+        .filter(|tag| !tag.is_empty())
+        .map(RylvStr::Borrowed);
+    let mut buffer: [RylvStr<'_>; STACK_TAGS_LEN] = std::array::from_fn(|_| RylvStr::Static(""));
+    let mut len = 0;
+    for tag in split.by_ref() {
+        if len == STACK_TAGS_LEN {
+            let mut tags = buffer.to_vec();
+            tags.push(tag);
+            tags.extend(split);
+            return f(&mut tags);
+        }
+        buffer[len] = tag;
+        len += 1;
+    }
+    f(&mut buffer[..len])
+}
+
+/// Metric fields of one event, copied out of the `Visit` callbacks.
+struct MetricFields {
+    // `name`, `kind` and `tags` share this buffer until it is full
+    inline: [u8; INLINE_FIELDS_LEN],
+    inline_len: usize,
+    name: FieldText,
+    kind: FieldText,
+    tags: FieldText,
+    value: Option<u64>,
+}
+
+/// String field value, stored in [`MetricFields::inline`] when it fits.
+enum FieldText {
+    Unset,
+    Inline(Range<usize>),
+    Heap(String),
+}
+
+impl MetricFields {
+    const fn new() -> Self {
+        Self {
+            inline: [0; INLINE_FIELDS_LEN],
+            inline_len: 0,
+            name: FieldText::Unset,
+            kind: FieldText::Unset,
+            tags: FieldText::Unset,
+            value: None,
+        }
+    }
+
+    fn store(&mut self, value: &str) -> FieldText {
+        let start = self.inline_len;
+        let end = start + value.len();
+        if end > INLINE_FIELDS_LEN {
+            return FieldText::Heap(value.to_owned());
+        }
+        self.inline[start..end].copy_from_slice(value.as_bytes());
+        self.inline_len = end;
+        FieldText::Inline(start..end)
+    }
+
+    fn get<'a>(&'a self, text: &'a FieldText) -> Option<&'a str> {
+        match text {
+            FieldText::Unset => None,
+            FieldText::Inline(range) => {
+                // SAFETY: the range holds a copy of a valid UTF-8 string.
+                Some(unsafe { std::str::from_utf8_unchecked(&self.inline[range.clone()]) })
+            }
+            FieldText::Heap(value) => Some(value),
+        }
+    }
+}
+
+impl Visit for MetricFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            NAME_FIELD => self.name = self.store(value),
+            KIND_FIELD => self.kind = self.store(value),
+            TAGS_FIELD => self.tags = self.store(value),
+            _ => {}
+        }
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        if field.name() == VALUE_FIELD {
+            self.value = Some(value);
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        if field.name() == VALUE_FIELD {
+            self.value = u64::try_from(value).ok();
+        }
+    }
+
+    fn record_debug(&mut self, _field: &Field, _value: &dyn fmt::Debug) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_tags_skip_empty_entries_and_spill_long_lists() {
+        let collected = with_split_tags("a:1,,b:2,", |tags| {
+            tags.iter()
+                .map(|tag| tag.as_ref().to_owned())
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(collected, vec!["a:1", "b:2"]);
+
+        let many = (0..20).map(|i| format!("t:{i}")).collect::<Vec<_>>();
+        let count = with_split_tags(&many.join(","), |tags| tags.len());
+        assert_eq!(count, 20);
+        assert_eq!(with_split_tags("", |tags| tags.len()), 0);
+    }
+
+    #[test]
+    fn fields_fall_back_to_heap_once_the_inline_buffer_is_full() {
+        let mut fields = MetricFields::new();
+        let name = fields.store("jobs.done");
+        assert!(matches!(name, FieldText::Inline(_)));
+        assert_eq!(fields.get(&name), Some("jobs.done"));
+        assert_eq!(fields.get(&FieldText::Unset), None);
+
+        let long = "t".repeat(INLINE_FIELDS_LEN);
+        let tags = fields.store(&long);
+        assert!(matches!(tags, FieldText::Heap(_)));
+        assert_eq!(fields.get(&tags), Some(long.as_str()));
+        assert_eq!(fields.get(&name), Some("jobs.done"));
+    }
+}
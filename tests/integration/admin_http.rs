@@ -0,0 +1,56 @@
+use rylv_metrics::admin_http::{RingBufferWriter, RECENT_LINES_PATH};
+use rylv_metrics::{
+    MetricCollector, MetricCollectorOptions, MetricCollectorTrait, RylvStr, SharedCollector,
+    StatsWriterType,
+};
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_ring_buffer_writer_keeps_most_recent_lines() {
+    let writer = RingBufferWriter::new(3);
+    let recent = writer.recent_lines();
+    let options = MetricCollectorOptions {
+        flush_interval: Duration::from_millis(50),
+        flush_jitter: Duration::ZERO,
+        writer_type: StatsWriterType::Custom(Box::new(writer)),
+        ..Default::default()
+    };
+    let collector = MetricCollector::new(
+        "0.0.0.0:0".parse().unwrap(),
+        "127.0.0.1:8125".parse().unwrap(),
+        options,
+        SharedCollector::default(),
+    )
+    .expect("failed to create collector");
+
+    for name in ["old.a", "old.b", "old.c", "old.d"] {
+        collector.count(
+            RylvStr::from_static(name),
+            &mut [RylvStr::from_static("env:test")],
+        );
+    }
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while recent.lines().len() < 3 && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert!(recent
+        .lines()
+        .iter()
+        .all(|line| line.starts_with("old.") && line.ends_with(":1|c|#env:test")));
+
+    collector.count_add(RylvStr::from_static("new.hits"), 7, &mut []);
+    collector.gauge(RylvStr::from_static("new.load"), 3, &mut []);
+    // the final flush runs on drop
+    drop(collector);
+
+    let lines = recent.lines();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("old."));
+    let mut newest = lines[1..].to_vec();
+    newest.sort_unstable();
+    assert_eq!(newest, vec!["new.hits:7|c", "new.load:3|g"]);
+
+    let response = recent.handle_request("GET", RECENT_LINES_PATH);
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, format!("{}\n", lines.join("\n")));
+}
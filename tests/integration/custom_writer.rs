@@ -1,8 +1,11 @@
 use rylv_metrics::{
-    HistogramConfig, MetricCollector, MetricCollectorOptions, MetricCollectorTrait, MetricKind,
-    MetricResult, RylvStr, SharedCollector, SharedCollectorOptions, SigFig, StatsWriterTrait,
-    StatsWriterType,
+    DerivedMetrics, FlushStats, HistogramConfig, Metric, MetricCollector, MetricCollectorOptions,
+    MetricCollectorTrait, MetricKind, MetricResult, NameTransform, PacketHook, RylvStr,
+    SharedCollector, SharedCollectorOptions, ShutdownReport, SigFig, StatsWriterTrait,
+    StatsWriterType, TagSectionStyle, WireFormat,
 };
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
@@ -29,6 +32,10 @@ impl TestStatsWriter {
     pub fn get_all_metrics_as_text(&self) -> String {
         self.metrics.lock().unwrap().join("")
     }
+
+    pub fn get_packets(&self) -> Vec<String> {
+        self.metrics.lock().unwrap().clone()
+    }
 }
 
 impl StatsWriterTrait for TestStatsWriter {
@@ -46,6 +53,9 @@ impl StatsWriterTrait for TestStatsWriter {
         let metric_type = match metric_type {
             MetricKind::Count => "c",
             MetricKind::Gauge => "g",
+            MetricKind::Timing => "ms",
+            MetricKind::Distribution => "d",
+            kind => return Err(format!("unsupported metric kind {kind:?}").into()),
         };
         let mut buffer = self.current_buffer.lock().unwrap();
 
@@ -127,6 +137,7 @@ fn test_custom_writer_basic() -> std::io::Result<()> {
         max_udp_batch_size: 100,
         flush_interval: Duration::from_millis(100),
         writer_type: StatsWriterType::Custom(Box::new(writer)),
+        ..Default::default()
     };
 
     let bind_addr = "0.0.0.0:0".parse().unwrap();
@@ -214,6 +225,7 @@ fn test_custom_writer_no_tags() -> std::io::Result<()> {
         max_udp_batch_size: 100,
         flush_interval: Duration::from_millis(100),
         writer_type: StatsWriterType::Custom(Box::new(writer)),
+        ..Default::default()
     };
 
     let bind_addr = "0.0.0.0:0".parse().unwrap();
@@ -264,345 +276,1721 @@ fn test_custom_writer_no_tags() -> std::io::Result<()> {
 }
 
 #[test]
-fn test_custom_writer_with_prefix() -> std::io::Result<()> {
-    let writer = TestStatsWriter::new(1024);
+fn test_custom_writer_plain_statsd_folds_tags_into_names() -> std::io::Result<()> {
+    let writer = TestStatsWriter::new(512);
     let writer_clone = writer.clone();
 
     let options = MetricCollectorOptions {
-        max_udp_packet_size: 1024,
-        max_udp_batch_size: 100,
         flush_interval: Duration::from_millis(100),
         writer_type: StatsWriterType::Custom(Box::new(writer)),
+        tag_section_style: TagSectionStyle::Always,
+        wire_format: WireFormat::PlainStatsD,
+        ..Default::default()
     };
 
     let bind_addr = "0.0.0.0:0".parse().unwrap();
     let datadog_addr = random_datadog_addr();
 
-    let inner = SharedCollector::new(SharedCollectorOptions {
-        stats_prefix: "app.".to_string(),
-        ..Default::default()
-    });
-    let collector = MetricCollector::new(bind_addr, datadog_addr, options, inner)
-        .expect("failed to create collector");
+    let collector =
+        MetricCollector::new(bind_addr, datadog_addr, options, SharedCollector::default())
+            .expect("failed to create collector");
 
     collector.count(
-        RylvStr::from_static("requests"),
-        &mut [RylvStr::from_static("method:GET")],
-    );
-    collector.gauge(
-        RylvStr::from_static("memory"),
-        512,
-        &mut [RylvStr::from_static("unit:mb")],
-    );
-    collector.histogram(
-        RylvStr::from_static("latency"),
-        150,
-        &mut [RylvStr::from_static("service:api")],
+        RylvStr::from_static("request.count"),
+        &mut [
+            RylvStr::from_static("method:get"),
+            RylvStr::from_static("endpoint:api"),
+        ],
     );
+    collector.gauge(RylvStr::from_static("queue.depth"), 3, []);
 
     drop(collector);
 
     let metrics = writer_clone.get_all_metrics_as_text();
-
-    // Verify prefix is included in wire format
     assert!(
-        metrics.contains("app.requests:1|c|#method:GET\n"),
-        "Should contain prefix in counter metric"
-    );
-    assert!(
-        metrics.contains("app.memory:512|g|#unit:mb\n"),
-        "Should contain prefix in gauge metric"
+        metrics.contains("request.count.api.get:1|c\n"),
+        "tag values should be folded into the name in sorted tag order: {metrics}"
     );
+    assert!(metrics.contains("queue.depth:3|g\n"));
+    assert!(!metrics.contains('#'), "no tag section expected: {metrics}");
+
+    Ok(())
+}
+
+#[test]
+fn test_custom_writer_entity_id_is_appended_only_when_configured() -> std::io::Result<()> {
+    for entity_id in [Some("pod-1234"), None] {
+        let writer = TestStatsWriter::new(512);
+        let writer_clone = writer.clone();
+        let options = MetricCollectorOptions {
+            flush_interval: Duration::from_millis(100),
+            writer_type: StatsWriterType::Custom(Box::new(writer)),
+            entity_id: entity_id.map(str::to_string),
+            ..Default::default()
+        };
+
+        let bind_addr = "0.0.0.0:0".parse().unwrap();
+        let datadog_addr = random_datadog_addr();
+        let collector =
+            MetricCollector::new(bind_addr, datadog_addr, options, SharedCollector::default())
+                .expect("failed to create collector");
+
+        for _ in 0..2 {
+            collector.count(
+                RylvStr::from_static("jobs.done"),
+                &mut [
+                    RylvStr::from_static("zone:b"),
+                    RylvStr::from_static("app:a"),
+                ],
+            );
+        }
+        collector.gauge(RylvStr::from_static("queue.depth"), 3, []);
+        drop(collector);
+
+        let metrics = writer_clone.get_all_metrics_as_text();
+        if entity_id.is_some() {
+            // the entity tag follows the sorted user tags and does not split the series
+            assert!(
+                metrics.contains("jobs.done:2|c|#app:a,zone:b,dd.internal.entity_id:pod-1234\n"),
+                "entity tag expected after user tags: {metrics}"
+            );
+            assert!(metrics.contains("queue.depth:3|g|#dd.internal.entity_id:pod-1234\n"));
+        } else {
+            assert!(metrics.contains("jobs.done:2|c|#app:a,zone:b\n"));
+            assert!(metrics.contains("queue.depth:3|g\n"));
+            assert!(!metrics.contains("dd.internal.entity_id"), "{metrics}");
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_custom_writer_packet_sequence_tag_grows_across_flushes() -> std::io::Result<()> {
+    // small packets so every flush spans several of them
+    let writer = TestStatsWriter::new(96);
+    let writer_clone = writer.clone();
+    let options = MetricCollectorOptions {
+        max_udp_packet_size: 96,
+        flush_interval: Duration::from_millis(20),
+        writer_type: StatsWriterType::Custom(Box::new(writer)),
+        packet_sequence_tag: Some("seq"),
+        ..Default::default()
+    };
+
+    let bind_addr = "0.0.0.0:0".parse().unwrap();
+    let datadog_addr = random_datadog_addr();
+    let collector =
+        MetricCollector::new(bind_addr, datadog_addr, options, SharedCollector::default())
+            .expect("failed to create collector");
+
+    for round in 0..3 {
+        for id in 0..8 {
+            let tag = format!("id:{id}");
+            collector.count_add(
+                RylvStr::from_static("jobs.done"),
+                round + 1,
+                &mut [RylvStr::from(tag.as_str())],
+            );
+        }
+        std::thread::sleep(Duration::from_millis(80));
+    }
+    drop(collector);
+
+    let packets = writer_clone.get_packets();
+    let mut sequences = Vec::new();
+    for packet in &packets {
+        let values = packet
+            .lines()
+            .map(|line| {
+                let (_, sequence) = line.rsplit_once(",seq:").expect("sequence tag is last");
+                sequence.parse::<u64>().unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert!(
+            values.windows(2).all(|pair| pair[0] == pair[1]),
+            "one sequence per packet: {packet}"
+        );
+        sequences.push(values[0]);
+    }
+    assert!(packets.len() > 3, "too few packets: {packets:?}");
     assert!(
-        metrics.contains("app.latency.count:1|c|#service:api\n"),
-        "Should contain prefix in histogram count"
+        sequences.windows(2).all(|pair| pair[0] <= pair[1]),
+        "sequence went backwards: {sequences:?}"
     );
+    sequences.dedup();
     assert!(
-        metrics.contains("app.latency.min:150|g|#service:api\n"),
-        "Should contain prefix in histogram min"
+        sequences.len() >= 3,
+        "expected one sequence per flush: {sequences:?}"
     );
 
     Ok(())
 }
 
 #[test]
-fn test_custom_writer_aggregation() -> std::io::Result<()> {
-    let writer = TestStatsWriter::new(1024);
+fn test_custom_writer_derived_metrics_use_the_flushed_aggregates() -> std::io::Result<()> {
+    let writer = TestStatsWriter::new(512);
     let writer_clone = writer.clone();
-
     let options = MetricCollectorOptions {
-        max_udp_packet_size: 1024,
-        max_udp_batch_size: 100,
-        flush_interval: Duration::from_millis(100),
+        flush_interval: Duration::from_secs(60),
         writer_type: StatsWriterType::Custom(Box::new(writer)),
+        derived_metrics: Some(DerivedMetrics::new(|snapshot| {
+            let errors = snapshot.count("errors", "route:/").unwrap_or(0);
+            let Some(requests) = snapshot.count("requests", "route:/") else {
+                return Vec::new();
+            };
+            vec![
+                Metric::Gauge {
+                    name: RylvStr::from_static("error_rate_percent"),
+                    value: errors * 100 / requests,
+                    tags: vec![
+                        RylvStr::from_static("route:/"),
+                        RylvStr::from_static("derived:true"),
+                    ],
+                },
+                Metric::Histogram {
+                    name: RylvStr::from_static("requests_per_error"),
+                    value: requests / errors.max(1),
+                    tags: vec![RylvStr::from_static("route:/")],
+                },
+            ]
+        })),
+        ..Default::default()
     };
 
     let bind_addr = "0.0.0.0:0".parse().unwrap();
     let datadog_addr = random_datadog_addr();
-
-    let inner = SharedCollector::default();
+    let inner = SharedCollector::new(SharedCollectorOptions {
+        stats_prefix: "app.".to_string(),
+        ..Default::default()
+    });
     let collector = MetricCollector::new(bind_addr, datadog_addr, options, inner)
         .expect("failed to create collector");
 
-    // Test counter aggregation
-    collector.count(
-        RylvStr::from_static("page.views"),
-        &mut [RylvStr::from_static("page:home")],
-    );
-    collector.count(
-        RylvStr::from_static("page.views"),
-        &mut [RylvStr::from_static("page:home")],
-    );
-    collector.count(
-        RylvStr::from_static("page.views"),
-        &mut [RylvStr::from_static("page:home")],
-    );
-
-    // Test gauge aggregation
-    collector.gauge(
-        RylvStr::from_static("cpu.usage"),
-        50,
-        &mut [RylvStr::from_static("host:web1")],
-    );
-    collector.gauge(
-        RylvStr::from_static("cpu.usage"),
-        75,
-        &mut [RylvStr::from_static("host:web1")],
-    );
-    collector.gauge(
-        RylvStr::from_static("cpu.usage"),
-        90,
-        &mut [RylvStr::from_static("host:web1")],
-    );
-
-    // Test histogram aggregation
-    collector.histogram(
-        RylvStr::from_static("request.duration"),
-        100,
-        &mut [RylvStr::from_static("endpoint:/users")],
-    );
-    collector.histogram(
-        RylvStr::from_static("request.duration"),
-        200,
-        &mut [RylvStr::from_static("endpoint:/users")],
-    );
-    collector.histogram(
-        RylvStr::from_static("request.duration"),
-        150,
-        &mut [RylvStr::from_static("endpoint:/users")],
+    collector.count_add(
+        RylvStr::from_static("requests"),
+        12,
+        &mut [RylvStr::from_static("route:/")],
     );
-    collector.histogram(
-        RylvStr::from_static("request.duration"),
-        300,
-        &mut [RylvStr::from_static("endpoint:/users")],
+    collector.count_add(
+        RylvStr::from_static("errors"),
+        3,
+        &mut [RylvStr::from_static("route:/")],
     );
-
     drop(collector);
 
-    let metrics = writer_clone.get_all_metrics_as_text();
-
-    // Verify counter aggregation
-    assert!(
-        metrics.contains("page.views:3|c|#page:home\n"),
-        "Counter should aggregate to 3"
+    let packets = writer_clone.get_packets();
+    assert_eq!(
+        packets.len(),
+        1,
+        "derived metrics share the flush: {packets:?}"
     );
-
-    // Verify gauge aggregation (should have gauge metric)
+    let metrics = &packets[0];
     assert!(
-        metrics.contains("cpu.usage:") && metrics.contains("|g|#host:web1\n"),
-        "Gauge should be present with correct format"
+        metrics.contains("app.requests:12|c|#route:/\n"),
+        "{metrics}"
     );
-
-    // Verify histogram aggregation
+    assert!(metrics.contains("app.errors:3|c|#route:/\n"), "{metrics}");
     assert!(
-        metrics.contains("request.duration.count:4|c|#endpoint:/users\n"),
-        "Histogram count should be 4"
+        metrics.contains("app.error_rate_percent:25|g|#derived:true,route:/\n"),
+        "{metrics}"
     );
     assert!(
-        metrics.contains("request.duration.min:100|g|#endpoint:/users\n"),
-        "Histogram min should be 100"
+        metrics.contains("app.requests_per_error.count:1|c|#route:/\n"),
+        "derived histograms are aggregated like recorded ones: {metrics}"
     );
     assert!(
-        metrics.contains("request.duration.max:300|g|#endpoint:/users\n"),
-        "Histogram max should be 300"
+        metrics.contains("app.requests_per_error.max:4|g|#route:/\n"),
+        "{metrics}"
     );
+    assert!(!metrics.contains("|ms"), "{metrics}");
 
     Ok(())
 }
 
 #[test]
-fn test_custom_writer_multiple_tags() -> std::io::Result<()> {
-    let writer = TestStatsWriter::new(1024);
+fn test_custom_writer_name_transform_rewrites_every_name() -> std::io::Result<()> {
+    let writer = TestStatsWriter::new(512);
     let writer_clone = writer.clone();
 
     let options = MetricCollectorOptions {
-        max_udp_packet_size: 1024,
-        max_udp_batch_size: 100,
         flush_interval: Duration::from_millis(100),
         writer_type: StatsWriterType::Custom(Box::new(writer)),
+        name_transform: Some(NameTransform::new(|name| {
+            Cow::Owned(name.to_ascii_uppercase())
+        })),
+        ..Default::default()
     };
 
     let bind_addr = "0.0.0.0:0".parse().unwrap();
     let datadog_addr = random_datadog_addr();
-
-    let inner = SharedCollector::default();
+    let inner = SharedCollector::new(SharedCollectorOptions {
+        stats_prefix: "app.".to_string(),
+        ..Default::default()
+    });
     let collector = MetricCollector::new(bind_addr, datadog_addr, options, inner)
         .expect("failed to create collector");
 
-    // Test with multiple tags (they should be sorted)
     collector.count(
-        RylvStr::from_static("multi.tag.metric"),
-        &mut [
-            RylvStr::from_static("tag3:value3"),
-            RylvStr::from_static("tag1:value1"),
-            RylvStr::from_static("tag2:value2"),
-        ],
+        RylvStr::from_static("request.count"),
+        &mut [RylvStr::from_static("method:get")],
     );
+    collector.histogram(RylvStr::from_static("latency"), 10, []);
+    collector.timing_raw(RylvStr::from_static("raw.latency"), 5, []);
 
     drop(collector);
 
     let metrics = writer_clone.get_all_metrics_as_text();
-
-    // RylvStrs should be sorted alphabetically
     assert!(
-        metrics.contains("multi.tag.metric:1|c|#tag1:value1,tag2:value2,tag3:value3\n"),
-        "RylvStrs should be sorted alphabetically in wire format"
+        metrics.contains("APP.REQUEST.COUNT:1|c|#method:get\n"),
+        "tags must not be transformed: {metrics}"
+    );
+    assert!(metrics.contains("APP.LATENCY.COUNT:1|c\n"), "{metrics}");
+    assert!(
+        metrics.contains("APP.LATENCY.95PERCENTILE:10|g\n"),
+        "{metrics}"
     );
+    assert!(metrics.contains("APP.RAW.LATENCY:5|ms\n"), "{metrics}");
+    assert!(!metrics.contains("app."), "{metrics}");
 
     Ok(())
 }
 
 #[test]
-fn test_custom_writer_skip_histogram_base_metrics() -> std::io::Result<()> {
-    let writer = TestStatsWriter::new(1024);
+fn test_custom_writer_name_separator_replace_rewrites_dots() -> std::io::Result<()> {
+    let writer = TestStatsWriter::new(512);
     let writer_clone = writer.clone();
 
     let options = MetricCollectorOptions {
-        max_udp_packet_size: 1024,
-        max_udp_batch_size: 100,
         flush_interval: Duration::from_millis(100),
         writer_type: StatsWriterType::Custom(Box::new(writer)),
+        name_separator_replace: Some(('.', '_')),
+        ..Default::default()
     };
 
     let bind_addr = "0.0.0.0:0".parse().unwrap();
     let datadog_addr = random_datadog_addr();
-    let inner = SharedCollector::new(SharedCollectorOptions {
-        default_histogram_config: HistogramConfig::default()
-            .with_count(false)
-            .with_min(false)
-            .with_avg(false)
-            .with_max(false),
-        ..Default::default()
-    });
-    let collector = MetricCollector::new(bind_addr, datadog_addr, options, inner)
-        .expect("failed to create collector");
+    let collector =
+        MetricCollector::new(bind_addr, datadog_addr, options, SharedCollector::default())
+            .expect("failed to create collector");
 
     collector.histogram(
-        RylvStr::from_static("configurable.histogram"),
-        123,
-        &mut [RylvStr::from_static("scope:test")],
+        RylvStr::from_static("request.latency"),
+        10,
+        &mut [RylvStr::from_static("host:a.b")],
     );
+    collector.count(RylvStr::from_static("requests"), []);
 
     drop(collector);
 
     let metrics = writer_clone.get_all_metrics_as_text();
-
-    assert!(
-        !metrics.contains("configurable.histogram.count:"),
-        "count metric should be skipped"
-    );
-    assert!(
-        !metrics.contains("configurable.histogram.min:"),
-        "min metric should be skipped"
-    );
-    assert!(
-        !metrics.contains("configurable.histogram.avg:"),
-        "avg metric should be skipped"
-    );
-    assert!(
-        !metrics.contains("configurable.histogram.max:"),
-        "max metric should be skipped"
-    );
     assert!(
-        metrics.contains("configurable.histogram.95percentile:123|g|#scope:test\n"),
-        "95 percentile should still be emitted"
+        metrics.contains("request_latency_count:1|c|#host:a.b\n"),
+        "tags must keep their dots: {metrics}"
     );
     assert!(
-        metrics.contains("configurable.histogram.99percentile:123|g|#scope:test\n"),
-        "99 percentile should still be emitted"
+        metrics.contains("request_latency_95percentile:10|g|#host:a.b\n"),
+        "{metrics}"
     );
+    assert!(metrics.contains("requests:1|c\n"), "{metrics}");
+    assert!(!metrics.contains("request.latency"), "{metrics}");
 
     Ok(())
 }
 
 #[test]
-fn test_custom_writer_custom_percentiles_skip_count_min() -> std::io::Result<()> {
-    let writer = TestStatsWriter::new(1024);
-    let writer_clone = writer.clone();
+fn test_name_separator_replace_rejects_wire_format_characters() {
+    for to in [':', '|', '#', ',', '\n'] {
+        let options = MetricCollectorOptions {
+            name_separator_replace: Some(('.', to)),
+            ..Default::default()
+        };
+        let bind_addr = "0.0.0.0:0".parse().unwrap();
+        let result = MetricCollector::new(
+            bind_addr,
+            random_datadog_addr(),
+            options,
+            SharedCollector::default(),
+        );
+        assert!(result.is_err(), "{to:?} must be rejected");
+    }
+}
 
-    let custom_histogram_config = HistogramConfig::new(SigFig::default(), vec![0.75, 0.9])
-        .unwrap()
-        .with_count(false)
-        .with_min(false);
+#[test]
+fn test_recent_errors_keep_the_latest_write_failures() -> std::io::Result<()> {
+    // every line is longer than the writer accepts
+    let writer = TestStatsWriter::new(8);
 
     let options = MetricCollectorOptions {
-        max_udp_packet_size: 1024,
-        max_udp_batch_size: 100,
-        flush_interval: Duration::from_millis(100),
+        flush_interval: Duration::from_secs(60),
         writer_type: StatsWriterType::Custom(Box::new(writer)),
+        recent_errors_capacity: 2,
+        ..Default::default()
     };
 
     let bind_addr = "0.0.0.0:0".parse().unwrap();
     let datadog_addr = random_datadog_addr();
-    let inner = SharedCollector::new(SharedCollectorOptions {
-        default_histogram_config: custom_histogram_config,
-        ..Default::default()
-    });
-    let collector = MetricCollector::new(bind_addr, datadog_addr, options, inner)
-        .expect("failed to create collector");
+    let collector =
+        MetricCollector::new(bind_addr, datadog_addr, options, SharedCollector::default())
+            .expect("failed to create collector");
+    assert!(collector.recent_errors().is_empty());
 
-    collector.histogram(
-        RylvStr::from_static("custom.percentiles.histogram"),
-        100,
-        &mut [RylvStr::from_static("scope:test")],
+    for metric in ["first.count", "second.count", "third.count"] {
+        collector.count(RylvStr::from(metric), []);
+    }
+    collector.quiesce().expect("flush thread is running");
+
+    let errors = collector.recent_errors();
+    assert_eq!(
+        errors.len(),
+        2,
+        "only the latest failures are kept: {errors:?}"
     );
+    for error in &errors {
+        assert!(
+            error.starts_with("Error sending metrics") && error.contains("larger than 8"),
+            "{error}"
+        );
+    }
 
-    drop(collector);
+    Ok(())
+}
 
-    let metrics = writer_clone.get_all_metrics_as_text();
+#[test]
+fn test_custom_writer_max_flush_duration_resumes_until_every_series_is_sent() -> std::io::Result<()>
+{
+    let writer = TestStatsWriter::new(512);
+    let writer_clone = writer.clone();
 
+    let options = MetricCollectorOptions {
+        flush_interval: Duration::from_millis(20),
+        writer_type: StatsWriterType::Custom(Box::new(writer)),
+        // every flush yields after the first few series
+        max_flush_duration: Some(Duration::from_nanos(1)),
+        ..Default::default()
+    };
+
+    let bind_addr = "0.0.0.0:0".parse().unwrap();
+    let datadog_addr = random_datadog_addr();
+
+    let collector =
+        MetricCollector::new(bind_addr, datadog_addr, options, SharedCollector::default())
+            .expect("failed to create collector");
+
+    let series = 10_000;
+    for id in 0..series {
+        let tag = format!("id:{id}");
+        collector.count(
+            RylvStr::from_static("jobs.done"),
+            &mut [RylvStr::from(tag.as_str())],
+        );
+    }
+
+    drop(collector);
+
+    let metrics = writer_clone.get_all_metrics_as_text();
+    let mut lines = metrics.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), series);
+    assert!(lines
+        .iter()
+        .all(|line| line.starts_with("jobs.done:1|c|#id:")));
+    lines.sort_unstable();
+    lines.dedup();
+    assert_eq!(lines.len(), series, "no series is sent twice");
+
+    Ok(())
+}
+
+#[test]
+fn test_custom_writer_with_prefix() -> std::io::Result<()> {
+    let writer = TestStatsWriter::new(1024);
+    let writer_clone = writer.clone();
+
+    let options = MetricCollectorOptions {
+        max_udp_packet_size: 1024,
+        max_udp_batch_size: 100,
+        flush_interval: Duration::from_millis(100),
+        writer_type: StatsWriterType::Custom(Box::new(writer)),
+        ..Default::default()
+    };
+
+    let bind_addr = "0.0.0.0:0".parse().unwrap();
+    let datadog_addr = random_datadog_addr();
+
+    let inner = SharedCollector::new(SharedCollectorOptions {
+        stats_prefix: "app.".to_string(),
+        ..Default::default()
+    });
+    let collector = MetricCollector::new(bind_addr, datadog_addr, options, inner)
+        .expect("failed to create collector");
+
+    collector.count(
+        RylvStr::from_static("requests"),
+        &mut [RylvStr::from_static("method:GET")],
+    );
+    collector.gauge(
+        RylvStr::from_static("memory"),
+        512,
+        &mut [RylvStr::from_static("unit:mb")],
+    );
+    collector.histogram(
+        RylvStr::from_static("latency"),
+        150,
+        &mut [RylvStr::from_static("service:api")],
+    );
+
+    drop(collector);
+
+    let metrics = writer_clone.get_all_metrics_as_text();
+
+    // Verify prefix is included in wire format
     assert!(
-        !metrics.contains("custom.percentiles.histogram.count:"),
-        "count metric should be skipped"
+        metrics.contains("app.requests:1|c|#method:GET\n"),
+        "Should contain prefix in counter metric"
     );
     assert!(
-        !metrics.contains("custom.percentiles.histogram.min:"),
-        "min metric should be skipped"
+        metrics.contains("app.memory:512|g|#unit:mb\n"),
+        "Should contain prefix in gauge metric"
     );
     assert!(
-        metrics.contains("custom.percentiles.histogram.avg:100|g|#scope:test\n"),
-        "avg metric should still be emitted"
+        metrics.contains("app.latency.count:1|c|#service:api\n"),
+        "Should contain prefix in histogram count"
     );
     assert!(
-        metrics.contains("custom.percentiles.histogram.max:100|g|#scope:test\n"),
-        "max metric should still be emitted"
+        metrics.contains("app.latency.min:150|g|#service:api\n"),
+        "Should contain prefix in histogram min"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_custom_writer_timing_raw() -> std::io::Result<()> {
+    let writer = TestStatsWriter::new(1024);
+    let writer_clone = writer.clone();
+
+    let options = MetricCollectorOptions {
+        max_udp_packet_size: 1024,
+        max_udp_batch_size: 100,
+        flush_interval: Duration::from_millis(100),
+        writer_type: StatsWriterType::Custom(Box::new(writer)),
+        ..Default::default()
+    };
+
+    let bind_addr = "0.0.0.0:0".parse().unwrap();
+    let datadog_addr = random_datadog_addr();
+
+    let inner = SharedCollector::new(SharedCollectorOptions {
+        stats_prefix: "app.".to_string(),
+        ..Default::default()
+    });
+    let collector = MetricCollector::new(bind_addr, datadog_addr, options, inner)
+        .expect("failed to create collector");
+
+    collector.timing_raw(
+        RylvStr::from_static("db.query"),
+        42,
+        &mut [
+            RylvStr::from_static("table:users"),
+            RylvStr::from_static("op:select"),
+        ],
+    );
+    collector.timing_raw(
+        "db.query",
+        58,
+        &mut [
+            RylvStr::from_static("op:select"),
+            RylvStr::from_static("table:users"),
+        ],
     );
+
+    drop(collector);
+
+    let metrics = writer_clone.get_all_metrics_as_text();
+
+    // Every sample is forwarded as its own `|ms` line, sorted tags included
     assert!(
-        metrics.contains("custom.percentiles.histogram.75percentile:100|g|#scope:test\n"),
-        "75 percentile should be emitted"
+        metrics.contains("app.db.query:42|ms|#op:select,table:users\n"),
+        "Should contain first raw timing line"
     );
     assert!(
-        metrics.contains("custom.percentiles.histogram.90percentile:100|g|#scope:test\n"),
-        "90 percentile should be emitted"
+        metrics.contains("app.db.query:58|ms|#op:select,table:users\n"),
+        "Should contain second raw timing line"
     );
+
+    // No client-side histogram fan-out
     assert!(
-        !metrics.contains("custom.percentiles.histogram.95percentile:"),
-        "95 percentile should not be emitted for custom percentiles"
+        !metrics.contains("app.db.query.count:"),
+        "Raw timings should not produce a .count line"
     );
     assert!(
-        !metrics.contains("custom.percentiles.histogram.99percentile:"),
-        "99 percentile should not be emitted for custom percentiles"
+        !metrics.contains("app.db.query.avg:"),
+        "Raw timings should not produce an .avg line"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_timing_raw_drops_samples_beyond_the_pending_limit() -> std::io::Result<()> {
+    let writer = TestStatsWriter::new(1024);
+    let writer_clone = writer.clone();
+    let options = MetricCollectorOptions {
+        flush_interval: Duration::from_secs(60),
+        writer_type: StatsWriterType::Custom(Box::new(writer)),
+        max_pending_raw_timings: 3,
+        ..Default::default()
+    };
+    let bind_addr = "0.0.0.0:0".parse().unwrap();
+    let datadog_addr = random_datadog_addr();
+    let collector =
+        MetricCollector::new(bind_addr, datadog_addr, options, SharedCollector::default())
+            .expect("failed to create collector");
+
+    for millis in 0..5 {
+        collector.timing_raw(RylvStr::from_static("job.duration"), millis, []);
+    }
+    assert_eq!(collector.raw_timing_drops(), 2);
+
+    // only the kept samples are sent
+    collector.quiesce().expect("flush thread is running");
+    let metrics = writer_clone.get_all_metrics_as_text();
+    assert_eq!(metrics.matches("job.duration:").count(), 3, "{metrics}");
+
+    Ok(())
+}
+
+#[test]
+fn test_custom_writer_emergency_counters() -> std::io::Result<()> {
+    let writer = TestStatsWriter::new(1024);
+    let writer_clone = writer.clone();
+
+    let options = MetricCollectorOptions {
+        max_udp_packet_size: 1024,
+        max_udp_batch_size: 100,
+        flush_interval: Duration::from_millis(100),
+        writer_type: StatsWriterType::Custom(Box::new(writer)),
+        emergency_counter_capacity: 2,
+        ..Default::default()
+    };
+
+    let bind_addr = "0.0.0.0:0".parse().unwrap();
+    let datadog_addr = random_datadog_addr();
+
+    let inner = SharedCollector::new(SharedCollectorOptions {
+        stats_prefix: "app.".to_string(),
+        ..Default::default()
+    });
+    let collector = MetricCollector::new(bind_addr, datadog_addr, options, inner)
+        .expect("failed to create collector");
+
+    let crashes = collector
+        .register_emergency_counter(
+            RylvStr::from_static("crash"),
+            &mut [
+                RylvStr::from_static("signal:segv"),
+                RylvStr::from_static("env:test"),
+            ],
+        )
+        .expect("capacity available");
+    let idle = collector
+        .register_emergency_counter(RylvStr::from_static("idle"), [])
+        .expect("capacity available");
+    assert!(collector
+        .register_emergency_counter(RylvStr::from_static("overflow"), [])
+        .is_err());
+
+    collector.emergency_inc(crashes);
+    collector.emergency_inc(crashes);
+    let _ = idle;
+
+    drop(collector);
+
+    let metrics = writer_clone.get_all_metrics_as_text();
+
+    assert!(
+        metrics.contains("app.crash:2|c|#env:test,signal:segv\n"),
+        "Should contain the emergency counter with sorted tags"
+    );
+    assert!(
+        !metrics.contains("app.idle:"),
+        "Counters that were not incremented should not be sent"
     );
 
     Ok(())
 }
+
+#[test]
+fn test_custom_writer_aggregation() -> std::io::Result<()> {
+    let writer = TestStatsWriter::new(1024);
+    let writer_clone = writer.clone();
+
+    let options = MetricCollectorOptions {
+        max_udp_packet_size: 1024,
+        max_udp_batch_size: 100,
+        flush_interval: Duration::from_millis(100),
+        writer_type: StatsWriterType::Custom(Box::new(writer)),
+        ..Default::default()
+    };
+
+    let bind_addr = "0.0.0.0:0".parse().unwrap();
+    let datadog_addr = random_datadog_addr();
+
+    let inner = SharedCollector::default();
+    let collector = MetricCollector::new(bind_addr, datadog_addr, options, inner)
+        .expect("failed to create collector");
+
+    // Test counter aggregation
+    collector.count(
+        RylvStr::from_static("page.views"),
+        &mut [RylvStr::from_static("page:home")],
+    );
+    collector.count(
+        RylvStr::from_static("page.views"),
+        &mut [RylvStr::from_static("page:home")],
+    );
+    collector.count(
+        RylvStr::from_static("page.views"),
+        &mut [RylvStr::from_static("page:home")],
+    );
+
+    // Test gauge aggregation
+    collector.gauge(
+        RylvStr::from_static("cpu.usage"),
+        50,
+        &mut [RylvStr::from_static("host:web1")],
+    );
+    collector.gauge(
+        RylvStr::from_static("cpu.usage"),
+        75,
+        &mut [RylvStr::from_static("host:web1")],
+    );
+    collector.gauge(
+        RylvStr::from_static("cpu.usage"),
+        90,
+        &mut [RylvStr::from_static("host:web1")],
+    );
+
+    // Test histogram aggregation
+    collector.histogram(
+        RylvStr::from_static("request.duration"),
+        100,
+        &mut [RylvStr::from_static("endpoint:/users")],
+    );
+    collector.histogram(
+        RylvStr::from_static("request.duration"),
+        200,
+        &mut [RylvStr::from_static("endpoint:/users")],
+    );
+    collector.histogram(
+        RylvStr::from_static("request.duration"),
+        150,
+        &mut [RylvStr::from_static("endpoint:/users")],
+    );
+    collector.histogram(
+        RylvStr::from_static("request.duration"),
+        300,
+        &mut [RylvStr::from_static("endpoint:/users")],
+    );
+
+    drop(collector);
+
+    let metrics = writer_clone.get_all_metrics_as_text();
+
+    // Verify counter aggregation
+    assert!(
+        metrics.contains("page.views:3|c|#page:home\n"),
+        "Counter should aggregate to 3"
+    );
+
+    // Verify gauge aggregation (should have gauge metric)
+    assert!(
+        metrics.contains("cpu.usage:") && metrics.contains("|g|#host:web1\n"),
+        "Gauge should be present with correct format"
+    );
+
+    // Verify histogram aggregation
+    assert!(
+        metrics.contains("request.duration.count:4|c|#endpoint:/users\n"),
+        "Histogram count should be 4"
+    );
+    assert!(
+        metrics.contains("request.duration.min:100|g|#endpoint:/users\n"),
+        "Histogram min should be 100"
+    );
+    assert!(
+        metrics.contains("request.duration.max:300|g|#endpoint:/users\n"),
+        "Histogram max should be 300"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_custom_writer_multiple_tags() -> std::io::Result<()> {
+    let writer = TestStatsWriter::new(1024);
+    let writer_clone = writer.clone();
+
+    let options = MetricCollectorOptions {
+        max_udp_packet_size: 1024,
+        max_udp_batch_size: 100,
+        flush_interval: Duration::from_millis(100),
+        writer_type: StatsWriterType::Custom(Box::new(writer)),
+        ..Default::default()
+    };
+
+    let bind_addr = "0.0.0.0:0".parse().unwrap();
+    let datadog_addr = random_datadog_addr();
+
+    let inner = SharedCollector::default();
+    let collector = MetricCollector::new(bind_addr, datadog_addr, options, inner)
+        .expect("failed to create collector");
+
+    // Test with multiple tags (they should be sorted)
+    collector.count(
+        RylvStr::from_static("multi.tag.metric"),
+        &mut [
+            RylvStr::from_static("tag3:value3"),
+            RylvStr::from_static("tag1:value1"),
+            RylvStr::from_static("tag2:value2"),
+        ],
+    );
+
+    drop(collector);
+
+    let metrics = writer_clone.get_all_metrics_as_text();
+
+    // RylvStrs should be sorted alphabetically
+    assert!(
+        metrics.contains("multi.tag.metric:1|c|#tag1:value1,tag2:value2,tag3:value3\n"),
+        "RylvStrs should be sorted alphabetically in wire format"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_custom_writer_skip_histogram_base_metrics() -> std::io::Result<()> {
+    let writer = TestStatsWriter::new(1024);
+    let writer_clone = writer.clone();
+
+    let options = MetricCollectorOptions {
+        max_udp_packet_size: 1024,
+        max_udp_batch_size: 100,
+        flush_interval: Duration::from_millis(100),
+        writer_type: StatsWriterType::Custom(Box::new(writer)),
+        ..Default::default()
+    };
+
+    let bind_addr = "0.0.0.0:0".parse().unwrap();
+    let datadog_addr = random_datadog_addr();
+    let inner = SharedCollector::new(SharedCollectorOptions {
+        default_histogram_config: HistogramConfig::default()
+            .with_count(false)
+            .with_min(false)
+            .with_avg(false)
+            .with_max(false),
+        ..Default::default()
+    });
+    let collector = MetricCollector::new(bind_addr, datadog_addr, options, inner)
+        .expect("failed to create collector");
+
+    collector.histogram(
+        RylvStr::from_static("configurable.histogram"),
+        123,
+        &mut [RylvStr::from_static("scope:test")],
+    );
+
+    drop(collector);
+
+    let metrics = writer_clone.get_all_metrics_as_text();
+
+    assert!(
+        !metrics.contains("configurable.histogram.count:"),
+        "count metric should be skipped"
+    );
+    assert!(
+        !metrics.contains("configurable.histogram.min:"),
+        "min metric should be skipped"
+    );
+    assert!(
+        !metrics.contains("configurable.histogram.avg:"),
+        "avg metric should be skipped"
+    );
+    assert!(
+        !metrics.contains("configurable.histogram.max:"),
+        "max metric should be skipped"
+    );
+    assert!(
+        metrics.contains("configurable.histogram.95percentile:123|g|#scope:test\n"),
+        "95 percentile should still be emitted"
+    );
+    assert!(
+        metrics.contains("configurable.histogram.99percentile:123|g|#scope:test\n"),
+        "99 percentile should still be emitted"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_custom_writer_custom_percentiles_skip_count_min() -> std::io::Result<()> {
+    let writer = TestStatsWriter::new(1024);
+    let writer_clone = writer.clone();
+
+    let custom_histogram_config = HistogramConfig::new(SigFig::default(), vec![0.75, 0.9])
+        .unwrap()
+        .with_count(false)
+        .with_min(false);
+
+    let options = MetricCollectorOptions {
+        max_udp_packet_size: 1024,
+        max_udp_batch_size: 100,
+        flush_interval: Duration::from_millis(100),
+        writer_type: StatsWriterType::Custom(Box::new(writer)),
+        ..Default::default()
+    };
+
+    let bind_addr = "0.0.0.0:0".parse().unwrap();
+    let datadog_addr = random_datadog_addr();
+    let inner = SharedCollector::new(SharedCollectorOptions {
+        default_histogram_config: custom_histogram_config,
+        ..Default::default()
+    });
+    let collector = MetricCollector::new(bind_addr, datadog_addr, options, inner)
+        .expect("failed to create collector");
+
+    collector.histogram(
+        RylvStr::from_static("custom.percentiles.histogram"),
+        100,
+        &mut [RylvStr::from_static("scope:test")],
+    );
+
+    drop(collector);
+
+    let metrics = writer_clone.get_all_metrics_as_text();
+
+    assert!(
+        !metrics.contains("custom.percentiles.histogram.count:"),
+        "count metric should be skipped"
+    );
+    assert!(
+        !metrics.contains("custom.percentiles.histogram.min:"),
+        "min metric should be skipped"
+    );
+    assert!(
+        metrics.contains("custom.percentiles.histogram.avg:100|g|#scope:test\n"),
+        "avg metric should still be emitted"
+    );
+    assert!(
+        metrics.contains("custom.percentiles.histogram.max:100|g|#scope:test\n"),
+        "max metric should still be emitted"
+    );
+    assert!(
+        metrics.contains("custom.percentiles.histogram.75percentile:100|g|#scope:test\n"),
+        "75 percentile should be emitted"
+    );
+    assert!(
+        metrics.contains("custom.percentiles.histogram.90percentile:100|g|#scope:test\n"),
+        "90 percentile should be emitted"
+    );
+    assert!(
+        !metrics.contains("custom.percentiles.histogram.95percentile:"),
+        "95 percentile should not be emitted for custom percentiles"
+    );
+    assert!(
+        !metrics.contains("custom.percentiles.histogram.99percentile:"),
+        "99 percentile should not be emitted for custom percentiles"
+    );
+
+    Ok(())
+}
+
+/// Wraps [`TestStatsWriter`] and sleeps on every flush to simulate a slow consumer.
+struct SlowStatsWriter {
+    inner: TestStatsWriter,
+    flush_delay: Duration,
+}
+
+impl StatsWriterTrait for SlowStatsWriter {
+    fn metric_copied(&self) -> bool {
+        self.inner.metric_copied()
+    }
+
+    fn write(
+        &mut self,
+        metrics: &[&str],
+        tags: &str,
+        value: &str,
+        metric_type: MetricKind,
+    ) -> MetricResult<()> {
+        self.inner.write(metrics, tags, value, metric_type)
+    }
+
+    fn flush(&mut self) -> MetricResult<usize> {
+        std::thread::sleep(self.flush_delay);
+        self.inner.flush()
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+}
+
+#[test]
+fn test_custom_writer_slow_flush_burst_loses_no_counts() -> std::io::Result<()> {
+    const THREADS: u64 = 8;
+    const COUNTS_PER_THREAD: u64 = 20_000;
+
+    let writer = TestStatsWriter::new(1024);
+    let writer_clone = writer.clone();
+
+    let options = MetricCollectorOptions {
+        max_udp_packet_size: 1024,
+        max_udp_batch_size: 100,
+        flush_interval: Duration::from_millis(5),
+        writer_type: StatsWriterType::Custom(Box::new(SlowStatsWriter {
+            inner: writer,
+            flush_delay: Duration::from_millis(20),
+        })),
+        ..Default::default()
+    };
+
+    let bind_addr = "0.0.0.0:0".parse().unwrap();
+    let datadog_addr = random_datadog_addr();
+    let inner = SharedCollector::new(SharedCollectorOptions {
+        max_pending_aggregators: 4,
+        ..Default::default()
+    });
+    let collector = MetricCollector::new(bind_addr, datadog_addr, options, inner)
+        .expect("failed to create collector");
+
+    std::thread::scope(|scope| {
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                for _ in 0..COUNTS_PER_THREAD {
+                    collector.count(
+                        RylvStr::from_static("burst.requests"),
+                        &mut [RylvStr::from_static("scope:test")],
+                    );
+                }
+            });
+        }
+    });
+
+    drop(collector);
+
+    let metrics = writer_clone.get_all_metrics_as_text();
+    let total: u64 = metrics
+        .lines()
+        .filter_map(|line| line.strip_prefix("burst.requests:"))
+        .map(|line| line.split('|').next().unwrap().parse::<u64>().unwrap())
+        .sum();
+
+    assert_eq!(total, THREADS * COUNTS_PER_THREAD);
+
+    Ok(())
+}
+
+/// Packet hook that prepends a 4-byte big-endian length header to every packet.
+struct LengthPrefixHook;
+
+impl PacketHook for LengthPrefixHook {
+    fn on_packet_complete(&mut self, packet: &[u8]) -> MetricResult<Vec<u8>> {
+        let len = u32::try_from(packet.len()).unwrap();
+        let mut framed = Vec::with_capacity(packet.len() + 4);
+        framed.extend_from_slice(&len.to_be_bytes());
+        framed.extend_from_slice(packet);
+        Ok(framed)
+    }
+}
+
+#[test]
+fn test_packet_hook_transforms_every_packet() -> std::io::Result<()> {
+    let receiver = std::net::UdpSocket::bind("127.0.0.1:0")?;
+    receiver.set_read_timeout(Some(Duration::from_millis(500)))?;
+
+    let options = MetricCollectorOptions {
+        max_udp_packet_size: 64,
+        max_udp_batch_size: 100,
+        flush_interval: Duration::from_secs(60),
+        writer_type: StatsWriterType::PacketHook {
+            hook: Box::new(LengthPrefixHook),
+            max_overhead: 4,
+        },
+        ..Default::default()
+    };
+    let bind_addr = "0.0.0.0:0".parse().unwrap();
+    let collector = MetricCollector::new(
+        bind_addr,
+        receiver.local_addr()?,
+        options,
+        SharedCollector::default(),
+    )
+    .expect("failed to create collector");
+
+    for index in 0..10 {
+        collector.gauge(
+            RylvStr::from(format!("hook.gauge.{index}")),
+            index,
+            &mut [RylvStr::from_static("env:test")],
+        );
+    }
+    drop(collector);
+
+    let mut buf = [0; 1024];
+    let mut lines = Vec::new();
+    let mut packets = 0;
+    while let Ok((number_of_bytes, _)) = receiver.recv_from(&mut buf) {
+        // the header is counted against the packet size
+        assert!(number_of_bytes <= 64);
+        let (header, payload) = buf[..number_of_bytes].split_at(4);
+        let len = u32::from_be_bytes(header.try_into().unwrap()) as usize;
+        assert_eq!(len, payload.len());
+        lines.extend(
+            std::str::from_utf8(payload)
+                .unwrap()
+                .lines()
+                .map(str::to_owned),
+        );
+        packets += 1;
+    }
+
+    // 64-byte packets only fit two lines each, so several packets were framed
+    assert!(packets > 1);
+    lines.sort();
+    let mut expected: Vec<String> = (0..10)
+        .map(|index| format!("hook.gauge.{index}:{index}|g|#env:test"))
+        .collect();
+    expected.sort();
+    assert_eq!(lines, expected);
+
+    Ok(())
+}
+
+/// Wraps [`TestStatsWriter`] and panics on the first write only.
+struct PanicOnceWriter {
+    inner: TestStatsWriter,
+    panicked: bool,
+}
+
+impl StatsWriterTrait for PanicOnceWriter {
+    fn metric_copied(&self) -> bool {
+        self.inner.metric_copied()
+    }
+
+    fn write(
+        &mut self,
+        metrics: &[&str],
+        tags: &str,
+        value: &str,
+        metric_type: MetricKind,
+    ) -> MetricResult<()> {
+        if !self.panicked {
+            self.panicked = true;
+            panic!("writer panics once");
+        }
+        self.inner.write(metrics, tags, value, metric_type)
+    }
+
+    fn flush(&mut self) -> MetricResult<usize> {
+        self.inner.flush()
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+}
+
+#[test]
+fn test_flush_thread_recovers_after_writer_panic() -> std::io::Result<()> {
+    let writer = TestStatsWriter::new(512);
+    let writer_clone = writer.clone();
+
+    let options = MetricCollectorOptions {
+        max_udp_packet_size: 512,
+        max_udp_batch_size: 100,
+        flush_interval: Duration::from_millis(50),
+        writer_type: StatsWriterType::Custom(Box::new(PanicOnceWriter {
+            inner: writer,
+            panicked: false,
+        })),
+        ..Default::default()
+    };
+    let bind_addr = "0.0.0.0:0".parse().unwrap();
+    let collector = MetricCollector::new(
+        bind_addr,
+        random_datadog_addr(),
+        options,
+        SharedCollector::default(),
+    )
+    .expect("failed to create collector");
+
+    collector.count(
+        RylvStr::from_static("panic.before"),
+        &mut [RylvStr::from_static("env:test")],
+    );
+    // The first flush panics inside the writer and is lost
+    std::thread::sleep(Duration::from_millis(200));
+    assert_eq!(collector.flush_thread_restarts(), 1);
+
+    collector.count(
+        RylvStr::from_static("panic.after"),
+        &mut [RylvStr::from_static("env:test")],
+    );
+    std::thread::sleep(Duration::from_millis(200));
+    assert!(writer_clone
+        .get_all_metrics_as_text()
+        .contains("panic.after:1|c|#env:test\n"));
+
+    drop(collector);
+
+    let metrics = writer_clone.get_all_metrics_as_text();
+    assert!(metrics.contains("rylv_metrics.flush_thread.restart:1|c\n"));
+    assert!(!metrics.contains("panic.before"));
+
+    Ok(())
+}
+
+/// Builds a collector that only flushes on shutdown, bounded by `shutdown_timeout`.
+fn shutdown_collector(
+    writer: Box<dyn StatsWriterTrait + Send + Sync>,
+    shutdown_timeout: Duration,
+) -> MetricCollector<SharedCollector> {
+    let options = MetricCollectorOptions {
+        flush_interval: Duration::from_secs(60),
+        writer_type: StatsWriterType::Custom(writer),
+        shutdown_timeout: Some(shutdown_timeout),
+        ..Default::default()
+    };
+    let bind_addr = "0.0.0.0:0".parse().unwrap();
+    let collector = MetricCollector::new(
+        bind_addr,
+        random_datadog_addr(),
+        options,
+        SharedCollector::default(),
+    )
+    .expect("failed to create collector");
+    collector.count(RylvStr::from_static("shutdown.requests"), &mut []);
+    collector.gauge(RylvStr::from_static("shutdown.queue"), 3, &mut []);
+    collector
+}
+
+#[test]
+fn test_try_shutdown_reports_a_clean_final_flush() -> std::io::Result<()> {
+    let writer = TestStatsWriter::new(512);
+    let writer_clone = writer.clone();
+    let collector = shutdown_collector(Box::new(writer), Duration::from_secs(10));
+
+    let report = collector.try_shutdown().expect("flush thread failed");
+    assert_eq!(
+        report,
+        ShutdownReport {
+            flushed_cleanly: true,
+            pending_metrics: 0,
+            timed_out: false,
+        }
+    );
+    let metrics = writer_clone.get_all_metrics_as_text();
+    assert!(metrics.contains("shutdown.requests:1|c\n"));
+    assert!(metrics.contains("shutdown.queue:3|g\n"));
+
+    Ok(())
+}
+
+#[test]
+fn test_try_shutdown_reports_a_timeout_on_a_stuck_writer() -> std::io::Result<()> {
+    let writer = TestStatsWriter::new(512);
+    let writer_clone = writer.clone();
+    let slow_writer = SlowStatsWriter {
+        inner: writer,
+        flush_delay: Duration::from_secs(2),
+    };
+    let collector = shutdown_collector(Box::new(slow_writer), Duration::from_millis(100));
+
+    let report = collector.try_shutdown().expect("flush thread failed");
+    assert!(report.timed_out);
+    assert!(!report.flushed_cleanly);
+    // both lines were written and are stuck in the writer's flush
+    assert_eq!(report.pending_metrics, 2);
+    assert!(writer_clone.get_all_metrics_as_text().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_custom_writer_count_add_f64_sends_the_fractional_total() -> std::io::Result<()> {
+    let writer = TestStatsWriter::new(512);
+    let writer_clone = writer.clone();
+    let options = MetricCollectorOptions {
+        flush_interval: Duration::from_secs(60),
+        writer_type: StatsWriterType::Custom(Box::new(writer)),
+        ..Default::default()
+    };
+
+    let bind_addr = "0.0.0.0:0".parse().unwrap();
+    let datadog_addr = random_datadog_addr();
+    let collector =
+        MetricCollector::new(bind_addr, datadog_addr, options, SharedCollector::default())
+            .expect("failed to create collector");
+
+    for _ in 0..3 {
+        collector.count_add_f64(
+            RylvStr::from_static("m"),
+            0.5,
+            &mut [RylvStr::from_static("env:test")],
+        );
+    }
+    // invalid increments are dropped instead of poisoning the total
+    for value in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY, -1.0] {
+        collector.count_add_f64(
+            RylvStr::from_static("m"),
+            value,
+            &mut [RylvStr::from_static("env:test")],
+        );
+        collector.count_add_f64(RylvStr::from_static("invalid"), value, &mut []);
+    }
+    collector.count_add_f64(RylvStr::from_static("whole"), 2.0, &mut []);
+    drop(collector);
+
+    let metrics = writer_clone.get_all_metrics_as_text();
+    assert!(metrics.contains("m:1.5|c|#env:test\n"), "{metrics}");
+    assert!(metrics.contains("whole:2|c\n"), "{metrics}");
+    assert!(!metrics.contains("invalid"), "{metrics}");
+
+    Ok(())
+}
+
+/// Forwards to a [`TestStatsWriter`], reporting itself full for the first `blocked` checks.
+struct BackpressureWriter {
+    inner: TestStatsWriter,
+    blocked: Arc<AtomicUsize>,
+    events: Arc<Mutex<Vec<&'static str>>>,
+}
+
+impl StatsWriterTrait for BackpressureWriter {
+    fn metric_copied(&self) -> bool {
+        self.inner.metric_copied()
+    }
+
+    fn write(
+        &mut self,
+        metrics: &[&str],
+        tags: &str,
+        value: &str,
+        metric_type: MetricKind,
+    ) -> MetricResult<()> {
+        self.inner.write(metrics, tags, value, metric_type)
+    }
+
+    fn flush(&mut self) -> MetricResult<usize> {
+        self.events.lock().unwrap().push("flushed");
+        self.inner.flush()
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+
+    fn would_block(&self) -> bool {
+        let blocked = self
+            .blocked
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| {
+                left.checked_sub(1)
+            })
+            .is_ok();
+        if blocked {
+            self.events.lock().unwrap().push("deferred");
+        }
+        blocked
+    }
+}
+
+#[test]
+fn test_custom_writer_would_block_skips_flush_cycles() -> std::io::Result<()> {
+    let inner = TestStatsWriter::new(512);
+    let inner_clone = inner.clone();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let writer = BackpressureWriter {
+        inner,
+        blocked: Arc::new(AtomicUsize::new(2)),
+        events: Arc::clone(&events),
+    };
+    let options = MetricCollectorOptions {
+        flush_interval: Duration::from_millis(20),
+        writer_type: StatsWriterType::Custom(Box::new(writer)),
+        ..Default::default()
+    };
+
+    let bind_addr = "0.0.0.0:0".parse().unwrap();
+    let datadog_addr = random_datadog_addr();
+    let collector =
+        MetricCollector::new(bind_addr, datadog_addr, options, SharedCollector::default())
+            .expect("failed to create collector");
+
+    collector.count(RylvStr::from_static("requests"), &mut []);
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    while inner_clone.get_all_metrics_as_text().is_empty() {
+        assert!(std::time::Instant::now() < deadline, "no flush completed");
+        std::thread::sleep(Duration::from_millis(5));
+    }
+
+    assert_eq!(
+        events.lock().unwrap()[..3],
+        ["deferred", "deferred", "flushed"]
+    );
+    assert_eq!(inner_clone.get_all_metrics_as_text(), "requests:1|c\n");
+    drop(collector);
+
+    Ok(())
+}
+
+#[test]
+fn test_custom_writer_would_block_does_not_hold_up_shutdown() -> std::io::Result<()> {
+    let inner = TestStatsWriter::new(512);
+    let inner_clone = inner.clone();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let writer = BackpressureWriter {
+        inner,
+        blocked: Arc::new(AtomicUsize::new(usize::MAX)),
+        events: Arc::clone(&events),
+    };
+    let options = MetricCollectorOptions {
+        flush_interval: Duration::from_secs(60),
+        writer_type: StatsWriterType::Custom(Box::new(writer)),
+        ..Default::default()
+    };
+
+    let bind_addr = "0.0.0.0:0".parse().unwrap();
+    let datadog_addr = random_datadog_addr();
+    let collector =
+        MetricCollector::new(bind_addr, datadog_addr, options, SharedCollector::default())
+            .expect("failed to create collector");
+
+    collector.count(RylvStr::from_static("requests"), &mut []);
+    drop(collector);
+
+    assert_eq!(*events.lock().unwrap(), ["flushed"]);
+    assert_eq!(inner_clone.get_all_metrics_as_text(), "requests:1|c\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_quiesce_flushes_and_drops_later_recordings() -> std::io::Result<()> {
+    const THREADS: u64 = 4;
+    const PER_THREAD: u64 = 100;
+
+    let writer = TestStatsWriter::new(512);
+    let writer_clone = writer.clone();
+    let options = MetricCollectorOptions {
+        flush_interval: Duration::from_secs(60),
+        writer_type: StatsWriterType::Custom(Box::new(writer)),
+        ..Default::default()
+    };
+    let bind_addr = "0.0.0.0:0".parse().unwrap();
+    let datadog_addr = random_datadog_addr();
+    let collector = Arc::new(
+        MetricCollector::new(bind_addr, datadog_addr, options, SharedCollector::default())
+            .expect("failed to create collector"),
+    );
+
+    let quiesced = Arc::new(std::sync::Barrier::new(THREADS as usize + 1));
+    let recorded = Arc::new(std::sync::Barrier::new(THREADS as usize + 1));
+    let recorders: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let collector = Arc::clone(&collector);
+            let quiesced = Arc::clone(&quiesced);
+            let recorded = Arc::clone(&recorded);
+            std::thread::spawn(move || {
+                for _ in 0..PER_THREAD {
+                    collector.count(RylvStr::from_static("before"), &mut []);
+                }
+                recorded.wait();
+                quiesced.wait();
+                for _ in 0..PER_THREAD {
+                    collector.count(RylvStr::from_static("after"), &mut []);
+                }
+            })
+        })
+        .collect();
+
+    recorded.wait();
+    // the recorders still hold their clones
+    let report = collector.quiesce().expect("quiesce failed");
+    assert!(report.flushed_cleanly, "{report:?}");
+    assert_eq!(
+        writer_clone.get_all_metrics_as_text(),
+        format!("before:{}|c\n", THREADS * PER_THREAD)
+    );
+
+    quiesced.wait();
+    for recorder in recorders {
+        recorder.join().unwrap();
+    }
+    assert_eq!(collector.post_shutdown_drops(), THREADS * PER_THREAD);
+
+    drop(collector);
+    let metrics = writer_clone.get_all_metrics_as_text();
+    assert!(!metrics.contains("after"), "{metrics}");
+
+    Ok(())
+}
+
+#[test]
+fn test_quiesce_sends_every_drain_cut_short_by_max_flush_duration() -> std::io::Result<()> {
+    let writer = TestStatsWriter::new(512);
+    let writer_clone = writer.clone();
+    let options = MetricCollectorOptions {
+        flush_interval: Duration::from_secs(60),
+        writer_type: StatsWriterType::Custom(Box::new(writer)),
+        // every drain yields after the first few series
+        max_flush_duration: Some(Duration::from_nanos(1)),
+        ..Default::default()
+    };
+    let bind_addr = "0.0.0.0:0".parse().unwrap();
+    let datadog_addr = random_datadog_addr();
+    let inner = SharedCollector::new(SharedCollectorOptions {
+        max_pending_aggregators: 4,
+        ..Default::default()
+    });
+    let collector = MetricCollector::new(bind_addr, datadog_addr, options, inner)
+        .expect("failed to create collector");
+
+    let series = 10_000;
+    for id in 0..series {
+        let tag = format!("id:{id}");
+        collector.count(
+            RylvStr::from_static("jobs.done"),
+            &mut [RylvStr::from(tag.as_str())],
+        );
+    }
+
+    let report = collector.quiesce().expect("quiesce failed");
+    assert!(report.flushed_cleanly, "{report:?}");
+    let metrics = writer_clone.get_all_metrics_as_text();
+    let mut lines = metrics.lines().collect::<Vec<_>>();
+    lines.sort_unstable();
+    lines.dedup();
+    assert_eq!(
+        lines.len(),
+        series,
+        "every series is sent before quiesce returns"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_last_flush_at_advances_with_each_flush() -> std::io::Result<()> {
+    let writer = TestStatsWriter::new(512);
+    let options = MetricCollectorOptions {
+        flush_interval: Duration::from_millis(20),
+        flush_jitter: Duration::ZERO,
+        writer_type: StatsWriterType::Custom(Box::new(writer)),
+        ..Default::default()
+    };
+    let bind_addr = "0.0.0.0:0".parse().unwrap();
+    let datadog_addr = random_datadog_addr();
+    let collector =
+        MetricCollector::new(bind_addr, datadog_addr, options, SharedCollector::default())
+            .expect("failed to create collector");
+    let created = std::time::Instant::now();
+
+    let wait_for_flush_after = |previous: Option<std::time::Instant>| {
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        loop {
+            if let Some(last) = collector
+                .last_flush_at()
+                .filter(|&last| Some(last) > previous)
+            {
+                return last;
+            }
+            assert!(std::time::Instant::now() < deadline, "no flush completed");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    };
+
+    collector.count(RylvStr::from_static("hits"), &mut []);
+    let first = wait_for_flush_after(None);
+    assert!(first <= std::time::Instant::now());
+    assert!(collector.last_flush_duration().is_some());
+
+    collector.count(RylvStr::from_static("hits"), &mut []);
+    let second = wait_for_flush_after(Some(first));
+    assert!(second > first);
+    assert!(second.duration_since(created) >= Duration::from_millis(20));
+
+    Ok(())
+}
+
+#[test]
+fn test_namespaced_handles_share_one_collector_under_distinct_prefixes() -> std::io::Result<()> {
+    let writer = TestStatsWriter::new(512);
+    let writer_clone = writer.clone();
+    let options = MetricCollectorOptions {
+        flush_interval: Duration::from_secs(60),
+        writer_type: StatsWriterType::Custom(Box::new(writer)),
+        ..Default::default()
+    };
+    let bind_addr = "0.0.0.0:0".parse().unwrap();
+    let datadog_addr = random_datadog_addr();
+    let inner = SharedCollector::new(SharedCollectorOptions {
+        stats_prefix: "app.".to_string(),
+        ..Default::default()
+    });
+    let collector = MetricCollector::new(bind_addr, datadog_addr, options, inner)
+        .expect("failed to create collector");
+
+    let billing = collector.namespaced("billing.");
+    let search = collector.namespaced("search.");
+    assert_eq!(billing.prefix(), "billing.");
+    billing.count(RylvStr::from_static("requests"), &mut []);
+    search.count(RylvStr::from_static("requests"), &mut []);
+    search.count_add(RylvStr::from_static("requests"), 2, &mut []);
+    // a second handle with the same prefix aggregates into the same series
+    collector
+        .namespaced("billing.")
+        .count(RylvStr::from_static("requests"), &mut []);
+    search.gauge(
+        RylvStr::from_static("index.size"),
+        7,
+        &mut [RylvStr::from_static("shard:1")],
+    );
+    billing.histogram(RylvStr::from_static("latency"), 10, &mut []);
+    collector.count(RylvStr::from_static("requests"), &mut []);
+
+    drop(collector);
+
+    let metrics = writer_clone.get_all_metrics_as_text();
+    assert!(metrics.contains("app.billing.requests:2|c\n"), "{metrics}");
+    assert!(metrics.contains("app.search.requests:3|c\n"), "{metrics}");
+    assert!(
+        metrics.contains("app.search.index.size:7|g|#shard:1\n"),
+        "{metrics}"
+    );
+    assert!(
+        metrics.contains("app.billing.latency.count:1|c\n"),
+        "{metrics}"
+    );
+    assert!(metrics.contains("app.requests:1|c\n"), "{metrics}");
+
+    Ok(())
+}
+
+#[test]
+fn test_paused_flushing_accumulates_until_resumed() -> std::io::Result<()> {
+    let writer = TestStatsWriter::new(512);
+    let writer_clone = writer.clone();
+    let options = MetricCollectorOptions {
+        flush_interval: Duration::from_millis(20),
+        flush_jitter: Duration::ZERO,
+        writer_type: StatsWriterType::Custom(Box::new(writer)),
+        ..Default::default()
+    };
+    let bind_addr = "0.0.0.0:0".parse().unwrap();
+    let datadog_addr = random_datadog_addr();
+    let collector =
+        MetricCollector::new(bind_addr, datadog_addr, options, SharedCollector::default())
+            .expect("failed to create collector");
+
+    collector.pause_flushing();
+    assert!(collector.is_flushing_paused());
+    // lets a flush that started before the pause finish
+    std::thread::sleep(Duration::from_millis(50));
+    for _ in 0..3 {
+        collector.count(RylvStr::from_static("paused"), &mut []);
+    }
+    std::thread::sleep(Duration::from_millis(150));
+    assert!(writer_clone.get_all_metrics_as_text().is_empty());
+
+    collector.resume_flushing();
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    while writer_clone.get_all_metrics_as_text().is_empty() {
+        assert!(
+            std::time::Instant::now() < deadline,
+            "no flush after resume"
+        );
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(writer_clone.get_all_metrics_as_text(), "paused:3|c\n");
+
+    Ok(())
+}
+
+/// Forwards to a [`TestStatsWriter`], recording every completed flush.
+struct FlushObservingWriter {
+    inner: TestStatsWriter,
+    completed: Arc<Mutex<Vec<FlushStats>>>,
+}
+
+impl StatsWriterTrait for FlushObservingWriter {
+    fn metric_copied(&self) -> bool {
+        self.inner.metric_copied()
+    }
+
+    fn write(
+        &mut self,
+        metrics: &[&str],
+        tags: &str,
+        value: &str,
+        metric_type: MetricKind,
+    ) -> MetricResult<()> {
+        self.inner.write(metrics, tags, value, metric_type)
+    }
+
+    fn flush(&mut self) -> MetricResult<usize> {
+        self.inner.flush()
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+
+    fn on_flush_complete(&mut self, stats: FlushStats) {
+        self.completed.lock().unwrap().push(stats);
+    }
+}
+
+#[test]
+fn test_on_flush_complete_reports_the_lines_and_bytes_of_the_cycle() {
+    let writer = TestStatsWriter::new(512);
+    let writer_clone = writer.clone();
+    let completed = Arc::new(Mutex::new(Vec::new()));
+    let options = MetricCollectorOptions {
+        flush_interval: Duration::from_secs(60),
+        writer_type: StatsWriterType::Custom(Box::new(FlushObservingWriter {
+            inner: writer,
+            completed: Arc::clone(&completed),
+        })),
+        ..Default::default()
+    };
+    let collector = MetricCollector::new(
+        "0.0.0.0:0".parse().unwrap(),
+        random_datadog_addr(),
+        options,
+        SharedCollector::default(),
+    )
+    .expect("failed to create collector");
+
+    collector.count(RylvStr::from_static("jobs"), &mut []);
+    collector.count(RylvStr::from_static("jobs"), &mut []);
+    collector.count_add(
+        RylvStr::from_static("bytes"),
+        512,
+        &mut [RylvStr::from_static("env:dev")],
+    );
+    collector.gauge(RylvStr::from_static("queue.depth"), 3, &mut []);
+    // the final flush on drop is the only cycle
+    drop(collector);
+
+    let completed = completed.lock().unwrap();
+    assert_eq!(completed.len(), 1);
+    let stats = completed[0];
+    assert_eq!(stats.lines, 3);
+    assert_eq!(
+        stats.bytes_sent,
+        writer_clone.get_all_metrics_as_text().len()
+    );
+    assert_eq!(stats.messages_sent, 0);
+}
@@ -1,6 +1,6 @@
 use rylv_metrics::{
     DrainMetricCollectorTrait, MetricCollector, MetricCollectorOptions, MetricCollectorTrait,
-    RylvStr, SharedCollector, SharedCollectorOptions, StatsWriterType,
+    RylvStr, SharedCollector, SharedCollectorOptions,
 };
 use std::net::UdpSocket;
 use std::time::Duration;
@@ -22,9 +22,8 @@ fn test_collector_survives_unreachable_destination() {
 
     let options = MetricCollectorOptions {
         max_udp_packet_size: 512,
-        max_udp_batch_size: 10,
         flush_interval: Duration::from_millis(50),
-        writer_type: StatsWriterType::Simple,
+        ..Default::default()
     };
 
     let bind_addr = "0.0.0.0:0".parse().unwrap();
@@ -68,9 +67,8 @@ fn test_collector_survives_destination_closed_midstream() {
 
     let options = MetricCollectorOptions {
         max_udp_packet_size: 512,
-        max_udp_batch_size: 10,
         flush_interval: Duration::from_millis(50),
-        writer_type: StatsWriterType::Simple,
+        ..Default::default()
     };
 
     let bind_addr = "0.0.0.0:0".parse().unwrap();
@@ -180,11 +178,9 @@ fn test_graceful_shutdown_flushes_pending_metrics() {
         .unwrap();
 
     let options = MetricCollectorOptions {
-        max_udp_packet_size: 1432,
-        max_udp_batch_size: 10,
         // Long flush interval so the only flush happens on drop
         flush_interval: Duration::from_secs(60),
-        writer_type: StatsWriterType::Simple,
+        ..Default::default()
     };
 
     let bind_addr = "0.0.0.0:0".parse().unwrap();
@@ -0,0 +1,98 @@
+use rylv_metrics::ffi::{
+    rylv_collector_new, rylv_count, rylv_gauge, rylv_histogram, rylv_shutdown,
+    RYLV_ERR_INVALID_ARGUMENT, RYLV_OK,
+};
+use std::collections::HashSet;
+use std::ffi::{c_char, CStr};
+use std::net::UdpSocket;
+use std::ptr;
+use std::time::Duration;
+
+fn receive_all(socket: &UdpSocket) -> HashSet<String> {
+    socket
+        .set_read_timeout(Some(Duration::from_millis(500)))
+        .unwrap();
+    let mut buf = [0; 10000];
+    let mut received = HashSet::new();
+    while let Ok((number_of_bytes, _)) = socket.recv_from(&mut buf) {
+        let text = std::str::from_utf8(&buf[..number_of_bytes]).unwrap();
+        received.extend(text.lines().map(str::to_owned));
+    }
+    received
+}
+
+#[test]
+fn test_ffi_records_and_flushes_on_shutdown() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let dst = format!("{}\0", socket.local_addr().unwrap());
+
+    let handle = unsafe { rylv_collector_new(c"0.0.0.0:0".as_ptr(), dst.as_ptr().cast(), 60_000) };
+    assert!(!handle.is_null());
+
+    let tags: [*const c_char; 2] = [c"env:test".as_ptr(), c"a:b".as_ptr()];
+    unsafe {
+        assert_eq!(
+            rylv_count(handle, c"ffi.count".as_ptr(), 2, tags.as_ptr(), tags.len()),
+            RYLV_OK
+        );
+        assert_eq!(
+            rylv_count(handle, c"ffi.count".as_ptr(), 3, tags.as_ptr(), tags.len()),
+            RYLV_OK
+        );
+        assert_eq!(
+            rylv_gauge(handle, c"ffi.gauge".as_ptr(), 7, ptr::null(), 0),
+            RYLV_OK
+        );
+        assert_eq!(
+            rylv_histogram(handle, c"ffi.histogram".as_ptr(), 10, tags.as_ptr(), 1),
+            RYLV_OK
+        );
+        rylv_shutdown(handle);
+    }
+
+    let received = receive_all(&socket);
+    assert!(received.contains("ffi.count:5|c|#a:b,env:test"));
+    assert!(received.contains("ffi.gauge:7|g"));
+    assert!(received.contains("ffi.histogram.count:1|c|#env:test"));
+}
+
+#[test]
+fn test_ffi_rejects_invalid_arguments() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let dst = format!("{}\0", socket.local_addr().unwrap());
+    let invalid_utf8: &CStr = c"bad\xff";
+
+    unsafe {
+        assert!(rylv_collector_new(ptr::null(), dst.as_ptr().cast(), 10).is_null());
+        assert!(rylv_collector_new(c"not an address".as_ptr(), dst.as_ptr().cast(), 10).is_null());
+
+        assert_eq!(
+            rylv_count(ptr::null(), c"ffi.count".as_ptr(), 1, ptr::null(), 0),
+            RYLV_ERR_INVALID_ARGUMENT
+        );
+
+        let handle = rylv_collector_new(c"0.0.0.0:0".as_ptr(), dst.as_ptr().cast(), 60_000);
+        assert!(!handle.is_null());
+        assert_eq!(
+            rylv_count(handle, ptr::null(), 1, ptr::null(), 0),
+            RYLV_ERR_INVALID_ARGUMENT
+        );
+        assert_eq!(
+            rylv_count(handle, invalid_utf8.as_ptr(), 1, ptr::null(), 0),
+            RYLV_ERR_INVALID_ARGUMENT
+        );
+        let tags: [*const c_char; 2] = [c"env:test".as_ptr(), invalid_utf8.as_ptr()];
+        assert_eq!(
+            rylv_gauge(handle, c"ffi.gauge".as_ptr(), 1, tags.as_ptr(), tags.len()),
+            RYLV_ERR_INVALID_ARGUMENT
+        );
+        assert_eq!(
+            rylv_histogram(handle, c"ffi.histogram".as_ptr(), 1, ptr::null(), 3),
+            RYLV_ERR_INVALID_ARGUMENT
+        );
+        rylv_shutdown(handle);
+        rylv_shutdown(ptr::null_mut());
+    }
+
+    assert!(receive_all(&socket).is_empty());
+}
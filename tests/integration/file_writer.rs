@@ -0,0 +1,54 @@
+use rylv_metrics::file_writer::FileWriter;
+use rylv_metrics::{
+    MetricCollector, MetricCollectorOptions, MetricCollectorTrait, RylvStr, SharedCollector,
+    StatsWriterType, TagSectionStyle,
+};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// Counts each of `names` with its own collector, reopening the file every time.
+fn flush_counts(path: &Path, rotate_at: u64, names: &[&'static str]) {
+    for name in names {
+        let writer = FileWriter::open(path, Some(rotate_at), TagSectionStyle::Omit)
+            .expect("failed to open file");
+        let options = MetricCollectorOptions {
+            flush_interval: Duration::from_secs(60),
+            writer_type: StatsWriterType::Custom(Box::new(writer)),
+            ..Default::default()
+        };
+        let collector = MetricCollector::new(
+            "0.0.0.0:0".parse().unwrap(),
+            "127.0.0.1:8125".parse().unwrap(),
+            options,
+            SharedCollector::default(),
+        )
+        .expect("failed to create collector");
+        collector.count(
+            RylvStr::from_static(name),
+            &mut [RylvStr::from_static("env:test")],
+        );
+        // the final flush on drop writes the line
+        drop(collector);
+    }
+}
+
+#[test]
+fn test_file_writer_rotates_by_size() {
+    let dir = std::env::temp_dir().join(format!("rylv-file-writer-it-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("metrics.log");
+
+    // the `first.*` lines take 44 bytes, so the `second` line starts a new file
+    flush_counts(&path, 50, &["first.a", "first.b", "second"]);
+
+    assert_eq!(
+        fs::read_to_string(dir.join("metrics.log.1")).unwrap(),
+        "first.a:1|c|#env:test\nfirst.b:1|c|#env:test\n"
+    );
+    assert_eq!(fs::read_to_string(&path).unwrap(), "second:1|c|#env:test\n");
+    assert!(!dir.join("metrics.log.2").exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
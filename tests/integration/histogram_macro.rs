@@ -1,6 +1,5 @@
 use rylv_metrics::{
     histogram, MetricCollector, MetricCollectorOptions, MetricCollectorTrait, SharedCollector,
-    StatsWriterType,
 };
 use std::time::Duration;
 
@@ -14,7 +13,7 @@ fn create_test_collector() -> MetricCollector<SharedCollector> {
         max_udp_packet_size: 1500,
         max_udp_batch_size: 100,
         flush_interval: Duration::from_millis(100),
-        writer_type: StatsWriterType::Simple,
+        ..Default::default()
     };
 
     let bind_addr = "0.0.0.0:0".parse().unwrap();
@@ -0,0 +1,175 @@
+use rylv_metrics::{
+    count, count_add, gauge, histogram, DrainMetricCollectorTrait, HistogramConfig,
+    MetricCollectorTrait, RylvStr, SharedCollector, SharedCollectorOptions,
+};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::BuildHasherDefault;
+
+/// Counts the allocations of the current thread, so tests running in parallel
+/// do not disturb each other.
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+type FixedHasher = BuildHasherDefault<DefaultHasher>;
+
+/// Allocations made by `record` when it inserts new series into a warmed-up collector.
+///
+/// A fixed hasher puts the series in the same shards on every call.
+fn allocations_of_new_series(record: impl Fn(&SharedCollector<FixedHasher>)) -> u64 {
+    let collector = SharedCollector::new(SharedCollectorOptions {
+        stats_prefix: String::new(),
+        prefix_separator: None,
+        histogram_prefix: String::new(),
+        count_prefix: String::new(),
+        gauge_prefix: String::new(),
+        histogram_configs: HashMap::with_hasher(FixedHasher::default()),
+        default_histogram_config: HistogramConfig::default(),
+        hasher_builder: FixedHasher::default(),
+        prewarm_histograms: 0,
+        max_pooled_histograms_per_sigfig: None,
+        max_pending_aggregators: 1,
+        normalize_names: false,
+        metric_filter: None,
+        keep_zero_values: false,
+        key_idle_ttl: None,
+        skip_tag_sort: false,
+        gauge_mode: rylv_metrics::GaugeMode::Average,
+        value_rounding: None,
+        gauge_min_update_intervals: HashMap::with_hasher(FixedHasher::default()),
+    });
+    collector.count(RylvStr::from_static("warm.up"), &mut []);
+    collector.gauge(RylvStr::from_static("warm.up"), 1, &mut []);
+    collector.histogram(RylvStr::from_static("warm.up"), 1, &mut []);
+
+    let before = ALLOCATIONS.with(Cell::get);
+    record(&collector);
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+#[test]
+fn test_macros_with_literals_allocate_like_the_static_api() {
+    let direct = allocations_of_new_series(|collector| {
+        collector.count_add(
+            RylvStr::from_static("requests"),
+            2,
+            &mut [
+                RylvStr::from_static("env:prod"),
+                RylvStr::from_static("route:/"),
+            ],
+        );
+        collector.gauge(
+            RylvStr::from_static("connections"),
+            5,
+            &mut [RylvStr::from_static("pool:main")],
+        );
+        collector.histogram(
+            RylvStr::from_static("latency"),
+            7,
+            &mut [RylvStr::from_static("env:prod")],
+        );
+    });
+    let macros = allocations_of_new_series(|collector| {
+        count_add!(collector, "requests", 2, "env:prod", "route:/");
+        gauge!(collector, "connections", 5, "pool:main");
+        histogram!(collector, "latency", 7, "env:prod");
+    });
+    let borrowed = allocations_of_new_series(|collector| {
+        let (requests, env, route) = ("requests", "env:prod", "route:/");
+        collector.count_add(
+            RylvStr::from(requests),
+            2,
+            &mut [RylvStr::from(env), RylvStr::from(route)],
+        );
+        collector.gauge(
+            RylvStr::from("connections"),
+            5,
+            &mut [RylvStr::from("pool:main")],
+        );
+        collector.histogram(
+            RylvStr::from("latency"),
+            7,
+            &mut [RylvStr::from("env:prod")],
+        );
+    });
+
+    assert_eq!(macros, direct);
+    assert!(macros < borrowed, "macros: {macros}, borrowed: {borrowed}");
+}
+
+#[test]
+fn test_count_multi_on_existing_series_does_not_allocate() {
+    let region = [
+        RylvStr::from_static("region:eu"),
+        RylvStr::from_static("az:1"),
+    ];
+    let az_only = [RylvStr::from_static("az:1")];
+    let record = |collector: &SharedCollector<FixedHasher>| {
+        collector.count_multi(
+            RylvStr::from_static("requests"),
+            1,
+            &[&region, &az_only, &[]],
+        );
+    };
+
+    let allocations = allocations_of_new_series(|collector| {
+        record(collector);
+        let before = ALLOCATIONS.with(Cell::get);
+        record(collector);
+        assert_eq!(ALLOCATIONS.with(Cell::get) - before, 0);
+    });
+    assert!(allocations > 0);
+}
+
+#[test]
+fn test_macros_mix_literal_and_expression_tags() {
+    let collector = SharedCollector::default();
+    let route = String::from("route:/");
+    let status = 200;
+
+    count!(
+        collector,
+        "requests",
+        "env:prod",
+        route.as_str(),
+        format!("status:{status}")
+    );
+    count!(collector, format!("{}.errors", "api"), "env:prod");
+    count_add!(collector, "bytes", 10);
+    gauge!(collector, "load", 3, route.clone(), "pool:main",);
+
+    let mut lines = collector
+        .try_begin_drain()
+        .expect("drain")
+        .map(|frame| format!("{}:{}|{}", frame.metric, frame.value, frame.tags))
+        .collect::<Vec<_>>();
+    lines.sort();
+    assert_eq!(
+        lines,
+        vec![
+            "api.errors:1|env:prod",
+            "bytes:10|",
+            "load:3|pool:main,route:/",
+            "requests:1|env:prod,route:/,status:200",
+        ]
+    );
+}
@@ -1,9 +1,17 @@
+#[cfg(all(feature = "admin-http", feature = "shared-collector"))]
+mod admin_http;
 #[cfg(all(feature = "udp", feature = "custom_writer"))]
 mod custom_writer;
 #[cfg(all(feature = "udp", feature = "shared-collector"))]
 mod failure_scenarios;
+#[cfg(feature = "ffi")]
+mod ffi;
+#[cfg(all(feature = "file-writer", feature = "shared-collector"))]
+mod file_writer;
 #[cfg(feature = "udp")]
 mod histogram_macro;
+#[cfg(any(feature = "shared-collector", feature = "simple-map"))]
+mod macro_allocations;
 #[cfg(feature = "udp")]
 mod parallel_stress;
 #[cfg(all(feature = "udp", feature = "custom_writer"))]
@@ -12,3 +20,5 @@ mod shared_collector;
 mod sync_collector;
 #[cfg(feature = "tls-collector")]
 mod tls_hashbrown;
+#[cfg(all(feature = "tracing-layer", feature = "shared-collector"))]
+mod tracing_layer;
@@ -1,8 +1,9 @@
 use rylv_metrics::{
-    DrainMetricCollectorTrait, HistogramConfig, MetricCollector, MetricCollectorOptions,
-    MetricCollectorTrait, RylvStr, SharedCollector, SharedCollectorOptions, SigFig,
-    StatsWriterType,
+    DrainMetricCollectorTrait, HistogramBaseMetric, HistogramConfig, HistogramSumKind, Metric,
+    MetricCollector, MetricCollectorOptions, MetricCollectorTrait, MetricFilter, NumericTag,
+    RylvStr, SharedCollector, SharedCollectorOptions, SigFig, StatsWriterType,
 };
+use std::num::NonZeroU64;
 use std::time::Duration;
 
 use super::custom_writer::TestStatsWriter;
@@ -29,6 +30,9 @@ where
                 let metric_type = match frame.kind {
                     rylv_metrics::MetricKind::Count => "c",
                     rylv_metrics::MetricKind::Gauge => "g",
+                    rylv_metrics::MetricKind::Timing => "ms",
+                    rylv_metrics::MetricKind::Distribution => "d",
+                    kind => unreachable!("unexpected {kind:?} frame"),
                 };
                 if frame.tags.is_empty() {
                     lines.push(format!("{metric}:{}|{metric_type}\n", frame.value));
@@ -109,6 +113,93 @@ fn test_shared_drain_basic() {
     assert!(drained_again.is_empty());
 }
 
+#[test]
+fn test_shared_timed_count_records_histogram_and_counter() {
+    let options = SharedCollectorOptions {
+        stats_prefix: "app.".to_string(),
+        ..Default::default()
+    };
+    let collector = SharedCollector::new(options);
+
+    collector.timed_count(
+        RylvStr::from_static("http"),
+        42,
+        &mut [
+            RylvStr::from_static("route:/"),
+            RylvStr::from_static("method:get"),
+        ],
+    );
+    // The series share their keys with the single-metric calls
+    collector.count(
+        RylvStr::from_static("http.count"),
+        &mut [
+            RylvStr::from_static("method:get"),
+            RylvStr::from_static("route:/"),
+        ],
+    );
+
+    let joined = drain_metrics_now(&collector).concat();
+
+    assert!(
+        joined.contains(
+            "app.http.count:2|c|#method:get,route:/
+"
+        ),
+        "{joined}"
+    );
+    assert!(
+        joined.contains(
+            "app.http.latency.count:1|c|#method:get,route:/
+"
+        ),
+        "{joined}"
+    );
+    assert!(
+        joined.contains(
+            "app.http.latency.min:42|g|#method:get,route:/
+"
+        ),
+        "{joined}"
+    );
+    assert!(
+        joined.contains(
+            "app.http.latency.max:42|g|#method:get,route:/
+"
+        ),
+        "{joined}"
+    );
+    assert!(
+        joined.contains(
+            "app.http.latency.99percentile:42|g|#method:get,route:/
+"
+        ),
+        "{joined}"
+    );
+}
+
+#[test]
+fn test_shared_value_rounding_buckets_histogram_and_gauge_values() {
+    let options = SharedCollectorOptions {
+        value_rounding: NonZeroU64::new(10),
+        ..Default::default()
+    };
+    let collector = SharedCollector::new(options);
+
+    for value in [73, 75] {
+        collector.histogram(RylvStr::from_static("latency"), value, &mut []);
+        collector.gauge(RylvStr::from_static("temperature"), value, &mut []);
+    }
+    collector.count_add(RylvStr::from_static("requests"), 73, &mut []);
+
+    let joined = drain_metrics_now(&collector).concat();
+
+    assert!(joined.contains("latency.count:2|c\n"), "{joined}");
+    assert!(joined.contains("latency.min:70|g\n"), "{joined}");
+    assert!(joined.contains("latency.max:70|g\n"), "{joined}");
+    assert!(joined.contains("temperature:70|g\n"), "{joined}");
+    assert!(joined.contains("requests:73|c\n"), "{joined}");
+}
+
 #[test]
 fn test_shared_custom_histogram_config() {
     let options = SharedCollectorOptions {
@@ -136,6 +227,253 @@ fn test_shared_custom_histogram_config() {
     assert!(lines.contains("custom.hist.max:100|g|#scope:test\n"));
 }
 
+#[test]
+fn test_shared_histogram_sum_kind() {
+    let mut histogram_configs = std::collections::HashMap::new();
+    histogram_configs.insert(
+        "latency.gauge".to_string(),
+        HistogramConfig::new(SigFig::default(), vec![])
+            .unwrap()
+            .with_base_metrics([])
+            .with_sum(true),
+    );
+    let options = SharedCollectorOptions {
+        default_histogram_config: HistogramConfig::new(SigFig::default(), vec![])
+            .unwrap()
+            .with_base_metrics([])
+            .with_sum(true)
+            .with_sum_kind(HistogramSumKind::Count),
+        histogram_configs,
+        ..Default::default()
+    };
+
+    let collector = SharedCollector::new(options);
+    for value in [100, 250, 400] {
+        collector.histogram(RylvStr::from_static("latency"), value, &mut []);
+        collector.histogram(RylvStr::from_static("latency.gauge"), value, &mut []);
+    }
+
+    let mut lines = drain_metrics_now(&collector);
+    lines.sort_unstable();
+    assert_eq!(
+        lines,
+        vec![
+            "latency.gauge.sum:750|g\n".to_string(),
+            "latency.sum:750|c\n".to_string(),
+        ]
+    );
+
+    // the sum is reset with the rest of the histogram
+    collector.histogram(RylvStr::from_static("latency"), 5, &mut []);
+    assert_eq!(drain_metrics_now(&collector), vec!["latency.sum:5|c\n"]);
+}
+
+/// Records one count per metric through `filter`, returning the drained lines and
+/// how many recordings were filtered.
+fn record_filtered(filter: MetricFilter) -> (Vec<String>, u64) {
+    let collector = SharedCollector::new(SharedCollectorOptions {
+        stats_prefix: "svc.".to_string(),
+        metric_filter: Some(filter),
+        ..Default::default()
+    });
+    for metric in ["app.requests", "app.debug.trace", "debug.cache", "other"] {
+        collector.count(RylvStr::from_static(metric), &mut []);
+    }
+    let sorted = collector.prepare_sorted_tags([]);
+    collector.count_add_sorted(RylvStr::from_static("debug.sorted"), 1, &sorted);
+    let prepared = collector.prepare_metric(RylvStr::from_static("debug.prepared"), sorted);
+    collector.count_add_prepared(&prepared, 1);
+
+    let mut lines = drain_metrics_now(&collector);
+    lines.sort_unstable();
+    (lines, collector.filtered_count())
+}
+
+#[test]
+fn test_shared_metric_filter_allow_only() {
+    let (lines, filtered) = record_filtered(MetricFilter::default().with_allow_prefix("app."));
+    assert_eq!(
+        lines,
+        vec!["svc.app.debug.trace:1|c\n", "svc.app.requests:1|c\n"]
+    );
+    assert_eq!(filtered, 4);
+}
+
+#[test]
+fn test_shared_metric_filter_deny_only() {
+    let (lines, filtered) = record_filtered(MetricFilter::default().with_deny_prefix("debug."));
+    assert_eq!(
+        lines,
+        vec![
+            "svc.app.debug.trace:1|c\n",
+            "svc.app.requests:1|c\n",
+            "svc.other:1|c\n"
+        ]
+    );
+    assert_eq!(filtered, 3);
+}
+
+#[test]
+fn test_shared_metric_filter_allow_and_deny() {
+    let (lines, filtered) = record_filtered(
+        MetricFilter::default()
+            .with_allow_prefix("app.")
+            .with_allow_prefix("debug.")
+            .with_deny_prefix("app.debug.")
+            .with_deny_prefix("debug.prepared"),
+    );
+    assert_eq!(
+        lines,
+        vec![
+            "svc.app.requests:1|c\n",
+            "svc.debug.cache:1|c\n",
+            "svc.debug.sorted:1|c\n",
+        ]
+    );
+    assert_eq!(filtered, 3);
+}
+
+#[test]
+fn test_shared_metric_filter_matches_prepared_names_before_normalization() {
+    let collector = SharedCollector::new(SharedCollectorOptions {
+        normalize_names: true,
+        metric_filter: Some(MetricFilter::default().with_deny_prefix("Debug.")),
+        ..Default::default()
+    });
+    collector.count(RylvStr::from_static("Debug.Cache"), &mut []);
+    let sorted = collector.prepare_sorted_tags([]);
+    let prepared = collector.prepare_metric(RylvStr::from_static("Debug.Prepared"), sorted);
+    collector.count_add_prepared(&prepared, 1);
+    collector.count_add_prepared(&prepared, 1);
+
+    assert!(drain_metrics_now(&collector).is_empty());
+    assert_eq!(collector.filtered_count(), 3);
+}
+
+#[test]
+fn test_shared_numeric_tag_matches_formatted_tag() {
+    let record = |numeric: bool| {
+        let collector = SharedCollector::new(SharedCollectorOptions::default());
+        for (code, shard) in [(200, 14), (404, 3), (200, 14)] {
+            if numeric {
+                let status = NumericTag::new("status", code);
+                let shard = NumericTag::new("shard", shard);
+                collector.count(
+                    RylvStr::from_static("requests"),
+                    &mut [status.as_rylv_str(), (&shard).into()],
+                );
+            } else {
+                collector.count(
+                    RylvStr::from_static("requests"),
+                    &mut [
+                        RylvStr::from(format!("status:{code}")),
+                        RylvStr::from(format!("shard:{shard}")),
+                    ],
+                );
+            }
+        }
+        let mut lines = drain_metrics_now(&collector);
+        lines.sort_unstable();
+        lines
+    };
+
+    let numeric = record(true);
+    assert_eq!(
+        numeric,
+        vec![
+            "requests:1|c|#shard:3,status:404\n",
+            "requests:2|c|#shard:14,status:200\n",
+        ]
+    );
+    assert_eq!(numeric, record(false));
+}
+
+#[test]
+fn test_shared_keep_zero_values_emits_idle_series() {
+    let record = |keep_zero_values: bool| {
+        let collector = SharedCollector::new(SharedCollectorOptions {
+            keep_zero_values,
+            ..Default::default()
+        });
+        collector.gauge(
+            RylvStr::from_static("errors"),
+            0,
+            &mut [RylvStr::from_static("svc:api")],
+        );
+        collector.count_add(RylvStr::from_static("retries"), 2, &mut []);
+        // three drains cover both aggregator generations
+        (0..3)
+            .map(|_| {
+                let mut lines = drain_metrics_now(&collector);
+                lines.sort_unstable();
+                lines
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let drains = record(true);
+    assert_eq!(drains[0], vec!["errors:0|g|#svc:api\n", "retries:2|c\n"]);
+    for idle in &drains[1..] {
+        assert_eq!(idle, &vec!["errors:0|g|#svc:api\n", "retries:0|c\n"]);
+    }
+
+    let drains = record(false);
+    assert_eq!(drains[0], vec!["errors:0|g|#svc:api\n", "retries:2|c\n"]);
+    assert!(drains[1..].iter().all(Vec::is_empty));
+}
+
+#[test]
+fn test_shared_key_idle_ttl_evicts_idle_kept_series() {
+    let collector = SharedCollector::new(SharedCollectorOptions {
+        keep_zero_values: true,
+        key_idle_ttl: Some(Duration::from_millis(100)),
+        ..Default::default()
+    });
+    let drain = || {
+        collector.count(RylvStr::from_static("live"), &mut []);
+        let mut lines = drain_metrics_now(&collector);
+        lines.sort_unstable();
+        lines
+    };
+    collector.count_add(RylvStr::from_static("retries"), 2, &mut []);
+    collector.gauge(RylvStr::from_static("queue"), 5, &mut []);
+
+    assert_eq!(
+        drain(),
+        vec![
+            "live:1|c
+",
+            "queue:5|g
+",
+            "retries:2|c
+"
+        ]
+    );
+    assert_eq!(
+        drain(),
+        vec![
+            "live:1|c
+",
+            "queue:0|g
+",
+            "retries:0|c
+"
+        ]
+    );
+
+    std::thread::sleep(Duration::from_millis(150));
+    // three drains cover both aggregator generations
+    for _ in 0..3 {
+        assert_eq!(
+            drain(),
+            vec![
+                "live:1|c
+"
+            ]
+        );
+    }
+}
+
 #[test]
 fn test_shared_drain_frames_borrowed_output() {
     let options = SharedCollectorOptions::default();
@@ -192,6 +530,7 @@ fn test_shared_parity_with_metric_collector_custom_writer() {
         max_udp_batch_size: 100,
         flush_interval: Duration::from_millis(50),
         writer_type: StatsWriterType::Custom(Box::new(writer)),
+        ..Default::default()
     };
 
     let bind_addr = "0.0.0.0:0".parse().unwrap();
@@ -272,3 +611,223 @@ fn test_shared_parity_with_metric_collector_custom_writer() {
 
     assert_eq!(with_job, shared_sorted);
 }
+
+#[test]
+fn test_shared_distribution_histogram_sends_one_packed_line() {
+    let writer = TestStatsWriter::new(512);
+    let writer_clone = writer.clone();
+    let options = MetricCollectorOptions {
+        max_udp_packet_size: 512,
+        flush_interval: Duration::from_millis(50),
+        writer_type: StatsWriterType::Custom(Box::new(writer)),
+        ..Default::default()
+    };
+    let mut histogram_configs = std::collections::HashMap::new();
+    histogram_configs.insert(
+        "latency".to_string(),
+        HistogramConfig::latency_millis()
+            .with_distribution(true)
+            .with_unit("ms"),
+    );
+    let inner = SharedCollector::new(SharedCollectorOptions {
+        stats_prefix: "app.".to_string(),
+        histogram_configs,
+        ..Default::default()
+    });
+    let collector = MetricCollector::new(
+        "0.0.0.0:0".parse().unwrap(),
+        random_datadog_addr(),
+        options,
+        inner,
+    )
+    .expect("failed to create collector");
+
+    for value in [30, 10, 30] {
+        collector.histogram(
+            RylvStr::from_static("latency"),
+            value,
+            &mut [RylvStr::from_static("route:/")],
+        );
+    }
+    collector.histogram(
+        RylvStr::from_static("latency"),
+        20,
+        &mut [RylvStr::from_static("route:/health")],
+    );
+    collector.histogram(RylvStr::from_static("size"), 5, &mut []);
+    drop(collector);
+
+    let lines = sorted_lines(&writer_clone.get_all_metrics_as_text());
+    let distributions = lines
+        .iter()
+        .filter(|line| line.contains("|d"))
+        .cloned()
+        .collect::<Vec<_>>();
+    assert_eq!(
+        distributions,
+        vec![
+            "app.latency:10:30:30|d|#route:/,unit:ms\n".to_string(),
+            "app.latency:20|d|#route:/health,unit:ms\n".to_string(),
+        ]
+    );
+    assert!(lines.iter().all(|line| !line.starts_with("app.latency.")));
+    assert!(lines
+        .iter()
+        .any(|line| line.starts_with("app.size.count:1|c")));
+}
+
+#[test]
+fn test_shared_record_dispatches_every_metric_kind() {
+    let writer = TestStatsWriter::new(512);
+    let writer_clone = writer.clone();
+    let options = MetricCollectorOptions {
+        flush_interval: Duration::from_millis(50),
+        writer_type: StatsWriterType::Custom(Box::new(writer)),
+        ..Default::default()
+    };
+    let mut histogram_configs = std::collections::HashMap::new();
+    histogram_configs.insert(
+        "payload".to_string(),
+        HistogramConfig::sizes_bytes().with_distribution(true),
+    );
+    let inner = SharedCollector::new(SharedCollectorOptions {
+        default_histogram_config: HistogramConfig::new(SigFig::default(), vec![])
+            .unwrap()
+            .with_base_metrics([HistogramBaseMetric::Max]),
+        histogram_configs,
+        ..Default::default()
+    });
+    let collector = MetricCollector::new(
+        "0.0.0.0:0".parse().unwrap(),
+        random_datadog_addr(),
+        options,
+        inner,
+    )
+    .expect("failed to create collector");
+
+    let tags = || {
+        vec![
+            RylvStr::from_static("source:dsl"),
+            RylvStr::from(String::from("env:dev")),
+        ]
+    };
+    collector.record(Metric::Count {
+        name: RylvStr::from_static("jobs"),
+        value: 3,
+        tags: tags(),
+    });
+    collector.record(Metric::Gauge {
+        name: RylvStr::from(String::from("queue.depth")),
+        value: 12,
+        tags: tags(),
+    });
+    collector.record(Metric::Histogram {
+        name: RylvStr::from_static("latency"),
+        value: 40,
+        tags: tags(),
+    });
+    collector.record(Metric::Distribution {
+        name: RylvStr::from_static("payload"),
+        value: 512,
+        tags: tags(),
+    });
+    drop(collector);
+
+    assert_eq!(
+        sorted_lines(&writer_clone.get_all_metrics_as_text()),
+        vec![
+            "jobs:3|c|#env:dev,source:dsl\n".to_string(),
+            "latency.max:40|g|#env:dev,source:dsl\n".to_string(),
+            "payload:512|d|#env:dev,source:dsl\n".to_string(),
+            "queue.depth:12|g|#env:dev,source:dsl\n".to_string(),
+        ]
+    );
+}
+
+#[test]
+fn test_shared_snapshot_copies_pending_metrics_without_resetting_them() {
+    let writer = TestStatsWriter::new(512);
+    let writer_clone = writer.clone();
+    let options = MetricCollectorOptions {
+        flush_interval: Duration::from_secs(60),
+        writer_type: StatsWriterType::Custom(Box::new(writer)),
+        ..Default::default()
+    };
+    let inner = SharedCollector::new(SharedCollectorOptions {
+        stats_prefix: "app.".to_string(),
+        default_histogram_config: HistogramConfig::new(SigFig::default(), vec![0.5])
+            .unwrap()
+            .with_base_metrics([HistogramBaseMetric::Count]),
+        ..Default::default()
+    });
+    let collector = MetricCollector::new(
+        "0.0.0.0:0".parse().unwrap(),
+        random_datadog_addr(),
+        options,
+        inner,
+    )
+    .expect("failed to create collector");
+
+    let mut tags = [RylvStr::from_static("env:dev"), RylvStr::from_static("a:1")];
+    collector.count_add("jobs", 2, &mut tags);
+    collector.count_add("jobs", 3, &mut tags);
+    collector.gauge("queue.depth", 10, &mut []);
+    collector.gauge("queue.depth", 20, &mut []);
+    for value in [10, 20, 30] {
+        collector.histogram("latency", value, &mut []);
+    }
+
+    let snapshot = collector.snapshot();
+    assert_eq!(snapshot.counts.len(), 1);
+    assert_eq!(snapshot.count("jobs", "a:1,env:dev"), Some(5));
+    assert_eq!(snapshot.gauge("queue.depth", ""), Some(15));
+    let latency = snapshot.histogram("latency", "").expect("latency snapshot");
+    assert_eq!(
+        (latency.count, latency.min, latency.max, latency.sum),
+        (3, 10, 30, 60)
+    );
+    assert_eq!(latency.percentiles, vec![(0.5, 20)]);
+
+    drop(collector);
+    assert_eq!(
+        sorted_lines(&writer_clone.get_all_metrics_as_text()),
+        vec![
+            "app.jobs:5|c|#a:1,env:dev\n".to_string(),
+            "app.latency.50percentile:20|g\n".to_string(),
+            "app.latency.count:3|c\n".to_string(),
+            "app.queue.depth:15|g\n".to_string(),
+        ]
+    );
+}
+
+#[test]
+fn test_shared_prehashed_recordings_merge_with_sorted_ones() {
+    let collector = SharedCollector::new(SharedCollectorOptions {
+        default_histogram_config: HistogramConfig::new(SigFig::default(), vec![])
+            .unwrap()
+            .with_base_metrics([HistogramBaseMetric::Count]),
+        ..Default::default()
+    });
+    let tags = collector
+        .prepare_sorted_tags([RylvStr::from_static("env:dev"), RylvStr::from_static("a:1")]);
+
+    let jobs = collector.prehash("jobs", &tags);
+    collector.count_add_sorted(RylvStr::from_static("jobs"), 2, &tags);
+    collector.count_add_prehashed(jobs, "jobs", 3, &tags);
+    let depth = collector.prehash("queue.depth", &tags);
+    collector.gauge_prehashed(depth, "queue.depth", 12, &tags);
+    let latency = collector.prehash("latency", &tags);
+    collector.histogram_sorted(RylvStr::from_static("latency"), 10, &tags);
+    collector.histogram_prehashed(latency, "latency", 20, &tags);
+
+    let mut lines = drain_metrics_now(&collector);
+    lines.sort_unstable();
+    assert_eq!(
+        lines,
+        vec![
+            "jobs:5|c|#a:1,env:dev\n",
+            "latency.count:2|c|#a:1,env:dev\n",
+            "queue.depth:12|g|#a:1,env:dev\n",
+        ]
+    );
+}
@@ -1,8 +1,9 @@
 use rylv_metrics::{
-    MetricCollector, MetricCollectorOptions, MetricCollectorTrait, RylvStr, SharedCollector,
-    SharedCollectorOptions, StatsWriterType, DEFAULT_STATS_WRITER_TYPE,
+    HistogramConfig, MetricCollector, MetricCollectorOptions, MetricCollectorTrait, MetricsError,
+    RylvStr, SharedCollector, SharedCollectorOptions, SocketOptions, StatsWriterKind,
+    StatsWriterType, WriterPreference, DEFAULT_STATS_WRITER_TYPE,
 };
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::net::UdpSocket;
 use std::thread::JoinHandle;
 use std::time::Duration;
@@ -68,6 +69,7 @@ fn create_collector(
         max_udp_batch_size: 100,
         flush_interval: Duration::from_millis(100),
         writer_type,
+        ..Default::default()
     };
 
     let bind_addr = "0.0.0.0:0".parse().unwrap();
@@ -147,9 +149,8 @@ fn test_collector_new_fails_when_bind_addr_is_in_use() {
 
     let options = MetricCollectorOptions {
         max_udp_packet_size: 512,
-        max_udp_batch_size: 10,
         flush_interval: Duration::from_millis(100),
-        writer_type: StatsWriterType::Simple,
+        ..Default::default()
     };
 
     let collector =
@@ -161,6 +162,434 @@ fn test_collector_new_fails_when_bind_addr_is_in_use() {
     );
 }
 
+#[test]
+fn test_unconnected_socket_delivers_metrics() {
+    let mut writer_types = vec![StatsWriterType::Simple];
+    #[cfg(target_os = "linux")]
+    writer_types.push(StatsWriterType::LinuxBatch);
+
+    for writer_type in writer_types {
+        let (socket, port) = ephemeral_socket();
+        let receiver = spawn_udp_receiver_exact(socket, 1);
+
+        let options = MetricCollectorOptions {
+            max_udp_packet_size: 512,
+            flush_interval: Duration::from_millis(100),
+            writer_type,
+            connect_socket: false,
+            ..Default::default()
+        };
+        let bind_addr = "0.0.0.0:0".parse().unwrap();
+        let datadog_addr = format!("127.0.0.1:{port}").parse().unwrap();
+        let collector =
+            MetricCollector::new(bind_addr, datadog_addr, options, SharedCollector::default())
+                .expect("failed to create collector");
+        collector.gauge(
+            RylvStr::from_static("test.unconnected"),
+            7,
+            &mut [RylvStr::from_static("env:test")],
+        );
+
+        let received = wait_and_collect_exact(receiver);
+        assert_eq!(
+            received,
+            HashSet::from(["test.unconnected:7|g|#env:test\n".to_owned()])
+        );
+    }
+}
+
+#[test]
+fn test_writer_threads_deliver_every_line() {
+    const GAUGES: usize = 200;
+
+    let mut writer_types = vec![StatsWriterType::Simple];
+    #[cfg(target_os = "linux")]
+    writer_types.push(StatsWriterType::LinuxBatch);
+
+    for writer_type in writer_types {
+        let (socket, port) = ephemeral_socket();
+        let receiver = spawn_udp_receiver(socket);
+
+        let options = MetricCollectorOptions {
+            max_udp_packet_size: 64,
+            flush_interval: Duration::from_secs(60),
+            writer_type,
+            writer_threads: 3,
+            ..Default::default()
+        };
+        let bind_addr = "0.0.0.0:0".parse().unwrap();
+        let datadog_addr = format!("127.0.0.1:{port}").parse().unwrap();
+        let collector =
+            MetricCollector::new(bind_addr, datadog_addr, options, SharedCollector::default())
+                .expect("failed to create collector");
+        for i in 0..GAUGES {
+            collector.gauge(
+                RylvStr::from(format!("test.threads.g{i}").as_str()),
+                1,
+                &mut [],
+            );
+        }
+        let report = collector.try_shutdown().expect("shutdown failed");
+        assert!(report.flushed_cleanly, "{report:?}");
+
+        let packets = receiver.join().unwrap();
+        assert!(packets.iter().all(|packet| packet.len() <= 64));
+        let lines: HashSet<String> = packets
+            .iter()
+            .flat_map(|packet| packet.lines().map(str::to_owned))
+            .collect();
+        let expected: HashSet<String> = (0..GAUGES)
+            .map(|i| format!("test.threads.g{i}:1|g"))
+            .collect();
+        assert_eq!(lines, expected);
+    }
+}
+
+#[cfg(feature = "socket2")]
+#[test]
+fn test_socket_options_collector_delivers_metrics() {
+    let (socket, port) = ephemeral_socket();
+    let receiver = spawn_udp_receiver_exact(socket, 1);
+
+    let options = MetricCollectorOptions {
+        flush_interval: Duration::from_millis(100),
+        socket_options: SocketOptions {
+            send_buffer_size: Some(256 * 1024),
+            reuse_address: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let bind_addr = "0.0.0.0:0".parse().unwrap();
+    let datadog_addr = format!("127.0.0.1:{port}").parse().unwrap();
+    let collector =
+        MetricCollector::new(bind_addr, datadog_addr, options, SharedCollector::default())
+            .expect("failed to create collector");
+    collector.count(RylvStr::from_static("test.socket2"), &mut []);
+
+    let received = wait_and_collect_exact(receiver);
+    assert_eq!(received, HashSet::from(["test.socket2:1|c\n".to_owned()]));
+}
+
+#[test]
+fn test_collector_from_socket_sends_through_the_supplied_socket() {
+    let (receiver_socket, port) = ephemeral_socket();
+    let receiver = spawn_udp_receiver_exact(receiver_socket, 1);
+
+    let (send_socket, send_port) = ephemeral_socket();
+    let options = MetricCollectorOptions {
+        flush_interval: Duration::from_millis(100),
+        ..Default::default()
+    };
+    let datadog_addr = format!("127.0.0.1:{port}").parse().unwrap();
+    let collector = MetricCollector::from_socket(
+        send_socket,
+        datadog_addr,
+        options,
+        SharedCollector::default(),
+    )
+    .expect("failed to create collector");
+    collector.count(RylvStr::from_static("test.from_socket"), &mut []);
+
+    let received = wait_and_collect_exact(receiver);
+    assert_eq!(
+        received,
+        HashSet::from(["test.from_socket:1|c\n".to_owned()])
+    );
+    drop(collector);
+    // the collector owned the socket and closed it, so its port is free again
+    UdpSocket::bind(format!("127.0.0.1:{send_port}")).expect("socket was not closed");
+}
+
+#[test]
+fn test_collector_from_socket_rejects_socket_options() {
+    let (socket, _) = ephemeral_socket();
+    let options = MetricCollectorOptions {
+        socket_options: SocketOptions {
+            reuse_address: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let datadog_addr = "127.0.0.1:8125".parse().unwrap();
+    let result =
+        MetricCollector::from_socket(socket, datadog_addr, options, SharedCollector::default());
+    assert!(result.is_err());
+}
+
+#[cfg(all(unix, feature = "raw-fd"))]
+#[test]
+fn test_collector_from_raw_fd_sends_metrics() {
+    use std::os::fd::IntoRawFd;
+
+    let (receiver_socket, port) = ephemeral_socket();
+    let receiver = spawn_udp_receiver_exact(receiver_socket, 1);
+
+    let (send_socket, _) = ephemeral_socket();
+    let fd = send_socket.into_raw_fd();
+    let options = MetricCollectorOptions {
+        flush_interval: Duration::from_millis(100),
+        ..Default::default()
+    };
+    let datadog_addr = format!("127.0.0.1:{port}").parse().unwrap();
+    // SAFETY: the descriptor was just released by `into_raw_fd` and is used nowhere else.
+    let collector = unsafe {
+        MetricCollector::from_raw_fd(fd, datadog_addr, options, SharedCollector::default())
+    }
+    .expect("failed to create collector");
+    collector.gauge(RylvStr::from_static("test.raw_fd"), 5, &mut []);
+
+    let received = wait_and_collect_exact(receiver);
+    assert_eq!(received, HashSet::from(["test.raw_fd:5|g\n".to_owned()]));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_collector_new_reports_invalid_socket_options() {
+    let options = MetricCollectorOptions {
+        socket_options: SocketOptions {
+            bind_device: Some("rylv-missing0".to_string()),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let bind_addr = "0.0.0.0:0".parse().unwrap();
+    let datadog_addr = "127.0.0.1:8125".parse().unwrap();
+    let result = MetricCollector::new(bind_addr, datadog_addr, options, SharedCollector::default());
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_collector_from_env_sends_entity_tag() {
+    // The only test in this binary touching these variables, so they cannot race.
+    let (socket, port) = ephemeral_socket();
+    let receiver = spawn_udp_receiver_exact(socket, 1);
+    std::env::set_var("DD_AGENT_HOST", "127.0.0.1");
+    std::env::set_var("DD_DOGSTATSD_PORT", port.to_string());
+    std::env::set_var("DD_ENTITY_ID", "pod-1234");
+
+    let collector = MetricCollector::<SharedCollector>::from_env();
+    for var in ["DD_AGENT_HOST", "DD_DOGSTATSD_PORT", "DD_ENTITY_ID"] {
+        std::env::remove_var(var);
+    }
+    let collector = collector.expect("failed to create collector");
+    collector.count(
+        RylvStr::from_static("test.env"),
+        &mut [RylvStr::from_static("env:test")],
+    );
+    drop(collector);
+
+    let received = wait_and_collect_exact(receiver);
+    assert_eq!(
+        received,
+        HashSet::from(["test.env:1|c|#env:test,dd.internal.entity_id:pod-1234\n".to_owned()])
+    );
+}
+
+#[test]
+fn test_histogram_unit_tag_is_appended_after_user_tags() {
+    let (socket, port) = ephemeral_socket();
+    let receiver = spawn_udp_receiver(socket);
+    let mut histogram_configs = HashMap::new();
+    histogram_configs.insert(
+        "request.latency".to_string(),
+        HistogramConfig::latency_millis().with_unit("ms"),
+    );
+    let inner = SharedCollector::new(SharedCollectorOptions {
+        histogram_configs,
+        ..Default::default()
+    });
+    let options = MetricCollectorOptions {
+        writer_type: StatsWriterType::Simple,
+        flush_interval: Duration::from_millis(100),
+        flush_jitter: Duration::ZERO,
+        ..Default::default()
+    };
+    let datadog_addr = format!("127.0.0.1:{port}").parse().unwrap();
+    let collector =
+        MetricCollector::new("0.0.0.0:0".parse().unwrap(), datadog_addr, options, inner)
+            .expect("failed to create collector");
+
+    let tags = [RylvStr::from_static("env:test")];
+    collector.histogram(
+        RylvStr::from_static("request.latency"),
+        42,
+        &mut tags.clone(),
+    );
+    collector.histogram(RylvStr::from_static("request.size"), 42, &mut tags.clone());
+    drop(collector);
+
+    let all_text = wait_and_collect(receiver);
+    assert!(all_text.contains("request.latency.max:42|g|#env:test,unit:ms\n"));
+    assert!(all_text.contains("request.latency.99percentile:42|g|#env:test,unit:ms\n"));
+    // `.count` is a number of samples, and other metrics keep their tags untouched
+    assert!(all_text.contains("request.latency.count:1|c|#env:test\n"));
+    assert!(all_text.contains("request.size.max:42|g|#env:test\n"));
+}
+
+#[test]
+fn test_preferred_writer_resolves_to_platform_batch_writer() {
+    let (socket, port) = ephemeral_socket();
+    let receiver = spawn_udp_receiver(socket);
+    let options = MetricCollectorOptions {
+        writer_type: StatsWriterType::Preferred(WriterPreference::Auto),
+        flush_interval: Duration::from_millis(100),
+        flush_jitter: Duration::ZERO,
+        ..Default::default()
+    };
+    let datadog_addr = format!("127.0.0.1:{port}").parse().unwrap();
+    let collector = MetricCollector::new(
+        "0.0.0.0:0".parse().unwrap(),
+        datadog_addr,
+        options,
+        SharedCollector::default(),
+    )
+    .expect("failed to create collector");
+
+    let expected = if cfg!(target_os = "linux") {
+        StatsWriterKind::LinuxBatch
+    } else if cfg!(target_vendor = "apple") {
+        StatsWriterKind::AppleBatch
+    } else {
+        StatsWriterKind::Simple
+    };
+    assert_eq!(collector.active_writer_kind(), expected);
+
+    collector.count(
+        RylvStr::from_static("preferred.requests"),
+        &mut [RylvStr::from_static("env:test")],
+    );
+    drop(collector);
+
+    let all_text = wait_and_collect(receiver);
+    assert!(all_text.contains("preferred.requests:1|c|#env:test\n"));
+}
+
+#[test]
+fn test_option_presets_deliver_metrics() {
+    let presets = [
+        MetricCollectorOptions::datadog_agent_localhost(),
+        MetricCollectorOptions::high_throughput(),
+        MetricCollectorOptions::low_overhead(),
+    ];
+    for options in presets {
+        let (socket, port) = ephemeral_socket();
+        let receiver = spawn_udp_receiver(socket);
+        let datadog_addr = format!("127.0.0.1:{port}").parse().unwrap();
+        let collector = MetricCollector::new(
+            "0.0.0.0:0".parse().unwrap(),
+            datadog_addr,
+            options,
+            SharedCollector::default(),
+        )
+        .expect("failed to create collector");
+
+        collector.count(
+            RylvStr::from_static("preset.requests"),
+            &mut [RylvStr::from_static("env:test")],
+        );
+        collector.gauge(RylvStr::from_static("preset.depth"), 7, []);
+        // every preset flushes less often than the test runs, so this is the final flush
+        drop(collector);
+
+        let all_text = wait_and_collect(receiver);
+        assert!(
+            all_text.contains("preset.requests:1|c|#env:test\n"),
+            "{all_text}"
+        );
+        assert!(all_text.contains("preset.depth:7|g\n"), "{all_text}");
+    }
+}
+
+#[test]
+fn test_collector_connect_resolves_localhost() {
+    let (socket, port) = ephemeral_socket();
+    let receiver = spawn_udp_receiver(socket);
+    let options = MetricCollectorOptions {
+        flush_interval: Duration::from_millis(100),
+        ..Default::default()
+    };
+    // the receiver listens on 127.0.0.1, which the default IPv4 preference picks
+    let collector =
+        MetricCollector::<SharedCollector>::connect("localhost", port, options).unwrap();
+
+    collector.count(
+        RylvStr::from_static("connect.requests"),
+        &mut [RylvStr::from_static("env:test")],
+    );
+    drop(collector);
+
+    let all_text = wait_and_collect(receiver);
+    assert!(all_text.contains("connect.requests:1|c|#env:test\n"));
+}
+
+#[test]
+fn test_collector_connect_reports_unresolvable_hosts() {
+    // `.invalid` is reserved and never resolves
+    let result = MetricCollector::<SharedCollector>::connect(
+        "agent.rylv-metrics.invalid",
+        8125,
+        MetricCollectorOptions::default(),
+    );
+    let Err(err) = result else {
+        panic!("an unresolvable host must fail");
+    };
+    assert!(
+        matches!(&err, MetricsError::Resolve { host, port: 8125, .. } if host == "agent.rylv-metrics.invalid"),
+        "{err:?}"
+    );
+    assert!(err
+        .to_string()
+        .starts_with("Cannot resolve destination agent.rylv-metrics.invalid:8125: "));
+    assert!(!err.is_transient());
+}
+
+#[test]
+fn test_collector_new_falls_back_when_connect_fails() {
+    // an IPv4 socket cannot connect to an IPv6 destination
+    let options = MetricCollectorOptions {
+        writer_type: StatsWriterType::Simple,
+        connect_socket: true,
+        ..Default::default()
+    };
+    let bind_addr = "127.0.0.1:0".parse().unwrap();
+    let datadog_addr = "[::1]:8125".parse().unwrap();
+
+    let collector =
+        MetricCollector::new(bind_addr, datadog_addr, options, SharedCollector::default());
+
+    assert!(
+        collector.is_ok(),
+        "constructor should not fail when the socket cannot be connected"
+    );
+}
+
+#[test]
+fn test_set_destination_moves_following_flushes() {
+    let (first_socket, first_port) = ephemeral_socket();
+    let (second_socket, second_port) = ephemeral_socket();
+    let first_receiver = spawn_udp_receiver_exact(first_socket, 1);
+    let second_receiver = spawn_udp_receiver_exact(second_socket, 1);
+
+    let collector = create_collector(first_port, StatsWriterType::Simple, String::new(), 512);
+    collector.gauge(RylvStr::from_static("before.move"), 1, &mut []);
+    assert_eq!(
+        wait_and_collect_exact(first_receiver),
+        HashSet::from(["before.move:1|g\n".to_owned()])
+    );
+
+    collector
+        .set_destination(format!("127.0.0.1:{second_port}").parse().unwrap())
+        .expect("flush thread should be running");
+    // the command is applied by the idle flush thread before the next tick
+    std::thread::sleep(Duration::from_millis(20));
+    collector.gauge(RylvStr::from_static("after.move"), 2, &mut []);
+    assert_eq!(
+        wait_and_collect_exact(second_receiver),
+        HashSet::from(["after.move:2|g\n".to_owned()])
+    );
+}
+
 #[test]
 fn test_simple_writer() -> std::io::Result<()> {
     let (socket, port) = ephemeral_socket();
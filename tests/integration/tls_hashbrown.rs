@@ -1,7 +1,8 @@
 use rylv_metrics::{
-    DrainMetricCollectorTrait, MetricCollectorTrait, MetricKind, MetricSuffix, RylvStr,
-    TLSCollector, TLSCollectorOptions,
+    DrainMetricCollectorTrait, HistogramConfig, HistogramSumKind, MetricCollectorTrait,
+    MetricFilter, MetricKind, MetricSuffix, RylvStr, TLSCollector, TLSCollectorOptions,
 };
+use std::time::Duration;
 
 fn percentile_suffix(percentile: f64) -> String {
     let mut percentile_number = (percentile * 100.0).to_string();
@@ -37,6 +38,9 @@ where
         let metric_type = match frame.kind {
             MetricKind::Count => "c",
             MetricKind::Gauge => "g",
+            MetricKind::Timing => "ms",
+            MetricKind::Distribution => "d",
+            kind => unreachable!("unexpected {kind:?} frame"),
         };
         if frame.tags.is_empty() {
             lines.push(format!("{metric}:{}|{metric_type}\n", frame.value));
@@ -55,6 +59,32 @@ fn sorted_lines(mut lines: Vec<String>) -> Vec<String> {
     lines
 }
 
+#[test]
+fn test_tls_hashbrown_histogram_sum_merges_threads() {
+    let collector = TLSCollector::new(TLSCollectorOptions {
+        default_histogram_config: HistogramConfig::default()
+            .with_base_metrics([])
+            .with_sum(true)
+            .with_sum_kind(HistogramSumKind::Count),
+        ..Default::default()
+    });
+
+    std::thread::scope(|scope| {
+        for values in [&[100, 250][..], &[400]] {
+            let collector = &collector;
+            scope.spawn(move || {
+                for &value in values {
+                    collector.histogram(RylvStr::from_static("latency"), value, &mut []);
+                }
+            });
+        }
+    });
+
+    let lines = drain_metrics_now(&collector);
+    assert!(lines.contains(&"latency.sum:750|c\n".to_string()));
+    assert!(!lines.iter().any(|line| line.starts_with("latency.count")));
+}
+
 #[test]
 fn test_tls_hashbrown_drain_consumes_prepared_metrics() {
     let collector = TLSCollector::new(TLSCollectorOptions {
@@ -97,3 +127,78 @@ fn test_tls_hashbrown_drain_consumes_prepared_metrics() {
     ]);
     assert_eq!(drained_third, expected_third);
 }
+
+#[test]
+fn test_tls_hashbrown_metric_filter_drops_before_recording() {
+    let collector = TLSCollector::new(TLSCollectorOptions {
+        metric_filter: Some(
+            MetricFilter::default()
+                .with_allow_prefix("app.")
+                .with_deny_prefix("app.debug."),
+        ),
+        ..Default::default()
+    });
+
+    collector.count(RylvStr::from_static("app.requests"), &mut []);
+    collector.gauge(RylvStr::from_static("app.debug.queue"), 3, &mut []);
+    collector.histogram(RylvStr::from_static("debug.latency"), 10, &mut []);
+    let sorted = collector.prepare_sorted_tags([]);
+    let prepared = collector.prepare_metric(RylvStr::from_static("app.debug.prepared"), sorted);
+    collector.count_add_prepared(&prepared, 1);
+
+    assert_eq!(drain_metrics_now(&collector), vec!["app.requests:1|c\n"]);
+    assert_eq!(collector.filtered_count(), 3);
+}
+
+#[test]
+fn test_tls_hashbrown_keep_zero_values_emits_idle_series() {
+    let record = |keep_zero_values: bool| {
+        let collector = TLSCollector::new(TLSCollectorOptions {
+            keep_zero_values,
+            ..Default::default()
+        });
+        collector.gauge(
+            RylvStr::from_static("errors"),
+            0,
+            &mut [RylvStr::from_static("svc:api")],
+        );
+        collector.count_add(RylvStr::from_static("retries"), 2, &mut []);
+        let first = sorted_lines(drain_metrics_now(&collector));
+        let second = sorted_lines(drain_metrics_now(&collector));
+        let third = sorted_lines(drain_metrics_now(&collector));
+        (first, second, third)
+    };
+
+    let (first, second, third) = record(true);
+    assert_eq!(first, vec!["errors:0|g|#svc:api\n", "retries:2|c\n"]);
+    assert_eq!(second, vec!["errors:0|g|#svc:api\n", "retries:0|c\n"]);
+    assert_eq!(third, second);
+
+    let (first, second, third) = record(false);
+    assert_eq!(first, vec!["errors:0|g|#svc:api\n", "retries:2|c\n"]);
+    assert!(second.is_empty());
+    assert!(third.is_empty());
+}
+
+#[test]
+fn test_tls_hashbrown_key_idle_ttl_evicts_idle_kept_series() {
+    let collector = TLSCollector::new(TLSCollectorOptions {
+        keep_zero_values: true,
+        key_idle_ttl: Some(Duration::from_millis(100)),
+        ..Default::default()
+    });
+    let drain = || {
+        collector.count(RylvStr::from_static("live"), &mut []);
+        sorted_lines(drain_metrics_now(&collector))
+    };
+    collector.count_add(RylvStr::from_static("retries"), 2, &mut []);
+    collector.gauge(RylvStr::from_static("queue"), 5, &mut []);
+
+    assert_eq!(drain(), vec!["live:1|c\n", "queue:5|g\n", "retries:2|c\n"]);
+    assert_eq!(drain(), vec!["live:1|c\n", "queue:0|g\n", "retries:0|c\n"]);
+
+    std::thread::sleep(Duration::from_millis(150));
+    for _ in 0..3 {
+        assert_eq!(drain(), vec!["live:1|c\n"]);
+    }
+}
@@ -0,0 +1,85 @@
+use rylv_metrics::tracing_layer::MetricsLayer;
+use rylv_metrics::{
+    DrainMetricCollectorTrait, HistogramBaseMetric, HistogramConfig, MetricKind, MetricSuffix,
+    SharedCollector, SharedCollectorOptions, SigFig,
+};
+use std::sync::Arc;
+use tracing_subscriber::layer::SubscriberExt;
+
+fn drain_metrics_now(collector: &SharedCollector) -> Vec<String> {
+    let drain = collector.try_begin_drain().into_iter().flatten();
+    let mut lines = Vec::new();
+    for frame in drain {
+        let mut metric = String::new();
+        metric.push_str(frame.prefix);
+        metric.push_str(frame.metric);
+        if let MetricSuffix::Static(suffix) = frame.suffix {
+            metric.push_str(suffix);
+        }
+
+        let metric_type = match frame.kind {
+            MetricKind::Count => "c",
+            MetricKind::Gauge => "g",
+            MetricKind::Timing => "ms",
+            MetricKind::Distribution => "d",
+            kind => unreachable!("unexpected {kind:?} frame"),
+        };
+        if frame.tags.is_empty() {
+            lines.push(format!("{metric}:{}|{metric_type}\n", frame.value));
+        } else {
+            lines.push(format!(
+                "{metric}:{}|{metric_type}|#{}\n",
+                frame.value, frame.tags
+            ));
+        }
+    }
+    lines.sort_unstable();
+    lines
+}
+
+#[test]
+fn test_tracing_layer_records_metric_events() {
+    let collector = Arc::new(SharedCollector::new(SharedCollectorOptions {
+        default_histogram_config: HistogramConfig::new(SigFig::default(), Vec::new())
+            .expect("valid histogram config")
+            .with_base_metrics([HistogramBaseMetric::Max]),
+        ..Default::default()
+    }));
+    let subscriber = tracing_subscriber::registry().with(MetricsLayer::new(Arc::clone(&collector)));
+
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(
+            metric.name = "jobs.done",
+            metric.tags = "queue:email,env:test"
+        );
+        tracing::info!(
+            metric.name = "jobs.done",
+            metric.value = 2_u64,
+            metric.tags = "env:test,queue:email",
+            "two more jobs"
+        );
+        tracing::warn!(
+            metric.name = "queue.depth",
+            metric.kind = "gauge",
+            metric.value = 7_i64
+        );
+        tracing::info!(
+            metric.name = "job.latency",
+            metric.kind = "histogram",
+            metric.value = 42_u64
+        );
+        // Ignored: no metric name, a gauge without value, an unknown kind
+        tracing::info!(metric.value = 3_u64, "not a metric");
+        tracing::info!(metric.name = "queue.size", metric.kind = "gauge");
+        tracing::info!(metric.name = "jobs.other", metric.kind = "set");
+    });
+
+    assert_eq!(
+        drain_metrics_now(&collector),
+        vec![
+            "job.latency.max:42|g\n".to_string(),
+            "jobs.done:3|c|#env:test,queue:email\n".to_string(),
+            "queue.depth:7|g\n".to_string(),
+        ]
+    );
+}
@@ -38,6 +38,9 @@ impl StatsWriterTrait for MiriCustomWriter {
         let metric_type = match metric_type {
             MetricKind::Count => "c",
             MetricKind::Gauge => "g",
+            MetricKind::Timing => "ms",
+            MetricKind::Distribution => "d",
+            kind => return Err(format!("unsupported metric kind {kind:?}").into()),
         };
         for metric in metrics {
             self.current.push_str(metric);
@@ -162,6 +165,7 @@ fn miri_shared_drain_keeps_borrowed_frame_fields_valid() {
                     saw_histogram = true;
                 }
             }
+            kind => unreachable!("no {kind:?} metrics are recorded"),
         }
     }
 
@@ -212,6 +216,7 @@ fn miri_tls_drain_keeps_borrowed_frame_fields_valid() {
                     saw_histogram = true;
                 }
             }
+            kind => unreachable!("no {kind:?} metrics are recorded"),
         }
     }
 